@@ -0,0 +1,91 @@
+//! Benchmarks of authentication hot paths, repos are mocked.
+//! Run with `cargo bench --features test-mocks --bench auth`,
+//! see `scripts/bench_check.sh` for comparison with a saved baseline.
+
+#[macro_use]
+extern crate criterion;
+extern crate jsonwebtoken;
+extern crate stq_static_resources;
+extern crate stq_types;
+extern crate tokio_core;
+extern crate users_lib;
+
+use std::fs::File;
+use std::io::prelude::*;
+use std::sync::Arc;
+
+use criterion::Criterion;
+use jsonwebtoken::{decode, encode, Algorithm, Header, Validation};
+use tokio_core::reactor::Core;
+
+use stq_static_resources::Provider;
+use stq_types::UserId;
+
+use users_lib::controller::routes::create_route_parser;
+use users_lib::models::JWTPayload;
+use users_lib::repos::repo_factory::tests::*;
+use users_lib::services::jwt::JWTService;
+use users_lib::services::util::{password_create, password_verify};
+
+/// 2100-01-01, so that decoded tokens never expire
+const TOKEN_EXPIRATION: i64 = 4_102_444_800;
+
+fn read_key(path: &str) -> Vec<u8> {
+    let mut key = Vec::new();
+    File::open(path).unwrap().read_to_end(&mut key).unwrap();
+    key
+}
+
+fn bench_password_hashing(c: &mut Criterion) {
+    c.bench_function("password_create", |b| b.iter(|| password_create(MOCK_PASSWORD.to_string())));
+
+    let hash = password_create(MOCK_PASSWORD.to_string());
+    c.bench_function("password_verify", move |b| {
+        b.iter(|| password_verify(&hash, MOCK_PASSWORD.to_string()).unwrap())
+    });
+}
+
+fn bench_jwt(c: &mut Criterion) {
+    let private_key = read_key("config/keys/private_key.der");
+    let public_key = read_key("config/keys/public_key.der");
+    let payload = JWTPayload::new(UserId(1), TOKEN_EXPIRATION, Provider::Email);
+    let token = encode(&Header::new(Algorithm::RS256), &payload, &private_key).unwrap();
+
+    c.bench_function("jwt_encode", move |b| {
+        b.iter(|| encode(&Header::new(Algorithm::RS256), &payload, &private_key).unwrap())
+    });
+
+    let mut validation = Validation::default();
+    validation.algorithms = vec![Algorithm::RS256];
+    c.bench_function("jwt_decode", move |b| {
+        b.iter(|| decode::<JWTPayload>(&token, &public_key, &validation).unwrap())
+    });
+}
+
+fn bench_router(c: &mut Criterion) {
+    let route_parser = create_route_parser();
+    c.bench_function("route_matching", move |b| {
+        b.iter(|| {
+            (
+                route_parser.test("/users/current"),
+                route_parser.test("/users/42"),
+                route_parser.test("/jwt/email"),
+                route_parser.test("/unknown"),
+            )
+        })
+    });
+}
+
+fn bench_login_flow(c: &mut Criterion) {
+    c.bench_function("login_email", |b| {
+        let mut core = Core::new().unwrap();
+        let service = create_service(None, Arc::new(core.handle()));
+        b.iter(|| {
+            let identity = create_new_email_identity(MOCK_EMAIL.to_string(), MOCK_PASSWORD.to_string());
+            core.run(service.create_token_email(identity, TOKEN_EXPIRATION)).unwrap()
+        })
+    });
+}
+
+criterion_group!(benches, bench_password_hashing, bench_jwt, bench_router, bench_login_flow);
+criterion_main!(benches);
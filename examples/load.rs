@@ -0,0 +1,231 @@
+//! Load test harness. Drives a configurable mix of logins, profile reads and searches
+//! against a running instance and reports latency percentiles per request kind.
+//!
+//! ```text
+//! cargo run --release --example load -- --url http://localhost:8000 --requests 10000 --concurrency 50 \
+//!     --mix login=1,profile=8,search=1 --email user@example.com --password secret --user-id 1
+//! ```
+//!
+//! Requests go directly to the service, so profile reads and searches are authorized
+//! with `--user-id` the same way the gateway does it. Searches require a moderator or superuser.
+
+extern crate futures;
+extern crate hyper;
+extern crate rand;
+#[macro_use]
+extern crate serde_json;
+extern crate tokio_core;
+
+use std::env;
+use std::process;
+use std::time::{Duration, Instant};
+
+use futures::{stream, Future, Stream};
+use hyper::header::{Authorization, ContentType};
+use hyper::{Client, Method, Request, Uri};
+use rand::Rng;
+use tokio_core::reactor::Core;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    Login,
+    Profile,
+    Search,
+}
+
+const KINDS: &'static [Kind] = &[Kind::Login, Kind::Profile, Kind::Search];
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Login => "login",
+            Kind::Profile => "profile",
+            Kind::Search => "search",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Kind> {
+        KINDS.iter().cloned().find(|kind| kind.name() == name)
+    }
+}
+
+struct Options {
+    url: String,
+    requests: usize,
+    concurrency: usize,
+    mix: Vec<(Kind, u32)>,
+    email: String,
+    password: String,
+    user_id: i32,
+}
+
+const USAGE: &'static str = "Usage: load [--url URL] [--requests N] [--concurrency N] \
+                             [--mix login=W,profile=W,search=W] [--email EMAIL] [--password PASSWORD] [--user-id ID]";
+
+fn parse_mix(mix: &str) -> Result<Vec<(Kind, u32)>, String> {
+    mix.split(',')
+        .map(|part| {
+            let mut pair = part.splitn(2, '=');
+            let name = pair.next().unwrap_or("");
+            let kind = Kind::from_name(name).ok_or_else(|| format!("Unknown request kind '{}'", name))?;
+            let weight = pair
+                .next()
+                .and_then(|weight| weight.parse().ok())
+                .ok_or_else(|| format!("Invalid weight of '{}'", name))?;
+            Ok((kind, weight))
+        })
+        .collect()
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut options = Options {
+        url: "http://localhost:8000".to_string(),
+        requests: 1000,
+        concurrency: 10,
+        mix: vec![(Kind::Login, 1), (Kind::Profile, 8), (Kind::Search, 1)],
+        email: "example@mail.com".to_string(),
+        password: "password".to_string(),
+        user_id: 1,
+    };
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next().ok_or_else(|| format!("Missing value of {}", arg))?;
+        match arg.as_str() {
+            "--url" => options.url = value.trim_right_matches('/').to_string(),
+            "--requests" => options.requests = value.parse().map_err(|_| "Invalid --requests".to_string())?,
+            "--concurrency" => options.concurrency = value.parse().map_err(|_| "Invalid --concurrency".to_string())?,
+            "--mix" => options.mix = parse_mix(&value)?,
+            "--email" => options.email = value,
+            "--password" => options.password = value,
+            "--user-id" => options.user_id = value.parse().map_err(|_| "Invalid --user-id".to_string())?,
+            _ => return Err(format!("Unknown option {}", arg)),
+        }
+    }
+
+    if options.mix.iter().all(|&(_, weight)| weight == 0) {
+        return Err("At least one request kind must have positive weight".to_string());
+    }
+
+    Ok(options)
+}
+
+fn pick_kind(mix: &[(Kind, u32)]) -> Kind {
+    let total = mix.iter().map(|&(_, weight)| weight).sum::<u32>();
+    let mut point = rand::thread_rng().gen_range(0, total);
+    for &(kind, weight) in mix {
+        if point < weight {
+            return kind;
+        }
+        point -= weight;
+    }
+    mix[0].0
+}
+
+fn build_request(kind: Kind, options: &Options) -> Request {
+    let uri = |path: &str| format!("{}{}", options.url, path).parse::<Uri>().expect("Invalid --url");
+
+    match kind {
+        Kind::Login => {
+            let mut request = Request::new(Method::Post, uri("/jwt/email"));
+            request.headers_mut().set(ContentType::json());
+            request.set_body(json!({"email": options.email, "password": options.password}).to_string());
+            request
+        }
+        Kind::Profile => {
+            let mut request = Request::new(Method::Get, uri("/users/current"));
+            request.headers_mut().set(Authorization(options.user_id.to_string()));
+            request
+        }
+        Kind::Search => {
+            let mut request = Request::new(Method::Post, uri("/users/search?count=20"));
+            request.headers_mut().set(ContentType::json());
+            request.headers_mut().set(Authorization(options.user_id.to_string()));
+            request.set_body(json!({"is_blocked": false}).to_string());
+            request
+        }
+    }
+}
+
+fn as_millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + f64::from(duration.subsec_nanos()) / 1_000_000.0
+}
+
+/// Nearest-rank percentile of sorted latencies
+fn percentile(sorted: &[Duration], percent: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::new(0, 0);
+    }
+    let rank = (percent / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.max(1).min(sorted.len()) - 1]
+}
+
+fn report(results: &[(Kind, Duration, bool)], elapsed: Duration) {
+    println!(
+        "{:<8} {:>8} {:>8} {:>10} {:>10} {:>10} {:>10}",
+        "kind", "requests", "errors", "p50 ms", "p90 ms", "p99 ms", "max ms"
+    );
+
+    for &kind in KINDS {
+        let mut latencies = results
+            .iter()
+            .filter(|&&(result_kind, _, _)| result_kind == kind)
+            .map(|&(_, latency, _)| latency)
+            .collect::<Vec<_>>();
+        if latencies.is_empty() {
+            continue;
+        }
+        latencies.sort();
+        let errors = results
+            .iter()
+            .filter(|&&(result_kind, _, success)| result_kind == kind && !success)
+            .count();
+
+        println!(
+            "{:<8} {:>8} {:>8} {:>10.2} {:>10.2} {:>10.2} {:>10.2}",
+            kind.name(),
+            latencies.len(),
+            errors,
+            as_millis(percentile(&latencies, 50.0)),
+            as_millis(percentile(&latencies, 90.0)),
+            as_millis(percentile(&latencies, 99.0)),
+            as_millis(latencies[latencies.len() - 1]),
+        );
+    }
+
+    println!(
+        "\n{} requests in {:.2} s, {:.1} requests/s",
+        results.len(),
+        as_millis(elapsed) / 1000.0,
+        results.len() as f64 * 1000.0 / as_millis(elapsed).max(1.0)
+    );
+}
+
+fn main() {
+    let options = parse_options().unwrap_or_else(|e| {
+        eprintln!("{}\n{}", e, USAGE);
+        process::exit(2);
+    });
+
+    let mut core = Core::new().expect("Failed to create event loop");
+    let client = Client::new(&core.handle());
+    let kinds = (0..options.requests).map(|_| pick_kind(&options.mix)).collect::<Vec<_>>();
+
+    let work = stream::iter_ok::<_, ()>(kinds)
+        .map(|kind| {
+            let started = Instant::now();
+            client
+                .request(build_request(kind, &options))
+                .and_then(|response| {
+                    let status = response.status();
+                    response.body().concat2().map(move |_| status)
+                })
+                .then(move |result| Ok::<_, ()>((kind, started.elapsed(), result.map(|status| status.is_success()).unwrap_or(false))))
+        })
+        .buffer_unordered(options.concurrency.max(1))
+        .collect();
+
+    let started = Instant::now();
+    let results = core.run(work).expect("Load test failed");
+    report(&results, started.elapsed());
+}
@@ -0,0 +1,8 @@
+-- Data keys of users wrapped by the PII master key, kept apart from the users database.
+-- Rows are deleted with users, which makes their sealed PII unreadable in backups of users,
+-- so keep backups of this database short-lived.
+CREATE TABLE user_keys (
+    user_id INTEGER PRIMARY KEY,
+    data_key BYTEA NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
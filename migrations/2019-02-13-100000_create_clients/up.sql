@@ -0,0 +1,16 @@
+CREATE TABLE clients (
+    id VARCHAR PRIMARY KEY,
+    name VARCHAR NOT NULL,
+    audience VARCHAR NOT NULL,
+    jwt_expiration_s BIGINT NOT NULL,
+    refresh_timeout_s BIGINT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    updated_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+SELECT diesel_manage_updated_at('clients');
+
+INSERT INTO clients (id, name, audience, jwt_expiration_s, refresh_timeout_s) VALUES
+    ('storefront', 'Storefront', 'storefront', 86400, 604800),
+    ('mobile', 'Mobile app', 'mobile', 604800, 2592000),
+    ('admin', 'Admin console', 'admin', 3600, 86400);
@@ -0,0 +1,2 @@
+ALTER TABLE clients DROP COLUMN service_user_id;
+ALTER TABLE clients DROP COLUMN secret_hash;
@@ -0,0 +1,2 @@
+ALTER TABLE clients ADD COLUMN secret_hash VARCHAR;
+ALTER TABLE clients ADD COLUMN service_user_id INTEGER REFERENCES users (id) ON DELETE SET NULL;
@@ -0,0 +1,11 @@
+CREATE TABLE device_codes (
+    device_code VARCHAR PRIMARY KEY,
+    user_code VARCHAR NOT NULL UNIQUE,
+    client_id VARCHAR NOT NULL REFERENCES clients (id) ON DELETE CASCADE,
+    user_id INTEGER REFERENCES users (id) ON DELETE CASCADE,
+    expires_at TIMESTAMP NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    updated_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+SELECT diesel_manage_updated_at('device_codes');
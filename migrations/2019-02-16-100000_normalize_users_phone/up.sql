@@ -0,0 +1,5 @@
+-- Numbers with international prefix can be normalized to E.164 in place,
+-- national numbers are normalized by the service on the next update
+UPDATE users SET phone = '+' || regexp_replace(phone, '[^0-9]', '', 'g') WHERE phone LIKE '+%';
+
+CREATE INDEX IF NOT EXISTS users_phone_idx ON users (phone);
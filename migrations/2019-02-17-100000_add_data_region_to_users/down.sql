@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS users_data_region_idx;
+
+ALTER TABLE users DROP COLUMN data_region;
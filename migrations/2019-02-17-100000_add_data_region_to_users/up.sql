@@ -0,0 +1,3 @@
+ALTER TABLE users ADD COLUMN data_region VARCHAR NOT NULL DEFAULT 'global';
+
+CREATE INDEX IF NOT EXISTS users_data_region_idx ON users (data_region);
@@ -0,0 +1,5 @@
+DROP TRIGGER IF EXISTS users_changed_notify ON users;
+DROP FUNCTION IF EXISTS notify_users_changed();
+
+DROP TRIGGER IF EXISTS user_roles_changed_notify ON user_roles;
+DROP FUNCTION IF EXISTS notify_user_roles_changed();
@@ -0,0 +1,30 @@
+CREATE OR REPLACE FUNCTION notify_user_roles_changed() RETURNS trigger AS $$
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        PERFORM pg_notify('user_roles_changed', OLD.user_id::text);
+    ELSE
+        PERFORM pg_notify('user_roles_changed', NEW.user_id::text);
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER user_roles_changed_notify
+    AFTER INSERT OR UPDATE OR DELETE ON user_roles
+    FOR EACH ROW EXECUTE PROCEDURE notify_user_roles_changed();
+
+CREATE OR REPLACE FUNCTION notify_users_changed() RETURNS trigger AS $$
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        PERFORM pg_notify('users_changed', OLD.id::text);
+    ELSE
+        PERFORM pg_notify('users_changed', NEW.id::text);
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Only changes affecting access are sent, e.g. not every login updating last_login_at
+CREATE TRIGGER users_changed_notify
+    AFTER UPDATE OF is_active, is_blocked, revoke_before OR DELETE ON users
+    FOR EACH ROW EXECUTE PROCEDURE notify_users_changed();
@@ -0,0 +1,8 @@
+CREATE TABLE user_tags (
+    user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    tag VARCHAR NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    PRIMARY KEY (user_id, tag)
+);
+
+CREATE INDEX user_tags_tag_idx ON user_tags (tag);
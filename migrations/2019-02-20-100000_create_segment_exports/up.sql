@@ -0,0 +1,13 @@
+CREATE TABLE segment_exports (
+    id UUID PRIMARY KEY,
+    state VARCHAR NOT NULL DEFAULT 'pending',
+    filters JSONB NOT NULL,
+    rows_count INTEGER NOT NULL DEFAULT 0,
+    csv TEXT,
+    error VARCHAR,
+    created_by INTEGER,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    updated_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+SELECT diesel_manage_updated_at('segment_exports');
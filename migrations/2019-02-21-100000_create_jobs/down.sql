@@ -0,0 +1,13 @@
+ALTER TABLE segment_exports
+    DROP CONSTRAINT segment_exports_id_fkey,
+    ADD COLUMN state VARCHAR NOT NULL DEFAULT 'pending',
+    ADD COLUMN rows_count INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN error VARCHAR,
+    ADD COLUMN created_by INTEGER;
+
+UPDATE segment_exports
+SET state = jobs.state, rows_count = jobs.progress, error = jobs.error, created_by = jobs.created_by
+FROM jobs
+WHERE jobs.id = segment_exports.id;
+
+DROP TABLE jobs;
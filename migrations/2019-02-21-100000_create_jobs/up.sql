@@ -0,0 +1,35 @@
+CREATE TABLE jobs (
+    id UUID PRIMARY KEY,
+    kind VARCHAR NOT NULL,
+    state VARCHAR NOT NULL DEFAULT 'pending',
+    progress INTEGER NOT NULL DEFAULT 0,
+    total INTEGER,
+    result_location VARCHAR,
+    error VARCHAR,
+    created_by INTEGER,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    updated_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+SELECT diesel_manage_updated_at('jobs');
+
+INSERT INTO jobs (id, kind, state, progress, total, result_location, error, created_by, created_at, updated_at)
+SELECT
+    id,
+    'segment_export',
+    state,
+    rows_count,
+    CASE WHEN state = 'done' THEN rows_count END,
+    CASE WHEN state = 'done' THEN '/users/segments/export/' || id || '/csv' END,
+    error,
+    created_by,
+    created_at,
+    updated_at
+FROM segment_exports;
+
+ALTER TABLE segment_exports
+    DROP COLUMN state,
+    DROP COLUMN rows_count,
+    DROP COLUMN error,
+    DROP COLUMN created_by,
+    ADD CONSTRAINT segment_exports_id_fkey FOREIGN KEY (id) REFERENCES jobs (id) ON DELETE CASCADE;
@@ -0,0 +1,257 @@
+CREATE TABLE countries (
+    alpha3 VARCHAR PRIMARY KEY,
+    alpha2 VARCHAR NOT NULL UNIQUE,
+    name VARCHAR NOT NULL,
+    phone_prefix VARCHAR
+);
+
+INSERT INTO countries (alpha3, alpha2, name, phone_prefix) VALUES
+    ('AFG', 'AF', 'Afghanistan', '+93'),
+    ('ALA', 'AX', 'Åland Islands', '+358'),
+    ('ALB', 'AL', 'Albania', '+355'),
+    ('DZA', 'DZ', 'Algeria', '+213'),
+    ('ASM', 'AS', 'American Samoa', '+1684'),
+    ('AND', 'AD', 'Andorra', '+376'),
+    ('AGO', 'AO', 'Angola', '+244'),
+    ('AIA', 'AI', 'Anguilla', '+1264'),
+    ('ATA', 'AQ', 'Antarctica', '+672'),
+    ('ATG', 'AG', 'Antigua and Barbuda', '+1268'),
+    ('ARG', 'AR', 'Argentina', '+54'),
+    ('ARM', 'AM', 'Armenia', '+374'),
+    ('ABW', 'AW', 'Aruba', '+297'),
+    ('AUS', 'AU', 'Australia', '+61'),
+    ('AUT', 'AT', 'Austria', '+43'),
+    ('AZE', 'AZ', 'Azerbaijan', '+994'),
+    ('BHS', 'BS', 'Bahamas', '+1242'),
+    ('BHR', 'BH', 'Bahrain', '+973'),
+    ('BGD', 'BD', 'Bangladesh', '+880'),
+    ('BRB', 'BB', 'Barbados', '+1246'),
+    ('BLR', 'BY', 'Belarus', '+375'),
+    ('BEL', 'BE', 'Belgium', '+32'),
+    ('BLZ', 'BZ', 'Belize', '+501'),
+    ('BEN', 'BJ', 'Benin', '+229'),
+    ('BMU', 'BM', 'Bermuda', '+1441'),
+    ('BTN', 'BT', 'Bhutan', '+975'),
+    ('BOL', 'BO', 'Bolivia', '+591'),
+    ('BES', 'BQ', 'Bonaire, Sint Eustatius and Saba', '+599'),
+    ('BIH', 'BA', 'Bosnia and Herzegovina', '+387'),
+    ('BWA', 'BW', 'Botswana', '+267'),
+    ('BVT', 'BV', 'Bouvet Island', NULL),
+    ('BRA', 'BR', 'Brazil', '+55'),
+    ('IOT', 'IO', 'British Indian Ocean Territory', '+246'),
+    ('BRN', 'BN', 'Brunei Darussalam', '+673'),
+    ('BGR', 'BG', 'Bulgaria', '+359'),
+    ('BFA', 'BF', 'Burkina Faso', '+226'),
+    ('BDI', 'BI', 'Burundi', '+257'),
+    ('CPV', 'CV', 'Cabo Verde', '+238'),
+    ('KHM', 'KH', 'Cambodia', '+855'),
+    ('CMR', 'CM', 'Cameroon', '+237'),
+    ('CAN', 'CA', 'Canada', '+1'),
+    ('CYM', 'KY', 'Cayman Islands', '+1345'),
+    ('CAF', 'CF', 'Central African Republic', '+236'),
+    ('TCD', 'TD', 'Chad', '+235'),
+    ('CHL', 'CL', 'Chile', '+56'),
+    ('CHN', 'CN', 'China', '+86'),
+    ('CXR', 'CX', 'Christmas Island', '+61'),
+    ('CCK', 'CC', 'Cocos (Keeling) Islands', '+61'),
+    ('COL', 'CO', 'Colombia', '+57'),
+    ('COM', 'KM', 'Comoros', '+269'),
+    ('COG', 'CG', 'Congo', '+242'),
+    ('COD', 'CD', 'Congo, Democratic Republic of the', '+243'),
+    ('COK', 'CK', 'Cook Islands', '+682'),
+    ('CRI', 'CR', 'Costa Rica', '+506'),
+    ('CIV', 'CI', 'Côte d''Ivoire', '+225'),
+    ('HRV', 'HR', 'Croatia', '+385'),
+    ('CUB', 'CU', 'Cuba', '+53'),
+    ('CUW', 'CW', 'Curaçao', '+599'),
+    ('CYP', 'CY', 'Cyprus', '+357'),
+    ('CZE', 'CZ', 'Czechia', '+420'),
+    ('DNK', 'DK', 'Denmark', '+45'),
+    ('DJI', 'DJ', 'Djibouti', '+253'),
+    ('DMA', 'DM', 'Dominica', '+1767'),
+    ('DOM', 'DO', 'Dominican Republic', '+1809'),
+    ('ECU', 'EC', 'Ecuador', '+593'),
+    ('EGY', 'EG', 'Egypt', '+20'),
+    ('SLV', 'SV', 'El Salvador', '+503'),
+    ('GNQ', 'GQ', 'Equatorial Guinea', '+240'),
+    ('ERI', 'ER', 'Eritrea', '+291'),
+    ('EST', 'EE', 'Estonia', '+372'),
+    ('SWZ', 'SZ', 'Eswatini', '+268'),
+    ('ETH', 'ET', 'Ethiopia', '+251'),
+    ('FLK', 'FK', 'Falkland Islands (Malvinas)', '+500'),
+    ('FRO', 'FO', 'Faroe Islands', '+298'),
+    ('FJI', 'FJ', 'Fiji', '+679'),
+    ('FIN', 'FI', 'Finland', '+358'),
+    ('FRA', 'FR', 'France', '+33'),
+    ('GUF', 'GF', 'French Guiana', '+594'),
+    ('PYF', 'PF', 'French Polynesia', '+689'),
+    ('ATF', 'TF', 'French Southern Territories', NULL),
+    ('GAB', 'GA', 'Gabon', '+241'),
+    ('GMB', 'GM', 'Gambia', '+220'),
+    ('GEO', 'GE', 'Georgia', '+995'),
+    ('DEU', 'DE', 'Germany', '+49'),
+    ('GHA', 'GH', 'Ghana', '+233'),
+    ('GIB', 'GI', 'Gibraltar', '+350'),
+    ('GRC', 'GR', 'Greece', '+30'),
+    ('GRL', 'GL', 'Greenland', '+299'),
+    ('GRD', 'GD', 'Grenada', '+1473'),
+    ('GLP', 'GP', 'Guadeloupe', '+590'),
+    ('GUM', 'GU', 'Guam', '+1671'),
+    ('GTM', 'GT', 'Guatemala', '+502'),
+    ('GGY', 'GG', 'Guernsey', '+44'),
+    ('GIN', 'GN', 'Guinea', '+224'),
+    ('GNB', 'GW', 'Guinea-Bissau', '+245'),
+    ('GUY', 'GY', 'Guyana', '+592'),
+    ('HTI', 'HT', 'Haiti', '+509'),
+    ('HMD', 'HM', 'Heard Island and McDonald Islands', NULL),
+    ('VAT', 'VA', 'Holy See', '+379'),
+    ('HND', 'HN', 'Honduras', '+504'),
+    ('HKG', 'HK', 'Hong Kong', '+852'),
+    ('HUN', 'HU', 'Hungary', '+36'),
+    ('ISL', 'IS', 'Iceland', '+354'),
+    ('IND', 'IN', 'India', '+91'),
+    ('IDN', 'ID', 'Indonesia', '+62'),
+    ('IRN', 'IR', 'Iran', '+98'),
+    ('IRQ', 'IQ', 'Iraq', '+964'),
+    ('IRL', 'IE', 'Ireland', '+353'),
+    ('IMN', 'IM', 'Isle of Man', '+44'),
+    ('ISR', 'IL', 'Israel', '+972'),
+    ('ITA', 'IT', 'Italy', '+39'),
+    ('JAM', 'JM', 'Jamaica', '+1876'),
+    ('JPN', 'JP', 'Japan', '+81'),
+    ('JEY', 'JE', 'Jersey', '+44'),
+    ('JOR', 'JO', 'Jordan', '+962'),
+    ('KAZ', 'KZ', 'Kazakhstan', '+7'),
+    ('KEN', 'KE', 'Kenya', '+254'),
+    ('KIR', 'KI', 'Kiribati', '+686'),
+    ('PRK', 'KP', 'Korea, Democratic People''s Republic of', '+850'),
+    ('KOR', 'KR', 'Korea, Republic of', '+82'),
+    ('KWT', 'KW', 'Kuwait', '+965'),
+    ('KGZ', 'KG', 'Kyrgyzstan', '+996'),
+    ('LAO', 'LA', 'Lao People''s Democratic Republic', '+856'),
+    ('LVA', 'LV', 'Latvia', '+371'),
+    ('LBN', 'LB', 'Lebanon', '+961'),
+    ('LSO', 'LS', 'Lesotho', '+266'),
+    ('LBR', 'LR', 'Liberia', '+231'),
+    ('LBY', 'LY', 'Libya', '+218'),
+    ('LIE', 'LI', 'Liechtenstein', '+423'),
+    ('LTU', 'LT', 'Lithuania', '+370'),
+    ('LUX', 'LU', 'Luxembourg', '+352'),
+    ('MAC', 'MO', 'Macao', '+853'),
+    ('MDG', 'MG', 'Madagascar', '+261'),
+    ('MWI', 'MW', 'Malawi', '+265'),
+    ('MYS', 'MY', 'Malaysia', '+60'),
+    ('MDV', 'MV', 'Maldives', '+960'),
+    ('MLI', 'ML', 'Mali', '+223'),
+    ('MLT', 'MT', 'Malta', '+356'),
+    ('MHL', 'MH', 'Marshall Islands', '+692'),
+    ('MTQ', 'MQ', 'Martinique', '+596'),
+    ('MRT', 'MR', 'Mauritania', '+222'),
+    ('MUS', 'MU', 'Mauritius', '+230'),
+    ('MYT', 'YT', 'Mayotte', '+262'),
+    ('MEX', 'MX', 'Mexico', '+52'),
+    ('FSM', 'FM', 'Micronesia', '+691'),
+    ('MDA', 'MD', 'Moldova', '+373'),
+    ('MCO', 'MC', 'Monaco', '+377'),
+    ('MNG', 'MN', 'Mongolia', '+976'),
+    ('MNE', 'ME', 'Montenegro', '+382'),
+    ('MSR', 'MS', 'Montserrat', '+1664'),
+    ('MAR', 'MA', 'Morocco', '+212'),
+    ('MOZ', 'MZ', 'Mozambique', '+258'),
+    ('MMR', 'MM', 'Myanmar', '+95'),
+    ('NAM', 'NA', 'Namibia', '+264'),
+    ('NRU', 'NR', 'Nauru', '+674'),
+    ('NPL', 'NP', 'Nepal', '+977'),
+    ('NLD', 'NL', 'Netherlands', '+31'),
+    ('NCL', 'NC', 'New Caledonia', '+687'),
+    ('NZL', 'NZ', 'New Zealand', '+64'),
+    ('NIC', 'NI', 'Nicaragua', '+505'),
+    ('NER', 'NE', 'Niger', '+227'),
+    ('NGA', 'NG', 'Nigeria', '+234'),
+    ('NIU', 'NU', 'Niue', '+683'),
+    ('NFK', 'NF', 'Norfolk Island', '+672'),
+    ('MKD', 'MK', 'North Macedonia', '+389'),
+    ('MNP', 'MP', 'Northern Mariana Islands', '+1670'),
+    ('NOR', 'NO', 'Norway', '+47'),
+    ('OMN', 'OM', 'Oman', '+968'),
+    ('PAK', 'PK', 'Pakistan', '+92'),
+    ('PLW', 'PW', 'Palau', '+680'),
+    ('PSE', 'PS', 'Palestine, State of', '+970'),
+    ('PAN', 'PA', 'Panama', '+507'),
+    ('PNG', 'PG', 'Papua New Guinea', '+675'),
+    ('PRY', 'PY', 'Paraguay', '+595'),
+    ('PER', 'PE', 'Peru', '+51'),
+    ('PHL', 'PH', 'Philippines', '+63'),
+    ('PCN', 'PN', 'Pitcairn', '+64'),
+    ('POL', 'PL', 'Poland', '+48'),
+    ('PRT', 'PT', 'Portugal', '+351'),
+    ('PRI', 'PR', 'Puerto Rico', '+1787'),
+    ('QAT', 'QA', 'Qatar', '+974'),
+    ('REU', 'RE', 'Réunion', '+262'),
+    ('ROU', 'RO', 'Romania', '+40'),
+    ('RUS', 'RU', 'Russian Federation', '+7'),
+    ('RWA', 'RW', 'Rwanda', '+250'),
+    ('BLM', 'BL', 'Saint Barthélemy', '+590'),
+    ('SHN', 'SH', 'Saint Helena, Ascension and Tristan da Cunha', '+290'),
+    ('KNA', 'KN', 'Saint Kitts and Nevis', '+1869'),
+    ('LCA', 'LC', 'Saint Lucia', '+1758'),
+    ('MAF', 'MF', 'Saint Martin (French part)', '+590'),
+    ('SPM', 'PM', 'Saint Pierre and Miquelon', '+508'),
+    ('VCT', 'VC', 'Saint Vincent and the Grenadines', '+1784'),
+    ('WSM', 'WS', 'Samoa', '+685'),
+    ('SMR', 'SM', 'San Marino', '+378'),
+    ('STP', 'ST', 'Sao Tome and Principe', '+239'),
+    ('SAU', 'SA', 'Saudi Arabia', '+966'),
+    ('SEN', 'SN', 'Senegal', '+221'),
+    ('SRB', 'RS', 'Serbia', '+381'),
+    ('SYC', 'SC', 'Seychelles', '+248'),
+    ('SLE', 'SL', 'Sierra Leone', '+232'),
+    ('SGP', 'SG', 'Singapore', '+65'),
+    ('SXM', 'SX', 'Sint Maarten (Dutch part)', '+1721'),
+    ('SVK', 'SK', 'Slovakia', '+421'),
+    ('SVN', 'SI', 'Slovenia', '+386'),
+    ('SLB', 'SB', 'Solomon Islands', '+677'),
+    ('SOM', 'SO', 'Somalia', '+252'),
+    ('ZAF', 'ZA', 'South Africa', '+27'),
+    ('SGS', 'GS', 'South Georgia and the South Sandwich Islands', '+500'),
+    ('SSD', 'SS', 'South Sudan', '+211'),
+    ('ESP', 'ES', 'Spain', '+34'),
+    ('LKA', 'LK', 'Sri Lanka', '+94'),
+    ('SDN', 'SD', 'Sudan', '+249'),
+    ('SUR', 'SR', 'Suriname', '+597'),
+    ('SJM', 'SJ', 'Svalbard and Jan Mayen', '+47'),
+    ('SWE', 'SE', 'Sweden', '+46'),
+    ('CHE', 'CH', 'Switzerland', '+41'),
+    ('SYR', 'SY', 'Syrian Arab Republic', '+963'),
+    ('TWN', 'TW', 'Taiwan', '+886'),
+    ('TJK', 'TJ', 'Tajikistan', '+992'),
+    ('TZA', 'TZ', 'Tanzania', '+255'),
+    ('THA', 'TH', 'Thailand', '+66'),
+    ('TLS', 'TL', 'Timor-Leste', '+670'),
+    ('TGO', 'TG', 'Togo', '+228'),
+    ('TKL', 'TK', 'Tokelau', '+690'),
+    ('TON', 'TO', 'Tonga', '+676'),
+    ('TTO', 'TT', 'Trinidad and Tobago', '+1868'),
+    ('TUN', 'TN', 'Tunisia', '+216'),
+    ('TUR', 'TR', 'Turkey', '+90'),
+    ('TKM', 'TM', 'Turkmenistan', '+993'),
+    ('TCA', 'TC', 'Turks and Caicos Islands', '+1649'),
+    ('TUV', 'TV', 'Tuvalu', '+688'),
+    ('UGA', 'UG', 'Uganda', '+256'),
+    ('UKR', 'UA', 'Ukraine', '+380'),
+    ('ARE', 'AE', 'United Arab Emirates', '+971'),
+    ('GBR', 'GB', 'United Kingdom', '+44'),
+    ('USA', 'US', 'United States', '+1'),
+    ('UMI', 'UM', 'United States Minor Outlying Islands', NULL),
+    ('URY', 'UY', 'Uruguay', '+598'),
+    ('UZB', 'UZ', 'Uzbekistan', '+998'),
+    ('VUT', 'VU', 'Vanuatu', '+678'),
+    ('VEN', 'VE', 'Venezuela', '+58'),
+    ('VNM', 'VN', 'Viet Nam', '+84'),
+    ('VGB', 'VG', 'Virgin Islands (British)', '+1284'),
+    ('VIR', 'VI', 'Virgin Islands (U.S.)', '+1340'),
+    ('WLF', 'WF', 'Wallis and Futuna', '+681'),
+    ('ESH', 'EH', 'Western Sahara', '+212'),
+    ('YEM', 'YE', 'Yemen', '+967'),
+    ('ZMB', 'ZM', 'Zambia', '+260'),
+    ('ZWE', 'ZW', 'Zimbabwe', '+263');
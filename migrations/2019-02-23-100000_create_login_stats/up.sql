@@ -0,0 +1,6 @@
+CREATE TABLE login_stats (
+    date DATE NOT NULL,
+    provider VARCHAR NOT NULL,
+    logins BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (date, provider)
+);
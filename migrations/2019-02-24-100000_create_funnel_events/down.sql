@@ -0,0 +1 @@
+DROP TABLE funnel_events;
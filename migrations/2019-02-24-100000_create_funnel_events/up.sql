@@ -0,0 +1,8 @@
+CREATE TABLE funnel_events (
+    email VARCHAR NOT NULL,
+    step VARCHAR NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    PRIMARY KEY (email, step)
+);
+
+CREATE INDEX funnel_events_step_created_at_idx ON funnel_events (step, created_at);
@@ -0,0 +1,5 @@
+DROP TRIGGER IF EXISTS user_deletion_requested_notify ON deletion_requests;
+DROP FUNCTION IF EXISTS notify_user_deletion_requested();
+
+DROP TABLE deletion_confirmations;
+DROP TABLE deletion_requests;
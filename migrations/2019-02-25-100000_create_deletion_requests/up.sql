@@ -0,0 +1,34 @@
+CREATE TABLE deletion_requests (
+    user_id INTEGER PRIMARY KEY,
+    state VARCHAR NOT NULL DEFAULT 'pending',
+    requested_by INTEGER,
+    deadline TIMESTAMP NOT NULL,
+    job_id UUID REFERENCES jobs (id),
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    updated_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+SELECT diesel_manage_updated_at('deletion_requests');
+
+CREATE INDEX deletion_requests_state_deadline_idx ON deletion_requests (state, deadline);
+
+CREATE TABLE deletion_confirmations (
+    user_id INTEGER NOT NULL REFERENCES deletion_requests (user_id) ON DELETE CASCADE,
+    service VARCHAR NOT NULL,
+    approved BOOLEAN NOT NULL,
+    reason VARCHAR,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    PRIMARY KEY (user_id, service)
+);
+
+-- Dependent services listen to the channel to check the user for open obligations
+CREATE OR REPLACE FUNCTION notify_user_deletion_requested() RETURNS trigger AS $$
+BEGIN
+    PERFORM pg_notify('user_deletion_requested', NEW.user_id::text);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER user_deletion_requested_notify
+    AFTER INSERT OR UPDATE OF state ON deletion_requests
+    FOR EACH ROW WHEN (NEW.state = 'pending') EXECUTE PROCEDURE notify_user_deletion_requested();
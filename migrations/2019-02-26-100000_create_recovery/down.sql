@@ -0,0 +1,3 @@
+DROP TABLE recovery_approvals;
+DROP TABLE recovery_requests;
+DROP TABLE trusted_contacts;
@@ -0,0 +1,28 @@
+CREATE TABLE trusted_contacts (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    email VARCHAR NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    UNIQUE (user_id, email)
+);
+
+CREATE TABLE recovery_requests (
+    id UUID PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    state VARCHAR NOT NULL DEFAULT 'pending',
+    required_approvals INTEGER NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    updated_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+SELECT diesel_manage_updated_at('recovery_requests');
+
+CREATE INDEX recovery_requests_user_id_idx ON recovery_requests (user_id);
+
+CREATE TABLE recovery_approvals (
+    request_id UUID NOT NULL REFERENCES recovery_requests (id) ON DELETE CASCADE,
+    contact_id INTEGER NOT NULL REFERENCES trusted_contacts (id) ON DELETE CASCADE,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    PRIMARY KEY (request_id, contact_id)
+);
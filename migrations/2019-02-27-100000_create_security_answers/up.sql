@@ -0,0 +1,7 @@
+CREATE TABLE security_answers (
+    user_id INTEGER NOT NULL REFERENCES users ON DELETE CASCADE,
+    question_id VARCHAR NOT NULL,
+    answer_hash VARCHAR NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    PRIMARY KEY (user_id, question_id)
+);
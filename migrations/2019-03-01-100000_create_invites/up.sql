@@ -0,0 +1,8 @@
+CREATE TABLE invites (
+    code VARCHAR PRIMARY KEY,
+    created_by INTEGER REFERENCES users (id) ON DELETE SET NULL,
+    max_uses INTEGER NOT NULL,
+    uses INTEGER NOT NULL DEFAULT 0,
+    expires_at TIMESTAMP NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
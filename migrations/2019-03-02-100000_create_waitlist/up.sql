@@ -0,0 +1,12 @@
+CREATE TABLE waitlist (
+    email VARCHAR PRIMARY KEY,
+    token VARCHAR NOT NULL UNIQUE,
+    confirmed BOOLEAN NOT NULL DEFAULT 'f',
+    invite_code VARCHAR REFERENCES invites (code) ON DELETE SET NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    updated_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+CREATE INDEX waitlist_confirmed_created_at_idx ON waitlist (confirmed, created_at) WHERE invite_code IS NULL;
+
+SELECT diesel_manage_updated_at('waitlist');
@@ -0,0 +1,7 @@
+CREATE TABLE profile_prompt_dismissals (
+    user_id INTEGER NOT NULL REFERENCES users ON DELETE CASCADE,
+    field VARCHAR NOT NULL,
+    snoozed_until TIMESTAMP NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    PRIMARY KEY (user_id, field)
+);
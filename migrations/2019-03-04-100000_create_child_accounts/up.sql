@@ -0,0 +1,7 @@
+CREATE TABLE child_accounts (
+    child_id INTEGER PRIMARY KEY,
+    parent_id INTEGER NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+CREATE INDEX child_accounts_parent_id_idx ON child_accounts (parent_id);
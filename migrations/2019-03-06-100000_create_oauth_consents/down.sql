@@ -0,0 +1,4 @@
+DROP TABLE oauth_consents;
+
+ALTER TABLE device_codes DROP COLUMN scope;
+ALTER TABLE clients DROP COLUMN is_third_party;
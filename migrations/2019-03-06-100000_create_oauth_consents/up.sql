@@ -0,0 +1,13 @@
+ALTER TABLE clients ADD COLUMN is_third_party BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE device_codes ADD COLUMN scope VARCHAR;
+
+CREATE TABLE oauth_consents (
+    user_id INTEGER NOT NULL,
+    client_id VARCHAR NOT NULL REFERENCES clients (id) ON DELETE CASCADE,
+    scopes VARCHAR[] NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    updated_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    PRIMARY KEY (user_id, client_id)
+);
+
+SELECT diesel_manage_updated_at('oauth_consents');
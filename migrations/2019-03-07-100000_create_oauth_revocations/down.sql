@@ -0,0 +1,4 @@
+DROP TRIGGER IF EXISTS oauth_client_revoked_notify ON oauth_revocations;
+DROP FUNCTION IF EXISTS notify_oauth_client_revoked();
+
+DROP TABLE oauth_revocations;
@@ -0,0 +1,26 @@
+CREATE TABLE oauth_revocations (
+    user_id INTEGER NOT NULL,
+    client_id VARCHAR NOT NULL REFERENCES clients (id) ON DELETE CASCADE,
+    revoke_before TIMESTAMP NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    updated_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    PRIMARY KEY (user_id, client_id)
+);
+
+SELECT diesel_manage_updated_at('oauth_revocations');
+
+-- Gateway and clients listen to the channel to drop tokens issued to the client before `revoke_before`
+CREATE OR REPLACE FUNCTION notify_oauth_client_revoked() RETURNS trigger AS $$
+BEGIN
+    PERFORM pg_notify('oauth_client_revoked', json_build_object(
+        'user_id', NEW.user_id,
+        'client_id', NEW.client_id,
+        'revoke_before', NEW.revoke_before
+    )::text);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER oauth_client_revoked_notify
+    AFTER INSERT OR UPDATE ON oauth_revocations
+    FOR EACH ROW EXECUTE PROCEDURE notify_oauth_client_revoked();
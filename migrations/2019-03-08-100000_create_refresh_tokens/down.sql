@@ -0,0 +1,3 @@
+DROP TABLE refresh_tokens;
+
+ALTER TABLE clients DROP COLUMN refresh_token_cookie;
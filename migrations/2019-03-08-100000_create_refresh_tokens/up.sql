@@ -0,0 +1,19 @@
+ALTER TABLE clients ADD COLUMN refresh_token_cookie BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE refresh_tokens (
+    id UUID PRIMARY KEY,
+    family_id UUID NOT NULL,
+    user_id INTEGER NOT NULL,
+    client_id VARCHAR NOT NULL REFERENCES clients (id) ON DELETE CASCADE,
+    provider VARCHAR NOT NULL,
+    auth_time BIGINT,
+    scope VARCHAR,
+    token_hash VARCHAR NOT NULL UNIQUE,
+    expires_at TIMESTAMP NOT NULL,
+    rotated_at TIMESTAMP,
+    revoked BOOLEAN NOT NULL DEFAULT FALSE,
+    family_created_at TIMESTAMP NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+CREATE INDEX refresh_tokens_family_id_idx ON refresh_tokens (family_id);
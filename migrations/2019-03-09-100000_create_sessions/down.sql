@@ -0,0 +1,3 @@
+DROP TABLE sessions;
+
+ALTER TABLE clients DROP COLUMN session_mode;
@@ -0,0 +1,15 @@
+ALTER TABLE clients ADD COLUMN session_mode BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE sessions (
+    id UUID PRIMARY KEY,
+    session_hash VARCHAR NOT NULL UNIQUE,
+    user_id INTEGER NOT NULL,
+    client_id VARCHAR NOT NULL REFERENCES clients (id) ON DELETE CASCADE,
+    provider VARCHAR NOT NULL,
+    auth_time BIGINT,
+    expires_at TIMESTAMP NOT NULL,
+    last_seen_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+CREATE INDEX sessions_user_id_idx ON sessions (user_id);
@@ -0,0 +1,3 @@
+DROP INDEX user_roles_expires_at_idx;
+
+ALTER TABLE user_roles DROP COLUMN expires_at;
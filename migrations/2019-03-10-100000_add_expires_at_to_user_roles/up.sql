@@ -0,0 +1,3 @@
+ALTER TABLE user_roles ADD COLUMN expires_at TIMESTAMP;
+
+CREATE INDEX user_roles_expires_at_idx ON user_roles (expires_at) WHERE expires_at IS NOT NULL;
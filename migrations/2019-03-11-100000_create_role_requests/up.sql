@@ -0,0 +1,13 @@
+CREATE TABLE role_requests (
+    id UUID PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    name VARCHAR NOT NULL,
+    data JSONB,
+    expires_at TIMESTAMP,
+    requested_by INTEGER NOT NULL,
+    approved_by INTEGER,
+    approved_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+CREATE INDEX role_requests_pending_idx ON role_requests (created_at) WHERE approved_by IS NULL;
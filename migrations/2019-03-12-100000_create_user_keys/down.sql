@@ -0,0 +1,3 @@
+ALTER TABLE users DROP COLUMN sealed_pii;
+
+DROP TABLE user_keys;
@@ -0,0 +1,9 @@
+-- Data keys of users wrapped by the master key. Rows are deleted with users, which makes
+-- their sealed PII unreadable in backups too, so keep backups of this table short-lived.
+CREATE TABLE user_keys (
+    user_id INTEGER PRIMARY KEY REFERENCES users (id) ON DELETE CASCADE,
+    data_key BYTEA NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+ALTER TABLE users ADD COLUMN sealed_pii BYTEA;
@@ -0,0 +1,14 @@
+-- Logins with valid credentials, along with the decision made on their fraud score.
+-- Blocked attempts are recorded too, to be reviewed by moderators.
+CREATE TABLE login_history (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    provider VARCHAR NOT NULL,
+    client_ip VARCHAR,
+    user_agent VARCHAR,
+    fraud_score DOUBLE PRECISION,
+    decision VARCHAR NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+CREATE INDEX login_history_user_id_created_at_idx ON login_history (user_id, created_at);
@@ -0,0 +1,13 @@
+-- Devices users logged in from, told apart by fingerprints sent by clients. Trusted devices
+-- skip step-up authentication of suspicious logins until `trusted_until`.
+CREATE TABLE devices (
+    id UUID PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    fingerprint VARCHAR NOT NULL,
+    name VARCHAR,
+    trusted_until TIMESTAMP,
+    first_seen_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    last_seen_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+CREATE UNIQUE INDEX devices_user_id_fingerprint_idx ON devices (user_id, fingerprint);
@@ -0,0 +1,2 @@
+ALTER TABLE clients DROP COLUMN remember_me_refresh_timeout_s;
+ALTER TABLE clients DROP COLUMN remember_me_expiration_s;
@@ -0,0 +1,2 @@
+ALTER TABLE clients ADD COLUMN remember_me_expiration_s BIGINT;
+ALTER TABLE clients ADD COLUMN remember_me_refresh_timeout_s BIGINT;
@@ -0,0 +1 @@
+DROP INDEX refresh_tokens_user_id_idx;
@@ -0,0 +1,11 @@
+CREATE TABLE role_access_policies (
+    role VARCHAR PRIMARY KEY,
+    starts_at TIME NOT NULL,
+    ends_at TIME NOT NULL,
+    utc_offset_minutes INTEGER NOT NULL DEFAULT 0,
+    restrict_login BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    updated_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+SELECT diesel_manage_updated_at('role_access_policies');
@@ -0,0 +1 @@
+ALTER TABLE identities DROP COLUMN must_change_password;
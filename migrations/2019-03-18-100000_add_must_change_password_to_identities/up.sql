@@ -0,0 +1 @@
+ALTER TABLE identities ADD COLUMN must_change_password BOOLEAN NOT NULL DEFAULT FALSE;
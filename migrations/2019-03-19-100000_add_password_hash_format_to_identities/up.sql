@@ -0,0 +1 @@
+ALTER TABLE identities ADD COLUMN password_hash_format VARCHAR;
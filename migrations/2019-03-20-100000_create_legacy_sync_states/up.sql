@@ -0,0 +1,12 @@
+CREATE TABLE legacy_sync_states (
+    user_id INTEGER PRIMARY KEY,
+    operation VARCHAR NOT NULL,
+    synced BOOLEAN NOT NULL,
+    error VARCHAR,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    updated_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+CREATE INDEX legacy_sync_states_synced_idx ON legacy_sync_states (synced);
+
+SELECT diesel_manage_updated_at('legacy_sync_states');
@@ -0,0 +1,13 @@
+CREATE TABLE crm_drifts (
+    id SERIAL PRIMARY KEY,
+    job_id UUID NOT NULL REFERENCES jobs (id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL,
+    emarsys_id INTEGER NOT NULL,
+    field VARCHAR NOT NULL,
+    user_value VARCHAR,
+    crm_value VARCHAR,
+    corrected BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+CREATE INDEX crm_drifts_job_id_idx ON crm_drifts (job_id);
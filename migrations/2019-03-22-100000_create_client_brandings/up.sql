@@ -0,0 +1,10 @@
+CREATE TABLE client_brandings (
+    client_id VARCHAR PRIMARY KEY REFERENCES clients (id) ON DELETE CASCADE,
+    app_name VARCHAR NOT NULL,
+    support_email VARCHAR NOT NULL,
+    logo_url VARCHAR,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    updated_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+SELECT diesel_manage_updated_at('client_brandings');
@@ -0,0 +1 @@
+ALTER TABLE device_codes ADD COLUMN last_polled_at TIMESTAMP;
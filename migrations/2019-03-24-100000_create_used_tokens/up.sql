@@ -0,0 +1,8 @@
+-- Ids of single-use signed tokens, kept until the token would have expired anyway
+CREATE TABLE used_tokens (
+    id VARCHAR PRIMARY KEY,
+    expires_at TIMESTAMP NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+CREATE INDEX used_tokens_expires_at_idx ON used_tokens (expires_at);
@@ -0,0 +1,10 @@
+ALTER TABLE funnel_events ADD COLUMN email VARCHAR;
+
+UPDATE funnel_events SET email = users.email FROM users WHERE users.id = funnel_events.user_id;
+
+DELETE FROM funnel_events WHERE email IS NULL;
+
+ALTER TABLE funnel_events DROP CONSTRAINT funnel_events_pkey;
+ALTER TABLE funnel_events DROP COLUMN user_id;
+ALTER TABLE funnel_events ALTER COLUMN email SET NOT NULL;
+ALTER TABLE funnel_events ADD PRIMARY KEY (email, step);
@@ -0,0 +1,11 @@
+ALTER TABLE funnel_events ADD COLUMN user_id INTEGER;
+
+UPDATE funnel_events SET user_id = users.id FROM users WHERE lower(users.email) = lower(funnel_events.email);
+
+-- events of users deleted since are not counted anymore
+DELETE FROM funnel_events WHERE user_id IS NULL;
+
+ALTER TABLE funnel_events DROP CONSTRAINT funnel_events_pkey;
+ALTER TABLE funnel_events DROP COLUMN email;
+ALTER TABLE funnel_events ALTER COLUMN user_id SET NOT NULL;
+ALTER TABLE funnel_events ADD PRIMARY KEY (user_id, step);
@@ -0,0 +1 @@
+ALTER TABLE deletion_requests DROP COLUMN not_before;
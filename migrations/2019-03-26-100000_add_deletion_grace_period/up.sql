@@ -0,0 +1,2 @@
+-- deletions are not approved before the grace period passes, even if all services confirmed them
+ALTER TABLE deletion_requests ADD COLUMN not_before TIMESTAMP NOT NULL DEFAULT current_timestamp;
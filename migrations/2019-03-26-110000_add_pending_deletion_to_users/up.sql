@@ -0,0 +1 @@
+ALTER TABLE users ADD COLUMN pending_deletion BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,5 @@
+CREATE TABLE user_keys (
+    user_id INTEGER PRIMARY KEY REFERENCES users (id) ON DELETE CASCADE,
+    data_key BYTEA NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
@@ -0,0 +1,3 @@
+-- Data keys of users are kept in the key store database, see `key_store_migrations`, so that
+-- backups of users don't hold the keys of their sealed PII. Copy the keys to the key store first.
+DROP TABLE user_keys;
@@ -0,0 +1,7 @@
+-- Social providers unlinked from users, logins with them don't attach to the user again
+CREATE TABLE provider_blocks (
+    user_id INTEGER NOT NULL,
+    provider VARCHAR NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    PRIMARY KEY (user_id, provider)
+);
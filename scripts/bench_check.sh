@@ -0,0 +1,25 @@
+#!/bin/sh
+# Fails if mean time of any benchmark increased by more than THRESHOLD percent
+# compared to the baseline of the last criterion run. Requires jq.
+#
+#   cargo bench --features test-mocks --bench auth -- --save-baseline master   # on master
+#   cargo bench --features test-mocks --bench auth -- --baseline master        # on a branch
+#   scripts/bench_check.sh 10
+
+THRESHOLD=${1:-10}
+CRITERION_DIR=${CRITERION_DIR:-target/criterion}
+
+status=0
+for change in "$CRITERION_DIR"/*/change/estimates.json; do
+    [ -f "$change" ] || continue
+    name=$(basename "$(dirname "$(dirname "$change")")")
+    percent=$(jq '.Mean.point_estimate * 100' "$change")
+    if awk -v percent="$percent" -v threshold="$THRESHOLD" 'BEGIN { exit !(percent > threshold) }'; then
+        echo "REGRESSION $name: ${percent}% (threshold ${THRESHOLD}%)"
+        status=1
+    else
+        echo "ok $name: ${percent}%"
+    fi
+done
+
+exit $status
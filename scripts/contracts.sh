@@ -0,0 +1,25 @@
+#!/bin/sh
+# Generates contracts of the HTTP API with its consumers to CONTRACTS_DIR and publishes them
+# to the Pact broker at PACT_BROKER_URL, if set, tagged with the current commit. Without the
+# broker contracts are left in CONTRACTS_DIR to be kept as CI artifacts.
+#
+#   scripts/contracts.sh
+#   PACT_BROKER_URL=http://pact-broker scripts/contracts.sh
+
+set -e
+
+export CONTRACTS_DIR=${CONTRACTS_DIR:-target/contracts}
+VERSION=${VERSION:-$(git rev-parse --short HEAD)}
+
+cargo test --features test-mocks --test contracts
+
+for pact in "$CONTRACTS_DIR"/users-*.json; do
+    consumer=$(basename "$pact" .json | sed 's/^users-//')
+    if [ -n "$PACT_BROKER_URL" ]; then
+        echo "Publishing contract of $consumer, version $VERSION"
+        curl --fail -s -X PUT -H "Content-Type: application/json" --data-binary "@$pact" \
+            "$PACT_BROKER_URL/pacts/provider/users/consumer/$consumer/version/$VERSION" > /dev/null
+    else
+        echo "Contract of $consumer: $pact"
+    fi
+done
@@ -0,0 +1,117 @@
+//! Propagation of cache invalidations between instances. Instances keep in-process copies
+//! of cached values, so invalidated keys are published to a Redis channel and every
+//! instance drops them from its in-process caches as soon as the message arrives.
+//! Deployments without Redis can rely on NOTIFY sent by database triggers instead.
+
+use std::io;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use failure::Error as FailureError;
+use fallible_iterator::FallibleIterator;
+use postgres::{Connection, TlsMode};
+use r2d2::Pool;
+use r2d2_redis::redis;
+use r2d2_redis::RedisConnectionManager;
+
+/// Delay before resubscribing after connection to Redis or Postgres is lost
+const RESUBSCRIBE_DELAY_MS: u64 = 1000;
+
+pub trait InvalidationPublisher: Send + Sync {
+    /// Notifies all instances that the key was invalidated
+    fn publish(&self, key: &str) -> Result<(), FailureError>;
+}
+
+pub struct RedisInvalidationPublisher {
+    pool: Pool<RedisConnectionManager>,
+    channel: String,
+}
+
+impl RedisInvalidationPublisher {
+    pub fn new(pool: Pool<RedisConnectionManager>, channel: String) -> Self {
+        Self { pool, channel }
+    }
+}
+
+impl InvalidationPublisher for RedisInvalidationPublisher {
+    fn publish(&self, key: &str) -> Result<(), FailureError> {
+        let conn = self.pool.get()?;
+        redis::cmd("PUBLISH").arg(&self.channel).arg(key).query::<usize>(&*conn)?;
+        Ok(())
+    }
+}
+
+/// Calls `on_invalidate` with every key published to the channel, in a background thread.
+/// Invalidations published while the connection is lost are missed,
+/// such values are dropped after ttl of in-process cache.
+pub fn subscribe_redis<F>(redis_url: String, channel: String, on_invalidate: F) -> io::Result<JoinHandle<()>>
+where
+    F: Fn(&str) + Send + 'static,
+{
+    thread::Builder::new()
+        .name(format!("invalidations<{}>", channel))
+        .spawn(move || loop {
+            if let Err(err) = listen(&redis_url, &channel, &on_invalidate) {
+                error!("Lost subscription to invalidations at channel '{}': {}", channel, err);
+            }
+            thread::sleep(Duration::from_millis(RESUBSCRIBE_DELAY_MS));
+        })
+}
+
+fn listen<F>(redis_url: &str, channel: &str, on_invalidate: &F) -> Result<(), FailureError>
+where
+    F: Fn(&str),
+{
+    let client = redis::Client::open(redis_url)?;
+    let mut pubsub = client.get_pubsub()?;
+    pubsub.subscribe(channel)?;
+    info!("Subscribed to invalidations at channel '{}'", channel);
+
+    loop {
+        let key = pubsub.get_message()?.get_payload::<String>()?;
+        debug!("Received invalidation of key '{}' at channel '{}'", key, channel);
+        on_invalidate(&key);
+    }
+}
+
+/// Calls `on_notification` with channel and payload of every NOTIFY sent to the channels
+/// of the database, in a background thread. Notifications sent while the connection is lost are missed.
+pub fn subscribe_postgres<F>(database_url: String, channels: Vec<String>, on_notification: F) -> io::Result<JoinHandle<()>>
+where
+    F: Fn(&str, &str) + Send + 'static,
+{
+    thread::Builder::new()
+        .name("invalidations<postgres>".to_string())
+        .spawn(move || loop {
+            if let Err(err) = listen_postgres(&database_url, &channels, &on_notification) {
+                error!("Lost subscription to database notifications at channels {:?}: {}", channels, err);
+            }
+            thread::sleep(Duration::from_millis(RESUBSCRIBE_DELAY_MS));
+        })
+}
+
+fn listen_postgres<F>(database_url: &str, channels: &[String], on_notification: &F) -> Result<(), FailureError>
+where
+    F: Fn(&str, &str),
+{
+    let conn = Connection::connect(database_url, TlsMode::None)?;
+    for channel in channels {
+        conn.batch_execute(&format!("LISTEN {}", channel))?;
+    }
+    info!("Listening to database notifications at channels {:?}", channels);
+
+    let notifications = conn.notifications();
+    let mut iter = notifications.blocking_iter();
+    loop {
+        match iter.next()? {
+            Some(notification) => {
+                debug!(
+                    "Received database notification '{}' at channel '{}'",
+                    notification.payload, notification.channel
+                );
+                on_notification(&notification.channel, &notification.payload);
+            }
+            None => return Err(format_err!("Database connection is closed")),
+        }
+    }
+}
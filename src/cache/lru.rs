@@ -0,0 +1,175 @@
+//! In-process LRU cache with expiration, used by in-memory cache backend and in front of
+//! shared caches on hot paths
+
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+struct Entry<V> {
+    value: V,
+    expires_at: Instant,
+    /// Position in recency order, the smallest tick is the least recently used
+    tick: u64,
+}
+
+pub struct LruCache<K, V> {
+    capacity: usize,
+    ttl: Duration,
+    entries: HashMap<K, Entry<V>>,
+    order: BTreeMap<u64, K>,
+    tick: u64,
+}
+
+impl<K, V> LruCache<K, V>
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+{
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            capacity,
+            ttl,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns value and marks it as recently used, expired values are dropped
+    pub fn get(&mut self, key: &K) -> Option<V> {
+        let expired = match self.entries.get(key) {
+            Some(entry) => Instant::now() >= entry.expires_at,
+            None => return None,
+        };
+        if expired {
+            self.remove(key);
+            return None;
+        }
+
+        let tick = self.next_tick();
+        let entry = self.entries.get_mut(key)?;
+        self.order.remove(&entry.tick);
+        self.order.insert(tick, key.clone());
+        entry.tick = tick;
+        Some(entry.value.clone())
+    }
+
+    /// Inserts value expiring after default ttl, evicting the least recently used ones if cache is full
+    pub fn insert(&mut self, key: K, value: V) {
+        let ttl = self.ttl;
+        self.insert_with_ttl(key, value, ttl);
+    }
+
+    pub fn insert_with_ttl(&mut self, key: K, value: V, ttl: Duration) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        self.remove(&key);
+        while self.entries.len() >= self.capacity {
+            let oldest = match self.order.keys().next() {
+                Some(&tick) => tick,
+                None => break,
+            };
+            if let Some(oldest_key) = self.order.remove(&oldest) {
+                self.entries.remove(&oldest_key);
+            }
+        }
+
+        let tick = self.next_tick();
+        self.order.insert(tick, key.clone());
+        self.entries.insert(
+            key,
+            Entry {
+                value,
+                expires_at: Instant::now() + ttl,
+                tick,
+            },
+        );
+    }
+
+    /// Updates value in place keeping its expiry, returns `false` if there is no value
+    pub fn update<F>(&mut self, key: &K, f: F) -> bool
+    where
+        F: FnOnce(&mut V),
+    {
+        if self.get(key).is_none() {
+            return false;
+        }
+        match self.entries.get_mut(key) {
+            Some(entry) => {
+                f(&mut entry.value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn remove(&mut self, key: &K) -> bool {
+        match self.entries.remove(key) {
+            Some(entry) => {
+                self.order.remove(&entry.tick);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lru_eviction() {
+        let mut cache = LruCache::new(2, Duration::from_secs(60));
+        cache.insert(1, "one");
+        cache.insert(2, "two");
+        assert_eq!(cache.get(&1), Some("one"));
+        // 2 is the least recently used now
+        cache.insert(3, "three");
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&1), Some("one"));
+        assert_eq!(cache.get(&3), Some("three"));
+    }
+
+    #[test]
+    fn test_lru_expiration_and_removal() {
+        let mut cache = LruCache::new(2, Duration::from_secs(0));
+        cache.insert(1, "one");
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.is_empty(), true);
+
+        let mut cache = LruCache::new(2, Duration::from_secs(60));
+        cache.insert(1, "one");
+        assert_eq!(cache.remove(&1), true);
+        assert_eq!(cache.remove(&1), false);
+        assert_eq!(cache.get(&1), None);
+
+        cache.insert_with_ttl(1, "one", Duration::from_secs(0));
+        cache.insert(2, "two");
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get(&2), Some("two"));
+        cache.clear();
+        assert_eq!(cache.is_empty(), true);
+    }
+}
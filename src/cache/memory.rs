@@ -0,0 +1,142 @@
+//! Cache keeping values in process memory, values are not shared between instances
+
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+use failure::Error as FailureError;
+
+use super::lru::LruCache;
+use super::{Cache, CacheMetrics, CacheStats, Counter};
+
+pub struct InMemoryCache<T> {
+    entries: Mutex<LruCache<String, T>>,
+    metrics: CacheMetrics,
+}
+
+impl<T> InMemoryCache<T>
+where
+    T: Clone,
+{
+    /// Creates cache holding at most `capacity` values expiring after `ttl` by default
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            entries: Mutex::new(LruCache::new(capacity, ttl)),
+            metrics: CacheMetrics::default(),
+        }
+    }
+
+    fn entries(&self) -> Result<MutexGuard<LruCache<String, T>>, FailureError> {
+        self.entries.lock().map_err(|_| format_err!("In-memory cache lock is poisoned"))
+    }
+}
+
+impl<T> Cache<T> for InMemoryCache<T>
+where
+    T: Clone + Send,
+{
+    fn get(&self, key: &str) -> Result<Option<T>, FailureError> {
+        let result = self.entries().map(|mut entries| entries.get(&key.to_string()));
+        self.metrics.record_get(&result);
+        result
+    }
+
+    fn set(&self, key: &str, value: T) -> Result<(), FailureError> {
+        let result = self.entries().map(|mut entries| entries.insert(key.to_string(), value));
+        self.metrics.record_set(&result);
+        result
+    }
+
+    fn set_with_ttl(&self, key: &str, value: T, ttl: Duration) -> Result<(), FailureError> {
+        let result = self
+            .entries()
+            .map(|mut entries| entries.insert_with_ttl(key.to_string(), value, ttl));
+        self.metrics.record_set(&result);
+        result
+    }
+
+    fn remove(&self, key: &str) -> Result<bool, FailureError> {
+        let result = self.entries().map(|mut entries| entries.remove(&key.to_string()));
+        self.metrics.record_remove(&result);
+        result
+    }
+
+    fn clear(&self) -> Result<(), FailureError> {
+        let result = self.entries().map(|mut entries| entries.clear());
+        self.metrics.record_remove(&result);
+        result
+    }
+
+    fn stats(&self) -> CacheStats {
+        self.metrics.stats()
+    }
+}
+
+impl Counter for InMemoryCache<u32> {
+    fn increment(&self, key: &str) -> Result<u32, FailureError> {
+        let result = self.entries().map(|mut entries| {
+            let key = key.to_string();
+            let mut value = 1;
+            let updated = entries.update(&key, |counter| {
+                *counter = counter.saturating_add(1);
+                value = *counter;
+            });
+            if !updated {
+                entries.insert(key, value);
+            }
+            value
+        });
+        self.metrics.record_set(&result);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn test_in_memory_cache() {
+        let cache = InMemoryCache::new(10, Duration::from_secs(60));
+        assert_eq!(cache.get("key").unwrap(), None::<u32>);
+        cache.set("key", 1).unwrap();
+        cache.set_with_ttl("expired", 2, Duration::from_secs(0)).unwrap();
+        assert_eq!(cache.get("key").unwrap(), Some(1));
+        assert_eq!(cache.get("expired").unwrap(), None);
+        assert_eq!(cache.remove("key").unwrap(), true);
+        assert_eq!(cache.get("key").unwrap(), None);
+
+        cache.set("key", 3).unwrap();
+        cache.clear().unwrap();
+        assert_eq!(cache.get("key").unwrap(), None);
+
+        let stats = cache.stats();
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.misses, 4);
+        assert_eq!(stats.sets, 3);
+    }
+
+    #[test]
+    fn test_in_memory_counter() {
+        let counter = Arc::new(InMemoryCache::<u32>::new(10, Duration::from_secs(60)));
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let counter = counter.clone();
+                thread::spawn(move || {
+                    for _ in 0..25 {
+                        counter.increment("key").unwrap();
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(counter.get("key").unwrap(), Some(100));
+        assert_eq!(counter.increment("key").unwrap(), 101);
+        assert_eq!(counter.remove("key").unwrap(), true);
+        assert_eq!(counter.increment("key").unwrap(), 1);
+    }
+}
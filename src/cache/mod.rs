@@ -0,0 +1,215 @@
+//! Cache is a module with key-value caches shared by repos and services, e.g. roles and
+//! attempts caches. Backend is selected in config: `noop` caches nothing, `memory` keeps
+//! values in process, `redis` shares them between instances. Every cache has its own
+//! namespace, so that caches on the same backend can be invalidated independently.
+
+pub mod invalidation;
+pub mod lru;
+pub mod memory;
+pub mod noop;
+pub mod redis;
+
+pub use self::invalidation::{InvalidationPublisher, RedisInvalidationPublisher};
+pub use self::memory::InMemoryCache;
+pub use self::noop::NoopCache;
+pub use self::redis::RedisCache;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use failure::Error as FailureError;
+use r2d2::Pool;
+use r2d2_redis::RedisConnectionManager;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use config::{CacheBackend, Config};
+
+pub trait Cache<T>: Send + Sync {
+    fn get(&self, key: &str) -> Result<Option<T>, FailureError>;
+
+    /// Sets value expiring after default ttl of the cache
+    fn set(&self, key: &str, value: T) -> Result<(), FailureError>;
+
+    fn set_with_ttl(&self, key: &str, value: T, ttl: Duration) -> Result<(), FailureError>;
+
+    /// Returns `true` if value was removed
+    fn remove(&self, key: &str) -> Result<bool, FailureError>;
+
+    /// Removes all values of the cache
+    fn clear(&self) -> Result<(), FailureError>;
+
+    fn stats(&self) -> CacheStats;
+}
+
+/// Counters, e.g. of failed attempts. Increments are atomic, so that concurrent ones are not lost
+pub trait Counter: Cache<u32> {
+    /// Increments the counter expiring after default ttl of the cache since the first increment,
+    /// returns updated value
+    fn increment(&self, key: &str) -> Result<u32, FailureError>;
+}
+
+impl<T, C> Cache<T> for Box<C>
+where
+    C: Cache<T> + ?Sized,
+{
+    fn get(&self, key: &str) -> Result<Option<T>, FailureError> {
+        (**self).get(key)
+    }
+
+    fn set(&self, key: &str, value: T) -> Result<(), FailureError> {
+        (**self).set(key, value)
+    }
+
+    fn set_with_ttl(&self, key: &str, value: T, ttl: Duration) -> Result<(), FailureError> {
+        (**self).set_with_ttl(key, value, ttl)
+    }
+
+    fn remove(&self, key: &str) -> Result<bool, FailureError> {
+        (**self).remove(key)
+    }
+
+    fn clear(&self) -> Result<(), FailureError> {
+        (**self).clear()
+    }
+
+    fn stats(&self) -> CacheStats {
+        (**self).stats()
+    }
+}
+
+impl<C> Counter for Box<C>
+where
+    C: Counter + ?Sized,
+{
+    fn increment(&self, key: &str) -> Result<u32, FailureError> {
+        (**self).increment(key)
+    }
+}
+
+/// Snapshot of cache usage counters
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+    pub sets: usize,
+    pub removals: usize,
+    pub errors: usize,
+}
+
+/// Cache usage counters, updated by cache backends
+#[derive(Debug, Default)]
+pub struct CacheMetrics {
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+    sets: AtomicUsize,
+    removals: AtomicUsize,
+    errors: AtomicUsize,
+}
+
+impl CacheMetrics {
+    /// Counts result of `get`
+    pub fn record_get<T>(&self, result: &Result<Option<T>, FailureError>) {
+        match *result {
+            Ok(Some(_)) => self.hits.fetch_add(1, Ordering::Relaxed),
+            Ok(None) => self.misses.fetch_add(1, Ordering::Relaxed),
+            Err(_) => self.errors.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+
+    pub fn record_set<R>(&self, result: &Result<R, FailureError>) {
+        match *result {
+            Ok(_) => self.sets.fetch_add(1, Ordering::Relaxed),
+            Err(_) => self.errors.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+
+    pub fn record_remove<R>(&self, result: &Result<R, FailureError>) {
+        match *result {
+            Ok(_) => self.removals.fetch_add(1, Ordering::Relaxed),
+            Err(_) => self.errors.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            sets: self.sets.load(Ordering::Relaxed),
+            removals: self.removals.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Creates caches of configured backend
+#[derive(Clone)]
+pub struct CacheFactory {
+    backend: CacheBackend,
+    memory_capacity: usize,
+    redis_pool: Option<Pool<RedisConnectionManager>>,
+}
+
+impl CacheFactory {
+    pub fn new(config: &Config) -> Result<Self, FailureError> {
+        let backend = config.cache.backend.clone().unwrap_or_else(|| {
+            if config.server.redis.is_some() {
+                CacheBackend::Redis
+            } else {
+                CacheBackend::Noop
+            }
+        });
+
+        if backend == CacheBackend::Redis && config.server.redis.is_none() {
+            return Err(format_err!("Redis URL must be set in configuration for redis cache backend"));
+        }
+
+        // Redis is also used to propagate invalidations with other backends
+        let redis_pool = match config.server.redis {
+            Some(ref redis_url) => {
+                let redis_manager = RedisConnectionManager::new(redis_url.as_ref())?;
+                Some(Pool::builder().build(redis_manager)?)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            backend,
+            memory_capacity: config.cache.memory_capacity,
+            redis_pool,
+        })
+    }
+
+    /// Creates cache with keys prefixed by `namespace` and values expiring after `ttl`
+    pub fn create<T>(&self, namespace: &str, ttl: Duration) -> Box<Cache<T>>
+    where
+        T: Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+    {
+        match (&self.backend, &self.redis_pool) {
+            (&CacheBackend::Redis, &Some(ref redis_pool)) => Box::new(RedisCache::new(redis_pool.clone(), namespace.to_string(), ttl)),
+            (&CacheBackend::Memory, _) => Box::new(InMemoryCache::new(self.memory_capacity, ttl)),
+            _ => Box::new(NoopCache::default()),
+        }
+    }
+
+    /// Creates counters with keys prefixed by `namespace` and expiring after `ttl`. Counters are
+    /// kept in Redis if it is set and in memory otherwise, `noop` backend is never used for them
+    pub fn create_counter(&self, namespace: &str, ttl: Duration) -> Box<Counter> {
+        match self.redis_pool {
+            Some(ref redis_pool) => Box::new(RedisCache::<u32>::new(redis_pool.clone(), namespace.to_string(), ttl)),
+            None => Box::new(InMemoryCache::<u32>::new(self.memory_capacity, ttl)),
+        }
+    }
+
+    /// Whether counters are shared between instances
+    pub fn has_shared_counters(&self) -> bool {
+        self.redis_pool.is_some()
+    }
+
+    /// Publisher of invalidations to other instances, if Redis is configured
+    pub fn invalidation_publisher(&self, channel: &str) -> Option<RedisInvalidationPublisher> {
+        self.redis_pool
+            .as_ref()
+            .map(|redis_pool| RedisInvalidationPublisher::new(redis_pool.clone(), channel.to_string()))
+    }
+}
@@ -0,0 +1,40 @@
+//! Cache that stores nothing, used when caching is disabled
+
+use std::time::Duration;
+
+use failure::Error as FailureError;
+
+use super::{Cache, CacheMetrics, CacheStats};
+
+#[derive(Debug, Default)]
+pub struct NoopCache {
+    metrics: CacheMetrics,
+}
+
+impl<T> Cache<T> for NoopCache {
+    fn get(&self, _key: &str) -> Result<Option<T>, FailureError> {
+        let result = Ok(None);
+        self.metrics.record_get(&result);
+        result
+    }
+
+    fn set(&self, _key: &str, _value: T) -> Result<(), FailureError> {
+        Ok(())
+    }
+
+    fn set_with_ttl(&self, _key: &str, _value: T, _ttl: Duration) -> Result<(), FailureError> {
+        Ok(())
+    }
+
+    fn remove(&self, _key: &str) -> Result<bool, FailureError> {
+        Ok(false)
+    }
+
+    fn clear(&self) -> Result<(), FailureError> {
+        Ok(())
+    }
+
+    fn stats(&self) -> CacheStats {
+        self.metrics.stats()
+    }
+}
@@ -0,0 +1,172 @@
+//! Cache storing JSON serialized values in Redis, shared between instances
+
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use failure::Error as FailureError;
+use r2d2::{Pool, PooledConnection};
+use r2d2_redis::redis;
+use r2d2_redis::RedisConnectionManager;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json;
+
+use super::{Cache, CacheMetrics, CacheStats, Counter};
+
+/// Number of keys fetched by one `SCAN` when clearing the cache
+const SCAN_COUNT: usize = 1000;
+
+/// Increments the counter and sets its expiry on the first increment in one step
+const INCREMENT_SCRIPT: &'static str = r#"
+local value = redis.call('INCR', KEYS[1])
+if value == 1 then
+    redis.call('PEXPIRE', KEYS[1], ARGV[1])
+end
+return value
+"#;
+
+pub struct RedisCache<T> {
+    pool: Pool<RedisConnectionManager>,
+    namespace: String,
+    ttl: Duration,
+    metrics: CacheMetrics,
+    _value: PhantomData<fn() -> T>,
+}
+
+impl<T> RedisCache<T>
+where
+    T: Serialize + DeserializeOwned,
+{
+    pub fn new(pool: Pool<RedisConnectionManager>, namespace: String, ttl: Duration) -> Self {
+        Self {
+            pool,
+            namespace,
+            ttl,
+            metrics: CacheMetrics::default(),
+            _value: PhantomData,
+        }
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}:{}", self.namespace, key)
+    }
+
+    fn connection(&self) -> Result<PooledConnection<RedisConnectionManager>, FailureError> {
+        self.pool.get().map_err(FailureError::from)
+    }
+
+    fn get_value(&self, key: &str) -> Result<Option<T>, FailureError> {
+        let conn = self.connection()?;
+        let value = redis::cmd("GET").arg(self.key(key)).query::<Option<String>>(&*conn)?;
+        match value {
+            Some(value) => Ok(Some(serde_json::from_str(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn set_value(&self, key: &str, value: T, ttl: Duration) -> Result<(), FailureError> {
+        let conn = self.connection()?;
+        let value = serde_json::to_string(&value)?;
+        redis::cmd("SET")
+            .arg(self.key(key))
+            .arg(value)
+            .arg("PX")
+            .arg(ttl_ms(ttl))
+            .query::<()>(&*conn)?;
+        Ok(())
+    }
+
+    fn remove_value(&self, key: &str) -> Result<bool, FailureError> {
+        let conn = self.connection()?;
+        let removed = redis::cmd("DEL").arg(self.key(key)).query::<usize>(&*conn)?;
+        Ok(removed > 0)
+    }
+
+    /// Removes keys of the namespace in batches, so that Redis is not blocked by `KEYS`
+    fn clear_values(&self) -> Result<(), FailureError> {
+        let conn = self.connection()?;
+        let pattern = self.key("*");
+        let mut cursor = 0u64;
+        loop {
+            let (next_cursor, keys) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(&pattern)
+                .arg("COUNT")
+                .arg(SCAN_COUNT)
+                .query::<(u64, Vec<String>)>(&*conn)?;
+            if !keys.is_empty() {
+                redis::cmd("DEL").arg(keys).query::<usize>(&*conn)?;
+            }
+            if next_cursor == 0 {
+                return Ok(());
+            }
+            cursor = next_cursor;
+        }
+    }
+}
+
+impl RedisCache<u32> {
+    fn increment_value(&self, key: &str) -> Result<u32, FailureError> {
+        let conn = self.connection()?;
+        let value = redis::cmd("EVAL")
+            .arg(INCREMENT_SCRIPT)
+            .arg(1)
+            .arg(self.key(key))
+            .arg(ttl_ms(self.ttl))
+            .query::<u32>(&*conn)?;
+        Ok(value)
+    }
+}
+
+impl Counter for RedisCache<u32> {
+    fn increment(&self, key: &str) -> Result<u32, FailureError> {
+        let result = self.increment_value(key);
+        self.metrics.record_set(&result);
+        result
+    }
+}
+
+/// Redis expiry in milliseconds, zero ttl is rejected by Redis
+fn ttl_ms(ttl: Duration) -> u64 {
+    (ttl.as_secs() * 1000 + u64::from(ttl.subsec_millis())).max(1)
+}
+
+impl<T> Cache<T> for RedisCache<T>
+where
+    T: Serialize + DeserializeOwned,
+{
+    fn get(&self, key: &str) -> Result<Option<T>, FailureError> {
+        let result = self.get_value(key);
+        self.metrics.record_get(&result);
+        result
+    }
+
+    fn set(&self, key: &str, value: T) -> Result<(), FailureError> {
+        let result = self.set_value(key, value, self.ttl);
+        self.metrics.record_set(&result);
+        result
+    }
+
+    fn set_with_ttl(&self, key: &str, value: T, ttl: Duration) -> Result<(), FailureError> {
+        let result = self.set_value(key, value, ttl);
+        self.metrics.record_set(&result);
+        result
+    }
+
+    fn remove(&self, key: &str) -> Result<bool, FailureError> {
+        let result = self.remove_value(key);
+        self.metrics.record_remove(&result);
+        result
+    }
+
+    fn clear(&self) -> Result<(), FailureError> {
+        let result = self.clear_values();
+        self.metrics.record_remove(&result);
+        result
+    }
+
+    fn stats(&self) -> CacheStats {
+        self.metrics.stats()
+    }
+}
@@ -0,0 +1,126 @@
+//! Typed client of the users service API for other Rust services, enabled by the `client`
+//! feature. Requests and responses are models of the service itself, so clients can't drift
+//! from them.
+//!
+//! Requests go directly to the service, they are authorized as the user set by `with_user_id`
+//! the same way the gateway does it.
+
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::future;
+use futures::Future;
+use hyper::header::{Authorization, ContentType};
+use hyper::{Headers, Method};
+use serde::de::DeserializeOwned;
+use serde_json;
+use serde_urlencoded;
+
+use stq_http::client::HttpClient;
+use stq_types::UserId;
+
+use errors::Error;
+use models::{Introspection, IntrospectionRequest, User, UserSearchResults, UsersSearchTerms};
+
+pub type ClientFuture<T> = Box<Future<Item = T, Error = FailureError>>;
+
+pub trait UsersClient {
+    /// Returns user by id, none if it does not exist
+    fn get_user(&self, user_id: UserId) -> ClientFuture<Option<User>>;
+    /// Checks personal access token, the request carries credentials of a first-party confidential client
+    fn introspect_token(&self, request: IntrospectionRequest) -> ClientFuture<Introspection>;
+    /// Searches users by terms, starting from user id `offset`
+    fn search_users(&self, terms: UsersSearchTerms, offset: Option<UserId>, skip: i64, count: i64) -> ClientFuture<UserSearchResults>;
+}
+
+pub struct UsersClientImpl<C: HttpClient + Clone> {
+    http_client: C,
+    base_url: String,
+    user_id: Option<UserId>,
+}
+
+impl<C: HttpClient + Clone> UsersClientImpl<C> {
+    pub fn new(http_client: C, base_url: String) -> Self {
+        Self {
+            http_client,
+            base_url: base_url.trim_right_matches('/').to_string(),
+            user_id: None,
+        }
+    }
+
+    /// Client authorized as the user
+    pub fn with_user_id(&self, user_id: UserId) -> Self {
+        Self {
+            http_client: self.http_client.clone(),
+            base_url: self.base_url.clone(),
+            user_id: Some(user_id),
+        }
+    }
+
+    fn headers(&self) -> Headers {
+        let mut headers = Headers::new();
+        if let Some(user_id) = self.user_id {
+            headers.set(Authorization(user_id.to_string()));
+        }
+        headers
+    }
+
+    fn request<T>(&self, method: Method, path: String, body: Option<String>, headers: Headers) -> ClientFuture<T>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        let url = format!("{}{}", self.base_url, path);
+        Box::new(
+            self.http_client
+                .request_json::<T>(method.clone(), url.clone(), body, Some(headers))
+                .map_err(move |e| {
+                    e.context(format!("Users service request {} {} failed", method, url))
+                        .context(Error::HttpClient)
+                        .into()
+                }),
+        )
+    }
+}
+
+impl<C: HttpClient + Clone> UsersClient for UsersClientImpl<C> {
+    fn get_user(&self, user_id: UserId) -> ClientFuture<Option<User>> {
+        self.request(Method::Get, format!("/users/{}", user_id), None, self.headers())
+    }
+
+    fn introspect_token(&self, request: IntrospectionRequest) -> ClientFuture<Introspection> {
+        let body = match serde_urlencoded::to_string(&request) {
+            Ok(body) => body,
+            Err(e) => return Box::new(future::err(e.into())),
+        };
+        let mut headers = self.headers();
+        headers.set(ContentType::form_url_encoded());
+        self.request(Method::Post, "/oauth/introspect".to_string(), Some(body), headers)
+    }
+
+    fn search_users(&self, terms: UsersSearchTerms, offset: Option<UserId>, skip: i64, count: i64) -> ClientFuture<UserSearchResults> {
+        let body = match serde_json::to_string(&terms) {
+            Ok(body) => body,
+            Err(e) => return Box::new(future::err(e.into())),
+        };
+        let mut headers = self.headers();
+        headers.set(ContentType::json());
+        self.request(Method::Post, search_path(offset, skip, count), Some(body), headers)
+    }
+}
+
+fn search_path(offset: Option<UserId>, skip: i64, count: i64) -> String {
+    match offset {
+        Some(offset) => format!("/users/search?offset={}&skip={}&count={}", offset, skip, count),
+        None => format!("/users/search?skip={}&count={}", skip, count),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_path() {
+        assert_eq!(search_path(None, 0, 10), "/users/search?skip=0&count=10");
+        assert_eq!(search_path(Some(UserId(5)), 1, 10), "/users/search?offset=5&skip=1&count=10");
+    }
+}
@@ -1,9 +1,11 @@
 //! Config module contains the top-level config for the app.
 use std::collections::HashMap;
 use std::env;
+use std::net::IpAddr;
 
 use stq_http;
 use stq_logging::GrayLogConfig;
+use stq_types::{UserId, UsersRole};
 
 use sentry_integration::SentryConfig;
 use serde::de::{Deserializer, Visitor};
@@ -11,6 +13,9 @@ use serde::Deserialize;
 
 use config_crate::{Config as RawConfig, ConfigError, Environment, File};
 
+use i18n::Locale;
+use models::{CrmField, Feature, PolicyRule, SecurityQuestion};
+
 /// Basic settings - HTTP binding address and database DSN
 #[derive(Debug, Deserialize, Clone)]
 pub struct Config {
@@ -21,9 +26,60 @@ pub struct Config {
     pub google: OAuth,
     pub facebook: OAuth,
     pub tokens: Tokens,
+    pub device_flow: DeviceFlow,
+    pub signed_actions: SignedActions,
+    pub validation: Validation,
+    pub name_screening: NameScreening,
+    pub profile_completion: ProfileCompletion,
+    pub profile_prompts: ProfilePrompts,
+    pub phone: Phone,
+    pub data_residency: DataResidency,
+    pub deletion: Deletion,
+    pub recovery: Recovery,
+    pub child_accounts: ChildAccounts,
+    pub security_questions: SecurityQuestions,
+    pub password_strength: PasswordStrength,
+    pub domain_roles: DomainRoles,
+    pub acl: AclPolicies,
+    pub role_expiry: RoleExpiry,
+    pub role_approvals: RoleApprovals,
+    pub invites: Invites,
+    pub access_tokens: AccessTokens,
+    pub refresh_tokens: RefreshTokens,
+    pub sessions: Sessions,
+    pub csrf: Csrf,
+    pub devices: Devices,
+    pub sharding: Sharding,
+    pub cache: CacheConf,
+    pub repo_limits: RepoLimits,
+    pub segment_exports: SegmentExports,
+    pub work_queues: WorkQueues,
+    pub access_log: AccessLog,
     pub graylog: Option<GrayLogConfig>,
     pub sentry: Option<SentryConfig>,
     pub testmode: Option<TestmodeConf>,
+    #[cfg(feature = "chaos")]
+    pub chaos: Option<Chaos>,
+    pub admin_ui: Option<AdminUi>,
+    pub cache_policy: CachePolicy,
+    pub warmup: Warmup,
+    pub schema_check: SchemaCheck,
+    pub auth_archive: Option<AuthArchive>,
+    pub break_glass: Option<BreakGlass>,
+    pub pii_encryption: Option<PiiEncryption>,
+    pub fraud_scoring: Option<FraudScoring>,
+    pub session_limits: Option<SessionLimits>,
+    pub geo_restriction: Option<GeoRestriction>,
+    pub legacy_sync: Option<LegacySync>,
+    pub crm_reconciliation: Option<CrmReconciliation>,
+    pub dependency_checks: Option<DependencyChecks>,
+    /// Percentage of users every feature under rollout is enabled for, features missing are disabled
+    #[serde(default)]
+    pub rollouts: HashMap<Feature, u8>,
+    /// Warm standby mode serving reads from a replica, see `controller::read_only`
+    pub read_only: bool,
+    pub analytics: Analytics,
+    pub public_stats: PublicStats,
 }
 
 /// Common server settings
@@ -36,6 +92,80 @@ pub struct Server {
     pub thread_count: usize,
     pub cache_ttl_sec: u64,
     pub processing_timeout_ms: u32,
+    /// Capacity of in-process roles cache consulted on every authenticated request, zero disables it
+    pub roles_local_cache_size: usize,
+    pub roles_local_cache_ttl_sec: u64,
+    /// Max size of request body in bytes
+    pub max_body_size: usize,
+    /// Port of the listener for debug endpoints, it must not be exposed outside the cluster.
+    /// Debug endpoints are disabled if not set
+    pub internal_port: Option<String>,
+    /// Host of the listener for debug endpoints. Requests to them are not authenticated,
+    /// so it is loopback by default and the endpoints are reached from the pod, e.g. with `kubectl exec`
+    pub internal_host: String,
+    /// Number of proxies in front of the service appending to `X-Forwarded-For`. Client address is
+    /// taken that many hops from the right of the chain, entries left of it are set by the client
+    pub trusted_proxy_hops: usize,
+}
+
+/// Max sizes of results returned by repos
+#[derive(Debug, Deserialize, Clone)]
+pub struct RepoLimits {
+    /// Max `count` of users in lists and searches
+    pub max_count: i64,
+}
+
+/// Exports of user segments to CSV
+#[derive(Debug, Deserialize, Clone)]
+pub struct SegmentExports {
+    /// CSV with PII of users is dropped this long after the export is done
+    pub csv_ttl_s: u64,
+    /// Interval of looking for expired CSVs
+    pub purge_interval_s: u64,
+}
+
+/// Bounded queues of db work by priority, see `services::work_pool`
+#[derive(Debug, Deserialize, Clone)]
+pub struct WorkQueues {
+    /// Token issuance and validation
+    pub auth: WorkQueue,
+    /// Max number of queued and running tasks of other requests, run on `server.thread_count` threads
+    pub regular_queue_size: usize,
+    /// Admin reports, searches, exports and background jobs
+    pub batch: WorkQueue,
+}
+
+impl WorkQueues {
+    /// Number of threads of all the classes, with `regular_threads` running regular work
+    pub fn total_threads(&self, regular_threads: usize) -> usize {
+        self.auth.threads + regular_threads + self.batch.threads
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct WorkQueue {
+    pub threads: usize,
+    /// Max number of queued and running tasks, more are rejected with 503
+    pub queue_size: usize,
+}
+
+/// Cache settings, shared by all caches of the app
+#[derive(Debug, Deserialize, Clone)]
+pub struct CacheConf {
+    /// Defaults to `redis` if `server.redis` is set and to `noop` otherwise
+    pub backend: Option<CacheBackend>,
+    /// Max number of values in every in-memory cache
+    pub memory_capacity: usize,
+    /// Drop in-process cached values on NOTIFY sent by database triggers, works without Redis
+    pub pg_notify_invalidations: bool,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheBackend {
+    Noop,
+    Memory,
+    Redis,
 }
 
 /// Http client settings
@@ -50,8 +180,12 @@ pub struct Client {
 /// Json Web Token seettings
 #[derive(Debug, Deserialize, Clone)]
 pub struct JWT {
+    /// DER of RSA private key signing JWTs
     pub secret_key_path: String,
     pub check_email: bool,
+    /// Secret of HMACs of refresh and personal access tokens, sessions, CSRF tokens, device cookies
+    /// and email links, kept apart from the RSA key signing JWTs
+    pub hmac_secret: String,
 }
 
 /// Oauth 2.0 basic settings
@@ -72,6 +206,530 @@ pub struct Tokens {
     pub jwt_expiration_s: u64,
     pub email_sending_timeout_s: u64,
     pub refresh_timeout_s: u64,
+    pub reauth_window_s: u64,
+    pub step_up_expiration_s: u64,
+    pub remember_me_expiration_s: u64,
+    pub remember_me_refresh_timeout_s: u64,
+    pub max_apply_attempts: u32,
+    pub apply_lockout_s: u64,
+}
+
+/// Access log settings. `sample_rates` are probabilities from 0 to 1 of writing a successful request
+/// by route template, e.g. `"/healthcheck" = 0.01`, requests to other routes are always written
+#[derive(Debug, Deserialize, Clone)]
+pub struct AccessLog {
+    pub enabled: bool,
+    pub sample_rates: HashMap<String, f64>,
+}
+
+/// One-click email action links settings
+#[derive(Debug, Deserialize, Clone)]
+pub struct SignedActions {
+    pub base_url: String,
+    pub expiration_s: u64,
+}
+
+/// User input validation settings
+#[derive(Debug, Deserialize, Clone)]
+pub struct Validation {
+    /// Allows UTF-8 local parts in emails (RFC 6531), IDN domains are always allowed
+    pub allow_utf8_email_local_part: bool,
+}
+
+/// Screening of user names for profanity and impersonation of staff
+#[derive(Debug, Deserialize, Clone)]
+pub struct NameScreening {
+    /// Words that must not appear in names
+    pub denylist: Vec<String>,
+    /// Names reserved for staff accounts
+    pub reserved_names: Vec<String>,
+}
+
+/// Weights of user fields in profile completion percentage,
+/// fields with zero weight are not taken into account
+#[derive(Debug, Deserialize, Clone)]
+pub struct ProfileCompletion {
+    pub weights: HashMap<String, u32>,
+}
+
+/// Progressive profiling settings
+#[derive(Debug, Deserialize, Clone)]
+pub struct ProfilePrompts {
+    /// Profile fields users are prompted to fill, in order of priority
+    pub fields: Vec<String>,
+    /// Number of prompts returned at once
+    pub limit: usize,
+    /// Time a dismissed prompt is not shown
+    pub snooze_s: u64,
+}
+
+/// Phone numbers settings
+#[derive(Debug, Deserialize, Clone)]
+pub struct Phone {
+    /// ISO 3166-1 alpha-2 region for numbers without international prefix
+    pub default_region: String,
+}
+
+/// Data residency settings. Region is assigned to the user at registration
+/// by user country (resolved by gateway from GeoIP if not provided by user).
+#[derive(Debug, Deserialize, Clone)]
+pub struct DataResidency {
+    pub default_region: String,
+    /// ISO 3166-1 alpha-3 country code to region
+    pub country_regions: HashMap<String, String>,
+}
+
+impl DataResidency {
+    /// Returns data region for user country
+    pub fn region_for(&self, country: Option<&str>) -> String {
+        country
+            .and_then(|country| self.country_regions.get(&country.to_uppercase()))
+            .unwrap_or(&self.default_region)
+            .clone()
+    }
+}
+
+/// Two-phase deletion of users settings
+#[derive(Debug, Deserialize, Clone)]
+pub struct Deletion {
+    /// Services which must confirm deletion of a user, e.g. orders and billing.
+    /// Confirmations of other services are rejected
+    pub confirming_services: Vec<String>,
+    /// Time to wait for confirmations, services which have not answered by then are considered to confirm
+    pub confirmation_timeout_s: u64,
+    /// Minimal time between request and purge of the user, even if all the services confirmed the deletion
+    pub grace_period_s: u64,
+    /// Interval of looking for requests with passed deadline
+    pub check_interval_s: u64,
+}
+
+/// Account recovery via trusted contacts settings
+#[derive(Debug, Deserialize, Clone)]
+pub struct Recovery {
+    /// Number of trusted contacts which must approve recovery, recovery is not available
+    /// to users having fewer contacts
+    pub required_approvals: u32,
+    pub max_contacts: u32,
+    /// Lifetime of recovery request and its approval links
+    pub expiration_s: u64,
+    /// Page the approval links lead to, token is passed in `token` query parameter
+    pub approve_url: String,
+}
+
+/// Parent-managed child accounts settings
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChildAccounts {
+    pub max_children: u32,
+}
+
+/// Security questions settings. Users who answered the questions are asked them on password reset.
+#[derive(Debug, Deserialize, Clone)]
+pub struct SecurityQuestions {
+    /// Number of questions the user must answer, and answer correctly on password reset
+    pub required_answers: u32,
+    /// Questions by locale, ids of the questions must be the same in all the locales
+    pub catalogs: HashMap<String, Vec<SecurityQuestion>>,
+}
+
+impl SecurityQuestions {
+    /// Returns questions of the locale, falls back to questions of default locale
+    pub fn catalog(&self, locale: Locale) -> Vec<SecurityQuestion> {
+        self.catalogs
+            .get(&locale.to_string())
+            .or_else(|| self.catalogs.get(&Locale::default().to_string()))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Checks that the question is present in catalogs
+    pub fn is_known(&self, question_id: &str) -> bool {
+        self.catalogs
+            .values()
+            .any(|questions| questions.iter().any(|question| question.id == question_id))
+    }
+}
+
+/// Password strength policy, strength is estimated the same way as by `POST /password/strength`
+#[derive(Debug, Deserialize, Clone)]
+pub struct PasswordStrength {
+    /// Minimal score from 0 to 4 of new passwords, 0 accepts any password
+    pub min_score: u8,
+}
+
+/// Roles assigned automatically to users by email domain once the email is verified
+#[derive(Debug, Deserialize, Clone)]
+pub struct DomainRoles {
+    pub rules: Vec<DomainRoleRule>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct DomainRoleRule {
+    /// Domain of the email without `@`, subdomains are not matched
+    pub domain: String,
+    pub roles: Vec<UsersRole>,
+}
+
+impl DomainRoles {
+    /// Returns roles of all the rules matching domain of the email
+    pub fn roles_for(&self, email: &str) -> Vec<UsersRole> {
+        let domain = match email.rfind('@') {
+            Some(pos) => email[pos + 1..].to_lowercase(),
+            None => return vec![],
+        };
+        let mut roles = vec![];
+        for rule in &self.rules {
+            if rule.domain.trim_left_matches('@').to_lowercase() == domain {
+                for role in &rule.roles {
+                    if !roles.contains(role) {
+                        roles.push(role.clone());
+                    }
+                }
+            }
+        }
+        roles
+    }
+}
+
+/// Policy rules granting permissions to roles in addition to the built-in ones
+#[derive(Debug, Deserialize, Clone)]
+pub struct AclPolicies {
+    pub rules: Vec<PolicyRule>,
+}
+
+/// Revocation of time-boxed roles, expired roles are ignored by ACL before they are revoked
+#[derive(Debug, Deserialize, Clone)]
+pub struct RoleExpiry {
+    /// Interval of looking for expired roles
+    pub check_interval_s: u64,
+}
+
+/// Grants of sensitive roles, applied once approved by a second admin
+#[derive(Debug, Deserialize, Clone)]
+pub struct RoleApprovals {
+    pub sensitive_roles: Vec<UsersRole>,
+    /// Time to approve a request, older requests can't be approved
+    pub request_ttl_s: u64,
+}
+
+/// Invite-only registration settings
+#[derive(Debug, Deserialize, Clone)]
+pub struct Invites {
+    /// Users are registered with a valid invite code only
+    pub required: bool,
+    /// Upper limit of uses of a single invite, also used if not set on creation
+    pub max_uses: i32,
+    /// Lifetime of invites if not set on creation
+    pub expiration_s: u64,
+    pub max_expiration_s: u64,
+}
+
+/// Personal access tokens settings
+#[derive(Debug, Deserialize, Clone)]
+pub struct AccessTokens {
+    /// Scopes tokens can be limited to
+    pub scopes: Vec<String>,
+    pub max_tokens: u32,
+    pub max_expiration_s: u64,
+}
+
+/// Rotating refresh tokens settings. Refresh tokens of clients with cookie mode are set
+/// by the gateway as `httpOnly` cookie with these attributes.
+#[derive(Debug, Deserialize, Clone)]
+pub struct RefreshTokens {
+    pub cookie_name: String,
+    /// Path of the renewal endpoint at the gateway, the cookie is sent there only
+    pub cookie_path: String,
+}
+
+/// Server-side sessions of clients with session mode. Session id is set by the gateway
+/// as `httpOnly` cookie, sessions expire after `idle_timeout_s` without requests.
+#[derive(Debug, Deserialize, Clone)]
+pub struct Sessions {
+    pub cookie_name: String,
+    pub cookie_path: String,
+    pub idle_timeout_s: u64,
+}
+
+/// CSRF protection of requests made with session or refresh token cookie
+#[derive(Debug, Deserialize, Clone)]
+pub struct Csrf {
+    pub cookie_name: String,
+    pub cookie_path: String,
+    pub max_age_s: u64,
+}
+
+/// Known devices of users. Trusted devices get `httpOnly` cookie with these attributes,
+/// suspicious logins sending it skip step-up authentication for `trust_duration_s`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct Devices {
+    pub cookie_name: String,
+    /// Path of the login endpoints at the gateway, the cookie is sent there only
+    pub cookie_path: String,
+    pub trust_duration_s: u64,
+}
+
+/// Database shards, user data is routed to a shard by user id hash.
+/// If sharding is disabled or no shards are set, all the data is stored in `server.database`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct Sharding {
+    /// Sharding is not ready for production: login, ACL and roles are still read from
+    /// the primary shard only and there is no backfill of existing users into shards
+    pub enabled: bool,
+    pub virtual_buckets: u32,
+    /// First shard is primary, it allocates user ids and stores data not owned by users
+    pub shards: Vec<Shard>,
+}
+
+impl Sharding {
+    /// Shards in use, empty unless sharding is enabled
+    pub fn active_shards(&self) -> &[Shard] {
+        if self.enabled {
+            &self.shards
+        } else {
+            &[]
+        }
+    }
+}
+
+/// Database shard owning inclusive range of virtual buckets
+#[derive(Debug, Deserialize, Clone)]
+pub struct Shard {
+    pub database: String,
+    pub first_bucket: u32,
+    pub last_bucket: u32,
+}
+
+/// OAuth2 device authorization grant settings
+#[derive(Debug, Deserialize, Clone)]
+pub struct DeviceFlow {
+    pub verification_uri: String,
+    pub code_expiration_s: u64,
+    pub polling_interval_s: u64,
+}
+
+/// Bundled admin SPA served under `/admin`, for small deployments without a separate web server
+#[derive(Debug, Deserialize, Clone)]
+pub struct AdminUi {
+    /// Directory with built SPA files, `index.html` must be in its root
+    pub dir: String,
+    /// Cache lifetime of assets other than `index.html`, their names should contain content hashes
+    pub max_age_s: u32,
+}
+
+/// `Cache-Control` of API responses, see `controller::cache_policy` for policies of routes
+#[derive(Debug, Deserialize, Clone)]
+pub struct CachePolicy {
+    pub enabled: bool,
+    /// Cache lifetime of user profiles, kept short as profiles are edited by users
+    pub profile_max_age_s: u32,
+    /// Cache lifetime of reference data, e.g. countries
+    pub reference_max_age_s: u32,
+}
+
+/// Warmup of pools and caches before the instance is reported ready by `/ready`
+#[derive(Debug, Deserialize, Clone)]
+pub struct Warmup {
+    pub enabled: bool,
+    /// Max number of superusers whose roles are cached, per shard
+    pub max_superusers: i64,
+}
+
+/// Compatibility checks of the database schema, failing `/ready` while the schema is not supported
+#[derive(Debug, Deserialize, Clone)]
+pub struct SchemaCheck {
+    pub enabled: bool,
+    pub check_interval_s: u64,
+    /// Newest supported migration version, set while a migration breaking this release is pending
+    pub max_version: Option<String>,
+}
+
+/// Pseudonymous analytics ids of users, exposed to analytics instead of user ids
+#[derive(Debug, Deserialize, Clone)]
+pub struct Analytics {
+    /// Secret HMAC key of analytics ids, ids of all users change when it is rotated
+    pub pepper: String,
+    /// Bumped on every rotation of the pepper, ids are prefixed with it
+    pub pepper_version: u32,
+}
+
+/// Disclosure control of `/stats/public`, counts are published with Laplace noise
+/// and counts of cohorts smaller than `min_cohort` are suppressed
+#[derive(Debug, Deserialize, Clone)]
+pub struct PublicStats {
+    /// Smallest published count, smaller noisy counts are withheld
+    pub min_cohort: i64,
+    /// Privacy budget of a published count, smaller values mean more noise
+    pub epsilon: f64,
+    /// Secret HMAC key of the noise, the same query always gets the same noise
+    pub noise_key: String,
+    /// Longest date range of a query
+    pub max_days: i64,
+}
+
+/// Exports and imports of auth data, the endpoints are disabled unless configured
+#[derive(Debug, Deserialize, Clone)]
+pub struct AuthArchive {
+    /// Base64 of 32 bytes AES-256-GCM key, the same in environments exchanging archives
+    pub key: String,
+    /// Addresses allowed to call the endpoints, superuser role is required too
+    pub allowed_ips: Vec<IpAddr>,
+}
+
+/// Emergency access for incidents where normal admin access is broken, disabled unless configured
+#[derive(Debug, Deserialize, Clone)]
+pub struct BreakGlass {
+    /// Account getting superuser role on activation
+    pub user_id: UserId,
+    /// DER of RSA public key of the offline key signing activation tokens
+    pub public_key_path: String,
+    /// How long superuser role and the token of the account last after activation
+    pub duration_s: u64,
+    /// Webhook paged on every activation
+    pub webhook_url: String,
+}
+
+/// Sealing of PII of users by per-user data keys, PII is stored in plain text unless configured.
+/// Sealed users are found by whole emails, phones and names only.
+#[derive(Debug, Deserialize, Clone)]
+pub struct PiiEncryption {
+    /// Base64 of 32 bytes AES-256-GCM key wrapping data keys of users
+    pub master_key: String,
+    /// DSN of the database of data keys, it must not share backups with `server.database`
+    pub key_store_database: String,
+}
+
+/// Scoring of logins by the fraud-scoring service, logins are allowed unless configured.
+/// Scores are in the range the service returns, higher is more suspicious.
+#[derive(Debug, Deserialize, Clone)]
+pub struct FraudScoring {
+    /// Endpoint login events are posted to
+    pub url: String,
+    /// Logins scored above it get tokens requiring step-up authentication
+    pub step_up_threshold: f64,
+    /// Logins scored above it are blocked
+    pub block_threshold: f64,
+}
+
+/// Cap on simultaneous sessions of a user, both server-side sessions and refresh token families
+/// count. Sessions are not limited unless configured.
+#[derive(Debug, Deserialize, Clone)]
+pub struct SessionLimits {
+    pub max_sessions: usize,
+    /// What happens to a new session once the user has `max_sessions` active ones
+    pub policy: SessionLimitPolicy,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionLimitPolicy {
+    /// The oldest sessions are closed to make room for the new one
+    EvictOldest,
+    /// The new session is rejected until some of the active ones end
+    RejectNew,
+}
+
+/// Countries users may log in from, by country of the client address resolved by the gateway
+/// from GeoIP. Registered clients may have lists of their own, `default` lists apply to the rest.
+/// Logins are not restricted unless configured.
+#[derive(Debug, Deserialize, Clone)]
+pub struct GeoRestriction {
+    pub default: CountryLists,
+    /// Client id to its lists
+    #[serde(default)]
+    pub clients: HashMap<String, CountryLists>,
+}
+
+impl GeoRestriction {
+    /// Whether users of the client may log in from the country
+    pub fn allows(&self, client_id: Option<&str>, country: Option<&str>) -> bool {
+        client_id
+            .and_then(|client_id| self.clients.get(client_id))
+            .unwrap_or(&self.default)
+            .allows(country)
+    }
+}
+
+/// ISO 3166-1 alpha-3 codes of countries
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct CountryLists {
+    /// Only these countries are allowed if set, logins from unknown countries are denied then
+    pub allowed: Option<Vec<String>>,
+    #[serde(default)]
+    pub denied: Vec<String>,
+}
+
+impl CountryLists {
+    pub fn allows(&self, country: Option<&str>) -> bool {
+        let listed = |countries: &Vec<String>, country: &str| countries.iter().any(|listed| listed.eq_ignore_ascii_case(country));
+        match (country, &self.allowed) {
+            (Some(country), _) if listed(&self.denied, country) => false,
+            (Some(country), &Some(ref allowed)) => listed(allowed, country),
+            (None, &Some(_)) => false,
+            (_, &None) => true,
+        }
+    }
+}
+
+/// Mirroring of created and updated users to the user API of the legacy system during migration
+/// off it. Writes are mirrored in background, outcomes are kept for reconciliation. Disabled unless
+/// configured.
+#[derive(Debug, Deserialize, Clone)]
+pub struct LegacySync {
+    /// Base url of the legacy user API, users are created by `POST <url>/users`
+    /// and updated by `PUT <url>/users/<id>`
+    pub url: String,
+    pub timeout_s: u64,
+}
+
+/// Periodic reconciliation of users against their CRM contacts, users are linked to contacts by
+/// `emarsys_id`. Disabled unless configured.
+#[derive(Debug, Deserialize, Clone)]
+pub struct CrmReconciliation {
+    /// Base url of the CRM API, contacts are read by `GET <url>/contacts/<emarsys_id>`
+    /// and corrected by `PUT <url>/contacts/<emarsys_id>`
+    pub url: String,
+    pub timeout_s: u64,
+    pub interval_s: u64,
+    /// Fields corrected in CRM with values of the user, drifts of other fields are only reported
+    #[serde(default)]
+    pub auto_correct: Vec<CrmField>,
+}
+
+/// Downstream services checked by `/ready/dependencies`, none unless configured
+#[derive(Debug, Deserialize, Clone)]
+pub struct DependencyChecks {
+    pub timeout_ms: u64,
+    pub dependencies: Vec<Dependency>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Dependency {
+    pub name: String,
+    /// Health url, checked by `GET` and considered up on any successful response
+    pub url: String,
+    /// Flows of the users service can't be run without the dependency
+    #[serde(default)]
+    pub critical: bool,
+}
+
+/// Faults injected into calls to upstream OAuth providers, for testing only.
+/// Read only by builds with `chaos` feature.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct Chaos {
+    /// Threads awaiting injected latency, apart from threads of db work
+    #[serde(default)]
+    pub latency_threads: usize,
+    pub google: Option<Faults>,
+    pub facebook: Option<Faults>,
+}
+
+/// Fault rates are probabilities from 0 to 1, latency is added to every call
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct Faults {
+    pub latency_ms: u64,
+    pub server_error_rate: f64,
+    pub malformed_json_rate: f64,
 }
 
 /// Testmode settings
@@ -124,11 +782,136 @@ impl<'de> Visitor<'de> for ApiModeVisitor {
 ///
 /// let config = Config::new();
 /// ```
+/// Placeholder of secrets in example configs, rejected on load
+const PLACEHOLDER_SECRET: &'static str = "change-me-per-environment";
+/// Shortest secret accepted on load
+const MIN_SECRET_LENGTH: usize = 32;
+
+/// Rejects missing, short and placeholder secrets, so that nothing is signed by a known key
+fn check_secret(name: &str, secret: &str) -> Result<(), ConfigError> {
+    if secret == PLACEHOLDER_SECRET || secret.len() < MIN_SECRET_LENGTH {
+        return Err(ConfigError::Message(format!(
+            "{} must be a random secret of at least {} characters set per environment",
+            name, MIN_SECRET_LENGTH
+        )));
+    }
+    Ok(())
+}
+
 impl Config {
     pub fn new() -> Result<Self, ConfigError> {
         let mut s = RawConfig::new();
 
         s.set_default("server.processing_timeout_ms", 1000 as i64).unwrap();
+        s.set_default("server.roles_local_cache_size", 10000 as i64).unwrap();
+        s.set_default("server.roles_local_cache_ttl_sec", 30 as i64).unwrap();
+        s.set_default("server.max_body_size", 1024 * 1024 as i64).unwrap();
+        s.set_default("server.trusted_proxy_hops", 0 as i64).unwrap();
+        s.set_default("server.internal_host", "127.0.0.1").unwrap();
+        s.set_default("tokens.reauth_window_s", 300 as i64).unwrap();
+        s.set_default("tokens.step_up_expiration_s", 900 as i64).unwrap();
+        s.set_default("tokens.remember_me_expiration_s", 2592000 as i64).unwrap();
+        s.set_default("tokens.remember_me_refresh_timeout_s", 7776000 as i64).unwrap();
+        s.set_default("tokens.max_apply_attempts", 5 as i64).unwrap();
+        s.set_default("tokens.apply_lockout_s", 900 as i64).unwrap();
+        s.set_default("device_flow.verification_uri", "https://storiqa.com/device").unwrap();
+        s.set_default("device_flow.code_expiration_s", 600 as i64).unwrap();
+        s.set_default("device_flow.polling_interval_s", 5 as i64).unwrap();
+        s.set_default("signed_actions.base_url", "https://storiqa.com/actions").unwrap();
+        s.set_default("signed_actions.expiration_s", 3600 as i64).unwrap();
+        s.set_default("validation.allow_utf8_email_local_part", false).unwrap();
+        s.set_default("name_screening.denylist", Vec::<String>::new()).unwrap();
+        s.set_default(
+            "name_screening.reserved_names",
+            vec!["admin", "administrator", "moderator", "support", "staff", "storiqa"]
+                .into_iter()
+                .map(String::from)
+                .collect::<Vec<_>>(),
+        )
+        .unwrap();
+        s.set_default("profile_completion.weights.email_verified", 20 as i64).unwrap();
+        s.set_default("profile_completion.weights.phone", 10 as i64).unwrap();
+        s.set_default("profile_completion.weights.phone_verified", 10 as i64).unwrap();
+        s.set_default("profile_completion.weights.first_name", 15 as i64).unwrap();
+        s.set_default("profile_completion.weights.last_name", 15 as i64).unwrap();
+        s.set_default("profile_completion.weights.gender", 5 as i64).unwrap();
+        s.set_default("profile_completion.weights.birthdate", 10 as i64).unwrap();
+        s.set_default("profile_completion.weights.avatar", 10 as i64).unwrap();
+        s.set_default("profile_completion.weights.country", 5 as i64).unwrap();
+        s.set_default("profile_prompts.fields", Vec::<String>::new()).unwrap();
+        s.set_default("profile_prompts.limit", 1 as i64).unwrap();
+        s.set_default("profile_prompts.snooze_s", 604800 as i64).unwrap();
+        s.set_default("phone.default_region", "RU").unwrap();
+        s.set_default("data_residency.default_region", "global").unwrap();
+        s.set_default("data_residency.country_regions", HashMap::<String, String>::new())
+            .unwrap();
+        s.set_default("deletion.confirming_services", vec!["orders".to_string(), "billing".to_string()])
+            .unwrap();
+        s.set_default("deletion.confirmation_timeout_s", 604800 as i64).unwrap();
+        s.set_default("deletion.grace_period_s", 86400 as i64).unwrap();
+        s.set_default("deletion.check_interval_s", 60 as i64).unwrap();
+        s.set_default("recovery.required_approvals", 2 as i64).unwrap();
+        s.set_default("recovery.max_contacts", 5 as i64).unwrap();
+        s.set_default("recovery.expiration_s", 86400 as i64).unwrap();
+        s.set_default("recovery.approve_url", "https://storiqa.com/recovery/approve")
+            .unwrap();
+        s.set_default("child_accounts.max_children", 5 as i64).unwrap();
+        s.set_default("password_strength.min_score", 0 as i64).unwrap();
+        s.set_default("domain_roles.rules", Vec::<String>::new()).unwrap();
+        s.set_default("acl.rules", Vec::<String>::new()).unwrap();
+        s.set_default("role_expiry.check_interval_s", 60 as i64).unwrap();
+        s.set_default("role_approvals.sensitive_roles", vec!["superuser".to_string()])
+            .unwrap();
+        s.set_default("role_approvals.request_ttl_s", 86400 as i64).unwrap();
+        s.set_default("invites.required", false).unwrap();
+        s.set_default("invites.max_uses", 100 as i64).unwrap();
+        s.set_default("invites.expiration_s", 604800 as i64).unwrap();
+        s.set_default("invites.max_expiration_s", 2592000 as i64).unwrap();
+        s.set_default(
+            "access_tokens.scopes",
+            vec!["read", "write"].into_iter().map(String::from).collect::<Vec<_>>(),
+        )
+        .unwrap();
+        s.set_default("access_tokens.max_tokens", 20 as i64).unwrap();
+        s.set_default("access_tokens.max_expiration_s", 31536000 as i64).unwrap();
+        s.set_default("refresh_tokens.cookie_name", "refresh_token").unwrap();
+        s.set_default("refresh_tokens.cookie_path", "/jwt/renew").unwrap();
+        s.set_default("sessions.cookie_name", "session_id").unwrap();
+        s.set_default("sessions.cookie_path", "/").unwrap();
+        s.set_default("sessions.idle_timeout_s", 1800 as i64).unwrap();
+        s.set_default("csrf.cookie_name", "csrf_token").unwrap();
+        s.set_default("csrf.cookie_path", "/").unwrap();
+        s.set_default("csrf.max_age_s", 86400 as i64).unwrap();
+        s.set_default("devices.cookie_name", "trusted_device").unwrap();
+        s.set_default("devices.cookie_path", "/jwt").unwrap();
+        s.set_default("devices.trust_duration_s", 2592000 as i64).unwrap();
+        s.set_default("sharding.enabled", false).unwrap();
+        s.set_default("sharding.virtual_buckets", 1024 as i64).unwrap();
+        s.set_default("sharding.shards", Vec::<String>::new()).unwrap();
+        s.set_default("cache.memory_capacity", 100000 as i64).unwrap();
+        s.set_default("cache.pg_notify_invalidations", false).unwrap();
+        s.set_default("repo_limits.max_count", 1000 as i64).unwrap();
+        s.set_default("segment_exports.csv_ttl_s", 86400 as i64).unwrap();
+        s.set_default("segment_exports.purge_interval_s", 600 as i64).unwrap();
+        s.set_default("work_queues.auth.threads", 4 as i64).unwrap();
+        s.set_default("work_queues.auth.queue_size", 1000 as i64).unwrap();
+        s.set_default("work_queues.regular_queue_size", 5000 as i64).unwrap();
+        s.set_default("work_queues.batch.threads", 2 as i64).unwrap();
+        s.set_default("work_queues.batch.queue_size", 100 as i64).unwrap();
+        s.set_default("access_log.enabled", true).unwrap();
+        s.set_default("access_log.sample_rates", HashMap::<String, f64>::new()).unwrap();
+        s.set_default("cache_policy.enabled", true).unwrap();
+        s.set_default("cache_policy.profile_max_age_s", 30 as i64).unwrap();
+        s.set_default("cache_policy.reference_max_age_s", 300 as i64).unwrap();
+        s.set_default("warmup.enabled", false).unwrap();
+        s.set_default("warmup.max_superusers", 100 as i64).unwrap();
+        s.set_default("schema_check.enabled", true).unwrap();
+        s.set_default("schema_check.check_interval_s", 30 as i64).unwrap();
+        s.set_default("analytics.pepper_version", 1 as i64).unwrap();
+        s.set_default("public_stats.min_cohort", 10 as i64).unwrap();
+        s.set_default("public_stats.epsilon", 1.0).unwrap();
+        s.set_default("public_stats.max_days", 366 as i64).unwrap();
+        s.set_default("read_only", false).unwrap();
 
         s.merge(File::with_name("config/base"))?;
 
@@ -136,10 +919,15 @@ impl Config {
         let env = env::var("RUN_MODE").unwrap_or_else(|_| "development".into());
         s.merge(File::with_name(&format!("config/{}", env)).required(false))?;
 
-        // Add in settings from the environment (with a prefix of STQ_USERS)
-        s.merge(Environment::with_prefix("STQ_USERS"))?;
+        // Add in settings from the environment (with a prefix of STQ_USERS), nested keys are
+        // separated by `__`, e.g. STQ_USERS_JWT__HMAC_SECRET
+        s.merge(Environment::with_prefix("STQ_USERS").separator("__"))?;
 
-        s.try_into()
+        let config: Config = s.try_into()?;
+        check_secret("jwt.hmac_secret", &config.jwt.hmac_secret)?;
+        check_secret("analytics.pepper", &config.analytics.pepper)?;
+        check_secret("public_stats.noise_key", &config.public_stats.noise_key)?;
+        Ok(config)
     }
 
     pub fn to_http_config(&self) -> stq_http::client::Config {
@@ -0,0 +1,81 @@
+//! Access log, one structured line per request. Lines are logged with `access_log` target,
+//! so that the logger can route them apart from application logs.
+
+use std::time::Duration;
+
+use rand::{self, Rng};
+use serde_json;
+
+use stq_types::UserId;
+
+use config::AccessLog as AccessLogConf;
+
+pub const ACCESS_LOG_TARGET: &'static str = "access_log";
+
+/// Request as written to the access log, `route` is the path template to group requests by
+#[derive(Clone, Debug, Serialize)]
+pub struct AccessRecord {
+    pub method: String,
+    pub route: &'static str,
+    pub status: u16,
+    pub latency_ms: u64,
+    pub user_id: Option<UserId>,
+    pub request_id: String,
+}
+
+/// Writes the record, if it is not skipped by sampling. Failed requests are always written.
+pub fn log_access(conf: &AccessLogConf, record: &AccessRecord) {
+    if !conf.enabled || (record.status < 400 && !sampled(conf, record.route)) {
+        return;
+    }
+
+    match serde_json::to_string(record) {
+        Ok(line) => info!(target: ACCESS_LOG_TARGET, "{}", line),
+        Err(e) => warn!("Access log record {:?} was not serialized: {}", record, e),
+    }
+}
+
+/// Writes every request of the break-glass account at warn level with the full uri, sampling
+/// and `enabled` are ignored
+pub fn log_break_glass_access(record: &AccessRecord, uri: &str) {
+    match serde_json::to_string(record) {
+        Ok(line) => warn!(target: ACCESS_LOG_TARGET, "Break-glass request {}: {}", uri, line),
+        Err(e) => warn!("Break-glass request {} record {:?} was not serialized: {}", uri, record, e),
+    }
+}
+
+fn sampled(conf: &AccessLogConf, route: &str) -> bool {
+    match conf.sample_rates.get(route) {
+        Some(rate) => rand::thread_rng().gen::<f64>() < *rate,
+        None => true,
+    }
+}
+
+pub fn duration_ms(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + u64::from(duration.subsec_nanos()) / 1_000_000
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_sampled() {
+        let mut sample_rates = HashMap::new();
+        sample_rates.insert("/healthcheck".to_string(), 0.0);
+        let conf = AccessLogConf {
+            enabled: true,
+            sample_rates,
+        };
+        assert_eq!(sampled(&conf, "/healthcheck"), false);
+        assert_eq!(sampled(&conf, "/users/:id"), true);
+    }
+
+    #[test]
+    fn test_duration_ms() {
+        assert_eq!(duration_ms(Duration::new(2, 345_678_901)), 2345);
+    }
+}
@@ -0,0 +1,221 @@
+//! Static files of the bundled admin SPA, served under `/admin` in small deployments without
+//! a separate web server. Wraps the API application, so the API stays on the same origin and
+//! session cookies of the admin console are same-site. Served only if `admin_ui` is configured.
+//!
+//! `index.html` is revalidated on every request, other assets are expected to have content
+//! hashes in their names and are cached for `admin_ui.max_age_s`. Unknown paths without
+//! extension are SPA routes and get `index.html`.
+
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use futures::future;
+use futures::Future;
+use futures_cpupool::CpuPool;
+use hyper;
+use hyper::header::{CacheControl, CacheDirective, ContentLength, ContentType, ETag, EntityTag, IfNoneMatch};
+use hyper::mime::{self, Mime};
+use hyper::server::{Request, Response, Service};
+use hyper::{Get, Head, StatusCode};
+
+use super::headers::XContentTypeOptions;
+use config;
+
+/// Path prefix the admin SPA is mounted under
+pub const ADMIN_UI_PREFIX: &str = "/admin";
+const INDEX_FILE: &str = "index.html";
+
+/// Serves admin SPA files and passes all other requests to the API application
+pub struct AdminUiService<S> {
+    inner: S,
+    conf: Option<config::AdminUi>,
+    cpu_pool: CpuPool,
+}
+
+impl<S> AdminUiService<S> {
+    pub fn new(inner: S, conf: Option<config::AdminUi>, cpu_pool: CpuPool) -> Self {
+        Self { inner, conf, cpu_pool }
+    }
+}
+
+impl<S> Service for AdminUiService<S>
+where
+    S: Service<Request = Request, Response = Response, Error = hyper::Error>,
+    S::Future: 'static,
+{
+    type Request = Request;
+    type Response = Response;
+    type Error = hyper::Error;
+    type Future = Box<Future<Item = Response, Error = hyper::Error>>;
+
+    fn call(&self, req: Request) -> Self::Future {
+        let conf = match self.conf {
+            Some(ref conf) if is_admin_ui_path(req.path()) => conf.clone(),
+            _ => return Box::new(self.inner.call(req)),
+        };
+        match *req.method() {
+            Get | Head => (),
+            _ => return Box::new(future::ok(Response::new().with_status(StatusCode::MethodNotAllowed))),
+        }
+
+        let is_head = *req.method() == Head;
+        let if_none_match = req.headers().get::<IfNoneMatch>().cloned();
+        let path = req.path().to_string();
+        let max_age_s = conf.max_age_s;
+        // files are read on the pool not to block the event loop
+        let file = self.cpu_pool.spawn_fn(move || -> Result<_, hyper::Error> {
+            Ok(find_file(Path::new(&conf.dir), &path).map(|(file_path, is_index)| {
+                let content = read_file(&file_path);
+                (file_path, is_index, content)
+            }))
+        });
+        Box::new(file.map(move |file| match file {
+            Some((file_path, is_index, Ok((body, etag)))) => {
+                file_response(&file_path, is_index, body, etag, if_none_match, is_head, max_age_s)
+            }
+            Some((file_path, _, Err(e))) => {
+                error!("Reading admin UI file {:?} failed: {}", file_path, e);
+                Response::new().with_status(StatusCode::InternalServerError)
+            }
+            None => Response::new().with_status(StatusCode::NotFound),
+        }))
+    }
+}
+
+fn is_admin_ui_path(path: &str) -> bool {
+    path == ADMIN_UI_PREFIX || path.starts_with(&format!("{}/", ADMIN_UI_PREFIX))
+}
+
+/// Relative path of the file for the request path, `None` for paths trying to escape the directory
+/// or to reach hidden files
+fn relative_path(request_path: &str) -> Option<PathBuf> {
+    let mut relative = PathBuf::new();
+    for segment in request_path[ADMIN_UI_PREFIX.len()..]
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+    {
+        if segment.starts_with('.') || segment.contains('\\') {
+            return None;
+        }
+        relative.push(segment);
+    }
+    if relative.as_os_str().is_empty() {
+        relative.push(INDEX_FILE);
+    }
+    Some(relative)
+}
+
+/// File to serve and whether it is the index page, falls back to the index page for SPA routes
+fn find_file(dir: &Path, request_path: &str) -> Option<(PathBuf, bool)> {
+    let relative = relative_path(request_path)?;
+    let file_path = dir.join(&relative);
+    if file_path.is_file() {
+        let is_index = relative == Path::new(INDEX_FILE);
+        Some((file_path, is_index))
+    } else if relative.extension().is_none() {
+        Some((dir.join(INDEX_FILE), true))
+    } else {
+        None
+    }
+}
+
+/// Reads the file along with its weak ETag made of size and modification time
+fn read_file(file_path: &Path) -> io::Result<(Vec<u8>, EntityTag)> {
+    let metadata = fs::metadata(file_path)?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|modified| modified.as_secs())
+        .unwrap_or(0);
+    let mut body = Vec::with_capacity(metadata.len() as usize);
+    File::open(file_path)?.read_to_end(&mut body)?;
+    Ok((body, EntityTag::weak(format!("{:x}-{:x}", metadata.len(), modified))))
+}
+
+fn file_response(
+    file_path: &Path,
+    is_index: bool,
+    body: Vec<u8>,
+    etag: EntityTag,
+    if_none_match: Option<IfNoneMatch>,
+    is_head: bool,
+    max_age_s: u32,
+) -> Response {
+    let cache_control = if is_index {
+        CacheControl(vec![CacheDirective::NoCache])
+    } else {
+        CacheControl(vec![CacheDirective::Public, CacheDirective::MaxAge(max_age_s)])
+    };
+    let not_modified = match if_none_match {
+        Some(IfNoneMatch::Any) => true,
+        Some(IfNoneMatch::Items(ref tags)) => tags.iter().any(|tag| tag.weak_eq(&etag)),
+        None => false,
+    };
+
+    let response = Response::new()
+        .with_header(cache_control)
+        .with_header(ETag(etag))
+        .with_header(XContentTypeOptions("nosniff".to_string()));
+    if not_modified {
+        return response.with_status(StatusCode::NotModified);
+    }
+    let response = response
+        .with_header(ContentType(content_type(file_path)))
+        .with_header(ContentLength(body.len() as u64));
+    if is_head {
+        response
+    } else {
+        response.with_body(body)
+    }
+}
+
+fn content_type(file_path: &Path) -> Mime {
+    let extension = file_path.extension().and_then(|extension| extension.to_str()).unwrap_or_default();
+    match extension {
+        "html" => mime::TEXT_HTML_UTF_8,
+        "js" => mime::APPLICATION_JAVASCRIPT_UTF_8,
+        "css" => mime::TEXT_CSS_UTF_8,
+        "json" | "map" => mime::APPLICATION_JSON,
+        "svg" => mime::IMAGE_SVG,
+        "png" => mime::IMAGE_PNG,
+        "jpg" | "jpeg" => mime::IMAGE_JPEG,
+        "ico" => "image/x-icon".parse().unwrap_or(mime::APPLICATION_OCTET_STREAM),
+        "woff2" => "font/woff2".parse().unwrap_or(mime::APPLICATION_OCTET_STREAM),
+        "txt" => mime::TEXT_PLAIN_UTF_8,
+        _ => mime::APPLICATION_OCTET_STREAM,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn test_admin_ui_path() {
+        assert_eq!(is_admin_ui_path("/admin"), true);
+        assert_eq!(is_admin_ui_path("/admin/users/1"), true);
+        assert_eq!(is_admin_ui_path("/administrators"), false);
+        assert_eq!(is_admin_ui_path("/users/current"), false);
+    }
+
+    #[test]
+    fn test_relative_path() {
+        assert_eq!(relative_path("/admin"), Some(PathBuf::from("index.html")));
+        assert_eq!(relative_path("/admin/"), Some(PathBuf::from("index.html")));
+        assert_eq!(relative_path("/admin/static//app.js"), Some(PathBuf::from("static/app.js")));
+        assert_eq!(relative_path("/admin/../config/k8s.toml"), None);
+        assert_eq!(relative_path("/admin/static/..\\..\\keys"), None);
+        assert_eq!(relative_path("/admin/.env"), None);
+    }
+
+    #[test]
+    fn test_content_type() {
+        assert_eq!(content_type(Path::new("index.html")), mime::TEXT_HTML_UTF_8);
+        assert_eq!(content_type(Path::new("static/app.3f2a.js")), mime::APPLICATION_JAVASCRIPT_UTF_8);
+        assert_eq!(content_type(Path::new("LICENSE")), mime::APPLICATION_OCTET_STREAM);
+    }
+}
@@ -0,0 +1,203 @@
+//! Cache policy of API responses, declared per route. Applied by a middleware wrapping the
+//! application, as the controller returns bodies only.
+//!
+//! Responses related to authentication are never stored. Reference data and user profiles are
+//! cached for a short time, other reads are revalidated. Only successful reads are cached.
+
+use std::sync::Arc;
+
+use futures::Future;
+use hyper;
+use hyper::header::{CacheControl, CacheDirective};
+use hyper::server::{Request, Response, Service};
+use hyper::{Get, Head, Method};
+
+use stq_router::RouteParser;
+
+use super::routes::Route;
+use config;
+
+/// Cache policy of a response
+#[derive(Clone, Debug, PartialEq)]
+pub enum CachePolicy {
+    /// Never stored, for tokens, secrets and writes
+    NoStore,
+    /// Stored by the client only and revalidated on every request
+    Revalidate,
+    /// Stored by the client only for `max_age_s`, the response depends on the caller
+    Private { max_age_s: u32 },
+    /// Stored by shared caches for `max_age_s`, the response is the same for every caller
+    Public { max_age_s: u32 },
+}
+
+impl CachePolicy {
+    /// Policy of the request to the route, routes not found are not stored
+    pub fn of(method: &Method, route: Option<&Route>, conf: &config::CachePolicy) -> Self {
+        let route = match route {
+            Some(route) => route,
+            None => return CachePolicy::NoStore,
+        };
+        if is_auth_route(route) || *route == Route::Readiness || *route == Route::DependencyHealth {
+            return CachePolicy::NoStore;
+        }
+        match *method {
+            Get | Head => (),
+            _ => return CachePolicy::NoStore,
+        }
+        match *route {
+            Route::Countries | Route::PublicStats | Route::EventSchemas => CachePolicy::Public {
+                max_age_s: conf.reference_max_age_s,
+            },
+            Route::User(_) => CachePolicy::Private {
+                max_age_s: conf.profile_max_age_s,
+            },
+            _ => CachePolicy::Revalidate,
+        }
+    }
+
+    pub fn header(&self) -> CacheControl {
+        CacheControl(match *self {
+            CachePolicy::NoStore => vec![CacheDirective::NoStore],
+            CachePolicy::Revalidate => vec![CacheDirective::Private, CacheDirective::NoCache],
+            CachePolicy::Private { max_age_s } => vec![CacheDirective::Private, CacheDirective::MaxAge(max_age_s)],
+            CachePolicy::Public { max_age_s } => vec![CacheDirective::Public, CacheDirective::MaxAge(max_age_s)],
+        })
+    }
+}
+
+/// Routes issuing, resolving or changing credentials of users
+fn is_auth_route(route: &Route) -> bool {
+    match *route {
+        Route::JWTEmail
+        | Route::JWTGoogle
+        | Route::JWTFacebook
+        | Route::JWTRefresh
+        | Route::JWTRevoke
+        | Route::JWTStepUp
+        | Route::JWTRenew
+        | Route::JWTRenewSession
+        | Route::Sessions
+        | Route::SessionResolve
+        | Route::SessionLogout
+        | Route::Csrf
+        | Route::OAuthToken
+        | Route::OAuthDeviceCode
+        | Route::OAuthIntrospect
+        | Route::DeviceApprove
+        | Route::SignedActions
+        | Route::AccessTokens
+        | Route::AccessToken { .. }
+        | Route::DeviceTrust
+        | Route::ChildAccountPassword { .. }
+        | Route::PasswordChange
+        | Route::UserPasswordResetToken
+        | Route::ResetSecurityQuestions
+        | Route::UserSecurityAnswers { .. }
+        | Route::UserEmailVerifyToken
+        | Route::GetUserEmalVerifyToken { .. }
+        | Route::GetUserPasswordResetToken { .. }
+        | Route::Recovery
+        | Route::RecoveryApprove
+        | Route::AuthArchiveExport
+        | Route::AuthArchiveImport
+        | Route::UsersImport
+        | Route::BreakGlass => true,
+        _ => false,
+    }
+}
+
+/// Sets `Cache-Control` header of API responses by the policy of their routes
+pub struct CachePolicyService<S> {
+    inner: S,
+    route_parser: Arc<RouteParser<Route>>,
+    conf: config::CachePolicy,
+}
+
+impl<S> CachePolicyService<S> {
+    pub fn new(inner: S, route_parser: Arc<RouteParser<Route>>, conf: config::CachePolicy) -> Self {
+        Self { inner, route_parser, conf }
+    }
+}
+
+impl<S> Service for CachePolicyService<S>
+where
+    S: Service<Request = Request, Response = Response, Error = hyper::Error>,
+    S::Future: 'static,
+{
+    type Request = Request;
+    type Response = Response;
+    type Error = hyper::Error;
+    type Future = Box<Future<Item = Response, Error = hyper::Error>>;
+
+    fn call(&self, req: Request) -> Self::Future {
+        if !self.conf.enabled {
+            return Box::new(self.inner.call(req));
+        }
+        let route = self.route_parser.test(req.path());
+        let policy = CachePolicy::of(req.method(), route.as_ref(), &self.conf);
+        Box::new(self.inner.call(req).map(move |mut response| {
+            // failed responses are not cached, e.g. not to keep serving 404 of a just created user
+            let policy = if response.status().is_success() {
+                policy
+            } else {
+                CachePolicy::NoStore
+            };
+            if !response.headers().has::<CacheControl>() {
+                response.headers_mut().set(policy.header());
+            }
+            response
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Delete, Post, Put};
+    use stq_types::UserId;
+
+    use super::*;
+
+    fn create_conf() -> config::CachePolicy {
+        config::CachePolicy {
+            enabled: true,
+            profile_max_age_s: 30,
+            reference_max_age_s: 300,
+        }
+    }
+
+    #[test]
+    fn test_auth_routes_are_not_stored() {
+        let conf = create_conf();
+        assert_eq!(CachePolicy::of(&Post, Some(&Route::JWTEmail), &conf), CachePolicy::NoStore);
+        assert_eq!(CachePolicy::of(&Get, Some(&Route::Csrf), &conf), CachePolicy::NoStore);
+        assert_eq!(CachePolicy::of(&Get, Some(&Route::AccessTokens), &conf), CachePolicy::NoStore);
+        assert_eq!(CachePolicy::of(&Post, Some(&Route::BreakGlass), &conf), CachePolicy::NoStore);
+        assert_eq!(CachePolicy::of(&Post, Some(&Route::DeviceTrust), &conf), CachePolicy::NoStore);
+        assert_eq!(CachePolicy::of(&Get, None, &conf), CachePolicy::NoStore);
+    }
+
+    #[test]
+    fn test_reads_are_cached() {
+        let conf = create_conf();
+        assert_eq!(
+            CachePolicy::of(&Get, Some(&Route::Countries), &conf),
+            CachePolicy::Public { max_age_s: 300 }
+        );
+        assert_eq!(
+            CachePolicy::of(&Get, Some(&Route::PublicStats), &conf),
+            CachePolicy::Public { max_age_s: 300 }
+        );
+        assert_eq!(
+            CachePolicy::of(&Get, Some(&Route::User(UserId(1))), &conf),
+            CachePolicy::Private { max_age_s: 30 }
+        );
+        assert_eq!(CachePolicy::of(&Get, Some(&Route::Current), &conf), CachePolicy::Revalidate);
+    }
+
+    #[test]
+    fn test_writes_are_not_stored() {
+        let conf = create_conf();
+        assert_eq!(CachePolicy::of(&Put, Some(&Route::User(UserId(1))), &conf), CachePolicy::NoStore);
+        assert_eq!(CachePolicy::of(&Delete, Some(&Route::User(UserId(1))), &conf), CachePolicy::NoStore);
+    }
+}
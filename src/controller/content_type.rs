@@ -0,0 +1,84 @@
+//! Content types of API responses not serialized to JSON, declared per route. Applied by a
+//! middleware wrapping the application, as the controller returns bodies only.
+
+use std::sync::Arc;
+
+use futures::Future;
+use hyper;
+use hyper::header::{Charset, ContentDisposition, ContentType, DispositionParam, DispositionType};
+use hyper::mime::{self, Mime};
+use hyper::server::{Request, Response, Service};
+
+use stq_router::RouteParser;
+
+use super::routes::Route;
+
+/// Content type and file name of successful responses of the route, `None` for JSON
+pub fn content_type_of(route: &Route) -> Option<(Mime, String)> {
+    match *route {
+        Route::SegmentExportCsv { id } => Some((mime::TEXT_CSV_UTF_8, format!("segment-{}.csv", id))),
+        _ => None,
+    }
+}
+
+/// Sets `Content-Type` and `Content-Disposition` headers of responses with files
+pub struct ContentTypeService<S> {
+    inner: S,
+    route_parser: Arc<RouteParser<Route>>,
+}
+
+impl<S> ContentTypeService<S> {
+    pub fn new(inner: S, route_parser: Arc<RouteParser<Route>>) -> Self {
+        Self { inner, route_parser }
+    }
+}
+
+impl<S> Service for ContentTypeService<S>
+where
+    S: Service<Request = Request, Response = Response, Error = hyper::Error>,
+    S::Future: 'static,
+{
+    type Request = Request;
+    type Response = Response;
+    type Error = hyper::Error;
+    type Future = Box<Future<Item = Response, Error = hyper::Error>>;
+
+    fn call(&self, req: Request) -> Self::Future {
+        let content_type = self.route_parser.test(req.path()).as_ref().and_then(content_type_of);
+        Box::new(self.inner.call(req).map(move |mut response| {
+            // errors are still serialized to JSON
+            if let Some((mime, file_name)) = content_type {
+                if response.status().is_success() {
+                    let headers = response.headers_mut();
+                    headers.set(ContentType(mime));
+                    headers.set(ContentDisposition {
+                        disposition: DispositionType::Attachment,
+                        parameters: vec![DispositionParam::Filename(
+                            Charset::Ext("UTF-8".to_string()),
+                            None,
+                            file_name.into_bytes(),
+                        )],
+                    });
+                }
+            }
+            response
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use stq_types::UserId;
+    use uuid::Uuid;
+
+    use super::*;
+
+    #[test]
+    fn test_content_type_of_segment_export_csv() {
+        let id = Uuid::nil();
+        let (mime, file_name) = content_type_of(&Route::SegmentExportCsv { id }).unwrap();
+        assert_eq!(mime, mime::TEXT_CSV_UTF_8);
+        assert_eq!(file_name, format!("segment-{}.csv", id));
+        assert_eq!(content_type_of(&Route::User(UserId(1))), None);
+    }
+}
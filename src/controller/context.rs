@@ -1,22 +1,33 @@
 //! `Context` is a top level module containg static context and dynamic context for each request
+use std::net::IpAddr;
 use std::sync::Arc;
 
 use diesel::connection::AnsiTransactionManager;
 use diesel::pg::Pg;
 use diesel::Connection;
+#[cfg(feature = "chaos")]
 use futures_cpupool::CpuPool;
-use r2d2::{ManageConnection, Pool};
+use r2d2::ManageConnection;
 
 use stq_http::client::{ClientHandle, TimeLimitedHttpClient};
 use stq_router::RouteParser;
 use stq_types::UserId;
 
+use super::deprecation::DeprecatedRouteUsage;
 use super::routes::*;
 use config::{ApiMode, Config};
 use repos::repo_factory::*;
+use repos::sharding::ShardedPool;
 use services::jwt::profile::{FacebookProfile, GoogleProfile};
 use services::jwt::{JWTProviderService, JWTProviderServiceImpl};
+#[cfg(feature = "chaos")]
+use services::mocks::chaos::ChaosProviderService;
 use services::mocks::jwt::JWTProviderServiceMock;
+use services::name_screening::NameScreeningService;
+use services::readiness::Readiness;
+use services::rollout::Rollouts;
+use services::single_flight::SingleFlights;
+use services::work_pool::{WorkPool, WorkPriority};
 
 /// Static context for all app
 pub struct StaticContext<T, M, F>
@@ -25,13 +36,23 @@ where
     M: ManageConnection<Connection = T>,
     F: ReposFactory<T>,
 {
-    pub db_pool: Pool<M>,
-    pub cpu_pool: CpuPool,
+    pub db_pool: ShardedPool<M>,
+    pub work_pool: WorkPool,
     pub config: Arc<Config>,
     pub route_parser: Arc<RouteParser<Route>>,
     pub client_handle: ClientHandle,
     pub repo_factory: F,
     pub jwt_private_key: Vec<u8>,
+    /// Key of HMACs of opaque tokens, cookies and links, see `config::JWT::hmac_secret`
+    pub hmac_key: Vec<u8>,
+    pub name_screening: Arc<NameScreeningService>,
+    pub single_flights: SingleFlights,
+    pub deprecated_route_usage: DeprecatedRouteUsage,
+    pub rollouts: Rollouts,
+    pub readiness: Arc<Readiness>,
+    /// Threads awaiting latency injected into provider calls, if fault injection is configured
+    #[cfg(feature = "chaos")]
+    pub chaos_pool: Option<CpuPool>,
 }
 
 impl<
@@ -42,22 +63,35 @@ impl<
 {
     /// Create a new static context
     pub fn new(
-        db_pool: Pool<M>,
-        cpu_pool: CpuPool,
+        db_pool: ShardedPool<M>,
+        work_pool: WorkPool,
         client_handle: ClientHandle,
         config: Arc<Config>,
         repo_factory: F,
         jwt_private_key: Vec<u8>,
+        name_screening: Arc<NameScreeningService>,
     ) -> Self {
         let route_parser = Arc::new(create_route_parser());
+        // instance is not ready until enabled startup checks are done
+        let readiness = Arc::new(Readiness::new(!config.warmup.enabled, !config.schema_check.enabled));
+        let rollouts = Rollouts::new(config.rollouts.clone());
+        let hmac_key = config.jwt.hmac_secret.as_bytes().to_vec();
         Self {
             route_parser,
             db_pool,
-            cpu_pool,
+            work_pool,
             client_handle,
             config,
             repo_factory,
             jwt_private_key,
+            hmac_key,
+            name_screening,
+            single_flights: SingleFlights::default(),
+            deprecated_route_usage: DeprecatedRouteUsage::default(),
+            rollouts,
+            readiness,
+            #[cfg(feature = "chaos")]
+            chaos_pool: chaos_pool(&config),
         }
     }
 
@@ -81,11 +115,49 @@ impl<
                 })
             };
 
+        #[cfg(feature = "chaos")]
+        let (google_provider_service, facebook_provider_service) = self.inject_faults(google_provider_service, facebook_provider_service);
+
         DynamicContextServices {
             google_provider_service,
             facebook_provider_service,
         }
     }
+
+    /// Wraps provider services into fault injection configured for them
+    #[cfg(feature = "chaos")]
+    fn inject_faults(
+        &self,
+        mut google_provider_service: Arc<JWTProviderService<GoogleProfile>>,
+        mut facebook_provider_service: Arc<JWTProviderService<FacebookProfile>>,
+    ) -> (Arc<JWTProviderService<GoogleProfile>>, Arc<JWTProviderService<FacebookProfile>>) {
+        if let (Some(chaos), Some(chaos_pool)) = (self.config.chaos.as_ref(), self.chaos_pool.as_ref()) {
+            if let Some(ref faults) = chaos.google {
+                google_provider_service = Arc::new(ChaosProviderService::new(
+                    google_provider_service,
+                    faults.clone(),
+                    chaos_pool.clone(),
+                ));
+            }
+            if let Some(ref faults) = chaos.facebook {
+                facebook_provider_service = Arc::new(ChaosProviderService::new(
+                    facebook_provider_service,
+                    faults.clone(),
+                    chaos_pool.clone(),
+                ));
+            }
+        }
+        (google_provider_service, facebook_provider_service)
+    }
+}
+
+/// Latency is awaited on its own threads, so that fault injection does not take threads of db work
+#[cfg(feature = "chaos")]
+fn chaos_pool(config: &Config) -> Option<CpuPool> {
+    config.chaos.as_ref().map(|chaos| {
+        warn!("Fault injection into provider calls is enabled, it must never be used in production");
+        CpuPool::new(chaos.latency_threads.max(1))
+    })
 }
 
 pub struct DynamicContextServices {
@@ -101,13 +173,19 @@ impl<
 {
     fn clone(&self) -> Self {
         Self {
-            cpu_pool: self.cpu_pool.clone(),
+            work_pool: self.work_pool.clone(),
             db_pool: self.db_pool.clone(),
             route_parser: self.route_parser.clone(),
             client_handle: self.client_handle.clone(),
             config: self.config.clone(),
             repo_factory: self.repo_factory.clone(),
             jwt_private_key: self.jwt_private_key.clone(),
+            hmac_key: self.hmac_key.clone(),
+            name_screening: self.name_screening.clone(),
+            single_flights: self.single_flights.clone(),
+            deprecated_route_usage: self.deprecated_route_usage.clone(),
+            rollouts: self.rollouts.clone(),
+            readiness: self.readiness.clone(),
         }
     }
 }
@@ -116,7 +194,19 @@ impl<
 #[derive(Clone)]
 pub struct DynamicContext {
     pub user_id: Option<UserId>,
+    pub auth_time: Option<i64>,
+    pub client_ip: Option<IpAddr>,
+    /// Country of the client address, resolved by the gateway from GeoIP
+    pub client_country: Option<String>,
+    pub user_agent: Option<String>,
+    pub device_fingerprint: Option<String>,
+    /// Value of the trusted device cookie
+    pub device_cookie: Option<String>,
     pub correlation_token: String,
+    /// Queue the db work of the request runs on
+    pub work_priority: WorkPriority,
+    /// Timing of login stages is requested with `X-Login-Timing`
+    pub login_timing: bool,
     pub http_client: TimeLimitedHttpClient<ClientHandle>,
     pub google_provider_service: Arc<JWTProviderService<GoogleProfile>>,
     pub facebook_provider_service: Arc<JWTProviderService<FacebookProfile>>,
@@ -126,14 +216,30 @@ impl DynamicContext {
     /// Create a new dynamic context for each request
     pub fn new(
         user_id: Option<UserId>,
+        auth_time: Option<i64>,
+        client_ip: Option<IpAddr>,
+        client_country: Option<String>,
+        user_agent: Option<String>,
+        device_fingerprint: Option<String>,
+        device_cookie: Option<String>,
         correlation_token: String,
+        work_priority: WorkPriority,
+        login_timing: bool,
         http_client: TimeLimitedHttpClient<ClientHandle>,
         google_provider_service: Arc<JWTProviderService<GoogleProfile>>,
         facebook_provider_service: Arc<JWTProviderService<FacebookProfile>>,
     ) -> Self {
         Self {
             user_id,
+            auth_time,
+            client_ip,
+            client_country,
+            user_agent,
+            device_fingerprint,
+            device_cookie,
             correlation_token,
+            work_priority,
+            login_timing,
             http_client,
             google_provider_service,
             facebook_provider_service,
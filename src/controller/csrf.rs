@@ -0,0 +1,56 @@
+//! CSRF protection of cookie-based flows with signed double-submit tokens. Browser gets the token
+//! both in the body of `GET /csrf` and in the cookie, and sends it back in `X-CSRF-Token` header.
+//! Pages of other sites can make the browser send cookies, but can not read them to set the header.
+//! Requests authenticated with bearer tokens are not affected, as browsers never add them by themselves.
+
+use failure::Error as FailureError;
+use failure::Fail;
+use hyper::header::Cookie;
+use hyper::server::Request;
+use hyper::{Get, Method};
+
+use super::headers::XCsrfToken;
+use super::routes::Route;
+use config::Config;
+use errors::Error;
+use models::CsrfToken;
+use services::util::{http_only_cookie, signed_token_create, signed_token_verify, token_hash};
+
+/// Creates new CSRF token along with the cookie holding it
+pub fn create_token(config: &Config, key: &[u8]) -> CsrfToken {
+    let csrf_token = signed_token_create(key);
+    let set_cookie = http_only_cookie(
+        &config.csrf.cookie_name,
+        &csrf_token,
+        &config.csrf.cookie_path,
+        config.csrf.max_age_s as i64,
+    );
+    CsrfToken { csrf_token, set_cookie }
+}
+
+/// Checks CSRF token of unsafe requests made with session or refresh token cookie.
+/// Gateway requests creating and resolving sessions do not come from the browser.
+pub fn verify(req: &Request, route: &Option<Route>, config: &Config, key: &[u8]) -> Result<(), FailureError> {
+    match (req.method(), route) {
+        (&Get, _) | (&Method::Head, _) | (&Method::Options, _) => return Ok(()),
+        (_, &Some(Route::Sessions)) | (_, &Some(Route::SessionResolve)) => return Ok(()),
+        _ => (),
+    }
+    let cookie = match req.headers().get::<Cookie>() {
+        Some(cookie) => cookie,
+        None => return Ok(()),
+    };
+    let auth_cookies = [&config.sessions.cookie_name, &config.refresh_tokens.cookie_name];
+    if auth_cookies.iter().all(|name| cookie.get(name.as_str()).is_none()) {
+        return Ok(());
+    }
+
+    let header = req.headers().get::<XCsrfToken>().map(|header| header.0.as_str());
+    match (header, cookie.get(&config.csrf.cookie_name)) {
+        // hashes are compared so that the comparison time does not depend on the token
+        (Some(header), Some(cookie)) if signed_token_verify(key, header) && token_hash(header) == token_hash(cookie) => Ok(()),
+        _ => Err(format_err!("CSRF token is missing or does not match, {} {:?}", req.method(), route)
+            .context(Error::Forbidden)
+            .into()),
+    }
+}
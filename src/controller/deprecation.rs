@@ -0,0 +1,122 @@
+//! Deprecation of API routes, declared per route by `Route::deprecation`. Applied by a
+//! middleware wrapping the application, which sets `Deprecation`, `Sunset` and successor `Link`
+//! headers on responses of deprecated routes and counts their requests. Counters are served by
+//! the internal listener, a deprecated route is safe to remove once they stop growing.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, UNIX_EPOCH};
+
+use futures::Future;
+use hyper;
+use hyper::header::{HttpDate, Link, LinkValue, RelationType};
+use hyper::server::{Request, Response, Service};
+
+use stq_router::RouteParser;
+
+use super::headers::{Deprecation as DeprecationHeader, Sunset};
+use super::routes::{Deprecation, Route};
+
+/// Requests to deprecated routes by path template
+#[derive(Clone, Debug, Default)]
+pub struct DeprecatedRouteUsage {
+    counters: Arc<Mutex<BTreeMap<&'static str, usize>>>,
+}
+
+impl DeprecatedRouteUsage {
+    pub fn record(&self, route: &Route) {
+        let mut counters = self.counters.lock().unwrap();
+        *counters.entry(route.template()).or_insert(0) += 1;
+    }
+
+    pub fn stats(&self) -> BTreeMap<&'static str, usize> {
+        self.counters.lock().unwrap().clone()
+    }
+}
+
+/// Sets deprecation headers of the route on the response
+pub fn set_deprecation_headers(deprecation: &Deprecation, response: &mut Response) {
+    let headers = response.headers_mut();
+    headers.set(DeprecationHeader(http_date(deprecation.deprecated_at)));
+    headers.set(Sunset(http_date(deprecation.sunset_at)));
+    if let Some(successor) = deprecation.successor {
+        headers.set(Link::new(vec![LinkValue::new(successor).push_rel(RelationType::SuccessorVersion)]));
+    }
+}
+
+fn http_date(secs: u64) -> HttpDate {
+    HttpDate::from(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// Marks responses of deprecated routes and counts their usage
+pub struct DeprecationService<S> {
+    inner: S,
+    route_parser: Arc<RouteParser<Route>>,
+    usage: DeprecatedRouteUsage,
+}
+
+impl<S> DeprecationService<S> {
+    pub fn new(inner: S, route_parser: Arc<RouteParser<Route>>, usage: DeprecatedRouteUsage) -> Self {
+        Self {
+            inner,
+            route_parser,
+            usage,
+        }
+    }
+}
+
+impl<S> Service for DeprecationService<S>
+where
+    S: Service<Request = Request, Response = Response, Error = hyper::Error>,
+    S::Future: 'static,
+{
+    type Request = Request;
+    type Response = Response;
+    type Error = hyper::Error;
+    type Future = Box<Future<Item = Response, Error = hyper::Error>>;
+
+    fn call(&self, req: Request) -> Self::Future {
+        let deprecation = match self.route_parser.test(req.path()) {
+            Some(route) => route.deprecation().map(|deprecation| {
+                self.usage.record(&route);
+                deprecation
+            }),
+            None => None,
+        };
+        match deprecation {
+            Some(deprecation) => Box::new(self.inner.call(req).map(move |mut response| {
+                set_deprecation_headers(&deprecation, &mut response);
+                response
+            })),
+            None => Box::new(self.inner.call(req)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deprecation_headers() {
+        let route = Route::UserBySagaId("saga".to_string());
+        let mut response = Response::new();
+        set_deprecation_headers(&route.deprecation().unwrap(), &mut response);
+        let headers = response.headers();
+        assert_eq!(headers.get_raw("Deprecation").unwrap(), "Mon, 01 Apr 2019 00:00:00 GMT");
+        assert_eq!(headers.get_raw("Sunset").unwrap(), "Tue, 01 Oct 2019 00:00:00 GMT");
+        assert_eq!(
+            headers.get_raw("Link").unwrap(),
+            "</users/by_saga_id/:saga_id>; rel=\"successor-version\""
+        );
+    }
+
+    #[test]
+    fn test_deprecated_route_usage() {
+        let usage = DeprecatedRouteUsage::default();
+        usage.record(&Route::UserBySagaId("first".to_string()));
+        usage.record(&Route::UserBySagaId("second".to_string()));
+        assert_eq!(usage.stats().get("/user_by_saga_id/:saga_id"), Some(&2));
+        assert_eq!(Route::UsersBySagaId("saga".to_string()).deprecation(), None);
+    }
+}
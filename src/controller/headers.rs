@@ -0,0 +1,55 @@
+//! Custom headers forwarded by the gateway along with the authenticated user id
+use std::net::IpAddr;
+
+use hyper::header::HttpDate;
+
+header! {
+    /// Value of the `auth_time` claim of the token the request was made with
+    (AuthTime, "Auth-Time") => [i64]
+}
+
+header! {
+    /// Value of the `scope` claim of the token, set for tokens of third-party clients, remember-me
+    /// and password change tokens only
+    (TokenScope, "Token-Scope") => [String]
+}
+
+header! {
+    /// Chain of client addresses set by proxies, only entries appended by trusted proxies are reliable
+    (XForwardedFor, "X-Forwarded-For") => (IpAddr)+
+}
+
+header! {
+    /// ISO 3166-1 alpha-3 country of the client address, resolved by the gateway from GeoIP
+    (XClientCountry, "X-Client-Country") => [String]
+}
+
+header! {
+    /// Fingerprint of the device the request is made from, computed by the client
+    (XDeviceFingerprint, "X-Device-Fingerprint") => [String]
+}
+
+header! {
+    /// CSRF token of cookie-based flows, must match the CSRF cookie
+    (XCsrfToken, "X-CSRF-Token") => [String]
+}
+
+header! {
+    /// Requests timing of login stages in the response, honored for superusers only
+    (XLoginTiming, "X-Login-Timing") => [bool]
+}
+
+header! {
+    /// Disables MIME type sniffing of static files by browsers
+    (XContentTypeOptions, "X-Content-Type-Options") => [String]
+}
+
+header! {
+    /// Date the route was deprecated at, set on responses of deprecated routes
+    (Deprecation, "Deprecation") => [HttpDate]
+}
+
+header! {
+    /// Date the deprecated route is removed after, RFC 8594
+    (Sunset, "Sunset") => [HttpDate]
+}
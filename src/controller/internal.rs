@@ -0,0 +1,109 @@
+//! Controller of the internal listener. Debug endpoints are served on `server.internal_host` and
+//! `server.internal_port`, which are reachable from the pod only, so they are not checked for user roles.
+
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::{future, Future, IntoFuture};
+use hyper::{server::Request, Get, Put};
+use log;
+
+use stq_http::controller::{Controller, ControllerFuture};
+use stq_http::request_util::serialize_future;
+use stq_router::RouteParser;
+
+use super::deprecation::DeprecatedRouteUsage;
+use super::routes::{create_internal_route_parser, InternalRoute};
+use super::utils::parse_json_body;
+use errors::Error;
+use logging;
+use services::rollout::Rollouts;
+use services::single_flight::SingleFlights;
+use services::work_pool::WorkPool;
+
+/// Filter of log records with the syntax of `RUST_LOG`, e.g. `info` for all modules
+/// or `warn,users_lib::services::jwt=debug` for specific ones
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LogLevel {
+    pub level: String,
+}
+
+impl LogLevel {
+    pub fn current() -> Self {
+        Self {
+            level: logging::current_filter().unwrap_or_else(|| log::max_level().to_string().to_lowercase()),
+        }
+    }
+}
+
+pub struct InternalControllerImpl {
+    pub route_parser: RouteParser<InternalRoute>,
+    pub max_body_size: usize,
+    pub single_flights: SingleFlights,
+    pub deprecated_route_usage: DeprecatedRouteUsage,
+    pub rollouts: Rollouts,
+    pub work_pool: WorkPool,
+}
+
+impl InternalControllerImpl {
+    pub fn new(
+        max_body_size: usize,
+        single_flights: SingleFlights,
+        deprecated_route_usage: DeprecatedRouteUsage,
+        rollouts: Rollouts,
+        work_pool: WorkPool,
+    ) -> Self {
+        Self {
+            route_parser: create_internal_route_parser(),
+            max_body_size,
+            single_flights,
+            deprecated_route_usage,
+            rollouts,
+            work_pool,
+        }
+    }
+}
+
+impl Controller for InternalControllerImpl {
+    fn call(&self, req: Request) -> ControllerFuture {
+        match (&req.method().clone(), self.route_parser.test(req.path())) {
+            // GET /debug/log_level
+            (&Get, Some(InternalRoute::LogLevel)) => serialize_future(future::ok::<_, FailureError>(LogLevel::current())),
+
+            // PUT /debug/log_level
+            (&Put, Some(InternalRoute::LogLevel)) => serialize_future(
+                parse_json_body::<LogLevel>(req.body(), self.max_body_size)
+                    .map_err(|e| e.context("Parsing body failed, target: LogLevel").context(Error::Parse).into())
+                    .and_then(|payload| {
+                        logging::set_filter(&payload.level)
+                            .map(|_| payload.level)
+                            .map_err(|e| e.context(Error::Parse).into())
+                            .into_future()
+                    })
+                    .map(|level| {
+                        warn!("Log filter is changed to {}", level);
+                        LogLevel::current()
+                    }),
+            ),
+
+            // GET /debug/single_flights
+            (&Get, Some(InternalRoute::SingleFlights)) => serialize_future(future::ok::<_, FailureError>(self.single_flights.stats())),
+
+            // GET /debug/deprecated_routes
+            (&Get, Some(InternalRoute::DeprecatedRoutes)) => {
+                serialize_future(future::ok::<_, FailureError>(self.deprecated_route_usage.stats()))
+            }
+
+            // GET /debug/rollouts
+            (&Get, Some(InternalRoute::Rollouts)) => serialize_future(future::ok::<_, FailureError>(self.rollouts.stats())),
+
+            // GET /debug/work_queues
+            (&Get, Some(InternalRoute::WorkQueues)) => serialize_future(future::ok::<_, FailureError>(self.work_pool.stats())),
+
+            (m, _) => Box::new(future::err(
+                format_err!("Request to non existing internal endpoint {:?} {:?}", m, req.path())
+                    .context(Error::NotFound)
+                    .into(),
+            )),
+        }
+    }
+}
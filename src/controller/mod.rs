@@ -3,39 +3,93 @@
 //! Basically it provides inputs to `Service` layer and converts outputs
 //! of `Service` layer to http responses
 
+pub mod access_log;
+pub mod admin_ui;
+pub mod cache_policy;
+pub mod content_type;
 pub mod context;
+pub mod csrf;
+pub mod deprecation;
+pub mod headers;
+pub mod internal;
+pub mod read_only;
 pub mod routes;
 pub mod utils;
 
+use std::net::IpAddr;
 use std::str::FromStr;
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use chrono::Utc;
+use chrono::{NaiveDate, Utc};
 use diesel::{connection::AnsiTransactionManager, pg::Pg, Connection};
+use failure::Error as FailureError;
 use failure::Fail;
 use futures::{future, Future, IntoFuture};
-use hyper::{header::Authorization, server::Request, Delete, Get, Post, Put};
+use hyper::{
+    header::{AcceptLanguage, Authorization, ContentLength, Cookie, UserAgent},
+    server::Request,
+    Delete, Get, Method, Post, Put,
+};
 use r2d2::ManageConnection;
+use serde_json;
 use validator::Validate;
 
 use stq_http::{
     client::TimeLimitedHttpClient,
     controller::{Controller, ControllerFuture},
     errors::ErrorMessageWrapper,
-    request_util::{self, parse_body, serialize_future, RequestTimeout as RequestTimeoutHeader},
+    request_util::{self, serialize_future, RequestTimeout as RequestTimeoutHeader},
 };
 use stq_static_resources::TokenType;
 use stq_types::UserId;
 
+use self::access_log::{duration_ms, log_access, log_break_glass_access, AccessRecord};
 use self::context::{DynamicContext, DynamicContextServices, StaticContext};
+use self::headers::{AuthTime, TokenScope, XClientCountry, XDeviceFingerprint, XForwardedFor, XLoginTiming};
 use self::routes::Route;
+use self::utils::{parse_form_body, parse_json_body};
 use errors::Error;
-use models;
+use i18n::{self, Locale};
+use models::{self, OAuthScope};
 use repos::repo_factory::*;
 use sentry_integration::log_and_capture_error;
+use services::access_tokens::AccessTokensService;
+use services::auth_archive::AuthArchiveService;
+use services::break_glass::BreakGlassService;
+use services::child_accounts::ChildAccountsService;
+use services::client_branding::ClientBrandingService;
+use services::connected_apps::ConnectedAppsService;
+use services::countries::CountriesService;
+use services::crm_reconciliation::CrmReconciliationService;
+use services::deletion_requests::DeletionRequestsService;
+use services::dependency_health::DependencyHealthService;
+use services::devices::DevicesService;
+use services::event_schemas::EventSchemasService;
+use services::funnel::FunnelService;
+use services::identities::IdentitiesService;
+use services::invites::InvitesService;
+use services::jobs::JobsService;
 use services::jwt::JWTService;
+use services::legacy_sync::LegacySyncService;
+use services::login_stats::LoginStatsService;
+use services::oauth::OAuthService;
+use services::password_strength::PasswordStrengthService;
+use services::profile_prompts::ProfilePromptsService;
+use services::recovery::RecoveryService;
+use services::refresh_tokens::RefreshTokensService;
+use services::role_access_policies::RoleAccessPoliciesService;
+use services::security_questions::SecurityQuestionsService;
+use services::segment_export::SegmentExportService;
+use services::sessions::SessionsService;
+use services::signed_action::SignedActionService;
+use services::stats::StatsService;
+use services::user_import::UserImportService;
 use services::user_roles::UserRolesService;
+use services::user_tags::UserTagsService;
 use services::users::UsersService;
+use services::waitlist::WaitlistService;
+use services::work_pool::WorkPriority;
 use services::Service;
 
 /// Controller handles route parsing and calling `Service` layer
@@ -45,7 +99,7 @@ where
     M: ManageConnection<Connection = T>,
     F: ReposFactory<T>,
 {
-    pub static_context: StaticContext<T, M, F>,
+    pub static_context: Arc<StaticContext<T, M, F>>,
 }
 
 impl<
@@ -55,7 +109,7 @@ impl<
     > ControllerImpl<T, M, F>
 {
     /// Create a new controller based on services
-    pub fn new(static_context: StaticContext<T, M, F>) -> Self {
+    pub fn new(static_context: Arc<StaticContext<T, M, F>>) -> Self {
         Self { static_context }
     }
 
@@ -64,18 +118,57 @@ impl<
 
         Utc::now().timestamp() + jwt_expiration_s as i64
     }
-}
 
-impl<
-        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
-        M: ManageConnection<Connection = T>,
-        F: ReposFactory<T>,
-    > Controller for ControllerImpl<T, M, F>
-{
-    /// Handle a request and get future response
-    fn call(&self, req: Request) -> ControllerFuture {
+    /// Guard for destructive self-service endpoints. Requires the user to have authenticated
+    /// within the last `tokens.reauth_window_s` seconds according to the `auth_time` claim.
+    fn require_recent_auth(&self, auth_time: Option<i64>) -> Result<(), FailureError> {
+        let reauth_window_s = self.static_context.config.tokens.reauth_window_s as i64;
+
+        match auth_time {
+            Some(auth_time) if Utc::now().timestamp() - auth_time <= reauth_window_s => Ok(()),
+            _ => Err(format_err!("Authentication is too old, auth_time: {:?}", auth_time)
+                .context(Error::ReauthRequired)
+                .into()),
+        }
+    }
+
+    /// Handle a request to the route and get future response
+    fn handle(&self, req: Request, route: Option<Route>) -> ControllerFuture {
         let user_id = get_user_id(&req);
+        let auth_time = get_auth_time(&req);
+        let token_scopes = match get_token_scopes(&req).and_then(|token_scopes| {
+            require_scope(req.method(), &route, token_scopes.as_ref())?;
+            Ok(token_scopes)
+        }) {
+            Ok(token_scopes) => token_scopes,
+            Err(e) => return Box::new(future::err(e)),
+        };
+        if let Err(e) = csrf::verify(&req, &route, &self.static_context.config, &self.static_context.hmac_key) {
+            return Box::new(future::err(e));
+        }
+        if let Err(e) = read_only::verify(req.method(), &route, &self.static_context.config) {
+            return Box::new(future::err(e));
+        }
+        let client_ip = get_client_ip(&req, self.static_context.config.server.trusted_proxy_hops);
+        let client_country = req.headers().get::<XClientCountry>().map(|country| country.0.clone());
+        let user_agent = req.headers().get::<UserAgent>().map(|user_agent| user_agent.to_string());
+        let device_fingerprint = req.headers().get::<XDeviceFingerprint>().map(|fingerprint| fingerprint.0.clone());
+        let device_cookie = get_cookie(&req, &self.static_context.config.devices.cookie_name);
+        let locale = get_locale(&req);
         let correlation_token = request_util::get_correlation_token(&req);
+        let work_priority = route.as_ref().map(Route::work_priority).unwrap_or(WorkPriority::Regular);
+        let login_timing = req.headers().get::<XLoginTiming>().map(|timing| timing.0).unwrap_or(false);
+        let max_body_size = self.static_context.config.server.max_body_size;
+
+        if let Some(&ContentLength(length)) = req.headers().get::<ContentLength>() {
+            if length > max_body_size as u64 {
+                return Box::new(future::err(
+                    format_err!("Request body of {} bytes exceeds {} bytes", length, max_body_size)
+                        .context(Error::PayloadTooLarge)
+                        .into(),
+                ));
+            }
+        }
 
         let request_timeout = req
             .headers()
@@ -95,7 +188,15 @@ impl<
 
         let dynamic_context = DynamicContext::new(
             user_id,
+            auth_time,
+            client_ip,
+            client_country,
+            user_agent,
+            device_fingerprint,
+            device_cookie,
             correlation_token,
+            work_priority,
+            login_timing,
             time_limited_http_client,
             google_provider_service,
             facebook_provider_service,
@@ -107,17 +208,154 @@ impl<
 
         let path = req.path().to_string();
 
-        let fut = match (&req.method().clone(), self.static_context.route_parser.test(req.path())) {
+        let fut = match (&req.method().clone(), route) {
             // GET /users/<user_id>
             (&Get, Some(Route::User(user_id))) => serialize_future(service.get(user_id)),
 
             // GET /users/current
-            (&Get, Some(Route::Current)) => serialize_future(service.current()),
+            (&Get, Some(Route::Current)) => {
+                serialize_future(service.current().and_then(move |user| scoped_profile(user, token_scopes.as_ref())))
+            }
+
+            // GET /users/current/prompts
+            (&Get, Some(Route::CurrentProfilePrompts)) => serialize_future(service.get_profile_prompts()),
+
+            // POST /users/current/prompts/dismiss
+            (&Post, Some(Route::DismissProfilePrompt)) => serialize_future(
+                parse_json_body::<models::DismissProfilePrompt>(req.body(), max_body_size)
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: DismissProfilePrompt")
+                            .context(Error::Parse)
+                            .into()
+                    })
+                    .and_then(move |payload| service.dismiss_profile_prompt(payload)),
+            ),
+
+            // GET /users/current/children
+            (&Get, Some(Route::ChildAccounts)) => serialize_future(service.get_child_accounts()),
+
+            // POST /users/current/children
+            (&Post, Some(Route::ChildAccounts)) => serialize_future(
+                parse_json_body::<models::NewChildAccountPayload>(req.body(), max_body_size)
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: NewChildAccountPayload")
+                            .context(Error::Parse)
+                            .into()
+                    })
+                    .and_then(move |payload| {
+                        payload
+                            .validate()
+                            .map_err(|e| {
+                                format_err!("Validation failed, target: NewChildAccountPayload")
+                                    .context(Error::Validate(e))
+                                    .into()
+                            })
+                            .into_future()
+                            .and_then(move |_| service.create_child_account(payload))
+                    }),
+            ),
+
+            // GET /users/current/children/<child_id>
+            (&Get, Some(Route::ChildAccount { child_id })) => serialize_future(service.get_child_profile(child_id)),
+
+            // POST /users/current/children/<child_id>/password
+            (&Post, Some(Route::ChildAccountPassword { child_id })) => serialize_future(
+                parse_json_body::<models::ChildPasswordReset>(req.body(), max_body_size)
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: ChildPasswordReset")
+                            .context(Error::Parse)
+                            .into()
+                    })
+                    .and_then(move |payload| {
+                        payload
+                            .validate()
+                            .map_err(|e| {
+                                format_err!("Validation failed, target: ChildPasswordReset")
+                                    .context(Error::Validate(e))
+                                    .into()
+                            })
+                            .into_future()
+                            .and_then(move |_| service.reset_child_password(child_id, payload))
+                    }),
+            ),
+
+            // GET /users/current/tokens
+            (&Get, Some(Route::AccessTokens)) => serialize_future(service.get_access_tokens()),
+
+            // POST /users/current/tokens
+            (&Post, Some(Route::AccessTokens)) => serialize_future(
+                parse_json_body::<models::NewAccessTokenPayload>(req.body(), max_body_size)
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: NewAccessTokenPayload")
+                            .context(Error::Parse)
+                            .into()
+                    })
+                    .and_then(move |payload| {
+                        payload
+                            .validate()
+                            .map_err(|e| {
+                                format_err!("Validation failed, target: NewAccessTokenPayload")
+                                    .context(Error::Validate(e))
+                                    .into()
+                            })
+                            .into_future()
+                            .and_then(move |_| service.create_access_token(payload))
+                    }),
+            ),
+
+            // DELETE /users/current/tokens/<id>
+            (&Delete, Some(Route::AccessToken { id })) => serialize_future(service.revoke_access_token(id)),
+
+            // GET /users/current/connected_apps
+            (&Get, Some(Route::ConnectedApps)) => serialize_future(service.get_connected_apps()),
+
+            // DELETE /users/current/connected_apps/<client_id>
+            (&Delete, Some(Route::ConnectedApp { client_id })) => serialize_future(service.disconnect_app(client_id)),
+
+            // GET /clients/<client_id>/branding
+            (&Get, Some(Route::ClientBranding { client_id })) => serialize_future(service.get_client_branding(client_id)),
+
+            // PUT /clients/<client_id>/branding
+            (&Put, Some(Route::ClientBranding { client_id })) => serialize_future(
+                parse_json_body::<models::ClientBrandingPayload>(req.body(), max_body_size)
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: ClientBrandingPayload")
+                            .context(Error::Parse)
+                            .into()
+                    })
+                    .and_then(move |payload| {
+                        payload
+                            .validate()
+                            .map_err(|e| {
+                                format_err!("Validation failed, target: ClientBrandingPayload")
+                                    .context(Error::Validate(e))
+                                    .into()
+                            })
+                            .into_future()
+                            .and_then(move |_| service.set_client_branding(client_id, payload))
+                    }),
+            ),
+
+            // GET /users/current/devices
+            (&Get, Some(Route::Devices)) => serialize_future(service.get_devices()),
+
+            // POST /users/current/devices/trust
+            (&Post, Some(Route::DeviceTrust)) => serialize_future(
+                self.require_recent_auth(auth_time)
+                    .into_future()
+                    .and_then(move |_| service.trust_device()),
+            ),
+
+            // GET /users/current/devices/<id>
+            (&Get, Some(Route::Device { id })) => serialize_future(service.get_device(id)),
+
+            // DELETE /users/current/devices/<id>
+            (&Delete, Some(Route::Device { id })) => serialize_future(service.delete_device(id)),
 
             // GET /users/by_email
             (&Get, Some(Route::UserByEmail)) => {
                 if let Some(email) = parse_query!(req.query().unwrap_or_default(), "email" => String) {
-                    serialize_future(service.find_by_email(email.to_lowercase()))
+                    serialize_future(service.find_by_email(models::normalize_email(&email)))
                 } else {
                     Box::new(future::err(
                         format_err!("Parsing query parameters failed, action: get user by email")
@@ -126,10 +364,14 @@ impl<
                     ))
                 }
             }
+
+            // GET /users/by_phone/<phone>
+            (&Get, Some(Route::UserByPhone(phone))) => serialize_future(service.find_by_phone(phone)),
+
             // GET /users/search/email
             (&Get, Some(Route::UsersSearchByEmail)) => {
                 if let Some(email) = parse_query!(req.query().unwrap_or_default(), "email" => String) {
-                    serialize_future(service.fuzzy_search_by_email(email.to_lowercase()))
+                    serialize_future(service.fuzzy_search_by_email(models::normalize_email(&email)))
                 } else {
                     Box::new(future::err(
                         format_err!("Parsing query parameters failed, action: search user by email")
@@ -154,7 +396,7 @@ impl<
 
             // POST /users
             (&Post, Some(Route::Users)) => serialize_future(
-                parse_body::<models::SagaCreateProfile>(req.body())
+                parse_json_body::<models::SagaCreateProfile>(req.body(), max_body_size)
                     .map_err(|e| {
                         e.context("Parsing body failed, target: SagaCreateProfile")
                             .context(Error::Parse)
@@ -175,25 +417,79 @@ impl<
                             })
                             .and_then(move |_| {
                                 let checked_new_ident = models::identity::NewIdentity {
-                                    email: payload.identity.email.to_lowercase(),
+                                    email: models::normalize_email(&payload.identity.email),
                                     password: payload.identity.password,
                                     provider: payload.identity.provider,
                                     saga_id: payload.identity.saga_id,
                                 };
 
                                 let user = payload.user.map(|mut user| {
-                                    user.email = user.email.to_lowercase();
+                                    user.email = models::normalize_email(&user.email);
                                     user
                                 });
 
-                                service.create(checked_new_ident, user)
+                                service.create(checked_new_ident, user, payload.invite_code)
+                            })
+                    }),
+            ),
+
+            // POST /invites
+            (&Post, Some(Route::Invites)) => serialize_future(
+                parse_json_body::<models::NewInvitePayload>(req.body(), max_body_size)
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: NewInvitePayload")
+                            .context(Error::Parse)
+                            .into()
+                    })
+                    .and_then(move |payload| service.create_invite(payload)),
+            ),
+
+            // POST /waitlist
+            (&Post, Some(Route::Waitlist)) => serialize_future(
+                parse_json_body::<models::WaitlistRequest>(req.body(), max_body_size)
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: WaitlistRequest")
+                            .context(Error::Parse)
+                            .into()
+                    })
+                    .and_then(move |waitlist_req| {
+                        waitlist_req
+                            .validate()
+                            .map_err(|e| {
+                                format_err!("Validation failed, target: WaitlistRequest")
+                                    .context(Error::Validate(e))
+                                    .into()
                             })
+                            .into_future()
+                            .and_then(move |_| service.join_waitlist(models::normalize_email(&waitlist_req.email)))
                     }),
             ),
 
+            // PUT /waitlist
+            (&Put, Some(Route::Waitlist)) => serialize_future(
+                parse_json_body::<models::WaitlistConfirm>(req.body(), max_body_size)
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: WaitlistConfirm")
+                            .context(Error::Parse)
+                            .into()
+                    })
+                    .and_then(move |confirm| service.confirm_waitlist(confirm.token)),
+            ),
+
+            // POST /waitlist/invites
+            (&Post, Some(Route::WaitlistInvites)) => serialize_future(
+                parse_json_body::<models::WaitlistInvitesPayload>(req.body(), max_body_size)
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: WaitlistInvitesPayload")
+                            .context(Error::Parse)
+                            .into()
+                    })
+                    .and_then(move |payload| service.invite_waitlist(payload)),
+            ),
+
             // PUT /users/<user_id>
             (&Put, Some(Route::User(user_id))) => serialize_future(
-                parse_body::<models::user::UpdateUser>(req.body())
+                parse_json_body::<models::user::UpdateUser>(req.body(), max_body_size)
                     .map_err(|e| e.context("Parsing body failed, target: UpdateUser").context(Error::Parse).into())
                     .and_then(move |update_user| {
                         update_user
@@ -218,17 +514,120 @@ impl<
             (&Post, Some(Route::UserUnblock(user_id))) => serialize_future(service.set_block_status(user_id, false)),
 
             // DELETE /users/<user_id>
-            (&Delete, Some(Route::User(user_id))) => serialize_future(service.deactivate(user_id)),
+            (&Delete, Some(Route::User(target_user_id))) => {
+                let guard = if user_id == Some(target_user_id) {
+                    self.require_recent_auth(auth_time)
+                } else {
+                    Ok(())
+                };
+
+                serialize_future(guard.into_future().and_then(move |_| service.deactivate(target_user_id)))
+            }
 
             // DELETE /users/:user_id
             (&Delete, Some(Route::UserDelete(user_id))) => serialize_future(service.delete(user_id)),
 
-            // DELETE /user_by_saga_id/<user_id>
+            // DELETE /user_by_saga_id/<saga_id>, deprecated
             (&Delete, Some(Route::UserBySagaId(saga_id))) => serialize_future(service.delete_by_saga_id(saga_id)),
 
+            // DELETE /users/by_saga_id/<saga_id>
+            (&Delete, Some(Route::UsersBySagaId(saga_id))) => serialize_future(service.delete_by_saga_id(saga_id)),
+
+            // POST /users/<user_id>/delete_request
+            (&Post, Some(Route::UserDeletionRequest(target_user_id))) => {
+                let guard = if user_id == Some(target_user_id) {
+                    self.require_recent_auth(auth_time)
+                } else {
+                    Ok(())
+                };
+
+                serialize_future(guard.into_future().and_then(move |_| service.request_deletion(target_user_id)))
+            }
+
+            // GET /users/<user_id>/delete_request
+            (&Get, Some(Route::UserDeletionRequest(target_user_id))) => serialize_future(service.get_deletion_request(target_user_id)),
+
+            // POST /users/<user_id>/delete_request/confirmations
+            (&Post, Some(Route::UserDeletionConfirmations(target_user_id))) => serialize_future(
+                parse_json_body::<models::DeletionConfirmationPayload>(req.body(), max_body_size)
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: DeletionConfirmationPayload")
+                            .context(Error::Parse)
+                            .into()
+                    })
+                    .and_then(move |payload| {
+                        payload
+                            .validate()
+                            .map_err(|e| {
+                                format_err!("Validation failed, target: DeletionConfirmationPayload")
+                                    .context(Error::Validate(e))
+                                    .into()
+                            })
+                            .into_future()
+                            .and_then(move |_| service.confirm_deletion(target_user_id, payload))
+                    }),
+            ),
+
+            // GET /users/<user_id>/trusted_contacts
+            (&Get, Some(Route::TrustedContacts { user_id })) => serialize_future(service.get_trusted_contacts(user_id)),
+
+            // POST /users/<user_id>/trusted_contacts
+            (&Post, Some(Route::TrustedContacts { user_id })) => serialize_future(
+                parse_json_body::<models::TrustedContactPayload>(req.body(), max_body_size)
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: TrustedContactPayload")
+                            .context(Error::Parse)
+                            .into()
+                    })
+                    .and_then(move |payload| {
+                        payload
+                            .validate()
+                            .map_err(|e| {
+                                format_err!("Validation failed, target: TrustedContactPayload")
+                                    .context(Error::Validate(e))
+                                    .into()
+                            })
+                            .into_future()
+                            .and_then(move |_| service.add_trusted_contact(user_id, payload))
+                    }),
+            ),
+
+            // DELETE /users/<user_id>/trusted_contacts/<contact_id>
+            (&Delete, Some(Route::TrustedContact { user_id, contact_id })) => {
+                serialize_future(service.remove_trusted_contact(user_id, contact_id))
+            }
+
+            // POST /users/recovery
+            (&Post, Some(Route::Recovery)) => serialize_future(
+                parse_json_body::<models::RecoveryStart>(req.body(), max_body_size)
+                    .map_err(|e| e.context("Parsing body failed, target: RecoveryStart").context(Error::Parse).into())
+                    .and_then(move |payload| {
+                        payload
+                            .validate()
+                            .map_err(|e| {
+                                format_err!("Validation failed, target: RecoveryStart")
+                                    .context(Error::Validate(e))
+                                    .into()
+                            })
+                            .into_future()
+                            .and_then(move |_| service.start_recovery(payload))
+                    }),
+            ),
+
+            // POST /users/recovery/approve
+            (&Post, Some(Route::RecoveryApprove)) => serialize_future(
+                parse_json_body::<models::RecoveryApprove>(req.body(), max_body_size)
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: RecoveryApprove")
+                            .context(Error::Parse)
+                            .into()
+                    })
+                    .and_then(move |payload| service.approve_recovery(payload.token)),
+            ),
+
             // POST /jwt/email
             (&Post, Some(Route::JWTEmail)) => serialize_future(
-                parse_body::<models::identity::EmailIdentity>(req.body())
+                parse_json_body::<models::identity::EmailIdentity>(req.body(), max_body_size)
                     .map_err(|e| e.context("Parsing body failed, target: EmailIdentity").context(Error::Parse).into())
                     .and_then(move |ident| {
                         ident
@@ -244,8 +643,10 @@ impl<
                             })
                             .and_then(move |_| {
                                 let checked_ident = models::identity::EmailIdentity {
-                                    email: ident.email.to_lowercase(),
+                                    email: models::normalize_email(&ident.email),
                                     password: ident.password,
+                                    client_id: ident.client_id,
+                                    remember_me: ident.remember_me,
                                 };
                                 service.create_token_email(checked_ident, token_expiration)
                             })
@@ -254,7 +655,7 @@ impl<
 
             // POST /jwt/google
             (&Post, Some(Route::JWTGoogle)) => serialize_future(
-                parse_body::<models::jwt::ProviderOauth>(req.body())
+                parse_json_body::<models::jwt::ProviderOauth>(req.body(), max_body_size)
                     .map_err(|e| e.context("Parsing body failed, target: ProviderOauth").context(Error::Parse).into())
                     .inspect(|payload| {
                         debug!("Received request to authenticate with Google token: {:?}", &payload);
@@ -264,7 +665,7 @@ impl<
 
             // POST /jwt/refresh
             (&Post, Some(Route::JWTRefresh)) => serialize_future(
-                parse_body::<models::jwt::JWTPayload>(req.body())
+                parse_json_body::<models::jwt::JWTPayload>(req.body(), max_body_size)
                     .map_err(|e| e.context("Parsing body failed, target: JWTPayload").context(Error::Parse).into())
                     .inspect(|payload| {
                         debug!("Received request to refresh jwt token for: {:?}", &payload);
@@ -274,7 +675,7 @@ impl<
 
             // POST /jwt/revoke
             (&Post, Some(Route::JWTRevoke)) => serialize_future(
-                parse_body::<models::jwt::JWTPayload>(req.body())
+                parse_json_body::<models::jwt::JWTPayload>(req.body(), max_body_size)
                     .map_err(|e| e.context("Parsing body failed, target: JWTPayload").context(Error::Parse).into())
                     .inspect(|payload| {
                         debug!("Received request to revoke all tokens for: {:?}", &payload);
@@ -282,9 +683,154 @@ impl<
                     .and_then(move |oauth| service.revoke_tokens(oauth.user_id, oauth.provider)),
             ),
 
+            // POST /jwt/step_up
+            (&Post, Some(Route::JWTStepUp)) => serialize_future(
+                parse_json_body::<models::jwt::StepUpRequest>(req.body(), max_body_size)
+                    .map_err(|e| e.context("Parsing body failed, target: StepUpRequest").context(Error::Parse).into())
+                    .and_then(move |step_up| service.step_up(step_up)),
+            ),
+
+            // POST /jwt/renew
+            (&Post, Some(Route::JWTRenew)) => serialize_future(
+                parse_json_body::<models::RenewalRequest>(req.body(), max_body_size)
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: RenewalRequest")
+                            .context(Error::Parse)
+                            .into()
+                    })
+                    .and_then(move |renewal| service.renew_token(renewal)),
+            ),
+
+            // POST /jwt/renew/session
+            (&Post, Some(Route::JWTRenewSession)) => serialize_future(
+                parse_json_body::<models::jwt::JWTPayload>(req.body(), max_body_size)
+                    .map_err(|e| e.context("Parsing body failed, target: JWTPayload").context(Error::Parse).into())
+                    .inspect(|payload| {
+                        debug!("Received request to create refresh session for: {:?}", &payload);
+                    })
+                    .and_then(move |payload| service.create_refresh_session(payload)),
+            ),
+
+            // POST /sessions
+            (&Post, Some(Route::Sessions)) => serialize_future(
+                parse_json_body::<models::jwt::JWTPayload>(req.body(), max_body_size)
+                    .map_err(|e| e.context("Parsing body failed, target: JWTPayload").context(Error::Parse).into())
+                    .inspect(|payload| {
+                        debug!("Received request to create session for: {:?}", &payload);
+                    })
+                    .and_then(move |payload| service.create_session(payload)),
+            ),
+
+            // POST /sessions/resolve
+            (&Post, Some(Route::SessionResolve)) => serialize_future(
+                parse_json_body::<models::SessionRequest>(req.body(), max_body_size)
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: SessionRequest")
+                            .context(Error::Parse)
+                            .into()
+                    })
+                    .and_then(move |session| service.resolve_session(session)),
+            ),
+
+            // GET /csrf
+            (&Get, Some(Route::Csrf)) => serialize_future(future::ok::<_, FailureError>(csrf::create_token(
+                &self.static_context.config,
+                &self.static_context.hmac_key,
+            ))),
+
+            // POST /sessions/logout
+            (&Post, Some(Route::SessionLogout)) => serialize_future(
+                parse_json_body::<models::SessionRequest>(req.body(), max_body_size)
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: SessionRequest")
+                            .context(Error::Parse)
+                            .into()
+                    })
+                    .and_then(move |session| service.delete_session(session)),
+            ),
+
+            // POST /oauth/token
+            (&Post, Some(Route::OAuthToken)) => serialize_future(
+                parse_form_body::<models::OAuthTokenRequest>(req.body())
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: OAuthTokenRequest")
+                            .context(Error::Parse)
+                            .into()
+                    })
+                    .and_then(move |token_request| service.oauth_token(token_request)),
+            ),
+
+            // POST /oauth/device/code
+            (&Post, Some(Route::OAuthDeviceCode)) => serialize_future(
+                parse_form_body::<models::DeviceAuthorizationRequest>(req.body())
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: DeviceAuthorizationRequest")
+                            .context(Error::Parse)
+                            .into()
+                    })
+                    .and_then(move |request| service.device_authorization(request)),
+            ),
+
+            // POST /oauth/introspect
+            (&Post, Some(Route::OAuthIntrospect)) => serialize_future(
+                parse_form_body::<models::IntrospectionRequest>(req.body())
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: IntrospectionRequest")
+                            .context(Error::Parse)
+                            .into()
+                    })
+                    .and_then(move |request| service.introspect_access_token(request)),
+            ),
+
+            // GET /device
+            (&Get, Some(Route::DeviceApprove)) => {
+                if let Some(user_code) = parse_query!(req.query().unwrap_or_default(), "user_code" => String) {
+                    serialize_future(service.device_consent(user_code))
+                } else {
+                    Box::new(future::err(
+                        format_err!("Parsing query parameters failed, action: device consent")
+                            .context(Error::Parse)
+                            .into(),
+                    ))
+                }
+            }
+
+            // POST /device
+            (&Post, Some(Route::DeviceApprove)) => serialize_future(
+                parse_json_body::<models::DeviceApproval>(req.body(), max_body_size)
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: DeviceApproval")
+                            .context(Error::Parse)
+                            .into()
+                    })
+                    .and_then(move |approval| service.approve_device(approval)),
+            ),
+
+            // POST /signed_actions
+            (&Post, Some(Route::SignedActions)) => serialize_future(
+                parse_json_body::<models::NewSignedAction>(req.body(), max_body_size)
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: NewSignedAction")
+                            .context(Error::Parse)
+                            .into()
+                    })
+                    .and_then(move |payload| service.create_signed_action(payload)),
+            ),
+
+            // PUT /signed_actions
+            (&Put, Some(Route::SignedActions)) => serialize_future(
+                parse_json_body::<models::SignedActionApply>(req.body(), max_body_size)
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: SignedActionApply")
+                            .context(Error::Parse)
+                            .into()
+                    })
+                    .and_then(move |payload| service.apply_signed_action(payload.token)),
+            ),
+
             // POST /jwt/facebook
             (&Post, Some(Route::JWTFacebook)) => serialize_future(
-                parse_body::<models::jwt::ProviderOauth>(req.body())
+                parse_json_body::<models::jwt::ProviderOauth>(req.body(), max_body_size)
                     .map_err(|e| e.context("Parsing body failed, target: ProviderOauth").context(Error::Parse).into())
                     .inspect(|payload| {
                         debug!("Received request to authenticate with Facebook token: {:?}", &payload);
@@ -293,14 +839,148 @@ impl<
             ),
 
             (Get, Some(Route::RolesByUserId { user_id })) => serialize_future({ service.get_roles(user_id) }),
-            (Post, Some(Route::Roles)) => {
-                serialize_future({ parse_body::<models::NewUserRole>(req.body()).and_then(move |data| service.create_user_role(data)) })
-            }
-            (Delete, Some(Route::Roles)) => {
-                serialize_future({ parse_body::<models::RemoveUserRole>(req.body()).and_then(move |data| service.delete_user_role(data)) })
-            }
+            (Post, Some(Route::Roles)) => serialize_future({
+                parse_json_body::<models::NewUserRole>(req.body(), max_body_size).and_then(move |data| service.create_user_role(data))
+            }),
+            (Delete, Some(Route::Roles)) => serialize_future({
+                parse_json_body::<models::RemoveUserRole>(req.body(), max_body_size).and_then(move |data| service.delete_user_role(data))
+            }),
             (Delete, Some(Route::RolesByUserId { user_id })) => serialize_future({ service.delete_user_role_by_user_id(user_id) }),
             (Delete, Some(Route::RoleById { id })) => serialize_future({ service.delete_user_role_by_id(id) }),
+            (Get, Some(Route::RoleRequests)) => serialize_future({ service.get_role_requests() }),
+            (Post, Some(Route::RoleRequestApprove { id })) => serialize_future({ service.approve_role_request(id) }),
+
+            // GET /role_access_policies
+            (Get, Some(Route::RoleAccessPolicies)) => serialize_future({ service.get_role_access_policies() }),
+
+            // PUT /role_access_policies
+            (Put, Some(Route::RoleAccessPolicies)) => serialize_future(
+                parse_json_body::<models::NewRoleAccessPolicy>(req.body(), max_body_size)
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: NewRoleAccessPolicy")
+                            .context(Error::Parse)
+                            .into()
+                    })
+                    .and_then(move |payload| {
+                        payload
+                            .validate()
+                            .map_err(|e| {
+                                format_err!("Validation failed, target: NewRoleAccessPolicy")
+                                    .context(Error::Validate(e))
+                                    .into()
+                            })
+                            .into_future()
+                            .and_then(move |_| service.upsert_role_access_policy(payload))
+                    }),
+            ),
+
+            // DELETE /role_access_policies/<role>
+            (Delete, Some(Route::RoleAccessPolicy { role })) => serialize_future({ service.delete_role_access_policy(role) }),
+
+            // GET /users/<user_id>/tags
+            (Get, Some(Route::UserTags { user_id })) => serialize_future({ service.get_user_tags(user_id) }),
+
+            // POST /users/<user_id>/tags
+            (Post, Some(Route::UserTags { user_id })) => serialize_future(
+                parse_json_body::<models::NewUserTag>(req.body(), max_body_size)
+                    .map_err(|e| e.context("Parsing body failed, target: NewUserTag").context(Error::Parse).into())
+                    .and_then(move |payload| {
+                        payload
+                            .validate()
+                            .map_err(|e| {
+                                format_err!("Validation failed, target: NewUserTag")
+                                    .context(Error::Validate(e))
+                                    .into()
+                            })
+                            .into_future()
+                            .and_then(move |_| service.add_user_tag(user_id, payload))
+                    }),
+            ),
+
+            // DELETE /users/<user_id>/tags/<tag>
+            (Delete, Some(Route::UserTag { user_id, tag })) => serialize_future({ service.remove_user_tag(user_id, tag) }),
+
+            // GET /users/<user_id>/identities
+            (Get, Some(Route::UserIdentities { user_id })) => serialize_future({ service.get_identities(user_id) }),
+
+            // DELETE /users/<user_id>/identities/<provider>
+            (Delete, Some(Route::UserIdentity { user_id, provider })) => serialize_future({ service.unlink_provider(user_id, provider) }),
+
+            // POST /users/<user_id>/identities/temporary_password
+            (Post, Some(Route::UserTemporaryPassword { user_id })) => serialize_future({ service.set_temporary_password(user_id) }),
+
+            // POST /users/segments/export
+            (Post, Some(Route::SegmentExports)) => serialize_future(
+                parse_json_body::<models::UsersSearchTerms>(req.body(), max_body_size)
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: UsersSearchTerms")
+                            .context(Error::Parse)
+                            .into()
+                    })
+                    .and_then(move |payload| service.export_segment(payload)),
+            ),
+
+            // GET /jobs/<id>
+            (Get, Some(Route::Job { id })) => serialize_future({ service.get_job(id) }),
+
+            // GET /users/segments/export/<id>/csv, CSV is returned as is, see `content_type::content_type_of`
+            (Get, Some(Route::SegmentExportCsv { id })) => Box::new(service.get_segment_export_csv(id)),
+
+            // POST /users/import
+            (Post, Some(Route::UsersImport)) => serialize_future(
+                parse_json_body::<models::ImportedUser>(req.body(), max_body_size)
+                    .map_err(|e| e.context("Parsing body failed, target: ImportedUser").context(Error::Parse).into())
+                    .and_then(move |payload| {
+                        payload
+                            .validate()
+                            .map_err(|e| {
+                                format_err!("Validation failed, target: ImportedUser")
+                                    .context(Error::Validate(e))
+                                    .into()
+                            })
+                            .into_future()
+                            .and_then(move |_| service.import_user(payload))
+                    }),
+            ),
+
+            // POST /auth_archive/export
+            (Post, Some(Route::AuthArchiveExport)) => serialize_future(service.export_auth_data()),
+
+            // POST /auth_archive/import
+            (Post, Some(Route::AuthArchiveImport)) => serialize_future(
+                parse_json_body::<models::AuthArchive>(req.body(), max_body_size)
+                    .map_err(|e| e.context("Parsing body failed, target: AuthArchive").context(Error::Parse).into())
+                    .and_then(move |payload| service.import_auth_data(payload)),
+            ),
+
+            // POST /break_glass
+            (Post, Some(Route::BreakGlass)) => serialize_future(
+                parse_json_body::<models::BreakGlassActivation>(req.body(), max_body_size)
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: BreakGlassActivation")
+                            .context(Error::Parse)
+                            .into()
+                    })
+                    .and_then(move |payload| service.activate_break_glass(payload)),
+            ),
+
+            // GET /legacy_sync/report
+            (Get, Some(Route::LegacySyncReport)) => serialize_future(service.get_legacy_sync_report()),
+
+            // GET /crm_reconciliation/report
+            (Get, Some(Route::CrmDriftReport)) => serialize_future(service.get_crm_drift_report()),
+
+            // GET /ready
+            (&Get, Some(Route::Readiness)) => match self.static_context.readiness.not_ready_reason() {
+                None => serialize_future(future::ok::<_, FailureError>("Ok")),
+                Some(reason) => Box::new(future::err(Error::NotReady.context(reason).into())),
+            },
+
+            // GET /ready/dependencies
+            (&Get, Some(Route::DependencyHealth)) => serialize_future(service.get_dependency_health()),
+
+            // GET /countries
+            (&Get, Some(Route::Countries)) => serialize_future(service.get_countries()),
 
             // GET /users/count
             (&Get, Some(Route::UserCount)) => {
@@ -312,9 +992,67 @@ impl<
                 serialize_future({ service.count(only_active_users.unwrap_or(false)) })
             }
 
+            // GET /users/profile_completion/stats
+            (&Get, Some(Route::ProfileCompletionStats)) => {
+                if let (Some(offset), Some(count)) = parse_query!(req.query().unwrap_or_default(), "offset" => UserId, "count" => i64) {
+                    serialize_future(service.profile_completion_stats(offset, count))
+                } else {
+                    Box::new(future::err(
+                        format_err!("Parsing query parameters failed, action: get profile completion stats")
+                            .context(Error::Parse)
+                            .into(),
+                    ))
+                }
+            }
+
+            // GET /stats/logins
+            (&Get, Some(Route::LoginStats)) => {
+                let query = req.query().unwrap_or_default();
+                let granularity = parse_query!(query, "granularity" => String)
+                    .map(|granularity| granularity.parse::<models::StatsGranularity>())
+                    .unwrap_or_else(|| Ok(Default::default()));
+                match (parse_query!(query, "from" => NaiveDate, "to" => NaiveDate), granularity) {
+                    ((Some(from), Some(to)), Ok(granularity)) => serialize_future(service.get_login_stats(from, to, granularity)),
+                    _ => Box::new(future::err(
+                        format_err!("Parsing query parameters failed, action: get login stats")
+                            .context(Error::Parse)
+                            .into(),
+                    )),
+                }
+            }
+
+            // GET /stats/funnel
+            (&Get, Some(Route::FunnelStats)) => {
+                if let (Some(from), Some(to)) = parse_query!(req.query().unwrap_or_default(), "from" => NaiveDate, "to" => NaiveDate) {
+                    serialize_future(service.get_funnel_stats(from, to))
+                } else {
+                    Box::new(future::err(
+                        format_err!("Parsing query parameters failed, action: get funnel stats")
+                            .context(Error::Parse)
+                            .into(),
+                    ))
+                }
+            }
+
+            // GET /stats/public
+            (&Get, Some(Route::PublicStats)) => {
+                if let (Some(from), Some(to)) = parse_query!(req.query().unwrap_or_default(), "from" => NaiveDate, "to" => NaiveDate) {
+                    serialize_future(service.get_public_stats(from, to))
+                } else {
+                    Box::new(future::err(
+                        format_err!("Parsing query parameters failed, action: get public stats")
+                            .context(Error::Parse)
+                            .into(),
+                    ))
+                }
+            }
+
+            // GET /events/schemas
+            (&Get, Some(Route::EventSchemas)) => serialize_future(service.get_event_schemas()),
+
             // POST /users/password_change
             (&Post, Some(Route::PasswordChange)) => serialize_future(
-                parse_body::<models::ChangeIdentityPassword>(req.body())
+                parse_json_body::<models::ChangeIdentityPassword>(req.body(), max_body_size)
                     .map_err(|e| {
                         e.context("Parsing body failed, target: ChangeIdentityPassword")
                             .context(Error::Parse)
@@ -340,7 +1078,7 @@ impl<
 
             // Post /users/password_reset_token
             (&Post, Some(Route::UserPasswordResetToken)) => serialize_future(
-                parse_body::<models::ResetRequest>(req.body())
+                parse_json_body::<models::ResetRequest>(req.body(), max_body_size)
                     .map_err(|e| e.context("Parsing body failed, target: ResetRequest").context(Error::Parse).into())
                     .and_then(move |reset_req| {
                         reset_req
@@ -351,13 +1089,13 @@ impl<
                                     .into()
                             })
                             .into_future()
-                            .and_then(move |_| service.get_password_reset_token(reset_req.email.to_lowercase(), reset_req.uuid))
+                            .and_then(move |_| service.get_password_reset_token(models::normalize_email(&reset_req.email), reset_req.uuid))
                     }),
             ),
 
             // PUT /users/password_reset_token
             (&Put, Some(Route::UserPasswordResetToken)) => serialize_future(
-                parse_body::<models::ResetApply>(req.body())
+                parse_json_body::<models::ResetApply>(req.body(), max_body_size)
                     .map_err(|e| {
                         e.context("Parsing body failed, target: ResetApply failed!")
                             .context(Error::Parse)
@@ -372,10 +1110,64 @@ impl<
                                     .into()
                             })
                             .into_future()
-                            .and_then(move |_| service.password_reset_apply(reset_apply.token, reset_apply.password))
+                            .and_then(move |_| {
+                                service.password_reset_apply(reset_apply.token, reset_apply.password, reset_apply.security_answers)
+                            })
                     }),
             ),
 
+            // POST /users/password_reset_token/security_questions
+            (&Post, Some(Route::ResetSecurityQuestions)) => serialize_future(
+                parse_json_body::<models::ResetSecurityQuestionsRequest>(req.body(), max_body_size)
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: ResetSecurityQuestionsRequest")
+                            .context(Error::Parse)
+                            .into()
+                    })
+                    .and_then(move |payload| service.get_reset_security_questions(payload.token, locale)),
+            ),
+
+            // GET /security_questions
+            (&Get, Some(Route::SecurityQuestions)) => serialize_future(service.get_security_questions(locale)),
+
+            // POST /password/strength
+            (&Post, Some(Route::PasswordStrength)) => serialize_future(
+                parse_json_body::<models::PasswordStrengthRequest>(req.body(), max_body_size)
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: PasswordStrengthRequest")
+                            .context(Error::Parse)
+                            .into()
+                    })
+                    .and_then(move |payload| service.check_password_strength(payload, locale)),
+            ),
+
+            // GET /users/<user_id>/security_answers
+            (&Get, Some(Route::UserSecurityAnswers { user_id: target_user_id })) => {
+                serialize_future(service.get_security_answers(target_user_id))
+            }
+
+            // PUT /users/<user_id>/security_answers
+            (&Put, Some(Route::UserSecurityAnswers { user_id: target_user_id })) => {
+                let guard = if user_id == Some(target_user_id) {
+                    self.require_recent_auth(auth_time)
+                } else {
+                    Ok(())
+                };
+
+                serialize_future(
+                    guard
+                        .into_future()
+                        .and_then(move |_| {
+                            parse_json_body::<models::SecurityAnswers>(req.body(), max_body_size).map_err(|e| {
+                                e.context("Parsing body failed, target: SecurityAnswers")
+                                    .context(Error::Parse)
+                                    .into()
+                            })
+                        })
+                        .and_then(move |payload| service.set_security_answers(target_user_id, payload)),
+                )
+            }
+
             // POST /users/<user_id>/email_verify_token
             (&Get, Some(Route::GetUserEmalVerifyToken { user_id })) => {
                 serialize_future(service.get_existing_reset_token(user_id, TokenType::EmailVerify))
@@ -383,7 +1175,7 @@ impl<
 
             // Post /users/email_verify_token
             (&Post, Some(Route::UserEmailVerifyToken)) => serialize_future(
-                parse_body::<models::VerifyRequest>(req.body())
+                parse_json_body::<models::VerifyRequest>(req.body(), max_body_size)
                     .map_err(|e| e.context("Parsing body failed, target: VerifyRequest").context(Error::Parse).into())
                     .and_then(move |reset_req| {
                         reset_req
@@ -394,7 +1186,7 @@ impl<
                                     .into()
                             })
                             .into_future()
-                            .and_then(move |_| service.get_email_verification_token(reset_req.email.to_lowercase()))
+                            .and_then(move |_| service.get_email_verification_token(models::normalize_email(&reset_req.email)))
                     }),
             ),
 
@@ -422,7 +1214,7 @@ impl<
                 let count = count_opt.unwrap_or(0);
 
                 serialize_future(
-                    parse_body::<models::UsersSearchTerms>(req.body())
+                    parse_json_body::<models::UsersSearchTerms>(req.body(), max_body_size)
                         .map_err(|e| {
                             e.context("Parsing body failed, target: UsersSearchTerms")
                                 .context(Error::Parse)
@@ -439,7 +1231,8 @@ impl<
                     .into(),
             )),
         }
-        .map_err(|err| {
+        .map_err(move |err| {
+            let err = i18n::localize_error(err, locale);
             let wrapper = ErrorMessageWrapper::<Error>::from(&err);
             if wrapper.inner.code == 500 {
                 log_and_capture_error(&err);
@@ -451,6 +1244,44 @@ impl<
     }
 }
 
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > Controller for ControllerImpl<T, M, F>
+{
+    /// Handle a request and get future response, the request is written to the access log
+    fn call(&self, req: Request) -> ControllerFuture {
+        let started_at = Instant::now();
+        let access_log_conf = self.static_context.config.access_log.clone();
+        let route = self.static_context.route_parser.test(req.path());
+        let mut record = AccessRecord {
+            method: req.method().to_string(),
+            route: route.as_ref().map(Route::template).unwrap_or("unknown"),
+            status: 200,
+            latency_ms: 0,
+            user_id: get_user_id(&req),
+            request_id: request_util::get_correlation_token(&req),
+        };
+        let break_glass_uri = match self.static_context.config.break_glass {
+            Some(ref break_glass) if record.user_id == Some(break_glass.user_id) => Some(req.uri().to_string()),
+            _ => None,
+        };
+
+        Box::new(self.handle(req, route).then(move |res| {
+            if let Err(ref err) = res {
+                record.status = ErrorMessageWrapper::<Error>::from(err).inner.code;
+            }
+            record.latency_ms = duration_ms(started_at.elapsed());
+            log_access(&access_log_conf, &record);
+            if let Some(ref uri) = break_glass_uri {
+                log_break_glass_access(&record, uri);
+            }
+            res
+        }))
+    }
+}
+
 fn get_user_id(req: &Request) -> Option<UserId> {
     req.headers()
         .get::<Authorization<String>>()
@@ -458,3 +1289,302 @@ fn get_user_id(req: &Request) -> Option<UserId> {
         .and_then(|id| i32::from_str(&id).ok())
         .map(UserId)
 }
+
+fn get_auth_time(req: &Request) -> Option<i64> {
+    req.headers().get::<AuthTime>().map(|auth_time| auth_time.0)
+}
+
+/// Scopes of the token of a third-party client, a remember-me, a step-up or a password change token, `None` for other tokens
+fn get_token_scopes(req: &Request) -> Result<Option<Vec<OAuthScope>>, FailureError> {
+    match req.headers().get::<TokenScope>() {
+        Some(token_scope) => models::parse_scope(&token_scope.0).map(Some).map_err(|_| {
+            format_err!("Unknown token scope {}", token_scope.0)
+                .context(Error::Forbidden)
+                .into()
+        }),
+        None => Ok(None),
+    }
+}
+
+/// Tokens of third-party clients can access only routes allowed by scopes the user consented to,
+/// remember-me tokens only routes allowed by `REMEMBER_ME_SCOPES`, tokens of suspicious logins only
+/// the step-up, tokens of users who must change the password only the password change. Personal access tokens access routes by method, `read` for `GET`
+/// and `write` for the others, except for managing access tokens themselves
+fn require_scope(method: &Method, route: &Option<Route>, token_scopes: Option<&Vec<OAuthScope>>) -> Result<(), FailureError> {
+    let token_scopes = match token_scopes {
+        Some(token_scopes) => token_scopes,
+        None => return Ok(()),
+    };
+    let required_scope = match (method, route) {
+        (&Get, &Some(Route::Current)) => Some(OAuthScope::ProfileRead),
+        (&Post, &Some(Route::PasswordChange)) => Some(OAuthScope::PasswordChange),
+        (&Post, &Some(Route::JWTStepUp)) => Some(OAuthScope::StepUp),
+        _ => None,
+    };
+    let access_token_scope = match (method, route) {
+        (_, &Some(Route::AccessTokens)) | (_, &Some(Route::AccessToken { .. })) => None,
+        (&Get, _) => Some(OAuthScope::Read),
+        _ => Some(OAuthScope::Write),
+    };
+
+    match (required_scope, access_token_scope) {
+        (Some(scope), _) if token_scopes.contains(&scope) => Ok(()),
+        (_, Some(scope)) if token_scopes.contains(&scope) => Ok(()),
+        _ => Err(format_err!("Token scopes {:?} do not allow {} {:?}", token_scopes, method, route)
+            .context(Error::Forbidden)
+            .into()),
+    }
+}
+
+/// Hides email of the profile from third-party clients without `email:read` scope,
+/// personal access tokens with `read` scope see it as the user does
+fn scoped_profile(
+    user: Option<models::CurrentUser>,
+    token_scopes: Option<&Vec<OAuthScope>>,
+) -> Result<Option<serde_json::Value>, FailureError> {
+    let mut profile = match user {
+        Some(user) => serde_json::to_value(user)?,
+        None => return Ok(None),
+    };
+    let hides_email = token_scopes
+        .map(|scopes| !scopes.contains(&OAuthScope::EmailRead) && !scopes.contains(&OAuthScope::Read))
+        .unwrap_or(false);
+    if hides_email {
+        if let Some(profile) = profile.as_object_mut() {
+            profile.remove("email");
+        }
+    }
+    Ok(Some(profile))
+}
+
+fn get_cookie(req: &Request, name: &str) -> Option<String> {
+    req.headers()
+        .get::<Cookie>()
+        .and_then(|cookie| cookie.get(name))
+        .map(str::to_string)
+}
+
+fn get_locale(req: &Request) -> Locale {
+    req.headers()
+        .get::<AcceptLanguage>()
+        .map(Locale::from_accept_language)
+        .unwrap_or_default()
+}
+
+fn get_client_ip(req: &Request, trusted_proxy_hops: usize) -> Option<IpAddr> {
+    let forwarded_for = req
+        .headers()
+        .get::<XForwardedFor>()
+        .map(|forwarded_for| forwarded_for.0.clone())
+        .unwrap_or_default();
+    client_ip(&forwarded_for, req.remote_addr().map(|addr| addr.ip()), trusted_proxy_hops)
+}
+
+/// Address the outermost trusted proxy received the request from. Each proxy appends the address
+/// of its peer to `X-Forwarded-For`, so only the last `trusted_proxy_hops` entries are not forged.
+fn client_ip(forwarded_for: &[IpAddr], peer: Option<IpAddr>, trusted_proxy_hops: usize) -> Option<IpAddr> {
+    if trusted_proxy_hops == 0 {
+        return peer;
+    }
+    let hops = forwarded_for.len().min(trusted_proxy_hops);
+    if hops == 0 {
+        return peer;
+    }
+    forwarded_for.get(forwarded_for.len() - hops).cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+    use std::sync::Arc;
+
+    use hyper::header::{Authorization, Cookie};
+    use hyper::{Method, Request};
+    use serde_json;
+    use tokio_core::reactor::Core;
+
+    use stq_http::controller::Controller;
+
+    use super::csrf;
+    use super::headers::{TokenScope, XCsrfToken};
+    use super::routes::Route;
+    use super::{client_ip, require_scope, ControllerImpl};
+    use models::{OAuthScope, UserSearchResults};
+    use repos::repo_factory::tests::*;
+
+    fn logout_request(csrf_cookie: Option<&str>, csrf_header: Option<&str>) -> Request {
+        let mut req = Request::new(Method::Post, "/sessions/logout".parse().unwrap());
+        let mut cookie = Cookie::new();
+        cookie.append("session_id", MOCK_SESSION_ID);
+        if let Some(csrf_cookie) = csrf_cookie {
+            cookie.append("csrf_token", csrf_cookie.to_string());
+        }
+        req.headers_mut().set(cookie);
+        if let Some(csrf_header) = csrf_header {
+            req.headers_mut().set(XCsrfToken(csrf_header.to_string()));
+        }
+        req.set_body(format!(r#"{{"session_id": "{}"}}"#, MOCK_SESSION_ID));
+        req
+    }
+
+    fn search_request(query: &str, body: &str) -> Request {
+        let uri = format!("/users/search?{}", query).parse().unwrap();
+        let mut req = Request::new(Method::Post, uri);
+        req.headers_mut().set(Authorization("1".to_string()));
+        req.set_body(body.to_string());
+        req
+    }
+
+    #[test]
+    fn test_search_with_hostile_terms() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let controller = ControllerImpl::new(create_service(None, handle).static_context);
+        let body = r#"{
+            "email": "' OR '1'='1",
+            "phone": "1; DROP TABLE users; --",
+            "first_name": "%",
+            "last_name": "_\\",
+            "is_blocked": null,
+            "data_region": "global' --"
+        }"#;
+
+        let response = core.run(controller.call(search_request("count=5", body))).unwrap();
+        let results = serde_json::from_str::<UserSearchResults>(&response).unwrap();
+        assert_eq!(results.users.len(), 5);
+    }
+
+    #[test]
+    fn test_search_with_hostile_query() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let controller = ControllerImpl::new(create_service(None, handle).static_context);
+
+        // parameters which are not numbers are ignored
+        let response = core
+            .run(controller.call(search_request("count=5&skip=0%20OR%201%3D1&offset=1;DROP%20TABLE%20users", "{}")))
+            .unwrap();
+        let results = serde_json::from_str::<UserSearchResults>(&response).unwrap();
+        assert_eq!(results.users.len(), 5);
+
+        let response = core.run(controller.call(search_request("count=5", r#"{"is_blocked": "false; --"}"#)));
+        assert_eq!(response.is_err(), true);
+    }
+
+    #[test]
+    fn test_scoped_token_hides_email() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let controller = ControllerImpl::new(create_service(None, handle).static_context);
+        let mut req = Request::new(Method::Get, "/users/current".parse().unwrap());
+        req.headers_mut().set(Authorization("1".to_string()));
+        req.headers_mut().set(TokenScope("profile:read".to_string()));
+
+        let response = core.run(controller.call(req)).unwrap();
+        let profile = serde_json::from_str::<serde_json::Value>(&response).unwrap();
+        assert_eq!(profile["id"], 1);
+        assert_eq!(profile.get("email"), None);
+    }
+
+    #[test]
+    fn test_scoped_token_forbidden_route() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let controller = ControllerImpl::new(create_service(None, handle).static_context);
+        let mut req = search_request("count=5", "{}");
+        req.headers_mut().set(TokenScope("profile:read email:read".to_string()));
+
+        let response = core.run(controller.call(req));
+        assert_eq!(response.is_err(), true);
+    }
+
+    #[test]
+    fn test_access_token_scopes() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let controller = ControllerImpl::new(create_service(None, handle).static_context);
+        let mut req = Request::new(Method::Get, "/users/current".parse().unwrap());
+        req.headers_mut().set(Authorization("1".to_string()));
+        req.headers_mut().set(TokenScope("read".to_string()));
+        let response = core.run(controller.call(req));
+        assert_eq!(response.is_ok(), true);
+
+        let mut req = search_request("count=5", "{}");
+        req.headers_mut().set(TokenScope("read".to_string()));
+        let response = core.run(controller.call(req));
+        assert_eq!(response.is_err(), true);
+
+        let mut req = Request::new(Method::Get, "/users/current/tokens".parse().unwrap());
+        req.headers_mut().set(Authorization("1".to_string()));
+        req.headers_mut().set(TokenScope("read write".to_string()));
+        let response = core.run(controller.call(req));
+        assert_eq!(response.is_err(), true);
+    }
+
+    #[test]
+    fn test_password_change_token_forbidden_route() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let controller = ControllerImpl::new(create_service(None, handle).static_context);
+        let mut req = Request::new(Method::Get, "/users/current".parse().unwrap());
+        req.headers_mut().set(Authorization("1".to_string()));
+        req.headers_mut().set(TokenScope("password:change".to_string()));
+
+        let response = core.run(controller.call(req));
+        assert_eq!(response.is_err(), true);
+    }
+
+    #[test]
+    fn test_step_up_token_forbidden_route() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let controller = ControllerImpl::new(create_service(None, handle).static_context);
+        let mut req = Request::new(Method::Post, "/users/current/devices/trust".parse().unwrap());
+        req.headers_mut().set(Authorization("1".to_string()));
+        req.headers_mut().set(TokenScope("session:step_up".to_string()));
+
+        let response = core.run(controller.call(req));
+        assert_eq!(response.is_err(), true);
+        assert_eq!(
+            require_scope(&Method::Post, &Some(Route::JWTStepUp), Some(&vec![OAuthScope::StepUp])).is_ok(),
+            true
+        );
+    }
+
+    #[test]
+    fn test_session_cookie_with_csrf_token() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let static_context = create_service(None, handle).static_context;
+        let token = csrf::create_token(&static_context.config, &static_context.hmac_key);
+        let controller = ControllerImpl::new(static_context);
+
+        let response = core.run(controller.call(logout_request(Some(&token.csrf_token), Some(&token.csrf_token))));
+        assert_eq!(response.is_ok(), true);
+    }
+
+    #[test]
+    fn test_session_cookie_without_csrf_token() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let static_context = create_service(None, handle).static_context;
+        let token = csrf::create_token(&static_context.config, &static_context.hmac_key);
+        let other_token = csrf::create_token(&static_context.config, &static_context.hmac_key);
+        let controller = ControllerImpl::new(static_context);
+
+        let response = core.run(controller.call(logout_request(None, None)));
+        assert_eq!(response.is_err(), true);
+        let response = core.run(controller.call(logout_request(Some(&token.csrf_token), Some(&other_token.csrf_token))));
+        assert_eq!(response.is_err(), true);
+    }
+
+    #[test]
+    fn test_client_ip_of_trusted_hop() {
+        let spoofed: IpAddr = "1.1.1.1".parse().unwrap();
+        let client: IpAddr = "2.2.2.2".parse().unwrap();
+        let gateway: IpAddr = "10.0.0.2".parse().unwrap();
+        assert_eq!(client_ip(&[spoofed, client], Some(gateway), 1), Some(client));
+        assert_eq!(client_ip(&[spoofed, client], Some(gateway), 0), Some(gateway));
+        assert_eq!(client_ip(&[], Some(gateway), 1), Some(gateway));
+    }
+}
@@ -0,0 +1,66 @@
+//! Read-only mode of a warm standby, set by `read_only` config or `STQ_USERS_READ_ONLY=true`.
+//! The standby in another region serves traffic from a database replica during failover of the
+//! primary database: reads and token validation go on, mutating requests are rejected with 503.
+//! Background jobs writing to the database are not started.
+
+use failure::Error as FailureError;
+use failure::Fail;
+use hyper::{Get, Method};
+
+use super::routes::Route;
+use config::Config;
+use errors::Error;
+
+/// Whether the request is served in read-only mode. Requests of safe methods are, along with
+/// unsafe ones not changing anything: searches, password checks and token validation.
+pub fn is_allowed(method: &Method, route: &Option<Route>) -> bool {
+    match (method, route) {
+        (&Get, _) | (&Method::Head, _) | (&Method::Options, _) => true,
+        (_, &Some(Route::UsersSearch))
+        | (_, &Some(Route::PasswordStrength))
+        | (_, &Some(Route::JWTRefresh))
+        | (_, &Some(Route::SessionResolve))
+        | (_, &Some(Route::OAuthIntrospect)) => true,
+        _ => false,
+    }
+}
+
+/// Rejects mutating requests in read-only mode
+pub fn verify(method: &Method, route: &Option<Route>, config: &Config) -> Result<(), FailureError> {
+    if !config.read_only || is_allowed(method, route) {
+        return Ok(());
+    }
+    Err(format_err!("{} {:?} is not available in read-only mode", method, route)
+        .context(Error::ReadOnly)
+        .into())
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Delete, Post, Put};
+
+    use stq_types::UserId;
+
+    use super::*;
+
+    #[test]
+    fn test_read_only_allows_reads_and_token_validation() {
+        assert_eq!(is_allowed(&Get, &Some(Route::User(UserId(1)))), true);
+        assert_eq!(is_allowed(&Post, &Some(Route::UsersSearch)), true);
+        assert_eq!(is_allowed(&Post, &Some(Route::OAuthIntrospect)), true);
+        assert_eq!(is_allowed(&Post, &Some(Route::SessionResolve)), true);
+        assert_eq!(is_allowed(&Post, &Some(Route::JWTEmail)), false);
+        assert_eq!(is_allowed(&Put, &Some(Route::User(UserId(1)))), false);
+        assert_eq!(is_allowed(&Delete, &Some(Route::UserDelete(UserId(1)))), false);
+        assert_eq!(is_allowed(&Post, &None), false);
+    }
+
+    #[test]
+    fn test_verify_in_read_only_mode() {
+        let mut config = Config::new().unwrap();
+        assert_eq!(verify(&Post, &Some(Route::JWTEmail), &config).is_ok(), true);
+        config.read_only = true;
+        assert_eq!(verify(&Post, &Some(Route::JWTEmail), &config).is_err(), true);
+        assert_eq!(verify(&Get, &Some(Route::Current), &config).is_ok(), true);
+    }
+}
@@ -1,51 +1,362 @@
+use serde_json;
+
 use stq_router::RouteParser;
-use stq_types::{RoleId, UserId};
+use stq_static_resources::Provider;
+use stq_types::{RoleId, UserId, UsersRole};
+use uuid::Uuid;
+
+use services::work_pool::WorkPriority;
 
 /// List of all routes with params for the app
 #[derive(Clone, Debug, PartialEq)]
 pub enum Route {
     Healthcheck,
+    Readiness,
+    DependencyHealth,
+    Countries,
     Users,
+    Invites,
+    Waitlist,
+    WaitlistInvites,
     User(UserId),
     UserDelete(UserId),
+    UserDeletionRequest(UserId),
+    UserDeletionConfirmations(UserId),
+    TrustedContacts { user_id: UserId },
+    TrustedContact { user_id: UserId, contact_id: i32 },
+    Recovery,
+    RecoveryApprove,
     UserBlock(UserId),
     UserUnblock(UserId),
     UserBySagaId(String),
+    UsersBySagaId(String),
     UserCount,
+    ProfileCompletionStats,
+    LoginStats,
+    FunnelStats,
+    PublicStats,
+    EventSchemas,
     UsersSearch,
     UsersSearchByEmail,
     UserByEmail,
+    UserByPhone(String),
     Current,
+    CurrentProfilePrompts,
+    DismissProfilePrompt,
+    ChildAccounts,
+    ChildAccount { child_id: UserId },
+    ChildAccountPassword { child_id: UserId },
+    AccessTokens,
+    AccessToken { id: Uuid },
+    ConnectedApps,
+    ConnectedApp { client_id: String },
+    ClientBranding { client_id: String },
+    Devices,
+    Device { id: Uuid },
+    DeviceTrust,
     JWTEmail,
     JWTGoogle,
     JWTFacebook,
     JWTRefresh,
     JWTRevoke,
+    JWTStepUp,
+    JWTRenew,
+    JWTRenewSession,
+    Sessions,
+    SessionResolve,
+    SessionLogout,
+    Csrf,
+    OAuthToken,
+    OAuthDeviceCode,
+    OAuthIntrospect,
+    DeviceApprove,
+    SignedActions,
     Roles,
     RoleById { id: RoleId },
     RolesByUserId { user_id: UserId },
+    RoleRequests,
+    RoleRequestApprove { id: Uuid },
+    RoleAccessPolicies,
+    RoleAccessPolicy { role: UsersRole },
+    UserTags { user_id: UserId },
+    UserTag { user_id: UserId, tag: String },
+    UserIdentities { user_id: UserId },
+    UserIdentity { user_id: UserId, provider: Provider },
+    UserTemporaryPassword { user_id: UserId },
+    SegmentExports,
+    UsersImport,
+    Job { id: Uuid },
+    SegmentExportCsv { id: Uuid },
+    AuthArchiveExport,
+    AuthArchiveImport,
+    BreakGlass,
+    LegacySyncReport,
+    CrmDriftReport,
     PasswordChange,
     UserPasswordResetToken,
+    ResetSecurityQuestions,
+    SecurityQuestions,
+    PasswordStrength,
+    UserSecurityAnswers { user_id: UserId },
     UserEmailVerifyToken,
     GetUserEmalVerifyToken { user_id: UserId },
     GetUserPasswordResetToken { user_id: UserId },
 }
 
+/// Deprecation of a route, dates are in seconds since the unix epoch
+#[derive(Clone, Debug, PartialEq)]
+pub struct Deprecation {
+    pub deprecated_at: u64,
+    /// The route is removed after the sunset
+    pub sunset_at: u64,
+    /// Path template of the route replacing the deprecated one
+    pub successor: Option<&'static str>,
+}
+
+/// Routes of the internal listener
+#[derive(Clone, Debug, PartialEq)]
+pub enum InternalRoute {
+    LogLevel,
+    SingleFlights,
+    DeprecatedRoutes,
+    Rollouts,
+    WorkQueues,
+}
+
+impl Route {
+    /// Deprecation of the route, responses of deprecated routes carry `Deprecation` and
+    /// `Sunset` headers. A route is removed once its usage is gone after the sunset.
+    pub fn deprecation(&self) -> Option<Deprecation> {
+        match *self {
+            Route::UserBySagaId(_) => Some(Deprecation {
+                deprecated_at: 1_554_076_800, // 2019-04-01
+                sunset_at: 1_569_888_000,     // 2019-10-01
+                successor: Some("/users/by_saga_id/:saga_id"),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Queue db work of the route runs on, token issuance is kept apart from heavy admin work
+    pub fn work_priority(&self) -> WorkPriority {
+        match *self {
+            Route::JWTEmail
+            | Route::JWTGoogle
+            | Route::JWTFacebook
+            | Route::JWTRefresh
+            | Route::JWTRenew
+            | Route::JWTRenewSession
+            | Route::JWTStepUp
+            | Route::SessionResolve
+            | Route::OAuthToken
+            | Route::OAuthIntrospect => WorkPriority::Auth,
+            Route::UsersSearch
+            | Route::UsersSearchByEmail
+            | Route::UserCount
+            | Route::ProfileCompletionStats
+            | Route::LoginStats
+            | Route::FunnelStats
+            | Route::PublicStats
+            | Route::SegmentExports
+            | Route::SegmentExportCsv { .. }
+            | Route::UsersImport
+            | Route::AuthArchiveExport
+            | Route::AuthArchiveImport
+            | Route::LegacySyncReport
+            | Route::CrmDriftReport => WorkPriority::Batch,
+            _ => WorkPriority::Regular,
+        }
+    }
+
+    /// Path template of the route, e.g. for logs and metrics grouped by route
+    pub fn template(&self) -> &'static str {
+        match *self {
+            Route::Healthcheck => "/healthcheck",
+            Route::Readiness => "/ready",
+            Route::DependencyHealth => "/ready/dependencies",
+            Route::Countries => "/countries",
+            Route::Users => "/users",
+            Route::Invites => "/invites",
+            Route::Waitlist => "/waitlist",
+            Route::WaitlistInvites => "/waitlist/invites",
+            Route::User(_) => "/users/:id",
+            Route::UserDelete(_) => "/users/:id/delete",
+            Route::UserDeletionRequest(_) => "/users/:id/delete_request",
+            Route::UserDeletionConfirmations(_) => "/users/:id/delete_request/confirmations",
+            Route::TrustedContacts { .. } => "/users/:id/trusted_contacts",
+            Route::TrustedContact { .. } => "/users/:id/trusted_contacts/:contact_id",
+            Route::Recovery => "/users/recovery",
+            Route::RecoveryApprove => "/users/recovery/approve",
+            Route::UserBlock(_) => "/users/:id/block",
+            Route::UserUnblock(_) => "/users/:id/unblock",
+            Route::UserBySagaId(_) => "/user_by_saga_id/:saga_id",
+            Route::UsersBySagaId(_) => "/users/by_saga_id/:saga_id",
+            Route::UserCount => "/users/count",
+            Route::ProfileCompletionStats => "/users/profile_completion/stats",
+            Route::LoginStats => "/stats/logins",
+            Route::FunnelStats => "/stats/funnel",
+            Route::PublicStats => "/stats/public",
+            Route::EventSchemas => "/events/schemas",
+            Route::UsersSearch => "/users/search",
+            Route::UsersSearchByEmail => "/users/search/by_email",
+            Route::UserByEmail => "/users/by_email",
+            Route::UserByPhone(_) => "/users/by_phone/:phone",
+            Route::Current => "/users/current",
+            Route::CurrentProfilePrompts => "/users/current/prompts",
+            Route::DismissProfilePrompt => "/users/current/prompts/dismiss",
+            Route::ChildAccounts => "/users/current/children",
+            Route::ChildAccount { .. } => "/users/current/children/:child_id",
+            Route::ChildAccountPassword { .. } => "/users/current/children/:child_id/password",
+            Route::AccessTokens => "/users/current/tokens",
+            Route::AccessToken { .. } => "/users/current/tokens/:id",
+            Route::ConnectedApps => "/users/current/connected_apps",
+            Route::ConnectedApp { .. } => "/users/current/connected_apps/:client_id",
+            Route::ClientBranding { .. } => "/clients/:client_id/branding",
+            Route::Devices => "/users/current/devices",
+            Route::Device { .. } => "/users/current/devices/:id",
+            Route::DeviceTrust => "/users/current/devices/trust",
+            Route::JWTEmail => "/jwt/email",
+            Route::JWTGoogle => "/jwt/google",
+            Route::JWTFacebook => "/jwt/facebook",
+            Route::JWTRefresh => "/jwt/refresh",
+            Route::JWTRevoke => "/jwt/revoke",
+            Route::JWTStepUp => "/jwt/step_up",
+            Route::JWTRenew => "/jwt/renew",
+            Route::JWTRenewSession => "/jwt/renew/session",
+            Route::Sessions => "/sessions",
+            Route::SessionResolve => "/sessions/resolve",
+            Route::SessionLogout => "/sessions/logout",
+            Route::Csrf => "/csrf",
+            Route::OAuthToken => "/oauth/token",
+            Route::OAuthDeviceCode => "/oauth/device/code",
+            Route::OAuthIntrospect => "/oauth/introspect",
+            Route::DeviceApprove => "/device",
+            Route::SignedActions => "/signed_actions",
+            Route::Roles => "/roles",
+            Route::RoleById { .. } => "/roles/by-id/:id",
+            Route::RolesByUserId { .. } => "/roles/by-user-id/:user_id",
+            Route::RoleRequests => "/role_requests",
+            Route::RoleRequestApprove { .. } => "/role_requests/:id/approve",
+            Route::RoleAccessPolicies => "/role_access_policies",
+            Route::RoleAccessPolicy { .. } => "/role_access_policies/:role",
+            Route::UserTags { .. } => "/users/:id/tags",
+            Route::UserTag { .. } => "/users/:id/tags/:tag",
+            Route::UserIdentities { .. } => "/users/:id/identities",
+            Route::UserIdentity { .. } => "/users/:id/identities/:provider",
+            Route::UserTemporaryPassword { .. } => "/users/:id/identities/temporary_password",
+            Route::SegmentExports => "/users/segments/export",
+            Route::UsersImport => "/users/import",
+            Route::Job { .. } => "/jobs/:id",
+            Route::SegmentExportCsv { .. } => "/users/segments/export/:id/csv",
+            Route::AuthArchiveExport => "/auth_archive/export",
+            Route::AuthArchiveImport => "/auth_archive/import",
+            Route::BreakGlass => "/break_glass",
+            Route::LegacySyncReport => "/legacy_sync/report",
+            Route::CrmDriftReport => "/crm_reconciliation/report",
+            Route::PasswordChange => "/users/password_change",
+            Route::UserPasswordResetToken => "/users/password_reset_token",
+            Route::ResetSecurityQuestions => "/users/password_reset_token/security_questions",
+            Route::SecurityQuestions => "/security_questions",
+            Route::PasswordStrength => "/password/strength",
+            Route::UserSecurityAnswers { .. } => "/users/:id/security_answers",
+            Route::UserEmailVerifyToken => "/users/email_verify_token",
+            Route::GetUserEmalVerifyToken { .. } => "/users/:id/email_verify_token",
+            Route::GetUserPasswordResetToken { .. } => "/users/:id/password_reset_token",
+        }
+    }
+}
+
 pub fn create_route_parser() -> RouteParser<Route> {
     let mut router = RouteParser::default();
 
     // Healthcheck
     router.add_route(r"^/healthcheck$", || Route::Healthcheck);
 
+    // Readiness probe, fails until warmup is done
+    router.add_route(r"^/ready$", || Route::Readiness);
+
+    // Health of downstream services as a dependency graph
+    router.add_route(r"^/ready/dependencies$", || Route::DependencyHealth);
+
+    // Countries reference data
+    router.add_route(r"^/countries$", || Route::Countries);
+
     // Users Routes
     router.add_route(r"^/users$", || Route::Users);
 
+    // Invites for invite-only registration
+    router.add_route(r"^/invites$", || Route::Invites);
+
+    // Waitlist while registration is invite-only
+    router.add_route(r"^/waitlist$", || Route::Waitlist);
+    router.add_route(r"^/waitlist/invites$", || Route::WaitlistInvites);
+
     // User by email Route
     router.add_route(r"^/users/by_email$", || Route::UserByEmail);
 
+    // User by phone Route, `+` and spaces may come percent-encoded
+    router.add_route_with_params(r"^/users/by_phone/([^/]+)$", |params| {
+        params
+            .get(0)
+            .map(|phone| Route::UserByPhone(phone.replace("%2B", "+").replace("%20", " ")))
+    });
+
     // Users Routes
     router.add_route(r"^/users/current$", || Route::Current);
 
+    // Progressive profiling prompts of current user
+    router.add_route(r"^/users/current/prompts$", || Route::CurrentProfilePrompts);
+    router.add_route(r"^/users/current/prompts/dismiss$", || Route::DismissProfilePrompt);
+
+    // Child accounts managed by current user
+    router.add_route(r"^/users/current/children$", || Route::ChildAccounts);
+    router.add_route_with_params(r"^/users/current/children/(\d+)$", |params| {
+        params
+            .get(0)
+            .and_then(|string_id| string_id.parse::<UserId>().ok())
+            .map(|child_id| Route::ChildAccount { child_id })
+    });
+    router.add_route_with_params(r"^/users/current/children/(\d+)/password$", |params| {
+        params
+            .get(0)
+            .and_then(|string_id| string_id.parse::<UserId>().ok())
+            .map(|child_id| Route::ChildAccountPassword { child_id })
+    });
+
+    // Personal access tokens of current user
+    router.add_route(r"^/users/current/tokens$", || Route::AccessTokens);
+    router.add_route_with_params(r"^/users/current/tokens/([a-zA-Z0-9-]+)$", |params| {
+        params
+            .get(0)
+            .and_then(|string_id| string_id.parse().ok())
+            .map(|id| Route::AccessToken { id })
+    });
+
+    // Third-party clients current user consented to
+    router.add_route(r"^/users/current/connected_apps$", || Route::ConnectedApps);
+    router.add_route_with_params(r"^/users/current/connected_apps/([a-zA-Z0-9_-]+)$", |params| {
+        params.get(0).map(|client_id| Route::ConnectedApp {
+            client_id: client_id.to_string(),
+        })
+    });
+
+    // Branding of marketplace the client belongs to
+    router.add_route_with_params(r"^/clients/([a-zA-Z0-9_-]+)/branding$", |params| {
+        params.get(0).map(|client_id| Route::ClientBranding {
+            client_id: client_id.to_string(),
+        })
+    });
+
+    // Known devices of current user, trust is registered first not to be parsed as device id
+    router.add_route(r"^/users/current/devices$", || Route::Devices);
+    router.add_route(r"^/users/current/devices/trust$", || Route::DeviceTrust);
+    router.add_route_with_params(r"^/users/current/devices/([a-zA-Z0-9-]+)$", |params| {
+        params
+            .get(0)
+            .and_then(|string_id| string_id.parse().ok())
+            .map(|id| Route::Device { id })
+    });
+
     router.add_route_with_params(r"^/users/(\d+)/delete$", |params| {
         params
             .get(0)
@@ -53,6 +364,22 @@ pub fn create_route_parser() -> RouteParser<Route> {
             .map(Route::UserDelete)
     });
 
+    // Users/:id/delete_request route, two-phase deletion coordinated with dependent services
+    router.add_route_with_params(r"^/users/(\d+)/delete_request$", |params| {
+        params
+            .get(0)
+            .and_then(|string_id| string_id.parse::<UserId>().ok())
+            .map(Route::UserDeletionRequest)
+    });
+
+    // Users/:id/delete_request/confirmations route
+    router.add_route_with_params(r"^/users/(\d+)/delete_request/confirmations$", |params| {
+        params
+            .get(0)
+            .and_then(|string_id| string_id.parse::<UserId>().ok())
+            .map(Route::UserDeletionConfirmations)
+    });
+
     // JWT email route
     router.add_route(r"^/jwt/email$", || Route::JWTEmail);
 
@@ -68,6 +395,38 @@ pub fn create_route_parser() -> RouteParser<Route> {
     // JWT revoke route
     router.add_route(r"^/jwt/revoke", || Route::JWTRevoke);
 
+    // JWT step up route
+    router.add_route(r"^/jwt/step_up$", || Route::JWTStepUp);
+
+    // JWT silent renewal with rotating refresh token route
+    router.add_route(r"^/jwt/renew$", || Route::JWTRenew);
+
+    // JWT refresh token session route
+    router.add_route(r"^/jwt/renew/session$", || Route::JWTRenewSession);
+
+    // Server-side sessions routes
+    router.add_route(r"^/sessions$", || Route::Sessions);
+    router.add_route(r"^/sessions/resolve$", || Route::SessionResolve);
+    router.add_route(r"^/sessions/logout$", || Route::SessionLogout);
+
+    // CSRF token route
+    router.add_route(r"^/csrf$", || Route::Csrf);
+
+    // OAuth2 token route
+    router.add_route(r"^/oauth/token$", || Route::OAuthToken);
+
+    // OAuth2 device authorization route
+    router.add_route(r"^/oauth/device/code$", || Route::OAuthDeviceCode);
+
+    // OAuth2 token introspection route, resolves personal access tokens
+    router.add_route(r"^/oauth/introspect$", || Route::OAuthIntrospect);
+
+    // Device approval route
+    router.add_route(r"^/device$", || Route::DeviceApprove);
+
+    // Signed email actions route
+    router.add_route(r"^/signed_actions$", || Route::SignedActions);
+
     // Users/:id route
     router.add_route_with_params(r"^/users/(\d+)$", |params| {
         params
@@ -99,6 +458,9 @@ pub fn create_route_parser() -> RouteParser<Route> {
             .and_then(|string_id| string_id.parse::<String>().ok())
             .map(Route::UserBySagaId)
     });
+    router.add_route_with_params(r"^/users/by_saga_id/(.+)$", |params| {
+        params.get(0).map(|saga_id| Route::UsersBySagaId(saga_id.to_string()))
+    });
 
     router.add_route(r"^/roles$", || Route::Roles);
     router.add_route_with_params(r"^/roles/by-user-id/(\d+)$", |params| {
@@ -114,15 +476,124 @@ pub fn create_route_parser() -> RouteParser<Route> {
             .map(|id| Route::RoleById { id })
     });
 
+    // Pending grants of sensitive roles, approved by a second admin
+    router.add_route(r"^/role_requests$", || Route::RoleRequests);
+    router.add_route_with_params(r"^/role_requests/([a-zA-Z0-9-]+)/approve$", |params| {
+        params
+            .get(0)
+            .and_then(|string_id| string_id.parse().ok())
+            .map(|id| Route::RoleRequestApprove { id })
+    });
+
+    // Time windows admin roles are in effect during, by role name
+    router.add_route(r"^/role_access_policies$", || Route::RoleAccessPolicies);
+    router.add_route_with_params(r"^/role_access_policies/([a-z_]+)$", |params| {
+        params
+            .get(0)
+            .and_then(|role| serde_json::from_value(serde_json::Value::String(role.to_string())).ok())
+            .map(|role| Route::RoleAccessPolicy { role })
+    });
+
+    // Identities of the user for support operations, temporary password is registered first not to be parsed as provider
+    router.add_route_with_params(r"^/users/(\d+)/identities$", |params| {
+        params
+            .get(0)
+            .and_then(|string_id| string_id.parse().ok())
+            .map(|user_id| Route::UserIdentities { user_id })
+    });
+    router.add_route_with_params(r"^/users/(\d+)/identities/temporary_password$", |params| {
+        params
+            .get(0)
+            .and_then(|string_id| string_id.parse().ok())
+            .map(|user_id| Route::UserTemporaryPassword { user_id })
+    });
+    router.add_route_with_params(r"^/users/(\d+)/identities/([a-z_]+)$", |params| {
+        if let (Some(string_id), Some(provider)) = (params.get(0), params.get(1)) {
+            let provider = serde_json::from_value(serde_json::Value::String(provider.to_string())).ok();
+            match (string_id.parse().ok(), provider) {
+                (Some(user_id), Some(provider)) => Some(Route::UserIdentity { user_id, provider }),
+                _ => None,
+            }
+        } else {
+            None
+        }
+    });
+
+    // Users/:id/tags route
+    router.add_route_with_params(r"^/users/(\d+)/tags$", |params| {
+        params
+            .get(0)
+            .and_then(|string_id| string_id.parse().ok())
+            .map(|user_id| Route::UserTags { user_id })
+    });
+
+    // Users/:id/tags/:tag route
+    router.add_route_with_params(r"^/users/(\d+)/tags/([^/]+)$", |params| {
+        if let (Some(string_id), Some(tag)) = (params.get(0), params.get(1)) {
+            string_id.parse().ok().map(|user_id| Route::UserTag {
+                user_id,
+                tag: tag.to_string(),
+            })
+        } else {
+            None
+        }
+    });
+
+    // Users/:id/trusted_contacts route, contacts approving recovery of the account
+    router.add_route_with_params(r"^/users/(\d+)/trusted_contacts$", |params| {
+        params
+            .get(0)
+            .and_then(|string_id| string_id.parse().ok())
+            .map(|user_id| Route::TrustedContacts { user_id })
+    });
+
+    // Users/:id/trusted_contacts/:contact_id route
+    router.add_route_with_params(r"^/users/(\d+)/trusted_contacts/(\d+)$", |params| {
+        if let (Some(string_id), Some(string_contact_id)) = (params.get(0), params.get(1)) {
+            match (string_id.parse().ok(), string_contact_id.parse().ok()) {
+                (Some(user_id), Some(contact_id)) => Some(Route::TrustedContact { user_id, contact_id }),
+                _ => None,
+            }
+        } else {
+            None
+        }
+    });
+
+    // Account recovery via trusted contacts routes
+    router.add_route(r"^/users/recovery$", || Route::Recovery);
+    router.add_route(r"^/users/recovery/approve$", || Route::RecoveryApprove);
+
     // /users/count route
     router.add_route(r"^/users/count$", || Route::UserCount);
 
+    // /users/profile_completion/stats route
+    router.add_route(r"^/users/profile_completion/stats$", || Route::ProfileCompletionStats);
+
     // /users/password_change route
     router.add_route(r"^/users/password_change$", || Route::PasswordChange);
 
     // /users/password_reset_token route
     router.add_route(r"^/users/password_reset_token$", || Route::UserPasswordResetToken);
 
+    // Security questions to be answered on password reset route
+    router.add_route(r"^/users/password_reset_token/security_questions$", || {
+        Route::ResetSecurityQuestions
+    });
+
+    // Security questions catalog route
+    router.add_route(r"^/security_questions$", || Route::SecurityQuestions);
+
+    // Password strength feedback route
+    router.add_route(r"^/password/strength$", || Route::PasswordStrength);
+
+    // Users/:id/security_answers route
+    router.add_route_with_params(r"^/users/(\d+)/security_answers$", |params| {
+        params
+            .get(0)
+            .and_then(|string_id| string_id.parse().ok())
+            .map(|user_id| Route::UserSecurityAnswers { user_id })
+    });
+
     // Get user password reset token route
     router.add_route_with_params(r"^/users/(\d+)/password_reset_token$", |params| {
         params
@@ -142,6 +613,55 @@ pub fn create_route_parser() -> RouteParser<Route> {
             .map(|user_id| Route::GetUserEmalVerifyToken { user_id })
     });
 
+    // Export users segment route
+    router.add_route(r"^/users/segments/export$", || Route::SegmentExports);
+
+    // Import of users from other systems route
+    router.add_route(r"^/users/import$", || Route::UsersImport);
+
+    // Segment export CSV route
+    router.add_route_with_params(r"^/users/segments/export/([a-zA-Z0-9-]+)/csv$", |params| {
+        params
+            .get(0)
+            .and_then(|string_id| string_id.parse().ok())
+            .map(|id| Route::SegmentExportCsv { id })
+    });
+
+    // Export of auth data route
+    router.add_route(r"^/auth_archive/export$", || Route::AuthArchiveExport);
+
+    // Import of auth data route
+    router.add_route(r"^/auth_archive/import$", || Route::AuthArchiveImport);
+
+    // Break-glass activation route
+    router.add_route(r"^/break_glass$", || Route::BreakGlass);
+
+    // Reconciliation report of dual-write to legacy system route
+    router.add_route(r"^/legacy_sync/report$", || Route::LegacySyncReport);
+
+    // Drifts of users from CRM contacts found by the last reconciliation route
+    router.add_route(r"^/crm_reconciliation/report$", || Route::CrmDriftReport);
+
+    // Job status route
+    router.add_route_with_params(r"^/jobs/([a-zA-Z0-9-]+)$", |params| {
+        params
+            .get(0)
+            .and_then(|string_id| string_id.parse().ok())
+            .map(|id| Route::Job { id })
+    });
+
+    // Login stats route
+    router.add_route(r"^/stats/logins$", || Route::LoginStats);
+
+    // Registration funnel stats route
+    router.add_route(r"^/stats/funnel$", || Route::FunnelStats);
+
+    // Public stats route, noisy stats safe to be shown to anyone
+    router.add_route(r"^/stats/public$", || Route::PublicStats);
+
+    // Schemas of emitted events
+    router.add_route(r"^/events/schemas$", || Route::EventSchemas);
+
     // Search users
     router.add_route(r"^/users/search$", || Route::UsersSearch);
 
@@ -150,3 +670,24 @@ pub fn create_route_parser() -> RouteParser<Route> {
 
     router
 }
+
+pub fn create_internal_route_parser() -> RouteParser<InternalRoute> {
+    let mut router = RouteParser::default();
+
+    // Runtime log level route
+    router.add_route(r"^/debug/log_level$", || InternalRoute::LogLevel);
+
+    // Counters of coalesced reads
+    router.add_route(r"^/debug/single_flights$", || InternalRoute::SingleFlights);
+
+    // Usage counters of deprecated routes
+    router.add_route(r"^/debug/deprecated_routes$", || InternalRoute::DeprecatedRoutes);
+
+    // Assignments of users to features under rollout
+    router.add_route(r"^/debug/rollouts$", || InternalRoute::Rollouts);
+
+    // Depths of db work queues by priority
+    router.add_route(r"^/debug/work_queues$", || InternalRoute::WorkQueues);
+
+    router
+}
@@ -1,6 +1,18 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::iter::FromIterator;
 
+use bytes::BytesMut;
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::{Future, Stream};
+use hyper::Body;
+use serde::de::DeserializeOwned;
+use serde_json;
+use serde_urlencoded;
+
+use errors::Error;
+
 /// Splits query string to key-value pairs. See `macros::parse_query` for more sophisticated parsing.
 // TODO: Cover more complex cases, e.g. `from=count=10`
 pub fn query_params(query: &str) -> HashMap<&str, &str> {
@@ -9,3 +21,94 @@ pub fn query_params(query: &str) -> HashMap<&str, &str> {
         (params.next().unwrap(), params.next().unwrap_or(""))
     }))
 }
+
+/// Reads `application/x-www-form-urlencoded` body, as required e.g. by OAuth2 token endpoint
+pub fn parse_form_body<T>(body: Body) -> Box<Future<Item = T, Error = FailureError>>
+where
+    T: DeserializeOwned + 'static,
+{
+    Box::new(
+        body.concat2()
+            .map_err(|e| format_err!("Failed to read request body: {}", e))
+            .and_then(|body| serde_urlencoded::from_bytes::<T>(&body).map_err(FailureError::from)),
+    )
+}
+
+/// Max number of body buffers kept for reuse by a reactor thread
+const MAX_POOLED_BUFFERS: usize = 16;
+/// Larger buffers are dropped after use, so that a single big request does not pin memory
+const MAX_POOLED_BUFFER_CAPACITY: usize = 64 * 1024;
+
+thread_local! {
+    static BODY_BUFFERS: RefCell<Vec<BytesMut>> = RefCell::new(Vec::new());
+}
+
+fn take_buffer() -> BytesMut {
+    BODY_BUFFERS
+        .with(|buffers| buffers.borrow_mut().pop())
+        .unwrap_or_else(BytesMut::new)
+}
+
+fn return_buffer(mut buffer: BytesMut) {
+    if buffer.capacity() > MAX_POOLED_BUFFER_CAPACITY {
+        return;
+    }
+
+    buffer.clear();
+    BODY_BUFFERS.with(|buffers| {
+        let mut buffers = buffers.borrow_mut();
+        if buffers.len() < MAX_POOLED_BUFFERS {
+            buffers.push(buffer);
+        }
+    });
+}
+
+/// Reads JSON body chunk by chunk into a reused buffer and deserializes it straight from bytes,
+/// without intermediate `String`. Bodies larger than `max_size` bytes are rejected while reading.
+pub fn parse_json_body<T>(body: Body, max_size: usize) -> Box<Future<Item = T, Error = FailureError>>
+where
+    T: DeserializeOwned + 'static,
+{
+    Box::new(
+        body.map_err(|e| format_err!("Failed to read request body: {}", e))
+            .fold(take_buffer(), move |mut buffer, chunk| -> Result<BytesMut, FailureError> {
+                if buffer.len() + chunk.len() > max_size {
+                    return Err(format_err!("Request body exceeds {} bytes", max_size)
+                        .context(Error::PayloadTooLarge)
+                        .into());
+                }
+                buffer.reserve(chunk.len());
+                buffer.extend_from_slice(&chunk);
+                Ok(buffer)
+            })
+            .and_then(|buffer| {
+                let payload = serde_json::from_slice::<T>(&buffer).map_err(FailureError::from);
+                return_buffer(buffer);
+                payload
+            }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Payload {
+        email: String,
+    }
+
+    #[test]
+    fn test_parse_json_body() {
+        let payload = parse_json_body::<Payload>(Body::from(r#"{"email": "user@example.com"}"#), 1024)
+            .wait()
+            .unwrap();
+        assert_eq!(payload.email, "user@example.com");
+    }
+
+    #[test]
+    fn test_parse_json_body_too_large() {
+        let result = parse_json_body::<Payload>(Body::from(r#"{"email": "user@example.com"}"#), 8).wait();
+        assert_eq!(result.is_err(), true);
+    }
+}
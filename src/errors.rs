@@ -4,6 +4,8 @@ use validator::ValidationErrors;
 
 use stq_http::errors::{Codeable, PayloadCarrier};
 
+use models::{OAuthError, OAuthErrorCode};
+
 #[derive(Debug, Fail)]
 pub enum Error {
     #[fail(display = "Not found")]
@@ -22,16 +24,37 @@ pub enum Error {
     InvalidToken,
     #[fail(display = "Invalid time duration")]
     InvalidTime,
+    #[fail(display = "Recent authentication required")]
+    ReauthRequired,
+    #[fail(display = "Too many attempts")]
+    TooManyAttempts,
+    #[fail(display = "Request body is too large")]
+    PayloadTooLarge,
+    #[fail(display = "Requested number of results exceeds limit of {}", _0)]
+    LimitExceeded(i64),
+    #[fail(display = "OAuth2 error: {:?}", _0)]
+    OAuth(OAuthErrorCode),
+    #[fail(display = "Service is not ready yet")]
+    NotReady,
+    #[fail(display = "Service is overloaded")]
+    Overloaded,
+    #[fail(display = "Service is in read-only mode")]
+    ReadOnly,
 }
 
 impl Codeable for Error {
     fn code(&self) -> StatusCode {
         match *self {
             Error::NotFound => StatusCode::NotFound,
-            Error::Validate(_) => StatusCode::BadRequest,
+            Error::Validate(_) | Error::LimitExceeded(_) => StatusCode::BadRequest,
             Error::Parse => StatusCode::UnprocessableEntity,
             Error::Connection | Error::HttpClient | Error::InvalidTime => StatusCode::InternalServerError,
             Error::Forbidden | Error::InvalidToken => StatusCode::Forbidden,
+            Error::ReauthRequired | Error::OAuth(OAuthErrorCode::InvalidClient) => StatusCode::Unauthorized,
+            Error::OAuth(_) => StatusCode::BadRequest,
+            Error::TooManyAttempts => StatusCode::TooManyRequests,
+            Error::PayloadTooLarge => StatusCode::PayloadTooLarge,
+            Error::NotReady | Error::Overloaded | Error::ReadOnly => StatusCode::ServiceUnavailable,
         }
     }
 }
@@ -40,6 +63,7 @@ impl PayloadCarrier for Error {
     fn payload(&self) -> Option<serde_json::Value> {
         match *self {
             Error::Validate(ref e) => serde_json::to_value(e.clone()).ok(),
+            Error::OAuth(error) => serde_json::to_value(OAuthError { error }).ok(),
             _ => None,
         }
     }
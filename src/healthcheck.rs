@@ -0,0 +1,83 @@
+//! Healthcheck mode of the binary, `users healthcheck` checks readiness of the instance running
+//! in the same container by `/ready`, `users healthcheck --db` pings configured databases
+//! instead. It exits with status 0 if healthy and 1 otherwise, for exec-based health checks
+//! of Docker and Kubernetes in images without curl.
+
+use std::time::Duration;
+
+use diesel;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use failure::Error as FailureError;
+use futures::Future;
+use hyper;
+use tokio_core::reactor::{Core, Timeout};
+
+use config::Config;
+
+/// What the healthcheck checks
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HealthcheckMode {
+    /// Readiness of the running instance
+    Http,
+    /// Connectivity to databases of all shards
+    Db,
+}
+
+impl HealthcheckMode {
+    pub fn from_arg(arg: Option<&str>) -> Option<Self> {
+        match arg {
+            None => Some(HealthcheckMode::Http),
+            Some("--db") => Some(HealthcheckMode::Db),
+            Some(_) => None,
+        }
+    }
+}
+
+pub fn healthcheck(config: &Config, mode: HealthcheckMode) -> Result<(), FailureError> {
+    match mode {
+        HealthcheckMode::Http => check_readiness(config),
+        HealthcheckMode::Db => check_databases(config),
+    }
+}
+
+/// Calls `/ready` of the instance listening on the configured port
+fn check_readiness(config: &Config) -> Result<(), FailureError> {
+    // the instance listening on all interfaces is reached over loopback
+    let host = if config.server.host == "0.0.0.0" {
+        "127.0.0.1"
+    } else {
+        config.server.host.as_str()
+    };
+    let uri: hyper::Uri = format!("http://{}:{}/ready", host, config.server.port).parse()?;
+
+    let mut core = Core::new()?;
+    let handle = core.handle();
+    let client = hyper::Client::new(&handle);
+    let timeout_ms = config.client.http_timeout_ms;
+    let timeout = Timeout::new(Duration::from_millis(timeout_ms), &handle)?
+        .map_err(FailureError::from)
+        .and_then(move |_| Err(format_err!("Readiness check timed out after {} ms", timeout_ms)));
+    let request = client.get(uri).map_err(FailureError::from);
+
+    let response = core.run(request.select(timeout).map(|(response, _)| response).map_err(|(e, _)| e))?;
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(format_err!("Instance is not ready, status {}", response.status()))
+    }
+}
+
+/// Connects to databases of all shards and runs a trivial query
+fn check_databases(config: &Config) -> Result<(), FailureError> {
+    let databases = if config.sharding.shards.is_empty() {
+        vec![config.server.database.as_str()]
+    } else {
+        config.sharding.shards.iter().map(|shard| shard.database.as_str()).collect()
+    };
+    for database in databases {
+        let conn = PgConnection::establish(database)?;
+        diesel::sql_query("SELECT 1").execute(&conn)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,176 @@
+//! Message catalog for validation errors. Locale is selected from `Accept-Language` header,
+//! messages are looked up by `<field>.<code>` key. The key is returned in `params.message_key`
+//! of every error, so that clients can localize messages themselves.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+
+use failure::{Context, Error as FailureError, Fail};
+use hyper::header::{q, AcceptLanguage};
+use serde_json;
+use validator::ValidationErrors;
+
+use errors::Error;
+
+const MESSAGE_KEY_PARAM: &'static str = "message_key";
+
+lazy_static! {
+    static ref CATALOGS: HashMap<Locale, HashMap<String, String>> = {
+        let mut catalogs = HashMap::new();
+        catalogs.insert(Locale::En, parse_catalog(include_str!("../../locales/en.json")));
+        catalogs.insert(Locale::Ru, parse_catalog(include_str!("../../locales/ru.json")));
+        catalogs
+    };
+}
+
+fn parse_catalog(source: &str) -> HashMap<String, String> {
+    serde_json::from_str(source).expect("Message catalog is not a valid json map")
+}
+
+/// Supported locales
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Locale {
+    En,
+    Ru,
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Locale::En
+    }
+}
+
+impl fmt::Display for Locale {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Locale::En => write!(f, "en"),
+            Locale::Ru => write!(f, "ru"),
+        }
+    }
+}
+
+impl Locale {
+    /// Returns locale for primary language subtag, e.g. `ru` for `ru-RU`
+    pub fn from_language(language: &str) -> Option<Self> {
+        match language.to_lowercase().as_str() {
+            "en" => Some(Locale::En),
+            "ru" => Some(Locale::Ru),
+            _ => None,
+        }
+    }
+
+    /// Picks the supported locale with the highest quality, falls back to default one
+    pub fn from_accept_language(accept_language: &AcceptLanguage) -> Self {
+        let mut languages = accept_language.0.iter().filter(|item| item.quality > q(0)).collect::<Vec<_>>();
+        languages.sort_by(|a, b| b.quality.cmp(&a.quality));
+
+        languages
+            .into_iter()
+            .filter_map(|item| item.item.language.as_ref().and_then(|language| Locale::from_language(language)))
+            .next()
+            .unwrap_or_default()
+    }
+
+    /// Looks up message in catalog of this locale
+    pub fn message(self, key: &str) -> Option<&'static str> {
+        CATALOGS
+            .get(&self)
+            .and_then(|catalog| catalog.get(key))
+            .map(|message| message.as_str())
+    }
+}
+
+/// Translates messages of validation errors. Errors without catalog entry keep original message.
+pub fn localize_validation_errors(errors: ValidationErrors, locale: Locale) -> ValidationErrors {
+    let mut localized = ValidationErrors::new();
+    for (field, field_errors) in errors.inner() {
+        for mut error in field_errors {
+            let key = format!("{}.{}", field, error.code);
+            if let Some(message) = locale.message(&key) {
+                error.message = Some(Cow::from(message));
+            }
+            error.params.insert(Cow::from(MESSAGE_KEY_PARAM), serde_json::Value::String(key));
+            localized.add(field, error);
+        }
+    }
+    localized
+}
+
+/// Translates validation errors carried by the error chain, other errors are returned as is
+pub fn localize_error(err: FailureError, locale: Locale) -> FailureError {
+    let validation_errors = err
+        .iter_chain()
+        .filter_map(|cause| {
+            cause
+                .downcast_ref::<Error>()
+                .or_else(|| cause.downcast_ref::<Context<Error>>().map(|context| context.get_context()))
+        })
+        .next()
+        .and_then(|error| match *error {
+            Error::Validate(ref errors) => Some(errors.clone()),
+            _ => None,
+        });
+
+    match validation_errors {
+        Some(errors) => err.context(Error::Validate(localize_validation_errors(errors, locale))).into(),
+        None => err,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use failure::Fail;
+    use hyper::header::{q, qitem, AcceptLanguage, QualityItem};
+    use serde_json;
+
+    use super::*;
+    use errors::Error;
+
+    #[test]
+    fn test_locale_from_accept_language() {
+        let header = AcceptLanguage(vec![
+            qitem("de".parse().unwrap()),
+            QualityItem::new("ru-RU".parse().unwrap(), q(800)),
+            QualityItem::new("en".parse().unwrap(), q(500)),
+        ]);
+        assert_eq!(Locale::from_accept_language(&header), Locale::Ru);
+
+        let header = AcceptLanguage(vec![qitem("fr".parse().unwrap())]);
+        assert_eq!(Locale::from_accept_language(&header), Locale::En);
+    }
+
+    #[test]
+    fn test_localize_validation_errors() {
+        let errors = validation_errors!({
+            "email": ["not_valid" => "Invalid email format"],
+            "nickname": ["unknown" => "Unknown error"]
+        });
+        let localized = localize_validation_errors(errors, Locale::Ru);
+        let json = serde_json::to_value(&localized).unwrap();
+
+        assert_eq!(json["email"][0]["code"], "not_valid");
+        assert_eq!(json["email"][0]["message"], "Неверный формат email");
+        assert_eq!(json["email"][0]["params"]["message_key"], "email.not_valid");
+        assert_eq!(json["nickname"][0]["message"], "Unknown error");
+        assert_eq!(json["nickname"][0]["params"]["message_key"], "nickname.unknown");
+    }
+
+    #[test]
+    fn test_localize_error_chain() {
+        let err: FailureError = format_err!("Validation failed")
+            .context(Error::Validate(validation_errors!({"password": ["password" => "Wrong password"]})))
+            .context("Service users, login endpoint error occured.")
+            .into();
+        let localized = localize_error(err, Locale::Ru);
+        let error = localized.downcast_ref::<Context<Error>>().map(|context| context.get_context());
+
+        match error {
+            Some(Error::Validate(errors)) => {
+                let json = serde_json::to_value(errors).unwrap();
+                assert_eq!(json["password"][0]["message"], "Неверный пароль");
+            }
+            _ => panic!("Validation error is expected"),
+        }
+    }
+}
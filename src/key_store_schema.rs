@@ -0,0 +1,7 @@
+table! {
+    user_keys (user_id) {
+        user_id -> Int4,
+        data_key -> Bytea,
+        created_at -> Timestamp,
+    }
+}
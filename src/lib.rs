@@ -9,40 +9,51 @@
 //! or `HttpClient` repo.
 
 #![allow(proc_macro_derive_resolution_fallback)]
+extern crate argon2;
 extern crate base64;
+extern crate bcrypt;
+extern crate bytes;
 extern crate chrono;
 extern crate config as config_crate;
 #[macro_use]
 extern crate diesel;
+extern crate env_logger;
 #[macro_use]
 extern crate failure;
+extern crate fallible_iterator;
 extern crate futures;
 extern crate futures_cpupool;
+extern crate hmac;
+#[macro_use]
 extern crate hyper;
 extern crate hyper_tls;
+extern crate idna;
 extern crate jsonwebtoken;
 #[macro_use]
 extern crate lazy_static;
 #[macro_use]
 extern crate log;
+extern crate postgres;
 extern crate r2d2;
 extern crate r2d2_redis;
 extern crate rand;
 extern crate regex;
+extern crate ring;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde_json;
+extern crate serde_urlencoded;
 extern crate sha3;
 extern crate tokio_core;
 extern crate tokio_signal;
+extern crate unicode_normalization;
 extern crate uuid;
 extern crate validator;
 #[macro_use]
 extern crate validator_derive;
 #[macro_use]
 extern crate sentry;
-extern crate stq_cache;
 extern crate stq_http;
 extern crate stq_logging;
 extern crate stq_router;
@@ -51,9 +62,17 @@ extern crate stq_types;
 
 #[macro_use]
 pub mod macros;
+pub mod cache;
+#[cfg(feature = "client")]
+pub mod client;
 pub mod config;
 pub mod controller;
 pub mod errors;
+pub mod healthcheck;
+pub mod i18n;
+#[rustfmt::skip]
+pub mod key_store_schema;
+pub mod logging;
 pub mod models;
 pub mod repos;
 #[rustfmt::skip]
@@ -70,18 +89,36 @@ use std::time::Duration;
 use diesel::pg::PgConnection;
 use diesel::r2d2::ConnectionManager;
 use futures::{Future, Stream};
-use futures_cpupool::CpuPool;
 use hyper::server::Http;
-use r2d2_redis::RedisConnectionManager;
-use stq_cache::cache::{redis::RedisCache, Cache, NullCache, TypedCache};
 use stq_http::controller::Application;
+use stq_types::{UserId, UsersRole};
 use tokio_core::reactor::Core;
 
+use cache::invalidation::{subscribe_postgres, subscribe_redis};
+use cache::CacheFactory;
 use config::Config;
+use controller::admin_ui::AdminUiService;
+use controller::cache_policy::CachePolicyService;
+use controller::content_type::ContentTypeService;
 use controller::context::StaticContext;
+use controller::deprecation::DeprecationService;
 use errors::Error;
-use repos::acl::RolesCacheImpl;
+use repos::acl::{policies_with_rules, RolesCacheImpl, ROLES_INVALIDATION_CHANNEL, ROLES_NOTIFY_CHANNELS};
+use repos::attempts_cache::AttemptsCacheImpl;
+use repos::key_store::KeyStoreImpl;
+use repos::pii_cipher::PiiCipher;
 use repos::repo_factory::ReposFactoryImpl;
+use repos::sharding::{ShardMap, ShardedPool};
+use repos::types::{DbPool, RepoLimits};
+use services::backfills::start_backfills;
+use services::crm_reconciliation::start_crm_reconciliation;
+use services::deletion_requests::start_deletion_checks;
+use services::name_screening::NameScreeningServiceImpl;
+use services::schema_check::start_schema_checks;
+use services::segment_export::start_segment_export_purge;
+use services::user_roles::start_role_expiry_checks;
+use services::warmup::start_warmup;
+use services::work_pool::{WorkPool, WorkPriority};
 
 /// Starts new web service from provided `Config`
 pub fn start_server(config: Config) {
@@ -93,6 +130,9 @@ pub fn start_server(config: Config) {
     let client_stream = client.stream();
     handle.spawn(client_stream.for_each(|_| Ok(())));
 
+    // Prepare validation rules
+    models::set_utf8_local_part_allowed(config.validation.allow_utf8_email_local_part);
+
     // Prepare server
     let thread_count = config.server.thread_count;
 
@@ -103,53 +143,227 @@ pub fn start_server(config: Config) {
             .expect("Could not parse address")
     };
 
+    let internal_port = config.server.internal_port.clone();
+    let internal_host = config.server.internal_host.clone();
+    let max_body_size = config.server.max_body_size;
+
     // Prepare database pool
-    let database_url: String = config.server.database.parse().expect("Database URL must be set in configuration");
-    let db_manager = ConnectionManager::<PgConnection>::new(database_url);
-    let db_pool = r2d2::Pool::builder()
-        .build(db_manager)
-        .expect("Failed to create DB connection pool");
+    if !config.sharding.enabled && !config.sharding.shards.is_empty() {
+        warn!("Sharding is disabled, configured shards are ignored and all the data is stored in server.database");
+    }
+    let shards = config.sharding.active_shards().to_vec();
+    // every thread of the work pool holds a connection while running db work, registrations on
+    // shards also hold the one of the primary shard and the ones checking e-mails on every shard
+    let worker_threads = config.work_queues.total_threads(thread_count) as u32;
+    let db_pool_size = if shards.is_empty() { worker_threads } else { worker_threads * 3 };
+    let db_pool = if shards.is_empty() {
+        ShardedPool::single(create_db_pool(&config.server.database, db_pool_size))
+    } else {
+        warn!("Sharding is enabled, login, ACL and roles are served by the primary shard only");
+        let shard_map = ShardMap::new(
+            config.sharding.virtual_buckets,
+            shards.iter().map(|shard| (shard.first_bucket, shard.last_bucket)).collect(),
+        )
+        .expect("Invalid shard map in configuration");
+        let shard_pools = shards.iter().map(|shard| create_db_pool(&shard.database, db_pool_size)).collect();
+        ShardedPool::new(shard_map, shard_pools)
+    };
 
-    // Prepare CPU pool
-    let cpu_pool = CpuPool::new(thread_count);
+    // Prepare pools of db work, token issuance runs apart from admin reports and exports
+    let work_pool = WorkPool::new(&config.work_queues, thread_count);
 
     // Prepare cache
-    let roles_cache = match &config.server.redis {
-        Some(redis_url) => {
-            // Prepare Redis pool
-            let redis_url: String = redis_url.parse().expect("Redis URL must be set in configuration");
-            let redis_manager = RedisConnectionManager::new(redis_url.as_ref()).expect("Failed to create Redis connection manager");
-            let redis_pool = r2d2::Pool::builder()
-                .build(redis_manager)
-                .expect("Failed to create Redis connection pool");
-
-            let ttl = Duration::from_secs(config.server.cache_ttl_sec);
-
-            let roles_cache_backend = Box::new(TypedCache::new(
-                RedisCache::new(redis_pool.clone(), "roles".to_string()).with_ttl(ttl),
-            )) as Box<dyn Cache<_, Error = _> + Send + Sync>;
-
-            RolesCacheImpl::new(roles_cache_backend)
+    let cache_factory = CacheFactory::new(&config).expect("Invalid cache configuration");
+    let roles_cache =
+        RolesCacheImpl::new(cache_factory.create::<Vec<UsersRole>>("roles", Duration::from_secs(config.server.cache_ttl_sec)));
+    // lockouts count attempts in memory if Redis is not set, instances count their attempts separately then
+    if !cache_factory.has_shared_counters() {
+        warn!("Redis is not set, failed token attempts are counted by every instance separately");
+    }
+    let attempts_cache =
+        AttemptsCacheImpl::new(cache_factory.create_counter("token_attempts", Duration::from_secs(config.tokens.apply_lockout_s)));
+    let mut roles_cache = roles_cache.with_local_cache(
+        config.server.roles_local_cache_size,
+        Duration::from_secs(config.server.roles_local_cache_ttl_sec),
+    );
+    // Roles changed on other instances are dropped from in-process cache, changes are propagated over Redis or by database triggers
+    let has_local_roles_cache = roles_cache.has_local_cache();
+    let invalidations_redis_url = if has_local_roles_cache { config.server.redis.clone() } else { None };
+    if let Some(publisher) = invalidations_redis_url
+        .as_ref()
+        .and_then(|_| cache_factory.invalidation_publisher(ROLES_INVALIDATION_CHANNEL))
+    {
+        roles_cache = roles_cache.with_invalidation_publisher(Box::new(publisher));
+    }
+
+    let repo_factory = ReposFactoryImpl::new(roles_cache, attempts_cache).with_limits(RepoLimits {
+        max_count: config.repo_limits.max_count,
+    });
+    let repo_factory = match config.pii_encryption {
+        Some(ref pii_encryption) => {
+            info!("PII of users is sealed by per-user data keys");
+            let key_store = KeyStoreImpl::new(create_db_pool(&pii_encryption.key_store_database, worker_threads));
+            repo_factory.with_pii_cipher(PiiCipher::new(pii_encryption, Arc::new(key_store)).expect("Invalid PII encryption config"))
         }
-        None => RolesCacheImpl::new(Box::new(NullCache::new()) as Box<_>),
+        None => repo_factory,
     };
+    let repo_factory = repo_factory.with_policies(policies_with_rules(config.acl.rules.clone()));
+    let repo_factory = match config.break_glass {
+        Some(ref break_glass) => repo_factory.with_break_glass_user(break_glass.user_id),
+        None => repo_factory,
+    };
+
+    if let Some(redis_url) = invalidations_redis_url {
+        let roles_cache = repo_factory.roles_cache();
+        subscribe_redis(redis_url, ROLES_INVALIDATION_CHANNEL.to_string(), move |key| {
+            match key.parse::<i32>() {
+                Ok(user_id) => roles_cache.local_remove(UserId(user_id)),
+                Err(_) => warn!("Invalid roles invalidation key '{}'", key),
+            }
+        })
+        .expect("Failed to start roles invalidation listener");
+    }
+
+    if config.cache.pg_notify_invalidations {
+        let databases = if shards.is_empty() {
+            vec![config.server.database.clone()]
+        } else {
+            shards.iter().map(|shard| shard.database.clone()).collect()
+        };
+        let channels = ROLES_NOTIFY_CHANNELS.iter().map(|channel| channel.to_string()).collect::<Vec<_>>();
+        for database in databases {
+            let roles_cache = repo_factory.roles_cache();
+            subscribe_postgres(database, channels.clone(), move |channel, payload| match payload.parse::<i32>() {
+                Ok(user_id) => roles_cache.invalidate(UserId(user_id)),
+                Err(_) => warn!("Invalid payload '{}' of database notification at channel '{}'", payload, channel),
+            })
+            .expect("Failed to start database notifications listener");
+        }
+    }
+
+    // Standby serves reads from a replica, background jobs writing to the database are left to the primary region
+    if config.read_only {
+        warn!("Serving in read-only mode, mutating requests are rejected");
+    } else {
+        // Deletions not answered by dependent services in time are approved in background
+        start_deletion_checks(
+            db_pool.clone(),
+            repo_factory.clone(),
+            work_pool.cpu_pool(WorkPriority::Batch),
+            config.deletion.confirming_services.clone(),
+            Duration::from_secs(config.deletion.check_interval_s),
+        )
+        .expect("Failed to start deletion requests checks");
+
+        // Time-boxed roles are revoked in background once they expire
+        start_role_expiry_checks(
+            db_pool.clone(),
+            repo_factory.clone(),
+            Duration::from_secs(config.role_expiry.check_interval_s),
+        )
+        .expect("Failed to start role expiry checks");
 
-    let repo_factory = ReposFactoryImpl::new(roles_cache);
+        // CSVs of segment exports hold PII, they are kept for a limited time only
+        start_segment_export_purge(
+            db_pool.clone(),
+            repo_factory.clone(),
+            Duration::from_secs(config.segment_exports.csv_ttl_s),
+            Duration::from_secs(config.segment_exports.purge_interval_s),
+        )
+        .expect("Failed to start segment exports purge");
+
+        // Rows stored before normalization changes are brought to the current form
+        start_backfills(
+            db_pool.clone(),
+            repo_factory.clone(),
+            config.phone.default_region.clone(),
+            config.pii_encryption.is_some(),
+        )
+        .expect("Failed to start backfills");
+    }
 
     debug!("Reading private key file {}", &config.jwt.secret_key_path);
     let mut f = File::open(config.jwt.secret_key_path.clone()).unwrap();
     let mut jwt_private_key: Vec<u8> = Vec::new();
     f.read_to_end(&mut jwt_private_key).unwrap();
 
-    let context = StaticContext::new(db_pool, cpu_pool, client_handle, Arc::new(config), repo_factory, jwt_private_key);
+    let name_screening = Arc::new(NameScreeningServiceImpl::new(config.name_screening.clone()));
+
+    // Admin SPA files are served by the same listener, if configured
+    let admin_ui = config.admin_ui.clone();
+    let admin_ui_cpu_pool = work_pool.cpu_pool(WorkPriority::Regular);
+    if let Some(ref admin_ui) = admin_ui {
+        info!(
+            "Serving admin UI from {} under {}",
+            admin_ui.dir,
+            controller::admin_ui::ADMIN_UI_PREFIX
+        );
+    }
+
+    let context = Arc::new(StaticContext::new(
+        db_pool,
+        work_pool,
+        client_handle,
+        Arc::new(config),
+        repo_factory,
+        jwt_private_key,
+        name_screening,
+    ));
+    // Counters of coalesced reads, deprecated route usage, rollouts and work queues are served by the internal listener
+    let single_flights = context.single_flights.clone();
+    let deprecated_route_usage = context.deprecated_route_usage.clone();
+    let rollouts = context.rollouts.clone();
+    let work_pool = context.work_pool.clone();
+
+    // Instance is reported ready by `/ready` once pools and caches are warmed up
+    if context.config.warmup.enabled {
+        start_warmup(
+            context.db_pool.clone(),
+            context.repo_factory.clone(),
+            context.jwt_private_key.clone(),
+            context.config.warmup.max_superusers,
+            context.readiness.clone(),
+        )
+        .expect("Failed to start warmup");
+    }
+
+    // Old and new binaries run side by side during deploys, while the schema is compatible with both
+    if context.config.schema_check.enabled {
+        start_schema_checks(
+            context.db_pool.clone(),
+            context.repo_factory.clone(),
+            context.config.schema_check.max_version.clone(),
+            Duration::from_secs(context.config.schema_check.check_interval_s),
+            context.readiness.clone(),
+        )
+        .expect("Failed to start schema checks");
+    }
+
+    // Users linked to CRM contacts are compared with them periodically, if configured, not by the standby
+    match context.config.crm_reconciliation {
+        Some(ref crm_reconciliation) if !context.config.read_only => {
+            start_crm_reconciliation(
+                context.db_pool.clone(),
+                context.repo_factory.clone(),
+                context.client_handle.clone(),
+                context.config.repo_limits.max_count,
+                crm_reconciliation.clone(),
+            )
+            .expect("Failed to start CRM reconciliation");
+        }
+        _ => (),
+    }
 
     let serve = Http::new()
         .serve_addr_handle(&address, &handle, move || {
             // Prepare application
             let controller = controller::ControllerImpl::new(context.clone());
             let app = Application::<Error>::new(controller);
+            let app = CachePolicyService::new(app, context.route_parser.clone(), context.config.cache_policy.clone());
+            let app = DeprecationService::new(app, context.route_parser.clone(), context.deprecated_route_usage.clone());
+            let app = ContentTypeService::new(app, context.route_parser.clone());
 
-            Ok(app)
+            Ok(AdminUiService::new(app, admin_ui.clone(), admin_ui_cpu_pool.clone()))
         })
         .unwrap_or_else(|why| {
             error!("Http Server Initialization Error: {}", why);
@@ -168,9 +382,51 @@ pub fn start_server(config: Config) {
 
     info!("Listening on http://{}, threads: {}", address, thread_count);
 
+    // Debug endpoints are served apart from the API, on a loopback address by default
+    if let Some(internal_port) = internal_port {
+        let internal_address = format!("{}:{}", internal_host, internal_port)
+            .parse()
+            .expect("Could not parse internal address");
+        let internal_serve = Http::new()
+            .serve_addr_handle(&internal_address, &handle, move || {
+                let controller = controller::internal::InternalControllerImpl::new(
+                    max_body_size,
+                    single_flights.clone(),
+                    deprecated_route_usage.clone(),
+                    rollouts.clone(),
+                    work_pool.clone(),
+                );
+                Ok(Application::<Error>::new(controller))
+            })
+            .unwrap_or_else(|why| {
+                error!("Internal Http Server Initialization Error: {}", why);
+                process::exit(1);
+            });
+
+        let handle_arc3 = handle.clone();
+        handle.spawn(
+            internal_serve
+                .for_each(move |conn| {
+                    handle_arc3.spawn(conn.map(|_| ()).map_err(|why| error!("Internal Server Error: {:?}", why)));
+                    Ok(())
+                })
+                .map_err(|_| ()),
+        );
+
+        info!("Listening for internal requests on http://{}", internal_address);
+    }
+
     core.run(tokio_signal::ctrl_c().flatten_stream().take(1u64).for_each(|()| {
         info!("Ctrl+C received. Exit");
         Ok(())
     }))
     .unwrap();
 }
+
+fn create_db_pool(database_url: &str, max_size: u32) -> DbPool {
+    let db_manager = ConnectionManager::<PgConnection>::new(database_url);
+    r2d2::Pool::builder()
+        .max_size(max_size)
+        .build(db_manager)
+        .expect("Failed to create DB connection pool")
+}
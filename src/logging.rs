@@ -0,0 +1,127 @@
+//! Console logger with the filter reloadable at runtime from the internal listener, see
+//! `controller::internal`. Filter has the syntax of `RUST_LOG`, e.g. `info,users_lib::services::jwt=debug`,
+//! and is initialized from it.
+//!
+//! Records sent to Graylog are filtered by `stq_logging` once at start, so the filter
+//! can't be changed at runtime if `graylog` is configured.
+
+use std::env;
+use std::sync::RwLock;
+
+use env_logger;
+use env_logger::filter::{self, Filter};
+use failure::Error as FailureError;
+use log::{self, LevelFilter, Log, Metadata, Record};
+
+use stq_logging::{self, GrayLogConfig};
+
+const DEFAULT_FILTER: &str = "info";
+
+lazy_static! {
+    static ref FILTER: RwLock<Option<(String, Filter)>> = RwLock::new(None);
+}
+
+/// Writes records passing the current filter with `env_logger`
+struct ReloadableLogger {
+    inner: env_logger::Logger,
+}
+
+impl Log for ReloadableLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        match FILTER.read() {
+            Ok(filter) => filter.as_ref().map(|&(_, ref filter)| filter.enabled(metadata)).unwrap_or(false),
+            Err(_) => false,
+        }
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Installs the global logger
+pub fn init(graylog: Option<&GrayLogConfig>) {
+    if graylog.is_some() {
+        stq_logging::init(graylog);
+        return;
+    }
+
+    let directives = env::var("RUST_LOG").unwrap_or_else(|_| DEFAULT_FILTER.to_string());
+    let (directives, filter) = match parse_filter(&directives) {
+        Ok(filter) => (directives, filter),
+        Err(e) => {
+            eprintln!("{}, logging with '{}'", e, DEFAULT_FILTER);
+            let filter = parse_filter(DEFAULT_FILTER).expect("Default log filter must be valid");
+            (DEFAULT_FILTER.to_string(), filter)
+        }
+    };
+    log::set_max_level(filter.filter());
+    *FILTER.write().expect("Log filter lock is poisoned") = Some((directives, filter));
+
+    // records are filtered by `ReloadableLogger`, the inner logger writes all of them
+    let inner = env_logger::Builder::new().filter(None, LevelFilter::Trace).build();
+    log::set_boxed_logger(Box::new(ReloadableLogger { inner })).expect("Logger is already set");
+}
+
+/// Current filter, `None` if it can't be changed at runtime
+pub fn current_filter() -> Option<String> {
+    FILTER
+        .read()
+        .ok()
+        .and_then(|filter| filter.as_ref().map(|&(ref directives, _)| directives.clone()))
+}
+
+/// Replaces the filter of the logger, e.g. with `warn,users_lib::services=debug`
+pub fn set_filter(directives: &str) -> Result<(), FailureError> {
+    let filter = parse_filter(directives)?;
+    let mut current = FILTER.write().map_err(|_| format_err!("Log filter lock is poisoned"))?;
+    if current.is_none() {
+        return Err(format_err!("Log filter can't be changed at runtime while logging to Graylog"));
+    }
+    log::set_max_level(filter.filter());
+    *current = Some((directives.to_string(), filter));
+    Ok(())
+}
+
+/// Parses directives separated by commas: `level`, `target` or `target=level`.
+/// `env_logger` skips invalid directives with a warning, they are rejected here instead.
+fn parse_filter(directives: &str) -> Result<Filter, FailureError> {
+    for directive in directives.split(',').map(str::trim).filter(|directive| !directive.is_empty()) {
+        let mut parts = directive.splitn(2, '=');
+        let first = parts.next().unwrap_or_default();
+        let level = match parts.next() {
+            Some(level) => level,
+            None if first.parse::<LevelFilter>().is_ok() => first,
+            None => continue,
+        };
+        if first.is_empty() || level.parse::<LevelFilter>().is_err() {
+            return Err(format_err!("Invalid log filter directive '{}'", directive));
+        }
+    }
+    Ok(filter::Builder::new().parse(directives).build())
+}
+
+#[cfg(test)]
+mod tests {
+    use log::LevelFilter;
+
+    use super::parse_filter;
+
+    #[test]
+    fn test_parse_filter() {
+        assert_eq!(parse_filter("debug").unwrap().filter(), LevelFilter::Debug);
+        assert_eq!(
+            parse_filter("warn,users_lib::services::jwt=trace").unwrap().filter(),
+            LevelFilter::Trace
+        );
+        assert!(parse_filter("users_lib::services").is_ok());
+        assert!(parse_filter("users_lib=loud").is_err());
+        assert!(parse_filter("=debug").is_err());
+    }
+}
@@ -1,17 +1,40 @@
 //! Users is a microservice responsible for authentication and managing user profiles.
 //! This create is for running the service from `users_lib`. See `users_lib` for details.
+//!
+//! `users healthcheck [--db]` checks health of the running service instead, see
+//! `users_lib::healthcheck`.
 
-extern crate stq_logging;
 extern crate users_lib;
 
+use std::env;
+use std::process;
+
+use users_lib::healthcheck::{healthcheck, HealthcheckMode};
+
 fn main() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+
     let config = users_lib::config::Config::new().expect("Can't load app config!");
 
+    if args.first().map(String::as_str) == Some("healthcheck") {
+        let mode = HealthcheckMode::from_arg(args.get(1).map(String::as_str)).unwrap_or_else(|| {
+            eprintln!("Usage: users healthcheck [--db]");
+            process::exit(1);
+        });
+        match healthcheck(&config, mode) {
+            Ok(()) => process::exit(0),
+            Err(e) => {
+                eprintln!("Unhealthy: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+
     // Prepare sentry integration
     let _sentry = users_lib::sentry_integration::init(config.sentry.as_ref());
 
-    // Prepare logger
-    stq_logging::init(config.graylog.as_ref());
+    // Prepare logger, its filter can be changed by the internal listener
+    users_lib::logging::init(config.graylog.as_ref());
 
     users_lib::start_server(config);
 }
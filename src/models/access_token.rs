@@ -0,0 +1,83 @@
+//! Models for personal access tokens. Users create named tokens limited to a set of scopes
+//! for API access without their password. Tokens are shown once and stored hashed.
+use std::time::SystemTime;
+
+use uuid::Uuid;
+use validator::Validate;
+
+use stq_types::UserId;
+
+use schema::access_tokens;
+
+/// Prefix telling personal access tokens apart from JWTs in the `Authorization` header
+pub const ACCESS_TOKEN_PREFIX: &str = "pat_";
+
+#[derive(Clone, Debug, Serialize, Deserialize, Queryable)]
+pub struct AccessToken {
+    pub id: Uuid,
+    pub user_id: UserId,
+    pub name: String,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<SystemTime>,
+    pub last_used_at: Option<SystemTime>,
+    pub created_at: SystemTime,
+}
+
+impl AccessToken {
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires_at.map(|expires_at| expires_at <= now).unwrap_or(false)
+    }
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "access_tokens"]
+pub struct NewAccessToken {
+    pub id: Uuid,
+    pub user_id: UserId,
+    pub name: String,
+    pub token_hash: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<SystemTime>,
+}
+
+/// Payload for creating personal access token of current user, token never expires
+/// if `expiration_s` is not set
+#[derive(Clone, Debug, Serialize, Deserialize, Validate)]
+pub struct NewAccessTokenPayload {
+    #[validate(length(min = "1", max = "64", message = "Name should be between 1 and 64 symbols"))]
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub expiration_s: Option<u64>,
+}
+
+/// Created token, the only time the token itself is returned
+#[derive(Clone, Debug, Serialize)]
+pub struct CreatedAccessToken {
+    #[serde(flatten)]
+    pub access_token: AccessToken,
+    pub token: String,
+}
+
+/// Introspection request received on `POST /oauth/introspect` (RFC 7662). The caller, e.g. the gateway,
+/// authenticates as a first-party confidential client
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IntrospectionRequest {
+    pub token: String,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+/// Introspection response, only `active` is set for unknown, expired and revoked tokens
+#[derive(Clone, Debug, Serialize, Deserialize, Default, PartialEq)]
+pub struct Introspection {
+    pub active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<UserId>,
+    /// Space-separated scopes of the token
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exp: Option<i64>,
+}
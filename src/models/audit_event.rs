@@ -0,0 +1,111 @@
+//! Models for audit log. Events record changes made to users, `actor_id` is empty
+//! if the change was made by the service itself.
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::VarChar;
+use failure::Error as FailureError;
+use serde_json;
+
+use stq_types::UserId;
+
+use schema::audit_log;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(rename_all = "snake_case")]
+#[sql_type = "VarChar"]
+pub enum AuditAction {
+    RoleAssigned,
+    RoleExpired,
+    RoleRequested,
+    RoleRequestApproved,
+    AuthDataExported,
+    AuthDataImported,
+    BreakGlassActivated,
+    LoginGeoRestricted,
+    ProviderUnlinked,
+    TemporaryPasswordSet,
+    UserImported,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            AuditAction::RoleAssigned => "role_assigned",
+            AuditAction::RoleExpired => "role_expired",
+            AuditAction::RoleRequested => "role_requested",
+            AuditAction::RoleRequestApproved => "role_request_approved",
+            AuditAction::AuthDataExported => "auth_data_exported",
+            AuditAction::AuthDataImported => "auth_data_imported",
+            AuditAction::BreakGlassActivated => "break_glass_activated",
+            AuditAction::LoginGeoRestricted => "login_geo_restricted",
+            AuditAction::ProviderUnlinked => "provider_unlinked",
+            AuditAction::TemporaryPasswordSet => "temporary_password_set",
+            AuditAction::UserImported => "user_imported",
+        }
+    }
+}
+
+impl fmt::Display for AuditAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for AuditAction {
+    type Err = FailureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "role_assigned" => Ok(AuditAction::RoleAssigned),
+            "role_expired" => Ok(AuditAction::RoleExpired),
+            "role_requested" => Ok(AuditAction::RoleRequested),
+            "role_request_approved" => Ok(AuditAction::RoleRequestApproved),
+            "auth_data_exported" => Ok(AuditAction::AuthDataExported),
+            "auth_data_imported" => Ok(AuditAction::AuthDataImported),
+            "break_glass_activated" => Ok(AuditAction::BreakGlassActivated),
+            "login_geo_restricted" => Ok(AuditAction::LoginGeoRestricted),
+            "provider_unlinked" => Ok(AuditAction::ProviderUnlinked),
+            "temporary_password_set" => Ok(AuditAction::TemporaryPasswordSet),
+            "user_imported" => Ok(AuditAction::UserImported),
+            _ => Err(format_err!("Unknown audit action '{}'", s)),
+        }
+    }
+}
+
+impl ToSql<VarChar, Pg> for AuditAction {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        ToSql::<VarChar, Pg>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<VarChar, Pg> for AuditAction {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        let action: String = FromSql::<VarChar, Pg>::from_sql(bytes)?;
+        action.parse().map_err(|e: FailureError| e.to_string().into())
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Queryable)]
+pub struct AuditEvent {
+    pub id: i32,
+    pub user_id: UserId,
+    pub actor_id: Option<UserId>,
+    pub action: AuditAction,
+    pub data: Option<serde_json::Value>,
+    pub created_at: SystemTime,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "audit_log"]
+pub struct NewAuditEvent {
+    pub user_id: UserId,
+    pub actor_id: Option<UserId>,
+    pub action: AuditAction,
+    pub data: Option<serde_json::Value>,
+}
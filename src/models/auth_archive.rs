@@ -0,0 +1,72 @@
+//! Models for exports and imports of auth data, used to move identities and roles
+//! between environments or to restore a tenant
+use std::time::SystemTime;
+
+use serde_json;
+
+use stq_types::{UserId, UsersRole};
+
+use models::{Identity, UserRole};
+
+/// Version of archive contents, bumped on incompatible changes
+pub const AUTH_ARCHIVE_VERSION: u32 = 1;
+
+/// Encrypted archive of auth data
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuthArchive {
+    pub archive: String,
+}
+
+/// Contents of the archive, identities keep password hashes
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuthArchiveData {
+    pub version: u32,
+    pub created_at: SystemTime,
+    pub identities: Vec<Identity>,
+    pub roles: Vec<ArchivedRole>,
+}
+
+/// Role of a user as it is archived, role ids are generated again on import
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ArchivedRole {
+    pub user_id: UserId,
+    pub name: UsersRole,
+    pub data: Option<serde_json::Value>,
+    #[serde(default)]
+    pub expires_at: Option<SystemTime>,
+}
+
+impl From<UserRole> for ArchivedRole {
+    fn from(role: UserRole) -> Self {
+        Self {
+            user_id: role.user_id,
+            name: role.name,
+            data: role.data,
+            expires_at: role.expires_at,
+        }
+    }
+}
+
+/// Numbers of imported rows, rows of users missing in this environment
+/// and rows already present are skipped. Sensitive roles are requested
+/// instead of imported, another admin must approve them
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct AuthArchiveImport {
+    pub identities_imported: usize,
+    pub identities_skipped: usize,
+    pub roles_imported: usize,
+    pub roles_skipped: usize,
+    pub roles_requested: usize,
+}
+
+impl AuthArchiveImport {
+    pub fn add(self, other: AuthArchiveImport) -> Self {
+        Self {
+            identities_imported: self.identities_imported + other.identities_imported,
+            identities_skipped: self.identities_skipped + other.identities_skipped,
+            roles_imported: self.roles_imported + other.roles_imported,
+            roles_skipped: self.roles_skipped + other.roles_skipped,
+            roles_requested: self.roles_requested + other.roles_requested,
+        }
+    }
+}
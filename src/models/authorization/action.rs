@@ -4,7 +4,8 @@ use std::fmt;
 // All gives all permissions.
 // Index - list resources, Read - read resource with id,
 // Write - Update or delete resource with id.
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Action {
     All,
     Read,
@@ -14,6 +15,12 @@ pub enum Action {
     Block,
 }
 
+impl Action {
+    pub fn all() -> Self {
+        Action::All
+    }
+}
+
 impl fmt::Display for Action {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
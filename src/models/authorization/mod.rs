@@ -6,6 +6,6 @@ pub mod resource;
 pub mod scope;
 
 pub use self::action::Action;
-pub use self::permission::Permission;
+pub use self::permission::{Condition, Permission, PolicyRule};
 pub use self::resource::Resource;
 pub use self::scope::Scope;
@@ -1,9 +1,57 @@
-//! Permission is a tuple for describing permisssions
+//! Permission is a tuple for describing permisssions, optionally narrowed by conditions on
+//! attributes of the resource. Permissions of roles are granted by policy rules, see `repos::acl::policies`.
+
+use serde_json::Value;
+
+use stq_types::UsersRole;
 
 use models::{Action, Resource, Scope};
 
+#[derive(Clone, Debug)]
 pub struct Permission {
     pub resource: Resource,
     pub action: Action,
     pub scope: Scope,
+    /// All conditions must hold for the resource, permissions with conditions are never granted without one
+    pub conditions: Vec<Condition>,
+}
+
+/// Condition on a top-level field of the resource as it is serialized in responses
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Condition {
+    pub field: String,
+    /// Values the field may have
+    pub one_of: Vec<Value>,
+}
+
+impl Condition {
+    pub fn holds(&self, attributes: &Value) -> bool {
+        attributes
+            .get(&self.field)
+            .map_or(false, |value| self.one_of.iter().any(|allowed| allowed == value))
+    }
+}
+
+/// Rule granting the permission to the role, loaded from `acl/default_policies.json` and `acl.rules` config
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PolicyRule {
+    pub role: UsersRole,
+    pub resource: Resource,
+    #[serde(default = "Action::all")]
+    pub action: Action,
+    #[serde(default = "Scope::all")]
+    pub scope: Scope,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+}
+
+impl From<PolicyRule> for Permission {
+    fn from(rule: PolicyRule) -> Self {
+        Permission {
+            resource: rule.resource,
+            action: rule.action,
+            scope: rule.scope,
+            conditions: rule.conditions,
+        }
+    }
 }
@@ -1,10 +1,33 @@
 //! Enum for resources available in ACLs
 use std::fmt;
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Resource {
     Users,
     UserRoles,
+    UserTags,
+    Jobs,
+    Stats,
+    DeletionRequests,
+    TrustedContacts,
+    SecurityAnswers,
+    AuditLog,
+    LoginHistory,
+    Invites,
+    Waitlist,
+    ProfilePrompts,
+    ChildAccounts,
+    AccessTokens,
+    #[serde(rename = "oauth_consents")]
+    OAuthConsents,
+    RoleRequests,
+    Devices,
+    RoleAccessPolicies,
+    Identities,
+    LegacySyncStates,
+    CrmDrifts,
+    ClientBrandings,
 }
 
 impl fmt::Display for Resource {
@@ -12,6 +35,27 @@ impl fmt::Display for Resource {
         match *self {
             Resource::Users => write!(f, "users"),
             Resource::UserRoles => write!(f, "user roles"),
+            Resource::UserTags => write!(f, "user tags"),
+            Resource::Jobs => write!(f, "jobs"),
+            Resource::Stats => write!(f, "stats"),
+            Resource::DeletionRequests => write!(f, "deletion requests"),
+            Resource::TrustedContacts => write!(f, "trusted contacts"),
+            Resource::SecurityAnswers => write!(f, "security answers"),
+            Resource::AuditLog => write!(f, "audit log"),
+            Resource::LoginHistory => write!(f, "login history"),
+            Resource::Invites => write!(f, "invites"),
+            Resource::Waitlist => write!(f, "waitlist"),
+            Resource::ProfilePrompts => write!(f, "profile prompts"),
+            Resource::ChildAccounts => write!(f, "child accounts"),
+            Resource::AccessTokens => write!(f, "access tokens"),
+            Resource::OAuthConsents => write!(f, "oauth consents"),
+            Resource::RoleRequests => write!(f, "role requests"),
+            Resource::Devices => write!(f, "devices"),
+            Resource::RoleAccessPolicies => write!(f, "role access policies"),
+            Resource::Identities => write!(f, "identities"),
+            Resource::LegacySyncStates => write!(f, "legacy sync states"),
+            Resource::CrmDrifts => write!(f, "crm drifts"),
+            Resource::ClientBrandings => write!(f, "client brandings"),
         }
     }
 }
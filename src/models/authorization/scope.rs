@@ -1,6 +1,7 @@
 //! Enum for scopes available in ACLs
 
-#[derive(PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Scope {
     /// Resource with any id
     All,
@@ -10,3 +11,9 @@ pub enum Scope {
     /// means that a user can only list resources that he owns.
     Owned,
 }
+
+impl Scope {
+    pub fn all() -> Self {
+        Scope::All
+    }
+}
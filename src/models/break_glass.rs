@@ -0,0 +1,22 @@
+//! Models for break-glass emergency access
+
+/// Subject of break-glass activation tokens
+pub const BREAK_GLASS_SUBJECT: &str = "break_glass";
+
+/// Payload of break-glass activation, the token is signed offline by the break-glass key
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BreakGlassActivation {
+    pub token: String,
+}
+
+/// Claims of break-glass activation token
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BreakGlassClaims {
+    pub sub: String,
+    /// Unique id of the token, every token activates the account once
+    pub jti: String,
+    pub iat: i64,
+    pub exp: i64,
+    /// Incident the access is needed for, recorded in the audit log and paged
+    pub reason: String,
+}
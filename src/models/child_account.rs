@@ -0,0 +1,69 @@
+//! Models for child accounts. Parents create limited sub-accounts for their children,
+//! view their profiles and reset their passwords. Links are kept on the primary shard.
+use std::time::SystemTime;
+
+use chrono::NaiveDate;
+use validator::Validate;
+
+use stq_static_resources::Provider;
+use stq_types::UserId;
+
+use models::unicode::validate_email;
+use models::{NewIdentity, NewUser};
+use schema::child_accounts;
+
+#[derive(Clone, Debug, Serialize, Deserialize, Queryable, PartialEq)]
+pub struct ChildAccount {
+    pub child_id: UserId,
+    pub parent_id: UserId,
+    pub created_at: SystemTime,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "child_accounts"]
+pub struct NewChildAccount {
+    pub child_id: UserId,
+    pub parent_id: UserId,
+}
+
+/// Payload for creating child account of current user
+#[derive(Clone, Debug, Serialize, Deserialize, Validate)]
+pub struct NewChildAccountPayload {
+    #[validate(custom = "validate_email")]
+    pub email: String,
+    #[validate(length(min = "8", max = "30", message = "Password should be between 8 and 30 symbols"))]
+    pub password: String,
+    #[validate(length(min = "1", message = "First name must not be empty"))]
+    pub first_name: Option<String>,
+    #[validate(length(min = "1", message = "Last name must not be empty"))]
+    pub last_name: Option<String>,
+    pub birthdate: Option<NaiveDate>,
+    /// Required in invite-only mode
+    pub invite_code: Option<String>,
+}
+
+impl NewChildAccountPayload {
+    /// Identity and profile of the child, children always sign in with email and password
+    pub fn into_identity(self, saga_id: String) -> (NewIdentity, NewUser) {
+        let identity = NewIdentity {
+            email: self.email,
+            password: Some(self.password),
+            provider: Provider::Email,
+            saga_id,
+        };
+        let user = NewUser {
+            first_name: self.first_name,
+            last_name: self.last_name,
+            birthdate: self.birthdate,
+            ..NewUser::from(identity.clone())
+        };
+        (identity, user)
+    }
+}
+
+/// Payload for resetting password of the child by the parent
+#[derive(Clone, Debug, Serialize, Deserialize, Validate)]
+pub struct ChildPasswordReset {
+    #[validate(length(min = "8", max = "30", message = "Password should be between 8 and 30 symbols"))]
+    pub new_password: String,
+}
@@ -0,0 +1,93 @@
+//! Models for registered clients (storefront, mobile app, admin console, etc.)
+use std::time::SystemTime;
+
+use chrono::Utc;
+use validator::Validate;
+
+use stq_types::UserId;
+
+use models::unicode::validate_email;
+use schema::client_brandings;
+
+/// Registered client. Tokens issued for a client carry its audience and have its TTLs.
+#[derive(Clone, Debug, Serialize, Deserialize, Queryable, PartialEq)]
+pub struct Client {
+    pub id: String,
+    pub name: String,
+    pub audience: String,
+    pub jwt_expiration_s: i64,
+    pub refresh_timeout_s: i64,
+    pub created_at: SystemTime,
+    pub updated_at: SystemTime,
+    /// Hash of the client secret, present for confidential clients only
+    #[serde(skip_serializing)]
+    pub secret_hash: Option<String>,
+    /// User the client acts as when authenticated with `client_credentials` grant
+    pub service_user_id: Option<UserId>,
+    /// Third-party clients get tokens limited to scopes the user consented to
+    pub is_third_party: bool,
+    /// Refresh tokens of the client are issued as `httpOnly` cookie instead of response body
+    pub refresh_token_cookie: bool,
+    /// Users of the client get server-side sessions referenced by cookie instead of tokens
+    pub session_mode: bool,
+    /// Expiration of remember-me tokens of the client, `tokens.remember_me_expiration_s` if absent
+    pub remember_me_expiration_s: Option<i64>,
+    /// Time remember-me tokens of the client can be refreshed after expiration,
+    /// `tokens.remember_me_refresh_timeout_s` if absent
+    pub remember_me_refresh_timeout_s: Option<i64>,
+}
+
+impl Client {
+    /// Expiration timestamp for a token issued now for this client
+    pub fn token_expiration(&self) -> i64 {
+        Utc::now().timestamp() + self.jwt_expiration_s
+    }
+
+    /// Confidential clients have a secret and must authenticate with it
+    pub fn is_confidential(&self) -> bool {
+        self.secret_hash.is_some()
+    }
+}
+
+/// Brand of the marketplace the client belongs to, shown in emails and on consent screens
+#[derive(Clone, Debug, Serialize, Deserialize, Queryable, PartialEq)]
+pub struct ClientBranding {
+    pub client_id: String,
+    pub app_name: String,
+    pub support_email: String,
+    pub logo_url: Option<String>,
+    pub created_at: SystemTime,
+    pub updated_at: SystemTime,
+}
+
+#[derive(Clone, Debug, Insertable, AsChangeset)]
+#[table_name = "client_brandings"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct NewClientBranding {
+    pub client_id: String,
+    pub app_name: String,
+    pub support_email: String,
+    pub logo_url: Option<String>,
+}
+
+/// Payload for setting branding of the client
+#[derive(Clone, Debug, Serialize, Deserialize, Validate)]
+pub struct ClientBrandingPayload {
+    #[validate(length(min = "1", message = "App name must not be empty"))]
+    pub app_name: String,
+    #[validate(custom = "validate_email")]
+    pub support_email: String,
+    #[validate(url(message = "Invalid logo url"))]
+    pub logo_url: Option<String>,
+}
+
+impl ClientBrandingPayload {
+    pub fn into_new(self, client_id: String) -> NewClientBranding {
+        NewClientBranding {
+            client_id,
+            app_name: self.app_name,
+            support_email: self.support_email,
+            logo_url: self.logo_url,
+        }
+    }
+}
@@ -0,0 +1,12 @@
+//! Models for countries reference data, ISO 3166 countries with phone prefixes
+use stq_types::Alpha3;
+
+/// Country, `alpha3` is the code users and addresses refer to
+#[derive(Clone, Debug, Serialize, Deserialize, Queryable, PartialEq)]
+pub struct Country {
+    pub alpha3: Alpha3,
+    pub alpha2: String,
+    pub name: String,
+    /// International calling code, e.g. `+7`, absent for uninhabited territories
+    pub phone_prefix: Option<String>,
+}
@@ -0,0 +1,170 @@
+//! Models for reconciliation of users against their CRM contacts. Key fields of every user
+//! linked to a contact are compared with the contact, differences are reported as drifts of
+//! the reconciliation job and optionally corrected in CRM.
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::VarChar;
+use failure::Error as FailureError;
+use uuid::Uuid;
+
+use stq_types::{EmarsysId, UserId};
+
+use models::{Job, User};
+use schema::crm_drifts;
+
+/// Key fields of the user kept in CRM
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(rename_all = "snake_case")]
+#[sql_type = "VarChar"]
+pub enum CrmField {
+    Email,
+    FirstName,
+    LastName,
+    Phone,
+}
+
+impl CrmField {
+    pub fn all() -> &'static [CrmField] {
+        &[CrmField::Email, CrmField::FirstName, CrmField::LastName, CrmField::Phone]
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            CrmField::Email => "email",
+            CrmField::FirstName => "first_name",
+            CrmField::LastName => "last_name",
+            CrmField::Phone => "phone",
+        }
+    }
+
+    /// Value of the field of the user, empty values are treated as missing
+    pub fn user_value(&self, user: &User) -> Option<String> {
+        let value = match *self {
+            CrmField::Email => Some(&user.email),
+            CrmField::FirstName => user.first_name.as_ref(),
+            CrmField::LastName => user.last_name.as_ref(),
+            CrmField::Phone => user.phone.as_ref(),
+        };
+        non_empty(value)
+    }
+
+    /// Value of the field of the contact, empty values are treated as missing
+    pub fn contact_value(&self, contact: &CrmContact) -> Option<String> {
+        let value = match *self {
+            CrmField::Email => contact.email.as_ref(),
+            CrmField::FirstName => contact.first_name.as_ref(),
+            CrmField::LastName => contact.last_name.as_ref(),
+            CrmField::Phone => contact.phone.as_ref(),
+        };
+        non_empty(value)
+    }
+
+    /// Sets the field of the contact
+    pub fn set_contact_value(&self, contact: &mut CrmContact, value: Option<String>) {
+        match *self {
+            CrmField::Email => contact.email = value,
+            CrmField::FirstName => contact.first_name = value,
+            CrmField::LastName => contact.last_name = value,
+            CrmField::Phone => contact.phone = value,
+        }
+    }
+
+    /// Whether values differ, e-mails are compared case-insensitively
+    pub fn is_drift(&self, user_value: &Option<String>, crm_value: &Option<String>) -> bool {
+        match (*self, user_value, crm_value) {
+            (CrmField::Email, &Some(ref user_value), &Some(ref crm_value)) => user_value.to_lowercase() != crm_value.to_lowercase(),
+            _ => user_value != crm_value,
+        }
+    }
+}
+
+fn non_empty(value: Option<&String>) -> Option<String> {
+    value.map(|value| value.trim()).filter(|value| !value.is_empty()).map(String::from)
+}
+
+impl fmt::Display for CrmField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for CrmField {
+    type Err = FailureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "email" => Ok(CrmField::Email),
+            "first_name" => Ok(CrmField::FirstName),
+            "last_name" => Ok(CrmField::LastName),
+            "phone" => Ok(CrmField::Phone),
+            _ => Err(format_err!("Unknown CRM field '{}'", s)),
+        }
+    }
+}
+
+impl ToSql<VarChar, Pg> for CrmField {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        ToSql::<VarChar, Pg>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<VarChar, Pg> for CrmField {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        let field: String = FromSql::<VarChar, Pg>::from_sql(bytes)?;
+        field.parse().map_err(|e: FailureError| e.to_string().into())
+    }
+}
+
+/// Contact of CRM API, only key fields are read and written
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CrmContact {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub phone: Option<String>,
+}
+
+/// Difference of the field between the user and its CRM contact
+#[derive(Clone, Debug, Serialize, Queryable)]
+pub struct CrmDrift {
+    pub id: i32,
+    pub job_id: Uuid,
+    pub user_id: UserId,
+    pub emarsys_id: EmarsysId,
+    pub field: CrmField,
+    pub user_value: Option<String>,
+    pub crm_value: Option<String>,
+    /// Contact was updated with the value of the user
+    pub corrected: bool,
+    pub created_at: SystemTime,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "crm_drifts"]
+pub struct NewCrmDrift {
+    pub job_id: Uuid,
+    pub user_id: UserId,
+    pub emarsys_id: EmarsysId,
+    pub field: CrmField,
+    pub user_value: Option<String>,
+    pub crm_value: Option<String>,
+    pub corrected: bool,
+}
+
+/// Drifts found by the last reconciliation
+#[derive(Clone, Debug, Serialize)]
+pub struct CrmDriftReport {
+    /// Last reconciliation job, none if reconciliation was never run
+    pub job: Option<Job>,
+    pub drifts: Vec<CrmDrift>,
+}
@@ -0,0 +1,164 @@
+//! Models for two-phase deletion of users. Deletion is requested first, dependent services
+//! (e.g. orders, billing) confirm it or veto it if the user has open obligations.
+//! The user is purged by a job once all of them confirmed or the deadline passed.
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::VarChar;
+use failure::Error as FailureError;
+use uuid::Uuid;
+use validator::Validate;
+
+use stq_types::UserId;
+
+use schema::{deletion_confirmations, deletion_requests};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(rename_all = "lowercase")]
+#[sql_type = "VarChar"]
+pub enum DeletionState {
+    /// Waiting for confirmations of dependent services
+    Pending,
+    /// One of the services has vetoed the deletion, it can be requested again later
+    Vetoed,
+    /// Purge job is started
+    Approved,
+    /// User is purged
+    Done,
+}
+
+impl DeletionState {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            DeletionState::Pending => "pending",
+            DeletionState::Vetoed => "vetoed",
+            DeletionState::Approved => "approved",
+            DeletionState::Done => "done",
+        }
+    }
+}
+
+impl fmt::Display for DeletionState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for DeletionState {
+    type Err = FailureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(DeletionState::Pending),
+            "vetoed" => Ok(DeletionState::Vetoed),
+            "approved" => Ok(DeletionState::Approved),
+            "done" => Ok(DeletionState::Done),
+            _ => Err(format_err!("Unknown deletion state '{}'", s)),
+        }
+    }
+}
+
+impl ToSql<VarChar, Pg> for DeletionState {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        ToSql::<VarChar, Pg>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<VarChar, Pg> for DeletionState {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        let state: String = FromSql::<VarChar, Pg>::from_sql(bytes)?;
+        state.parse().map_err(|e: FailureError| e.to_string().into())
+    }
+}
+
+/// Request to delete the user, services that did not answer until `deadline` are considered to confirm it.
+/// The deletion is never approved before `not_before`, even if all the services confirmed it.
+/// `job_id` is the id of the purge job, once the deletion is approved.
+#[derive(Clone, Debug, Serialize, Queryable)]
+pub struct DeletionRequest {
+    pub user_id: UserId,
+    pub state: DeletionState,
+    pub requested_by: Option<UserId>,
+    pub deadline: SystemTime,
+    pub job_id: Option<Uuid>,
+    pub created_at: SystemTime,
+    pub updated_at: SystemTime,
+    pub not_before: SystemTime,
+}
+
+#[derive(Clone, Debug, Insertable, AsChangeset)]
+#[table_name = "deletion_requests"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct NewDeletionRequest {
+    pub user_id: UserId,
+    pub state: DeletionState,
+    pub requested_by: Option<UserId>,
+    pub deadline: SystemTime,
+    pub job_id: Option<Uuid>,
+    pub not_before: SystemTime,
+}
+
+impl NewDeletionRequest {
+    pub fn new(user_id: UserId, requested_by: Option<UserId>, not_before: SystemTime, deadline: SystemTime) -> Self {
+        Self {
+            user_id,
+            state: DeletionState::Pending,
+            requested_by,
+            deadline,
+            job_id: None,
+            not_before,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, AsChangeset)]
+#[table_name = "deletion_requests"]
+pub struct UpdateDeletionRequest {
+    pub state: Option<DeletionState>,
+    pub job_id: Option<Uuid>,
+}
+
+/// Answer of a dependent service to the deletion request
+#[derive(Clone, Debug, Serialize, Queryable)]
+pub struct DeletionConfirmation {
+    pub user_id: UserId,
+    pub service: String,
+    pub approved: bool,
+    pub reason: Option<String>,
+    pub created_at: SystemTime,
+}
+
+#[derive(Clone, Debug, Insertable, AsChangeset)]
+#[table_name = "deletion_confirmations"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct NewDeletionConfirmation {
+    pub user_id: UserId,
+    pub service: String,
+    pub approved: bool,
+    pub reason: Option<String>,
+}
+
+/// Payload of `POST /users/<user_id>/delete_request/confirmations`,
+/// `approved: false` vetoes the deletion, e.g. because of open orders
+#[derive(Clone, Debug, Deserialize, Validate)]
+pub struct DeletionConfirmationPayload {
+    #[validate(length(min = "1", message = "Service must not be empty"))]
+    pub service: String,
+    pub approved: bool,
+    pub reason: Option<String>,
+}
+
+/// Deletion request with answers of dependent services,
+/// `awaiting` are the services which have not answered yet
+#[derive(Clone, Debug, Serialize)]
+pub struct DeletionStatus {
+    #[serde(flatten)]
+    pub request: DeletionRequest,
+    pub confirmations: Vec<DeletionConfirmation>,
+    pub awaiting: Vec<String>,
+}
@@ -0,0 +1,41 @@
+//! Models for health of downstream services, reported as a dependency graph rooted at the users
+//! service. Dependencies reporting their health in the same format are expanded into subgraphs.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Up,
+    /// Up, but some of its non-critical dependencies are not
+    Degraded,
+    Down,
+}
+
+/// Node of the dependency graph
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DependencyHealth {
+    pub name: String,
+    pub status: HealthStatus,
+    /// Flows of the dependent service can't be run without the dependency
+    #[serde(default)]
+    pub critical: bool,
+    /// Response time of the health check, none if it was not checked directly
+    #[serde(default)]
+    pub latency_ms: Option<u64>,
+    #[serde(default)]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub dependencies: Vec<DependencyHealth>,
+}
+
+impl DependencyHealth {
+    /// Status of the service by its own readiness and statuses of its dependencies
+    pub fn aggregate_status(ready: bool, dependencies: &[DependencyHealth]) -> HealthStatus {
+        if !ready || dependencies.iter().any(|dep| dep.critical && dep.status == HealthStatus::Down) {
+            HealthStatus::Down
+        } else if dependencies.iter().any(|dep| dep.status != HealthStatus::Up) {
+            HealthStatus::Degraded
+        } else {
+            HealthStatus::Up
+        }
+    }
+}
@@ -0,0 +1,47 @@
+//! Models for known devices of users. Devices are told apart by fingerprints clients send in
+//! `X-Device-Fingerprint` header and are recorded on every login. Trusted devices get a signed
+//! cookie, suspicious logins from them skip step-up authentication until trust expires.
+use std::time::SystemTime;
+
+use uuid::Uuid;
+
+use stq_types::UserId;
+
+use schema::devices;
+
+#[derive(Clone, Debug, Serialize, Deserialize, Queryable)]
+pub struct Device {
+    pub id: Uuid,
+    pub user_id: UserId,
+    #[serde(skip_serializing)]
+    pub fingerprint: String,
+    /// User agent of the last login from the device
+    pub name: Option<String>,
+    /// Absent if the device is not trusted
+    pub trusted_until: Option<SystemTime>,
+    pub first_seen_at: SystemTime,
+    pub last_seen_at: SystemTime,
+}
+
+impl Device {
+    pub fn is_trusted(&self, now: SystemTime) -> bool {
+        self.trusted_until.map(|trusted_until| trusted_until > now).unwrap_or(false)
+    }
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "devices"]
+pub struct NewDevice {
+    pub id: Uuid,
+    pub user_id: UserId,
+    pub fingerprint: String,
+    pub name: Option<String>,
+}
+
+/// Trusted device, gateway sends `set_cookie` in `Set-Cookie` header and removes it from the body
+#[derive(Clone, Debug, Serialize)]
+pub struct TrustedDevice {
+    #[serde(flatten)]
+    pub device: Device,
+    pub set_cookie: String,
+}
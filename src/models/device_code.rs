@@ -0,0 +1,78 @@
+//! Models for OAuth2 device authorization grant (RFC 8628)
+use std::time::{Duration, SystemTime};
+
+use rand;
+use rand::Rng;
+use uuid::Uuid;
+
+use stq_types::UserId;
+
+use schema::device_codes;
+
+/// Alphabet for user codes, without vowels and easily confused characters
+const USER_CODE_ALPHABET: &'static [u8] = b"BCDFGHJKLMNPQRSTVWXZ";
+const USER_CODE_LENGTH: usize = 8;
+
+/// Device authorization issued to a device, approved by a logged in user via `POST /device`
+#[derive(Clone, Debug, Serialize, Deserialize, Queryable)]
+pub struct DeviceCode {
+    pub device_code: String,
+    pub user_code: String,
+    pub client_id: String,
+    pub user_id: Option<UserId>,
+    pub expires_at: SystemTime,
+    pub created_at: SystemTime,
+    pub updated_at: SystemTime,
+    /// Space-separated scopes requested by the client
+    pub scope: Option<String>,
+    /// Last poll of the token endpoint while the code was pending
+    pub last_polled_at: Option<SystemTime>,
+}
+
+impl DeviceCode {
+    pub fn is_expired(&self) -> bool {
+        self.expires_at < SystemTime::now()
+    }
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "device_codes"]
+pub struct NewDeviceCode {
+    pub device_code: String,
+    pub user_code: String,
+    pub client_id: String,
+    pub expires_at: SystemTime,
+    pub scope: Option<String>,
+}
+
+impl NewDeviceCode {
+    pub fn new(client_id: String, scope: Option<String>, expires_in: Duration) -> Self {
+        let mut rng = rand::thread_rng();
+        let user_code = (0..USER_CODE_LENGTH)
+            .map(|_| *rng.choose(USER_CODE_ALPHABET).unwrap() as char)
+            .collect::<String>();
+
+        Self {
+            device_code: format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple()),
+            user_code: format_user_code(&user_code),
+            client_id,
+            expires_at: SystemTime::now() + expires_in,
+            scope,
+        }
+    }
+}
+
+/// Brings user input to the stored `XXXX-XXXX` form, ignoring case, spaces and dashes
+pub fn format_user_code(user_code: &str) -> String {
+    let code = user_code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect::<String>();
+
+    if code.len() == USER_CODE_LENGTH {
+        format!("{}-{}", &code[..USER_CODE_LENGTH / 2], &code[USER_CODE_LENGTH / 2..])
+    } else {
+        code
+    }
+}
@@ -0,0 +1,13 @@
+//! Models for schemas of events emitted to other systems, see `events/schemas`
+use serde_json;
+
+/// Registered schema of an event, payloads of the event are validated against it before
+/// they are emitted
+#[derive(Clone, Debug, Serialize)]
+pub struct EventSchema {
+    pub event: &'static str,
+    /// Bumped on incompatible changes of the schema
+    pub version: u32,
+    /// JSON Schema draft-07
+    pub schema: serde_json::Value,
+}
@@ -0,0 +1,113 @@
+//! Models for registration funnel. Every user passes the steps in order,
+//! each step is recorded once per user with the time it was first reached.
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use chrono::NaiveDate;
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::VarChar;
+use failure::Error as FailureError;
+
+use stq_types::UserId;
+
+use schema::funnel_events;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(rename_all = "snake_case")]
+#[sql_type = "VarChar"]
+pub enum FunnelStep {
+    RegistrationSubmitted,
+    EmailSent,
+    EmailVerified,
+    FirstLogin,
+}
+
+impl FunnelStep {
+    /// All steps in the order users pass them
+    pub fn all() -> Vec<FunnelStep> {
+        vec![
+            FunnelStep::RegistrationSubmitted,
+            FunnelStep::EmailSent,
+            FunnelStep::EmailVerified,
+            FunnelStep::FirstLogin,
+        ]
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            FunnelStep::RegistrationSubmitted => "registration_submitted",
+            FunnelStep::EmailSent => "email_sent",
+            FunnelStep::EmailVerified => "email_verified",
+            FunnelStep::FirstLogin => "first_login",
+        }
+    }
+}
+
+impl fmt::Display for FunnelStep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for FunnelStep {
+    type Err = FailureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "registration_submitted" => Ok(FunnelStep::RegistrationSubmitted),
+            "email_sent" => Ok(FunnelStep::EmailSent),
+            "email_verified" => Ok(FunnelStep::EmailVerified),
+            "first_login" => Ok(FunnelStep::FirstLogin),
+            _ => Err(format_err!("Unknown funnel step '{}'", s)),
+        }
+    }
+}
+
+impl ToSql<VarChar, Pg> for FunnelStep {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        ToSql::<VarChar, Pg>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<VarChar, Pg> for FunnelStep {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        let step: String = FromSql::<VarChar, Pg>::from_sql(bytes)?;
+        step.parse().map_err(|e: FailureError| e.to_string().into())
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Queryable)]
+pub struct FunnelEvent {
+    pub step: FunnelStep,
+    pub created_at: SystemTime,
+    pub user_id: UserId,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "funnel_events"]
+pub struct NewFunnelEvent {
+    pub user_id: UserId,
+    pub step: FunnelStep,
+}
+
+/// Number of users of the cohort who reached the step. `conversion` is the share
+/// of users who registered, `step_conversion` is the share of users who reached the previous step.
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct FunnelStepStats {
+    pub step: FunnelStep,
+    pub users: i64,
+    pub conversion: f64,
+    pub step_conversion: f64,
+}
+
+/// Funnel of users who submitted registration from `from` to `to` inclusive
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct FunnelStats {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub steps: Vec<FunnelStepStats>,
+}
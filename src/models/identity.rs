@@ -7,6 +7,8 @@ use validator::Validate;
 use stq_static_resources::Provider;
 use stq_types::UserId;
 
+use models::unicode::validate_email;
+use models::PasswordHashFormat;
 use schema::identities;
 
 /// Payload for creating identity for users
@@ -14,18 +16,26 @@ use schema::identities;
 #[table_name = "identities"]
 pub struct Identity {
     pub user_id: UserId,
-    #[validate(email(code = "not_valid", message = "Invalid email format"))]
+    #[validate(custom = "validate_email")]
     pub email: String,
     #[validate(length(min = "8", max = "30", message = "Password should be between 8 and 30 symbols"))]
     pub password: Option<String>,
     pub provider: Provider,
     pub saga_id: String,
+    /// Logins issue tokens usable only for changing the password, see
+    /// `AssuranceLevel::PasswordChangeRequired`
+    #[serde(default)]
+    pub must_change_password: bool,
+    /// Format of the password hash imported from another system, `None` for hashes made by
+    /// `password_create`
+    #[serde(default)]
+    pub password_hash_format: Option<PasswordHashFormat>,
 }
 
 /// Payload for creating users
 #[derive(Clone, Debug, Serialize, Deserialize, Validate)]
 pub struct NewIdentity {
-    #[validate(email(code = "not_valid", message = "Invalid email format"))]
+    #[validate(custom = "validate_email")]
     pub email: String,
     #[validate(length(min = "8", max = "30", message = "Password should be between 8 and 30 symbols"))]
     pub password: Option<String>,
@@ -35,9 +45,15 @@ pub struct NewIdentity {
 
 #[derive(Clone, Debug, Serialize, Deserialize, Validate)]
 pub struct EmailIdentity {
-    #[validate(email(code = "not_valid", message = "Invalid email format"))]
+    #[validate(custom = "validate_email")]
     pub email: String,
     pub password: String,
+    /// Registered client the token is issued for
+    #[serde(default)]
+    pub client_id: Option<String>,
+    /// Issue long-lived remember-me token, see `AssuranceLevel::RememberMe`
+    #[serde(default)]
+    pub remember_me: bool,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, Validate)]
@@ -54,6 +70,7 @@ pub struct UpdateIdentity {
     #[validate(length(min = "8", max = "30", message = "Password should be between 8 and 30 symbols"))]
     pub password: Option<String>,
     pub provider: Option<Provider>,
+    pub must_change_password: Option<bool>,
 }
 
 impl From<EmailIdentity> for NewIdentity {
@@ -72,3 +89,33 @@ impl fmt::Display for EmailIdentity {
         write!(f, "EmailIdentity {{ email: \"{}\", password: \"******\" }}", self.email)
     }
 }
+
+/// Identity as shown to admins, without the password hash
+#[derive(Clone, Debug, Serialize)]
+pub struct IdentityInfo {
+    pub user_id: UserId,
+    pub email: String,
+    pub provider: Provider,
+    pub has_password: bool,
+    pub must_change_password: bool,
+}
+
+impl From<Identity> for IdentityInfo {
+    fn from(ident: Identity) -> Self {
+        Self {
+            user_id: ident.user_id,
+            email: ident.email,
+            provider: ident.provider,
+            has_password: ident.password.is_some(),
+            must_change_password: ident.must_change_password,
+        }
+    }
+}
+
+/// Temporary password set by admin, shown only once
+#[derive(Clone, Debug, Serialize)]
+pub struct TemporaryPassword {
+    #[serde(flatten)]
+    pub identity: IdentityInfo,
+    pub password: String,
+}
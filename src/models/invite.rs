@@ -0,0 +1,46 @@
+//! Models for invites. In invite-only mode users are registered with an invite code only,
+//! each code can be used `max_uses` times until it expires.
+use std::time::{Duration, SystemTime};
+
+use uuid::Uuid;
+
+use stq_types::UserId;
+
+use schema::invites;
+
+#[derive(Clone, Debug, Serialize, Deserialize, Queryable)]
+pub struct Invite {
+    pub code: String,
+    pub created_by: Option<UserId>,
+    pub max_uses: i32,
+    pub uses: i32,
+    pub expires_at: SystemTime,
+    pub created_at: SystemTime,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "invites"]
+pub struct NewInvite {
+    pub code: String,
+    pub created_by: Option<UserId>,
+    pub max_uses: i32,
+    pub expires_at: SystemTime,
+}
+
+impl NewInvite {
+    pub fn new(created_by: Option<UserId>, max_uses: i32, expires_in: Duration) -> Self {
+        Self {
+            code: Uuid::new_v4().simple().to_string(),
+            created_by,
+            max_uses,
+            expires_at: SystemTime::now() + expires_in,
+        }
+    }
+}
+
+/// Payload for creating invite, defaults are taken from config
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct NewInvitePayload {
+    pub max_uses: Option<i32>,
+    pub expiration_s: Option<u64>,
+}
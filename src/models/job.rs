@@ -0,0 +1,199 @@
+//! Models for tracking long-running operations, e.g. exports. Job is created on request
+//! and updated by the background task, clients poll it until it is done or failed.
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::VarChar;
+use failure::Error as FailureError;
+use uuid::Uuid;
+
+use stq_types::UserId;
+
+use schema::jobs;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(rename_all = "snake_case")]
+#[sql_type = "VarChar"]
+pub enum JobKind {
+    SegmentExport,
+    UserPurge,
+    CrmReconciliation,
+}
+
+impl JobKind {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            JobKind::SegmentExport => "segment_export",
+            JobKind::UserPurge => "user_purge",
+            JobKind::CrmReconciliation => "crm_reconciliation",
+        }
+    }
+}
+
+impl fmt::Display for JobKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for JobKind {
+    type Err = FailureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "segment_export" => Ok(JobKind::SegmentExport),
+            "user_purge" => Ok(JobKind::UserPurge),
+            "crm_reconciliation" => Ok(JobKind::CrmReconciliation),
+            _ => Err(format_err!("Unknown job kind '{}'", s)),
+        }
+    }
+}
+
+impl ToSql<VarChar, Pg> for JobKind {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        ToSql::<VarChar, Pg>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<VarChar, Pg> for JobKind {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        let kind: String = FromSql::<VarChar, Pg>::from_sql(bytes)?;
+        kind.parse().map_err(|e: FailureError| e.to_string().into())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(rename_all = "lowercase")]
+#[sql_type = "VarChar"]
+pub enum JobState {
+    Pending,
+    Running,
+    Done,
+    Failed,
+}
+
+impl JobState {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            JobState::Pending => "pending",
+            JobState::Running => "running",
+            JobState::Done => "done",
+            JobState::Failed => "failed",
+        }
+    }
+}
+
+impl fmt::Display for JobState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for JobState {
+    type Err = FailureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(JobState::Pending),
+            "running" => Ok(JobState::Running),
+            "done" => Ok(JobState::Done),
+            "failed" => Ok(JobState::Failed),
+            _ => Err(format_err!("Unknown job state '{}'", s)),
+        }
+    }
+}
+
+impl ToSql<VarChar, Pg> for JobState {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        ToSql::<VarChar, Pg>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<VarChar, Pg> for JobState {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        let state: String = FromSql::<VarChar, Pg>::from_sql(bytes)?;
+        state.parse().map_err(|e: FailureError| e.to_string().into())
+    }
+}
+
+/// Long-running operation. `progress` is the number of processed items out of `total`, if known.
+/// `result_location` is the path to fetch the result from, once the job is done.
+#[derive(Clone, Debug, Serialize, Queryable)]
+pub struct Job {
+    pub id: Uuid,
+    pub kind: JobKind,
+    pub state: JobState,
+    pub progress: i32,
+    pub total: Option<i32>,
+    pub result_location: Option<String>,
+    pub error: Option<String>,
+    pub created_by: Option<UserId>,
+    pub created_at: SystemTime,
+    pub updated_at: SystemTime,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "jobs"]
+pub struct NewJob {
+    pub id: Uuid,
+    pub kind: JobKind,
+    pub created_by: Option<UserId>,
+}
+
+impl NewJob {
+    pub fn new(kind: JobKind, created_by: Option<UserId>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            kind,
+            created_by,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, AsChangeset)]
+#[table_name = "jobs"]
+pub struct UpdateJob {
+    pub state: Option<JobState>,
+    pub progress: Option<i32>,
+    pub total: Option<i32>,
+    pub result_location: Option<String>,
+    pub error: Option<String>,
+}
+
+impl UpdateJob {
+    pub fn running(total: Option<i32>) -> Self {
+        Self {
+            state: Some(JobState::Running),
+            total,
+            ..Self::default()
+        }
+    }
+
+    pub fn progress(progress: i32) -> Self {
+        Self {
+            progress: Some(progress),
+            ..Self::default()
+        }
+    }
+
+    pub fn done(result_location: Option<String>) -> Self {
+        Self {
+            state: Some(JobState::Done),
+            result_location,
+            ..Self::default()
+        }
+    }
+
+    pub fn failed(error: String) -> Self {
+        Self {
+            state: Some(JobState::Failed),
+            error: Some(error),
+            ..Self::default()
+        }
+    }
+}
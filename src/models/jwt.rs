@@ -3,6 +3,8 @@
 use stq_static_resources::Provider;
 use stq_types::{Alpha3, UserId};
 
+use models::{format_scope, ChildAccount, Client, LoginTiming, OAuthScope};
+
 /// Json Web Token created by provider user status
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum UserStatus {
@@ -15,6 +17,9 @@ pub enum UserStatus {
 pub struct JWT {
     pub token: String,
     pub status: UserStatus,
+    /// Timing of login stages, present only if requested by a superuser with `X-Login-Timing`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timing: Option<LoginTiming>,
 }
 
 /// Payload received from gateway for creating JWT token by provider
@@ -22,6 +27,9 @@ pub struct JWT {
 pub struct ProviderOauth {
     pub token: String,
     pub additional_data: Option<NewUserAdditionalData>,
+    /// Registered client the token is issued for
+    #[serde(default)]
+    pub client_id: Option<String>,
 }
 
 /// Json web token payload
@@ -30,6 +38,28 @@ pub struct JWTPayload {
     pub user_id: UserId,
     pub exp: i64,
     pub provider: Provider,
+    /// Unix timestamp of the last time the user actually authenticated (password or provider),
+    /// preserved across refreshes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_time: Option<i64>,
+    /// Assurance level of the session, absent for regular sessions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acr: Option<AssuranceLevel>,
+    /// Audience of the client the token was issued for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aud: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    /// Parent of the child account, for parental control in downstream services
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<UserId>,
+    /// Space-separated scopes the user granted to the third-party client, absent for
+    /// first-party tokens which are not limited
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    /// Pseudonymous id of the user for analytics, absent for third-party tokens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub analytics_id: Option<String>,
 }
 
 impl JWTPayload {
@@ -38,8 +68,124 @@ impl JWTPayload {
             user_id: id,
             exp: exp_arg,
             provider: provider_arg,
+            auth_time: None,
+            acr: None,
+            aud: None,
+            client_id: None,
+            parent_id: None,
+            scope: None,
+            analytics_id: None,
+        }
+    }
+
+    pub fn with_auth_time(self, auth_time: i64) -> Self {
+        Self {
+            auth_time: Some(auth_time),
+            ..self
+        }
+    }
+
+    pub fn with_acr(self, acr: AssuranceLevel) -> Self {
+        Self { acr: Some(acr), ..self }
+    }
+
+    /// Marks token of the child account with its parent
+    pub fn with_parent(self, child: Option<ChildAccount>) -> Self {
+        Self {
+            parent_id: child.map(|child| child.parent_id),
+            ..self
+        }
+    }
+
+    /// Limits token to scopes granted to the third-party client
+    pub fn with_scope(self, scope: Option<String>) -> Self {
+        Self { scope, ..self }
+    }
+
+    /// Sets analytics id of the user, unless the token is limited to scopes of a third-party client,
+    /// so that ids can not be used to match users across third-party clients. Remember-me tokens
+    /// are limited too, but are first-party and keep the id
+    pub fn with_analytics_id(self, analytics_id: String) -> Self {
+        let analytics_id = match self.scope {
+            Some(_) if self.acr != Some(AssuranceLevel::RememberMe) => None,
+            _ => Some(analytics_id),
+        };
+        Self { analytics_id, ..self }
+    }
+
+    /// Makes long-lived remember-me token expiring at `exp`, limited to reading the profile
+    pub fn with_remember_me(self, exp: i64) -> Self {
+        Self {
+            exp,
+            acr: Some(AssuranceLevel::RememberMe),
+            scope: Some(format_scope(REMEMBER_ME_SCOPES)),
+            ..self
         }
     }
+
+    /// Makes token usable only for stepping up, see `AssuranceLevel::StepUpRequired`
+    pub fn with_step_up_required(self) -> Self {
+        Self {
+            acr: Some(AssuranceLevel::StepUpRequired),
+            scope: Some(format_scope(STEP_UP_SCOPES)),
+            ..self
+        }
+    }
+
+    /// Makes token usable only for changing the password, see `AssuranceLevel::PasswordChangeRequired`
+    pub fn with_password_change_required(self) -> Self {
+        Self {
+            acr: Some(AssuranceLevel::PasswordChangeRequired),
+            scope: Some(format_scope(PASSWORD_CHANGE_SCOPES)),
+            ..self
+        }
+    }
+
+    /// Binds token to a registered client, setting its audience and client's token expiration
+    pub fn with_client(self, client: &Client) -> Self {
+        Self {
+            exp: client.token_expiration(),
+            aud: Some(client.audience.clone()),
+            client_id: Some(client.id.clone()),
+            ..self
+        }
+    }
+}
+
+/// Authentication context class reference (`acr` claim). Gateway and other services
+/// can require a certain level, e.g. for payment-related operations.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AssuranceLevel {
+    /// Session upgraded by re-entering credentials via `POST /jwt/step_up`
+    #[serde(rename = "step_up")]
+    StepUp,
+    /// Login looked suspicious to the fraud-scoring service, the token is limited to `STEP_UP_SCOPES`,
+    /// the session has to be upgraded via `POST /jwt/step_up` before use
+    #[serde(rename = "step_up_required")]
+    StepUpRequired,
+    /// Long-lived session of a "remember me" login, limited to `REMEMBER_ME_SCOPES`.
+    /// Anything else requires logging in with the password again
+    #[serde(rename = "remember_me")]
+    RememberMe,
+    /// Password was set by an admin or imported, the token is limited to `PASSWORD_CHANGE_SCOPES`.
+    /// Regular tokens are issued once the password is changed
+    #[serde(rename = "password_change_required")]
+    PasswordChangeRequired,
+}
+
+/// Scopes of remember-me tokens
+pub const REMEMBER_ME_SCOPES: &[OAuthScope] = &[OAuthScope::ProfileRead, OAuthScope::EmailRead];
+
+/// Scopes of tokens of suspicious logins
+pub const STEP_UP_SCOPES: &[OAuthScope] = &[OAuthScope::StepUp];
+
+/// Scopes of tokens of users who must change the password
+pub const PASSWORD_CHANGE_SCOPES: &[OAuthScope] = &[OAuthScope::PasswordChange];
+
+/// Payload for upgrading current session to a higher assurance level
+#[derive(Clone, Serialize, Deserialize)]
+pub struct StepUpRequest {
+    pub password: String,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, Default)]
@@ -48,4 +194,6 @@ pub struct NewUserAdditionalData {
     pub utm_marks: Option<serde_json::Value>,
     pub country: Option<Alpha3>,
     pub referer: Option<String>,
+    /// Required for new users in invite-only mode
+    pub invite_code: Option<String>,
 }
@@ -0,0 +1,95 @@
+//! Models for mirroring users to the legacy system during migration off it. Only the outcome of
+//! the last mirrored write of every user is kept, users whose last write failed are to be
+//! reconciled.
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::VarChar;
+use failure::Error as FailureError;
+
+use stq_types::UserId;
+
+use schema::legacy_sync_states;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(rename_all = "snake_case")]
+#[sql_type = "VarChar"]
+pub enum LegacySyncOperation {
+    Create,
+    Update,
+}
+
+impl LegacySyncOperation {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            LegacySyncOperation::Create => "create",
+            LegacySyncOperation::Update => "update",
+        }
+    }
+}
+
+impl fmt::Display for LegacySyncOperation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for LegacySyncOperation {
+    type Err = FailureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "create" => Ok(LegacySyncOperation::Create),
+            "update" => Ok(LegacySyncOperation::Update),
+            _ => Err(format_err!("Unknown legacy sync operation '{}'", s)),
+        }
+    }
+}
+
+impl ToSql<VarChar, Pg> for LegacySyncOperation {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        ToSql::<VarChar, Pg>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<VarChar, Pg> for LegacySyncOperation {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        let operation: String = FromSql::<VarChar, Pg>::from_sql(bytes)?;
+        operation.parse().map_err(|e: FailureError| e.to_string().into())
+    }
+}
+
+/// Outcome of the last write of the user mirrored to the legacy system
+#[derive(Clone, Debug, Serialize, Queryable)]
+pub struct LegacySyncState {
+    pub user_id: UserId,
+    pub operation: LegacySyncOperation,
+    pub synced: bool,
+    pub error: Option<String>,
+    pub created_at: SystemTime,
+    pub updated_at: SystemTime,
+}
+
+#[derive(Clone, Debug, Insertable, AsChangeset)]
+#[table_name = "legacy_sync_states"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct NewLegacySyncState {
+    pub user_id: UserId,
+    pub operation: LegacySyncOperation,
+    pub synced: bool,
+    pub error: Option<String>,
+}
+
+/// Reconciliation report of mirroring to the legacy system
+#[derive(Clone, Debug, Serialize)]
+pub struct LegacySyncReport {
+    pub synced: i64,
+    pub failed: i64,
+    /// Users whose last mirrored write failed
+    pub failures: Vec<LegacySyncState>,
+}
@@ -0,0 +1,127 @@
+//! Models for login history. Every login with valid credentials is scored by the fraud-scoring
+//! service, the decision made on the score is recorded along with the login.
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::VarChar;
+use failure::Error as FailureError;
+
+use stq_static_resources::Provider;
+use stq_types::UserId;
+
+use schema::login_history;
+
+/// Decision made on the login by its fraud score
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(rename_all = "snake_case")]
+#[sql_type = "VarChar"]
+pub enum LoginDecision {
+    Allowed,
+    /// Token is issued, but the user has to re-enter the password via `POST /jwt/step_up`
+    StepUpRequired,
+    Blocked,
+}
+
+impl LoginDecision {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            LoginDecision::Allowed => "allowed",
+            LoginDecision::StepUpRequired => "step_up_required",
+            LoginDecision::Blocked => "blocked",
+        }
+    }
+}
+
+impl fmt::Display for LoginDecision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for LoginDecision {
+    type Err = FailureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allowed" => Ok(LoginDecision::Allowed),
+            "step_up_required" => Ok(LoginDecision::StepUpRequired),
+            "blocked" => Ok(LoginDecision::Blocked),
+            _ => Err(format_err!("Unknown login decision '{}'", s)),
+        }
+    }
+}
+
+impl ToSql<VarChar, Pg> for LoginDecision {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        ToSql::<VarChar, Pg>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<VarChar, Pg> for LoginDecision {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        let decision: String = FromSql::<VarChar, Pg>::from_sql(bytes)?;
+        decision.parse().map_err(|e: FailureError| e.to_string().into())
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Queryable)]
+pub struct LoginEvent {
+    pub id: i32,
+    pub user_id: UserId,
+    pub provider: Provider,
+    pub client_ip: Option<String>,
+    pub user_agent: Option<String>,
+    /// Absent if the fraud-scoring service was not available
+    pub fraud_score: Option<f64>,
+    pub decision: LoginDecision,
+    pub created_at: SystemTime,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "login_history"]
+pub struct NewLoginEvent {
+    pub user_id: UserId,
+    pub provider: Provider,
+    pub client_ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub fraud_score: Option<f64>,
+    pub decision: LoginDecision,
+}
+
+/// Device, IP and velocity features of the login, computed from the login history of the user
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct LoginFeatures {
+    pub logins_last_hour: u32,
+    pub logins_last_day: u32,
+    /// Distinct IPs the user logged in from during the last day, including this login
+    pub ips_last_day: u32,
+    /// IP was not seen in the history
+    pub new_ip: bool,
+    /// User agent was not seen in the history
+    pub new_user_agent: bool,
+    /// Absent for the first login in the history
+    pub seconds_since_last_login: Option<u64>,
+    /// Login is made from a device the user trusted
+    pub trusted_device: bool,
+}
+
+/// Login event sent to the fraud-scoring service
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FraudScoreRequest {
+    pub user_id: UserId,
+    pub provider: Provider,
+    pub client_ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub features: LoginFeatures,
+}
+
+/// Score returned by the fraud-scoring service, higher is more suspicious
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FraudScoreResponse {
+    pub score: f64,
+}
@@ -0,0 +1,68 @@
+//! Models for login analytics, tokens issued per provider per day
+use std::str::FromStr;
+
+use chrono::{Datelike, Duration, NaiveDate};
+use failure::Error as FailureError;
+
+use stq_static_resources::Provider;
+
+/// Number of tokens issued for logins with `provider`. Stored per day,
+/// aggregated stats have `date` set to the first day of the period.
+#[derive(Clone, Debug, Serialize, Queryable, PartialEq)]
+pub struct LoginStat {
+    pub date: NaiveDate,
+    pub provider: Provider,
+    pub logins: i64,
+}
+
+/// Period login stats are aggregated over
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatsGranularity {
+    Day,
+    Week,
+    Month,
+}
+
+impl StatsGranularity {
+    /// First day of the period the date belongs to, weeks start on Monday
+    pub fn period_start(&self, date: NaiveDate) -> NaiveDate {
+        match *self {
+            StatsGranularity::Day => date,
+            StatsGranularity::Week => date - Duration::days(i64::from(date.weekday().num_days_from_monday())),
+            StatsGranularity::Month => NaiveDate::from_ymd(date.year(), date.month(), 1),
+        }
+    }
+}
+
+impl Default for StatsGranularity {
+    fn default() -> Self {
+        StatsGranularity::Day
+    }
+}
+
+impl FromStr for StatsGranularity {
+    type Err = FailureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "day" => Ok(StatsGranularity::Day),
+            "week" => Ok(StatsGranularity::Week),
+            "month" => Ok(StatsGranularity::Month),
+            _ => Err(format_err!("Unknown stats granularity '{}'", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_period_start() {
+        let date = NaiveDate::from_ymd(2019, 2, 21);
+        assert_eq!(StatsGranularity::Day.period_start(date), date);
+        assert_eq!(StatsGranularity::Week.period_start(date), NaiveDate::from_ymd(2019, 2, 18));
+        assert_eq!(StatsGranularity::Month.period_start(date), NaiveDate::from_ymd(2019, 2, 1));
+    }
+}
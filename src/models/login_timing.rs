@@ -0,0 +1,61 @@
+//! Timing of login stages, recorded on request of superusers for investigating slow logins
+use std::fmt;
+
+/// Stage of the login flow
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoginStage {
+    /// Request of the profile to Google or Facebook
+    ProviderFetch,
+    /// Registered client the token is issued for
+    ClientLookup,
+    /// User, identity and parent account lookups
+    UserLookup,
+    /// Creation of the user or identity on the first login with a provider
+    UserCreate,
+    /// Password verification and re-hashing of imported passwords
+    PasswordHash,
+    /// Geo restriction and login hours of roles
+    AccessPolicies,
+    /// Trusted device lookup and update
+    DeviceTracking,
+    /// Login history lookup and fraud scoring request
+    RiskAssessment,
+    /// Parent account lookup and signing of the issued token
+    JwtEncode,
+    /// Login counters and funnel events
+    Stats,
+}
+
+impl fmt::Display for LoginStage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            LoginStage::ProviderFetch => "provider_fetch",
+            LoginStage::ClientLookup => "client_lookup",
+            LoginStage::UserLookup => "user_lookup",
+            LoginStage::UserCreate => "user_create",
+            LoginStage::PasswordHash => "password_hash",
+            LoginStage::AccessPolicies => "access_policies",
+            LoginStage::DeviceTracking => "device_tracking",
+            LoginStage::RiskAssessment => "risk_assessment",
+            LoginStage::JwtEncode => "jwt_encode",
+            LoginStage::Stats => "stats",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Duration of a login stage, stages run more than once are listed every time
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LoginStageTiming {
+    pub stage: LoginStage,
+    pub duration_us: u64,
+}
+
+/// Diagnostic section of the login response
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct LoginTiming {
+    pub stages: Vec<LoginStageTiming>,
+    /// Time since the login started, including waiting for the db pool and threads
+    pub total_us: u64,
+}
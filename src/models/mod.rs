@@ -1,22 +1,108 @@
 //! Models contains all structures that are used in different
 //! modules of the app
 
+pub mod access_token;
+pub mod audit_event;
+pub mod auth_archive;
 pub mod authorization;
+pub mod break_glass;
+pub mod child_account;
+pub mod client;
+pub mod country;
+pub mod crm_reconciliation;
+pub mod deletion_request;
+pub mod dependency_health;
+pub mod device;
+pub mod device_code;
+pub mod event_schema;
+pub mod funnel;
 pub mod identity;
+pub mod invite;
+pub mod job;
 pub mod jwt;
+pub mod legacy_sync;
+pub mod login_event;
+pub mod login_stat;
+pub mod login_timing;
+pub mod oauth;
+pub mod oauth_consent;
+pub mod password_strength;
+pub mod phone;
+pub mod profile_prompt;
+pub mod provider_block;
+pub mod public_stats;
+pub mod recovery;
+pub mod refresh_token;
 pub mod reset_token;
+pub mod role_access_policy;
+pub mod role_request;
+pub mod rollout;
+pub mod security_question;
+pub mod segment_export;
+pub mod session;
+pub mod signed_action;
+pub mod unicode;
+pub mod used_token;
 pub mod user;
+pub mod user_import;
+pub mod user_key;
 pub mod user_role;
+pub mod user_tag;
+pub mod waitlist;
 
+pub use self::access_token::*;
+pub use self::audit_event::*;
+pub use self::auth_archive::*;
 pub use self::authorization::*;
+pub use self::break_glass::*;
+pub use self::child_account::*;
+pub use self::client::*;
+pub use self::country::*;
+pub use self::crm_reconciliation::*;
+pub use self::deletion_request::*;
+pub use self::dependency_health::*;
+pub use self::device::*;
+pub use self::device_code::*;
+pub use self::event_schema::*;
+pub use self::funnel::*;
 pub use self::identity::*;
+pub use self::invite::*;
+pub use self::job::*;
 pub use self::jwt::*;
+pub use self::legacy_sync::*;
+pub use self::login_event::*;
+pub use self::login_stat::*;
+pub use self::login_timing::*;
+pub use self::oauth::*;
+pub use self::oauth_consent::*;
+pub use self::password_strength::*;
+pub use self::phone::*;
+pub use self::profile_prompt::*;
+pub use self::provider_block::*;
+pub use self::public_stats::*;
+pub use self::recovery::*;
+pub use self::refresh_token::*;
 pub use self::reset_token::*;
+pub use self::role_access_policy::*;
+pub use self::role_request::*;
+pub use self::rollout::*;
+pub use self::security_question::*;
+pub use self::segment_export::*;
+pub use self::session::*;
+pub use self::signed_action::*;
+pub use self::unicode::*;
+pub use self::used_token::*;
 pub use self::user::*;
+pub use self::user_import::*;
+pub use self::user_key::*;
 pub use self::user_role::*;
+pub use self::user_tag::*;
+pub use self::waitlist::*;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SagaCreateProfile {
     pub user: Option<NewUser>,
     pub identity: NewIdentity,
+    /// Required in invite-only mode
+    pub invite_code: Option<String>,
 }
@@ -0,0 +1,170 @@
+//! Models for OAuth2 authorization server mode (RFC 6749)
+use std::fmt;
+use std::str::FromStr;
+
+/// Token request received on `POST /oauth/token`. Client credentials are accepted
+/// in the request body only, as `Authorization` header is occupied by gateway.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OAuthTokenRequest {
+    pub grant_type: String,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub scope: Option<String>,
+    /// Device code for `urn:ietf:params:oauth:grant-type:device_code` grant
+    pub device_code: Option<String>,
+}
+
+/// Supported grant types
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OAuthGrantType {
+    ClientCredentials,
+    Password,
+    DeviceCode,
+}
+
+impl FromStr for OAuthGrantType {
+    type Err = OAuthErrorCode;
+
+    fn from_str(grant_type: &str) -> Result<Self, Self::Err> {
+        match grant_type {
+            "client_credentials" => Ok(OAuthGrantType::ClientCredentials),
+            "password" => Ok(OAuthGrantType::Password),
+            "urn:ietf:params:oauth:grant-type:device_code" => Ok(OAuthGrantType::DeviceCode),
+            _ => Err(OAuthErrorCode::UnsupportedGrantType),
+        }
+    }
+}
+
+/// Successful token response
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OAuthToken {
+    pub access_token: String,
+    pub token_type: String,
+    pub expires_in: i64,
+}
+
+impl OAuthToken {
+    pub fn bearer(access_token: String, exp: i64, now: i64) -> Self {
+        Self {
+            access_token,
+            token_type: "bearer".to_string(),
+            expires_in: exp - now,
+        }
+    }
+}
+
+/// Error codes defined in RFC 6749, section 5.2
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OAuthErrorCode {
+    InvalidRequest,
+    InvalidClient,
+    InvalidGrant,
+    UnauthorizedClient,
+    UnsupportedGrantType,
+    AuthorizationPending,
+    SlowDown,
+    AccessDenied,
+    ExpiredToken,
+    InvalidScope,
+}
+
+/// Error response body
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OAuthError {
+    pub error: OAuthErrorCode,
+}
+
+/// Device authorization request received on `POST /oauth/device/code`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DeviceAuthorizationRequest {
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub scope: Option<String>,
+}
+
+/// Device authorization response, user code is shown to the user on the device
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DeviceAuthorization {
+    pub device_code: String,
+    pub user_code: String,
+    pub verification_uri: String,
+    pub expires_in: u64,
+    pub interval: u64,
+}
+
+/// Approval of a device by logged in user
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DeviceApproval {
+    pub user_code: String,
+}
+
+/// Scopes third-party clients can request, the user consents to them per client
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum OAuthScope {
+    #[serde(rename = "profile:read")]
+    ProfileRead,
+    #[serde(rename = "email:read")]
+    EmailRead,
+    /// Granted only to tokens of users who must change the password, never to third-party clients
+    #[serde(rename = "password:change")]
+    PasswordChange,
+    /// Granted only to tokens of suspicious logins, never to third-party clients
+    #[serde(rename = "session:step_up")]
+    StepUp,
+    /// Scopes of personal access tokens, never granted to third-party clients.
+    /// `read` allows `GET` requests and `write` the others
+    #[serde(rename = "read")]
+    Read,
+    #[serde(rename = "write")]
+    Write,
+}
+
+impl FromStr for OAuthScope {
+    type Err = OAuthErrorCode;
+
+    fn from_str(scope: &str) -> Result<Self, Self::Err> {
+        match scope {
+            "profile:read" => Ok(OAuthScope::ProfileRead),
+            "email:read" => Ok(OAuthScope::EmailRead),
+            "password:change" => Ok(OAuthScope::PasswordChange),
+            "session:step_up" => Ok(OAuthScope::StepUp),
+            "read" => Ok(OAuthScope::Read),
+            "write" => Ok(OAuthScope::Write),
+            _ => Err(OAuthErrorCode::InvalidScope),
+        }
+    }
+}
+
+impl fmt::Display for OAuthScope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            OAuthScope::ProfileRead => write!(f, "profile:read"),
+            OAuthScope::EmailRead => write!(f, "email:read"),
+            OAuthScope::PasswordChange => write!(f, "password:change"),
+            OAuthScope::StepUp => write!(f, "session:step_up"),
+            OAuthScope::Read => write!(f, "read"),
+            OAuthScope::Write => write!(f, "write"),
+        }
+    }
+}
+
+/// Parses space-separated `scope` parameter (RFC 6749, section 3.3), duplicates are dropped
+pub fn parse_scope(scope: &str) -> Result<Vec<OAuthScope>, OAuthErrorCode> {
+    let mut scopes = Vec::new();
+    for scope in scope.split_whitespace() {
+        let scope = scope.parse::<OAuthScope>()?;
+        if !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+    }
+    Ok(scopes)
+}
+
+/// Joins scopes back into `scope` parameter form
+pub fn format_scope(scopes: &[OAuthScope]) -> String {
+    scopes.iter().map(|scope| scope.to_string()).collect::<Vec<_>>().join(" ")
+}
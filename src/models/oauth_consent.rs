@@ -0,0 +1,77 @@
+//! Models for consents of users to scopes requested by third-party clients
+use std::time::SystemTime;
+
+use stq_types::UserId;
+
+use models::{Client, ClientBranding};
+use schema::{oauth_consents, oauth_revocations};
+
+/// Scopes the user granted to the client, the consent screen is skipped if requested scopes
+/// were granted before
+#[derive(Clone, Debug, Serialize, Deserialize, Queryable)]
+pub struct OAuthConsent {
+    pub user_id: UserId,
+    pub client_id: String,
+    pub scopes: Vec<String>,
+    pub created_at: SystemTime,
+    pub updated_at: SystemTime,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "oauth_consents"]
+pub struct NewOAuthConsent {
+    pub user_id: UserId,
+    pub client_id: String,
+    pub scopes: Vec<String>,
+}
+
+/// Data for the consent screen shown to the user before approving the device
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ConsentScreen {
+    pub client_id: String,
+    pub client_name: String,
+    /// Scopes requested by the client
+    pub scopes: Vec<String>,
+    /// Requested scopes the user granted to the client before
+    pub granted_scopes: Vec<String>,
+    /// Brand of the marketplace the client belongs to, if any
+    pub branding: Option<ClientBranding>,
+}
+
+/// Tokens issued to the client for the user expiring before `revoke_before` are revoked
+#[derive(Clone, Debug, Serialize, Deserialize, Queryable)]
+pub struct OAuthRevocation {
+    pub user_id: UserId,
+    pub client_id: String,
+    pub revoke_before: SystemTime,
+    pub created_at: SystemTime,
+    pub updated_at: SystemTime,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "oauth_revocations"]
+pub struct NewOAuthRevocation {
+    pub user_id: UserId,
+    pub client_id: String,
+    pub revoke_before: SystemTime,
+}
+
+/// Third-party client the user consented to, shown in connected apps of the user
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ConnectedApp {
+    pub client_id: String,
+    pub client_name: String,
+    pub scopes: Vec<String>,
+    pub connected_at: SystemTime,
+}
+
+impl ConnectedApp {
+    pub fn new(consent: OAuthConsent, client: &Client) -> Self {
+        Self {
+            client_id: consent.client_id,
+            client_name: client.name.clone(),
+            scopes: consent.scopes,
+            connected_at: consent.created_at,
+        }
+    }
+}
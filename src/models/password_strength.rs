@@ -0,0 +1,40 @@
+//! Models for password strength feedback. Hints carry message keys of the catalog along with
+//! localized messages, so that web and mobile clients show the same feedback.
+use std::fmt;
+
+/// Password to be estimated, `user_inputs` are e.g. email and names the user entered in the form,
+/// passwords containing them are easy to guess
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PasswordStrengthRequest {
+    pub password: String,
+    #[serde(default)]
+    pub user_inputs: Vec<String>,
+}
+
+impl fmt::Debug for PasswordStrengthRequest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "PasswordStrengthRequest {{ password: \"*****\", user_inputs: {:?} }}",
+            self.user_inputs
+        )
+    }
+}
+
+/// Hint on improving the password, `message_key` is looked up as `password_strength.<code>`
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct PasswordHint {
+    pub code: String,
+    pub message_key: String,
+    pub message: String,
+}
+
+/// Strength of the password, `score` is from 0 (too guessable) to 4 (very unguessable)
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct PasswordStrength {
+    pub score: u8,
+    /// Estimated number of guesses needed to crack the password, base 10 logarithm
+    pub guesses_log10: f64,
+    pub warning: Option<PasswordHint>,
+    pub suggestions: Vec<PasswordHint>,
+}
@@ -0,0 +1,102 @@
+//! Phone numbers are stored in E.164 format, e.g. `+79991234567`.
+//! Numbers without international prefix are resolved with configured default region.
+
+/// Calling code and national trunk prefix by ISO 3166-1 alpha-2 region
+const REGIONS: &'static [(&'static str, &'static str, &'static str)] = &[
+    ("BY", "375", "8"),
+    ("CA", "1", "1"),
+    ("CN", "86", "0"),
+    ("DE", "49", "0"),
+    ("ES", "34", ""),
+    ("FR", "33", "0"),
+    ("GB", "44", "0"),
+    ("IN", "91", "0"),
+    ("IT", "39", ""),
+    ("JP", "81", "0"),
+    ("KR", "82", "0"),
+    ("KZ", "7", "8"),
+    ("RU", "7", "8"),
+    ("SG", "65", ""),
+    ("UA", "380", "0"),
+    ("US", "1", "1"),
+];
+
+/// Max number of digits in E.164 number
+const E164_MAX_DIGITS: usize = 15;
+/// Min number of digits, as accepted by `validate_phone`
+const E164_MIN_DIGITS: usize = 7;
+/// Length of national significant number in most numbering plans we serve
+const NATIONAL_NUMBER_LEN: usize = 10;
+
+/// Characters used to format phone numbers, e.g. `+7 (999) 123-45-67`
+pub fn is_separator(c: char) -> bool {
+    c.is_whitespace() || c == '-' || c == '.' || c == '(' || c == ')' || c == '/'
+}
+
+/// Returns E.164 form of the phone or `None` if it can not be a phone number
+pub fn normalize_phone(phone: &str, default_region: &str) -> Option<String> {
+    let phone = phone.trim();
+    let (international, rest) = if phone.starts_with('+') {
+        (true, &phone[1..])
+    } else {
+        (false, phone)
+    };
+
+    if !rest.chars().all(|c| c.is_ascii_digit() || is_separator(c)) {
+        return None;
+    }
+    let digits = rest.chars().filter(|c| c.is_ascii_digit()).collect::<String>();
+
+    let digits = if international {
+        digits
+    } else if digits.starts_with("00") {
+        digits[2..].to_string()
+    } else {
+        let &(_, calling_code, trunk_prefix) = REGIONS
+            .iter()
+            .find(|&&(region, _, _)| region.eq_ignore_ascii_case(default_region))?;
+
+        if !trunk_prefix.is_empty() && digits.starts_with(trunk_prefix) && digits.len() > NATIONAL_NUMBER_LEN {
+            format!("{}{}", calling_code, &digits[trunk_prefix.len()..])
+        } else if digits.starts_with(calling_code) && digits.len() >= calling_code.len() + NATIONAL_NUMBER_LEN {
+            digits
+        } else {
+            format!("{}{}", calling_code, digits)
+        }
+    };
+
+    if digits.len() < E164_MIN_DIGITS || digits.len() > E164_MAX_DIGITS || digits.starts_with('0') {
+        return None;
+    }
+
+    Some(format!("+{}", digits))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_international_phone() {
+        assert_eq!(normalize_phone("+7 (999) 123-45-67", "RU"), Some("+79991234567".to_string()));
+        assert_eq!(normalize_phone("0044 20 7946 0958", "RU"), Some("+442079460958".to_string()));
+        assert_eq!(normalize_phone("+1 212.555.0100", "GB"), Some("+12125550100".to_string()));
+    }
+
+    #[test]
+    fn test_normalize_national_phone() {
+        assert_eq!(normalize_phone("8 999 123 45 67", "RU"), Some("+79991234567".to_string()));
+        assert_eq!(normalize_phone("79991234567", "RU"), Some("+79991234567".to_string()));
+        assert_eq!(normalize_phone("9991234567", "ru"), Some("+79991234567".to_string()));
+        assert_eq!(normalize_phone("020 7946 0958", "GB"), Some("+442079460958".to_string()));
+        assert_eq!(normalize_phone("(212) 555-0100", "US"), Some("+12125550100".to_string()));
+    }
+
+    #[test]
+    fn test_normalize_invalid_phone() {
+        assert_eq!(normalize_phone("phone", "RU"), None);
+        assert_eq!(normalize_phone("+7 999 CALL-ME", "RU"), None);
+        assert_eq!(normalize_phone("123", "RU"), None);
+        assert_eq!(normalize_phone("9991234567", "XX"), None);
+    }
+}
@@ -0,0 +1,35 @@
+//! Models for progressive profiling. Users are prompted to fill missing profile fields
+//! one by one in configured order, a dismissed prompt is not shown until it is snoozed.
+use std::time::SystemTime;
+
+use stq_types::UserId;
+
+use schema::profile_prompt_dismissals;
+
+/// Profile field the user is prompted to fill, `weight` is its weight in profile completion
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ProfilePrompt {
+    pub field: String,
+    pub weight: u32,
+}
+
+#[derive(Clone, Debug, Serialize, Queryable)]
+pub struct ProfilePromptDismissal {
+    pub user_id: UserId,
+    pub field: String,
+    pub snoozed_until: SystemTime,
+    pub created_at: SystemTime,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "profile_prompt_dismissals"]
+pub struct NewProfilePromptDismissal {
+    pub user_id: UserId,
+    pub field: String,
+    pub snoozed_until: SystemTime,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DismissProfilePrompt {
+    pub field: String,
+}
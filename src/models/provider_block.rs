@@ -0,0 +1,13 @@
+//! Models for social providers unlinked from users, logins with a blocked provider are rejected
+//! instead of attaching a new identity to the user with the same e-mail
+use stq_static_resources::Provider;
+use stq_types::UserId;
+
+use schema::provider_blocks;
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "provider_blocks"]
+pub struct NewProviderBlock {
+    pub user_id: UserId,
+    pub provider: Provider,
+}
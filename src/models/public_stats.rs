@@ -0,0 +1,32 @@
+//! Models for stats exposed outside of admin tools. Counts are noisy, counts of small
+//! cohorts are withheld and serialized as `null`.
+use chrono::NaiveDate;
+
+use stq_static_resources::Provider;
+
+use models::FunnelStep;
+
+/// Logins with `provider` in the month starting on `date`
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct PublicLoginStat {
+    pub date: NaiveDate,
+    pub provider: Provider,
+    pub logins: Option<i64>,
+}
+
+/// Users of the cohort who reached the step, `conversion` is the share of users who registered
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct PublicFunnelStep {
+    pub step: FunnelStep,
+    pub users: Option<i64>,
+    pub conversion: Option<f64>,
+}
+
+/// Monthly logins and registration funnel from `from` to `to` inclusive
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct PublicStats {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub logins: Vec<PublicLoginStat>,
+    pub funnel: Vec<PublicFunnelStep>,
+}
@@ -0,0 +1,192 @@
+//! Models for account recovery via trusted contacts. The user designates contacts in advance,
+//! recovery is approved by the contacts following signed links, and once enough of them
+//! approved it a password reset token is issued for the user.
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::VarChar;
+use failure::Error as FailureError;
+use uuid::Uuid;
+use validator::Validate;
+
+use stq_types::UserId;
+
+use models::unicode::validate_email;
+use schema::{recovery_approvals, recovery_requests, trusted_contacts};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(rename_all = "lowercase")]
+#[sql_type = "VarChar"]
+pub enum RecoveryState {
+    /// Waiting for approvals of trusted contacts
+    Pending,
+    /// Enough contacts approved, reset token is issued
+    Approved,
+    /// Replaced by a newer request of the same user
+    Cancelled,
+}
+
+impl RecoveryState {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            RecoveryState::Pending => "pending",
+            RecoveryState::Approved => "approved",
+            RecoveryState::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl fmt::Display for RecoveryState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for RecoveryState {
+    type Err = FailureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(RecoveryState::Pending),
+            "approved" => Ok(RecoveryState::Approved),
+            "cancelled" => Ok(RecoveryState::Cancelled),
+            _ => Err(format_err!("Unknown recovery state '{}'", s)),
+        }
+    }
+}
+
+impl ToSql<VarChar, Pg> for RecoveryState {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        ToSql::<VarChar, Pg>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<VarChar, Pg> for RecoveryState {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        let state: String = FromSql::<VarChar, Pg>::from_sql(bytes)?;
+        state.parse().map_err(|e: FailureError| e.to_string().into())
+    }
+}
+
+/// Person the user trusts to approve recovery of the account, contact does not have to be a user
+#[derive(Clone, Debug, Serialize, Queryable)]
+pub struct TrustedContact {
+    pub id: i32,
+    pub user_id: UserId,
+    pub email: String,
+    pub created_at: SystemTime,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "trusted_contacts"]
+pub struct NewTrustedContact {
+    pub user_id: UserId,
+    pub email: String,
+}
+
+/// Payload for adding trusted contact
+#[derive(Clone, Debug, Serialize, Deserialize, Validate)]
+pub struct TrustedContactPayload {
+    #[validate(custom = "validate_email")]
+    pub email: String,
+}
+
+/// Request to recover the account, `required_approvals` is fixed when the request is created
+#[derive(Clone, Debug, Serialize, Queryable)]
+pub struct RecoveryRequest {
+    pub id: Uuid,
+    pub user_id: UserId,
+    pub state: RecoveryState,
+    pub required_approvals: i32,
+    pub expires_at: SystemTime,
+    pub created_at: SystemTime,
+    pub updated_at: SystemTime,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "recovery_requests"]
+pub struct NewRecoveryRequest {
+    pub id: Uuid,
+    pub user_id: UserId,
+    pub state: RecoveryState,
+    pub required_approvals: i32,
+    pub expires_at: SystemTime,
+}
+
+impl NewRecoveryRequest {
+    pub fn new(user_id: UserId, required_approvals: i32, expires_at: SystemTime) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            user_id,
+            state: RecoveryState::Pending,
+            required_approvals,
+            expires_at,
+        }
+    }
+}
+
+/// Approval of the request by one of the trusted contacts
+#[derive(Clone, Debug, Serialize, Queryable)]
+pub struct RecoveryApproval {
+    pub request_id: Uuid,
+    pub contact_id: i32,
+    pub created_at: SystemTime,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "recovery_approvals"]
+pub struct NewRecoveryApproval {
+    pub request_id: Uuid,
+    pub contact_id: i32,
+}
+
+/// Payload for starting recovery of the account with the email
+#[derive(Clone, Debug, Serialize, Deserialize, Validate)]
+pub struct RecoveryStart {
+    #[validate(custom = "validate_email")]
+    pub email: String,
+}
+
+/// Approval link signed for one of the contacts, to be put into email sent to the contact
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecoveryLink {
+    pub contact_id: i32,
+    pub email: String,
+    pub url: String,
+    pub token: String,
+}
+
+/// Started recovery with links for all trusted contacts of the user
+#[derive(Clone, Debug, Serialize)]
+pub struct RecoveryStarted {
+    #[serde(flatten)]
+    pub request: RecoveryRequest,
+    pub links: Vec<RecoveryLink>,
+}
+
+/// Payload for approving recovery by following the link
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecoveryApprove {
+    pub token: String,
+}
+
+/// Approval signed for the contact, nothing is persisted until the contact follows the link
+#[derive(Clone, Debug, PartialEq)]
+pub struct SignedRecoveryApproval {
+    pub request_id: Uuid,
+    pub contact_id: i32,
+    pub exp: i64,
+}
+
+/// Recovery request with number of contacts approved it so far
+#[derive(Clone, Debug, Serialize)]
+pub struct RecoveryStatus {
+    #[serde(flatten)]
+    pub request: RecoveryRequest,
+    pub approvals: i64,
+}
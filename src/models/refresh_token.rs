@@ -0,0 +1,89 @@
+//! Models for rotating refresh tokens used by browser clients for silent token renewal.
+//! Every renewal rotates the refresh token, tokens issued from the same login form a family.
+use std::time::SystemTime;
+
+use uuid::Uuid;
+
+use stq_static_resources::Provider;
+use stq_types::UserId;
+
+use schema::refresh_tokens;
+
+/// Refresh token, `rotated_at` is set once the token was exchanged for the next one.
+/// Claims of the access token are kept to issue the same token on renewal.
+#[derive(Clone, Debug, Serialize, Deserialize, Queryable)]
+pub struct RefreshToken {
+    pub id: Uuid,
+    pub family_id: Uuid,
+    pub user_id: UserId,
+    pub client_id: String,
+    pub provider: Provider,
+    pub auth_time: Option<i64>,
+    pub scope: Option<String>,
+    pub token_hash: String,
+    pub expires_at: SystemTime,
+    pub rotated_at: Option<SystemTime>,
+    pub revoked: bool,
+    pub family_created_at: SystemTime,
+    pub created_at: SystemTime,
+}
+
+impl RefreshToken {
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires_at <= now
+    }
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "refresh_tokens"]
+pub struct NewRefreshToken {
+    pub id: Uuid,
+    pub family_id: Uuid,
+    pub user_id: UserId,
+    pub client_id: String,
+    pub provider: Provider,
+    pub auth_time: Option<i64>,
+    pub scope: Option<String>,
+    pub token_hash: String,
+    pub expires_at: SystemTime,
+    pub family_created_at: SystemTime,
+}
+
+impl NewRefreshToken {
+    /// Next token of the family replacing the rotated one
+    pub fn rotate(token: &RefreshToken, token_hash: String, expires_at: SystemTime) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            family_id: token.family_id,
+            user_id: token.user_id,
+            client_id: token.client_id.clone(),
+            provider: token.provider.clone(),
+            auth_time: token.auth_time,
+            scope: token.scope.clone(),
+            token_hash,
+            expires_at,
+            family_created_at: token.family_created_at,
+        }
+    }
+}
+
+/// Payload for renewing access token of browser client. Gateway moves refresh token
+/// from the cookie to the body for clients with cookie mode.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RenewalRequest {
+    pub client_id: String,
+    pub refresh_token: String,
+}
+
+/// Renewed access token with the next refresh token. Either `refresh_token` is returned
+/// in the body or `set_cookie` is set, which gateway sends in `Set-Cookie` header and
+/// removes from the body.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Renewal {
+    pub token: String,
+    pub expires_in: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub set_cookie: Option<String>,
+}
@@ -2,12 +2,13 @@
 use std::fmt;
 use std::time::SystemTime;
 
-use base64::encode;
 use uuid::Uuid;
 use validator::Validate;
 
 use stq_static_resources::TokenType;
 
+use models::security_question::SecurityAnswerPayload;
+use models::unicode::validate_email;
 use models::user::User;
 use schema::reset_tokens;
 
@@ -23,9 +24,8 @@ pub struct ResetToken {
 }
 
 impl ResetToken {
-    pub fn new(email: String, token_type: TokenType, uuid: Option<Uuid>) -> ResetToken {
+    pub fn new(email: String, token_type: TokenType, uuid: Option<Uuid>, token: String) -> ResetToken {
         let uuid = uuid.unwrap_or(Uuid::new_v4());
-        let token = encode(&Uuid::new_v4().to_string());
         ResetToken {
             token,
             email,
@@ -39,14 +39,14 @@ impl ResetToken {
 
 #[derive(Serialize, Deserialize, Validate, Debug)]
 pub struct ResetRequest {
-    #[validate(email(code = "not_valid", message = "Invalid email format"))]
+    #[validate(custom = "validate_email")]
     pub email: String,
     pub uuid: Uuid,
 }
 
 #[derive(Serialize, Deserialize, Validate, Debug)]
 pub struct VerifyRequest {
-    #[validate(email(code = "not_valid", message = "Invalid email format"))]
+    #[validate(custom = "validate_email")]
     pub email: String,
 }
 
@@ -55,6 +55,9 @@ pub struct ResetApply {
     pub token: String,
     #[validate(length(min = "8", max = "30", message = "Password should be between 8 and 30 symbols"))]
     pub password: String,
+    /// Required if the user has answered security questions
+    #[serde(default)]
+    pub security_answers: Vec<SecurityAnswerPayload>,
 }
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ResetApplyToken {
@@ -0,0 +1,52 @@
+//! Models for access policies of admin roles. A role is in effect only during the time window of
+//! its policy, e.g. superuser actions are allowed 08:00–20:00 only. Roles without a policy are
+//! always in effect, the break-glass user is never restricted.
+use std::time::SystemTime;
+
+use chrono::{DateTime, Duration, NaiveTime, Utc};
+use validator::Validate;
+
+use stq_types::UsersRole;
+
+use schema::role_access_policies;
+
+#[derive(Clone, Debug, Serialize, Queryable)]
+pub struct RoleAccessPolicy {
+    pub role: UsersRole,
+    /// Local time the window opens at
+    pub starts_at: NaiveTime,
+    /// Local time the window closes at, windows ending before they start span midnight
+    pub ends_at: NaiveTime,
+    /// Offset of the local time from UTC the window is defined in
+    pub utc_offset_minutes: i32,
+    /// Users with the role can't log in outside the window, not just act with the role
+    pub restrict_login: bool,
+    pub created_at: SystemTime,
+    pub updated_at: SystemTime,
+}
+
+impl RoleAccessPolicy {
+    /// Whether the role is in effect at the moment
+    pub fn is_open(&self, now: DateTime<Utc>) -> bool {
+        let local_time = (now + Duration::minutes(i64::from(self.utc_offset_minutes))).time();
+        if self.starts_at <= self.ends_at {
+            self.starts_at <= local_time && local_time < self.ends_at
+        } else {
+            self.starts_at <= local_time || local_time < self.ends_at
+        }
+    }
+}
+
+/// Payload for creating or replacing the policy of the role
+#[derive(Clone, Debug, Serialize, Deserialize, Validate, Insertable, AsChangeset)]
+#[table_name = "role_access_policies"]
+pub struct NewRoleAccessPolicy {
+    pub role: UsersRole,
+    pub starts_at: NaiveTime,
+    pub ends_at: NaiveTime,
+    #[serde(default)]
+    #[validate(range(min = "-720", max = "840", message = "UTC offset should be between -720 and 840 minutes"))]
+    pub utc_offset_minutes: i32,
+    #[serde(default)]
+    pub restrict_login: bool,
+}
@@ -0,0 +1,70 @@
+//! Models for approvals of sensitive role grants. Grants of sensitive roles, e.g. superuser,
+//! are not applied right away, they are requested and applied once a second admin approves them.
+use std::time::SystemTime;
+
+use serde_json;
+use uuid::Uuid;
+
+use stq_types::{UserId, UsersRole};
+
+use models::{NewUserRole, UserRole};
+use schema::role_requests;
+
+/// Grant of the role waiting for approval, `approved_by` is set once it is approved
+#[derive(Clone, Debug, Serialize, Queryable)]
+pub struct RoleRequest {
+    pub id: Uuid,
+    pub user_id: UserId,
+    pub name: UsersRole,
+    pub data: Option<serde_json::Value>,
+    pub expires_at: Option<SystemTime>,
+    pub requested_by: UserId,
+    pub approved_by: Option<UserId>,
+    pub approved_at: Option<SystemTime>,
+    pub created_at: SystemTime,
+}
+
+impl RoleRequest {
+    /// The role granted by the request
+    pub fn new_user_role(&self) -> NewUserRole {
+        NewUserRole {
+            id: None,
+            user_id: self.user_id,
+            name: self.name.clone(),
+            data: self.data.clone(),
+            expires_at: self.expires_at,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "role_requests"]
+pub struct NewRoleRequest {
+    pub id: Uuid,
+    pub user_id: UserId,
+    pub name: UsersRole,
+    pub data: Option<serde_json::Value>,
+    pub expires_at: Option<SystemTime>,
+    pub requested_by: UserId,
+}
+
+impl NewRoleRequest {
+    pub fn new(role: NewUserRole, requested_by: UserId) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            user_id: role.user_id,
+            name: role.name,
+            data: role.data,
+            expires_at: role.expires_at,
+            requested_by,
+        }
+    }
+}
+
+/// Result of `POST /roles`, grants of sensitive roles are pending until approved
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum UserRoleGrant {
+    Granted(UserRole),
+    Pending(RoleRequest),
+}
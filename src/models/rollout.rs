@@ -0,0 +1,32 @@
+//! Features rolled out to a percentage of users before being enabled for everyone
+use std::fmt;
+
+/// Feature under percentage rollout, percentages are configured in `rollouts`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    /// New passwords are hashed with argon2 instead of salted SHA3
+    Argon2Passwords,
+}
+
+impl Feature {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Feature::Argon2Passwords => "argon2_passwords",
+        }
+    }
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Assignments of users to a feature under rollout
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct RolloutStats {
+    pub percentage: u8,
+    pub enabled: usize,
+    pub disabled: usize,
+}
@@ -0,0 +1,67 @@
+//! Models for security questions. Answers are hashed the same way as passwords,
+//! question texts are taken from per-locale catalogs in config by question id.
+use std::fmt;
+use std::time::SystemTime;
+
+use stq_types::UserId;
+
+use schema::security_answers;
+
+/// Question of the catalog, `id` is the same in all the locales
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SecurityQuestion {
+    pub id: String,
+    pub text: String,
+}
+
+/// Hashed answer of the user to the question
+#[derive(Clone, Debug, Serialize, Queryable)]
+pub struct SecurityAnswer {
+    pub user_id: UserId,
+    pub question_id: String,
+    #[serde(skip_serializing)]
+    pub answer_hash: String,
+    pub created_at: SystemTime,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "security_answers"]
+pub struct NewSecurityAnswer {
+    pub user_id: UserId,
+    pub question_id: String,
+    pub answer_hash: String,
+}
+
+/// Answer in clear text as given by the user
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SecurityAnswerPayload {
+    pub question_id: String,
+    pub answer: String,
+}
+
+impl fmt::Debug for SecurityAnswerPayload {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "SecurityAnswerPayload {{ question_id: \"{}\", answer: \"*****\" }}",
+            self.question_id
+        )
+    }
+}
+
+/// Payload for setting answers of the user, previous answers are replaced
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SecurityAnswers {
+    pub answers: Vec<SecurityAnswerPayload>,
+}
+
+/// Payload for getting questions to be answered on password reset
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ResetSecurityQuestionsRequest {
+    pub token: String,
+}
+
+/// Normalizes answer before hashing, so that case and spacing do not matter
+pub fn normalize_answer(answer: &str) -> String {
+    answer.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
@@ -0,0 +1,26 @@
+//! Models for exporting user segments to CSV. Export is run as a job,
+//! the export itself keeps filters and CSV once the job is done.
+use std::time::SystemTime;
+
+use serde_json;
+use uuid::Uuid;
+
+use schema::segment_exports;
+
+/// Export of users matching `filters`, `id` is the id of the export job
+#[derive(Clone, Debug, Serialize, Queryable)]
+pub struct SegmentExport {
+    pub id: Uuid,
+    pub filters: serde_json::Value,
+    #[serde(skip_serializing)]
+    pub csv: Option<String>,
+    pub created_at: SystemTime,
+    pub updated_at: SystemTime,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "segment_exports"]
+pub struct NewSegmentExport {
+    pub id: Uuid,
+    pub filters: serde_json::Value,
+}
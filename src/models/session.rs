@@ -0,0 +1,78 @@
+//! Models for server-side sessions of clients with session mode, e.g. server-rendered admin
+//! console which can not safely hold tokens in the browser. Session id is kept in `httpOnly`
+//! cookie and stored hashed, the gateway resolves it to a short-lived token on every request.
+use std::time::{Duration, SystemTime};
+
+use uuid::Uuid;
+
+use stq_static_resources::Provider;
+use stq_types::UserId;
+
+use schema::sessions;
+
+#[derive(Clone, Debug, Serialize, Deserialize, Queryable)]
+pub struct Session {
+    pub id: Uuid,
+    #[serde(skip_serializing)]
+    pub session_hash: String,
+    pub user_id: UserId,
+    pub client_id: String,
+    pub provider: Provider,
+    pub auth_time: Option<i64>,
+    pub expires_at: SystemTime,
+    pub last_seen_at: SystemTime,
+    pub created_at: SystemTime,
+}
+
+impl Session {
+    /// Session is expired after its lifetime or after `idle_timeout` without requests
+    pub fn is_expired(&self, now: SystemTime, idle_timeout: Duration) -> bool {
+        self.expires_at <= now || self.last_seen_at + idle_timeout <= now
+    }
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "sessions"]
+pub struct NewSession {
+    pub id: Uuid,
+    pub session_hash: String,
+    pub user_id: UserId,
+    pub client_id: String,
+    pub provider: Provider,
+    pub auth_time: Option<i64>,
+    pub expires_at: SystemTime,
+}
+
+/// Session id taken by the gateway from the cookie
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SessionRequest {
+    pub session_id: String,
+}
+
+/// Created session, gateway sends `set_cookie` in `Set-Cookie` header and removes it from the body
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CreatedSession {
+    pub expires_in: i64,
+    pub set_cookie: String,
+}
+
+/// Short-lived token of the session user, passed by the gateway to downstream services
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ResolvedSession {
+    pub user_id: UserId,
+    pub token: String,
+}
+
+/// Closed session, `set_cookie` expires the cookie in the browser
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ClosedSession {
+    pub set_cookie: String,
+}
+
+/// CSRF token of cookie-based flows, the browser sends it back in `X-CSRF-Token` header.
+/// Gateway sends `set_cookie` in `Set-Cookie` header and removes it from the body.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CsrfToken {
+    pub csrf_token: String,
+    pub set_cookie: String,
+}
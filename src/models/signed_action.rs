@@ -0,0 +1,69 @@
+//! Models for signed one-click email actions
+use std::fmt;
+use std::str::FromStr;
+
+use stq_types::UserId;
+
+/// Actions that can be performed by following a signed link from email
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignedActionKind {
+    VerifyEmail,
+    RevertEmailChange,
+    ApproveLogin,
+}
+
+impl fmt::Display for SignedActionKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match *self {
+            SignedActionKind::VerifyEmail => "verify_email",
+            SignedActionKind::RevertEmailChange => "revert_email_change",
+            SignedActionKind::ApproveLogin => "approve_login",
+        };
+        write!(f, "{}", kind)
+    }
+}
+
+impl FromStr for SignedActionKind {
+    type Err = ();
+
+    fn from_str(kind: &str) -> Result<Self, Self::Err> {
+        match kind {
+            "verify_email" => Ok(SignedActionKind::VerifyEmail),
+            "revert_email_change" => Ok(SignedActionKind::RevertEmailChange),
+            "approve_login" => Ok(SignedActionKind::ApproveLogin),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Action signed with HMAC, nothing is persisted until the action is applied
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SignedAction {
+    pub action: SignedActionKind,
+    pub user_id: UserId,
+    pub exp: i64,
+    /// Random id, recorded once the action is applied so that the link works only once
+    pub id: String,
+}
+
+/// Payload for creating signed action link
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NewSignedAction {
+    pub action: SignedActionKind,
+    pub user_id: UserId,
+}
+
+/// Signed link to be put into email
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SignedActionUrl {
+    pub url: String,
+    pub token: String,
+    pub exp: i64,
+}
+
+/// Payload for applying signed action
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SignedActionApply {
+    pub token: String,
+}
@@ -0,0 +1,155 @@
+//! Unicode-aware validation and normalization of user input. Emails may have
+//! internationalized domains (IDN) and, if enabled in config, UTF-8 local parts (RFC 6531).
+//! Names are normalized to NFC before storage and search.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use idna::domain_to_ascii;
+use regex::Regex;
+use unicode_normalization::UnicodeNormalization;
+use validator::ValidationError;
+
+/// Max length of email local part, RFC 5321
+const LOCAL_PART_MAX_LEN: usize = 64;
+/// Non-alphanumeric ASCII characters allowed in email local part
+const LOCAL_PART_SPECIALS: &'static str = ".!#$%&'*+/=?^_`{|}~-";
+
+static UTF8_LOCAL_PART_ALLOWED: AtomicBool = AtomicBool::new(false);
+
+/// Enables UTF-8 local parts in emails, set once on startup from config.
+/// Custom validators do not have access to config, hence the global switch.
+pub fn set_utf8_local_part_allowed(allowed: bool) {
+    UTF8_LOCAL_PART_ALLOWED.store(allowed, Ordering::SeqCst);
+}
+
+fn is_utf8_local_part_allowed() -> bool {
+    UTF8_LOCAL_PART_ALLOWED.load(Ordering::SeqCst)
+}
+
+fn invalid_email() -> ValidationError {
+    ValidationError {
+        code: Cow::from("not_valid"),
+        message: Some(Cow::from("Invalid email format")),
+        params: HashMap::new(),
+    }
+}
+
+fn validate_local_part(local_part: &str, utf8_allowed: bool) -> bool {
+    lazy_static! {
+        static ref EMAIL_LOCAL_PART_RE: Regex = Regex::new(r"^(?i)[a-z0-9.!#$%&'*+/=?^_`{|}~-]+\z").unwrap();
+    }
+
+    if local_part.is_empty() || local_part.len() > LOCAL_PART_MAX_LEN {
+        return false;
+    }
+
+    if local_part.is_ascii() {
+        EMAIL_LOCAL_PART_RE.is_match(local_part)
+    } else {
+        utf8_allowed
+            && local_part.chars().all(|c| {
+                if c.is_ascii() {
+                    c.is_ascii_alphanumeric() || LOCAL_PART_SPECIALS.contains(c)
+                } else {
+                    !c.is_whitespace() && !c.is_control()
+                }
+            })
+    }
+}
+
+fn validate_domain_part(domain_part: &str) -> bool {
+    lazy_static! {
+        static ref EMAIL_DOMAIN_RE: Regex =
+            Regex::new(r"^(?i)[a-z0-9](?:[a-z0-9-]{0,61}[a-z0-9])?(?:\.[a-z0-9](?:[a-z0-9-]{0,61}[a-z0-9])?)*$").unwrap();
+    }
+
+    if domain_part.starts_with('[') && domain_part.ends_with(']') {
+        return domain_part[1..domain_part.len() - 1].parse::<IpAddr>().is_ok();
+    }
+
+    domain_to_ascii(domain_part)
+        .map(|ascii_domain| EMAIL_DOMAIN_RE.is_match(&ascii_domain))
+        .unwrap_or(false)
+}
+
+/// Validates email with IDN domain, UTF-8 local part is accepted if enabled in config
+pub fn validate_email(email: &str) -> Result<(), ValidationError> {
+    validate_email_with(email, is_utf8_local_part_allowed())
+}
+
+/// Validates email with IDN domain, UTF-8 local part is accepted if `utf8_local_part_allowed`
+pub fn validate_email_with(email: &str, utf8_local_part_allowed: bool) -> Result<(), ValidationError> {
+    let at = match email.rfind('@') {
+        Some(at) => at,
+        None => return Err(invalid_email()),
+    };
+    let (local_part, domain_part) = (&email[..at], &email[at + 1..]);
+
+    if validate_local_part(local_part, utf8_local_part_allowed) && validate_domain_part(domain_part) {
+        Ok(())
+    } else {
+        Err(invalid_email())
+    }
+}
+
+/// Brings email to the stored form: lowercase, with IDN domain in punycode. The same address
+/// typed with Unicode or ASCII domain is then unique and found the same way.
+pub fn normalize_email(email: &str) -> String {
+    let email = email.to_lowercase();
+    match email.rfind('@') {
+        Some(at) if !email[at + 1..].is_ascii() => match domain_to_ascii(&email[at + 1..]) {
+            Ok(domain) => format!("{}@{}", &email[..at], domain),
+            Err(_) => email,
+        },
+        _ => email,
+    }
+}
+
+/// Normalizes name to Unicode NFC, so that the same name typed on different
+/// keyboards is stored and searched the same way
+pub fn normalize_name(name: &str) -> String {
+    name.nfc().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_email() {
+        assert_eq!(validate_email("user@example.com").is_ok(), true);
+        assert_eq!(validate_email("user@пример.рф").is_ok(), true);
+        assert_eq!(validate_email("user@bücher.de").is_ok(), true);
+        assert_eq!(validate_email("user@[127.0.0.1]").is_ok(), true);
+        assert_eq!(validate_email("user").is_err(), true);
+        assert_eq!(validate_email("@example.com").is_err(), true);
+        assert_eq!(validate_email("user@").is_err(), true);
+        assert_eq!(validate_email("us er@example.com").is_err(), true);
+        assert_eq!(validate_email("user@exa mple.com").is_err(), true);
+    }
+
+    #[test]
+    fn test_validate_email_utf8_local_part() {
+        assert_eq!(validate_email_with("пользователь@пример.рф", false).is_err(), true);
+        assert_eq!(validate_email_with("пользователь@пример.рф", true).is_ok(), true);
+        assert_eq!(validate_email_with("поль зователь@пример.рф", true).is_err(), true);
+    }
+
+    #[test]
+    fn test_normalize_email() {
+        assert_eq!(normalize_email("User@Example.com"), "user@example.com");
+        assert_eq!(normalize_email("user@Bücher.de"), "user@xn--bcher-kva.de");
+        assert_eq!(normalize_email("user@пример.рф"), "user@xn--e1afmkfd.xn--p1ai");
+        assert_eq!(normalize_email("user@xn--bcher-kva.de"), "user@xn--bcher-kva.de");
+    }
+
+    #[test]
+    fn test_normalize_name() {
+        // "e" followed by combining acute accent is composed into "é"
+        assert_eq!(normalize_name("Jose\u{301}"), "Jos\u{e9}");
+        assert_eq!(normalize_name("Jos\u{e9}"), "Jos\u{e9}");
+    }
+}
@@ -0,0 +1,12 @@
+//! Models for ids of single-use signed tokens, recorded when the token is applied
+use std::time::SystemTime;
+
+use schema::used_tokens;
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "used_tokens"]
+pub struct NewUsedToken {
+    /// Id of the token prefixed with its kind, e.g. `signed_action:<uuid>`
+    pub id: String,
+    pub expires_at: SystemTime,
+}
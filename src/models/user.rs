@@ -11,6 +11,9 @@ use validator::{Validate, ValidationError};
 use stq_static_resources::Gender;
 use stq_types::{Alpha3, EmarsysId, UserId};
 
+use models::phone::is_separator;
+use models::unicode::{normalize_name, validate_email};
+use models::user_tag::normalize_tag;
 use models::NewIdentity;
 use schema::users;
 
@@ -19,7 +22,8 @@ pub fn validate_phone(phone: &str) -> Result<(), ValidationError> {
         static ref PHONE_VALIDATION_RE: Regex = Regex::new(r"^\+?\d{7}\d*$").unwrap();
     }
 
-    if PHONE_VALIDATION_RE.is_match(phone) {
+    let phone = phone.chars().filter(|c| !is_separator(*c)).collect::<String>();
+    if PHONE_VALIDATION_RE.is_match(&phone) {
         Ok(())
     } else {
         Err(ValidationError {
@@ -55,13 +59,41 @@ pub struct User {
     pub country: Option<Alpha3>,
     pub referer: Option<String>,
     pub revoke_before: SystemTime,
+    /// Region where user data must be stored, assigned at registration
+    pub data_region: String,
+    /// PII sealed by the data key of the user, see `UserPii`
+    #[serde(skip)]
+    pub sealed_pii: Option<Vec<u8>>,
+    /// Deletion of the user is requested and awaits confirmations of dependent services
+    pub pending_deletion: bool,
+}
+
+/// Current user with computed profile fields
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CurrentUser {
+    #[serde(flatten)]
+    pub user: User,
+    /// Weighted percentage of filled profile fields
+    pub profile_completion: u8,
+    pub is_birthday: bool,
+}
+
+/// Profile completion over a page of users
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ProfileCompletionStats {
+    pub users_count: u64,
+    pub average: f64,
+    /// Users count by completion tens, the last bucket includes 100%
+    pub histogram: Vec<u64>,
+    /// Start of the next page, if there may be more users
+    pub next_offset: Option<UserId>,
 }
 
 /// Payload for creating users
 #[derive(Debug, Serialize, Deserialize, Insertable, Validate, Clone)]
 #[table_name = "users"]
 pub struct NewUser {
-    #[validate(email(code = "not_valid", message = "Invalid email format"))]
+    #[validate(custom = "validate_email")]
     pub email: String,
     #[validate(custom = "validate_phone")]
     pub phone: Option<String>,
@@ -79,6 +111,11 @@ pub struct NewUser {
     pub utm_marks: Option<serde_json::Value>,
     pub country: Option<Alpha3>,
     pub referer: Option<String>,
+    /// Assigned by service at registration
+    pub data_region: Option<String>,
+    /// Allocated by service at registration if users are sharded, otherwise assigned by db
+    #[serde(skip)]
+    pub id: Option<UserId>,
 }
 
 /// Payload for updating users
@@ -101,7 +138,19 @@ pub struct UpdateUser {
     pub emarsys_id: Option<EmarsysId>,
 }
 
+impl NewUser {
+    /// Normalizes names to NFC before storage
+    pub fn normalize_names(&mut self) {
+        normalize_names(&mut [&mut self.first_name, &mut self.last_name, &mut self.middle_name]);
+    }
+}
+
 impl UpdateUser {
+    /// Normalizes names to NFC before storage
+    pub fn normalize_names(&mut self) {
+        normalize_names(&mut [&mut self.first_name, &mut self.last_name, &mut self.middle_name]);
+    }
+
     pub fn is_empty(&self) -> bool {
         self.phone.is_none()
             && self.first_name.is_none()
@@ -128,18 +177,48 @@ impl From<NewIdentity> for NewUser {
             utm_marks: None,
             country: None,
             referer: None,
+            data_region: None,
+            id: None,
         }
     }
 }
 
 /// Payload for searching for user
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct UsersSearchTerms {
     pub email: Option<String>,
     pub phone: Option<String>,
     pub first_name: Option<String>,
     pub last_name: Option<String>,
     pub is_blocked: Option<bool>,
+    pub data_region: Option<String>,
+    /// Users having all of the tags
+    pub tags: Option<Vec<String>>,
+}
+
+impl UsersSearchTerms {
+    /// Normalizes names to NFC, so that they match names normalized on storage
+    pub fn normalize_names(&mut self) {
+        normalize_names(&mut [&mut self.first_name, &mut self.last_name]);
+    }
+
+    /// Normalizes tags, so that they match tags normalized on storage
+    pub fn normalize_tags(&mut self) {
+        if let Some(ref mut tags) = self.tags {
+            for tag in tags.iter_mut() {
+                *tag = normalize_tag(tag);
+            }
+        }
+    }
+}
+
+fn normalize_names(names: &mut [&mut Option<String>]) {
+    for name in names.iter_mut() {
+        if let Some(ref mut name) = **name {
+            let normalized = normalize_name(name);
+            *name = normalized;
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
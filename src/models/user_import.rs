@@ -0,0 +1,123 @@
+//! Models for importing users from other systems along with their password hashes. Foreign hashes
+//! are verified with their own algorithm until the first login, when the password is re-hashed
+//! by `password_create`.
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::VarChar;
+use failure::Error as FailureError;
+use uuid::Uuid;
+use validator::Validate;
+
+use models::unicode::validate_email;
+use models::NewUser;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(rename_all = "snake_case")]
+#[sql_type = "VarChar"]
+pub enum PasswordHashFormat {
+    /// Modular crypt format, `$2a$`, `$2b$` or `$2y$` prefixed
+    Bcrypt,
+    /// PHC string format, `$argon2i$` or `$argon2id$` prefixed
+    Argon2,
+    /// Hex-encoded unsalted SHA-1 of the password
+    Sha1,
+}
+
+impl PasswordHashFormat {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            PasswordHashFormat::Bcrypt => "bcrypt",
+            PasswordHashFormat::Argon2 => "argon2",
+            PasswordHashFormat::Sha1 => "sha1",
+        }
+    }
+
+    /// Whether the hash looks like a hash of the format, so that broken imports fail early
+    /// rather than on login
+    pub fn is_valid_hash(&self, hash: &str) -> bool {
+        match *self {
+            PasswordHashFormat::Bcrypt => hash.len() == 60 && ["$2a$", "$2b$", "$2y$"].iter().any(|prefix| hash.starts_with(prefix)),
+            PasswordHashFormat::Argon2 => hash.starts_with("$argon2"),
+            PasswordHashFormat::Sha1 => hash.len() == 40 && hash.chars().all(|c| c.is_ascii_hexdigit()),
+        }
+    }
+}
+
+impl fmt::Display for PasswordHashFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for PasswordHashFormat {
+    type Err = FailureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bcrypt" => Ok(PasswordHashFormat::Bcrypt),
+            "argon2" => Ok(PasswordHashFormat::Argon2),
+            "sha1" => Ok(PasswordHashFormat::Sha1),
+            _ => Err(format_err!("Unknown password hash format '{}'", s)),
+        }
+    }
+}
+
+impl ToSql<VarChar, Pg> for PasswordHashFormat {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        ToSql::<VarChar, Pg>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<VarChar, Pg> for PasswordHashFormat {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        let format: String = FromSql::<VarChar, Pg>::from_sql(bytes)?;
+        format.parse().map_err(|e: FailureError| e.to_string().into())
+    }
+}
+
+/// Payload for importing user with password hash from another system
+#[derive(Clone, Debug, Serialize, Deserialize, Validate)]
+pub struct ImportedUser {
+    #[validate(custom = "validate_email")]
+    pub email: String,
+    pub password_hash: String,
+    pub password_hash_format: PasswordHashFormat,
+    #[validate(length(min = "1", message = "First name must not be empty"))]
+    pub first_name: Option<String>,
+    #[validate(length(min = "1", message = "Last name must not be empty"))]
+    pub last_name: Option<String>,
+    /// E-mail was verified by the other system
+    #[serde(default)]
+    pub email_verified: bool,
+    /// User has to change the password on the first login, see `Identity::must_change_password`
+    #[serde(default)]
+    pub must_change_password: bool,
+}
+
+impl<'a> From<&'a ImportedUser> for NewUser {
+    fn from(imported: &'a ImportedUser) -> Self {
+        NewUser {
+            email: imported.email.clone(),
+            phone: None,
+            first_name: imported.first_name.clone(),
+            last_name: imported.last_name.clone(),
+            middle_name: None,
+            gender: None,
+            birthdate: None,
+            last_login_at: SystemTime::now(),
+            saga_id: Uuid::new_v4().to_string(),
+            referal: None,
+            utm_marks: None,
+            country: None,
+            referer: None,
+            data_region: None,
+            id: None,
+        }
+    }
+}
@@ -0,0 +1,109 @@
+//! Models for data keys of users. PII of a user is sealed by the data key of the user,
+//! deleting the key makes the sealed PII unreadable wherever it was copied, e.g. to backups.
+use std::mem;
+use std::time::SystemTime;
+
+use chrono::NaiveDate;
+
+use stq_types::UserId;
+
+use key_store_schema::user_keys;
+use models::{NewUser, UpdateUser, User};
+use schema::users;
+
+#[derive(Clone, Debug, Queryable)]
+pub struct UserKey {
+    pub user_id: UserId,
+    /// Data key wrapped by the master key
+    pub data_key: Vec<u8>,
+    pub created_at: SystemTime,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "user_keys"]
+pub struct NewUserKey {
+    pub user_id: UserId,
+    pub data_key: Vec<u8>,
+}
+
+/// Columns of PII of sealed users, email, phone and names hold their blind indexes
+#[derive(Clone, Debug, AsChangeset)]
+#[table_name = "users"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct SealedPiiColumns {
+    pub email: String,
+    pub phone: Option<String>,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub middle_name: Option<String>,
+    pub birthdate: Option<NaiveDate>,
+    pub sealed_pii: Option<Vec<u8>>,
+}
+
+/// PII sealed by the data key of the user. Columns of email, phone and names of sealed users
+/// hold blind indexes of the values, the rest are empty.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct UserPii {
+    /// Missing in PII sealed before emails and phones were, the columns hold them then
+    pub email: Option<String>,
+    pub phone: Option<String>,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub middle_name: Option<String>,
+    pub birthdate: Option<NaiveDate>,
+}
+
+impl UserPii {
+    pub fn of_user(user: &User) -> Self {
+        Self {
+            email: Some(user.email.clone()),
+            phone: user.phone.clone(),
+            first_name: user.first_name.clone(),
+            last_name: user.last_name.clone(),
+            middle_name: user.middle_name.clone(),
+            birthdate: user.birthdate,
+        }
+    }
+
+    /// Takes PII out of the payload, so that it is not stored in plain text
+    pub fn take_from_new_user(payload: &mut NewUser) -> Self {
+        Self {
+            email: Some(mem::replace(&mut payload.email, String::new())),
+            phone: payload.phone.take(),
+            first_name: payload.first_name.take(),
+            last_name: payload.last_name.take(),
+            middle_name: payload.middle_name.take(),
+            birthdate: payload.birthdate.take(),
+        }
+    }
+
+    /// Moves fields changed by the payload out of it into this PII
+    pub fn take_update(&mut self, payload: &mut UpdateUser) {
+        if let Some(phone) = payload.phone.take() {
+            self.phone = Some(phone);
+        }
+        if let Some(first_name) = payload.first_name.take() {
+            self.first_name = Some(first_name);
+        }
+        if let Some(last_name) = payload.last_name.take() {
+            self.last_name = Some(last_name);
+        }
+        if let Some(middle_name) = payload.middle_name.take() {
+            self.middle_name = Some(middle_name);
+        }
+        if let Some(birthdate) = payload.birthdate.take() {
+            self.birthdate = Some(birthdate);
+        }
+    }
+
+    pub fn apply(self, user: &mut User) {
+        if let Some(email) = self.email {
+            user.email = email;
+            user.phone = self.phone;
+        }
+        user.first_name = self.first_name;
+        user.last_name = self.last_name;
+        user.middle_name = self.middle_name;
+        user.birthdate = self.birthdate;
+    }
+}
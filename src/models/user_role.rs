@@ -15,6 +15,14 @@ pub struct UserRole {
     pub name: UsersRole,
     pub data: Option<serde_json::Value>,
     pub id: RoleId,
+    /// Time-boxed roles are ignored after this time and revoked by the scheduler
+    pub expires_at: Option<SystemTime>,
+}
+
+impl UserRole {
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires_at.map_or(false, |expires_at| expires_at <= now)
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, Insertable)]
@@ -24,6 +32,9 @@ pub struct NewUserRole {
     pub user_id: UserId,
     pub name: UsersRole,
     pub data: Option<serde_json::Value>,
+    /// Role is granted until this time, e.g. temporary admin access for on-call engineers
+    #[serde(default)]
+    pub expires_at: Option<SystemTime>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -0,0 +1,66 @@
+//! Models for tagging users. Tags group users into marketing segments, e.g. beta testers or VIP,
+//! so that new segments do not require schema changes.
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+use regex::Regex;
+use validator::ValidationError;
+
+use stq_types::UserId;
+
+#[derive(Clone, Debug, Serialize, Deserialize, Queryable)]
+pub struct UserTag {
+    pub user_id: UserId,
+    pub tag: String,
+    pub created_at: SystemTime,
+}
+
+/// Payload for tagging a user
+#[derive(Clone, Debug, Serialize, Deserialize, Validate)]
+pub struct NewUserTag {
+    #[validate(custom = "validate_tag")]
+    pub tag: String,
+}
+
+/// Tags are compared case insensitive, so they are stored lowercase
+pub fn normalize_tag(tag: &str) -> String {
+    tag.trim().to_lowercase()
+}
+
+/// Validates that tag has from 1 to 64 latin letters, digits, `_` or `-`
+pub fn validate_tag(tag: &str) -> Result<(), ValidationError> {
+    lazy_static! {
+        static ref TAG_RE: Regex = Regex::new(r"^[a-z0-9_-]{1,64}$").unwrap();
+    }
+
+    if TAG_RE.is_match(&normalize_tag(tag)) {
+        Ok(())
+    } else {
+        Err(ValidationError {
+            code: Cow::from("not_valid"),
+            message: Some(Cow::from("Tag should be from 1 to 64 latin letters, digits, '_' or '-'")),
+            params: HashMap::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_tag() {
+        assert_eq!(validate_tag("beta_testers").is_ok(), true);
+        assert_eq!(validate_tag(" VIP ").is_ok(), true);
+        assert_eq!(validate_tag("").is_err(), true);
+        assert_eq!(validate_tag("vip customers").is_err(), true);
+        assert_eq!(validate_tag("vip'--").is_err(), true);
+        assert_eq!(validate_tag(&"a".repeat(65)).is_err(), true);
+    }
+
+    #[test]
+    fn test_normalize_tag() {
+        assert_eq!(normalize_tag(" VIP "), "vip");
+    }
+}
@@ -0,0 +1,51 @@
+//! Models for waitlist. While registration is invite-only, people leave their emails
+//! and confirm them, admins convert confirmed entries into invites.
+use std::time::SystemTime;
+
+use validator::Validate;
+
+use models::unicode::validate_email;
+use schema::waitlist;
+
+#[derive(Clone, Debug, Serialize, Queryable)]
+pub struct WaitlistEntry {
+    pub email: String,
+    #[serde(skip_serializing)]
+    pub token: String,
+    pub confirmed: bool,
+    pub invite_code: Option<String>,
+    pub created_at: SystemTime,
+    pub updated_at: SystemTime,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "waitlist"]
+pub struct NewWaitlistEntry {
+    pub email: String,
+    pub token: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Validate)]
+pub struct WaitlistRequest {
+    #[validate(custom = "validate_email")]
+    pub email: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WaitlistConfirm {
+    pub token: String,
+}
+
+/// Payload for inviting `count` people who confirmed their emails first
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WaitlistInvitesPayload {
+    pub count: i64,
+    pub expiration_s: Option<u64>,
+}
+
+/// Invite issued to the person from the waitlist, to be sent to `email`
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct WaitlistInvite {
+    pub email: String,
+    pub invite_code: String,
+}
@@ -0,0 +1,164 @@
+//! Repo for access_tokens table, personal access tokens of users
+
+use std::time::SystemTime;
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::sql_types::Integer;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use uuid::Uuid;
+
+use stq_types::UserId;
+
+use repos::legacy_acl::*;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::*;
+use models::{AccessToken, NewAccessToken};
+use schema::access_tokens::dsl::*;
+
+/// AccessTokens repository, responsible for handling personal access tokens
+pub trait AccessTokensRepo {
+    /// Returns tokens of the user
+    fn list_for_user(&self, user_id: UserId) -> RepoResult<Vec<AccessToken>>;
+
+    /// Creates token
+    fn create(&self, payload: NewAccessToken) -> RepoResult<AccessToken>;
+
+    /// Deletes token of the user, returns `None` if there was no such token
+    fn delete(&self, user_id: UserId, token_id: Uuid) -> RepoResult<Option<AccessToken>>;
+
+    /// Find token by hash, tokens are presented by API consumers not logged in, no ACL check
+    fn find_by_hash(&self, token_hash: String) -> RepoResult<Option<AccessToken>>;
+
+    /// Saves last time the token was used, no ACL check
+    fn touch(&self, token_id: Uuid, used_at: SystemTime) -> RepoResult<()>;
+
+    /// Locks tokens of the user until the end of current transaction, so that concurrent
+    /// creations are checked against the max number of tokens one by one
+    fn lock_user(&self, user_id: UserId) -> RepoResult<()>;
+}
+
+/// Implementation of AccessTokens trait
+pub struct AccessTokensRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, AccessToken>>,
+    pub db_conn: &'a T,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> AccessTokensRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, AccessToken>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> AccessTokensRepo
+    for AccessTokensRepoImpl<'a, T>
+{
+    /// Returns tokens of the user
+    fn list_for_user(&self, user_id_arg: UserId) -> RepoResult<Vec<AccessToken>> {
+        let query = access_tokens.filter(user_id.eq(user_id_arg)).order(created_at);
+        query
+            .get_results::<AccessToken>(self.db_conn)
+            .map_err(From::from)
+            .and_then(|tokens: Vec<AccessToken>| {
+                for token in &tokens {
+                    acl::check(&*self.acl, Resource::AccessTokens, Action::Read, self, Some(token))?;
+                }
+                Ok(tokens)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("List access tokens of user {} error occured", user_id_arg))
+                    .into()
+            })
+    }
+
+    /// Creates token
+    fn create(&self, payload: NewAccessToken) -> RepoResult<AccessToken> {
+        let query = diesel::insert_into(access_tokens).values(&payload);
+        query
+            .get_result::<AccessToken>(self.db_conn)
+            .map_err(From::from)
+            .and_then(|token: AccessToken| {
+                acl::check(&*self.acl, Resource::AccessTokens, Action::Create, self, Some(&token))?;
+                Ok(token)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!(
+                    "Create access token {} of user {} error occured",
+                    payload.name, payload.user_id
+                ))
+                .into()
+            })
+    }
+
+    /// Deletes token of the user, returns `None` if there was no such token
+    fn delete(&self, user_id_arg: UserId, token_id: Uuid) -> RepoResult<Option<AccessToken>> {
+        let filtered = access_tokens.filter(id.eq(token_id)).filter(user_id.eq(user_id_arg));
+        let query = diesel::delete(filtered);
+        query
+            .get_result::<AccessToken>(self.db_conn)
+            .optional()
+            .map_err(From::from)
+            .and_then(|token: Option<AccessToken>| {
+                if let Some(ref token) = token {
+                    acl::check(&*self.acl, Resource::AccessTokens, Action::Delete, self, Some(token))?;
+                }
+                Ok(token)
+            })
+            .map_err(|e: FailureError| e.context(format!("Delete access token {} error occured", token_id)).into())
+    }
+
+    /// Find token by hash, tokens are presented by API consumers not logged in, no ACL check
+    fn find_by_hash(&self, token_hash_arg: String) -> RepoResult<Option<AccessToken>> {
+        let query = access_tokens.filter(token_hash.eq(token_hash_arg));
+        query
+            .get_result::<AccessToken>(self.db_conn)
+            .optional()
+            .map_err(|e| e.context("Find access token by hash error occured").into())
+    }
+
+    /// Saves last time the token was used, no ACL check
+    fn touch(&self, token_id: Uuid, used_at: SystemTime) -> RepoResult<()> {
+        let query = diesel::update(access_tokens.find(token_id)).set(last_used_at.eq(used_at));
+        query
+            .execute(self.db_conn)
+            .map(|_| ())
+            .map_err(|e| e.context(format!("Touch access token {} error occured", token_id)).into())
+    }
+
+    /// Locks tokens of the user until the end of current transaction, so that concurrent
+    /// creations are checked against the max number of tokens one by one
+    fn lock_user(&self, user_id_arg: UserId) -> RepoResult<()> {
+        diesel::sql_query("SELECT pg_advisory_xact_lock(hashtext('access_tokens'), $1)")
+            .bind::<Integer, _>(user_id_arg.0)
+            .execute(self.db_conn)
+            .map(|_| ())
+            .map_err(|e| {
+                e.context(format!("Lock access tokens of user {} error occured", user_id_arg))
+                    .into()
+            })
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, AccessToken>
+    for AccessTokensRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, user_id_arg: UserId, scope: &Scope, obj: Option<&AccessToken>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => {
+                if let Some(token) = obj {
+                    token.user_id == user_id_arg
+                } else {
+                    false
+                }
+            }
+        }
+    }
+}
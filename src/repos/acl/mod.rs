@@ -1,14 +1,17 @@
 //! Repos is a module responsible for interacting with access control lists
 
-#[macro_use]
-pub mod macros;
 pub mod legacy_acl;
+pub mod policies;
 pub mod roles_cache;
 
-pub use self::roles_cache::RolesCacheImpl;
+pub use self::policies::Policies;
+pub use self::roles_cache::{RolesCacheImpl, ROLES_INVALIDATION_CHANNEL, ROLES_NOTIFY_CHANNELS};
 
-use std::collections::HashMap;
-use std::rc::Rc;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json;
 
 use errors::Error;
 use failure::Error as FailureError;
@@ -18,6 +21,7 @@ use stq_types::{UserId, UsersRole};
 
 use super::legacy_acl::{Acl, CheckScope};
 use models::authorization::*;
+use models::RoleAccessPolicy;
 
 pub fn check<T>(
     acl: &Acl<Resource, Action, Scope, FailureError, T>,
@@ -37,54 +41,44 @@ pub fn check<T>(
     })
 }
 
+lazy_static! {
+    /// Built-in policies, parsed once on the first check or at warmup
+    static ref BUILTIN_POLICIES: Arc<Policies> = Arc::new(Policies::builtin());
+}
+
+/// Parses built-in policies ahead of the first request
+pub fn warm_up_permissions() {
+    ::lazy_static::initialize(&BUILTIN_POLICIES);
+}
+
+/// Built-in policies extended by the rules, e.g. from config
+pub fn policies_with_rules(rules: Vec<PolicyRule>) -> Arc<Policies> {
+    Arc::new((**BUILTIN_POLICIES).clone().with_rules(rules))
+}
+
 /// ApplicationAcl contains main logic for manipulation with resources
 #[derive(Clone)]
 pub struct ApplicationAcl {
-    acls: Rc<HashMap<UsersRole, Vec<Permission>>>,
+    policies: Arc<Policies>,
     roles: Vec<UsersRole>,
     user_id: UserId,
 }
 
 impl ApplicationAcl {
     pub fn new(roles: Vec<UsersRole>, user_id: UserId) -> Self {
-        let mut hash = ::std::collections::HashMap::new();
-        hash.insert(
-            UsersRole::Superuser,
-            vec![
-                permission!(Resource::Users, Action::Read),
-                permission!(Resource::Users, Action::Create),
-                permission!(Resource::Users, Action::Block),
-                permission!(Resource::Users, Action::Delete),
-                permission!(Resource::Users, Action::Update),
-                permission!(Resource::UserRoles),
-            ],
-        );
-        hash.insert(
-            UsersRole::User,
-            vec![
-                permission!(Resource::Users, Action::Read, Scope::Owned),
-                permission!(Resource::Users, Action::Update, Scope::Owned),
-                permission!(Resource::UserRoles, Action::Read, Scope::Owned),
-            ],
-        );
-        hash.insert(
-            UsersRole::Moderator,
-            vec![
-                permission!(Resource::Users, Action::Read),
-                permission!(Resource::Users, Action::Block),
-                permission!(Resource::UserRoles, Action::Read),
-            ],
-        );
-
         ApplicationAcl {
-            acls: Rc::new(hash),
+            policies: BUILTIN_POLICIES.clone(),
             roles,
             user_id,
         }
     }
+
+    pub fn with_policies(self, policies: Arc<Policies>) -> Self {
+        Self { policies, ..self }
+    }
 }
 
-impl<T> Acl<Resource, Action, Scope, FailureError, T> for ApplicationAcl {
+impl<T: Serialize> Acl<Resource, Action, Scope, FailureError, T> for ApplicationAcl {
     fn allows(
         &self,
         resource: Resource,
@@ -92,17 +86,29 @@ impl<T> Acl<Resource, Action, Scope, FailureError, T> for ApplicationAcl {
         scope_checker: &CheckScope<Scope, T>,
         obj: Option<&T>,
     ) -> Result<bool, FailureError> {
-        let empty: Vec<Permission> = Vec::new();
         let user_id = &self.user_id;
-        let hashed_acls = self.acls.clone();
-        let acls = self
+        let policies = &self.policies;
+        let permissions = self
             .roles
             .iter()
-            .flat_map(|role| hashed_acls.get(role).unwrap_or(&empty))
+            .flat_map(|role| policies.permissions(role))
             .filter(|permission| (permission.resource == resource) && ((permission.action == action) || (permission.action == Action::All)))
-            .filter(|permission| scope_checker.is_in_scope(*user_id, &permission.scope, obj));
+            .filter(|permission| scope_checker.is_in_scope(*user_id, &permission.scope, obj))
+            .collect::<Vec<_>>();
 
-        if acls.count() > 0 {
+        // the resource is serialized only if conditions are to be checked on it
+        let allowed = permissions.iter().any(|permission| permission.conditions.is_empty())
+            || match (permissions.is_empty(), obj) {
+                (false, Some(obj)) => {
+                    let attributes = serde_json::to_value(obj)?;
+                    permissions
+                        .iter()
+                        .any(|permission| permission.conditions.iter().all(|condition| condition.holds(&attributes)))
+                }
+                _ => false,
+            };
+
+        if allowed {
             Ok(true)
         } else {
             error!("Denied request from user {} to do {} on {}.", user_id, action, resource);
@@ -111,12 +117,28 @@ impl<T> Acl<Resource, Action, Scope, FailureError, T> for ApplicationAcl {
     }
 }
 
+/// Roles of the user in effect at the moment, roles outside the window of their access policy are dropped
+pub fn roles_in_effect(roles: Vec<UsersRole>, policies: &[RoleAccessPolicy], now: DateTime<Utc>) -> Vec<UsersRole> {
+    roles
+        .into_iter()
+        .filter(|role| {
+            policies
+                .iter()
+                .find(|policy| policy.role == *role)
+                .map_or(true, |policy| policy.is_open(now))
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use std::time::SystemTime;
 
+    use chrono::{NaiveTime, TimeZone, Utc};
     use stq_types::{RoleId, UserId, UsersRole};
 
+    use serde_json;
+
     use repos::legacy_acl::{Acl, CheckScope};
 
     use models::*;
@@ -147,6 +169,9 @@ mod tests {
             referer: None,
             utm_marks: None,
             revoke_before: SystemTime::now(),
+            data_region: "global".to_string(),
+            sealed_pii: None,
+            pending_deletion: false,
         }
     }
 
@@ -337,6 +362,7 @@ mod tests {
             data: None,
             created_at: SystemTime::now(),
             updated_at: SystemTime::now(),
+            expires_at: None,
         };
         assert_eq!(
             acl.allows(Resource::UserRoles, Action::All, &s, Some(&resource)).unwrap(),
@@ -377,6 +403,7 @@ mod tests {
             data: None,
             created_at: SystemTime::now(),
             updated_at: SystemTime::now(),
+            expires_at: None,
         };
 
         assert_eq!(
@@ -405,4 +432,73 @@ mod tests {
             "ACL does not allow read actions on all user roles for moderator."
         );
     }
+
+    fn business_hours(role: UsersRole) -> RoleAccessPolicy {
+        RoleAccessPolicy {
+            role,
+            starts_at: NaiveTime::from_hms(8, 0, 0),
+            ends_at: NaiveTime::from_hms(20, 0, 0),
+            utc_offset_minutes: 180,
+            restrict_login: false,
+            created_at: SystemTime::now(),
+            updated_at: SystemTime::now(),
+        }
+    }
+
+    #[test]
+    fn test_roles_in_business_hours() {
+        let policies = vec![business_hours(UsersRole::Superuser)];
+        let roles = vec![UsersRole::User, UsersRole::Superuser];
+        let now = Utc.ymd(2019, 3, 17).and_hms(10, 0, 0);
+        assert_eq!(roles_in_effect(roles, &policies, now), vec![UsersRole::User, UsersRole::Superuser]);
+    }
+
+    #[test]
+    fn test_roles_out_of_business_hours() {
+        let policies = vec![business_hours(UsersRole::Superuser)];
+        let roles = vec![UsersRole::User, UsersRole::Superuser, UsersRole::Moderator];
+        let now = Utc.ymd(2019, 3, 17).and_hms(17, 30, 0);
+        assert_eq!(roles_in_effect(roles, &policies, now), vec![UsersRole::User, UsersRole::Moderator]);
+    }
+
+    #[test]
+    fn test_policy_window_over_midnight() {
+        let policy = RoleAccessPolicy {
+            starts_at: NaiveTime::from_hms(22, 0, 0),
+            ends_at: NaiveTime::from_hms(6, 0, 0),
+            utc_offset_minutes: 0,
+            ..business_hours(UsersRole::Moderator)
+        };
+        assert_eq!(policy.is_open(Utc.ymd(2019, 3, 17).and_hms(23, 0, 0)), true);
+        assert_eq!(policy.is_open(Utc.ymd(2019, 3, 17).and_hms(3, 0, 0)), true);
+        assert_eq!(policy.is_open(Utc.ymd(2019, 3, 17).and_hms(12, 0, 0)), false);
+    }
+
+    #[test]
+    fn test_builtin_policies() {
+        let policies = Policies::builtin();
+        assert_eq!(policies.permissions(&UsersRole::Superuser).is_empty(), false);
+        assert_eq!(policies.permissions(&UsersRole::User).is_empty(), false);
+        assert_eq!(policies.permissions(&UsersRole::Moderator).is_empty(), false);
+    }
+
+    #[test]
+    fn test_policy_rule_with_conditions() {
+        let rules: Vec<PolicyRule> = serde_json::from_str(
+            r#"[{"role": "moderator", "resource": "users", "action": "update", "conditions": [{"field": "email_verified", "one_of": [false]}]}]"#,
+        )
+        .unwrap();
+        let acl = ApplicationAcl::new(vec![UsersRole::Moderator], UserId(1)).with_policies(policies_with_rules(rules));
+        let s = ScopeChecker::default();
+        let unverified = create_user(UserId(2));
+        let verified = User {
+            email_verified: true,
+            ..create_user(UserId(3))
+        };
+
+        assert_eq!(acl.allows(Resource::Users, Action::Update, &s, Some(&unverified)).unwrap(), true);
+        assert_eq!(acl.allows(Resource::Users, Action::Update, &s, Some(&verified)).unwrap(), false);
+        assert_eq!(acl.allows(Resource::Users, Action::Update, &s, None::<&User>).unwrap(), false);
+        assert_eq!(acl.allows(Resource::Users, Action::Read, &s, Some(&verified)).unwrap(), true);
+    }
 }
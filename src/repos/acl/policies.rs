@@ -0,0 +1,45 @@
+//! Policies granting permissions to roles. Built-in rules are loaded from `acl/default_policies.json`,
+//! deployments add their own with `acl.rules` config, so that granting roles access to resources
+//! does not require code changes. Rules only grant permissions, there are no deny rules.
+
+use std::collections::HashMap;
+
+use serde_json;
+
+use stq_types::UsersRole;
+
+use models::authorization::*;
+
+/// Permissions of every role, built from policy rules
+#[derive(Clone, Debug, Default)]
+pub struct Policies {
+    permissions: HashMap<UsersRole, Vec<Permission>>,
+}
+
+impl Policies {
+    pub fn new(rules: Vec<PolicyRule>) -> Self {
+        Policies::default().with_rules(rules)
+    }
+
+    /// Built-in policies of the service
+    pub fn builtin() -> Self {
+        Policies::new(builtin_rules())
+    }
+
+    /// Adds permissions granted by the rules
+    pub fn with_rules(mut self, rules: Vec<PolicyRule>) -> Self {
+        for rule in rules {
+            self.permissions.entry(rule.role).or_insert_with(Vec::new).push(rule.into());
+        }
+        self
+    }
+
+    /// Permissions granted to the role
+    pub fn permissions(&self, role: &UsersRole) -> &[Permission] {
+        self.permissions.get(role).map_or(&[], Vec::as_slice)
+    }
+}
+
+fn builtin_rules() -> Vec<PolicyRule> {
+    serde_json::from_str(include_str!("../../../acl/default_policies.json")).expect("Invalid built-in ACL policies")
+}
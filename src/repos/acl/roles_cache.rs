@@ -1,14 +1,30 @@
-//! RolesCache is a module that caches received from db information about user and his roles
+//! RolesCache is a module that caches received from db information about user and his roles.
+//! Roles are looked up on every authenticated request, so an optional in-process LRU
+//! is consulted before the shared cache. Tokens are verified by the gateway, which forwards
+//! only user id, so entries are keyed by user id and dropped on role changes and token revocation.
+//! Removals are published to other instances, so that they drop their in-process copies
+//! immediately, either over Redis or by database triggers. Entries also expire after a short ttl, in case an invalidation is missed.
+
+use std::sync::Mutex;
+use std::time::Duration;
 
-use failure::Fail;
-use stq_cache::cache::Cache;
 use stq_types::{UserId, UsersRole};
 
+use cache::lru::LruCache;
+use cache::{Cache, InvalidationPublisher};
+
+/// Channel of user ids whose roles were changed
+pub const ROLES_INVALIDATION_CHANNEL: &'static str = "users:roles_invalidation";
+/// Database notification channels with ids of users whose roles or access were changed, see triggers in migrations
+pub const ROLES_NOTIFY_CHANNELS: &'static [&'static str] = &["user_roles_changed", "users_changed"];
+
 pub struct RolesCacheImpl<C>
 where
     C: Cache<Vec<UsersRole>>,
 {
     cache: C,
+    local: Option<Mutex<LruCache<UserId, Vec<UsersRole>>>>,
+    publisher: Option<Box<InvalidationPublisher>>,
 }
 
 impl<C> RolesCacheImpl<C>
@@ -16,35 +32,166 @@ where
     C: Cache<Vec<UsersRole>>,
 {
     pub fn new(cache: C) -> Self {
-        RolesCacheImpl { cache }
+        RolesCacheImpl {
+            cache,
+            local: None,
+            publisher: None,
+        }
+    }
+
+    /// Enables in-process LRU in front of the shared cache, zero capacity disables it
+    pub fn with_local_cache(self, capacity: usize, ttl: Duration) -> Self {
+        RolesCacheImpl {
+            local: if capacity > 0 {
+                Some(Mutex::new(LruCache::new(capacity, ttl)))
+            } else {
+                None
+            },
+            ..self
+        }
+    }
+
+    /// Enables publishing of removals to other instances
+    pub fn with_invalidation_publisher(self, publisher: Box<InvalidationPublisher>) -> Self {
+        RolesCacheImpl {
+            publisher: Some(publisher),
+            ..self
+        }
+    }
+
+    pub fn has_local_cache(&self) -> bool {
+        self.local.is_some()
     }
 
     pub fn get(&self, user_id: UserId) -> Option<Vec<UsersRole>> {
+        if let Some(roles) = self.local_get(user_id) {
+            return Some(roles);
+        }
+
         debug!("Getting roles from RolesCache at key '{}'", user_id);
 
-        self.cache.get(user_id.to_string().as_str()).unwrap_or_else(|err| {
+        let roles = self.cache.get(user_id.to_string().as_str()).unwrap_or_else(|err| {
             let err = err.context(format!("Failed to get roles from RolesCache at key '{}'", user_id));
             error!("{}", err);
             None
-        })
+        });
+        if let Some(ref roles) = roles {
+            self.local_set(user_id, roles.clone());
+        }
+        roles
     }
 
     pub fn remove(&self, user_id: UserId) -> bool {
         debug!("Removing roles from RolesCache at key '{}'", user_id);
 
-        self.cache.remove(user_id.to_string().as_str()).unwrap_or_else(|err| {
+        self.local_remove(user_id);
+        let removed = self.cache.remove(user_id.to_string().as_str()).unwrap_or_else(|err| {
             let err = err.context(format!("Failed to remove roles from RolesCache at key '{}'", user_id));
             error!("{}", err);
             false
-        })
+        });
+        if let Some(ref publisher) = self.publisher {
+            publisher.publish(user_id.to_string().as_str()).unwrap_or_else(|err| {
+                let err = err.context(format!("Failed to publish invalidation of RolesCache at key '{}'", user_id));
+                error!("{}", err);
+            });
+        }
+        removed
     }
 
     pub fn set(&self, user_id: UserId, roles: Vec<UsersRole>) {
         debug!("Setting roles in RolesCache at key '{}'", user_id);
 
+        self.local_set(user_id, roles.clone());
         self.cache.set(user_id.to_string().as_str(), roles).unwrap_or_else(|err| {
             let err = err.context(format!("Failed to set roles in RolesCache at key '{}'", user_id));
             error!("{}", err);
         })
     }
+
+    fn local_get(&self, user_id: UserId) -> Option<Vec<UsersRole>> {
+        self.local
+            .as_ref()
+            .and_then(|local| local.lock().ok())
+            .and_then(|mut local| local.get(&user_id))
+    }
+
+    fn local_set(&self, user_id: UserId, roles: Vec<UsersRole>) {
+        if let Some(mut local) = self.local.as_ref().and_then(|local| local.lock().ok()) {
+            local.insert(user_id, roles);
+        }
+    }
+
+    /// Drops roles from both cache tiers without publishing the removal, used for database notifications,
+    /// which are received by every instance, so that the shared tier is dropped even if it is in-process too
+    pub fn invalidate(&self, user_id: UserId) {
+        debug!("Invalidating roles in RolesCache at key '{}'", user_id);
+
+        self.local_remove(user_id);
+        self.cache.remove(user_id.to_string().as_str()).unwrap_or_else(|err| {
+            let err = err.context(format!("Failed to invalidate roles in RolesCache at key '{}'", user_id));
+            error!("{}", err);
+            false
+        });
+    }
+
+    /// Drops roles from in-process cache only, used for invalidations received from other instances
+    pub fn local_remove(&self, user_id: UserId) {
+        if let Some(mut local) = self.local.as_ref().and_then(|local| local.lock().ok()) {
+            local.remove(&user_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use failure::Error as FailureError;
+
+    use stq_types::{UserId, UsersRole};
+
+    use super::*;
+    use cache::{InMemoryCache, InvalidationPublisher};
+
+    #[derive(Clone, Default)]
+    struct PublisherMock {
+        published: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl InvalidationPublisher for PublisherMock {
+        fn publish(&self, key: &str) -> Result<(), FailureError> {
+            self.published.lock().unwrap().push(key.to_string());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_remove_publishes_invalidation() {
+        let publisher = PublisherMock::default();
+        let roles_cache = RolesCacheImpl::new(InMemoryCache::new(10, Duration::from_secs(60)))
+            .with_local_cache(10, Duration::from_secs(60))
+            .with_invalidation_publisher(Box::new(publisher.clone()));
+
+        roles_cache.set(UserId(1), vec![UsersRole::User]);
+        assert_eq!(roles_cache.get(UserId(1)), Some(vec![UsersRole::User]));
+        roles_cache.remove(UserId(1));
+        assert_eq!(roles_cache.get(UserId(1)), None);
+        assert_eq!(*publisher.published.lock().unwrap(), vec!["1".to_string()]);
+    }
+
+    #[test]
+    fn test_invalidate_drops_both_tiers() {
+        let publisher = PublisherMock::default();
+        let roles_cache = RolesCacheImpl::new(InMemoryCache::new(10, Duration::from_secs(60)))
+            .with_local_cache(10, Duration::from_secs(60))
+            .with_invalidation_publisher(Box::new(publisher.clone()));
+
+        roles_cache.set(UserId(1), vec![UsersRole::User]);
+        roles_cache.invalidate(UserId(1));
+        assert_eq!(roles_cache.get(UserId(1)), None);
+        // every instance gets the notification, there is nothing to publish
+        assert_eq!(publisher.published.lock().unwrap().is_empty(), true);
+    }
 }
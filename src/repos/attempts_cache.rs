@@ -0,0 +1,65 @@
+//! AttemptsCache counts failed attempts to apply reset and email verification tokens,
+//! so that these unauthenticated endpoints can be locked after repeated guessing
+
+use cache::{Cache, Counter};
+
+pub trait AttemptsCache: Send + Sync {
+    /// Number of failed attempts registered for the key
+    fn get(&self, key: &str) -> u32;
+
+    /// Registers failed attempt, returns updated number of attempts
+    fn increment(&self, key: &str) -> u32;
+
+    /// Forgets failed attempts for the key
+    fn reset(&self, key: &str);
+}
+
+pub struct AttemptsCacheImpl<C>
+where
+    C: Counter,
+{
+    cache: C,
+}
+
+impl<C> AttemptsCacheImpl<C>
+where
+    C: Counter,
+{
+    pub fn new(cache: C) -> Self {
+        AttemptsCacheImpl { cache }
+    }
+}
+
+impl<C> AttemptsCache for AttemptsCacheImpl<C>
+where
+    C: Counter,
+{
+    fn get(&self, key: &str) -> u32 {
+        self.cache.get(key).map(|attempts| attempts.unwrap_or(0)).unwrap_or_else(|err| {
+            let err = err.context(format!("Failed to get attempts from AttemptsCache at key '{}'", key));
+            error!("{}", err);
+            0
+        })
+    }
+
+    fn increment(&self, key: &str) -> u32 {
+        self.cache
+            .increment(key)
+            .map(|attempts| {
+                debug!("Registered {} attempts in AttemptsCache at key '{}'", attempts, key);
+                attempts
+            })
+            .unwrap_or_else(|err| {
+                let err = err.context(format!("Failed to increment attempts in AttemptsCache at key '{}'", key));
+                error!("{}", err);
+                0
+            })
+    }
+
+    fn reset(&self, key: &str) {
+        self.cache.remove(key).map(|_| ()).unwrap_or_else(|err| {
+            let err = err.context(format!("Failed to remove attempts from AttemptsCache at key '{}'", key));
+            error!("{}", err);
+        })
+    }
+}
@@ -0,0 +1,107 @@
+//! Repo for audit_log table, changes made to users
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::sql_types::{Array, Integer, Text};
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_types::UserId;
+
+use repos::legacy_acl::*;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::*;
+use models::{AuditEvent, NewAuditEvent};
+use schema::audit_log::dsl::*;
+
+/// Keys of event data holding PII, they are removed from events of deleted users
+pub const AUDIT_PII_KEYS: &[&str] = &["client_ip", "email", "phone"];
+
+/// AuditLog repository, responsible for handling audit events
+pub trait AuditLogRepo {
+    /// Records the event. Events are recorded on behalf of anyone, no ACL check
+    fn add(&self, payload: NewAuditEvent) -> RepoResult<AuditEvent>;
+
+    /// Returns events of the user, latest first
+    fn list_for_user(&self, user_id: UserId) -> RepoResult<Vec<AuditEvent>>;
+
+    /// Removes PII from data of events of the user, see `AUDIT_PII_KEYS`. Events are kept, no ACL check
+    fn redact_user(&self, user_id: UserId) -> RepoResult<usize>;
+}
+
+/// Implementation of AuditLog trait
+pub struct AuditLogRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, AuditEvent>>,
+    pub db_conn: &'a T,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> AuditLogRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, AuditEvent>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> AuditLogRepo for AuditLogRepoImpl<'a, T> {
+    /// Records the event. Events are recorded on behalf of anyone, no ACL check
+    fn add(&self, payload: NewAuditEvent) -> RepoResult<AuditEvent> {
+        let query = diesel::insert_into(audit_log).values(&payload);
+        query.get_result::<AuditEvent>(self.db_conn).map_err(|e| {
+            e.context(format!(
+                "Add audit event {} of user {} error occured",
+                payload.action, payload.user_id
+            ))
+            .into()
+        })
+    }
+
+    /// Returns events of the user, latest first
+    fn list_for_user(&self, user_id_arg: UserId) -> RepoResult<Vec<AuditEvent>> {
+        let query = audit_log.filter(user_id.eq(user_id_arg)).order(created_at.desc());
+        query
+            .get_results::<AuditEvent>(self.db_conn)
+            .map_err(From::from)
+            .and_then(|events: Vec<AuditEvent>| {
+                for event in &events {
+                    acl::check(&*self.acl, Resource::AuditLog, Action::Read, self, Some(event))?;
+                }
+                Ok(events)
+            })
+            .map_err(|e: FailureError| e.context(format!("List audit events of user {} error occured", user_id_arg)).into())
+    }
+
+    /// Removes PII from data of events of the user, see `AUDIT_PII_KEYS`. Events are kept, no ACL check
+    fn redact_user(&self, user_id_arg: UserId) -> RepoResult<usize> {
+        let keys = AUDIT_PII_KEYS.iter().map(|key| key.to_string()).collect::<Vec<_>>();
+        diesel::sql_query("UPDATE audit_log SET data = data - $1::text[] WHERE user_id = $2 AND data IS NOT NULL")
+            .bind::<Array<Text>, _>(keys)
+            .bind::<Integer, _>(user_id_arg.0)
+            .execute(self.db_conn)
+            .map_err(|e| {
+                e.context(format!("Redact audit events of user {} error occured", user_id_arg))
+                    .into()
+            })
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, AuditEvent>
+    for AuditLogRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, user_id_arg: UserId, scope: &Scope, obj: Option<&AuditEvent>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => {
+                if let Some(event) = obj {
+                    event.user_id == user_id_arg
+                } else {
+                    false
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//! Backfills repo, finds and fixes rows stored before a normalization change, see `services::backfills`
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::dsl::sql;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::sql_types::Bool;
+use diesel::Connection;
+use failure::Fail;
+
+use stq_types::UserId;
+
+use super::types::RepoResult;
+use schema::{identities, users};
+
+/// Backfills repository, used by background backfills only
+pub struct BackfillsRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub db_conn: &'a T,
+}
+
+pub trait BackfillsRepo {
+    /// Users after `from` whose email has a domain with non-ASCII characters, ordered by id
+    fn list_idn_emails(&self, from: UserId, count: i64) -> RepoResult<Vec<(UserId, String)>>;
+
+    /// Replaces email of the user and of their identities
+    fn set_email(&self, user_id: UserId, email: String) -> RepoResult<()>;
+
+    /// Users after `from` whose phone is stored in national format, ordered by id
+    fn list_national_phones(&self, from: UserId, count: i64) -> RepoResult<Vec<(UserId, String)>>;
+
+    /// Replaces phone of the user
+    fn set_phone(&self, user_id: UserId, phone: String) -> RepoResult<()>;
+
+    /// Users after `from` whose PII is stored in plain text, ordered by id
+    fn list_unsealed(&self, from: UserId, count: i64) -> RepoResult<Vec<UserId>>;
+
+    /// Users after `from` whose PII is sealed, but the email of their identity is stored in plain text, ordered by id
+    fn list_unsealed_identities(&self, from: UserId, count: i64) -> RepoResult<Vec<UserId>>;
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> BackfillsRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T) -> Self {
+        Self { db_conn }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> BackfillsRepo for BackfillsRepoImpl<'a, T> {
+    /// Users after `from` whose email has a domain with non-ASCII characters, ordered by id
+    fn list_idn_emails(&self, from: UserId, count: i64) -> RepoResult<Vec<(UserId, String)>> {
+        let query = users::table
+            .select((users::id, users::email))
+            .filter(users::id.gt(from))
+            .filter(users::sealed_pii.is_null())
+            .filter(sql::<Bool>("email ~ '@[^@]*[^[:ascii:]][^@]*$'"))
+            .order(users::id)
+            .limit(count);
+
+        query
+            .get_results(self.db_conn)
+            .map_err(|e| e.context("List users with IDN emails error occured").into())
+    }
+
+    /// Replaces email of the user and of their identities
+    fn set_email(&self, user_id: UserId, email: String) -> RepoResult<()> {
+        diesel::update(users::table.find(user_id))
+            .set(users::email.eq(email.clone()))
+            .execute(self.db_conn)
+            .and_then(|_| {
+                diesel::update(identities::table.filter(identities::user_id.eq(user_id)))
+                    .set(identities::email.eq(email))
+                    .execute(self.db_conn)
+            })
+            .map(|_| ())
+            .map_err(|e| e.context(format!("Set email of user {} error occured", user_id)).into())
+    }
+
+    /// Users after `from` whose phone is stored in national format, ordered by id
+    fn list_national_phones(&self, from: UserId, count: i64) -> RepoResult<Vec<(UserId, String)>> {
+        let query = users::table
+            .select((users::id, users::phone.assume_not_null()))
+            .filter(users::id.gt(from))
+            .filter(users::sealed_pii.is_null())
+            .filter(users::phone.is_not_null())
+            .filter(users::phone.not_like("+%"))
+            .order(users::id)
+            .limit(count);
+
+        query
+            .get_results(self.db_conn)
+            .map_err(|e| e.context("List users with national phones error occured").into())
+    }
+
+    /// Replaces phone of the user
+    fn set_phone(&self, user_id: UserId, phone: String) -> RepoResult<()> {
+        diesel::update(users::table.find(user_id))
+            .set(users::phone.eq(Some(phone)))
+            .execute(self.db_conn)
+            .map(|_| ())
+            .map_err(|e| e.context(format!("Set phone of user {} error occured", user_id)).into())
+    }
+
+    /// Users after `from` whose PII is stored in plain text, ordered by id
+    fn list_unsealed(&self, from: UserId, count: i64) -> RepoResult<Vec<UserId>> {
+        let query = users::table
+            .select(users::id)
+            .filter(users::id.gt(from))
+            .filter(users::sealed_pii.is_null())
+            .order(users::id)
+            .limit(count);
+
+        query
+            .get_results(self.db_conn)
+            .map_err(|e| e.context("List users with unsealed PII error occured").into())
+    }
+
+    /// Users after `from` whose PII is sealed, but the email of their identity is stored in plain text, ordered by id
+    fn list_unsealed_identities(&self, from: UserId, count: i64) -> RepoResult<Vec<UserId>> {
+        let sealed_users = users::table.select(users::id).filter(users::sealed_pii.is_not_null());
+        let query = identities::table
+            .select(identities::user_id)
+            .filter(identities::user_id.gt(from))
+            .filter(identities::email.like("%@%"))
+            .filter(identities::user_id.eq_any(sealed_users))
+            .order(identities::user_id)
+            .limit(count);
+
+        query
+            .get_results(self.db_conn)
+            .map_err(|e| e.context("List identities with unsealed emails error occured").into())
+    }
+}
@@ -0,0 +1,112 @@
+//! Repo for child_accounts table, links of parent-managed child accounts to their parents
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_types::UserId;
+
+use repos::legacy_acl::*;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::*;
+use models::{ChildAccount, NewChildAccount};
+use schema::child_accounts::dsl::*;
+
+/// ChildAccounts repository, responsible for handling links of child accounts
+pub trait ChildAccountsRepo {
+    /// Returns child accounts of the parent
+    fn list_children(&self, parent_id: UserId) -> RepoResult<Vec<ChildAccount>>;
+
+    /// Find link of the child account, `None` if the user is not a child account
+    fn find_by_child(&self, child_id: UserId) -> RepoResult<Option<ChildAccount>>;
+
+    /// Links child account to the parent
+    fn create(&self, payload: NewChildAccount) -> RepoResult<ChildAccount>;
+}
+
+/// Implementation of ChildAccounts trait
+pub struct ChildAccountsRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, ChildAccount>>,
+    pub db_conn: &'a T,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> ChildAccountsRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, ChildAccount>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> ChildAccountsRepo
+    for ChildAccountsRepoImpl<'a, T>
+{
+    /// Returns child accounts of the parent
+    fn list_children(&self, parent_id_arg: UserId) -> RepoResult<Vec<ChildAccount>> {
+        let query = child_accounts.filter(parent_id.eq(parent_id_arg)).order(created_at);
+        query
+            .get_results::<ChildAccount>(self.db_conn)
+            .map_err(From::from)
+            .and_then(|children: Vec<ChildAccount>| {
+                for child in &children {
+                    acl::check(&*self.acl, Resource::ChildAccounts, Action::Read, self, Some(child))?;
+                }
+                Ok(children)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("List child accounts of user {} error occured", parent_id_arg))
+                    .into()
+            })
+    }
+
+    /// Find link of the child account, `None` if the user is not a child account
+    fn find_by_child(&self, child_id_arg: UserId) -> RepoResult<Option<ChildAccount>> {
+        child_accounts
+            .find(child_id_arg)
+            .get_result::<ChildAccount>(self.db_conn)
+            .optional()
+            .map_err(From::from)
+            .and_then(|child: Option<ChildAccount>| {
+                if let Some(ref child) = child {
+                    acl::check(&*self.acl, Resource::ChildAccounts, Action::Read, self, Some(child))?;
+                }
+                Ok(child)
+            })
+            .map_err(|e: FailureError| e.context(format!("Find child account {} error occured", child_id_arg)).into())
+    }
+
+    /// Links child account to the parent
+    fn create(&self, payload: NewChildAccount) -> RepoResult<ChildAccount> {
+        let query = diesel::insert_into(child_accounts).values(&payload);
+        query
+            .get_result::<ChildAccount>(self.db_conn)
+            .map_err(From::from)
+            .and_then(|child: ChildAccount| {
+                acl::check(&*self.acl, Resource::ChildAccounts, Action::Create, self, Some(&child))?;
+                Ok(child)
+            })
+            .map_err(|e: FailureError| e.context(format!("Create child account {:?} error occured", payload)).into())
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, ChildAccount>
+    for ChildAccountsRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, user_id: UserId, scope: &Scope, obj: Option<&ChildAccount>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => {
+                if let Some(child) = obj {
+                    child.parent_id == user_id
+                } else {
+                    false
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,89 @@
+//! Repo for client_brandings table, brands of marketplaces clients belong to
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_types::UserId;
+
+use repos::legacy_acl::*;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::*;
+use models::{ClientBranding, NewClientBranding};
+use schema::client_brandings::dsl::*;
+
+/// ClientBrandings repository, responsible for handling branding of clients
+pub trait ClientBrandingsRepo {
+    /// Returns branding of the client
+    fn find(&self, client_id_arg: String) -> RepoResult<Option<ClientBranding>>;
+
+    /// Creates or replaces branding of the client
+    fn upsert(&self, payload: NewClientBranding) -> RepoResult<ClientBranding>;
+}
+
+/// Implementation of ClientBrandings trait
+pub struct ClientBrandingsRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, ClientBranding>>,
+    pub db_conn: &'a T,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> ClientBrandingsRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, ClientBranding>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> ClientBrandingsRepo
+    for ClientBrandingsRepoImpl<'a, T>
+{
+    /// Returns branding of the client
+    fn find(&self, client_id_arg: String) -> RepoResult<Option<ClientBranding>> {
+        let query = client_brandings.find(client_id_arg.clone());
+        query
+            .get_result::<ClientBranding>(self.db_conn)
+            .optional()
+            .map_err(From::from)
+            .and_then(|branding: Option<ClientBranding>| {
+                if let Some(ref branding) = branding {
+                    acl::check(&*self.acl, Resource::ClientBrandings, Action::Read, self, Some(branding))?;
+                }
+                Ok(branding)
+            })
+            .map_err(|e: FailureError| e.context(format!("Find branding of client {} error occured", client_id_arg)).into())
+    }
+
+    /// Creates or replaces branding of the client
+    fn upsert(&self, payload: NewClientBranding) -> RepoResult<ClientBranding> {
+        acl::check(&*self.acl, Resource::ClientBrandings, Action::Update, self, None)
+            .and_then(|_| {
+                let query = diesel::insert_into(client_brandings)
+                    .values(&payload)
+                    .on_conflict(client_id)
+                    .do_update()
+                    .set(&payload);
+                query.get_result::<ClientBranding>(self.db_conn).map_err(From::from)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Upsert branding of client {} error occured", payload.client_id))
+                    .into()
+            })
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, ClientBranding>
+    for ClientBrandingsRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, _user_id: UserId, scope: &Scope, _obj: Option<&ClientBranding>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => false,
+        }
+    }
+}
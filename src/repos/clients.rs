@@ -0,0 +1,51 @@
+//! Clients repo, presents read operations with db for registered clients
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use failure::Fail;
+
+use super::types::RepoResult;
+use models::Client;
+use schema::clients::dsl::*;
+
+/// Clients repository, responsible for handling registered clients
+pub struct ClientsRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub db_conn: &'a T,
+}
+
+pub trait ClientsRepo {
+    /// Find client by id
+    fn find(&self, client_id: String) -> RepoResult<Option<Client>>;
+
+    /// Returns list of all registered clients
+    fn list(&self) -> RepoResult<Vec<Client>>;
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> ClientsRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T) -> Self {
+        Self { db_conn }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> ClientsRepo for ClientsRepoImpl<'a, T> {
+    /// Find client by id
+    fn find(&self, client_id: String) -> RepoResult<Option<Client>> {
+        let query = clients.find(client_id.clone());
+
+        query
+            .get_result(self.db_conn)
+            .optional()
+            .map_err(|e| e.context(format!("Find client {} error occured", client_id)).into())
+    }
+
+    /// Returns list of all registered clients
+    fn list(&self) -> RepoResult<Vec<Client>> {
+        let query = clients.order(id);
+
+        query
+            .get_results(self.db_conn)
+            .map_err(|e| e.context("List clients error occured").into())
+    }
+}
@@ -0,0 +1,53 @@
+//! Countries repo, presents read operations with db for countries reference data
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use failure::Fail;
+
+use stq_types::Alpha3;
+
+use super::types::RepoResult;
+use models::Country;
+use schema::countries::dsl::*;
+
+/// Countries repository, responsible for handling countries
+pub struct CountriesRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub db_conn: &'a T,
+}
+
+pub trait CountriesRepo {
+    /// Find country by ISO 3166-1 alpha-3 code
+    fn find(&self, alpha3_arg: Alpha3) -> RepoResult<Option<Country>>;
+
+    /// Returns list of all countries
+    fn list(&self) -> RepoResult<Vec<Country>>;
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CountriesRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T) -> Self {
+        Self { db_conn }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CountriesRepo for CountriesRepoImpl<'a, T> {
+    /// Find country by ISO 3166-1 alpha-3 code
+    fn find(&self, alpha3_arg: Alpha3) -> RepoResult<Option<Country>> {
+        let query = countries.find(alpha3_arg.0.clone());
+
+        query
+            .get_result(self.db_conn)
+            .optional()
+            .map_err(|e| e.context(format!("Find country {} error occured", alpha3_arg.0)).into())
+    }
+
+    /// Returns list of all countries
+    fn list(&self) -> RepoResult<Vec<Country>> {
+        let query = countries.order(name);
+
+        query
+            .get_results(self.db_conn)
+            .map_err(|e| e.context("List countries error occured").into())
+    }
+}
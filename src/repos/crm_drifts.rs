@@ -0,0 +1,91 @@
+//! Repo for crm_drifts table, differences of users from their CRM contacts
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use uuid::Uuid;
+
+use stq_types::UserId;
+
+use repos::legacy_acl::*;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::*;
+use models::{CrmDrift, NewCrmDrift};
+use schema::crm_drifts::dsl::*;
+
+/// CrmDrifts repository, responsible for handling drifts found by CRM reconciliation
+pub trait CrmDriftsRepo {
+    /// Adds drifts found by the reconciliation job
+    fn add(&self, payload: Vec<NewCrmDrift>) -> RepoResult<()>;
+
+    /// Returns drifts found by the reconciliation job
+    fn list_for_job(&self, job_id_arg: Uuid, limit: i64) -> RepoResult<Vec<CrmDrift>>;
+
+    /// Deletes drifts found by other reconciliation jobs
+    fn delete_other_jobs(&self, job_id_arg: Uuid) -> RepoResult<()>;
+}
+
+/// Implementation of CrmDrifts trait
+pub struct CrmDriftsRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, CrmDrift>>,
+    pub db_conn: &'a T,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CrmDriftsRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, CrmDrift>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CrmDriftsRepo for CrmDriftsRepoImpl<'a, T> {
+    /// Adds drifts found by the reconciliation job
+    fn add(&self, payload: Vec<NewCrmDrift>) -> RepoResult<()> {
+        acl::check(&*self.acl, Resource::CrmDrifts, Action::Create, self, None)
+            .and_then(|_| {
+                let query = diesel::insert_into(crm_drifts).values(&payload);
+                query.execute(self.db_conn).map(|_| ()).map_err(From::from)
+            })
+            .map_err(|e: FailureError| e.context(format!("Add {} crm drifts error occured", payload.len())).into())
+    }
+
+    /// Returns drifts found by the reconciliation job
+    fn list_for_job(&self, job_id_arg: Uuid, limit: i64) -> RepoResult<Vec<CrmDrift>> {
+        acl::check(&*self.acl, Resource::CrmDrifts, Action::Read, self, None)
+            .and_then(|_| {
+                let query = crm_drifts.filter(job_id.eq(job_id_arg)).order(id).limit(limit);
+                query.get_results::<CrmDrift>(self.db_conn).map_err(From::from)
+            })
+            .map_err(|e: FailureError| e.context(format!("List crm drifts of job {} error occured", job_id_arg)).into())
+    }
+
+    /// Deletes drifts found by other reconciliation jobs
+    fn delete_other_jobs(&self, job_id_arg: Uuid) -> RepoResult<()> {
+        acl::check(&*self.acl, Resource::CrmDrifts, Action::Delete, self, None)
+            .and_then(|_| {
+                let filtered = crm_drifts.filter(job_id.ne(job_id_arg));
+                diesel::delete(filtered).execute(self.db_conn).map(|_| ()).map_err(From::from)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Delete crm drifts of jobs other than {} error occured", job_id_arg))
+                    .into()
+            })
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, CrmDrift>
+    for CrmDriftsRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, _user_id: UserId, scope: &Scope, _obj: Option<&CrmDrift>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => false,
+        }
+    }
+}
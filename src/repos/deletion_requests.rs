@@ -0,0 +1,186 @@
+//! Repo for deletion_requests and deletion_confirmations tables, two-phase deletion of users.
+//! Confirmations are answers of dependent services to the request of the user.
+
+use std::time::SystemTime;
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_types::UserId;
+
+use repos::legacy_acl::*;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::*;
+use models::{DeletionConfirmation, DeletionRequest, DeletionState, NewDeletionConfirmation, NewDeletionRequest, UpdateDeletionRequest};
+use schema::{deletion_confirmations, deletion_requests};
+
+/// DeletionRequests repository, responsible for handling deletion requests and their confirmations
+pub trait DeletionRequestsRepo {
+    /// Creates deletion request of the user, or restarts the previous one removing its confirmations
+    fn create(&self, payload: NewDeletionRequest) -> RepoResult<DeletionRequest>;
+
+    /// Find deletion request of the user
+    fn find(&self, user_id: UserId) -> RepoResult<Option<DeletionRequest>>;
+
+    /// Find deletion request of the user locking it until the end of current transaction
+    fn find_for_update(&self, user_id: UserId) -> RepoResult<Option<DeletionRequest>>;
+
+    /// Updates state of the request
+    fn update(&self, user_id: UserId, payload: UpdateDeletionRequest) -> RepoResult<DeletionRequest>;
+
+    /// Returns pending requests which can be settled by `now`: with the grace period or the deadline passed
+    fn list_due(&self, now: SystemTime) -> RepoResult<Vec<DeletionRequest>>;
+
+    /// Saves answer of the service, the service may change its answer while the request is pending
+    fn confirm(&self, payload: NewDeletionConfirmation) -> RepoResult<DeletionConfirmation>;
+
+    /// Returns answers to the request of the user, access is checked on the request
+    fn list_confirmations(&self, user_id: UserId) -> RepoResult<Vec<DeletionConfirmation>>;
+}
+
+/// Implementation of DeletionRequests trait
+pub struct DeletionRequestsRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, DeletionRequest>>,
+    pub db_conn: &'a T,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> DeletionRequestsRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, DeletionRequest>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> DeletionRequestsRepo
+    for DeletionRequestsRepoImpl<'a, T>
+{
+    /// Creates deletion request of the user, or restarts the previous one removing its confirmations
+    fn create(&self, payload: NewDeletionRequest) -> RepoResult<DeletionRequest> {
+        self.db_conn
+            .transaction::<DeletionRequest, FailureError, _>(|| {
+                diesel::delete(deletion_confirmations::table.filter(deletion_confirmations::user_id.eq(payload.user_id)))
+                    .execute(self.db_conn)?;
+                let request = diesel::insert_into(deletion_requests::table)
+                    .values(&payload)
+                    .on_conflict(deletion_requests::user_id)
+                    .do_update()
+                    .set(&payload)
+                    .get_result::<DeletionRequest>(self.db_conn)?;
+                acl::check(&*self.acl, Resource::DeletionRequests, Action::Create, self, Some(&request))?;
+                Ok(request)
+            })
+            .map_err(|e: FailureError| e.context(format!("Create deletion request {:?} error occured", payload)).into())
+    }
+
+    /// Find deletion request of the user
+    fn find(&self, user_id_arg: UserId) -> RepoResult<Option<DeletionRequest>> {
+        deletion_requests::table
+            .find(user_id_arg)
+            .get_result::<DeletionRequest>(self.db_conn)
+            .optional()
+            .map_err(From::from)
+            .and_then(|request: Option<DeletionRequest>| {
+                if let Some(ref request) = request {
+                    acl::check(&*self.acl, Resource::DeletionRequests, Action::Read, self, Some(request))?;
+                }
+                Ok(request)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Find deletion request of user {} error occured", user_id_arg))
+                    .into()
+            })
+    }
+
+    /// Find deletion request of the user locking it until the end of current transaction
+    fn find_for_update(&self, user_id_arg: UserId) -> RepoResult<Option<DeletionRequest>> {
+        deletion_requests::table
+            .find(user_id_arg)
+            .for_update()
+            .get_result::<DeletionRequest>(self.db_conn)
+            .optional()
+            .map_err(From::from)
+            .and_then(|request: Option<DeletionRequest>| {
+                if let Some(ref request) = request {
+                    acl::check(&*self.acl, Resource::DeletionRequests, Action::Update, self, Some(request))?;
+                }
+                Ok(request)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Lock deletion request of user {} error occured", user_id_arg))
+                    .into()
+            })
+    }
+
+    /// Updates state of the request
+    fn update(&self, user_id_arg: UserId, payload: UpdateDeletionRequest) -> RepoResult<DeletionRequest> {
+        acl::check(&*self.acl, Resource::DeletionRequests, Action::Update, self, None)?;
+
+        let query = diesel::update(deletion_requests::table.find(user_id_arg)).set(&payload);
+        query.get_result::<DeletionRequest>(self.db_conn).map_err(|e| {
+            e.context(format!("Update deletion request of user {} error occured", user_id_arg))
+                .into()
+        })
+    }
+
+    /// Returns pending requests with deadline passed by `now`
+    fn list_due(&self, now: SystemTime) -> RepoResult<Vec<DeletionRequest>> {
+        acl::check(&*self.acl, Resource::DeletionRequests, Action::Read, self, None)?;
+
+        let query = deletion_requests::table
+            .filter(deletion_requests::state.eq(DeletionState::Pending))
+            .filter(deletion_requests::deadline.le(now).or(deletion_requests::not_before.le(now)))
+            .order(deletion_requests::deadline);
+        query
+            .get_results::<DeletionRequest>(self.db_conn)
+            .map_err(|e| e.context("List due deletion requests error occured").into())
+    }
+
+    /// Saves answer of the service, the service may change its answer while the request is pending
+    fn confirm(&self, payload: NewDeletionConfirmation) -> RepoResult<DeletionConfirmation> {
+        acl::check(&*self.acl, Resource::DeletionRequests, Action::Update, self, None)?;
+
+        let query = diesel::insert_into(deletion_confirmations::table)
+            .values(&payload)
+            .on_conflict((deletion_confirmations::user_id, deletion_confirmations::service))
+            .do_update()
+            .set(&payload);
+        query
+            .get_result::<DeletionConfirmation>(self.db_conn)
+            .map_err(|e| e.context(format!("Save deletion confirmation {:?} error occured", payload)).into())
+    }
+
+    /// Returns answers to the request of the user, access is checked on the request
+    fn list_confirmations(&self, user_id_arg: UserId) -> RepoResult<Vec<DeletionConfirmation>> {
+        let query = deletion_confirmations::table
+            .filter(deletion_confirmations::user_id.eq(user_id_arg))
+            .order(deletion_confirmations::service);
+        query.get_results::<DeletionConfirmation>(self.db_conn).map_err(|e| {
+            e.context(format!("List deletion confirmations of user {} error occured", user_id_arg))
+                .into()
+        })
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, DeletionRequest>
+    for DeletionRequestsRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, user_id_arg: UserId, scope: &Scope, obj: Option<&DeletionRequest>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => {
+                if let Some(request) = obj {
+                    request.user_id == user_id_arg
+                } else {
+                    false
+                }
+            }
+        }
+    }
+}
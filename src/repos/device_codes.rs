@@ -0,0 +1,130 @@
+//! Device codes repo, presents CRUD operations with db for OAuth2 device authorization grant
+use std::time::{Duration, SystemTime};
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use failure::Fail;
+
+use stq_types::UserId;
+
+use super::types::RepoResult;
+use models::{DeviceCode, NewDeviceCode};
+use schema::device_codes::dsl::*;
+
+/// Device codes repository, responsible for handling device authorizations
+pub struct DeviceCodesRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub db_conn: &'a T,
+}
+
+pub trait DeviceCodesRepo {
+    /// Create device code
+    fn create(&self, payload: NewDeviceCode) -> RepoResult<DeviceCode>;
+
+    /// Find by device code
+    fn find(&self, device_code_arg: String) -> RepoResult<Option<DeviceCode>>;
+
+    /// Find by user code
+    fn find_by_user_code(&self, user_code_arg: String) -> RepoResult<Option<DeviceCode>>;
+
+    /// Mark device code as approved by user, unless it is approved already
+    fn approve(&self, device_code_arg: String, user_id_arg: UserId) -> RepoResult<Option<DeviceCode>>;
+
+    /// Record poll of the pending device code, false if polled within the interval
+    fn poll(&self, device_code_arg: String, interval: Duration) -> RepoResult<bool>;
+
+    /// Delete approved device code, returning it to the only caller that redeems it
+    fn redeem(&self, device_code_arg: String) -> RepoResult<Option<DeviceCode>>;
+
+    /// Delete device code
+    fn delete(&self, device_code_arg: String) -> RepoResult<DeviceCode>;
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> DeviceCodesRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T) -> Self {
+        Self { db_conn }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> DeviceCodesRepo
+    for DeviceCodesRepoImpl<'a, T>
+{
+    /// Create device code
+    fn create(&self, payload: NewDeviceCode) -> RepoResult<DeviceCode> {
+        let query = diesel::insert_into(device_codes).values(&payload);
+
+        query.get_result(self.db_conn).map_err(|e| {
+            e.context(format!("Create device code for client {} error occured", payload.client_id))
+                .into()
+        })
+    }
+
+    /// Find by device code
+    fn find(&self, device_code_arg: String) -> RepoResult<Option<DeviceCode>> {
+        let query = device_codes.find(device_code_arg);
+
+        query
+            .get_result(self.db_conn)
+            .optional()
+            .map_err(|e| e.context("Find by device code error occured").into())
+    }
+
+    /// Find by user code
+    fn find_by_user_code(&self, user_code_arg: String) -> RepoResult<Option<DeviceCode>> {
+        let query = device_codes.filter(user_code.eq(user_code_arg.clone()));
+
+        query
+            .get_result(self.db_conn)
+            .optional()
+            .map_err(|e| e.context(format!("Find by user code {} error occured", user_code_arg)).into())
+    }
+
+    /// Mark device code as approved by user, unless it is approved already
+    fn approve(&self, device_code_arg: String, user_id_arg: UserId) -> RepoResult<Option<DeviceCode>> {
+        let filtered = device_codes.find(device_code_arg).filter(user_id.is_null());
+        let query = diesel::update(filtered).set(user_id.eq(Some(user_id_arg)));
+
+        query.get_result(self.db_conn).optional().map_err(|e| {
+            e.context(format!("Approve device code by user {} error occured", user_id_arg))
+                .into()
+        })
+    }
+
+    /// Record poll of the pending device code, false if polled within the interval
+    fn poll(&self, device_code_arg: String, interval: Duration) -> RepoResult<bool> {
+        let now = SystemTime::now();
+        let filtered = device_codes
+            .find(device_code_arg)
+            .filter(last_polled_at.is_null().or(last_polled_at.le(now - interval)));
+        let query = diesel::update(filtered).set(last_polled_at.eq(Some(now)));
+
+        query
+            .execute(self.db_conn)
+            .map(|updated| updated > 0)
+            .map_err(|e| e.context("Poll device code error occured").into())
+    }
+
+    /// Delete approved device code, returning it to the only caller that redeems it
+    fn redeem(&self, device_code_arg: String) -> RepoResult<Option<DeviceCode>> {
+        let filtered = device_codes.find(device_code_arg).filter(user_id.is_not_null());
+        let query = diesel::delete(filtered);
+
+        query
+            .get_result(self.db_conn)
+            .optional()
+            .map_err(|e| e.context("Redeem device code error occured").into())
+    }
+
+    /// Delete device code
+    fn delete(&self, device_code_arg: String) -> RepoResult<DeviceCode> {
+        let filtered = device_codes.find(device_code_arg);
+        let query = diesel::delete(filtered);
+
+        query
+            .get_result(self.db_conn)
+            .map_err(|e| e.context("Delete device code error occured").into())
+    }
+}
@@ -0,0 +1,151 @@
+//! Repo for devices table, known devices of users
+
+use std::time::SystemTime;
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use uuid::Uuid;
+
+use stq_types::UserId;
+
+use repos::legacy_acl::*;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::*;
+use models::{Device, NewDevice};
+use schema::devices::dsl::*;
+
+/// Devices repository, responsible for handling known devices of users
+pub trait DevicesRepo {
+    /// Returns devices of the user, last seen first
+    fn list_for_user(&self, user_id: UserId) -> RepoResult<Vec<Device>>;
+
+    /// Returns device of the user
+    fn find(&self, user_id: UserId, device_id: Uuid) -> RepoResult<Option<Device>>;
+
+    /// Records login from the device, creating it if it was not seen before.
+    /// Devices are recorded on behalf of users logging in, no ACL check
+    fn touch(&self, payload: NewDevice, seen_at: SystemTime) -> RepoResult<Device>;
+
+    /// Sets until when the device of the user is trusted, returns `None` if there was no such device
+    fn set_trusted_until(&self, user_id: UserId, device_id: Uuid, until: Option<SystemTime>) -> RepoResult<Option<Device>>;
+
+    /// Deletes device of the user, returns `None` if there was no such device
+    fn delete(&self, user_id: UserId, device_id: Uuid) -> RepoResult<Option<Device>>;
+}
+
+/// Implementation of Devices trait
+pub struct DevicesRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, Device>>,
+    pub db_conn: &'a T,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> DevicesRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, Device>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> DevicesRepo for DevicesRepoImpl<'a, T> {
+    /// Returns devices of the user, last seen first
+    fn list_for_user(&self, user_id_arg: UserId) -> RepoResult<Vec<Device>> {
+        let query = devices.filter(user_id.eq(user_id_arg)).order(last_seen_at.desc());
+        query
+            .get_results::<Device>(self.db_conn)
+            .map_err(From::from)
+            .and_then(|found: Vec<Device>| {
+                for device in &found {
+                    acl::check(&*self.acl, Resource::Devices, Action::Read, self, Some(device))?;
+                }
+                Ok(found)
+            })
+            .map_err(|e: FailureError| e.context(format!("List devices of user {} error occured", user_id_arg)).into())
+    }
+
+    /// Returns device of the user
+    fn find(&self, user_id_arg: UserId, device_id: Uuid) -> RepoResult<Option<Device>> {
+        let query = devices.filter(id.eq(device_id)).filter(user_id.eq(user_id_arg));
+        query
+            .get_result::<Device>(self.db_conn)
+            .optional()
+            .map_err(From::from)
+            .and_then(|device: Option<Device>| {
+                if let Some(ref device) = device {
+                    acl::check(&*self.acl, Resource::Devices, Action::Read, self, Some(device))?;
+                }
+                Ok(device)
+            })
+            .map_err(|e: FailureError| e.context(format!("Find device {} error occured", device_id)).into())
+    }
+
+    /// Records login from the device, creating it if it was not seen before.
+    /// Devices are recorded on behalf of users logging in, no ACL check
+    fn touch(&self, payload: NewDevice, seen_at: SystemTime) -> RepoResult<Device> {
+        let query = diesel::insert_into(devices)
+            .values(&payload)
+            .on_conflict((user_id, fingerprint))
+            .do_update()
+            .set((name.eq(payload.name.clone()), last_seen_at.eq(seen_at)));
+        query
+            .get_result::<Device>(self.db_conn)
+            .map_err(|e| e.context(format!("Touch device of user {} error occured", payload.user_id)).into())
+    }
+
+    /// Sets until when the device of the user is trusted, returns `None` if there was no such device
+    fn set_trusted_until(&self, user_id_arg: UserId, device_id: Uuid, until: Option<SystemTime>) -> RepoResult<Option<Device>> {
+        let filtered = devices.filter(id.eq(device_id)).filter(user_id.eq(user_id_arg));
+        let query = diesel::update(filtered).set(trusted_until.eq(until));
+        query
+            .get_result::<Device>(self.db_conn)
+            .optional()
+            .map_err(From::from)
+            .and_then(|device: Option<Device>| {
+                if let Some(ref device) = device {
+                    acl::check(&*self.acl, Resource::Devices, Action::Update, self, Some(device))?;
+                }
+                Ok(device)
+            })
+            .map_err(|e: FailureError| e.context(format!("Set trust of device {} error occured", device_id)).into())
+    }
+
+    /// Deletes device of the user, returns `None` if there was no such device
+    fn delete(&self, user_id_arg: UserId, device_id: Uuid) -> RepoResult<Option<Device>> {
+        let filtered = devices.filter(id.eq(device_id)).filter(user_id.eq(user_id_arg));
+        let query = diesel::delete(filtered);
+        query
+            .get_result::<Device>(self.db_conn)
+            .optional()
+            .map_err(From::from)
+            .and_then(|device: Option<Device>| {
+                if let Some(ref device) = device {
+                    acl::check(&*self.acl, Resource::Devices, Action::Delete, self, Some(device))?;
+                }
+                Ok(device)
+            })
+            .map_err(|e: FailureError| e.context(format!("Delete device {} error occured", device_id)).into())
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, Device>
+    for DevicesRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, user_id_arg: UserId, scope: &Scope, obj: Option<&Device>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => {
+                if let Some(device) = obj {
+                    device.user_id == user_id_arg
+                } else {
+                    false
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! Repo for funnel_events table, steps of registration funnel reached by users
+
+use chrono::NaiveDate;
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_types::UserId;
+
+use repos::legacy_acl::*;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::*;
+use models::{FunnelEvent, FunnelStep, NewFunnelEvent};
+use schema::funnel_events::dsl::*;
+
+/// FunnelEvents repository, responsible for handling funnel events
+pub trait FunnelEventsRepo {
+    /// Records the step reached by the user, only the first time is kept. Steps are recorded for anyone, no ACL check
+    fn add(&self, payload: NewFunnelEvent) -> RepoResult<()>;
+
+    /// Returns number of users who reached the step out of users who submitted registration from `from` to `to` inclusive
+    fn count_cohort_step(&self, from: NaiveDate, to: NaiveDate, step_arg: FunnelStep) -> RepoResult<i64>;
+
+    /// Deletes all the steps of the user, used by user purge, no ACL check
+    fn delete_by_user(&self, user_id_arg: UserId) -> RepoResult<()>;
+}
+
+/// Implementation of FunnelEvents trait
+pub struct FunnelEventsRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, FunnelEvent>>,
+    pub db_conn: &'a T,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> FunnelEventsRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, FunnelEvent>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> FunnelEventsRepo
+    for FunnelEventsRepoImpl<'a, T>
+{
+    /// Records the step reached by the user, only the first time is kept. Steps are recorded for anyone, no ACL check
+    fn add(&self, payload: NewFunnelEvent) -> RepoResult<()> {
+        let query = diesel::insert_into(funnel_events).values(&payload).on_conflict_do_nothing();
+        query
+            .execute(self.db_conn)
+            .map(|_| ())
+            .map_err(|e| e.context(format!("Add funnel event {:?} error occured", payload)).into())
+    }
+
+    /// Returns number of users who reached the step out of users who submitted registration from `from` to `to` inclusive
+    fn count_cohort_step(&self, from: NaiveDate, to: NaiveDate, step_arg: FunnelStep) -> RepoResult<i64> {
+        acl::check(&*self.acl, Resource::Stats, Action::Read, self, None)?;
+
+        let cohort = funnel_events
+            .filter(step.eq(FunnelStep::RegistrationSubmitted))
+            .filter(created_at.ge(from.and_hms(0, 0, 0)))
+            .filter(created_at.lt(to.succ().and_hms(0, 0, 0)))
+            .select(user_id);
+        let query = funnel_events.filter(step.eq(step_arg)).filter(user_id.eq_any(cohort)).count();
+        query.get_result(self.db_conn).map_err(|e| {
+            e.context(format!("Count funnel step {} from {} to {} error occured", step_arg, from, to))
+                .into()
+        })
+    }
+
+    /// Deletes all the steps of the user, used by user purge, no ACL check
+    fn delete_by_user(&self, user_id_arg: UserId) -> RepoResult<()> {
+        let query = diesel::delete(funnel_events.filter(user_id.eq(user_id_arg)));
+        query.execute(self.db_conn).map(|_| ()).map_err(|e| {
+            e.context(format!("Delete funnel events of user {} error occured", user_id_arg))
+                .into()
+        })
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, FunnelEvent>
+    for FunnelEventsRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, _user_id: UserId, scope: &Scope, _obj: Option<&FunnelEvent>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => false,
+        }
+    }
+}
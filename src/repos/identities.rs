@@ -1,3 +1,7 @@
+//! Identities repo. Once PII of users is sealed, emails of identities are stored as blind indexes
+//! and opened from sealed PII of their users, see `repos::pii_cipher`.
+use std::collections::HashMap;
+
 use diesel;
 use diesel::connection::AnsiTransactionManager;
 use diesel::dsl::exists;
@@ -6,6 +10,7 @@ use diesel::prelude::*;
 use diesel::query_dsl::LoadQuery;
 use diesel::query_dsl::RunQueryDsl;
 use diesel::select;
+use diesel::sql_types::VarChar;
 use diesel::Connection;
 use failure::Error as FailureError;
 use failure::Fail;
@@ -13,13 +18,22 @@ use failure::Fail;
 use stq_static_resources::Provider;
 use stq_types::UserId;
 
+use repos::legacy_acl::*;
+
+use super::acl;
+use super::pii_cipher::PiiCipher;
 use super::types::RepoResult;
-use models::{Identity, UpdateIdentity};
+use models::authorization::*;
+use models::{Identity, PasswordHashFormat, UpdateIdentity};
 use schema::identities::dsl::*;
+use schema::users;
 
-/// Identities repository, responsible for handling identities
+/// Identities repository, responsible for handling identities.
+/// Only methods for admins check ACL, the rest are used on behalf of users logging in.
 pub struct IdentitiesRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, Identity>>,
     pub db_conn: &'a T,
+    pub pii_cipher: Option<PiiCipher>,
 }
 
 pub trait IdentitiesRepo {
@@ -47,16 +61,124 @@ pub trait IdentitiesRepo {
     /// Find specific user by email
     fn find_by_email_provider(&self, email_arg: String, provider_arg: Provider) -> RepoResult<Identity>;
 
-    /// Update identity
+    /// Update identity, passwords are hashed by `password_create`, so the format of imported hash is reset
     fn update(&self, ident: Identity, update: UpdateIdentity) -> RepoResult<Identity>;
 
     // Get by user email
     fn get_by_email(&self, email_arg: String) -> RepoResult<Identity>;
+
+    /// Locks e-mail until the end of current transaction, so that concurrent
+    /// registrations with the same e-mail are run one by one
+    fn lock_email(&self, email_arg: String) -> RepoResult<()>;
+
+    /// Returns all identities, for exports of auth data
+    fn list_all(&self) -> RepoResult<Vec<Identity>>;
+
+    /// Inserts exported identity as is, returns `false` if its user or e-mail already has an identity
+    fn restore(&self, ident: Identity) -> RepoResult<bool>;
+
+    /// Returns identities of the user
+    fn list_for_user(&self, user_id_arg: UserId) -> RepoResult<Vec<Identity>>;
+
+    /// Unlinks the social provider from the identity of the user, so that the user logs in with e-mail.
+    /// Returns `None` if the user had no identity with the provider
+    fn unlink_provider(&self, user_id_arg: UserId, provider_arg: Provider) -> RepoResult<Option<Identity>>;
+
+    /// Sets password hash of the user, switching the identity to e-mail provider. The user has to
+    /// change the password on the next login. Returns `None` if the user had no identity
+    fn set_password(&self, user_id_arg: UserId, password_hash: String) -> RepoResult<Option<Identity>>;
+
+    /// Replaces e-mail of the identity stored in plain text by its blind index, returns whether it was replaced
+    fn seal_email(&self, user_id_arg: UserId) -> RepoResult<bool>;
 }
 
 impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> IdentitiesRepoImpl<'a, T> {
     pub fn new(db_conn: &'a T) -> Self {
-        Self { db_conn }
+        Self {
+            db_conn,
+            acl: Box::new(SystemACL::default()),
+            pii_cipher: None,
+        }
+    }
+
+    pub fn with_acl(self, acl: Box<Acl<Resource, Action, Scope, FailureError, Identity>>) -> Self {
+        Self { acl, ..self }
+    }
+
+    /// Stores e-mails as blind indexes, e-mails are stored in plain text without the cipher
+    pub fn with_pii_cipher(self, pii_cipher: Option<PiiCipher>) -> Self {
+        Self { pii_cipher, ..self }
+    }
+
+    /// Values of the email column matching the e-mail, the e-mail itself or its blind index
+    fn lookup_emails(&self, email_arg: &str) -> Vec<String> {
+        let mut values = vec![email_arg.to_string()];
+        if let Some(ref cipher) = self.pii_cipher {
+            values.push(cipher.blind_index("email", email_arg));
+        }
+        values
+    }
+
+    /// Value of the email column of the e-mail, its blind index once PII is sealed
+    fn stored_email(&self, email_arg: &str) -> String {
+        match self.pii_cipher {
+            Some(ref cipher) => cipher.blind_index("email", email_arg),
+            None => email_arg.to_string(),
+        }
+    }
+
+    /// Opens e-mails of the identities stored as blind indexes from PII of their users. Users not
+    /// sealed yet keep e-mails in plain text, identities of shredded users keep blind indexes.
+    fn open_emails(&self, mut found: Vec<Identity>) -> RepoResult<Vec<Identity>> {
+        let sealed_ids = found
+            .iter()
+            .filter(|ident| is_blind_index(&ident.email))
+            .map(|ident| ident.user_id)
+            .collect::<Vec<_>>();
+        if sealed_ids.is_empty() {
+            return Ok(found);
+        }
+
+        let owners = users::table
+            .filter(users::id.eq_any(sealed_ids))
+            .select((users::id, users::email, users::sealed_pii))
+            .get_results::<(UserId, String, Option<Vec<u8>>)>(self.db_conn)?;
+        let sealed = owners
+            .iter()
+            .filter_map(|&(owner_id, _, ref sealed_pii)| sealed_pii.clone().map(|sealed_pii| (owner_id, sealed_pii)))
+            .collect::<Vec<_>>();
+        let mut opened = match self.pii_cipher {
+            Some(ref cipher) => cipher.open(sealed)?,
+            None => {
+                warn!(
+                    "E-mails of {} identities are sealed, but PII encryption is not configured",
+                    found.len()
+                );
+                HashMap::new()
+            }
+        };
+
+        let mut emails = HashMap::new();
+        for (owner_id, owner_email, _) in owners {
+            // PII sealed before emails were keeps the email in the column of the user
+            match opened.remove(&owner_id.0).and_then(|pii| pii.email) {
+                Some(opened_email) => emails.insert(owner_id.0, opened_email),
+                None if !is_blind_index(&owner_email) => emails.insert(owner_id.0, owner_email),
+                None => None,
+            };
+        }
+        for ident in &mut found {
+            if let Some(opened_email) = emails.get(&ident.user_id.0) {
+                if is_blind_index(&ident.email) {
+                    ident.email = opened_email.clone();
+                }
+            }
+        }
+        Ok(found)
+    }
+
+    fn open_email(&self, ident: Identity) -> RepoResult<Identity> {
+        self.open_emails(vec![ident]).map(|mut found| found.remove(0))
     }
 
     fn execute_query<Q: Send + 'static, U: LoadQuery<T, Q> + Send + 'static>(&self, query: U) -> Result<Q, FailureError> {
@@ -69,24 +191,21 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
 impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> IdentitiesRepo for IdentitiesRepoImpl<'a, T> {
     /// Checks if e-mail is already registered
     fn email_exists(&self, email_arg: String) -> RepoResult<bool> {
-        self.execute_query(select(exists(identities.filter(email.eq(email_arg.clone())))))
-            .map_err(|e| {
-                e.context(format!("Checks if e-mail {} is already registered error occurred.", email_arg))
-                    .into()
-            })
+        self.execute_query(select(exists(identities.filter(email.eq_any(self.lookup_emails(&email_arg))))))
+            .map_err(|e| e.context("Checks if e-mail is already registered error occurred.").into())
     }
 
     /// Checks if e-mail with provider is already registered
     fn email_provider_exists(&self, email_arg: String, provider_arg: Provider) -> RepoResult<bool> {
         self.execute_query(select(exists(
             identities
-                .filter(email.eq(email_arg.clone()))
+                .filter(email.eq_any(self.lookup_emails(&email_arg)))
                 .filter(provider.eq(provider_arg.clone())),
         )))
         .map_err(|e| {
             e.context(format!(
-                "Checks if e-mail {} with provider {} is already registered error occurred.",
-                email_arg, provider_arg
+                "Checks if e-mail with provider {} is already registered error occurred.",
+                provider_arg
             ))
             .into()
         })
@@ -103,32 +222,32 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
     ) -> RepoResult<Identity> {
         let identity_arg = Identity {
             user_id: user_id_arg,
-            email: email_arg,
+            email: self.stored_email(&email_arg),
             provider: provider_arg,
             password: password_arg,
             saga_id: saga_id_arg,
+            must_change_password: false,
+            password_hash_format: None,
         };
 
         let ident_query = diesel::insert_into(identities).values(&identity_arg);
         ident_query
             .get_result::<Identity>(self.db_conn)
-            .map_err(|e| e.context(format!("Creates new identity {:?} error occurred.", identity_arg)).into())
+            .map(|ident| Identity { email: email_arg, ..ident })
+            .map_err(|e| {
+                e.context(format!("Creates new identity of user {} error occurred.", user_id_arg))
+                    .into()
+            })
     }
 
     /// Verifies password
     fn verify_password(&self, email_arg: String, password_arg: String) -> RepoResult<bool> {
         self.execute_query(select(exists(
             identities
-                .filter(email.eq(email_arg.clone()))
+                .filter(email.eq_any(self.lookup_emails(&email_arg)))
                 .filter(password.eq(password_arg.clone())),
         )))
-        .map_err(|e| {
-            e.context(format!(
-                "Verifies password email {} password {} error occurred.",
-                email_arg, password_arg
-            ))
-            .into()
-        })
+        .map_err(|e| e.context("Verifies password error occurred.").into())
     }
 
     /// Find specific user by user_id
@@ -137,53 +256,210 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
             .filter(user_id.eq(user_id_arg.clone()))
             .filter(provider.eq(provider_arg.clone()));
 
-        query.first::<Identity>(self.db_conn).map_err(|e| {
-            e.context(format!(
-                "Find specific user by user_id {} provider {} error occurred.",
-                user_id_arg, provider_arg
-            ))
-            .into()
-        })
+        query
+            .first::<Identity>(self.db_conn)
+            .map_err(From::from)
+            .and_then(|ident| self.open_email(ident))
+            .map_err(|e: FailureError| {
+                e.context(format!(
+                    "Find specific user by user_id {} provider {} error occurred.",
+                    user_id_arg, provider_arg
+                ))
+                .into()
+            })
     }
 
     /// Find specific user by email
     fn find_by_email_provider(&self, email_arg: String, provider_arg: Provider) -> RepoResult<Identity> {
         let query = identities
-            .filter(email.eq(email_arg.clone()))
+            .filter(email.eq_any(self.lookup_emails(&email_arg)))
             .filter(provider.eq(provider_arg.clone()));
 
-        query.first::<Identity>(self.db_conn).map_err(|e| {
-            e.context(format!(
-                "Find specific user by email {} provider {} error occurred.",
-                email_arg, provider_arg
-            ))
-            .into()
-        })
+        query
+            .first::<Identity>(self.db_conn)
+            .map(|ident| Identity { email: email_arg, ..ident })
+            .map_err(|e| {
+                e.context(format!(
+                    "Find specific user by email with provider {} error occurred.",
+                    provider_arg
+                ))
+                .into()
+            })
     }
 
-    /// Update identity
+    /// Update identity, passwords are hashed by `password_create`, so the format of imported hash is reset
     fn update(&self, ident: Identity, update: UpdateIdentity) -> RepoResult<Identity> {
         let filter = identities
-            .filter(email.eq(ident.email.clone()))
+            .filter(email.eq_any(self.lookup_emails(&ident.email)))
             .filter(provider.eq(ident.provider.clone()));
 
-        let query = diesel::update(filter).set(&update);
-        query.get_result::<Identity>(self.db_conn).map_err(|e| {
-            e.context(format!(
-                "Update identity {:?} with new identity {:?} error occurred.",
-                ident, update
-            ))
-            .into()
-        })
+        let result = if update.password.is_some() {
+            diesel::update(filter)
+                .set((&update, password_hash_format.eq(None::<PasswordHashFormat>)))
+                .get_result::<Identity>(self.db_conn)
+        } else {
+            diesel::update(filter).set(&update).get_result::<Identity>(self.db_conn)
+        };
+        result
+            .map(|updated| Identity {
+                email: ident.email.clone(),
+                ..updated
+            })
+            .map_err(|e| {
+                e.context(format!("Update identity of user {} error occurred.", ident.user_id))
+                    .into()
+            })
     }
 
     // Get by user email
     fn get_by_email(&self, email_arg: String) -> RepoResult<Identity> {
-        let query = identities.filter(email.eq(&email_arg));
+        let query = identities.filter(email.eq_any(self.lookup_emails(&email_arg)));
 
-        query.first::<Identity>(self.db_conn).map_err(|e| {
-            e.context(format!("Find specific user by email {} error occurred.", email_arg))
-                .into()
-        })
+        query
+            .first::<Identity>(self.db_conn)
+            .map(|ident| Identity { email: email_arg, ..ident })
+            .map_err(|e| e.context("Find specific user by email error occurred.").into())
+    }
+
+    /// Locks e-mail until the end of current transaction, so that concurrent
+    /// registrations with the same e-mail are run one by one
+    fn lock_email(&self, email_arg: String) -> RepoResult<()> {
+        diesel::sql_query("SELECT pg_advisory_xact_lock(hashtext($1))")
+            .bind::<VarChar, _>(&email_arg)
+            .execute(self.db_conn)
+            .map(|_| ())
+            .map_err(|e| e.context("Lock e-mail error occurred.").into())
+    }
+
+    /// Returns all identities, for exports of auth data
+    fn list_all(&self) -> RepoResult<Vec<Identity>> {
+        identities
+            .order(user_id)
+            .get_results::<Identity>(self.db_conn)
+            .map_err(From::from)
+            .and_then(|found| self.open_emails(found))
+            .map_err(|e: FailureError| e.context("List all identities error occurred.").into())
+    }
+
+    /// Inserts exported identity as is, returns `false` if its user or e-mail already has an identity
+    fn restore(&self, mut ident: Identity) -> RepoResult<bool> {
+        ident.email = self.stored_email(&ident.email);
+        diesel::insert_into(identities)
+            .values(&ident)
+            .on_conflict_do_nothing()
+            .execute(self.db_conn)
+            .map(|inserted| inserted > 0)
+            .map_err(|e| {
+                e.context(format!("Restore identity of user {} error occurred.", ident.user_id))
+                    .into()
+            })
+    }
+
+    /// Returns identities of the user
+    fn list_for_user(&self, user_id_arg: UserId) -> RepoResult<Vec<Identity>> {
+        let query = identities.filter(user_id.eq(user_id_arg));
+        query
+            .get_results::<Identity>(self.db_conn)
+            .map_err(From::from)
+            .and_then(|found| self.open_emails(found))
+            .and_then(|found: Vec<Identity>| {
+                for ident in &found {
+                    acl::check(&*self.acl, Resource::Identities, Action::Read, self, Some(ident))?;
+                }
+                Ok(found)
+            })
+            .map_err(|e: FailureError| e.context(format!("List identities of user {} error occurred.", user_id_arg)).into())
+    }
+
+    /// Unlinks the social provider from the identity of the user, so that the user logs in with e-mail.
+    /// Returns `None` if the user had no identity with the provider
+    fn unlink_provider(&self, user_id_arg: UserId, provider_arg: Provider) -> RepoResult<Option<Identity>> {
+        acl::check(&*self.acl, Resource::Identities, Action::Update, self, None)
+            .and_then(|_| {
+                let filtered = identities.filter(user_id.eq(user_id_arg)).filter(provider.eq(provider_arg.clone()));
+                let query = diesel::update(filtered).set((
+                    provider.eq(Provider::Email),
+                    password.eq(None::<String>),
+                    password_hash_format.eq(None::<PasswordHashFormat>),
+                ));
+                match query.get_result::<Identity>(self.db_conn).optional()? {
+                    Some(ident) => self.open_email(ident).map(Some),
+                    None => Ok(None),
+                }
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Unlink provider {} of user {} error occurred.", provider_arg, user_id_arg))
+                    .into()
+            })
+    }
+
+    /// Sets password hash of the user, switching the identity to e-mail provider. The user has to
+    /// change the password on the next login. Returns `None` if the user had no identity
+    fn set_password(&self, user_id_arg: UserId, password_hash: String) -> RepoResult<Option<Identity>> {
+        acl::check(&*self.acl, Resource::Identities, Action::Update, self, None)
+            .and_then(|_| {
+                let filtered = identities.filter(user_id.eq(user_id_arg));
+                let query = diesel::update(filtered).set((
+                    provider.eq(Provider::Email),
+                    password.eq(Some(password_hash)),
+                    password_hash_format.eq(None::<PasswordHashFormat>),
+                    must_change_password.eq(true),
+                ));
+                match query.get_result::<Identity>(self.db_conn).optional()? {
+                    Some(ident) => self.open_email(ident).map(Some),
+                    None => Ok(None),
+                }
+            })
+            .map_err(|e: FailureError| e.context(format!("Set password of user {} error occurred.", user_id_arg)).into())
     }
+
+    /// Replaces e-mail of the identity stored in plain text by its blind index, returns whether it was replaced
+    fn seal_email(&self, user_id_arg: UserId) -> RepoResult<bool> {
+        let cipher = match self.pii_cipher {
+            Some(ref cipher) => cipher,
+            None => return Ok(false),
+        };
+
+        let plain_email = match identities
+            .filter(user_id.eq(user_id_arg))
+            .select(email)
+            .first::<String>(self.db_conn)
+            .optional()?
+        {
+            Some(ref stored) if !is_blind_index(stored) => stored.clone(),
+            _ => return Ok(false),
+        };
+        // identities changed concurrently are left as they are
+        let filter = identities.filter(user_id.eq(user_id_arg)).filter(email.eq(plain_email.clone()));
+        diesel::update(filter)
+            .set(email.eq(cipher.blind_index("email", &plain_email)))
+            .execute(self.db_conn)
+            .map(|updated| updated > 0)
+            .map_err(|e| {
+                e.context(format!("Seal e-mail of identity of user {} error occurred.", user_id_arg))
+                    .into()
+            })
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, Identity>
+    for IdentitiesRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, user_id_arg: UserId, scope: &Scope, obj: Option<&Identity>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => {
+                if let Some(ident) = obj {
+                    ident.user_id == user_id_arg
+                } else {
+                    false
+                }
+            }
+        }
+    }
+}
+
+/// E-mails always have `@`, blind indexes are URL safe base64
+fn is_blind_index(value: &str) -> bool {
+    !value.contains('@')
 }
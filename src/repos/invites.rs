@@ -0,0 +1,103 @@
+//! Repo for invites table, invite codes required for registration in invite-only mode
+
+use std::time::SystemTime;
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_types::UserId;
+
+use repos::legacy_acl::*;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::*;
+use models::{Invite, NewInvite};
+use schema::invites::dsl::*;
+
+/// Invites repository, responsible for handling invites
+pub trait InvitesRepo {
+    /// Creates new invite
+    fn create(&self, payload: NewInvite) -> RepoResult<Invite>;
+
+    /// Uses the invite once, returns `None` if the invite does not exist, is expired or used up.
+    /// Invites are used by people not registered yet, no ACL check
+    fn use_invite(&self, code: String) -> RepoResult<Option<Invite>>;
+
+    /// Returns the use of the invite back, if registration with it has failed
+    fn release_invite(&self, code: String) -> RepoResult<()>;
+}
+
+/// Implementation of Invites trait
+pub struct InvitesRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, Invite>>,
+    pub db_conn: &'a T,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> InvitesRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, Invite>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> InvitesRepo for InvitesRepoImpl<'a, T> {
+    /// Creates new invite
+    fn create(&self, payload: NewInvite) -> RepoResult<Invite> {
+        let query = diesel::insert_into(invites).values(&payload);
+        query
+            .get_result::<Invite>(self.db_conn)
+            .map_err(From::from)
+            .and_then(|invite: Invite| {
+                acl::check(&*self.acl, Resource::Invites, Action::Create, self, Some(&invite))?;
+                Ok(invite)
+            })
+            .map_err(|e: FailureError| e.context(format!("Create invite {:?} error occured", payload)).into())
+    }
+
+    /// Uses the invite once, returns `None` if the invite does not exist, is expired or used up.
+    /// Invites are used by people not registered yet, no ACL check
+    fn use_invite(&self, code_arg: String) -> RepoResult<Option<Invite>> {
+        let filtered = invites
+            .filter(code.eq(&code_arg))
+            .filter(uses.lt(max_uses))
+            .filter(expires_at.gt(SystemTime::now()));
+        let query = diesel::update(filtered).set(uses.eq(uses + 1));
+        query
+            .get_result::<Invite>(self.db_conn)
+            .optional()
+            .map_err(|e| e.context(format!("Use invite {} error occured", code_arg)).into())
+    }
+
+    /// Returns the use of the invite back, if registration with it has failed
+    fn release_invite(&self, code_arg: String) -> RepoResult<()> {
+        let filtered = invites.filter(code.eq(&code_arg)).filter(uses.gt(0));
+        let query = diesel::update(filtered).set(uses.eq(uses - 1));
+        query
+            .execute(self.db_conn)
+            .map(|_| ())
+            .map_err(|e| e.context(format!("Release invite {} error occured", code_arg)).into())
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, Invite>
+    for InvitesRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, user_id_arg: UserId, scope: &Scope, obj: Option<&Invite>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => {
+                if let Some(invite) = obj {
+                    invite.created_by == Some(user_id_arg)
+                } else {
+                    false
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! Repo for jobs table, long-running operations run in background
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use failure::Error as FailureError;
+use uuid::Uuid;
+
+use stq_types::UserId;
+
+use repos::legacy_acl::*;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::*;
+use models::{Job, JobKind, JobState, NewJob, UpdateJob};
+use schema::jobs::dsl::*;
+
+/// Jobs repository, responsible for handling jobs
+pub trait JobsRepo {
+    /// Creates pending job
+    fn create(&self, payload: NewJob) -> RepoResult<Job>;
+
+    /// Find specific job by ID
+    fn find(&self, id_arg: Uuid) -> RepoResult<Option<Job>>;
+
+    /// Updates state and progress of the job
+    fn update(&self, id_arg: Uuid, payload: UpdateJob) -> RepoResult<Job>;
+
+    /// Returns the most recently created job of the kind in the state
+    fn find_latest(&self, kind_arg: JobKind, state_arg: JobState) -> RepoResult<Option<Job>>;
+}
+
+/// Implementation of Jobs trait
+pub struct JobsRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, Job>>,
+    pub db_conn: &'a T,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> JobsRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, Job>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> JobsRepo for JobsRepoImpl<'a, T> {
+    /// Creates pending job
+    fn create(&self, payload: NewJob) -> RepoResult<Job> {
+        acl::check(&*self.acl, Resource::Jobs, Action::Create, self, None)?;
+
+        let query = diesel::insert_into(jobs).values(&payload);
+        query
+            .get_result::<Job>(self.db_conn)
+            .map_err(|e| e.context(format!("Create job {:?} error occured", payload)).into())
+    }
+
+    /// Find specific job by ID
+    fn find(&self, id_arg: Uuid) -> RepoResult<Option<Job>> {
+        let query = jobs.find(id_arg);
+        query
+            .get_result::<Job>(self.db_conn)
+            .optional()
+            .map_err(From::from)
+            .and_then(|job: Option<Job>| {
+                if let Some(ref job) = job {
+                    acl::check(&*self.acl, Resource::Jobs, Action::Read, self, Some(job))?;
+                }
+                Ok(job)
+            })
+            .map_err(|e: FailureError| e.context(format!("Find job {} error occured", id_arg)).into())
+    }
+
+    /// Updates state and progress of the job
+    fn update(&self, id_arg: Uuid, payload: UpdateJob) -> RepoResult<Job> {
+        let query = diesel::update(jobs.find(id_arg)).set(&payload);
+        query
+            .get_result::<Job>(self.db_conn)
+            .map_err(From::from)
+            .and_then(|job: Job| {
+                acl::check(&*self.acl, Resource::Jobs, Action::Update, self, Some(&job))?;
+                Ok(job)
+            })
+            .map_err(|e: FailureError| e.context(format!("Update job {} error occured", id_arg)).into())
+    }
+
+    /// Returns the most recently created job of the kind in the state
+    fn find_latest(&self, kind_arg: JobKind, state_arg: JobState) -> RepoResult<Option<Job>> {
+        let query = jobs.filter(kind.eq(kind_arg)).filter(state.eq(state_arg)).order(created_at.desc());
+        query
+            .first::<Job>(self.db_conn)
+            .optional()
+            .map_err(From::from)
+            .and_then(|job: Option<Job>| {
+                if let Some(ref job) = job {
+                    acl::check(&*self.acl, Resource::Jobs, Action::Read, self, Some(job))?;
+                }
+                Ok(job)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Find latest {} job of kind {} error occured", state_arg, kind_arg))
+                    .into()
+            })
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, Job>
+    for JobsRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, user_id_arg: UserId, scope: &Scope, obj: Option<&Job>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => {
+                if let Some(job) = obj {
+                    job.created_by == Some(user_id_arg)
+                } else {
+                    false
+                }
+            }
+        }
+    }
+}
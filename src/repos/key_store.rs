@@ -0,0 +1,76 @@
+//! Key store of data keys of users sealing their PII, see `PiiCipher`. The store has its own
+//! database, so that backups of users don't hold the keys, and shredded keys are gone for good
+//! once backups of the store expire.
+use std::collections::HashMap;
+
+use diesel;
+use diesel::prelude::*;
+use failure::Fail;
+
+use stq_types::UserId;
+
+use super::types::{DbPool, RepoResult};
+use errors::Error;
+use key_store_schema::user_keys;
+use models::{NewUserKey, UserKey};
+
+/// Data keys of users wrapped by the master key
+pub trait KeyStore: Send + Sync {
+    /// Data keys of the users by user id, users without keys are missing
+    fn find(&self, user_ids: Vec<UserId>) -> RepoResult<HashMap<i32, Vec<u8>>>;
+
+    /// Data key of the user, `new_key` is stored if the user has none
+    fn find_or_create(&self, new_key: NewUserKey) -> RepoResult<Vec<u8>>;
+
+    /// Deletes the data key of the user
+    fn delete(&self, user_id: UserId) -> RepoResult<()>;
+}
+
+/// Key store in the database of `pii_encryption.key_store_database`
+pub struct KeyStoreImpl {
+    db_pool: DbPool,
+}
+
+impl KeyStoreImpl {
+    pub fn new(db_pool: DbPool) -> Self {
+        Self { db_pool }
+    }
+}
+
+impl KeyStore for KeyStoreImpl {
+    fn find(&self, user_ids: Vec<UserId>) -> RepoResult<HashMap<i32, Vec<u8>>> {
+        let conn = self.db_pool.get().map_err(|e| e.context(Error::Connection))?;
+        let query = user_keys::table.filter(user_keys::user_id.eq_any(user_ids.clone()));
+
+        query
+            .get_results::<UserKey>(&*conn)
+            .map(|keys| keys.into_iter().map(|key| (key.user_id.0, key.data_key)).collect())
+            .map_err(|e| e.context(format!("Find data keys of users {:?} error occured", user_ids)).into())
+    }
+
+    fn find_or_create(&self, new_key: NewUserKey) -> RepoResult<Vec<u8>> {
+        let conn = self.db_pool.get().map_err(|e| e.context(Error::Connection))?;
+        let user_id = new_key.user_id;
+
+        // concurrent requests of the same user get the key stored first
+        diesel::insert_into(user_keys::table)
+            .values(&new_key)
+            .on_conflict_do_nothing()
+            .execute(&*conn)
+            .and_then(|_| user_keys::table.find(user_id).get_result::<UserKey>(&*conn))
+            .map(|key| key.data_key)
+            .map_err(|e| {
+                e.context(format!("Find or create data key of user {} error occured", user_id))
+                    .into()
+            })
+    }
+
+    fn delete(&self, user_id: UserId) -> RepoResult<()> {
+        let conn = self.db_pool.get().map_err(|e| e.context(Error::Connection))?;
+
+        diesel::delete(user_keys::table.filter(user_keys::user_id.eq(user_id)))
+            .execute(&*conn)
+            .map(|_| ())
+            .map_err(|e| e.context(format!("Delete data key of user {} error occured", user_id)).into())
+    }
+}
@@ -0,0 +1,96 @@
+//! Repo for legacy_sync_states table, outcomes of writes mirrored to the legacy system
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_types::UserId;
+
+use repos::legacy_acl::*;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::*;
+use models::{LegacySyncState, NewLegacySyncState};
+use schema::legacy_sync_states::dsl::*;
+
+/// LegacySyncStates repository, responsible for handling outcomes of mirroring users
+pub trait LegacySyncStatesRepo {
+    /// Saves outcome of the last mirrored write of the user
+    fn upsert(&self, payload: NewLegacySyncState) -> RepoResult<LegacySyncState>;
+
+    /// Returns number of users whose last write was mirrored or failed
+    fn count(&self, synced_arg: bool) -> RepoResult<i64>;
+
+    /// Returns users whose last mirrored write failed, oldest failures first
+    fn list_failed(&self, limit: i64) -> RepoResult<Vec<LegacySyncState>>;
+}
+
+/// Implementation of LegacySyncStates trait
+pub struct LegacySyncStatesRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, LegacySyncState>>,
+    pub db_conn: &'a T,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> LegacySyncStatesRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, LegacySyncState>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> LegacySyncStatesRepo
+    for LegacySyncStatesRepoImpl<'a, T>
+{
+    /// Saves outcome of the last mirrored write of the user
+    fn upsert(&self, payload: NewLegacySyncState) -> RepoResult<LegacySyncState> {
+        acl::check(&*self.acl, Resource::LegacySyncStates, Action::Update, self, None)
+            .and_then(|_| {
+                let query = diesel::insert_into(legacy_sync_states)
+                    .values(&payload)
+                    .on_conflict(user_id)
+                    .do_update()
+                    .set(&payload);
+                query.get_result::<LegacySyncState>(self.db_conn).map_err(From::from)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Upsert legacy sync state of user {} error occured", payload.user_id))
+                    .into()
+            })
+    }
+
+    /// Returns number of users whose last write was mirrored or failed
+    fn count(&self, synced_arg: bool) -> RepoResult<i64> {
+        acl::check(&*self.acl, Resource::LegacySyncStates, Action::Read, self, None)
+            .and_then(|_| {
+                let query = legacy_sync_states.filter(synced.eq(synced_arg)).count();
+                query.get_result::<i64>(self.db_conn).map_err(From::from)
+            })
+            .map_err(|e: FailureError| e.context("Count legacy sync states error occured").into())
+    }
+
+    /// Returns users whose last mirrored write failed, oldest failures first
+    fn list_failed(&self, limit: i64) -> RepoResult<Vec<LegacySyncState>> {
+        acl::check(&*self.acl, Resource::LegacySyncStates, Action::Read, self, None)
+            .and_then(|_| {
+                let query = legacy_sync_states.filter(synced.eq(false)).order(updated_at).limit(limit);
+                query.get_results::<LegacySyncState>(self.db_conn).map_err(From::from)
+            })
+            .map_err(|e: FailureError| e.context("List failed legacy sync states error occured").into())
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, LegacySyncState>
+    for LegacySyncStatesRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, _user_id: UserId, scope: &Scope, _obj: Option<&LegacySyncState>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => false,
+        }
+    }
+}
@@ -0,0 +1,90 @@
+//! Repo for login_history table, logins along with decisions made on their fraud score
+
+use std::time::SystemTime;
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_types::UserId;
+
+use repos::legacy_acl::*;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::*;
+use models::{LoginEvent, NewLoginEvent};
+use schema::login_history::dsl::*;
+
+/// LoginHistory repository, responsible for handling login events
+pub trait LoginHistoryRepo {
+    /// Records the login. Logins are recorded on behalf of anyone, no ACL check
+    fn add(&self, payload: NewLoginEvent) -> RepoResult<LoginEvent>;
+
+    /// Returns logins of the user made since `since`, latest first
+    fn list_since(&self, user_id: UserId, since: SystemTime) -> RepoResult<Vec<LoginEvent>>;
+}
+
+/// Implementation of LoginHistory trait
+pub struct LoginHistoryRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, LoginEvent>>,
+    pub db_conn: &'a T,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> LoginHistoryRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, LoginEvent>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> LoginHistoryRepo
+    for LoginHistoryRepoImpl<'a, T>
+{
+    /// Records the login. Logins are recorded on behalf of anyone, no ACL check
+    fn add(&self, payload: NewLoginEvent) -> RepoResult<LoginEvent> {
+        let query = diesel::insert_into(login_history).values(&payload);
+        query
+            .get_result::<LoginEvent>(self.db_conn)
+            .map_err(|e| e.context(format!("Add login event {:?} error occured", payload)).into())
+    }
+
+    /// Returns logins of the user made since `since`, latest first
+    fn list_since(&self, user_id_arg: UserId, since: SystemTime) -> RepoResult<Vec<LoginEvent>> {
+        let query = login_history
+            .filter(user_id.eq(user_id_arg))
+            .filter(created_at.ge(since))
+            .order(created_at.desc());
+        query
+            .get_results::<LoginEvent>(self.db_conn)
+            .map_err(From::from)
+            .and_then(|events: Vec<LoginEvent>| {
+                for event in &events {
+                    acl::check(&*self.acl, Resource::LoginHistory, Action::Read, self, Some(event))?;
+                }
+                Ok(events)
+            })
+            .map_err(|e: FailureError| e.context(format!("List login events of user {} error occured", user_id_arg)).into())
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, LoginEvent>
+    for LoginHistoryRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, user_id_arg: UserId, scope: &Scope, obj: Option<&LoginEvent>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => {
+                if let Some(event) = obj {
+                    event.user_id == user_id_arg
+                } else {
+                    false
+                }
+            }
+        }
+    }
+}
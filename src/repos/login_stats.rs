@@ -0,0 +1,79 @@
+//! Repo for login_stats table, counters of tokens issued per provider per day
+
+use chrono::NaiveDate;
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_static_resources::Provider;
+use stq_types::UserId;
+
+use repos::legacy_acl::*;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::*;
+use models::LoginStat;
+use schema::login_stats::dsl::*;
+
+/// LoginStats repository, responsible for handling login counters
+pub trait LoginStatsRepo {
+    /// Counts a login with the provider on the date. Logins are counted for anyone, no ACL check
+    fn add_login(&self, date_arg: NaiveDate, provider_arg: Provider) -> RepoResult<()>;
+
+    /// Returns daily counters from `from` to `to` inclusive
+    fn list(&self, from: NaiveDate, to: NaiveDate) -> RepoResult<Vec<LoginStat>>;
+}
+
+/// Implementation of LoginStats trait
+pub struct LoginStatsRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, LoginStat>>,
+    pub db_conn: &'a T,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> LoginStatsRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, LoginStat>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> LoginStatsRepo for LoginStatsRepoImpl<'a, T> {
+    /// Counts a login with the provider on the date. Logins are counted for anyone, no ACL check
+    fn add_login(&self, date_arg: NaiveDate, provider_arg: Provider) -> RepoResult<()> {
+        let query = diesel::insert_into(login_stats)
+            .values((date.eq(date_arg), provider.eq(provider_arg.clone()), logins.eq(1)))
+            .on_conflict((date, provider))
+            .do_update()
+            .set(logins.eq(logins + 1));
+        query.execute(self.db_conn).map(|_| ()).map_err(|e| {
+            e.context(format!("Add {} login on {} error occured", provider_arg, date_arg))
+                .into()
+        })
+    }
+
+    /// Returns daily counters from `from` to `to` inclusive
+    fn list(&self, from: NaiveDate, to: NaiveDate) -> RepoResult<Vec<LoginStat>> {
+        acl::check(&*self.acl, Resource::Stats, Action::Read, self, None)?;
+
+        let query = login_stats.filter(date.between(from, to)).order((date, provider));
+        query
+            .get_results(self.db_conn)
+            .map_err(|e| e.context(format!("List login stats from {} to {} error occured", from, to)).into())
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, LoginStat>
+    for LoginStatsRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, _user_id: UserId, scope: &Scope, _obj: Option<&LoginStat>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => false,
+        }
+    }
+}
@@ -2,17 +2,87 @@
 
 #[macro_use]
 pub mod acl;
+pub mod access_tokens;
+pub mod attempts_cache;
+pub mod audit_log;
+pub mod backfills;
+pub mod child_accounts;
+pub mod client_brandings;
+pub mod clients;
+pub mod countries;
+pub mod crm_drifts;
+pub mod deletion_requests;
+pub mod device_codes;
+pub mod devices;
+pub mod funnel_events;
 pub mod identities;
+pub mod invites;
+pub mod jobs;
+pub mod key_store;
+pub mod legacy_sync_states;
+pub mod login_history;
+pub mod login_stats;
+pub mod oauth_consents;
+pub mod pii_cipher;
+pub mod profile_prompts;
+pub mod provider_blocks;
+pub mod recovery;
+pub mod refresh_tokens;
 pub mod repo_factory;
 pub mod reset_token;
+pub mod role_access_policies;
+pub mod role_requests;
+pub mod schema_migrations;
+pub mod security_answers;
+pub mod segment_exports;
+pub mod sessions;
+pub mod sharding;
 pub mod types;
+pub mod used_tokens;
 pub mod user_roles;
+pub mod user_tags;
 pub mod users;
+pub mod waitlist;
 
+pub use self::access_tokens::*;
 pub use self::acl::*;
+pub use self::attempts_cache::*;
+pub use self::audit_log::*;
+pub use self::backfills::*;
+pub use self::child_accounts::*;
+pub use self::client_brandings::*;
+pub use self::clients::*;
+pub use self::countries::*;
+pub use self::crm_drifts::*;
+pub use self::deletion_requests::*;
+pub use self::device_codes::*;
+pub use self::devices::*;
+pub use self::funnel_events::*;
 pub use self::identities::*;
+pub use self::invites::*;
+pub use self::jobs::*;
+pub use self::key_store::*;
+pub use self::legacy_sync_states::*;
+pub use self::login_history::*;
+pub use self::login_stats::*;
+pub use self::oauth_consents::*;
+pub use self::pii_cipher::*;
+pub use self::profile_prompts::*;
+pub use self::provider_blocks::*;
+pub use self::recovery::*;
+pub use self::refresh_tokens::*;
 pub use self::repo_factory::*;
 pub use self::reset_token::*;
+pub use self::role_access_policies::*;
+pub use self::role_requests::*;
+pub use self::schema_migrations::*;
+pub use self::security_answers::*;
+pub use self::segment_exports::*;
+pub use self::sessions::*;
+pub use self::sharding::*;
 pub use self::types::*;
+pub use self::used_tokens::*;
 pub use self::user_roles::*;
+pub use self::user_tags::*;
 pub use self::users::*;
+pub use self::waitlist::*;
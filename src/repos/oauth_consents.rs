@@ -0,0 +1,175 @@
+//! Repo for oauth_consents table, scopes users granted to third-party clients,
+//! and oauth_revocations table, tokens revoked when the user disconnects the client
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_types::UserId;
+
+use repos::legacy_acl::*;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::*;
+use models::{NewOAuthConsent, NewOAuthRevocation, OAuthConsent, OAuthRevocation};
+use schema::oauth_consents::dsl::*;
+use schema::oauth_revocations;
+
+/// OAuthConsents repository, responsible for handling consents of users to third-party clients
+pub trait OAuthConsentsRepo {
+    /// Find consent of the user to the client
+    fn find(&self, user_id: UserId, client_id: String) -> RepoResult<Option<OAuthConsent>>;
+
+    /// Saves consent, scopes of existing consent are replaced
+    fn upsert(&self, payload: NewOAuthConsent) -> RepoResult<OAuthConsent>;
+
+    /// Returns consents of the user
+    fn list_for_user(&self, user_id: UserId) -> RepoResult<Vec<OAuthConsent>>;
+
+    /// Deletes consent and revokes tokens issued to the client for the user,
+    /// returns `None` if there was no such consent
+    fn revoke(&self, payload: NewOAuthRevocation) -> RepoResult<Option<OAuthConsent>>;
+
+    /// Find revocation of tokens issued to the client for the user, no ACL check
+    fn find_revocation(&self, user_id: UserId, client_id: String) -> RepoResult<Option<OAuthRevocation>>;
+}
+
+/// Implementation of OAuthConsents trait
+pub struct OAuthConsentsRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, OAuthConsent>>,
+    pub db_conn: &'a T,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> OAuthConsentsRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, OAuthConsent>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> OAuthConsentsRepo
+    for OAuthConsentsRepoImpl<'a, T>
+{
+    /// Find consent of the user to the client
+    fn find(&self, user_id_arg: UserId, client_id_arg: String) -> RepoResult<Option<OAuthConsent>> {
+        let query = oauth_consents.find((user_id_arg, &client_id_arg));
+        query
+            .get_result::<OAuthConsent>(self.db_conn)
+            .optional()
+            .map_err(From::from)
+            .and_then(|consent: Option<OAuthConsent>| {
+                if let Some(ref consent) = consent {
+                    acl::check(&*self.acl, Resource::OAuthConsents, Action::Read, self, Some(consent))?;
+                }
+                Ok(consent)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!(
+                    "Find consent of user {} to client {} error occured",
+                    user_id_arg, client_id_arg
+                ))
+                .into()
+            })
+    }
+
+    /// Saves consent, scopes of existing consent are replaced
+    fn upsert(&self, payload: NewOAuthConsent) -> RepoResult<OAuthConsent> {
+        let query = diesel::insert_into(oauth_consents)
+            .values(&payload)
+            .on_conflict((user_id, client_id))
+            .do_update()
+            .set(scopes.eq(&payload.scopes));
+        query
+            .get_result::<OAuthConsent>(self.db_conn)
+            .map_err(From::from)
+            .and_then(|consent: OAuthConsent| {
+                acl::check(&*self.acl, Resource::OAuthConsents, Action::Create, self, Some(&consent))?;
+                Ok(consent)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!(
+                    "Save consent of user {} to client {} error occured",
+                    payload.user_id, payload.client_id
+                ))
+                .into()
+            })
+    }
+
+    /// Returns consents of the user
+    fn list_for_user(&self, user_id_arg: UserId) -> RepoResult<Vec<OAuthConsent>> {
+        let query = oauth_consents.filter(user_id.eq(user_id_arg)).order(created_at);
+        query
+            .get_results::<OAuthConsent>(self.db_conn)
+            .map_err(From::from)
+            .and_then(|consents: Vec<OAuthConsent>| {
+                for consent in &consents {
+                    acl::check(&*self.acl, Resource::OAuthConsents, Action::Read, self, Some(consent))?;
+                }
+                Ok(consents)
+            })
+            .map_err(|e: FailureError| e.context(format!("List consents of user {} error occured", user_id_arg)).into())
+    }
+
+    /// Deletes consent and revokes tokens issued to the client for the user,
+    /// returns `None` if there was no such consent
+    fn revoke(&self, payload: NewOAuthRevocation) -> RepoResult<Option<OAuthConsent>> {
+        let query = diesel::delete(oauth_consents.find((payload.user_id, &payload.client_id)));
+        query
+            .get_result::<OAuthConsent>(self.db_conn)
+            .optional()
+            .map_err(From::from)
+            .and_then(|consent: Option<OAuthConsent>| {
+                if let Some(ref consent) = consent {
+                    acl::check(&*self.acl, Resource::OAuthConsents, Action::Delete, self, Some(consent))?;
+                    diesel::insert_into(oauth_revocations::table)
+                        .values(&payload)
+                        .on_conflict((oauth_revocations::user_id, oauth_revocations::client_id))
+                        .do_update()
+                        .set(oauth_revocations::revoke_before.eq(payload.revoke_before))
+                        .execute(self.db_conn)?;
+                }
+                Ok(consent)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!(
+                    "Revoke consent of user {} to client {} error occured",
+                    payload.user_id, payload.client_id
+                ))
+                .into()
+            })
+    }
+
+    /// Find revocation of tokens issued to the client for the user, no ACL check
+    fn find_revocation(&self, user_id_arg: UserId, client_id_arg: String) -> RepoResult<Option<OAuthRevocation>> {
+        let query = oauth_revocations::table.find((user_id_arg, &client_id_arg));
+        query.get_result::<OAuthRevocation>(self.db_conn).optional().map_err(|e| {
+            e.context(format!(
+                "Find revocation of user {} to client {} error occured",
+                user_id_arg, client_id_arg
+            ))
+            .into()
+        })
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, OAuthConsent>
+    for OAuthConsentsRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, user_id_arg: UserId, scope: &Scope, obj: Option<&OAuthConsent>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => {
+                if let Some(consent) = obj {
+                    consent.user_id == user_id_arg
+                } else {
+                    false
+                }
+            }
+        }
+    }
+}
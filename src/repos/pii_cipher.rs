@@ -0,0 +1,242 @@
+//! Sealing of PII of users by per-user data keys, for crypto-shredding. Data keys are random
+//! AES-256-GCM keys wrapped by the master key and kept in the key store, apart from the users
+//! database. Once the data key of a user is deleted, the sealed PII can't be read anymore,
+//! neither in the database nor in its backups.
+//!
+//! Sealed users are looked up by blind indexes of their emails, phones and names, HMACs of
+//! the lowercase values by a key derived from the master key, so only whole values match.
+//! Identities of sealed users keep the blind index of the email too, see `repos::identities`.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use base64;
+use failure::Error as FailureError;
+use failure::Fail;
+use ring::aead::{self, OpeningKey, SealingKey, AES_256_GCM};
+use ring::digest;
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_json;
+
+use stq_types::UserId;
+
+use super::key_store::KeyStore;
+use config;
+use models::{NewUserKey, UserPii};
+
+/// Authenticated along with wrapped data keys
+const DATA_KEY_AD: &[u8] = b"user-key-v1";
+/// Authenticated along with sealed PII
+const PII_AD: &[u8] = b"user-pii-v1";
+/// Derives the key of blind indexes from the master key
+const INDEX_KEY_INFO: &[u8] = b"user-pii-index-v1";
+const NONCE_LEN: usize = 12;
+
+/// Seals and opens PII by data keys wrapped by the master key, cloning is cheap enough
+#[derive(Clone)]
+pub struct PiiCipher {
+    master_key: Vec<u8>,
+    index_key: Vec<u8>,
+    key_store: Arc<KeyStore>,
+}
+
+impl PiiCipher {
+    pub fn new(conf: &config::PiiEncryption, key_store: Arc<KeyStore>) -> Result<Self, FailureError> {
+        let master_key = base64::decode(&conf.master_key).map_err(|e| e.context("PII master key is not valid base64"))?;
+        if master_key.len() != AES_256_GCM.key_len() {
+            return Err(format_err!("PII master key must be {} bytes", AES_256_GCM.key_len()));
+        }
+        let index_key = hmac::sign(&hmac::SigningKey::new(&digest::SHA256, &master_key), INDEX_KEY_INFO)
+            .as_ref()
+            .to_vec();
+        Ok(Self {
+            master_key,
+            index_key,
+            key_store,
+        })
+    }
+
+    /// Blind index of the value of the field, stored in the column of the field of sealed users
+    pub fn blind_index(&self, field: &str, value: &str) -> String {
+        let index_key = hmac::SigningKey::new(&digest::SHA256, &self.index_key);
+        let index = hmac::sign(&index_key, format!("{}:{}", field, value.to_lowercase()).as_bytes());
+        base64::encode_config(index.as_ref(), base64::URL_SAFE_NO_PAD)
+    }
+
+    /// Seals PII of the user by its data key, the key is created for users having none
+    pub fn seal(&self, user_id: UserId, pii: &UserPii) -> Result<Vec<u8>, FailureError> {
+        let new_key = NewUserKey {
+            user_id,
+            data_key: self.new_data_key()?,
+        };
+        let wrapped_key = self.key_store.find_or_create(new_key)?;
+        let data_key = open(&self.master_key, DATA_KEY_AD, &wrapped_key)?;
+        seal(&data_key, PII_AD, serde_json::to_vec(pii)?)
+    }
+
+    /// Opens sealed PII of the users, returns it by user id. PII of users whose data keys were
+    /// shredded, e.g. deleted users restored from backups, is missing.
+    pub fn open(&self, sealed: Vec<(UserId, Vec<u8>)>) -> Result<HashMap<i32, UserPii>, FailureError> {
+        let data_keys = self.key_store.find(sealed.iter().map(|&(user_id, _)| user_id).collect())?;
+        let mut opened = HashMap::new();
+        for (user_id, sealed_pii) in sealed {
+            if let Some(wrapped_key) = data_keys.get(&user_id.0) {
+                let data_key = open(&self.master_key, DATA_KEY_AD, wrapped_key)?;
+                let pii = open(&data_key, PII_AD, &sealed_pii)?;
+                opened.insert(user_id.0, serde_json::from_slice(&pii)?);
+            }
+        }
+        Ok(opened)
+    }
+
+    /// Deletes the data key of the user, so that its sealed PII can't be opened anymore
+    pub fn shred(&self, user_id: UserId) -> Result<(), FailureError> {
+        self.key_store.delete(user_id)
+    }
+
+    /// Generates new data key, returns it wrapped by the master key
+    fn new_data_key(&self) -> Result<Vec<u8>, FailureError> {
+        let mut data_key = vec![0u8; AES_256_GCM.key_len()];
+        SystemRandom::new()
+            .fill(&mut data_key)
+            .map_err(|_| format_err!("Couldn't generate data key"))?;
+        seal(&self.master_key, DATA_KEY_AD, data_key)
+    }
+}
+
+/// Encrypts `plaintext`, the nonce is prepended to the result
+fn seal(key: &[u8], ad: &[u8], plaintext: Vec<u8>) -> Result<Vec<u8>, FailureError> {
+    let sealing_key = SealingKey::new(&AES_256_GCM, key).map_err(|_| format_err!("Key must be {} bytes", AES_256_GCM.key_len()))?;
+    let mut nonce = [0u8; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| format_err!("Couldn't generate nonce"))?;
+
+    let tag_len = AES_256_GCM.tag_len();
+    let mut in_out = plaintext;
+    in_out.extend(vec![0u8; tag_len]);
+    let sealed_len = aead::seal_in_place(&sealing_key, &nonce, ad, &mut in_out, tag_len).map_err(|_| format_err!("Couldn't encrypt"))?;
+
+    let mut sealed = nonce.to_vec();
+    sealed.extend_from_slice(&in_out[..sealed_len]);
+    Ok(sealed)
+}
+
+fn open(key: &[u8], ad: &[u8], sealed: &[u8]) -> Result<Vec<u8>, FailureError> {
+    let opening_key = OpeningKey::new(&AES_256_GCM, key).map_err(|_| format_err!("Key must be {} bytes", AES_256_GCM.key_len()))?;
+    if sealed.len() < NONCE_LEN {
+        return Err(format_err!("Sealed data is too short"));
+    }
+    let mut sealed = sealed.to_vec();
+    let (nonce, in_out) = sealed.split_at_mut(NONCE_LEN);
+    let plaintext = aead::open_in_place(&opening_key, nonce, ad, 0, in_out)
+        .map_err(|_| format_err!("Sealed data is damaged or sealed by another key"))?;
+    Ok(plaintext.to_vec())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Mutex;
+
+    use chrono::NaiveDate;
+
+    use super::*;
+    use repos::types::RepoResult;
+
+    #[derive(Default)]
+    pub struct KeyStoreMock {
+        keys: Mutex<HashMap<i32, Vec<u8>>>,
+    }
+
+    impl KeyStore for KeyStoreMock {
+        fn find(&self, user_ids: Vec<UserId>) -> RepoResult<HashMap<i32, Vec<u8>>> {
+            let keys = self.keys.lock().unwrap();
+            Ok(user_ids
+                .into_iter()
+                .filter_map(|user_id| keys.get(&user_id.0).map(|key| (user_id.0, key.clone())))
+                .collect())
+        }
+
+        fn find_or_create(&self, new_key: NewUserKey) -> RepoResult<Vec<u8>> {
+            let mut keys = self.keys.lock().unwrap();
+            Ok(keys.entry(new_key.user_id.0).or_insert(new_key.data_key).clone())
+        }
+
+        fn delete(&self, user_id: UserId) -> RepoResult<()> {
+            self.keys.lock().unwrap().remove(&user_id.0);
+            Ok(())
+        }
+    }
+
+    pub fn create_cipher(master_key: &[u8], key_store: Arc<KeyStore>) -> PiiCipher {
+        let conf = config::PiiEncryption {
+            master_key: base64::encode(master_key),
+            key_store_database: String::default(),
+        };
+        PiiCipher::new(&conf, key_store).unwrap()
+    }
+
+    fn create_pii() -> UserPii {
+        UserPii {
+            email: Some("john@example.com".to_string()),
+            phone: Some("+79031234567".to_string()),
+            first_name: Some("John".to_string()),
+            last_name: Some("Doe".to_string()),
+            middle_name: None,
+            birthdate: Some(NaiveDate::from_ymd(1990, 5, 17)),
+        }
+    }
+
+    #[test]
+    fn test_seal_and_open_pii() {
+        let cipher = create_cipher(&[1u8; 32], Arc::new(KeyStoreMock::default()));
+        let sealed = cipher.seal(UserId(1), &create_pii()).unwrap();
+        assert_eq!(String::from_utf8_lossy(&sealed).contains("John"), false);
+        let opened = cipher.open(vec![(UserId(1), sealed)]).unwrap();
+        assert_eq!(opened.get(&1), Some(&create_pii()));
+    }
+
+    #[test]
+    fn test_pii_is_not_opened_by_other_data_key() {
+        let cipher = create_cipher(&[1u8; 32], Arc::new(KeyStoreMock::default()));
+        let sealed = cipher.seal(UserId(1), &create_pii()).unwrap();
+        cipher.seal(UserId(2), &create_pii()).unwrap();
+        assert_eq!(cipher.open(vec![(UserId(2), sealed)]).is_err(), true);
+    }
+
+    #[test]
+    fn test_data_key_is_not_unwrapped_by_other_master_key() {
+        let key_store = Arc::new(KeyStoreMock::default());
+        let sealed = create_cipher(&[1u8; 32], key_store.clone()).seal(UserId(1), &create_pii()).unwrap();
+        assert_eq!(create_cipher(&[2u8; 32], key_store).open(vec![(UserId(1), sealed)]).is_err(), true);
+    }
+
+    #[test]
+    fn test_pii_is_not_opened_after_shredding() {
+        let cipher = create_cipher(&[1u8; 32], Arc::new(KeyStoreMock::default()));
+        let sealed = cipher.seal(UserId(1), &create_pii()).unwrap();
+        cipher.shred(UserId(1)).unwrap();
+        assert_eq!(cipher.open(vec![(UserId(1), sealed)]).unwrap().is_empty(), true);
+    }
+
+    #[test]
+    fn test_blind_index() {
+        let cipher = create_cipher(&[1u8; 32], Arc::new(KeyStoreMock::default()));
+        let index = cipher.blind_index("email", "john@example.com");
+        assert_eq!(index.contains("john"), false);
+        assert_eq!(cipher.blind_index("email", "John@Example.com"), index);
+        assert_ne!(cipher.blind_index("first_name", "john@example.com"), index);
+        let other_cipher = create_cipher(&[2u8; 32], Arc::new(KeyStoreMock::default()));
+        assert_ne!(other_cipher.blind_index("email", "john@example.com"), index);
+    }
+
+    #[test]
+    fn test_short_master_key() {
+        let conf = config::PiiEncryption {
+            master_key: base64::encode(&[1u8; 16]),
+            key_store_database: String::default(),
+        };
+        assert_eq!(PiiCipher::new(&conf, Arc::new(KeyStoreMock::default())).is_err(), true);
+    }
+}
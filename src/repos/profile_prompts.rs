@@ -0,0 +1,101 @@
+//! Repo for profile_prompt_dismissals table, profile prompts dismissed by users
+
+use std::time::SystemTime;
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_types::UserId;
+
+use repos::legacy_acl::*;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::*;
+use models::{NewProfilePromptDismissal, ProfilePromptDismissal};
+use schema::profile_prompt_dismissals::dsl::*;
+
+/// ProfilePrompts repository, responsible for handling dismissed profile prompts
+pub trait ProfilePromptsRepo {
+    /// Returns prompts of the user snoozed at `now`
+    fn list_snoozed(&self, user_id: UserId, now: SystemTime) -> RepoResult<Vec<ProfilePromptDismissal>>;
+
+    /// Dismisses the prompt, snooze time of the prompt dismissed before is replaced
+    fn dismiss(&self, payload: NewProfilePromptDismissal) -> RepoResult<ProfilePromptDismissal>;
+}
+
+/// Implementation of ProfilePrompts trait
+pub struct ProfilePromptsRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, ProfilePromptDismissal>>,
+    pub db_conn: &'a T,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> ProfilePromptsRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, ProfilePromptDismissal>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> ProfilePromptsRepo
+    for ProfilePromptsRepoImpl<'a, T>
+{
+    /// Returns prompts of the user snoozed at `now`
+    fn list_snoozed(&self, user_id_arg: UserId, now: SystemTime) -> RepoResult<Vec<ProfilePromptDismissal>> {
+        let query = profile_prompt_dismissals
+            .filter(user_id.eq(user_id_arg))
+            .filter(snoozed_until.gt(now));
+        query
+            .get_results::<ProfilePromptDismissal>(self.db_conn)
+            .map_err(From::from)
+            .and_then(|dismissals: Vec<ProfilePromptDismissal>| {
+                for dismissal in &dismissals {
+                    acl::check(&*self.acl, Resource::ProfilePrompts, Action::Read, self, Some(dismissal))?;
+                }
+                Ok(dismissals)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("List snoozed profile prompts of user {} error occured", user_id_arg))
+                    .into()
+            })
+    }
+
+    /// Dismisses the prompt, snooze time of the prompt dismissed before is replaced
+    fn dismiss(&self, payload: NewProfilePromptDismissal) -> RepoResult<ProfilePromptDismissal> {
+        let query = diesel::insert_into(profile_prompt_dismissals)
+            .values(&payload)
+            .on_conflict((user_id, field))
+            .do_update()
+            .set(snoozed_until.eq(payload.snoozed_until));
+        query
+            .get_result::<ProfilePromptDismissal>(self.db_conn)
+            .map_err(From::from)
+            .and_then(|dismissal: ProfilePromptDismissal| {
+                acl::check(&*self.acl, Resource::ProfilePrompts, Action::Create, self, Some(&dismissal))?;
+                Ok(dismissal)
+            })
+            .map_err(|e: FailureError| e.context(format!("Dismiss profile prompt {:?} error occured", payload)).into())
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, ProfilePromptDismissal>
+    for ProfilePromptsRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, user_id_arg: UserId, scope: &Scope, obj: Option<&ProfilePromptDismissal>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => {
+                if let Some(dismissal) = obj {
+                    dismissal.user_id == user_id_arg
+                } else {
+                    false
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,71 @@
+//! Provider blocks repo, keeps social providers unlinked from users, so that the next login with
+//! the provider is not merged into the user by e-mail.
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::dsl::exists;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::select;
+use diesel::Connection;
+use failure::Fail;
+
+use stq_static_resources::Provider;
+use stq_types::UserId;
+
+use super::types::RepoResult;
+use models::NewProviderBlock;
+use schema::provider_blocks::dsl::*;
+
+/// Provider blocks repository
+pub struct ProviderBlocksRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub db_conn: &'a T,
+}
+
+pub trait ProviderBlocksRepo {
+    /// Blocks logins of the user with the provider
+    fn block(&self, payload: NewProviderBlock) -> RepoResult<()>;
+
+    /// Checks if logins of the user with the provider are blocked
+    fn is_blocked(&self, user_id_arg: UserId, provider_arg: Provider) -> RepoResult<bool>;
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> ProviderBlocksRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T) -> Self {
+        Self { db_conn }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> ProviderBlocksRepo
+    for ProviderBlocksRepoImpl<'a, T>
+{
+    /// Blocks logins of the user with the provider
+    fn block(&self, payload: NewProviderBlock) -> RepoResult<()> {
+        let query = diesel::insert_into(provider_blocks).values(&payload).on_conflict_do_nothing();
+
+        query.execute(self.db_conn).map(|_| ()).map_err(|e| {
+            e.context(format!(
+                "Block provider {} of user {} error occured",
+                payload.provider, payload.user_id
+            ))
+            .into()
+        })
+    }
+
+    /// Checks if logins of the user with the provider are blocked
+    fn is_blocked(&self, user_id_arg: UserId, provider_arg: Provider) -> RepoResult<bool> {
+        let query = select(exists(
+            provider_blocks
+                .filter(user_id.eq(user_id_arg))
+                .filter(provider.eq(provider_arg.clone())),
+        ));
+
+        query.get_result(self.db_conn).map_err(|e| {
+            e.context(format!(
+                "Check block of provider {} of user {} error occured",
+                provider_arg, user_id_arg
+            ))
+            .into()
+        })
+    }
+}
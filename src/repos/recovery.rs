@@ -0,0 +1,253 @@
+//! Repo for trusted_contacts, recovery_requests and recovery_approvals tables, account recovery
+//! via trusted contacts. Requests and approvals are made by people not logged in,
+//! so the service works with them using system ACL only.
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use uuid::Uuid;
+
+use stq_types::UserId;
+
+use repos::legacy_acl::*;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::*;
+use models::{NewRecoveryApproval, NewRecoveryRequest, NewTrustedContact, RecoveryRequest, RecoveryState, TrustedContact};
+use schema::{recovery_approvals, recovery_requests, trusted_contacts};
+
+/// Recovery repository, responsible for handling trusted contacts and recovery requests
+pub trait RecoveryRepo {
+    /// Returns trusted contacts of the user
+    fn list_contacts(&self, user_id: UserId) -> RepoResult<Vec<TrustedContact>>;
+
+    /// Find trusted contact by id
+    fn find_contact(&self, contact_id: i32) -> RepoResult<Option<TrustedContact>>;
+
+    /// Adds trusted contact, adding the same email twice is a no-op
+    fn add_contact(&self, payload: NewTrustedContact) -> RepoResult<TrustedContact>;
+
+    /// Removes trusted contact of the user, returns `None` if there was no such contact
+    fn remove_contact(&self, user_id: UserId, contact_id: i32) -> RepoResult<Option<TrustedContact>>;
+
+    /// Creates recovery request, pending requests of the user are cancelled
+    fn create_request(&self, payload: NewRecoveryRequest) -> RepoResult<RecoveryRequest>;
+
+    /// Returns the latest recovery request of the user
+    fn last_request(&self, user_id: UserId) -> RepoResult<Option<RecoveryRequest>>;
+
+    /// Find recovery request locking it until the end of current transaction
+    fn find_request_for_update(&self, request_id: Uuid) -> RepoResult<Option<RecoveryRequest>>;
+
+    /// Updates state of the request
+    fn update_request_state(&self, request_id: Uuid, state: RecoveryState) -> RepoResult<RecoveryRequest>;
+
+    /// Saves approval of the contact, approving twice is a no-op
+    fn approve(&self, payload: NewRecoveryApproval) -> RepoResult<()>;
+
+    /// Returns number of contacts approved the request
+    fn count_approvals(&self, request_id: Uuid) -> RepoResult<i64>;
+}
+
+/// Implementation of Recovery trait
+pub struct RecoveryRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, TrustedContact>>,
+    pub db_conn: &'a T,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> RecoveryRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, TrustedContact>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> RecoveryRepo for RecoveryRepoImpl<'a, T> {
+    /// Returns trusted contacts of the user
+    fn list_contacts(&self, user_id_arg: UserId) -> RepoResult<Vec<TrustedContact>> {
+        let query = trusted_contacts::table
+            .filter(trusted_contacts::user_id.eq(user_id_arg))
+            .order(trusted_contacts::id);
+        query
+            .get_results::<TrustedContact>(self.db_conn)
+            .map_err(From::from)
+            .and_then(|contacts: Vec<TrustedContact>| {
+                for contact in &contacts {
+                    acl::check(&*self.acl, Resource::TrustedContacts, Action::Read, self, Some(contact))?;
+                }
+                Ok(contacts)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("List trusted contacts of user {} error occured", user_id_arg))
+                    .into()
+            })
+    }
+
+    /// Find trusted contact by id
+    fn find_contact(&self, contact_id_arg: i32) -> RepoResult<Option<TrustedContact>> {
+        trusted_contacts::table
+            .find(contact_id_arg)
+            .get_result::<TrustedContact>(self.db_conn)
+            .optional()
+            .map_err(From::from)
+            .and_then(|contact: Option<TrustedContact>| {
+                if let Some(ref contact) = contact {
+                    acl::check(&*self.acl, Resource::TrustedContacts, Action::Read, self, Some(contact))?;
+                }
+                Ok(contact)
+            })
+            .map_err(|e: FailureError| e.context(format!("Find trusted contact {} error occured", contact_id_arg)).into())
+    }
+
+    /// Adds trusted contact, adding the same email twice is a no-op
+    fn add_contact(&self, payload: NewTrustedContact) -> RepoResult<TrustedContact> {
+        let query = diesel::insert_into(trusted_contacts::table)
+            .values(&payload)
+            .on_conflict_do_nothing();
+        query
+            .execute(self.db_conn)
+            .and_then(|_| {
+                trusted_contacts::table
+                    .filter(trusted_contacts::user_id.eq(payload.user_id))
+                    .filter(trusted_contacts::email.eq(&payload.email))
+                    .get_result::<TrustedContact>(self.db_conn)
+            })
+            .map_err(From::from)
+            .and_then(|contact: TrustedContact| {
+                acl::check(&*self.acl, Resource::TrustedContacts, Action::Create, self, Some(&contact))?;
+                Ok(contact)
+            })
+            .map_err(|e: FailureError| e.context(format!("Add trusted contact {:?} error occured", payload)).into())
+    }
+
+    /// Removes trusted contact of the user, returns `None` if there was no such contact
+    fn remove_contact(&self, user_id_arg: UserId, contact_id_arg: i32) -> RepoResult<Option<TrustedContact>> {
+        let filtered = trusted_contacts::table
+            .filter(trusted_contacts::id.eq(contact_id_arg))
+            .filter(trusted_contacts::user_id.eq(user_id_arg));
+        let query = diesel::delete(filtered);
+        query
+            .get_result::<TrustedContact>(self.db_conn)
+            .optional()
+            .map_err(From::from)
+            .and_then(|contact: Option<TrustedContact>| {
+                if let Some(ref contact) = contact {
+                    acl::check(&*self.acl, Resource::TrustedContacts, Action::Delete, self, Some(contact))?;
+                }
+                Ok(contact)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!(
+                    "Remove trusted contact {} of user {} error occured",
+                    contact_id_arg, user_id_arg
+                ))
+                .into()
+            })
+    }
+
+    /// Creates recovery request, pending requests of the user are cancelled
+    fn create_request(&self, payload: NewRecoveryRequest) -> RepoResult<RecoveryRequest> {
+        acl::check(&*self.acl, Resource::TrustedContacts, Action::Update, self, None)?;
+
+        self.db_conn
+            .transaction::<RecoveryRequest, FailureError, _>(|| {
+                let pending = recovery_requests::table
+                    .filter(recovery_requests::user_id.eq(payload.user_id))
+                    .filter(recovery_requests::state.eq(RecoveryState::Pending));
+                diesel::update(pending)
+                    .set(recovery_requests::state.eq(RecoveryState::Cancelled))
+                    .execute(self.db_conn)?;
+                let request = diesel::insert_into(recovery_requests::table)
+                    .values(&payload)
+                    .get_result::<RecoveryRequest>(self.db_conn)?;
+                Ok(request)
+            })
+            .map_err(|e: FailureError| e.context(format!("Create recovery request {:?} error occured", payload)).into())
+    }
+
+    /// Returns the latest recovery request of the user
+    fn last_request(&self, user_id_arg: UserId) -> RepoResult<Option<RecoveryRequest>> {
+        acl::check(&*self.acl, Resource::TrustedContacts, Action::Read, self, None)?;
+
+        let query = recovery_requests::table
+            .filter(recovery_requests::user_id.eq(user_id_arg))
+            .order(recovery_requests::created_at.desc());
+        query.first::<RecoveryRequest>(self.db_conn).optional().map_err(|e| {
+            e.context(format!("Find last recovery request of user {} error occured", user_id_arg))
+                .into()
+        })
+    }
+
+    /// Find recovery request locking it until the end of current transaction
+    fn find_request_for_update(&self, request_id_arg: Uuid) -> RepoResult<Option<RecoveryRequest>> {
+        acl::check(&*self.acl, Resource::TrustedContacts, Action::Update, self, None)?;
+
+        let query = recovery_requests::table.find(request_id_arg).for_update();
+        query
+            .get_result::<RecoveryRequest>(self.db_conn)
+            .optional()
+            .map_err(|e| e.context(format!("Lock recovery request {} error occured", request_id_arg)).into())
+    }
+
+    /// Updates state of the request
+    fn update_request_state(&self, request_id_arg: Uuid, state_arg: RecoveryState) -> RepoResult<RecoveryRequest> {
+        acl::check(&*self.acl, Resource::TrustedContacts, Action::Update, self, None)?;
+
+        let query = diesel::update(recovery_requests::table.find(request_id_arg)).set(recovery_requests::state.eq(state_arg));
+        query.get_result::<RecoveryRequest>(self.db_conn).map_err(|e| {
+            e.context(format!(
+                "Set state {} of recovery request {} error occured",
+                state_arg, request_id_arg
+            ))
+            .into()
+        })
+    }
+
+    /// Saves approval of the contact, approving twice is a no-op
+    fn approve(&self, payload: NewRecoveryApproval) -> RepoResult<()> {
+        acl::check(&*self.acl, Resource::TrustedContacts, Action::Update, self, None)?;
+
+        let query = diesel::insert_into(recovery_approvals::table)
+            .values(&payload)
+            .on_conflict_do_nothing();
+        query
+            .execute(self.db_conn)
+            .map(|_| ())
+            .map_err(|e| e.context(format!("Save recovery approval {:?} error occured", payload)).into())
+    }
+
+    /// Returns number of contacts approved the request
+    fn count_approvals(&self, request_id_arg: Uuid) -> RepoResult<i64> {
+        acl::check(&*self.acl, Resource::TrustedContacts, Action::Read, self, None)?;
+
+        let query = recovery_approvals::table
+            .filter(recovery_approvals::request_id.eq(request_id_arg))
+            .count();
+        query.get_result::<i64>(self.db_conn).map_err(|e| {
+            e.context(format!("Count approvals of recovery request {} error occured", request_id_arg))
+                .into()
+        })
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, TrustedContact>
+    for RecoveryRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, user_id_arg: UserId, scope: &Scope, obj: Option<&TrustedContact>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => {
+                if let Some(contact) = obj {
+                    contact.user_id == user_id_arg
+                } else {
+                    false
+                }
+            }
+        }
+    }
+}
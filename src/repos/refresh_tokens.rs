@@ -0,0 +1,112 @@
+//! Refresh tokens repo, presents CRUD operations with db for rotating refresh tokens.
+//! Tokens are presented by browser clients without access token, so there is no ACL check.
+use std::time::SystemTime;
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use failure::Fail;
+use uuid::Uuid;
+
+use super::types::RepoResult;
+use stq_types::UserId;
+
+use models::{NewRefreshToken, RefreshToken};
+use schema::refresh_tokens::dsl::*;
+
+/// Refresh tokens repository, responsible for handling rotating refresh tokens
+pub struct RefreshTokensRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub db_conn: &'a T,
+}
+
+pub trait RefreshTokensRepo {
+    /// Create refresh token
+    fn create(&self, payload: NewRefreshToken) -> RepoResult<RefreshToken>;
+
+    /// Find by token hash
+    fn find_by_hash(&self, token_hash_arg: String) -> RepoResult<Option<RefreshToken>>;
+
+    /// Mark token as rotated, returns `false` if the token was already rotated
+    fn rotate(&self, token_id: Uuid, rotated_at_arg: SystemTime) -> RepoResult<bool>;
+
+    /// Revoke all tokens of the family
+    fn revoke_family(&self, family_id_arg: Uuid) -> RepoResult<()>;
+
+    /// Latest tokens of the user's families not revoked or expired by `now`, one per family,
+    /// oldest family first
+    fn list_active(&self, user_id: UserId, now: SystemTime) -> RepoResult<Vec<RefreshToken>>;
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> RefreshTokensRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T) -> Self {
+        Self { db_conn }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> RefreshTokensRepo
+    for RefreshTokensRepoImpl<'a, T>
+{
+    /// Create refresh token
+    fn create(&self, payload: NewRefreshToken) -> RepoResult<RefreshToken> {
+        let query = diesel::insert_into(refresh_tokens).values(&payload);
+
+        query.get_result(self.db_conn).map_err(|e| {
+            e.context(format!(
+                "Create refresh token of user {} for client {} error occured",
+                payload.user_id, payload.client_id
+            ))
+            .into()
+        })
+    }
+
+    /// Find by token hash
+    fn find_by_hash(&self, token_hash_arg: String) -> RepoResult<Option<RefreshToken>> {
+        let query = refresh_tokens.filter(token_hash.eq(token_hash_arg));
+
+        query
+            .get_result(self.db_conn)
+            .optional()
+            .map_err(|e| e.context("Find refresh token by hash error occured").into())
+    }
+
+    /// Mark token as rotated, returns `false` if the token was already rotated
+    fn rotate(&self, token_id: Uuid, rotated_at_arg: SystemTime) -> RepoResult<bool> {
+        let filtered = refresh_tokens.find(token_id).filter(rotated_at.is_null());
+        let query = diesel::update(filtered).set(rotated_at.eq(Some(rotated_at_arg)));
+
+        query
+            .execute(self.db_conn)
+            .map(|updated| updated > 0)
+            .map_err(|e| e.context(format!("Rotate refresh token {} error occured", token_id)).into())
+    }
+
+    /// Revoke all tokens of the family
+    fn revoke_family(&self, family_id_arg: Uuid) -> RepoResult<()> {
+        let filtered = refresh_tokens.filter(family_id.eq(family_id_arg));
+        let query = diesel::update(filtered).set(revoked.eq(true));
+
+        query.execute(self.db_conn).map(|_| ()).map_err(|e| {
+            e.context(format!("Revoke refresh token family {} error occured", family_id_arg))
+                .into()
+        })
+    }
+
+    /// Latest tokens of the user's families not revoked or expired by `now`, one per family,
+    /// oldest family first
+    fn list_active(&self, user_id_arg: UserId, now: SystemTime) -> RepoResult<Vec<RefreshToken>> {
+        let query = refresh_tokens
+            .filter(user_id.eq(user_id_arg))
+            .filter(rotated_at.is_null())
+            .filter(revoked.eq(false))
+            .filter(expires_at.gt(now))
+            .order(family_created_at.asc());
+
+        query.get_results(self.db_conn).map_err(|e| {
+            e.context(format!("List active refresh tokens of user {} error occured", user_id_arg))
+                .into()
+        })
+    }
+}
@@ -1,13 +1,15 @@
 use std::sync::Arc;
 
+use chrono::Utc;
 use diesel::connection::AnsiTransactionManager;
 use diesel::pg::Pg;
 use diesel::Connection;
 use failure::Error as FailureError;
+use serde::Serialize;
 
-use stq_cache::cache::Cache;
 use stq_types::{UserId, UsersRole};
 
+use cache::{Cache, Counter};
 use models::*;
 use repos::legacy_acl::{Acl, SystemACL, UnauthorizedACL};
 use repos::*;
@@ -18,39 +20,136 @@ pub trait ReposFactory<C: Connection<Backend = Pg, TransactionManager = AnsiTran
     fn create_users_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<UsersRepo + 'a>;
     fn create_users_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<UsersRepo + 'a>;
     fn create_identities_repo<'a>(&self, db_conn: &'a C) -> Box<IdentitiesRepo + 'a>;
+    fn create_identities_repo_with_acl<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<IdentitiesRepo + 'a>;
+    fn create_clients_repo<'a>(&self, db_conn: &'a C) -> Box<ClientsRepo + 'a>;
+    fn create_client_brandings_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<ClientBrandingsRepo + 'a>;
+    fn create_client_brandings_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<ClientBrandingsRepo + 'a>;
+    fn create_countries_repo<'a>(&self, db_conn: &'a C) -> Box<CountriesRepo + 'a>;
+    fn create_device_codes_repo<'a>(&self, db_conn: &'a C) -> Box<DeviceCodesRepo + 'a>;
+    fn create_used_tokens_repo<'a>(&self, db_conn: &'a C) -> Box<UsedTokensRepo + 'a>;
+    fn create_provider_blocks_repo<'a>(&self, db_conn: &'a C) -> Box<ProviderBlocksRepo + 'a>;
+    fn create_backfills_repo<'a>(&self, db_conn: &'a C) -> Box<BackfillsRepo + 'a>;
+    fn create_attempts_cache(&self) -> Arc<AttemptsCache>;
     fn create_reset_token_repo<'a>(&self, db_conn: &'a C) -> Box<ResetTokenRepo + 'a>;
     fn create_user_roles_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<UserRolesRepo + 'a>;
     fn create_user_roles_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<UserRolesRepo + 'a>;
+    fn create_role_requests_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<RoleRequestsRepo + 'a>;
+    fn create_user_tags_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<UserTagsRepo + 'a>;
+    fn create_jobs_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<JobsRepo + 'a>;
+    fn create_jobs_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<JobsRepo + 'a>;
+    fn create_legacy_sync_states_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<LegacySyncStatesRepo + 'a>;
+    fn create_legacy_sync_states_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<LegacySyncStatesRepo + 'a>;
+    fn create_crm_drifts_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<CrmDriftsRepo + 'a>;
+    fn create_crm_drifts_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<CrmDriftsRepo + 'a>;
+    fn create_segment_exports_repo<'a>(&self, db_conn: &'a C) -> Box<SegmentExportsRepo + 'a>;
+    fn create_login_stats_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<LoginStatsRepo + 'a>;
+    fn create_login_stats_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<LoginStatsRepo + 'a>;
+    fn create_funnel_events_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<FunnelEventsRepo + 'a>;
+    fn create_funnel_events_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<FunnelEventsRepo + 'a>;
+    fn create_deletion_requests_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<DeletionRequestsRepo + 'a>;
+    fn create_deletion_requests_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<DeletionRequestsRepo + 'a>;
+    fn create_recovery_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<RecoveryRepo + 'a>;
+    fn create_recovery_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<RecoveryRepo + 'a>;
+    fn create_security_answers_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<SecurityAnswersRepo + 'a>;
+    fn create_security_answers_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<SecurityAnswersRepo + 'a>;
+    fn create_audit_log_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<AuditLogRepo + 'a>;
+    fn create_audit_log_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<AuditLogRepo + 'a>;
+    fn create_login_history_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<LoginHistoryRepo + 'a>;
+    fn create_login_history_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<LoginHistoryRepo + 'a>;
+    fn create_devices_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<DevicesRepo + 'a>;
+    fn create_devices_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<DevicesRepo + 'a>;
+    fn create_role_access_policies_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<RoleAccessPoliciesRepo + 'a>;
+    fn create_role_access_policies_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<RoleAccessPoliciesRepo + 'a>;
+    fn create_invites_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<InvitesRepo + 'a>;
+    fn create_waitlist_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<WaitlistRepo + 'a>;
+    fn create_profile_prompts_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<ProfilePromptsRepo + 'a>;
+    fn create_child_accounts_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<ChildAccountsRepo + 'a>;
+    fn create_child_accounts_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<ChildAccountsRepo + 'a>;
+    fn create_access_tokens_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<AccessTokensRepo + 'a>;
+    fn create_oauth_consents_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<OAuthConsentsRepo + 'a>;
+    fn create_refresh_tokens_repo<'a>(&self, db_conn: &'a C) -> Box<RefreshTokensRepo + 'a>;
+    fn create_sessions_repo<'a>(&self, db_conn: &'a C) -> Box<SessionsRepo + 'a>;
+    fn create_schema_migrations_repo<'a>(&self, db_conn: &'a C) -> Box<SchemaMigrationsRepo + 'a>;
 }
 
-pub struct ReposFactoryImpl<C1>
+pub struct ReposFactoryImpl<C1, C2>
 where
     C1: Cache<Vec<UsersRole>>,
+    C2: Counter,
 {
     roles_cache: Arc<RolesCacheImpl<C1>>,
+    attempts_cache: Arc<AttemptsCacheImpl<C2>>,
+    limits: RepoLimits,
+    pii_cipher: Option<PiiCipher>,
+    break_glass_user: Option<UserId>,
+    policies: Option<Arc<Policies>>,
 }
 
-impl<C1> Clone for ReposFactoryImpl<C1>
+impl<C1, C2> Clone for ReposFactoryImpl<C1, C2>
 where
     C1: Cache<Vec<UsersRole>>,
+    C2: Counter,
 {
     fn clone(&self) -> Self {
         Self {
             roles_cache: self.roles_cache.clone(),
+            attempts_cache: self.attempts_cache.clone(),
+            limits: self.limits,
+            pii_cipher: self.pii_cipher.clone(),
+            break_glass_user: self.break_glass_user,
+            policies: self.policies.clone(),
         }
     }
 }
 
-impl<C1> ReposFactoryImpl<C1>
+impl<C1, C2> ReposFactoryImpl<C1, C2>
 where
     C1: Cache<Vec<UsersRole>> + Send + Sync + 'static,
+    C2: Counter + 'static,
 {
-    pub fn new(roles_cache: RolesCacheImpl<C1>) -> Self {
+    pub fn new(roles_cache: RolesCacheImpl<C1>, attempts_cache: AttemptsCacheImpl<C2>) -> Self {
         Self {
             roles_cache: Arc::new(roles_cache),
+            attempts_cache: Arc::new(attempts_cache),
+            limits: RepoLimits::default(),
+            pii_cipher: None,
+            break_glass_user: None,
+            policies: None,
         }
     }
 
+    pub fn with_limits(self, limits: RepoLimits) -> Self {
+        Self { limits, ..self }
+    }
+
+    /// Seals PII of users by per-user data keys
+    pub fn with_pii_cipher(self, pii_cipher: PiiCipher) -> Self {
+        Self {
+            pii_cipher: Some(pii_cipher),
+            ..self
+        }
+    }
+
+    /// Exempts the break-glass account from access policies of roles
+    pub fn with_break_glass_user(self, user_id: UserId) -> Self {
+        Self {
+            break_glass_user: Some(user_id),
+            ..self
+        }
+    }
+
+    /// Grants permissions by the policies instead of the built-in ones
+    pub fn with_policies(self, policies: Arc<Policies>) -> Self {
+        Self {
+            policies: Some(policies),
+            ..self
+        }
+    }
+
+    pub fn roles_cache(&self) -> Arc<RolesCacheImpl<C1>> {
+        self.roles_cache.clone()
+    }
+
     pub fn get_roles<'a, C: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static>(
         &self,
         id: UserId,
@@ -62,7 +161,28 @@ where
             .unwrap_or_default()
     }
 
-    fn get_acl<'a, T, C: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static>(
+    /// Roles of the user in effect at the moment by access policies of roles.
+    /// Ordinary users and the break-glass account are not restricted.
+    pub fn get_roles_in_effect<'a, C: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static>(
+        &self,
+        id: UserId,
+        db_conn: &'a C,
+    ) -> Vec<UsersRole> {
+        let roles = self.get_roles(id, db_conn);
+        if self.break_glass_user == Some(id) || roles.iter().all(|role| *role == UsersRole::User) {
+            return roles;
+        }
+        match RoleAccessPoliciesRepoImpl::new(db_conn, Box::new(SystemACL::default())).list() {
+            Ok(policies) => roles_in_effect(roles, &policies, Utc::now()),
+            Err(e) => {
+                // admin roles are not granted unless their policies are known
+                error!("Couldn't get role access policies for user {}: {}", id, e);
+                roles.into_iter().filter(|role| *role == UsersRole::User).collect()
+            }
+        }
+    }
+
+    fn get_acl<'a, T: Serialize, C: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static>(
         &self,
         db_conn: &'a C,
         user_id: Option<UserId>,
@@ -70,32 +190,94 @@ where
         user_id.map_or(
             Box::new(UnauthorizedACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, T>>,
             |id| {
-                let roles = self.get_roles(id, db_conn);
-                (Box::new(ApplicationAcl::new(roles, id)) as Box<Acl<Resource, Action, Scope, FailureError, T>>)
+                let roles = self.get_roles_in_effect(id, db_conn);
+                let acl = match self.policies {
+                    Some(ref policies) => ApplicationAcl::new(roles, id).with_policies(policies.clone()),
+                    None => ApplicationAcl::new(roles, id),
+                };
+                (Box::new(acl) as Box<Acl<Resource, Action, Scope, FailureError, T>>)
             },
         )
     }
 }
 
-impl<C, C1> ReposFactory<C> for ReposFactoryImpl<C1>
+impl<C, C1, C2> ReposFactory<C> for ReposFactoryImpl<C1, C2>
 where
     C: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
     C1: Cache<Vec<UsersRole>> + Send + Sync + 'static,
+    C2: Counter + 'static,
 {
     fn create_users_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<UsersRepo + 'a> {
         let acl = self.get_acl(db_conn, user_id);
-        Box::new(UsersRepoImpl::new(db_conn, acl)) as Box<UsersRepo>
+        Box::new(
+            UsersRepoImpl::new(db_conn, acl)
+                .with_limits(self.limits)
+                .with_pii_cipher(self.pii_cipher.clone()),
+        ) as Box<UsersRepo>
     }
 
     fn create_users_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<UsersRepo + 'a> {
-        Box::new(UsersRepoImpl::new(
-            db_conn,
-            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, User>>,
-        )) as Box<UsersRepo>
+        Box::new(
+            UsersRepoImpl::new(
+                db_conn,
+                Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, User>>,
+            )
+            .with_limits(self.limits)
+            .with_pii_cipher(self.pii_cipher.clone()),
+        ) as Box<UsersRepo>
     }
 
     fn create_identities_repo<'a>(&self, db_conn: &'a C) -> Box<IdentitiesRepo + 'a> {
-        Box::new(IdentitiesRepoImpl::new(db_conn)) as Box<IdentitiesRepo>
+        Box::new(IdentitiesRepoImpl::new(db_conn).with_pii_cipher(self.pii_cipher.clone())) as Box<IdentitiesRepo>
+    }
+
+    fn create_identities_repo_with_acl<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<IdentitiesRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(
+            IdentitiesRepoImpl::new(db_conn)
+                .with_acl(acl)
+                .with_pii_cipher(self.pii_cipher.clone()),
+        ) as Box<IdentitiesRepo>
+    }
+
+    fn create_clients_repo<'a>(&self, db_conn: &'a C) -> Box<ClientsRepo + 'a> {
+        Box::new(ClientsRepoImpl::new(db_conn)) as Box<ClientsRepo>
+    }
+
+    fn create_client_brandings_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<ClientBrandingsRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(ClientBrandingsRepoImpl::new(db_conn, acl)) as Box<ClientBrandingsRepo>
+    }
+
+    fn create_client_brandings_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<ClientBrandingsRepo + 'a> {
+        Box::new(ClientBrandingsRepoImpl::new(
+            db_conn,
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, ClientBranding>>,
+        )) as Box<ClientBrandingsRepo>
+    }
+
+    fn create_countries_repo<'a>(&self, db_conn: &'a C) -> Box<CountriesRepo + 'a> {
+        Box::new(CountriesRepoImpl::new(db_conn)) as Box<CountriesRepo>
+    }
+
+    fn create_device_codes_repo<'a>(&self, db_conn: &'a C) -> Box<DeviceCodesRepo + 'a> {
+        Box::new(DeviceCodesRepoImpl::new(db_conn)) as Box<DeviceCodesRepo>
+    }
+
+    fn create_used_tokens_repo<'a>(&self, db_conn: &'a C) -> Box<UsedTokensRepo + 'a> {
+        Box::new(UsedTokensRepoImpl::new(db_conn)) as Box<UsedTokensRepo>
+    }
+
+    fn create_provider_blocks_repo<'a>(&self, db_conn: &'a C) -> Box<ProviderBlocksRepo + 'a> {
+        Box::new(ProviderBlocksRepoImpl::new(db_conn)) as Box<ProviderBlocksRepo>
+    }
+
+    fn create_backfills_repo<'a>(&self, db_conn: &'a C) -> Box<BackfillsRepo + 'a> {
+        Box::new(BackfillsRepoImpl::new(db_conn)) as Box<BackfillsRepo>
+    }
+
+    fn create_attempts_cache(&self) -> Arc<AttemptsCache> {
+        self.attempts_cache.clone()
     }
 
     fn create_reset_token_repo<'a>(&self, db_conn: &'a C) -> Box<ResetTokenRepo + 'a> {
@@ -114,9 +296,217 @@ where
         let acl = self.get_acl(db_conn, user_id);
         Box::new(UserRolesRepoImpl::new(db_conn, acl, self.roles_cache.clone())) as Box<UserRolesRepo>
     }
+
+    fn create_role_requests_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<RoleRequestsRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(RoleRequestsRepoImpl::new(db_conn, acl)) as Box<RoleRequestsRepo>
+    }
+
+    fn create_user_tags_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<UserTagsRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(UserTagsRepoImpl::new(db_conn, acl)) as Box<UserTagsRepo>
+    }
+
+    fn create_jobs_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<JobsRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(JobsRepoImpl::new(db_conn, acl)) as Box<JobsRepo>
+    }
+
+    fn create_jobs_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<JobsRepo + 'a> {
+        Box::new(JobsRepoImpl::new(
+            db_conn,
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, Job>>,
+        )) as Box<JobsRepo>
+    }
+
+    fn create_legacy_sync_states_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<LegacySyncStatesRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(LegacySyncStatesRepoImpl::new(db_conn, acl)) as Box<LegacySyncStatesRepo>
+    }
+
+    fn create_legacy_sync_states_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<LegacySyncStatesRepo + 'a> {
+        Box::new(LegacySyncStatesRepoImpl::new(
+            db_conn,
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, LegacySyncState>>,
+        )) as Box<LegacySyncStatesRepo>
+    }
+
+    fn create_crm_drifts_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<CrmDriftsRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(CrmDriftsRepoImpl::new(db_conn, acl)) as Box<CrmDriftsRepo>
+    }
+
+    fn create_crm_drifts_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<CrmDriftsRepo + 'a> {
+        Box::new(CrmDriftsRepoImpl::new(
+            db_conn,
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, CrmDrift>>,
+        )) as Box<CrmDriftsRepo>
+    }
+
+    fn create_segment_exports_repo<'a>(&self, db_conn: &'a C) -> Box<SegmentExportsRepo + 'a> {
+        Box::new(SegmentExportsRepoImpl::new(db_conn)) as Box<SegmentExportsRepo>
+    }
+
+    fn create_login_stats_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<LoginStatsRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(LoginStatsRepoImpl::new(db_conn, acl)) as Box<LoginStatsRepo>
+    }
+
+    fn create_login_stats_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<LoginStatsRepo + 'a> {
+        Box::new(LoginStatsRepoImpl::new(
+            db_conn,
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, LoginStat>>,
+        )) as Box<LoginStatsRepo>
+    }
+
+    fn create_funnel_events_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<FunnelEventsRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(FunnelEventsRepoImpl::new(db_conn, acl)) as Box<FunnelEventsRepo>
+    }
+
+    fn create_funnel_events_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<FunnelEventsRepo + 'a> {
+        Box::new(FunnelEventsRepoImpl::new(
+            db_conn,
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, FunnelEvent>>,
+        )) as Box<FunnelEventsRepo>
+    }
+
+    fn create_deletion_requests_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<DeletionRequestsRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(DeletionRequestsRepoImpl::new(db_conn, acl)) as Box<DeletionRequestsRepo>
+    }
+
+    fn create_deletion_requests_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<DeletionRequestsRepo + 'a> {
+        Box::new(DeletionRequestsRepoImpl::new(
+            db_conn,
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, DeletionRequest>>,
+        )) as Box<DeletionRequestsRepo>
+    }
+
+    fn create_recovery_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<RecoveryRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(RecoveryRepoImpl::new(db_conn, acl)) as Box<RecoveryRepo>
+    }
+
+    fn create_recovery_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<RecoveryRepo + 'a> {
+        Box::new(RecoveryRepoImpl::new(
+            db_conn,
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, TrustedContact>>,
+        )) as Box<RecoveryRepo>
+    }
+
+    fn create_security_answers_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<SecurityAnswersRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(SecurityAnswersRepoImpl::new(db_conn, acl)) as Box<SecurityAnswersRepo>
+    }
+
+    fn create_security_answers_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<SecurityAnswersRepo + 'a> {
+        Box::new(SecurityAnswersRepoImpl::new(
+            db_conn,
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, SecurityAnswer>>,
+        )) as Box<SecurityAnswersRepo>
+    }
+
+    fn create_audit_log_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<AuditLogRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(AuditLogRepoImpl::new(db_conn, acl)) as Box<AuditLogRepo>
+    }
+
+    fn create_audit_log_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<AuditLogRepo + 'a> {
+        Box::new(AuditLogRepoImpl::new(
+            db_conn,
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, AuditEvent>>,
+        )) as Box<AuditLogRepo>
+    }
+
+    fn create_login_history_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<LoginHistoryRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(LoginHistoryRepoImpl::new(db_conn, acl)) as Box<LoginHistoryRepo>
+    }
+
+    fn create_login_history_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<LoginHistoryRepo + 'a> {
+        Box::new(LoginHistoryRepoImpl::new(
+            db_conn,
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, LoginEvent>>,
+        )) as Box<LoginHistoryRepo>
+    }
+
+    fn create_devices_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<DevicesRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(DevicesRepoImpl::new(db_conn, acl)) as Box<DevicesRepo>
+    }
+
+    fn create_devices_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<DevicesRepo + 'a> {
+        Box::new(DevicesRepoImpl::new(
+            db_conn,
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, Device>>,
+        )) as Box<DevicesRepo>
+    }
+
+    fn create_role_access_policies_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<RoleAccessPoliciesRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(RoleAccessPoliciesRepoImpl::new(db_conn, acl)) as Box<RoleAccessPoliciesRepo>
+    }
+
+    fn create_role_access_policies_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<RoleAccessPoliciesRepo + 'a> {
+        Box::new(RoleAccessPoliciesRepoImpl::new(
+            db_conn,
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, RoleAccessPolicy>>,
+        )) as Box<RoleAccessPoliciesRepo>
+    }
+
+    fn create_invites_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<InvitesRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(InvitesRepoImpl::new(db_conn, acl)) as Box<InvitesRepo>
+    }
+
+    fn create_waitlist_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<WaitlistRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(WaitlistRepoImpl::new(db_conn, acl)) as Box<WaitlistRepo>
+    }
+
+    fn create_profile_prompts_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<ProfilePromptsRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(ProfilePromptsRepoImpl::new(db_conn, acl)) as Box<ProfilePromptsRepo>
+    }
+
+    fn create_child_accounts_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<ChildAccountsRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(ChildAccountsRepoImpl::new(db_conn, acl)) as Box<ChildAccountsRepo>
+    }
+
+    fn create_child_accounts_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<ChildAccountsRepo + 'a> {
+        Box::new(ChildAccountsRepoImpl::new(
+            db_conn,
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, ChildAccount>>,
+        )) as Box<ChildAccountsRepo>
+    }
+
+    fn create_access_tokens_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<AccessTokensRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(AccessTokensRepoImpl::new(db_conn, acl)) as Box<AccessTokensRepo>
+    }
+
+    fn create_oauth_consents_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<OAuthConsentsRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(OAuthConsentsRepoImpl::new(db_conn, acl)) as Box<OAuthConsentsRepo>
+    }
+
+    fn create_refresh_tokens_repo<'a>(&self, db_conn: &'a C) -> Box<RefreshTokensRepo + 'a> {
+        Box::new(RefreshTokensRepoImpl::new(db_conn)) as Box<RefreshTokensRepo>
+    }
+
+    fn create_sessions_repo<'a>(&self, db_conn: &'a C) -> Box<SessionsRepo + 'a> {
+        Box::new(SessionsRepoImpl::new(db_conn)) as Box<SessionsRepo>
+    }
+
+    fn create_schema_migrations_repo<'a>(&self, db_conn: &'a C) -> Box<SchemaMigrationsRepo + 'a> {
+        Box::new(SchemaMigrationsRepoImpl::new(db_conn)) as Box<SchemaMigrationsRepo>
+    }
 }
 
-#[cfg(test)]
+/// Mocked repos, also exposed to benchmarks with `test-mocks` feature
+#[cfg(any(test, feature = "test-mocks"))]
 pub mod tests {
     extern crate base64;
     extern crate diesel;
@@ -138,6 +528,7 @@ pub mod tests {
     use std::time::{Duration, SystemTime};
 
     use base64::encode;
+    use chrono::NaiveDate;
     use diesel::connection::AnsiTransactionManager;
     use diesel::connection::SimpleConnection;
     use diesel::deserialize::QueryableByName;
@@ -151,7 +542,6 @@ pub mod tests {
     use diesel::QueryResult;
     use diesel::Queryable;
     use futures::Stream;
-    use futures_cpupool::CpuPool;
     use r2d2::ManageConnection;
     use sha3::{Digest, Sha3_256};
     use tokio_core::reactor::Handle;
@@ -159,20 +549,56 @@ pub mod tests {
 
     use stq_http::client::TimeLimitedHttpClient;
     use stq_static_resources::{Provider, TokenType};
-    use stq_types::{RoleId, UserId, UsersRole};
+    use stq_types::{Alpha3, EmarsysId, RoleId, UserId, UsersRole};
 
     use config::Config;
     use controller::context::{DynamicContext, StaticContext};
     use models::*;
+    use repos::access_tokens::AccessTokensRepo;
+    use repos::attempts_cache::AttemptsCache;
+    use repos::audit_log::AuditLogRepo;
+    use repos::backfills::BackfillsRepo;
+    use repos::child_accounts::ChildAccountsRepo;
+    use repos::client_brandings::ClientBrandingsRepo;
+    use repos::clients::ClientsRepo;
+    use repos::countries::CountriesRepo;
+    use repos::crm_drifts::CrmDriftsRepo;
+    use repos::deletion_requests::DeletionRequestsRepo;
+    use repos::device_codes::DeviceCodesRepo;
+    use repos::devices::DevicesRepo;
+    use repos::funnel_events::FunnelEventsRepo;
     use repos::identities::IdentitiesRepo;
+    use repos::invites::InvitesRepo;
+    use repos::jobs::JobsRepo;
+    use repos::legacy_sync_states::LegacySyncStatesRepo;
+    use repos::login_history::LoginHistoryRepo;
+    use repos::login_stats::LoginStatsRepo;
+    use repos::oauth_consents::OAuthConsentsRepo;
+    use repos::profile_prompts::ProfilePromptsRepo;
+    use repos::provider_blocks::ProviderBlocksRepo;
+    use repos::recovery::RecoveryRepo;
+    use repos::refresh_tokens::RefreshTokensRepo;
     use repos::repo_factory::ReposFactory;
     use repos::reset_token::ResetTokenRepo;
+    use repos::role_access_policies::RoleAccessPoliciesRepo;
+    use repos::schema_migrations::SchemaMigrationsRepo;
+    use repos::security_answers::SecurityAnswersRepo;
+    use repos::segment_exports::SegmentExportsRepo;
+    use repos::sessions::SessionsRepo;
+    use repos::sharding::ShardedPool;
     use repos::types::RepoResult;
+    use repos::used_tokens::UsedTokensRepo;
     use repos::user_roles::UserRolesRepo;
+    use repos::user_tags::UserTagsRepo;
     use repos::users::UsersRepo;
+    use repos::waitlist::WaitlistRepo;
     use services::jwt::profile::{FacebookProfile, GoogleProfile};
     use services::jwt::JWTProviderService;
     use services::mocks::jwt::JWTProviderServiceMock;
+    use services::name_screening::NameScreeningServiceImpl;
+    use services::schema_check::MIN_SCHEMA_VERSION;
+    use services::util::token_hash;
+    use services::work_pool::{WorkPool, WorkPriority};
     use services::Service;
 
     #[derive(Default, Copy, Clone)]
@@ -191,6 +617,46 @@ pub mod tests {
             Box::new(IdentitiesRepoMock::default()) as Box<IdentitiesRepo>
         }
 
+        fn create_identities_repo_with_acl<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<IdentitiesRepo + 'a> {
+            Box::new(IdentitiesRepoMock::default()) as Box<IdentitiesRepo>
+        }
+
+        fn create_clients_repo<'a>(&self, _db_conn: &'a C) -> Box<ClientsRepo + 'a> {
+            Box::new(ClientsRepoMock::default()) as Box<ClientsRepo>
+        }
+
+        fn create_client_brandings_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<ClientBrandingsRepo + 'a> {
+            Box::new(ClientBrandingsRepoMock::default()) as Box<ClientBrandingsRepo>
+        }
+
+        fn create_client_brandings_repo_with_sys_acl<'a>(&self, _db_conn: &'a C) -> Box<ClientBrandingsRepo + 'a> {
+            Box::new(ClientBrandingsRepoMock::default()) as Box<ClientBrandingsRepo>
+        }
+
+        fn create_countries_repo<'a>(&self, _db_conn: &'a C) -> Box<CountriesRepo + 'a> {
+            Box::new(CountriesRepoMock::default()) as Box<CountriesRepo>
+        }
+
+        fn create_device_codes_repo<'a>(&self, _db_conn: &'a C) -> Box<DeviceCodesRepo + 'a> {
+            Box::new(DeviceCodesRepoMock::default()) as Box<DeviceCodesRepo>
+        }
+
+        fn create_used_tokens_repo<'a>(&self, _db_conn: &'a C) -> Box<UsedTokensRepo + 'a> {
+            Box::new(UsedTokensRepoMock::default()) as Box<UsedTokensRepo>
+        }
+
+        fn create_provider_blocks_repo<'a>(&self, _db_conn: &'a C) -> Box<ProviderBlocksRepo + 'a> {
+            Box::new(ProviderBlocksRepoMock::default()) as Box<ProviderBlocksRepo>
+        }
+
+        fn create_backfills_repo<'a>(&self, _db_conn: &'a C) -> Box<BackfillsRepo + 'a> {
+            Box::new(BackfillsRepoMock::default()) as Box<BackfillsRepo>
+        }
+
+        fn create_attempts_cache(&self) -> Arc<AttemptsCache> {
+            Arc::new(AttemptsCacheMock::default())
+        }
+
         fn create_reset_token_repo<'a>(&self, _db_conn: &'a C) -> Box<ResetTokenRepo + 'a> {
             Box::new(ResetTokenRepoMock::default()) as Box<ResetTokenRepo>
         }
@@ -202,6 +668,154 @@ pub mod tests {
         fn create_user_roles_repo_with_sys_acl<'a>(&self, _db_conn: &'a C) -> Box<UserRolesRepo + 'a> {
             Box::new(UserRolesRepoMock::default()) as Box<UserRolesRepo>
         }
+
+        fn create_role_requests_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<RoleRequestsRepo + 'a> {
+            Box::new(RoleRequestsRepoMock::default()) as Box<RoleRequestsRepo>
+        }
+
+        fn create_user_tags_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<UserTagsRepo + 'a> {
+            Box::new(UserTagsRepoMock::default()) as Box<UserTagsRepo>
+        }
+
+        fn create_jobs_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<JobsRepo + 'a> {
+            Box::new(JobsRepoMock::default()) as Box<JobsRepo>
+        }
+
+        fn create_jobs_repo_with_sys_acl<'a>(&self, _db_conn: &'a C) -> Box<JobsRepo + 'a> {
+            Box::new(JobsRepoMock::default()) as Box<JobsRepo>
+        }
+
+        fn create_legacy_sync_states_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<LegacySyncStatesRepo + 'a> {
+            Box::new(LegacySyncStatesRepoMock::default()) as Box<LegacySyncStatesRepo>
+        }
+
+        fn create_legacy_sync_states_repo_with_sys_acl<'a>(&self, _db_conn: &'a C) -> Box<LegacySyncStatesRepo + 'a> {
+            Box::new(LegacySyncStatesRepoMock::default()) as Box<LegacySyncStatesRepo>
+        }
+
+        fn create_crm_drifts_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<CrmDriftsRepo + 'a> {
+            Box::new(CrmDriftsRepoMock::default()) as Box<CrmDriftsRepo>
+        }
+
+        fn create_crm_drifts_repo_with_sys_acl<'a>(&self, _db_conn: &'a C) -> Box<CrmDriftsRepo + 'a> {
+            Box::new(CrmDriftsRepoMock::default()) as Box<CrmDriftsRepo>
+        }
+
+        fn create_segment_exports_repo<'a>(&self, _db_conn: &'a C) -> Box<SegmentExportsRepo + 'a> {
+            Box::new(SegmentExportsRepoMock::default()) as Box<SegmentExportsRepo>
+        }
+
+        fn create_login_stats_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<LoginStatsRepo + 'a> {
+            Box::new(LoginStatsRepoMock::default()) as Box<LoginStatsRepo>
+        }
+
+        fn create_login_stats_repo_with_sys_acl<'a>(&self, _db_conn: &'a C) -> Box<LoginStatsRepo + 'a> {
+            Box::new(LoginStatsRepoMock::default()) as Box<LoginStatsRepo>
+        }
+
+        fn create_funnel_events_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<FunnelEventsRepo + 'a> {
+            Box::new(FunnelEventsRepoMock::default()) as Box<FunnelEventsRepo>
+        }
+
+        fn create_funnel_events_repo_with_sys_acl<'a>(&self, _db_conn: &'a C) -> Box<FunnelEventsRepo + 'a> {
+            Box::new(FunnelEventsRepoMock::default()) as Box<FunnelEventsRepo>
+        }
+
+        fn create_deletion_requests_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<DeletionRequestsRepo + 'a> {
+            Box::new(DeletionRequestsRepoMock::default()) as Box<DeletionRequestsRepo>
+        }
+
+        fn create_deletion_requests_repo_with_sys_acl<'a>(&self, _db_conn: &'a C) -> Box<DeletionRequestsRepo + 'a> {
+            Box::new(DeletionRequestsRepoMock::default()) as Box<DeletionRequestsRepo>
+        }
+
+        fn create_recovery_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<RecoveryRepo + 'a> {
+            Box::new(RecoveryRepoMock::default()) as Box<RecoveryRepo>
+        }
+
+        fn create_recovery_repo_with_sys_acl<'a>(&self, _db_conn: &'a C) -> Box<RecoveryRepo + 'a> {
+            Box::new(RecoveryRepoMock::default()) as Box<RecoveryRepo>
+        }
+
+        fn create_security_answers_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<SecurityAnswersRepo + 'a> {
+            Box::new(SecurityAnswersRepoMock::default()) as Box<SecurityAnswersRepo>
+        }
+
+        fn create_security_answers_repo_with_sys_acl<'a>(&self, _db_conn: &'a C) -> Box<SecurityAnswersRepo + 'a> {
+            Box::new(SecurityAnswersRepoMock::default()) as Box<SecurityAnswersRepo>
+        }
+
+        fn create_audit_log_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<AuditLogRepo + 'a> {
+            Box::new(AuditLogRepoMock::default()) as Box<AuditLogRepo>
+        }
+
+        fn create_audit_log_repo_with_sys_acl<'a>(&self, _db_conn: &'a C) -> Box<AuditLogRepo + 'a> {
+            Box::new(AuditLogRepoMock::default()) as Box<AuditLogRepo>
+        }
+
+        fn create_login_history_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<LoginHistoryRepo + 'a> {
+            Box::new(LoginHistoryRepoMock::default()) as Box<LoginHistoryRepo>
+        }
+
+        fn create_login_history_repo_with_sys_acl<'a>(&self, _db_conn: &'a C) -> Box<LoginHistoryRepo + 'a> {
+            Box::new(LoginHistoryRepoMock::default()) as Box<LoginHistoryRepo>
+        }
+
+        fn create_devices_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<DevicesRepo + 'a> {
+            Box::new(DevicesRepoMock::default()) as Box<DevicesRepo>
+        }
+
+        fn create_devices_repo_with_sys_acl<'a>(&self, _db_conn: &'a C) -> Box<DevicesRepo + 'a> {
+            Box::new(DevicesRepoMock::default()) as Box<DevicesRepo>
+        }
+
+        fn create_role_access_policies_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<RoleAccessPoliciesRepo + 'a> {
+            Box::new(RoleAccessPoliciesRepoMock::default()) as Box<RoleAccessPoliciesRepo>
+        }
+
+        fn create_role_access_policies_repo_with_sys_acl<'a>(&self, _db_conn: &'a C) -> Box<RoleAccessPoliciesRepo + 'a> {
+            Box::new(RoleAccessPoliciesRepoMock::default()) as Box<RoleAccessPoliciesRepo>
+        }
+
+        fn create_invites_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<InvitesRepo + 'a> {
+            Box::new(InvitesRepoMock::default()) as Box<InvitesRepo>
+        }
+
+        fn create_waitlist_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<WaitlistRepo + 'a> {
+            Box::new(WaitlistRepoMock::default()) as Box<WaitlistRepo>
+        }
+
+        fn create_profile_prompts_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<ProfilePromptsRepo + 'a> {
+            Box::new(ProfilePromptsRepoMock::default()) as Box<ProfilePromptsRepo>
+        }
+
+        fn create_child_accounts_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<ChildAccountsRepo + 'a> {
+            Box::new(ChildAccountsRepoMock::default()) as Box<ChildAccountsRepo>
+        }
+
+        fn create_child_accounts_repo_with_sys_acl<'a>(&self, _db_conn: &'a C) -> Box<ChildAccountsRepo + 'a> {
+            Box::new(ChildAccountsRepoMock::default()) as Box<ChildAccountsRepo>
+        }
+
+        fn create_access_tokens_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<AccessTokensRepo + 'a> {
+            Box::new(AccessTokensRepoMock::default()) as Box<AccessTokensRepo>
+        }
+
+        fn create_oauth_consents_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<OAuthConsentsRepo + 'a> {
+            Box::new(OAuthConsentsRepoMock::default()) as Box<OAuthConsentsRepo>
+        }
+
+        fn create_refresh_tokens_repo<'a>(&self, _db_conn: &'a C) -> Box<RefreshTokensRepo + 'a> {
+            Box::new(RefreshTokensRepoMock::default()) as Box<RefreshTokensRepo>
+        }
+
+        fn create_sessions_repo<'a>(&self, _db_conn: &'a C) -> Box<SessionsRepo + 'a> {
+            Box::new(SessionsRepoMock::default()) as Box<SessionsRepo>
+        }
+
+        fn create_schema_migrations_repo<'a>(&self, _db_conn: &'a C) -> Box<SchemaMigrationsRepo + 'a> {
+            Box::new(SchemaMigrationsRepoMock::default()) as Box<SchemaMigrationsRepo>
+        }
     }
 
     #[derive(Clone, Default)]
@@ -226,6 +840,16 @@ pub mod tests {
             Ok(Some(user))
         }
 
+        fn find_by_phone(&self, phone_arg: String) -> RepoResult<Option<User>> {
+            if phone_arg == MOCK_PHONE {
+                let mut user = create_user(UserId(1), MOCK_EMAIL.to_string());
+                user.phone = Some(phone_arg);
+                Ok(Some(user))
+            } else {
+                Ok(None)
+            }
+        }
+
         fn list(&self, from: UserId, count: i64) -> RepoResult<Vec<User>> {
             let mut users = vec![];
             for i in from.0..(from.0 + count as i32) {
@@ -236,10 +860,14 @@ pub mod tests {
         }
 
         fn create(&self, payload: NewUser) -> RepoResult<User> {
-            let user = create_user(UserId(1), payload.email);
+            let user = create_user(payload.id.unwrap_or(UserId(1)), payload.email);
             Ok(user)
         }
 
+        fn next_id(&self) -> RepoResult<UserId> {
+            Ok(UserId(2))
+        }
+
         fn update(&self, user_id: UserId, _payload: UpdateUser) -> RepoResult<User> {
             let user = create_user(user_id, MOCK_EMAIL.to_string());
             Ok(user)
@@ -260,11 +888,19 @@ pub mod tests {
             Ok(())
         }
 
-        fn search(&self, from: Option<UserId>, skip: i64, count: i64, _term: UsersSearchTerms) -> RepoResult<UserSearchResults> {
+        fn seal_pii(&self, _user_id_arg: UserId) -> RepoResult<bool> {
+            Ok(true)
+        }
+
+        fn search(&self, from: Option<UserId>, skip: i64, count: i64, term: UsersSearchTerms) -> RepoResult<UserSearchResults> {
             let mut users = vec![];
             let from_id = from.unwrap_or(UserId(1));
             let range = (from_id.0..).skip(skip as usize).take(count as usize);
             for i in range {
+                // segment of the tag is finite, so that it can be read page by page
+                if i > MOCK_USERS_COUNT && term.tags == Some(vec![MOCK_SEGMENT_TAG.to_string()]) {
+                    break;
+                }
                 let user = create_user(UserId(i), MOCK_EMAIL.to_string());
                 users.push(user);
             }
@@ -277,6 +913,11 @@ pub mod tests {
             let user = create_user(user_id_arg, MOCK_EMAIL.to_string());
             Ok(user)
         }
+        fn set_pending_deletion(&self, user_id_arg: UserId, pending_deletion_arg: bool) -> RepoResult<User> {
+            let mut user = create_user(user_id_arg, MOCK_EMAIL.to_string());
+            user.pending_deletion = pending_deletion_arg;
+            Ok(user)
+        }
         fn fuzzy_search_by_email(&self, _term_email: String) -> RepoResult<Vec<User>> {
             let user = create_user(UserId(1), MOCK_EMAIL.to_string());
             Ok(vec![user])
@@ -351,6 +992,285 @@ pub mod tests {
             );
             Ok(ident)
         }
+
+        fn lock_email(&self, _email_arg: String) -> RepoResult<()> {
+            Ok(())
+        }
+
+        fn list_all(&self) -> RepoResult<Vec<Identity>> {
+            let ident = create_identity(
+                MOCK_EMAIL.to_string(),
+                Some(password_create(MOCK_PASSWORD.to_string())),
+                UserId(1),
+                Provider::Email,
+                MOCK_SAGA_ID.to_string(),
+            );
+            Ok(vec![ident])
+        }
+
+        fn restore(&self, ident: Identity) -> RepoResult<bool> {
+            Ok(ident.email != MOCK_EMAIL)
+        }
+
+        fn list_for_user(&self, user_id: UserId) -> RepoResult<Vec<Identity>> {
+            Ok(vec![create_identity(
+                MOCK_EMAIL.to_string(),
+                None,
+                user_id,
+                Provider::Google,
+                MOCK_SAGA_ID.to_string(),
+            )])
+        }
+
+        fn unlink_provider(&self, user_id: UserId, provider_arg: Provider) -> RepoResult<Option<Identity>> {
+            Ok(if provider_arg == Provider::Google {
+                Some(create_identity(
+                    MOCK_EMAIL.to_string(),
+                    None,
+                    user_id,
+                    Provider::Email,
+                    MOCK_SAGA_ID.to_string(),
+                ))
+            } else {
+                None
+            })
+        }
+
+        fn set_password(&self, user_id: UserId, password_hash: String) -> RepoResult<Option<Identity>> {
+            let ident = create_identity(
+                MOCK_EMAIL.to_string(),
+                Some(password_hash),
+                user_id,
+                Provider::Email,
+                MOCK_SAGA_ID.to_string(),
+            );
+            Ok(Some(Identity {
+                must_change_password: true,
+                ..ident
+            }))
+        }
+
+        fn seal_email(&self, _user_id: UserId) -> RepoResult<bool> {
+            Ok(true)
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct ClientsRepoMock;
+
+    impl ClientsRepo for ClientsRepoMock {
+        fn find(&self, client_id: String) -> RepoResult<Option<Client>> {
+            Ok(if client_id == MOCK_CLIENT_ID {
+                Some(create_client(client_id))
+            } else if client_id == MOCK_CONFIDENTIAL_CLIENT_ID {
+                Some(Client {
+                    secret_hash: Some(password_create(MOCK_CLIENT_SECRET.to_string())),
+                    service_user_id: Some(UserId(1)),
+                    ..create_client(client_id)
+                })
+            } else if client_id == MOCK_THIRD_PARTY_CLIENT_ID {
+                Some(Client {
+                    is_third_party: true,
+                    ..create_client(client_id)
+                })
+            } else if client_id == MOCK_COOKIE_CLIENT_ID {
+                Some(Client {
+                    refresh_token_cookie: true,
+                    ..create_client(client_id)
+                })
+            } else if client_id == MOCK_SESSION_CLIENT_ID {
+                Some(Client {
+                    session_mode: true,
+                    ..create_client(client_id)
+                })
+            } else {
+                None
+            })
+        }
+
+        fn list(&self) -> RepoResult<Vec<Client>> {
+            Ok(vec![
+                create_client(MOCK_CLIENT_ID.to_string()),
+                Client {
+                    is_third_party: true,
+                    ..create_client(MOCK_THIRD_PARTY_CLIENT_ID.to_string())
+                },
+            ])
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct ClientBrandingsRepoMock;
+
+    impl ClientBrandingsRepo for ClientBrandingsRepoMock {
+        fn find(&self, client_id_arg: String) -> RepoResult<Option<ClientBranding>> {
+            Ok(if client_id_arg == MOCK_CLIENT_ID {
+                Some(create_client_branding(client_id_arg))
+            } else {
+                None
+            })
+        }
+
+        fn upsert(&self, payload: NewClientBranding) -> RepoResult<ClientBranding> {
+            Ok(ClientBranding {
+                app_name: payload.app_name,
+                support_email: payload.support_email,
+                logo_url: payload.logo_url,
+                ..create_client_branding(payload.client_id)
+            })
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct CountriesRepoMock;
+
+    impl CountriesRepo for CountriesRepoMock {
+        fn find(&self, alpha3_arg: Alpha3) -> RepoResult<Option<Country>> {
+            Ok(if alpha3_arg.0 == MOCK_COUNTRY {
+                Some(create_country(alpha3_arg))
+            } else {
+                None
+            })
+        }
+
+        fn list(&self) -> RepoResult<Vec<Country>> {
+            Ok(vec![create_country(Alpha3(MOCK_COUNTRY.to_string()))])
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct AttemptsCacheMock;
+
+    impl AttemptsCache for AttemptsCacheMock {
+        fn get(&self, key: &str) -> u32 {
+            if key == format!("ip:{}", MOCK_LOCKED_IP) {
+                u32::max_value()
+            } else {
+                0
+            }
+        }
+
+        fn increment(&self, key: &str) -> u32 {
+            self.get(key).saturating_add(1)
+        }
+
+        fn reset(&self, _key: &str) {}
+    }
+
+    #[derive(Clone, Default)]
+    pub struct DeviceCodesRepoMock;
+
+    impl DeviceCodesRepo for DeviceCodesRepoMock {
+        fn create(&self, payload: NewDeviceCode) -> RepoResult<DeviceCode> {
+            Ok(DeviceCode {
+                device_code: payload.device_code,
+                user_code: payload.user_code,
+                client_id: payload.client_id,
+                user_id: None,
+                expires_at: payload.expires_at,
+                created_at: SystemTime::now(),
+                updated_at: SystemTime::now(),
+                scope: payload.scope,
+                last_polled_at: None,
+            })
+        }
+
+        fn find(&self, device_code: String) -> RepoResult<Option<DeviceCode>> {
+            Ok(if device_code == MOCK_APPROVED_DEVICE_CODE {
+                Some(create_device_code(device_code, Some(UserId(1))))
+            } else if device_code == MOCK_PENDING_DEVICE_CODE || device_code == MOCK_SLOW_DEVICE_CODE {
+                Some(create_device_code(device_code, None))
+            } else if device_code == MOCK_THIRD_PARTY_DEVICE_CODE {
+                Some(create_third_party_device_code(Some(UserId(1))))
+            } else {
+                None
+            })
+        }
+
+        fn find_by_user_code(&self, user_code: String) -> RepoResult<Option<DeviceCode>> {
+            Ok(if user_code == MOCK_USER_CODE {
+                Some(create_device_code(MOCK_PENDING_DEVICE_CODE.to_string(), None))
+            } else if user_code == MOCK_THIRD_PARTY_USER_CODE {
+                Some(create_third_party_device_code(None))
+            } else {
+                None
+            })
+        }
+
+        fn approve(&self, device_code: String, user_id: UserId) -> RepoResult<Option<DeviceCode>> {
+            Ok(Some(create_device_code(device_code, Some(user_id))))
+        }
+
+        fn poll(&self, device_code: String, _interval: Duration) -> RepoResult<bool> {
+            Ok(device_code != MOCK_SLOW_DEVICE_CODE)
+        }
+
+        fn redeem(&self, device_code: String) -> RepoResult<Option<DeviceCode>> {
+            Ok(self.find(device_code)?.filter(|device_code| device_code.user_id.is_some()))
+        }
+
+        fn delete(&self, device_code: String) -> RepoResult<DeviceCode> {
+            Ok(create_device_code(device_code, None))
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct BackfillsRepoMock;
+
+    impl BackfillsRepo for BackfillsRepoMock {
+        fn list_idn_emails(&self, _from: UserId, _count: i64) -> RepoResult<Vec<(UserId, String)>> {
+            Ok(vec![])
+        }
+
+        fn set_email(&self, _user_id: UserId, _email: String) -> RepoResult<()> {
+            Ok(())
+        }
+
+        fn list_national_phones(&self, from: UserId, _count: i64) -> RepoResult<Vec<(UserId, String)>> {
+            Ok(if from.0 < 1 {
+                vec![(UserId(1), "8 999 123 45 67".to_string())]
+            } else {
+                vec![]
+            })
+        }
+
+        fn set_phone(&self, _user_id: UserId, _phone: String) -> RepoResult<()> {
+            Ok(())
+        }
+
+        fn list_unsealed(&self, from: UserId, _count: i64) -> RepoResult<Vec<UserId>> {
+            Ok(if from.0 < 1 { vec![UserId(1)] } else { vec![] })
+        }
+
+        fn list_unsealed_identities(&self, from: UserId, _count: i64) -> RepoResult<Vec<UserId>> {
+            Ok(if from.0 < 1 { vec![UserId(1)] } else { vec![] })
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct UsedTokensRepoMock;
+
+    impl UsedTokensRepo for UsedTokensRepoMock {
+        fn mark_used(&self, payload: NewUsedToken) -> RepoResult<bool> {
+            Ok(!payload.id.ends_with(MOCK_USED_TOKEN_ID))
+        }
+
+        fn delete_expired(&self, _now: SystemTime) -> RepoResult<usize> {
+            Ok(0)
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct ProviderBlocksRepoMock;
+
+    impl ProviderBlocksRepo for ProviderBlocksRepoMock {
+        fn block(&self, _payload: NewProviderBlock) -> RepoResult<()> {
+            Ok(())
+        }
+
+        fn is_blocked(&self, _user_id_arg: UserId, provider_arg: Provider) -> RepoResult<bool> {
+            Ok(provider_arg == MOCK_BLOCKED_PROVIDER)
+        }
     }
 
     #[derive(Clone, Default)]
@@ -358,7 +1278,13 @@ pub mod tests {
 
     impl ResetTokenRepo for ResetTokenRepoMock {
         /// Create token for user
-        fn upsert(&self, _email_arg: String, _token_type_arg: TokenType, _uuid_: Option<Uuid>) -> RepoResult<ResetToken> {
+        fn upsert(
+            &self,
+            _email_arg: String,
+            _token_type_arg: TokenType,
+            _uuid_: Option<Uuid>,
+            _token_arg: String,
+        ) -> RepoResult<ResetToken> {
             let token = create_reset_token(MOCK_TOKEN.to_string(), MOCK_EMAIL.to_string());
 
             Ok(token)
@@ -404,6 +1330,25 @@ pub mod tests {
             })
         }
 
+        fn list_users_with_role(&self, role: UsersRole, _count: i64) -> RepoResult<Vec<UserId>> {
+            Ok(match role {
+                UsersRole::Superuser => vec![UserId(1)],
+                _ => vec![],
+            })
+        }
+
+        fn list_all(&self) -> RepoResult<Vec<UserRole>> {
+            Ok(vec![UserRole {
+                id: RoleId::new(),
+                user_id: UserId(1),
+                name: UsersRole::Superuser,
+                data: None,
+                created_at: SystemTime::now(),
+                updated_at: SystemTime::now(),
+                expires_at: None,
+            }])
+        }
+
         fn create(&self, payload: NewUserRole) -> RepoResult<UserRole> {
             Ok(UserRole {
                 id: RoleId::new(),
@@ -412,6 +1357,7 @@ pub mod tests {
                 data: None,
                 created_at: SystemTime::now(),
                 updated_at: SystemTime::now(),
+                expires_at: payload.expires_at,
             })
         }
 
@@ -423,6 +1369,19 @@ pub mod tests {
                 data: None,
                 created_at: SystemTime::now(),
                 updated_at: SystemTime::now(),
+                expires_at: None,
+            }])
+        }
+
+        fn delete_expired(&self, now: SystemTime) -> RepoResult<Vec<UserRole>> {
+            Ok(vec![UserRole {
+                id: RoleId::new(),
+                user_id: UserId(1),
+                name: UsersRole::Moderator,
+                data: None,
+                created_at: now,
+                updated_at: now,
+                expires_at: Some(now),
             }])
         }
 
@@ -434,6 +1393,7 @@ pub mod tests {
                 data: None,
                 created_at: SystemTime::now(),
                 updated_at: SystemTime::now(),
+                expires_at: None,
             })
         }
 
@@ -445,122 +1405,1157 @@ pub mod tests {
                 data: None,
                 created_at: SystemTime::now(),
                 updated_at: SystemTime::now(),
+                expires_at: None,
             })
         }
+
+        fn invalidate_cache(&self, _user_id_arg: UserId) {}
     }
 
-    pub fn create_service(
-        user_id: Option<UserId>,
-        handle: Arc<Handle>,
-    ) -> Service<MockConnection, MockConnectionManager, ReposFactoryMock> {
-        let manager = MockConnectionManager::default();
-        let db_pool = r2d2::Pool::builder().build(manager).expect("Failed to create connection pool");
-        let cpu_pool = CpuPool::new(1);
+    #[derive(Clone, Default)]
+    pub struct RoleRequestsRepoMock;
 
-        let config = Config::new().unwrap();
-        let client = stq_http::client::Client::new(&config.to_http_config(), &handle);
-        let client_handle = client.handle();
-        let client_stream = client.stream();
-        handle.spawn(client_stream.for_each(|_| Ok(())));
-        let mut f = File::open(config.jwt.secret_key_path.clone()).unwrap();
-        let mut jwt_private_key: Vec<u8> = Vec::new();
-        f.read_to_end(&mut jwt_private_key).unwrap();
-        let google_provider_service: Arc<JWTProviderService<GoogleProfile>> = Arc::new(JWTProviderServiceMock);
-        let facebook_provider_service: Arc<JWTProviderService<FacebookProfile>> = Arc::new(JWTProviderServiceMock);
-        let static_context = StaticContext::new(
-            db_pool,
-            cpu_pool,
-            client_handle.clone(),
-            Arc::new(config),
-            MOCK_REPO_FACTORY,
-            jwt_private_key,
-        );
-        let time_limited_http_client = TimeLimitedHttpClient::new(client_handle, Duration::new(1, 0));
-        let dynamic_context = DynamicContext::new(
-            user_id,
-            String::default(),
-            time_limited_http_client,
-            google_provider_service,
-            facebook_provider_service,
-        );
+    impl RoleRequestsRepo for RoleRequestsRepoMock {
+        fn create(&self, payload: NewRoleRequest) -> RepoResult<RoleRequest> {
+            Ok(RoleRequest {
+                id: payload.id,
+                user_id: payload.user_id,
+                name: payload.name,
+                data: payload.data,
+                expires_at: payload.expires_at,
+                requested_by: payload.requested_by,
+                approved_by: None,
+                approved_at: None,
+                created_at: SystemTime::now(),
+            })
+        }
+
+        fn find_for_update(&self, id: Uuid) -> RepoResult<Option<RoleRequest>> {
+            Ok(Some(create_role_request(id)))
+        }
 
-        Service::new(static_context, dynamic_context)
+        fn list_pending(&self) -> RepoResult<Vec<RoleRequest>> {
+            Ok(vec![create_role_request(Uuid::new_v4())])
+        }
+
+        fn approve(&self, id: Uuid, approved_by: UserId) -> RepoResult<RoleRequest> {
+            Ok(RoleRequest {
+                approved_by: Some(approved_by),
+                approved_at: Some(SystemTime::now()),
+                ..create_role_request(id)
+            })
+        }
     }
 
-    pub fn create_user(id: UserId, email: String) -> User {
-        User {
-            id: id,
-            email: email,
-            email_verified: true,
-            phone: None,
-            phone_verified: false,
-            is_active: true,
-            first_name: None,
-            last_name: None,
-            middle_name: None,
-            gender: None,
-            avatar: None,
-            birthdate: None,
-            last_login_at: SystemTime::now(),
+    /// Pending request of user 2 to grant superuser role to user 3
+    pub fn create_role_request(id: Uuid) -> RoleRequest {
+        RoleRequest {
+            id,
+            user_id: UserId(3),
+            name: UsersRole::Superuser,
+            data: None,
+            expires_at: None,
+            requested_by: UserId(2),
+            approved_by: None,
+            approved_at: None,
             created_at: SystemTime::now(),
-            updated_at: SystemTime::now(),
-            saga_id: MOCK_SAGA_ID.to_string(),
-            is_blocked: false,
-            emarsys_id: None,
-            country: None,
-            referal: None,
-            referer: None,
-            utm_marks: None,
-            revoke_before: SystemTime::now(),
         }
     }
 
-    pub fn create_new_identity(email: String, password: String, provider: Provider, saga_id: String) -> NewIdentity {
-        NewIdentity {
-            email,
-            password: Some(password),
-            provider,
-            saga_id,
+    #[derive(Clone, Default)]
+    pub struct UserTagsRepoMock;
+
+    impl UserTagsRepo for UserTagsRepoMock {
+        fn list_for_user(&self, user_id_arg: UserId) -> RepoResult<Vec<UserTag>> {
+            Ok(vec![create_user_tag(user_id_arg, MOCK_TAG.to_string())])
         }
-    }
 
-    pub fn create_new_email_identity(email: String, password: String) -> EmailIdentity {
-        EmailIdentity { email, password }
-    }
+        fn add(&self, user_id_arg: UserId, tag_arg: String) -> RepoResult<UserTag> {
+            Ok(create_user_tag(user_id_arg, tag_arg))
+        }
 
-    pub fn create_update_user(_email: String) -> UpdateUser {
-        UpdateUser {
-            phone: None,
-            first_name: None,
-            last_name: None,
-            middle_name: None,
-            gender: None,
-            birthdate: None,
-            avatar: None,
-            is_active: None,
-            email_verified: None,
-            emarsys_id: None,
+        fn remove(&self, user_id_arg: UserId, tag_arg: String) -> RepoResult<Option<UserTag>> {
+            if tag_arg == MOCK_TAG {
+                Ok(Some(create_user_tag(user_id_arg, tag_arg)))
+            } else {
+                Ok(None)
+            }
         }
     }
 
-    pub fn create_identity(email: String, password: Option<String>, user_id: UserId, provider: Provider, saga_id: String) -> Identity {
-        Identity {
-            email,
-            password,
-            user_id,
-            provider,
-            saga_id,
+    #[derive(Clone, Default)]
+    pub struct JobsRepoMock;
+
+    impl JobsRepo for JobsRepoMock {
+        fn create(&self, payload: NewJob) -> RepoResult<Job> {
+            let mut job = create_job(payload.id);
+            job.kind = payload.kind;
+            job.created_by = payload.created_by;
+            Ok(job)
+        }
+
+        fn find(&self, id_arg: Uuid) -> RepoResult<Option<Job>> {
+            Ok(Some(create_job(id_arg)))
+        }
+
+        fn update(&self, id_arg: Uuid, payload: UpdateJob) -> RepoResult<Job> {
+            let mut job = create_job(id_arg);
+            job.state = payload.state.unwrap_or(job.state);
+            job.progress = payload.progress.unwrap_or(job.progress);
+            job.total = payload.total.or(job.total);
+            job.result_location = payload.result_location;
+            job.error = payload.error;
+            Ok(job)
+        }
+
+        fn find_latest(&self, kind_arg: JobKind, state_arg: JobState) -> RepoResult<Option<Job>> {
+            let mut job = create_job(Uuid::new_v4());
+            job.kind = kind_arg;
+            job.state = state_arg;
+            Ok(Some(job))
         }
     }
 
-    pub fn create_reset_token(token: String, email: String) -> ResetToken {
-        ResetToken {
-            token,
-            email,
-            token_type: TokenType::EmailVerify,
-            uuid: uuid::Uuid::new_v4(),
-            created_at: SystemTime::now(),
-            updated_at: SystemTime::now(),
+    #[derive(Clone, Default)]
+    pub struct LegacySyncStatesRepoMock;
+
+    impl LegacySyncStatesRepo for LegacySyncStatesRepoMock {
+        fn upsert(&self, payload: NewLegacySyncState) -> RepoResult<LegacySyncState> {
+            Ok(LegacySyncState {
+                user_id: payload.user_id,
+                operation: payload.operation,
+                synced: payload.synced,
+                error: payload.error,
+                created_at: SystemTime::now(),
+                updated_at: SystemTime::now(),
+            })
+        }
+
+        fn count(&self, synced_arg: bool) -> RepoResult<i64> {
+            Ok(if synced_arg { 2 } else { 1 })
+        }
+
+        fn list_failed(&self, _limit: i64) -> RepoResult<Vec<LegacySyncState>> {
+            Ok(vec![LegacySyncState {
+                user_id: UserId(1),
+                operation: LegacySyncOperation::Update,
+                synced: false,
+                error: Some("Legacy user API is unavailable".to_string()),
+                created_at: SystemTime::now(),
+                updated_at: SystemTime::now(),
+            }])
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct CrmDriftsRepoMock;
+
+    impl CrmDriftsRepo for CrmDriftsRepoMock {
+        fn add(&self, _payload: Vec<NewCrmDrift>) -> RepoResult<()> {
+            Ok(())
+        }
+
+        fn list_for_job(&self, job_id_arg: Uuid, _limit: i64) -> RepoResult<Vec<CrmDrift>> {
+            Ok(vec![CrmDrift {
+                id: 1,
+                job_id: job_id_arg,
+                user_id: UserId(1),
+                emarsys_id: EmarsysId(1),
+                field: CrmField::LastName,
+                user_value: Some("Lastname".to_string()),
+                crm_value: None,
+                corrected: false,
+                created_at: SystemTime::now(),
+            }])
+        }
+
+        fn delete_other_jobs(&self, _job_id_arg: Uuid) -> RepoResult<()> {
+            Ok(())
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct SegmentExportsRepoMock;
+
+    impl SegmentExportsRepo for SegmentExportsRepoMock {
+        fn create(&self, payload: NewSegmentExport) -> RepoResult<SegmentExport> {
+            let mut export = create_segment_export(payload.id);
+            export.csv = None;
+            export.filters = payload.filters;
+            Ok(export)
+        }
+
+        fn find(&self, id_arg: Uuid) -> RepoResult<Option<SegmentExport>> {
+            Ok(Some(create_segment_export(id_arg)))
+        }
+
+        fn set_csv(&self, id_arg: Uuid, csv_arg: String) -> RepoResult<SegmentExport> {
+            let mut export = create_segment_export(id_arg);
+            export.csv = Some(csv_arg);
+            Ok(export)
+        }
+
+        fn purge_csv(&self, _saved_before: SystemTime) -> RepoResult<usize> {
+            Ok(1)
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct LoginStatsRepoMock;
+
+    impl LoginStatsRepo for LoginStatsRepoMock {
+        fn add_login(&self, _date_arg: NaiveDate, _provider_arg: Provider) -> RepoResult<()> {
+            Ok(())
+        }
+
+        fn list(&self, from: NaiveDate, to: NaiveDate) -> RepoResult<Vec<LoginStat>> {
+            Ok(vec![
+                create_login_stat(from, Provider::Email, 3),
+                create_login_stat(from, Provider::Google, 2),
+                create_login_stat(to, Provider::Email, 1),
+            ])
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct FunnelEventsRepoMock;
+
+    impl FunnelEventsRepo for FunnelEventsRepoMock {
+        fn add(&self, _payload: NewFunnelEvent) -> RepoResult<()> {
+            Ok(())
+        }
+
+        fn count_cohort_step(&self, _from: NaiveDate, _to: NaiveDate, step_arg: FunnelStep) -> RepoResult<i64> {
+            Ok(match step_arg {
+                FunnelStep::RegistrationSubmitted => 200,
+                FunnelStep::EmailSent => 200,
+                FunnelStep::EmailVerified => 100,
+                FunnelStep::FirstLogin => 50,
+            })
+        }
+
+        fn delete_by_user(&self, _user_id_arg: UserId) -> RepoResult<()> {
+            Ok(())
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct DeletionRequestsRepoMock;
+
+    impl DeletionRequestsRepo for DeletionRequestsRepoMock {
+        fn create(&self, payload: NewDeletionRequest) -> RepoResult<DeletionRequest> {
+            Ok(DeletionRequest {
+                requested_by: payload.requested_by,
+                deadline: payload.deadline,
+                not_before: payload.not_before,
+                ..create_deletion_request(payload.user_id)
+            })
+        }
+
+        fn find(&self, user_id: UserId) -> RepoResult<Option<DeletionRequest>> {
+            Ok(Some(create_deletion_request(user_id)))
+        }
+
+        fn find_for_update(&self, user_id: UserId) -> RepoResult<Option<DeletionRequest>> {
+            Ok(Some(create_deletion_request(user_id)))
+        }
+
+        fn update(&self, user_id: UserId, payload: UpdateDeletionRequest) -> RepoResult<DeletionRequest> {
+            let mut request = create_deletion_request(user_id);
+            request.state = payload.state.unwrap_or(request.state);
+            request.job_id = payload.job_id.or(request.job_id);
+            Ok(request)
+        }
+
+        fn list_due(&self, _now: SystemTime) -> RepoResult<Vec<DeletionRequest>> {
+            Ok(vec![])
+        }
+
+        fn confirm(&self, payload: NewDeletionConfirmation) -> RepoResult<DeletionConfirmation> {
+            Ok(DeletionConfirmation {
+                user_id: payload.user_id,
+                service: payload.service,
+                approved: payload.approved,
+                reason: payload.reason,
+                created_at: SystemTime::now(),
+            })
+        }
+
+        fn list_confirmations(&self, _user_id: UserId) -> RepoResult<Vec<DeletionConfirmation>> {
+            Ok(vec![])
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct RecoveryRepoMock;
+
+    impl RecoveryRepo for RecoveryRepoMock {
+        fn list_contacts(&self, user_id: UserId) -> RepoResult<Vec<TrustedContact>> {
+            Ok((1..MOCK_TRUSTED_CONTACTS_COUNT + 1)
+                .map(|id| create_trusted_contact(id, user_id))
+                .collect())
+        }
+
+        fn find_contact(&self, contact_id: i32) -> RepoResult<Option<TrustedContact>> {
+            Ok(Some(create_trusted_contact(contact_id, UserId(1))))
+        }
+
+        fn add_contact(&self, payload: NewTrustedContact) -> RepoResult<TrustedContact> {
+            Ok(TrustedContact {
+                email: payload.email,
+                ..create_trusted_contact(MOCK_TRUSTED_CONTACTS_COUNT + 1, payload.user_id)
+            })
+        }
+
+        fn remove_contact(&self, user_id: UserId, contact_id: i32) -> RepoResult<Option<TrustedContact>> {
+            Ok(Some(create_trusted_contact(contact_id, user_id)))
+        }
+
+        fn create_request(&self, payload: NewRecoveryRequest) -> RepoResult<RecoveryRequest> {
+            Ok(RecoveryRequest {
+                id: payload.id,
+                user_id: payload.user_id,
+                state: payload.state,
+                required_approvals: payload.required_approvals,
+                expires_at: payload.expires_at,
+                created_at: SystemTime::now(),
+                updated_at: SystemTime::now(),
+            })
+        }
+
+        fn last_request(&self, _user_id: UserId) -> RepoResult<Option<RecoveryRequest>> {
+            Ok(None)
+        }
+
+        fn find_request_for_update(&self, request_id: Uuid) -> RepoResult<Option<RecoveryRequest>> {
+            Ok(Some(create_recovery_request(request_id, UserId(1))))
+        }
+
+        fn update_request_state(&self, request_id: Uuid, state: RecoveryState) -> RepoResult<RecoveryRequest> {
+            let mut request = create_recovery_request(request_id, UserId(1));
+            request.state = state;
+            Ok(request)
+        }
+
+        fn approve(&self, _payload: NewRecoveryApproval) -> RepoResult<()> {
+            Ok(())
+        }
+
+        fn count_approvals(&self, _request_id: Uuid) -> RepoResult<i64> {
+            Ok(MOCK_RECOVERY_APPROVALS)
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct SecurityAnswersRepoMock;
+
+    impl SecurityAnswersRepo for SecurityAnswersRepoMock {
+        fn list_for_user(&self, user_id: UserId) -> RepoResult<Vec<SecurityAnswer>> {
+            Ok(vec![create_security_answer(user_id)])
+        }
+
+        fn replace(&self, _user_id: UserId, answers: Vec<NewSecurityAnswer>) -> RepoResult<Vec<SecurityAnswer>> {
+            Ok(answers
+                .into_iter()
+                .map(|answer| SecurityAnswer {
+                    user_id: answer.user_id,
+                    question_id: answer.question_id,
+                    answer_hash: answer.answer_hash,
+                    created_at: SystemTime::now(),
+                })
+                .collect())
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct AuditLogRepoMock;
+
+    impl AuditLogRepo for AuditLogRepoMock {
+        fn add(&self, payload: NewAuditEvent) -> RepoResult<AuditEvent> {
+            Ok(AuditEvent {
+                id: 1,
+                user_id: payload.user_id,
+                actor_id: payload.actor_id,
+                action: payload.action,
+                data: payload.data,
+                created_at: SystemTime::now(),
+            })
+        }
+
+        fn list_for_user(&self, _user_id: UserId) -> RepoResult<Vec<AuditEvent>> {
+            Ok(vec![])
+        }
+
+        fn redact_user(&self, _user_id: UserId) -> RepoResult<usize> {
+            Ok(0)
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct LoginHistoryRepoMock;
+
+    impl LoginHistoryRepo for LoginHistoryRepoMock {
+        fn add(&self, payload: NewLoginEvent) -> RepoResult<LoginEvent> {
+            Ok(LoginEvent {
+                id: 1,
+                user_id: payload.user_id,
+                provider: payload.provider,
+                client_ip: payload.client_ip,
+                user_agent: payload.user_agent,
+                fraud_score: payload.fraud_score,
+                decision: payload.decision,
+                created_at: SystemTime::now(),
+            })
+        }
+
+        fn list_since(&self, _user_id: UserId, _since: SystemTime) -> RepoResult<Vec<LoginEvent>> {
+            Ok(vec![])
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct DevicesRepoMock;
+
+    impl DevicesRepo for DevicesRepoMock {
+        fn list_for_user(&self, user_id: UserId) -> RepoResult<Vec<Device>> {
+            Ok(vec![create_device(Uuid::new_v4(), user_id)])
+        }
+
+        fn find(&self, user_id: UserId, device_id: Uuid) -> RepoResult<Option<Device>> {
+            Ok(Some(create_device(device_id, user_id)))
+        }
+
+        fn touch(&self, payload: NewDevice, seen_at: SystemTime) -> RepoResult<Device> {
+            Ok(Device {
+                id: payload.id,
+                user_id: payload.user_id,
+                fingerprint: payload.fingerprint,
+                name: payload.name,
+                trusted_until: None,
+                first_seen_at: seen_at,
+                last_seen_at: seen_at,
+            })
+        }
+
+        fn set_trusted_until(&self, user_id: UserId, device_id: Uuid, until: Option<SystemTime>) -> RepoResult<Option<Device>> {
+            Ok(Some(Device {
+                trusted_until: until,
+                ..create_device(device_id, user_id)
+            }))
+        }
+
+        fn delete(&self, user_id: UserId, device_id: Uuid) -> RepoResult<Option<Device>> {
+            Ok(Some(create_device(device_id, user_id)))
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct RoleAccessPoliciesRepoMock;
+
+    impl RoleAccessPoliciesRepo for RoleAccessPoliciesRepoMock {
+        fn list(&self) -> RepoResult<Vec<RoleAccessPolicy>> {
+            Ok(vec![])
+        }
+
+        fn upsert(&self, payload: NewRoleAccessPolicy) -> RepoResult<RoleAccessPolicy> {
+            Ok(RoleAccessPolicy {
+                role: payload.role,
+                starts_at: payload.starts_at,
+                ends_at: payload.ends_at,
+                utc_offset_minutes: payload.utc_offset_minutes,
+                restrict_login: payload.restrict_login,
+                created_at: SystemTime::now(),
+                updated_at: SystemTime::now(),
+            })
+        }
+
+        fn delete(&self, _role: UsersRole) -> RepoResult<Option<RoleAccessPolicy>> {
+            Ok(None)
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct InvitesRepoMock;
+
+    impl InvitesRepo for InvitesRepoMock {
+        fn create(&self, payload: NewInvite) -> RepoResult<Invite> {
+            Ok(Invite {
+                code: payload.code,
+                created_by: payload.created_by,
+                max_uses: payload.max_uses,
+                uses: 0,
+                expires_at: payload.expires_at,
+                created_at: SystemTime::now(),
+            })
+        }
+
+        fn use_invite(&self, code: String) -> RepoResult<Option<Invite>> {
+            if code == MOCK_INVITE_CODE {
+                Ok(Some(Invite {
+                    code,
+                    created_by: Some(UserId(1)),
+                    max_uses: 1,
+                    uses: 1,
+                    expires_at: SystemTime::now() + Duration::from_secs(3600),
+                    created_at: SystemTime::now(),
+                }))
+            } else {
+                Ok(None)
+            }
+        }
+
+        fn release_invite(&self, _code: String) -> RepoResult<()> {
+            Ok(())
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct WaitlistRepoMock;
+
+    impl WaitlistRepo for WaitlistRepoMock {
+        fn upsert(&self, payload: NewWaitlistEntry) -> RepoResult<WaitlistEntry> {
+            Ok(create_waitlist_entry(payload.email, payload.token))
+        }
+
+        fn find_by_token(&self, token: String) -> RepoResult<Option<WaitlistEntry>> {
+            if token == MOCK_TOKEN {
+                Ok(Some(create_waitlist_entry(MOCK_EMAIL.to_string(), token)))
+            } else {
+                Ok(None)
+            }
+        }
+
+        fn confirm(&self, email: String) -> RepoResult<WaitlistEntry> {
+            let mut entry = create_waitlist_entry(email, MOCK_TOKEN.to_string());
+            entry.confirmed = true;
+            Ok(entry)
+        }
+
+        fn list_to_invite(&self, count: i64) -> RepoResult<Vec<WaitlistEntry>> {
+            Ok((0..count)
+                .map(|i| {
+                    let mut entry = create_waitlist_entry(format!("waiting{}@mail.com", i), MOCK_TOKEN.to_string());
+                    entry.confirmed = true;
+                    entry
+                })
+                .collect())
+        }
+
+        fn set_invite(&self, email: String, invite_code: String) -> RepoResult<WaitlistEntry> {
+            let mut entry = create_waitlist_entry(email, MOCK_TOKEN.to_string());
+            entry.confirmed = true;
+            entry.invite_code = Some(invite_code);
+            Ok(entry)
+        }
+    }
+
+    pub fn create_waitlist_entry(email: String, token: String) -> WaitlistEntry {
+        WaitlistEntry {
+            email,
+            token,
+            confirmed: false,
+            invite_code: None,
+            created_at: SystemTime::now(),
+            updated_at: SystemTime::now(),
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct ProfilePromptsRepoMock;
+
+    impl ProfilePromptsRepo for ProfilePromptsRepoMock {
+        fn list_snoozed(&self, user_id: UserId, now: SystemTime) -> RepoResult<Vec<ProfilePromptDismissal>> {
+            Ok(vec![ProfilePromptDismissal {
+                user_id,
+                field: MOCK_SNOOZED_PROMPT.to_string(),
+                snoozed_until: now + Duration::from_secs(3600),
+                created_at: now,
+            }])
+        }
+
+        fn dismiss(&self, payload: NewProfilePromptDismissal) -> RepoResult<ProfilePromptDismissal> {
+            Ok(ProfilePromptDismissal {
+                user_id: payload.user_id,
+                field: payload.field,
+                snoozed_until: payload.snoozed_until,
+                created_at: SystemTime::now(),
+            })
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct ChildAccountsRepoMock;
+
+    impl ChildAccountsRepo for ChildAccountsRepoMock {
+        fn list_children(&self, parent_id: UserId) -> RepoResult<Vec<ChildAccount>> {
+            Ok(if parent_id == MOCK_PARENT_ID {
+                vec![create_child_account(MOCK_CHILD_ID, parent_id)]
+            } else {
+                vec![]
+            })
+        }
+
+        fn find_by_child(&self, child_id: UserId) -> RepoResult<Option<ChildAccount>> {
+            Ok(if child_id == MOCK_CHILD_ID {
+                Some(create_child_account(child_id, MOCK_PARENT_ID))
+            } else {
+                None
+            })
+        }
+
+        fn create(&self, payload: NewChildAccount) -> RepoResult<ChildAccount> {
+            Ok(create_child_account(payload.child_id, payload.parent_id))
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct AccessTokensRepoMock;
+
+    impl AccessTokensRepo for AccessTokensRepoMock {
+        fn list_for_user(&self, user_id: UserId) -> RepoResult<Vec<AccessToken>> {
+            Ok(vec![create_access_token(user_id)])
+        }
+
+        fn create(&self, payload: NewAccessToken) -> RepoResult<AccessToken> {
+            Ok(AccessToken {
+                id: payload.id,
+                user_id: payload.user_id,
+                name: payload.name,
+                token_hash: payload.token_hash,
+                scopes: payload.scopes,
+                expires_at: payload.expires_at,
+                last_used_at: None,
+                created_at: SystemTime::now(),
+            })
+        }
+
+        fn delete(&self, user_id: UserId, token_id: Uuid) -> RepoResult<Option<AccessToken>> {
+            Ok(if token_id == Uuid::nil() {
+                Some(create_access_token(user_id))
+            } else {
+                None
+            })
+        }
+
+        fn find_by_hash(&self, token_hash: String) -> RepoResult<Option<AccessToken>> {
+            Ok(Some(AccessToken {
+                token_hash,
+                ..create_access_token(UserId(1))
+            }))
+        }
+
+        fn touch(&self, _token_id: Uuid, _used_at: SystemTime) -> RepoResult<()> {
+            Ok(())
+        }
+
+        fn lock_user(&self, _user_id: UserId) -> RepoResult<()> {
+            Ok(())
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct OAuthConsentsRepoMock;
+
+    impl OAuthConsentsRepo for OAuthConsentsRepoMock {
+        fn find(&self, user_id: UserId, client_id: String) -> RepoResult<Option<OAuthConsent>> {
+            Ok(if client_id == MOCK_THIRD_PARTY_CLIENT_ID {
+                Some(create_oauth_consent(user_id, client_id, vec!["profile:read".to_string()]))
+            } else {
+                None
+            })
+        }
+
+        fn upsert(&self, payload: NewOAuthConsent) -> RepoResult<OAuthConsent> {
+            Ok(create_oauth_consent(payload.user_id, payload.client_id, payload.scopes))
+        }
+
+        fn list_for_user(&self, user_id: UserId) -> RepoResult<Vec<OAuthConsent>> {
+            Ok(vec![create_oauth_consent(
+                user_id,
+                MOCK_THIRD_PARTY_CLIENT_ID.to_string(),
+                vec!["profile:read".to_string()],
+            )])
+        }
+
+        fn revoke(&self, payload: NewOAuthRevocation) -> RepoResult<Option<OAuthConsent>> {
+            self.find(payload.user_id, payload.client_id)
+        }
+
+        fn find_revocation(&self, user_id: UserId, client_id: String) -> RepoResult<Option<OAuthRevocation>> {
+            Ok(if user_id == MOCK_REVOKED_USER_ID && client_id == MOCK_THIRD_PARTY_CLIENT_ID {
+                Some(OAuthRevocation {
+                    user_id,
+                    client_id,
+                    revoke_before: SystemTime::now() + Duration::from_secs(3600),
+                    created_at: SystemTime::now(),
+                    updated_at: SystemTime::now(),
+                })
+            } else {
+                None
+            })
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct RefreshTokensRepoMock;
+
+    impl RefreshTokensRepo for RefreshTokensRepoMock {
+        fn create(&self, payload: NewRefreshToken) -> RepoResult<RefreshToken> {
+            Ok(RefreshToken {
+                id: payload.id,
+                family_id: payload.family_id,
+                user_id: payload.user_id,
+                client_id: payload.client_id,
+                provider: payload.provider,
+                auth_time: payload.auth_time,
+                scope: payload.scope,
+                token_hash: payload.token_hash,
+                expires_at: payload.expires_at,
+                rotated_at: None,
+                revoked: false,
+                family_created_at: payload.family_created_at,
+                created_at: SystemTime::now(),
+            })
+        }
+
+        fn find_by_hash(&self, hash: String) -> RepoResult<Option<RefreshToken>> {
+            Ok(if hash == token_hash(MOCK_REFRESH_TOKEN) {
+                Some(create_refresh_token(MOCK_CLIENT_ID.to_string(), None))
+            } else if hash == token_hash(MOCK_ROTATED_REFRESH_TOKEN) {
+                Some(create_refresh_token(MOCK_CLIENT_ID.to_string(), Some(SystemTime::now())))
+            } else if hash == token_hash(MOCK_COOKIE_REFRESH_TOKEN) {
+                Some(create_refresh_token(MOCK_COOKIE_CLIENT_ID.to_string(), None))
+            } else {
+                None
+            })
+        }
+
+        fn rotate(&self, _token_id: Uuid, _rotated_at: SystemTime) -> RepoResult<bool> {
+            Ok(true)
+        }
+
+        fn revoke_family(&self, _family_id: Uuid) -> RepoResult<()> {
+            Ok(())
+        }
+
+        fn list_active(&self, _user_id: UserId, _now: SystemTime) -> RepoResult<Vec<RefreshToken>> {
+            Ok(vec![create_refresh_token(MOCK_CLIENT_ID.to_string(), None)])
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct SessionsRepoMock;
+
+    impl SessionsRepo for SessionsRepoMock {
+        fn create(&self, payload: NewSession) -> RepoResult<Session> {
+            Ok(Session {
+                id: payload.id,
+                session_hash: payload.session_hash,
+                user_id: payload.user_id,
+                client_id: payload.client_id,
+                provider: payload.provider,
+                auth_time: payload.auth_time,
+                expires_at: payload.expires_at,
+                last_seen_at: SystemTime::now(),
+                created_at: SystemTime::now(),
+            })
+        }
+
+        fn find_by_hash(&self, hash: String) -> RepoResult<Option<Session>> {
+            Ok(if hash == token_hash(MOCK_SESSION_ID) {
+                Some(create_session(SystemTime::now()))
+            } else if hash == token_hash(MOCK_IDLE_SESSION_ID) {
+                Some(create_session(SystemTime::now() - Duration::from_secs(86400)))
+            } else {
+                None
+            })
+        }
+
+        fn touch(&self, _session_id: Uuid, _last_seen_at: SystemTime) -> RepoResult<()> {
+            Ok(())
+        }
+
+        fn delete_by_hash(&self, hash: String) -> RepoResult<Option<Session>> {
+            self.find_by_hash(hash)
+        }
+
+        fn list_active(&self, _user_id: UserId, _now: SystemTime) -> RepoResult<Vec<Session>> {
+            Ok(vec![create_session(SystemTime::now())])
+        }
+
+        fn delete(&self, _session_id: Uuid) -> RepoResult<()> {
+            Ok(())
+        }
+
+        fn lock_user(&self, _user_id: UserId) -> RepoResult<()> {
+            Ok(())
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct SchemaMigrationsRepoMock;
+
+    impl SchemaMigrationsRepo for SchemaMigrationsRepoMock {
+        fn current_version(&self) -> RepoResult<Option<String>> {
+            Ok(Some(MIN_SCHEMA_VERSION.to_string()))
+        }
+    }
+
+    pub fn create_service(
+        user_id: Option<UserId>,
+        handle: Arc<Handle>,
+    ) -> Service<MockConnection, MockConnectionManager, ReposFactoryMock> {
+        let manager = MockConnectionManager::default();
+        let db_pool = r2d2::Pool::builder().build(manager).expect("Failed to create connection pool");
+        let db_pool = ShardedPool::single(db_pool);
+
+        let config = Config::new().unwrap();
+        let work_pool = WorkPool::new(&config.work_queues, 1);
+        let client = stq_http::client::Client::new(&config.to_http_config(), &handle);
+        let client_handle = client.handle();
+        let client_stream = client.stream();
+        handle.spawn(client_stream.for_each(|_| Ok(())));
+        let mut f = File::open(config.jwt.secret_key_path.clone()).unwrap();
+        let mut jwt_private_key: Vec<u8> = Vec::new();
+        f.read_to_end(&mut jwt_private_key).unwrap();
+        let google_provider_service: Arc<JWTProviderService<GoogleProfile>> = Arc::new(JWTProviderServiceMock);
+        let facebook_provider_service: Arc<JWTProviderService<FacebookProfile>> = Arc::new(JWTProviderServiceMock);
+        let name_screening = Arc::new(NameScreeningServiceImpl::new(config.name_screening.clone()));
+        let static_context = StaticContext::new(
+            db_pool,
+            work_pool,
+            client_handle.clone(),
+            Arc::new(config),
+            MOCK_REPO_FACTORY,
+            jwt_private_key,
+            name_screening,
+        );
+        let time_limited_http_client = TimeLimitedHttpClient::new(client_handle, Duration::new(1, 0));
+        let dynamic_context = DynamicContext::new(
+            user_id,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            String::default(),
+            WorkPriority::Regular,
+            false,
+            time_limited_http_client,
+            google_provider_service,
+            facebook_provider_service,
+        );
+
+        Service::new(Arc::new(static_context), dynamic_context)
+    }
+
+    pub fn create_user(id: UserId, email: String) -> User {
+        User {
+            id: id,
+            email: email,
+            email_verified: true,
+            phone: None,
+            phone_verified: false,
+            is_active: true,
+            first_name: None,
+            last_name: None,
+            middle_name: None,
+            gender: None,
+            avatar: None,
+            birthdate: None,
+            last_login_at: SystemTime::now(),
+            created_at: SystemTime::now(),
+            updated_at: SystemTime::now(),
+            saga_id: MOCK_SAGA_ID.to_string(),
+            is_blocked: false,
+            emarsys_id: None,
+            country: None,
+            referal: None,
+            referer: None,
+            utm_marks: None,
+            revoke_before: SystemTime::now(),
+            data_region: "global".to_string(),
+            sealed_pii: None,
+            pending_deletion: false,
+        }
+    }
+
+    pub fn create_new_identity(email: String, password: String, provider: Provider, saga_id: String) -> NewIdentity {
+        NewIdentity {
+            email,
+            password: Some(password),
+            provider,
+            saga_id,
+        }
+    }
+
+    pub fn create_new_email_identity(email: String, password: String) -> EmailIdentity {
+        EmailIdentity {
+            email,
+            password,
+            client_id: None,
+            remember_me: false,
+        }
+    }
+
+    pub fn create_client(id: String) -> Client {
+        Client {
+            name: id.clone(),
+            audience: id.clone(),
+            id,
+            jwt_expiration_s: 3600,
+            refresh_timeout_s: 86400,
+            created_at: SystemTime::now(),
+            updated_at: SystemTime::now(),
+            secret_hash: None,
+            service_user_id: None,
+            is_third_party: false,
+            refresh_token_cookie: false,
+            session_mode: false,
+            remember_me_expiration_s: None,
+            remember_me_refresh_timeout_s: None,
+        }
+    }
+
+    pub fn create_client_branding(client_id: String) -> ClientBranding {
+        ClientBranding {
+            client_id,
+            app_name: "Marketplace".to_string(),
+            support_email: "support@example.com".to_string(),
+            logo_url: Some("https://example.com/logo.png".to_string()),
+            created_at: SystemTime::now(),
+            updated_at: SystemTime::now(),
+        }
+    }
+
+    pub fn create_country(alpha3: Alpha3) -> Country {
+        Country {
+            alpha3,
+            alpha2: "RU".to_string(),
+            name: "Russian Federation".to_string(),
+            phone_prefix: Some("+7".to_string()),
+        }
+    }
+
+    pub fn create_device(id: Uuid, user_id: UserId) -> Device {
+        Device {
+            id,
+            user_id,
+            fingerprint: MOCK_DEVICE_FINGERPRINT.to_string(),
+            name: Some("Mozilla/5.0".to_string()),
+            trusted_until: None,
+            first_seen_at: SystemTime::now(),
+            last_seen_at: SystemTime::now(),
+        }
+    }
+
+    pub fn create_device_code(device_code: String, user_id: Option<UserId>) -> DeviceCode {
+        DeviceCode {
+            device_code,
+            user_code: MOCK_USER_CODE.to_string(),
+            client_id: MOCK_CLIENT_ID.to_string(),
+            user_id,
+            expires_at: SystemTime::now() + Duration::from_secs(600),
+            created_at: SystemTime::now(),
+            updated_at: SystemTime::now(),
+            scope: None,
+            last_polled_at: None,
+        }
+    }
+
+    pub fn create_third_party_device_code(user_id: Option<UserId>) -> DeviceCode {
+        DeviceCode {
+            user_code: MOCK_THIRD_PARTY_USER_CODE.to_string(),
+            client_id: MOCK_THIRD_PARTY_CLIENT_ID.to_string(),
+            scope: Some("profile:read email:read".to_string()),
+            ..create_device_code(MOCK_THIRD_PARTY_DEVICE_CODE.to_string(), user_id)
+        }
+    }
+
+    pub fn create_oauth_consent(user_id: UserId, client_id: String, scopes: Vec<String>) -> OAuthConsent {
+        OAuthConsent {
+            user_id,
+            client_id,
+            scopes,
+            created_at: SystemTime::now(),
+            updated_at: SystemTime::now(),
+        }
+    }
+
+    pub fn create_refresh_token(client_id: String, rotated_at: Option<SystemTime>) -> RefreshToken {
+        RefreshToken {
+            id: Uuid::new_v4(),
+            family_id: Uuid::new_v4(),
+            user_id: UserId(1),
+            client_id,
+            provider: Provider::Email,
+            auth_time: Some(0),
+            scope: None,
+            token_hash: "token_hash".to_string(),
+            expires_at: SystemTime::now() + Duration::from_secs(86400),
+            rotated_at,
+            revoked: false,
+            family_created_at: SystemTime::now(),
+            created_at: SystemTime::now(),
+        }
+    }
+
+    pub fn create_session(last_seen_at: SystemTime) -> Session {
+        Session {
+            id: Uuid::new_v4(),
+            session_hash: "session_hash".to_string(),
+            user_id: UserId(1),
+            client_id: MOCK_SESSION_CLIENT_ID.to_string(),
+            provider: Provider::Email,
+            auth_time: Some(0),
+            expires_at: SystemTime::now() + Duration::from_secs(86400),
+            last_seen_at,
+            created_at: SystemTime::now(),
+        }
+    }
+
+    pub fn create_update_user(_email: String) -> UpdateUser {
+        UpdateUser {
+            phone: None,
+            first_name: None,
+            last_name: None,
+            middle_name: None,
+            gender: None,
+            birthdate: None,
+            avatar: None,
+            is_active: None,
+            email_verified: None,
+            emarsys_id: None,
+        }
+    }
+
+    pub fn create_identity(email: String, password: Option<String>, user_id: UserId, provider: Provider, saga_id: String) -> Identity {
+        Identity {
+            email,
+            password,
+            user_id,
+            provider,
+            saga_id,
+            must_change_password: false,
+            password_hash_format: None,
+        }
+    }
+
+    pub fn create_reset_token(token: String, email: String) -> ResetToken {
+        ResetToken {
+            token,
+            email,
+            token_type: TokenType::EmailVerify,
+            uuid: uuid::Uuid::new_v4(),
+            created_at: SystemTime::now(),
+            updated_at: SystemTime::now(),
+        }
+    }
+
+    /// Pending job of a superuser
+    pub fn create_job(id: Uuid) -> Job {
+        Job {
+            id,
+            kind: JobKind::SegmentExport,
+            state: JobState::Pending,
+            progress: 0,
+            total: None,
+            result_location: None,
+            error: None,
+            created_by: Some(UserId(1)),
+            created_at: SystemTime::now(),
+            updated_at: SystemTime::now(),
+        }
+    }
+
+    /// Pending deletion requested by the user, deadline is in an hour
+    pub fn create_deletion_request(user_id: UserId) -> DeletionRequest {
+        DeletionRequest {
+            user_id,
+            state: DeletionState::Pending,
+            requested_by: Some(user_id),
+            deadline: SystemTime::now() + Duration::from_secs(3600),
+            job_id: None,
+            created_at: SystemTime::now(),
+            updated_at: SystemTime::now(),
+            not_before: SystemTime::now(),
+        }
+    }
+
+    pub fn create_trusted_contact(id: i32, user_id: UserId) -> TrustedContact {
+        TrustedContact {
+            id,
+            user_id,
+            email: format!("contact{}@example.com", id),
+            created_at: SystemTime::now(),
+        }
+    }
+
+    /// Answer `MOCK_SECURITY_ANSWER` to `MOCK_SECURITY_QUESTION`
+    pub fn create_security_answer(user_id: UserId) -> SecurityAnswer {
+        SecurityAnswer {
+            user_id,
+            question_id: MOCK_SECURITY_QUESTION.to_string(),
+            answer_hash: password_create(MOCK_SECURITY_ANSWER.to_string()),
+            created_at: SystemTime::now(),
+        }
+    }
+
+    /// Pending request needing two approvals, the mocked repo reports both of them
+    pub fn create_recovery_request(id: Uuid, user_id: UserId) -> RecoveryRequest {
+        RecoveryRequest {
+            id,
+            user_id,
+            state: RecoveryState::Pending,
+            required_approvals: MOCK_RECOVERY_APPROVALS as i32,
+            expires_at: SystemTime::now() + Duration::from_secs(3600),
+            created_at: SystemTime::now(),
+            updated_at: SystemTime::now(),
+        }
+    }
+
+    /// Export with CSV of a single mocked user
+    pub fn create_segment_export(id: Uuid) -> SegmentExport {
+        SegmentExport {
+            id,
+            filters: serde_json::Value::Null,
+            csv: Some(format!("id,email\n2,{}\n", MOCK_EMAIL)),
+            created_at: SystemTime::now(),
+            updated_at: SystemTime::now(),
+        }
+    }
+
+    pub fn create_login_stat(date: NaiveDate, provider: Provider, logins: i64) -> LoginStat {
+        LoginStat { date, provider, logins }
+    }
+
+    pub fn create_access_token(user_id: UserId) -> AccessToken {
+        AccessToken {
+            id: Uuid::nil(),
+            user_id,
+            name: "ci".to_string(),
+            token_hash: "token_hash".to_string(),
+            scopes: vec!["read".to_string()],
+            expires_at: None,
+            last_used_at: None,
+            created_at: SystemTime::now(),
+        }
+    }
+
+    pub fn create_child_account(child_id: UserId, parent_id: UserId) -> ChildAccount {
+        ChildAccount {
+            child_id,
+            parent_id,
+            created_at: SystemTime::now(),
+        }
+    }
+
+    pub fn create_user_tag(user_id: UserId, tag: String) -> UserTag {
+        UserTag {
+            user_id,
+            tag,
+            created_at: SystemTime::now(),
         }
     }
 
@@ -669,10 +2664,51 @@ pub mod tests {
     pub const MOCK_REPO_FACTORY: ReposFactoryMock = ReposFactoryMock {};
     pub const MOCK_USERS: UsersRepoMock = UsersRepoMock {};
     pub const MOCK_IDENT: IdentitiesRepoMock = IdentitiesRepoMock {};
+    /// Users with ids up to this one are found by search for `MOCK_SEGMENT_TAG`
+    pub const MOCK_USERS_COUNT: i32 = 1500;
+    pub const MOCK_TRUSTED_CONTACTS_COUNT: i32 = 3;
+    pub const MOCK_RECOVERY_APPROVALS: i64 = 2;
+    pub const MOCK_PARENT_ID: UserId = UserId(1);
+    pub const MOCK_CHILD_ID: UserId = UserId(3);
+    /// User who disconnected the third-party client
+    pub const MOCK_REVOKED_USER_ID: UserId = UserId(4);
     pub static MOCK_EMAIL: &'static str = "example@mail.com";
+    pub static MOCK_PHONE: &'static str = "+79991234567";
+    pub static MOCK_TAG: &'static str = "beta_testers";
+    pub static MOCK_SEGMENT_TAG: &'static str = "vip";
+    pub static MOCK_SECURITY_QUESTION: &'static str = "first_pet";
+    pub static MOCK_SECURITY_ANSWER: &'static str = "rex";
+    pub static MOCK_SNOOZED_PROMPT: &'static str = "first_name";
+    pub static MOCK_INVITE_CODE: &'static str = "2f6bd21a9e3c4b5d8a7f0e1c2b3a4d5e";
     pub static MOCK_PASSWORD: &'static str = "password";
     pub static MOCK_TOKEN: &'static str = "token";
     pub static MOCK_SAGA_ID: &'static str = "saga_id";
+    pub static MOCK_COUNTRY: &'static str = "RUS";
+    pub static MOCK_CLIENT_ID: &'static str = "storefront";
+    pub static MOCK_CONFIDENTIAL_CLIENT_ID: &'static str = "integration";
+    pub static MOCK_CLIENT_SECRET: &'static str = "client_secret";
+    pub static MOCK_THIRD_PARTY_CLIENT_ID: &'static str = "weather_app";
+    /// Browser client receiving refresh tokens as cookie
+    pub static MOCK_COOKIE_CLIENT_ID: &'static str = "web_app";
+    /// Server-rendered client with server-side sessions
+    pub static MOCK_SESSION_CLIENT_ID: &'static str = "admin_console";
+    pub static MOCK_APPROVED_DEVICE_CODE: &'static str = "approved_device_code";
+    pub static MOCK_PENDING_DEVICE_CODE: &'static str = "pending_device_code";
+    /// Pending device code polled faster than the interval
+    pub static MOCK_SLOW_DEVICE_CODE: &'static str = "slow_device_code";
+    pub static MOCK_USER_CODE: &'static str = "BCDF-GHJK";
+    pub static MOCK_THIRD_PARTY_DEVICE_CODE: &'static str = "third_party_device_code";
+    /// Id of a single-use token applied before
+    pub static MOCK_USED_TOKEN_ID: &'static str = "used_token_id";
+    pub static MOCK_BLOCKED_PROVIDER: Provider = Provider::Facebook;
+    pub static MOCK_DEVICE_FINGERPRINT: &'static str = "device_fingerprint";
+    pub static MOCK_THIRD_PARTY_USER_CODE: &'static str = "LMNP-QRST";
+    pub static MOCK_REFRESH_TOKEN: &'static str = "refresh_token";
+    pub static MOCK_ROTATED_REFRESH_TOKEN: &'static str = "rotated_refresh_token";
+    pub static MOCK_COOKIE_REFRESH_TOKEN: &'static str = "cookie_refresh_token";
+    pub static MOCK_SESSION_ID: &'static str = "session_id";
+    pub static MOCK_IDLE_SESSION_ID: &'static str = "idle_session_id";
+    pub static MOCK_LOCKED_IP: &'static str = "10.0.0.13";
     pub static GOOGLE_TOKEN: &'static str =
         "ya29.GlxRBXyOU1dfRmFEdVE1oOK3SyQ6UKh4RTESu0J-C19N2o5RCQVEALMi5DKlgctjTQclLCrLQkUovOb05ikfYQdZ2paFja9Uf4GN1hoysgp_dDr9NLgvfo7fGth \
          Y8A";
@@ -21,8 +21,8 @@ pub struct ResetTokenRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager
 }
 
 pub trait ResetTokenRepo {
-    /// Create token for user
-    fn upsert(&self, email_arg: String, token_type_arg: TokenType, uuid: Option<Uuid>) -> RepoResult<ResetToken>;
+    /// Create token for user or replace existing one with the new token
+    fn upsert(&self, email_arg: String, token_type_arg: TokenType, uuid: Option<Uuid>, token_arg: String) -> RepoResult<ResetToken>;
 
     /// Find by token
     fn find_by_token(&self, token_arg: String, token_type_arg: TokenType) -> RepoResult<ResetToken>;
@@ -44,8 +44,8 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
 }
 
 impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> ResetTokenRepo for ResetTokenRepoImpl<'a, T> {
-    /// Create token for user
-    fn upsert(&self, email_arg: String, token_type_arg: TokenType, uuid_: Option<Uuid>) -> RepoResult<ResetToken> {
+    /// Create token for user or replace existing one with the new token
+    fn upsert(&self, email_arg: String, token_type_arg: TokenType, uuid_: Option<Uuid>, token_arg: String) -> RepoResult<ResetToken> {
         let filtered = reset_tokens
             .filter(email.eq(email_arg.clone()))
             .filter(token_type.eq(token_type_arg.clone()));
@@ -57,11 +57,11 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
 
         if token_.is_some() {
             diesel::update(filtered)
-                .set(updated_at.eq(SystemTime::now()))
+                .set((token.eq(token_arg), updated_at.eq(SystemTime::now())))
                 .get_result(self.db_conn)
                 .map_err(|e| e.context(format!("Update token error occured")).into())
         } else {
-            let payload = ResetToken::new(email_arg.clone(), token_type_arg, uuid_);
+            let payload = ResetToken::new(email_arg.clone(), token_type_arg, uuid_, token_arg);
             diesel::insert_into(reset_tokens)
                 .values(payload)
                 .get_result::<ResetToken>(self.db_conn)
@@ -0,0 +1,102 @@
+//! Repo for role_access_policies table, time windows admin roles are in effect during
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_types::{UserId, UsersRole};
+
+use repos::legacy_acl::*;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::*;
+use models::{NewRoleAccessPolicy, RoleAccessPolicy};
+use schema::role_access_policies::dsl::*;
+
+/// RoleAccessPolicies repository, responsible for handling access policies of roles
+pub trait RoleAccessPoliciesRepo {
+    /// Returns policies of all roles
+    fn list(&self) -> RepoResult<Vec<RoleAccessPolicy>>;
+
+    /// Creates the policy of the role or replaces the existing one
+    fn upsert(&self, payload: NewRoleAccessPolicy) -> RepoResult<RoleAccessPolicy>;
+
+    /// Deletes the policy of the role, returns `None` if the role had no policy
+    fn delete(&self, role_arg: UsersRole) -> RepoResult<Option<RoleAccessPolicy>>;
+}
+
+/// Implementation of RoleAccessPolicies trait
+pub struct RoleAccessPoliciesRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, RoleAccessPolicy>>,
+    pub db_conn: &'a T,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> RoleAccessPoliciesRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, RoleAccessPolicy>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> RoleAccessPoliciesRepo
+    for RoleAccessPoliciesRepoImpl<'a, T>
+{
+    /// Returns policies of all roles
+    fn list(&self) -> RepoResult<Vec<RoleAccessPolicy>> {
+        let query = role_access_policies.order(role);
+        query
+            .get_results::<RoleAccessPolicy>(self.db_conn)
+            .map_err(From::from)
+            .and_then(|policies: Vec<RoleAccessPolicy>| {
+                for policy in &policies {
+                    acl::check(&*self.acl, Resource::RoleAccessPolicies, Action::Read, self, Some(policy))?;
+                }
+                Ok(policies)
+            })
+            .map_err(|e: FailureError| e.context("List role access policies error occured").into())
+    }
+
+    /// Creates the policy of the role or replaces the existing one
+    fn upsert(&self, payload: NewRoleAccessPolicy) -> RepoResult<RoleAccessPolicy> {
+        acl::check(&*self.acl, Resource::RoleAccessPolicies, Action::Update, self, None)
+            .and_then(|_| {
+                let query = diesel::insert_into(role_access_policies)
+                    .values(&payload)
+                    .on_conflict(role)
+                    .do_update()
+                    .set(&payload);
+                query.get_result::<RoleAccessPolicy>(self.db_conn).map_err(From::from)
+            })
+            .map_err(|e: FailureError| e.context(format!("Upsert role access policy {:?} error occured", payload)).into())
+    }
+
+    /// Deletes the policy of the role, returns `None` if the role had no policy
+    fn delete(&self, role_arg: UsersRole) -> RepoResult<Option<RoleAccessPolicy>> {
+        acl::check(&*self.acl, Resource::RoleAccessPolicies, Action::Delete, self, None)
+            .and_then(|_| {
+                let filtered = role_access_policies.filter(role.eq(role_arg));
+                let query = diesel::delete(filtered);
+                query.get_result::<RoleAccessPolicy>(self.db_conn).optional().map_err(From::from)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Delete access policy of role {:?} error occured", role_arg))
+                    .into()
+            })
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, RoleAccessPolicy>
+    for RoleAccessPoliciesRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, _user_id: UserId, scope: &Scope, _obj: Option<&RoleAccessPolicy>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => false,
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! Repo for role_requests table, grants of sensitive roles waiting for approval of a second admin.
+
+use std::time::SystemTime;
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use uuid::Uuid;
+
+use stq_types::UserId;
+
+use repos::legacy_acl::*;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::*;
+use models::{NewRoleRequest, RoleRequest};
+use schema::role_requests::dsl::*;
+
+/// RoleRequests repository, responsible for handling grants of sensitive roles
+pub trait RoleRequestsRepo {
+    /// Creates request to grant the role
+    fn create(&self, payload: NewRoleRequest) -> RepoResult<RoleRequest>;
+
+    /// Find the request locking it until the end of current transaction
+    fn find_for_update(&self, id: Uuid) -> RepoResult<Option<RoleRequest>>;
+
+    /// Returns requests not approved yet, oldest first
+    fn list_pending(&self) -> RepoResult<Vec<RoleRequest>>;
+
+    /// Marks the request approved by the user
+    fn approve(&self, id: Uuid, approved_by: UserId) -> RepoResult<RoleRequest>;
+}
+
+/// Implementation of RoleRequests trait
+pub struct RoleRequestsRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, RoleRequest>>,
+    pub db_conn: &'a T,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> RoleRequestsRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, RoleRequest>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> RoleRequestsRepo
+    for RoleRequestsRepoImpl<'a, T>
+{
+    /// Creates request to grant the role
+    fn create(&self, payload: NewRoleRequest) -> RepoResult<RoleRequest> {
+        let query = diesel::insert_into(role_requests).values(&payload);
+        query
+            .get_result::<RoleRequest>(self.db_conn)
+            .map_err(From::from)
+            .and_then(|request| {
+                acl::check(&*self.acl, Resource::RoleRequests, Action::Create, self, Some(&request))?;
+                Ok(request)
+            })
+            .map_err(|e: FailureError| e.context(format!("Create role request {:?} error occured", payload)).into())
+    }
+
+    /// Find the request locking it until the end of current transaction
+    fn find_for_update(&self, id_arg: Uuid) -> RepoResult<Option<RoleRequest>> {
+        role_requests
+            .find(id_arg)
+            .for_update()
+            .get_result::<RoleRequest>(self.db_conn)
+            .optional()
+            .map_err(From::from)
+            .and_then(|request: Option<RoleRequest>| {
+                if let Some(ref request) = request {
+                    acl::check(&*self.acl, Resource::RoleRequests, Action::Update, self, Some(request))?;
+                }
+                Ok(request)
+            })
+            .map_err(|e: FailureError| e.context(format!("Lock role request {} error occured", id_arg)).into())
+    }
+
+    /// Returns requests not approved yet, oldest first
+    fn list_pending(&self) -> RepoResult<Vec<RoleRequest>> {
+        acl::check(&*self.acl, Resource::RoleRequests, Action::Read, self, None)?;
+
+        let query = role_requests.filter(approved_by.is_null()).order(created_at);
+        query
+            .get_results::<RoleRequest>(self.db_conn)
+            .map_err(|e| e.context("List pending role requests error occured").into())
+    }
+
+    /// Marks the request approved by the user
+    fn approve(&self, id_arg: Uuid, approved_by_arg: UserId) -> RepoResult<RoleRequest> {
+        acl::check(&*self.acl, Resource::RoleRequests, Action::Update, self, None)?;
+
+        let query = diesel::update(role_requests.find(id_arg)).set((approved_by.eq(approved_by_arg), approved_at.eq(SystemTime::now())));
+        query
+            .get_result::<RoleRequest>(self.db_conn)
+            .map_err(|e| e.context(format!("Approve role request {} error occured", id_arg)).into())
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, RoleRequest>
+    for RoleRequestsRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, user_id_arg: UserId, scope: &Scope, obj: Option<&RoleRequest>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => {
+                if let Some(request) = obj {
+                    request.user_id == user_id_arg
+                } else {
+                    false
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,49 @@
+//! Repo for diesel migrations table, versions of migrations applied to the database.
+//! Migrations are applied by deploy tooling, the service only reads their versions.
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::dsl::max;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use failure::Fail;
+
+use super::types::RepoResult;
+
+table! {
+    __diesel_schema_migrations (version) {
+        version -> VarChar,
+        run_on -> Timestamp,
+    }
+}
+
+use self::__diesel_schema_migrations::dsl::*;
+
+/// SchemaMigrations repository, responsible for reading the schema version
+pub struct SchemaMigrationsRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub db_conn: &'a T,
+}
+
+pub trait SchemaMigrationsRepo {
+    /// Version of the last applied migration, `None` if no migrations were applied
+    fn current_version(&self) -> RepoResult<Option<String>>;
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> SchemaMigrationsRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T) -> Self {
+        Self { db_conn }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> SchemaMigrationsRepo
+    for SchemaMigrationsRepoImpl<'a, T>
+{
+    /// Version of the last applied migration, `None` if no migrations were applied
+    fn current_version(&self) -> RepoResult<Option<String>> {
+        let query = __diesel_schema_migrations.select(max(version));
+        query
+            .get_result::<Option<String>>(self.db_conn)
+            .map_err(|e| e.context("Read schema version error occured").into())
+    }
+}
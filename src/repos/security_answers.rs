@@ -0,0 +1,98 @@
+//! Repo for security_answers table, hashed answers of users to security questions
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use failure::Error as FailureError;
+
+use stq_types::UserId;
+
+use repos::legacy_acl::*;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::*;
+use models::{NewSecurityAnswer, SecurityAnswer};
+use schema::security_answers::dsl::*;
+
+/// SecurityAnswers repository for handling answers to security questions
+pub trait SecurityAnswersRepo {
+    /// Returns answers of a specific user
+    fn list_for_user(&self, user_id: UserId) -> RepoResult<Vec<SecurityAnswer>>;
+
+    /// Replaces all the answers of the user with the new ones
+    fn replace(&self, user_id: UserId, answers: Vec<NewSecurityAnswer>) -> RepoResult<Vec<SecurityAnswer>>;
+}
+
+/// Implementation of SecurityAnswers trait
+pub struct SecurityAnswersRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, SecurityAnswer>>,
+    pub db_conn: &'a T,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> SecurityAnswersRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, SecurityAnswer>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> SecurityAnswersRepo
+    for SecurityAnswersRepoImpl<'a, T>
+{
+    /// Returns answers of a specific user
+    fn list_for_user(&self, user_id_arg: UserId) -> RepoResult<Vec<SecurityAnswer>> {
+        let query = security_answers.filter(user_id.eq(user_id_arg)).order(question_id);
+        query
+            .get_results::<SecurityAnswer>(self.db_conn)
+            .map_err(From::from)
+            .and_then(|answers: Vec<SecurityAnswer>| {
+                for answer in &answers {
+                    acl::check(&*self.acl, Resource::SecurityAnswers, Action::Read, self, Some(answer))?;
+                }
+                Ok(answers)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("List security answers of user {} error occured.", user_id_arg))
+                    .into()
+            })
+    }
+
+    /// Replaces all the answers of the user with the new ones
+    fn replace(&self, user_id_arg: UserId, answers: Vec<NewSecurityAnswer>) -> RepoResult<Vec<SecurityAnswer>> {
+        self.db_conn
+            .transaction::<Vec<SecurityAnswer>, FailureError, _>(|| {
+                diesel::delete(security_answers.filter(user_id.eq(user_id_arg))).execute(self.db_conn)?;
+                let answers = diesel::insert_into(security_answers)
+                    .values(&answers)
+                    .get_results::<SecurityAnswer>(self.db_conn)?;
+                for answer in &answers {
+                    acl::check(&*self.acl, Resource::SecurityAnswers, Action::Create, self, Some(answer))?;
+                }
+                Ok(answers)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Replace security answers of user {} error occured", user_id_arg))
+                    .into()
+            })
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, SecurityAnswer>
+    for SecurityAnswersRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, user_id_arg: UserId, scope: &Scope, obj: Option<&SecurityAnswer>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => {
+                if let Some(answer) = obj {
+                    answer.user_id == user_id_arg
+                } else {
+                    false
+                }
+            }
+        }
+    }
+}
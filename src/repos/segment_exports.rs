@@ -0,0 +1,81 @@
+//! Repo for segment_exports table, exports of users to CSV for marketing.
+//! Access to exports is checked on their jobs.
+
+use std::time::SystemTime;
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use failure::Fail;
+use uuid::Uuid;
+
+use super::types::RepoResult;
+use models::{NewSegmentExport, SegmentExport};
+use schema::segment_exports::dsl::*;
+
+/// SegmentExports repository, responsible for handling segment exports
+pub trait SegmentExportsRepo {
+    /// Creates export of the job
+    fn create(&self, payload: NewSegmentExport) -> RepoResult<SegmentExport>;
+
+    /// Find specific export by job ID
+    fn find(&self, id_arg: Uuid) -> RepoResult<Option<SegmentExport>>;
+
+    /// Saves exported CSV
+    fn set_csv(&self, id_arg: Uuid, csv_arg: String) -> RepoResult<SegmentExport>;
+
+    /// Drops CSVs saved before `saved_before`, returns number of dropped CSVs
+    fn purge_csv(&self, saved_before: SystemTime) -> RepoResult<usize>;
+}
+
+/// Implementation of SegmentExports trait
+pub struct SegmentExportsRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub db_conn: &'a T,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> SegmentExportsRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T) -> Self {
+        Self { db_conn }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> SegmentExportsRepo
+    for SegmentExportsRepoImpl<'a, T>
+{
+    /// Creates export of the job
+    fn create(&self, payload: NewSegmentExport) -> RepoResult<SegmentExport> {
+        let query = diesel::insert_into(segment_exports).values(&payload);
+        query
+            .get_result::<SegmentExport>(self.db_conn)
+            .map_err(|e| e.context(format!("Create segment export {:?} error occured", payload)).into())
+    }
+
+    /// Find specific export by job ID
+    fn find(&self, id_arg: Uuid) -> RepoResult<Option<SegmentExport>> {
+        segment_exports
+            .find(id_arg)
+            .get_result::<SegmentExport>(self.db_conn)
+            .optional()
+            .map_err(|e| e.context(format!("Find segment export {} error occured", id_arg)).into())
+    }
+
+    /// Saves exported CSV
+    fn set_csv(&self, id_arg: Uuid, csv_arg: String) -> RepoResult<SegmentExport> {
+        let query = diesel::update(segment_exports.find(id_arg)).set(csv.eq(csv_arg));
+        query
+            .get_result::<SegmentExport>(self.db_conn)
+            .map_err(|e| e.context(format!("Save CSV of segment export {} error occured", id_arg)).into())
+    }
+
+    /// Drops CSVs saved before `saved_before`, returns number of dropped CSVs
+    fn purge_csv(&self, saved_before: SystemTime) -> RepoResult<usize> {
+        let filtered = segment_exports.filter(csv.is_not_null()).filter(updated_at.lt(saved_before));
+        let query = diesel::update(filtered).set(csv.eq(None::<String>));
+        query
+            .execute(self.db_conn)
+            .map_err(|e| e.context("Purge CSVs of segment exports error occured").into())
+    }
+}
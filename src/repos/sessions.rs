@@ -0,0 +1,133 @@
+//! Sessions repo, presents CRUD operations with db for server-side sessions.
+//! Sessions are resolved by the gateway before the user is known, so there is no ACL check.
+use std::time::SystemTime;
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::sql_types::Integer;
+use diesel::Connection;
+use failure::Fail;
+use uuid::Uuid;
+
+use super::types::RepoResult;
+use stq_types::UserId;
+
+use models::{NewSession, Session};
+use schema::sessions::dsl::*;
+
+/// Sessions repository, responsible for handling server-side sessions
+pub struct SessionsRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub db_conn: &'a T,
+}
+
+pub trait SessionsRepo {
+    /// Create session
+    fn create(&self, payload: NewSession) -> RepoResult<Session>;
+
+    /// Find by session id hash
+    fn find_by_hash(&self, session_hash_arg: String) -> RepoResult<Option<Session>>;
+
+    /// Saves last time the session was used
+    fn touch(&self, session_id: Uuid, last_seen_at_arg: SystemTime) -> RepoResult<()>;
+
+    /// Delete by session id hash, returns `None` if there was no such session
+    fn delete_by_hash(&self, session_hash_arg: String) -> RepoResult<Option<Session>>;
+
+    /// Sessions of the user not expired by `now`, oldest first. Idle ones are included
+    fn list_active(&self, user_id: UserId, now: SystemTime) -> RepoResult<Vec<Session>>;
+
+    /// Delete session
+    fn delete(&self, session_id: Uuid) -> RepoResult<()>;
+
+    /// Locks sessions and refresh token families of the user until the end of current transaction,
+    /// so that concurrently started sessions are checked against the session limit one by one
+    fn lock_user(&self, user_id: UserId) -> RepoResult<()>;
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> SessionsRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T) -> Self {
+        Self { db_conn }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> SessionsRepo for SessionsRepoImpl<'a, T> {
+    /// Create session
+    fn create(&self, payload: NewSession) -> RepoResult<Session> {
+        let query = diesel::insert_into(sessions).values(&payload);
+
+        query.get_result(self.db_conn).map_err(|e| {
+            e.context(format!(
+                "Create session of user {} for client {} error occured",
+                payload.user_id, payload.client_id
+            ))
+            .into()
+        })
+    }
+
+    /// Find by session id hash
+    fn find_by_hash(&self, session_hash_arg: String) -> RepoResult<Option<Session>> {
+        let query = sessions.filter(session_hash.eq(session_hash_arg));
+
+        query
+            .get_result(self.db_conn)
+            .optional()
+            .map_err(|e| e.context("Find session by hash error occured").into())
+    }
+
+    /// Saves last time the session was used
+    fn touch(&self, session_id: Uuid, last_seen_at_arg: SystemTime) -> RepoResult<()> {
+        let query = diesel::update(sessions.find(session_id)).set(last_seen_at.eq(last_seen_at_arg));
+
+        query
+            .execute(self.db_conn)
+            .map(|_| ())
+            .map_err(|e| e.context(format!("Touch session {} error occured", session_id)).into())
+    }
+
+    /// Delete by session id hash, returns `None` if there was no such session
+    fn delete_by_hash(&self, session_hash_arg: String) -> RepoResult<Option<Session>> {
+        let filtered = sessions.filter(session_hash.eq(session_hash_arg));
+        let query = diesel::delete(filtered);
+
+        query
+            .get_result(self.db_conn)
+            .optional()
+            .map_err(|e| e.context("Delete session by hash error occured").into())
+    }
+
+    /// Sessions of the user not expired by `now`, oldest first. Idle ones are included
+    fn list_active(&self, user_id_arg: UserId, now: SystemTime) -> RepoResult<Vec<Session>> {
+        let query = sessions
+            .filter(user_id.eq(user_id_arg))
+            .filter(expires_at.gt(now))
+            .order(created_at.asc());
+
+        query.get_results(self.db_conn).map_err(|e| {
+            e.context(format!("List active sessions of user {} error occured", user_id_arg))
+                .into()
+        })
+    }
+
+    /// Delete session
+    fn delete(&self, session_id: Uuid) -> RepoResult<()> {
+        let query = diesel::delete(sessions.find(session_id));
+
+        query
+            .execute(self.db_conn)
+            .map(|_| ())
+            .map_err(|e| e.context(format!("Delete session {} error occured", session_id)).into())
+    }
+
+    /// Locks sessions and refresh token families of the user until the end of current transaction,
+    /// so that concurrently started sessions are checked against the session limit one by one
+    fn lock_user(&self, user_id_arg: UserId) -> RepoResult<()> {
+        diesel::sql_query("SELECT pg_advisory_xact_lock(hashtext('sessions'), $1)")
+            .bind::<Integer, _>(user_id_arg.0)
+            .execute(self.db_conn)
+            .map(|_| ())
+            .map_err(|e| e.context(format!("Lock sessions of user {} error occured", user_id_arg)).into())
+    }
+}
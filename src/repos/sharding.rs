@@ -0,0 +1,171 @@
+//! Sharding of user data between several Postgres instances. User id is hashed into one of
+//! `virtual_buckets`, each shard owns a contiguous range of buckets, so shards can be split
+//! by moving bucket ranges without rehashing all users.
+//!
+//! Shard 0 is primary: it holds data that is not owned by users (clients, device codes)
+//! and allocates user ids, so that ids are unique across shards.
+//!
+//! Multi-shard setup is kept behind `sharding.enabled` until the rest of the service is
+//! shard aware: auth flows resolve identities and ACL reads `user_roles` on the primary
+//! shard only, foreign keys to users can not span shards, e-mail uniqueness is checked
+//! per shard and existing users are not moved out of the primary shard.
+
+use std::sync::Arc;
+
+use failure::Error as FailureError;
+use r2d2::{ManageConnection, Pool};
+
+use stq_types::UserId;
+
+/// Maps user ids to shard indices
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShardMap {
+    virtual_buckets: u32,
+    /// Inclusive bucket range of every shard, in shard order
+    ranges: Vec<(u32, u32)>,
+}
+
+impl ShardMap {
+    /// Creates shard map, bucket ranges must cover all virtual buckets exactly once
+    pub fn new(virtual_buckets: u32, ranges: Vec<(u32, u32)>) -> Result<Self, FailureError> {
+        if virtual_buckets == 0 || ranges.is_empty() {
+            return Err(format_err!("Shard map must have at least one bucket and one shard"));
+        }
+
+        let mut sorted = ranges.clone();
+        sorted.sort();
+        let mut next_bucket = 0;
+        for &(first, last) in &sorted {
+            if first != next_bucket || last < first {
+                return Err(format_err!("Shard map has a gap or overlap at bucket {}", next_bucket));
+            }
+            next_bucket = last + 1;
+        }
+        if next_bucket != virtual_buckets {
+            return Err(format_err!("Shard map covers {} buckets out of {}", next_bucket, virtual_buckets));
+        }
+
+        Ok(Self { virtual_buckets, ranges })
+    }
+
+    /// Shard map with everything on the primary shard
+    pub fn single() -> Self {
+        Self {
+            virtual_buckets: 1,
+            ranges: vec![(0, 0)],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ranges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn bucket(&self, user_id: UserId) -> u32 {
+        mix(user_id.0 as u32) % self.virtual_buckets
+    }
+
+    pub fn shard_index(&self, user_id: UserId) -> usize {
+        let bucket = self.bucket(user_id);
+        self.ranges
+            .iter()
+            .position(|&(first, last)| first <= bucket && bucket <= last)
+            .unwrap_or(0)
+    }
+}
+
+/// Finalizer of MurmurHash3, spreads sequential ids evenly between buckets.
+/// Must never change, otherwise users would be routed to wrong shards.
+fn mix(mut h: u32) -> u32 {
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2_ae35);
+    h ^= h >> 16;
+    h
+}
+
+/// Connection pools of all shards
+pub struct ShardedPool<M: ManageConnection> {
+    map: Arc<ShardMap>,
+    shards: Vec<Pool<M>>,
+}
+
+impl<M: ManageConnection> ShardedPool<M> {
+    pub fn new(map: ShardMap, shards: Vec<Pool<M>>) -> Self {
+        assert_eq!(map.len(), shards.len(), "Every shard in shard map must have a pool");
+        Self {
+            map: Arc::new(map),
+            shards,
+        }
+    }
+
+    /// Pool without sharding, all the data is on the primary shard
+    pub fn single(pool: Pool<M>) -> Self {
+        Self::new(ShardMap::single(), vec![pool])
+    }
+
+    pub fn is_sharded(&self) -> bool {
+        self.shards.len() > 1
+    }
+
+    pub fn primary(&self) -> &Pool<M> {
+        &self.shards[0]
+    }
+
+    pub fn for_user(&self, user_id: UserId) -> &Pool<M> {
+        &self.shards[self.shard_index(user_id)]
+    }
+
+    /// Index of the shard owning user data in `shards`
+    pub fn shard_index(&self, user_id: UserId) -> usize {
+        self.map.shard_index(user_id)
+    }
+
+    pub fn shards(&self) -> &[Pool<M>] {
+        &self.shards
+    }
+}
+
+impl<M: ManageConnection> Clone for ShardedPool<M> {
+    fn clone(&self) -> Self {
+        Self {
+            map: self.map.clone(),
+            shards: self.shards.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shard_map_validation() {
+        assert_eq!(ShardMap::new(4, vec![(0, 1), (2, 3)]).is_ok(), true);
+        assert_eq!(ShardMap::new(4, vec![(2, 3), (0, 1)]).is_ok(), true);
+        assert_eq!(ShardMap::new(4, vec![(0, 1), (3, 3)]).is_err(), true);
+        assert_eq!(ShardMap::new(4, vec![(0, 2), (2, 3)]).is_err(), true);
+        assert_eq!(ShardMap::new(4, vec![(0, 1)]).is_err(), true);
+        assert_eq!(ShardMap::new(0, vec![]).is_err(), true);
+    }
+
+    #[test]
+    fn test_shard_index() {
+        let map = ShardMap::new(1024, vec![(0, 511), (512, 1023)]).unwrap();
+        let mut counts = [0; 2];
+        for id in 1..1001 {
+            let user_id = UserId(id);
+            let shard = map.shard_index(user_id);
+            assert_eq!(shard, map.shard_index(user_id));
+            counts[shard] += 1;
+        }
+        // sequential ids are spread between shards
+        assert_eq!(counts[0] > 400 && counts[1] > 400, true);
+
+        assert_eq!(ShardMap::single().shard_index(UserId(42)), 0);
+    }
+}
@@ -1,11 +1,75 @@
 use diesel::pg::PgConnection;
 use diesel::r2d2::ConnectionManager;
 use failure::Error as FailureError;
+use failure::Fail;
 use futures::future::Future;
 use r2d2;
 
+use errors::Error;
+
 /// Repos layer Future
 pub type RepoFuture<T> = Box<Future<Item = T, Error = FailureError>>;
 pub type RepoResult<T> = Result<T, FailureError>;
 pub type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
 pub type DbConnection = r2d2::PooledConnection<ConnectionManager<PgConnection>>;
+
+/// Max sizes of results returned by repos, so that a request can not load a whole table
+#[derive(Clone, Copy, Debug)]
+pub struct RepoLimits {
+    pub max_count: i64,
+}
+
+impl Default for RepoLimits {
+    fn default() -> Self {
+        Self { max_count: 1000 }
+    }
+}
+
+impl RepoLimits {
+    /// Returns number of results to fetch, non-positive `count` means as many as allowed.
+    /// Before the limits non-positive `count` meant all the results, callers relying on it
+    /// now get at most `max_count` results and have to page through the rest.
+    pub fn count(&self, count: i64) -> RepoResult<i64> {
+        if count > self.max_count {
+            Err(Error::LimitExceeded(self.max_count)
+                .context(format!("Requested {} results", count))
+                .into())
+        } else if count <= 0 {
+            Ok(self.max_count)
+        } else {
+            Ok(count)
+        }
+    }
+
+    /// Returns number of results to fetch, `count` above the limit is cut to `max_count` instead of failing
+    pub fn clamp(&self, count: i64) -> i64 {
+        if count <= 0 || count > self.max_count {
+            self.max_count
+        } else {
+            count
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repo_limits_count() {
+        let limits = RepoLimits { max_count: 100 };
+        assert_eq!(limits.count(10).unwrap(), 10);
+        assert_eq!(limits.count(100).unwrap(), 100);
+        assert_eq!(limits.count(0).unwrap(), 100);
+        assert_eq!(limits.count(-1).unwrap(), 100);
+        assert_eq!(limits.count(100_000_000).is_err(), true);
+    }
+
+    #[test]
+    fn test_repo_limits_clamp() {
+        let limits = RepoLimits { max_count: 100 };
+        assert_eq!(limits.clamp(10), 10);
+        assert_eq!(limits.clamp(0), 100);
+        assert_eq!(limits.clamp(100_000_000), 100);
+    }
+}
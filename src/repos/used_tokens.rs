@@ -0,0 +1,55 @@
+//! Used tokens repo, makes signed tokens single-use. Tokens are checked by signature, so only
+//! ids of the applied ones are stored, until they expire.
+use std::time::SystemTime;
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use failure::Fail;
+
+use super::types::RepoResult;
+use models::NewUsedToken;
+use schema::used_tokens::dsl::*;
+
+/// Used tokens repository
+pub struct UsedTokensRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub db_conn: &'a T,
+}
+
+pub trait UsedTokensRepo {
+    /// Records the token as used, false if it was used already
+    fn mark_used(&self, payload: NewUsedToken) -> RepoResult<bool>;
+
+    /// Deletes ids of tokens expired before `now`, they can't be applied anyway
+    fn delete_expired(&self, now: SystemTime) -> RepoResult<usize>;
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> UsedTokensRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T) -> Self {
+        Self { db_conn }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> UsedTokensRepo for UsedTokensRepoImpl<'a, T> {
+    /// Records the token as used, false if it was used already
+    fn mark_used(&self, payload: NewUsedToken) -> RepoResult<bool> {
+        let query = diesel::insert_into(used_tokens).values(&payload).on_conflict_do_nothing();
+
+        query
+            .execute(self.db_conn)
+            .map(|inserted| inserted > 0)
+            .map_err(|e| e.context(format!("Mark token {} as used error occured", payload.id)).into())
+    }
+
+    /// Deletes ids of tokens expired before `now`, they can't be applied anyway
+    fn delete_expired(&self, now: SystemTime) -> RepoResult<usize> {
+        let query = diesel::delete(used_tokens.filter(expires_at.lt(now)));
+
+        query
+            .execute(self.db_conn)
+            .map_err(|e| e.context("Delete expired used tokens error occured").into())
+    }
+}
@@ -10,9 +10,10 @@ use diesel::query_dsl::RunQueryDsl;
 use diesel::Connection;
 use failure::Error as FailureError;
 use std::sync::Arc;
-use stq_cache::cache::Cache;
+use std::time::SystemTime;
 use stq_types::{RoleId, UserId, UsersRole};
 
+use cache::Cache;
 use repos::legacy_acl::*;
 
 use super::acl;
@@ -24,9 +25,15 @@ use schema::user_roles::dsl::*;
 
 /// UserRoles repository for handling UserRoles
 pub trait UserRolesRepo {
-    /// Returns list of user_roles for a specific user
+    /// Returns list of user_roles for a specific user, expired roles are skipped
     fn list_for_user(&self, user_id: UserId) -> RepoResult<Vec<UsersRole>>;
 
+    /// Returns ids of users with the role, at most `count` of them
+    fn list_users_with_role(&self, role: UsersRole, count: i64) -> RepoResult<Vec<UserId>>;
+
+    /// Returns all user roles, for exports of auth data
+    fn list_all(&self) -> RepoResult<Vec<UserRole>>;
+
     /// Create a new user role
     fn create(&self, payload: NewUserRole) -> RepoResult<UserRole>;
 
@@ -38,6 +45,12 @@ pub trait UserRolesRepo {
 
     /// Delete user roles by user id
     fn delete_by_user_id(&self, user_id_arg: UserId) -> RepoResult<Vec<UserRole>>;
+
+    /// Deletes roles expired by `now`
+    fn delete_expired(&self, now: SystemTime) -> RepoResult<Vec<UserRole>>;
+
+    /// Drops cached roles of a user, so that they are read from db on the next request
+    fn invalidate_cache(&self, user_id_arg: UserId);
 }
 
 /// Implementation of UserRoles trait
@@ -74,13 +87,15 @@ where
     C: Cache<Vec<UsersRole>>,
     T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
 {
-    /// Returns list of user_roles for a specific user
+    /// Returns list of user_roles for a specific user, expired roles are skipped
     fn list_for_user(&self, user_id_value: UserId) -> RepoResult<Vec<UsersRole>> {
         debug!("list user roles for id {}.", user_id_value);
         if let Some(roles) = self.cached_roles.get(user_id_value) {
             Ok(roles)
         } else {
-            let query = user_roles.filter(user_id.eq(user_id_value));
+            let query = user_roles
+                .filter(user_id.eq(user_id_value))
+                .filter(expires_at.is_null().or(expires_at.gt(SystemTime::now())));
             query
                 .get_results::<UserRole>(self.db_conn)
                 .map_err(From::from)
@@ -88,14 +103,16 @@ where
                     for user_role_arg in &user_roles_arg {
                         acl::check(&*self.acl, Resource::UserRoles, Action::Read, self, Some(&user_role_arg))?;
                     }
+                    // roles of users with time-boxed roles are not cached, so that they are not used after expiry
+                    let time_boxed = user_roles_arg.iter().any(|user_role| user_role.expires_at.is_some());
                     let roles = user_roles_arg
                         .into_iter()
                         .map(|user_role| user_role.name)
                         .collect::<Vec<UsersRole>>();
-                    Ok(roles)
+                    Ok((roles, time_boxed))
                 })
-                .and_then(|roles| {
-                    if !roles.is_empty() {
+                .and_then(|(roles, time_boxed)| {
+                    if !roles.is_empty() && !time_boxed {
                         self.cached_roles.set(user_id_value, roles.clone());
                     }
                     Ok(roles)
@@ -107,6 +124,40 @@ where
         }
     }
 
+    /// Returns ids of users with the role, at most `count` of them
+    fn list_users_with_role(&self, role: UsersRole, count: i64) -> RepoResult<Vec<UserId>> {
+        let query = user_roles
+            .filter(name.eq(role))
+            .filter(expires_at.is_null().or(expires_at.gt(SystemTime::now())))
+            .order(user_id)
+            .limit(count);
+        query
+            .get_results::<UserRole>(self.db_conn)
+            .map_err(From::from)
+            .and_then(|user_roles_arg: Vec<UserRole>| {
+                for user_role_arg in &user_roles_arg {
+                    acl::check(&*self.acl, Resource::UserRoles, Action::Read, self, Some(&user_role_arg))?;
+                }
+                Ok(user_roles_arg.into_iter().map(|user_role| user_role.user_id).collect())
+            })
+            .map_err(|e: FailureError| e.context(format!("List users with role {:?} error occured.", role)).into())
+    }
+
+    /// Returns all user roles, for exports of auth data
+    fn list_all(&self) -> RepoResult<Vec<UserRole>> {
+        let query = user_roles.order((user_id, created_at));
+        query
+            .get_results::<UserRole>(self.db_conn)
+            .map_err(From::from)
+            .and_then(|user_roles_arg: Vec<UserRole>| {
+                for user_role_arg in &user_roles_arg {
+                    acl::check(&*self.acl, Resource::UserRoles, Action::Read, self, Some(&user_role_arg))?;
+                }
+                Ok(user_roles_arg)
+            })
+            .map_err(|e: FailureError| e.context("List all user roles error occured.").into())
+    }
+
     /// Create a new user role
     fn create(&self, payload: NewUserRole) -> RepoResult<UserRole> {
         self.cached_roles.remove(payload.user_id);
@@ -156,6 +207,28 @@ where
             .map_err(|e: FailureError| e.context(format!("Delete user {} roles error occured", user_id_arg)).into())
     }
 
+    /// Deletes roles expired by `now`
+    fn delete_expired(&self, now: SystemTime) -> RepoResult<Vec<UserRole>> {
+        let filtered = user_roles.filter(expires_at.le(now));
+        let query = diesel::delete(filtered);
+        query
+            .get_results(self.db_conn)
+            .map_err(From::from)
+            .and_then(|user_roles_arg: Vec<UserRole>| {
+                for user_role_arg in &user_roles_arg {
+                    acl::check(&*self.acl, Resource::UserRoles, Action::Delete, self, Some(&user_role_arg))?;
+                }
+                Ok(user_roles_arg)
+            })
+            .map(|user_roles_arg| {
+                for user_role_arg in &user_roles_arg {
+                    self.cached_roles.remove(user_role_arg.user_id);
+                }
+                user_roles_arg
+            })
+            .map_err(|e: FailureError| e.context("Delete expired user roles error occured").into())
+    }
+
     /// Delete user roles by user id and name
     fn delete_user_role(&self, user_id_arg: UserId, name_arg: UsersRole) -> RepoResult<UserRole> {
         self.cached_roles.remove(user_id_arg);
@@ -173,6 +246,11 @@ where
                     .into()
             })
     }
+
+    /// Drops cached roles of a user, so that they are read from db on the next request
+    fn invalidate_cache(&self, user_id_arg: UserId) {
+        self.cached_roles.remove(user_id_arg);
+    }
 }
 
 impl<'a, C, T> CheckScope<Scope, UserRole> for UserRolesRepoImpl<'a, C, T>
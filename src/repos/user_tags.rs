@@ -0,0 +1,117 @@
+//! Repo for user_tags table. Tags group users into marketing segments,
+//! i.e. this table is for user has-many tags relationship
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use failure::Error as FailureError;
+
+use stq_types::UserId;
+
+use repos::legacy_acl::*;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::*;
+use models::UserTag;
+use schema::user_tags::dsl::*;
+
+/// UserTags repository for handling UserTags
+pub trait UserTagsRepo {
+    /// Returns list of tags of a specific user
+    fn list_for_user(&self, user_id: UserId) -> RepoResult<Vec<UserTag>>;
+
+    /// Tags a user, tagging with the same tag twice is a no-op
+    fn add(&self, user_id: UserId, tag: String) -> RepoResult<UserTag>;
+
+    /// Removes tag of a user, returns `None` if the user was not tagged
+    fn remove(&self, user_id: UserId, tag: String) -> RepoResult<Option<UserTag>>;
+}
+
+/// Implementation of UserTags trait
+pub struct UserTagsRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, UserTag>>,
+    pub db_conn: &'a T,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> UserTagsRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, UserTag>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> UserTagsRepo for UserTagsRepoImpl<'a, T> {
+    /// Returns list of tags of a specific user
+    fn list_for_user(&self, user_id_arg: UserId) -> RepoResult<Vec<UserTag>> {
+        let query = user_tags.filter(user_id.eq(user_id_arg)).order(tag);
+        query
+            .get_results::<UserTag>(self.db_conn)
+            .map_err(From::from)
+            .and_then(|user_tags_arg: Vec<UserTag>| {
+                for user_tag_arg in &user_tags_arg {
+                    acl::check(&*self.acl, Resource::UserTags, Action::Read, self, Some(&user_tag_arg))?;
+                }
+                Ok(user_tags_arg)
+            })
+            .map_err(|e: FailureError| e.context(format!("List tags for user {} error occured.", user_id_arg)).into())
+    }
+
+    /// Tags a user, tagging with the same tag twice is a no-op
+    fn add(&self, user_id_arg: UserId, tag_arg: String) -> RepoResult<UserTag> {
+        let query = diesel::insert_into(user_tags)
+            .values((user_id.eq(user_id_arg), tag.eq(&tag_arg)))
+            .on_conflict_do_nothing();
+        query
+            .execute(self.db_conn)
+            .and_then(|_| user_tags.find((user_id_arg, &tag_arg)).get_result::<UserTag>(self.db_conn))
+            .map_err(From::from)
+            .and_then(|user_tag_arg: UserTag| {
+                acl::check(&*self.acl, Resource::UserTags, Action::Create, self, Some(&user_tag_arg))?;
+                Ok(user_tag_arg)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Add tag {} to user {} error occured", tag_arg, user_id_arg))
+                    .into()
+            })
+    }
+
+    /// Removes tag of a user, returns `None` if the user was not tagged
+    fn remove(&self, user_id_arg: UserId, tag_arg: String) -> RepoResult<Option<UserTag>> {
+        let filtered = user_tags.filter(user_id.eq(user_id_arg)).filter(tag.eq(&tag_arg));
+        let query = diesel::delete(filtered);
+        query
+            .get_result::<UserTag>(self.db_conn)
+            .optional()
+            .map_err(From::from)
+            .and_then(|user_tag_arg: Option<UserTag>| {
+                if let Some(ref user_tag_arg) = user_tag_arg {
+                    acl::check(&*self.acl, Resource::UserTags, Action::Delete, self, Some(user_tag_arg))?;
+                }
+                Ok(user_tag_arg)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Remove tag {} of user {} error occured", tag_arg, user_id_arg))
+                    .into()
+            })
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, UserTag>
+    for UserTagsRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, user_id_arg: UserId, scope: &Scope, obj: Option<&UserTag>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => {
+                if let Some(user_tag) = obj {
+                    user_tag.user_id == user_id_arg
+                } else {
+                    false
+                }
+            }
+        }
+    }
+}
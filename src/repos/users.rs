@@ -1,4 +1,8 @@
-//! Users repo, presents CRUD operations with db for users
+//! Users repo, presents CRUD operations with db for users.
+//!
+//! If PII encryption is configured, emails, phones, names and birthdates are stored sealed by the
+//! data key of the user, the data key is shredded with the user. Columns of emails, phones and
+//! names of sealed users hold blind indexes, so sealed users are found by whole values only.
 use std::time::SystemTime;
 
 use diesel;
@@ -8,7 +12,7 @@ use diesel::pg::Pg;
 use diesel::prelude::*;
 use diesel::query_dsl::RunQueryDsl;
 use diesel::select;
-use diesel::sql_types::{Bool, VarChar};
+use diesel::sql_types::{Bool, Integer, VarChar};
 use diesel::{Connection, PgTextExpressionMethods};
 use failure::Error as FailureError;
 use failure::Fail;
@@ -16,16 +20,21 @@ use failure::Fail;
 use stq_types::UserId;
 
 use super::acl;
-use super::types::RepoResult;
+use super::pii_cipher::PiiCipher;
+use super::types::{RepoLimits, RepoResult};
 use models::authorization::*;
-use models::{NewUser, UpdateUser, User, UserSearchResults, UsersSearchTerms};
+use models::{NewUser, SealedPiiColumns, UpdateUser, User, UserPii, UserSearchResults, UsersSearchTerms};
 use repos::legacy_acl::*;
+use schema::user_tags;
 use schema::users::dsl::*;
+use schema::users::BoxedQuery;
 
 /// Users repository, responsible for handling users
 pub struct UsersRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
     pub db_conn: &'a T,
     pub acl: Box<Acl<Resource, Action, Scope, FailureError, User>>,
+    pub limits: RepoLimits,
+    pub pii_cipher: Option<PiiCipher>,
 }
 
 pub trait UsersRepo {
@@ -41,12 +50,18 @@ pub trait UsersRepo {
     /// Find specific user by email
     fn find_by_email(&self, email_arg: String) -> RepoResult<Option<User>>;
 
-    /// Returns list of users, limited by `from` and `count` parameters
+    /// Find user by phone in E.164 format, verified phones take precedence
+    fn find_by_phone(&self, phone_arg: String) -> RepoResult<Option<User>>;
+
+    /// Returns list of users, limited by `from` and `count` parameters, see `RepoLimits::count`
     fn list(&self, from: UserId, count: i64) -> RepoResult<Vec<User>>;
 
     /// Creates new user
     fn create(&self, payload: NewUser) -> RepoResult<User>;
 
+    /// Allocates id for a new user, so that the user can be routed to a shard before creation
+    fn next_id(&self) -> RepoResult<UserId>;
+
     /// Updates specific user
     fn update(&self, user_id: UserId, payload: UpdateUser) -> RepoResult<User>;
 
@@ -56,13 +71,19 @@ pub trait UsersRepo {
     /// Set block status of specific user
     fn set_block_status(&self, user_id: UserId, is_blocked_arg: bool) -> RepoResult<User>;
 
+    /// Marks the user as waiting for deletion, or unmarks it once the deletion is vetoed
+    fn set_pending_deletion(&self, user_id: UserId, pending_deletion_arg: bool) -> RepoResult<User>;
+
     /// Deletes specific user
     fn delete_by_saga_id(&self, saga_id_arg: String) -> RepoResult<User>;
 
-    /// Delete user by id
+    /// Delete user by id, the data key of the user is shredded, so that copies of sealed PII can't be read
     fn delete(&self, user_id: UserId) -> RepoResult<()>;
 
-    /// Search users limited by `from`, `skip` and `count` parameters
+    /// Seals PII of the user stored in plain text, returns whether it was sealed
+    fn seal_pii(&self, user_id: UserId) -> RepoResult<bool>;
+
+    /// Search users limited by `from`, `skip` and `count` parameters, see `RepoLimits::count`
     fn search(&self, from: Option<UserId>, skip: i64, count: i64, term: UsersSearchTerms) -> RepoResult<UserSearchResults>;
 
     /// Fuzzy search users by email
@@ -74,7 +95,117 @@ pub trait UsersRepo {
 
 impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> UsersRepoImpl<'a, T> {
     pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, User>>) -> Self {
-        Self { db_conn, acl }
+        Self {
+            db_conn,
+            acl,
+            limits: RepoLimits::default(),
+            pii_cipher: None,
+        }
+    }
+
+    pub fn with_limits(self, limits: RepoLimits) -> Self {
+        Self { limits, ..self }
+    }
+
+    /// Seals PII of users by their data keys, PII is stored in plain text without the cipher
+    pub fn with_pii_cipher(self, pii_cipher: Option<PiiCipher>) -> Self {
+        Self { pii_cipher, ..self }
+    }
+
+    /// Opens sealed PII of the users. Users whose data keys were shredded, e.g. deleted users
+    /// restored from backups, keep blind indexes in place of their PII.
+    fn open_pii(&self, mut found: Vec<User>) -> RepoResult<Vec<User>> {
+        let sealed = found
+            .iter()
+            .filter_map(|user| user.sealed_pii.clone().map(|sealed| (user.id, sealed)))
+            .collect::<Vec<_>>();
+        if sealed.is_empty() {
+            return Ok(found);
+        }
+        let cipher = match self.pii_cipher {
+            Some(ref cipher) => cipher,
+            None => {
+                warn!("PII of {} users is sealed, but PII encryption is not configured", sealed.len());
+                return Ok(found);
+            }
+        };
+
+        let mut opened = cipher.open(sealed)?;
+        for user in &mut found {
+            if let Some(pii) = opened.remove(&user.id.0) {
+                pii.apply(user);
+            }
+        }
+        Ok(found)
+    }
+
+    fn open_user_pii(&self, user: User) -> RepoResult<User> {
+        self.open_pii(vec![user]).map(|mut found| found.remove(0))
+    }
+
+    /// Values of the column of the field matching the value, the value itself or its blind index
+    fn lookup_values(&self, field: &str, value: &str) -> Vec<String> {
+        let mut values = vec![value.to_string()];
+        if let Some(ref cipher) = self.pii_cipher {
+            values.push(cipher.blind_index(field, value));
+        }
+        values
+    }
+
+    /// Deletes the data key of the user, its sealed PII left in backups can't be opened anymore
+    fn shred(&self, user_id_arg: UserId) -> RepoResult<()> {
+        match self.pii_cipher {
+            Some(ref cipher) => cipher.shred(user_id_arg),
+            None => Ok(()),
+        }
+    }
+
+    /// Creates the user with PII sealed. Id of the user is assigned on insert, so the user is
+    /// inserted with the blind index of the email first and sealed by its data key then.
+    fn create_sealed(&self, mut payload: NewUser, cipher: &PiiCipher) -> RepoResult<User> {
+        let pii = UserPii::take_from_new_user(&mut payload);
+        payload.email = blind_index(cipher, "email", &pii.email).unwrap_or_default();
+        self.db_conn.transaction(|| {
+            let user = diesel::insert_into(users).values(&payload).get_result::<User>(self.db_conn)?;
+            let sealed = cipher.seal(user.id, &pii)?;
+            let mut user = diesel::update(users.filter(id.eq(user.id)))
+                .set(&sealed_columns(cipher, &pii, sealed))
+                .get_result::<User>(self.db_conn)?;
+            pii.clone().apply(&mut user);
+            Ok(user)
+        })
+    }
+
+    fn update_user(&self, user_id_arg: UserId, payload: &mut UpdateUser) -> RepoResult<User> {
+        let user: User = users.find(user_id_arg).get_result(self.db_conn)?;
+        acl::check(&*self.acl, Resource::Users, Action::Update, self, Some(&user))?;
+        match self.pii_cipher {
+            Some(ref cipher) => self.update_sealed(user, payload, cipher),
+            None => {
+                let filter = users.filter(id.eq(user_id_arg)).filter(is_active.eq(true));
+                diesel::update(filter)
+                    .set(&*payload)
+                    .get_result::<User>(self.db_conn)
+                    .map_err(From::from)
+            }
+        }
+    }
+
+    /// Updates the user, changed PII is sealed along with the rest of PII of the user.
+    /// PII of users created before PII encryption was configured is sealed too.
+    fn update_sealed(&self, user: User, payload: &mut UpdateUser, cipher: &PiiCipher) -> RepoResult<User> {
+        let user_id_arg = user.id;
+        let mut pii = UserPii::of_user(&self.open_user_pii(user)?);
+        pii.take_update(payload);
+        self.db_conn.transaction(|| {
+            let sealed = cipher.seal(user_id_arg, &pii)?;
+            let filter = users.filter(id.eq(user_id_arg)).filter(is_active.eq(true));
+            let mut user = diesel::update(filter)
+                .set((&*payload, &sealed_columns(cipher, &pii, sealed)))
+                .get_result::<User>(self.db_conn)?;
+            pii.clone().apply(&mut user);
+            Ok(user)
+        })
     }
 }
 
@@ -106,26 +237,27 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
                 };
                 Ok(user)
             })
+            .and_then(|user| match user {
+                Some(user) => self.open_user_pii(user).map(Some),
+                None => Ok(None),
+            })
             .map_err(|e: FailureError| e.context(format!("Find specific user {} error occured", user_id_arg)).into())
     }
 
     /// Check that user with specified email already exists
     fn email_exists(&self, email_arg: String) -> RepoResult<bool> {
-        let query = select(exists(users.filter(email.eq(email_arg.clone()))));
+        let query = select(exists(users.filter(email.eq_any(self.lookup_values("email", &email_arg)))));
 
         query
             .get_result(self.db_conn)
             .map_err(From::from)
             .and_then(|exists| acl::check(&*self.acl, Resource::Users, Action::Read, self, None).and_then(|_| Ok(exists)))
-            .map_err(|e: FailureError| {
-                e.context(format!("Check that user with email {} already exists error occured", email_arg))
-                    .into()
-            })
+            .map_err(|e: FailureError| e.context("Check that user with email already exists error occured").into())
     }
 
     /// Find specific user by email
     fn find_by_email(&self, email_arg: String) -> RepoResult<Option<User>> {
-        let query = users.filter(email.eq(email_arg.clone()));
+        let query = users.filter(email.eq_any(self.lookup_values("email", &email_arg)));
 
         query
             .first(self.db_conn)
@@ -137,30 +269,56 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
                 };
                 Ok(user)
             })
-            .map_err(|e: FailureError| {
-                e.context(format!("Find specific user by email {:?} error occured", email_arg))
-                    .into()
+            .and_then(|user| match user {
+                Some(user) => self.open_user_pii(user).map(Some),
+                None => Ok(None),
             })
+            .map_err(|e: FailureError| e.context("Find specific user by email error occured").into())
     }
 
-    /// Returns list of users, limited by `from` and `count` parameters
-    fn list(&self, from: UserId, count: i64) -> RepoResult<Vec<User>> {
+    /// Find user by phone in E.164 format, verified phones take precedence
+    fn find_by_phone(&self, phone_arg: String) -> RepoResult<Option<User>> {
         let query = users
-            .filter(id.ne(1)) // hide user_id == 1
-            .filter(is_active.eq(true))
-            .filter(id.ge(from))
-            .order(id)
-            .limit(count);
+            .filter(phone.eq_any(self.lookup_values("phone", &phone_arg)))
+            .order((phone_verified.desc(), id));
 
         query
-            .get_results(self.db_conn)
+            .first(self.db_conn)
+            .optional()
             .map_err(From::from)
+            .and_then(|user: Option<User>| {
+                if let Some(ref user) = user {
+                    acl::check(&*self.acl, Resource::Users, Action::Read, self, Some(user))?;
+                };
+                Ok(user)
+            })
+            .and_then(|user| match user {
+                Some(user) => self.open_user_pii(user).map(Some),
+                None => Ok(None),
+            })
+            .map_err(|e: FailureError| e.context("Find specific user by phone error occured").into())
+    }
+
+    /// Returns list of users, limited by `from` and `count` parameters
+    fn list(&self, from: UserId, count: i64) -> RepoResult<Vec<User>> {
+        self.limits
+            .count(count)
+            .and_then(|limit| {
+                users
+                    .filter(id.ne(1)) // hide user_id == 1
+                    .filter(is_active.eq(true))
+                    .filter(id.ge(from))
+                    .order(id)
+                    .limit(limit)
+                    .get_results(self.db_conn)
+                    .map_err(From::from)
+            })
             .and_then(|users_res: Vec<User>| {
                 for user in &users_res {
                     acl::check(&*self.acl, Resource::Users, Action::Read, self, Some(&user))?;
                 }
 
-                Ok(users_res)
+                self.open_pii(users_res)
             })
             .map_err(|e: FailureError| {
                 e.context(format!("list of users, limited by {} and {} error occured", from, count))
@@ -169,32 +327,37 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
     }
 
     /// Creates new user
-    fn create(&self, payload: NewUser) -> RepoResult<User> {
-        let query_user = diesel::insert_into(users).values(&payload);
+    fn create(&self, mut payload: NewUser) -> RepoResult<User> {
+        payload.normalize_names();
         acl::check(&*self.acl, Resource::Users, Action::Create, self, None)?;
-        query_user
-            .get_result::<User>(self.db_conn)
-            .map_err(|e| e.context(format!("Create a new user {:?} error occured", payload)).into())
+        let saga_id_arg = payload.saga_id.clone();
+        match self.pii_cipher {
+            Some(ref cipher) => self.create_sealed(payload, cipher),
+            None => diesel::insert_into(users)
+                .values(&payload)
+                .get_result::<User>(self.db_conn)
+                .map_err(From::from),
+        }
+        .map_err(|e: FailureError| {
+            e.context(format!("Create a new user with saga id {} error occured", saga_id_arg))
+                .into()
+        })
     }
 
-    /// Updates specific user
-    fn update(&self, user_id_arg: UserId, payload: UpdateUser) -> RepoResult<User> {
-        let query = users.find(user_id_arg.clone());
-
-        query
-            .get_result(self.db_conn)
-            .map_err(From::from)
-            .and_then(|user: User| acl::check(&*self.acl, Resource::Users, Action::Update, self, Some(&user)))
-            .and_then(|_| {
-                let filter = users.filter(id.eq(user_id_arg.clone())).filter(is_active.eq(true));
+    /// Allocates id for a new user, so that the user can be routed to a shard before creation
+    fn next_id(&self) -> RepoResult<UserId> {
+        acl::check(&*self.acl, Resource::Users, Action::Create, self, None)?;
+        select(sql::<Integer>("nextval('users_id_seq')"))
+            .get_result::<i32>(self.db_conn)
+            .map(UserId)
+            .map_err(|e| e.context("Allocate new user id error occured").into())
+    }
 
-                let query = diesel::update(filter).set(&payload);
-                query.get_result::<User>(self.db_conn).map_err(From::from)
-            })
-            .map_err(|e: FailureError| {
-                e.context(format!("update user {} with {:?} error occured", user_id_arg, payload))
-                    .into()
-            })
+    /// Updates specific user
+    fn update(&self, user_id_arg: UserId, mut payload: UpdateUser) -> RepoResult<User> {
+        payload.normalize_names();
+        self.update_user(user_id_arg, &mut payload)
+            .map_err(|e: FailureError| e.context(format!("update user {} error occured", user_id_arg)).into())
     }
 
     /// Deactivates specific user
@@ -211,6 +374,7 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
 
                 query.get_result(self.db_conn).map_err(From::from)
             })
+            .and_then(|user| self.open_user_pii(user))
             .map_err(|e: FailureError| e.context(format!("Deactivates user {:?} error occured", user_id_arg)).into())
     }
 
@@ -228,68 +392,112 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
 
                 query.get_result(self.db_conn).map_err(From::from)
             })
+            .and_then(|user| self.open_user_pii(user))
             .map_err(|e: FailureError| {
                 e.context(format!("Set Block status for user {:?} error occured", user_id_arg))
                     .into()
             })
     }
 
-    /// Deletes specific user by saga id
+    /// Marks the user as waiting for deletion, or unmarks it once the deletion is vetoed
+    fn set_pending_deletion(&self, user_id_arg: UserId, pending_deletion_arg: bool) -> RepoResult<User> {
+        let query = users.find(user_id_arg.clone());
+
+        query
+            .get_result(self.db_conn)
+            .map_err(From::from)
+            .and_then(|user: User| acl::check(&*self.acl, Resource::Users, Action::Update, self, Some(&user)))
+            .and_then(|_| {
+                let filter = users.filter(id.eq(user_id_arg.clone()));
+                let query = diesel::update(filter).set(pending_deletion.eq(pending_deletion_arg));
+
+                query.get_result(self.db_conn).map_err(From::from)
+            })
+            .and_then(|user| self.open_user_pii(user))
+            .map_err(|e: FailureError| {
+                e.context(format!("Set pending deletion for user {:?} error occured", user_id_arg))
+                    .into()
+            })
+    }
+
+    /// Deletes specific user by saga id, the data key of the user is shredded
     fn delete_by_saga_id(&self, saga_id_arg: String) -> RepoResult<User> {
         let filtered = users.filter(saga_id.eq(saga_id_arg.clone()));
         let query = diesel::delete(filtered);
-        query.get_result(self.db_conn).map_err(|e| {
-            e.context(format!("Delete specific user by saga id {:?} error occured", saga_id_arg))
-                .into()
-        })
+        query
+            .get_result(self.db_conn)
+            .map_err(From::from)
+            .and_then(|user| self.open_user_pii(user))
+            .and_then(|user| self.shred(user.id).map(|_| user))
+            .map_err(|e: FailureError| {
+                e.context(format!("Delete specific user by saga id {:?} error occured", saga_id_arg))
+                    .into()
+            })
     }
 
-    /// Delete user by id
+    /// Delete user by id, the data key of the user is shredded
     fn delete(&self, user_id_arg: UserId) -> RepoResult<()> {
         let filtered = users.filter(id.eq(user_id_arg.clone()));
         let query = diesel::delete(filtered);
 
         query
             .get_result::<User>(self.db_conn)
-            .map_err(|e| e.context(format!("Delete user by id: {} error occured", user_id_arg)).into())
-            .map(|_| ())
+            .map_err(From::from)
+            .and_then(|_| self.shred(user_id_arg))
+            .map_err(|e: FailureError| e.context(format!("Delete user by id: {} error occured", user_id_arg)).into())
     }
 
-    /// Search users limited by `from`, `skip` and `count` parameters
-    fn search(&self, from: Option<UserId>, skip: i64, count: i64, term: UsersSearchTerms) -> RepoResult<UserSearchResults> {
-        // hide user_id == 1
-        let total_count_query = users.filter(id.ne(1).and(by_search_terms(&term))).count();
+    /// Seals PII of the user stored in plain text, returns whether it was sealed
+    fn seal_pii(&self, user_id_arg: UserId) -> RepoResult<bool> {
+        let cipher = match self.pii_cipher {
+            Some(ref cipher) => cipher,
+            None => return Ok(false),
+        };
+
+        let user: User = users.find(user_id_arg).get_result(self.db_conn)?;
+        acl::check(&*self.acl, Resource::Users, Action::Update, self, Some(&user))?;
+        if user.sealed_pii.is_some() {
+            return Ok(false);
+        }
+        let pii = UserPii::of_user(&user);
+        self.db_conn
+            .transaction(|| {
+                let sealed = cipher.seal(user_id_arg, &pii)?;
+                // users sealed concurrently by an update are left as they are
+                let filter = users.filter(id.eq(user_id_arg)).filter(sealed_pii.is_null());
+                diesel::update(filter)
+                    .set(&sealed_columns(cipher, &pii, sealed))
+                    .execute(self.db_conn)
+                    .map_err(From::from)
+            })
+            .map(|updated| updated > 0)
+            .map_err(|e: FailureError| e.context(format!("Seal PII of user {} error occured", user_id_arg)).into())
+    }
 
-        let mut query = users.filter(id.ne(1)).into_boxed();
+    /// Search users limited by `from`, `skip` and `count` parameters
+    fn search(&self, from: Option<UserId>, skip: i64, count: i64, mut term: UsersSearchTerms) -> RepoResult<UserSearchResults> {
+        term.normalize_names();
+        term.normalize_tags();
+        let total_count_query = search_query(None, &term, self.pii_cipher.as_ref()).count();
 
-        if let Some(from_id) = from {
-            query = query.filter(id.ge(from_id));
-        }
+        let mut query = search_query(from, &term, self.pii_cipher.as_ref());
         if skip > 0 {
             query = query.offset(skip);
         }
-        if count > 0 {
-            query = query.limit(count);
-        }
-
-        query = query.filter(by_search_terms(&term));
 
-        query
-            .order(id)
-            .get_results(self.db_conn)
-            .map_err(From::from)
+        self.limits
+            .count(count)
+            .and_then(|limit| query.order(id).limit(limit).get_results(self.db_conn).map_err(From::from))
             .and_then(|users_res: Vec<User>| {
                 for user in &users_res {
                     acl::check(&*self.acl, Resource::Users, Action::Read, self, Some(&user))?;
                 }
 
-                total_count_query
-                    .get_result::<i64>(self.db_conn)
-                    .map(move |total_count| UserSearchResults {
-                        total_count: total_count as u32,
-                        users: users_res,
-                    })
-                    .map_err(From::from)
+                let total_count = total_count_query.get_result::<i64>(self.db_conn)?;
+                Ok(UserSearchResults {
+                    total_count: total_count as u32,
+                    users: self.open_pii(users_res)?,
+                })
             })
             .map_err(|e: FailureError| {
                 e.context(format!(
@@ -300,9 +508,17 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
             })
     }
 
-    /// Fuzzy search users by email
+    /// Fuzzy search users by email, at most `max_count` users are returned
     fn fuzzy_search_by_email(&self, term_email: String) -> RepoResult<Vec<User>> {
-        let query = users.filter(email.like(format!("%{}%", term_email))).order(id);
+        // sealed users are matched by whole emails only
+        let query = users
+            .filter(
+                email
+                    .like(contains_pattern(&term_email))
+                    .or(email.eq_any(self.lookup_values("email", &term_email))),
+            )
+            .order(id)
+            .limit(self.limits.max_count);
         query
             .get_results(self.db_conn)
             .map_err(From::from)
@@ -311,7 +527,7 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
                     acl::check(&*self.acl, Resource::Users, Action::Read, self, Some(&user))?;
                 }
 
-                Ok(users_res)
+                self.open_pii(users_res)
             })
             .map_err(|e: FailureError| e.context(format!("fuzzy search for users by email error occured")).into())
     }
@@ -353,28 +569,189 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
     }
 }
 
-fn by_search_terms(term: &UsersSearchTerms) -> Box<BoxableExpression<users, Pg, SqlType = Bool>> {
+/// Filter of users matching the search terms, sealed users match whole emails, phones and names
+fn by_search_terms(term: &UsersSearchTerms, cipher: Option<&PiiCipher>) -> Box<BoxableExpression<users, Pg, SqlType = Bool>> {
     let mut expr: Box<BoxableExpression<users, Pg, SqlType = Bool>> = Box::new(id.eq(id));
-
-    if let Some(term_email) = term.email.clone() {
-        expr = Box::new(expr.and(email.ilike(format!("%{}%", term_email))));
+    let index = |field: &str, value: &str| cipher.map(|cipher| cipher.blind_index(field, value));
+
+    if let Some(ref term_email) = term.email {
+        let ilike_expr = email.ilike(contains_pattern(term_email));
+        expr = match index("email", term_email) {
+            Some(term_index) => Box::new(expr.and(ilike_expr.or(email.eq(term_index)))),
+            None => Box::new(expr.and(ilike_expr)),
+        };
     }
-    if let Some(term_phone) = term.phone.clone() {
-        expr = Box::new(expr.and(phone.eq(term_phone)));
+    if let Some(ref term_phone) = term.phone {
+        let mut values = vec![term_phone.clone()];
+        values.extend(index("phone", term_phone));
+        expr = Box::new(expr.and(phone.eq_any(values)));
     }
-    if let Some(term_first_name) = term.first_name.clone() {
-        let ilike_expr = sql("first_name ILIKE concat('%', ")
-            .bind::<VarChar, _>(term_first_name)
-            .sql(", '%')");
-        expr = Box::new(expr.and(ilike_expr));
+    if let Some(ref term_first_name) = term.first_name {
+        let ilike_expr = sql("first_name ILIKE ").bind::<VarChar, _>(contains_pattern(term_first_name));
+        expr = match index("first_name", term_first_name) {
+            Some(term_index) => Box::new(expr.and(ilike_expr.or(first_name.eq(term_index)))),
+            None => Box::new(expr.and(ilike_expr)),
+        };
     }
-    if let Some(term_last_name) = term.last_name.clone() {
-        let ilike_expr = sql("last_name ILIKE concat('%', ").bind::<VarChar, _>(term_last_name).sql(", '%')");
-        expr = Box::new(expr.and(ilike_expr));
+    if let Some(ref term_last_name) = term.last_name {
+        let ilike_expr = sql("last_name ILIKE ").bind::<VarChar, _>(contains_pattern(term_last_name));
+        expr = match index("last_name", term_last_name) {
+            Some(term_index) => Box::new(expr.and(ilike_expr.or(last_name.eq(term_index)))),
+            None => Box::new(expr.and(ilike_expr)),
+        };
     }
     if let Some(term_is_blocked) = term.is_blocked.clone() {
         expr = Box::new(expr.and(is_blocked.eq(term_is_blocked)));
     }
+    if let Some(term_data_region) = term.data_region.clone() {
+        expr = Box::new(expr.and(data_region.eq(term_data_region)));
+    }
+    if let Some(ref term_tags) = term.tags {
+        for term_tag in term_tags {
+            let tagged = user_tags::table
+                .filter(user_tags::tag.eq(term_tag.clone()))
+                .select(user_tags::user_id);
+            expr = Box::new(expr.and(id.eq_any(tagged)));
+        }
+    }
 
     expr
 }
+
+/// Query of users matching all of the search terms, terms are always passed as bind parameters
+fn search_query<'a>(from: Option<UserId>, term: &UsersSearchTerms, cipher: Option<&PiiCipher>) -> BoxedQuery<'a, Pg> {
+    // hide user_id == 1
+    let mut query = users.filter(id.ne(1)).into_boxed().filter(by_search_terms(term, cipher));
+    if let Some(from_id) = from {
+        query = query.filter(id.ge(from_id));
+    }
+    query
+}
+
+/// Blind index of the value of the sealed field, see `PiiCipher::blind_index`
+fn blind_index(cipher: &PiiCipher, field: &str, value: &Option<String>) -> Option<String> {
+    value.as_ref().map(|value| cipher.blind_index(field, value))
+}
+
+/// Columns of the sealed user, blind indexes replace email, phone and names, the rest of PII is emptied
+fn sealed_columns(cipher: &PiiCipher, pii: &UserPii, sealed: Vec<u8>) -> SealedPiiColumns {
+    SealedPiiColumns {
+        email: blind_index(cipher, "email", &pii.email).unwrap_or_default(),
+        phone: blind_index(cipher, "phone", &pii.phone),
+        first_name: blind_index(cipher, "first_name", &pii.first_name),
+        last_name: blind_index(cipher, "last_name", &pii.last_name),
+        middle_name: None,
+        birthdate: None,
+        sealed_pii: Some(sealed),
+    }
+}
+
+/// LIKE pattern matching values containing the term, wildcards in the term are matched literally
+fn contains_pattern(term: &str) -> String {
+    let mut pattern = String::with_capacity(term.len() + 2);
+    pattern.push('%');
+    for c in term.chars() {
+        if c == '%' || c == '_' || c == '\\' {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use diesel::debug_query;
+
+    use super::*;
+    use repos::pii_cipher::tests::{create_cipher, KeyStoreMock};
+
+    const HOSTILE: &'static str = "x'); DROP TABLE users; --";
+
+    /// Splits debug output of a query into sql and bind parameters
+    fn debug_sql(query: &BoxedQuery<Pg>) -> (String, String) {
+        let debug = debug_query::<Pg, _>(query).to_string();
+        let mut parts = debug.splitn(2, " -- binds: ");
+        let sql = parts.next().unwrap_or_default().to_string();
+        let binds = parts.next().unwrap_or_default().to_string();
+        (sql, binds)
+    }
+
+    #[test]
+    fn test_search_query_binds_terms() {
+        let term = UsersSearchTerms {
+            email: Some(HOSTILE.to_string()),
+            phone: Some(HOSTILE.to_string()),
+            first_name: Some(HOSTILE.to_string()),
+            last_name: Some(HOSTILE.to_string()),
+            is_blocked: Some(false),
+            data_region: Some(HOSTILE.to_string()),
+            tags: Some(vec![HOSTILE.to_string()]),
+        };
+        let (sql, binds) = debug_sql(&search_query(Some(UserId(10)), &term, None));
+        assert_eq!(sql.contains("DROP TABLE"), false);
+        assert_eq!(binds.matches("DROP TABLE").count(), 6);
+    }
+
+    #[test]
+    fn test_search_query_combines_filters() {
+        let term = UsersSearchTerms {
+            email: None,
+            phone: None,
+            first_name: Some("John".to_string()),
+            last_name: None,
+            is_blocked: Some(true),
+            data_region: None,
+            tags: None,
+        };
+        let (sql, _) = debug_sql(&search_query(None, &term, None));
+        assert_eq!(sql.contains("first_name ILIKE"), true);
+        assert_eq!(sql.contains("\"is_blocked\""), true);
+        assert_eq!(sql.contains("last_name ILIKE"), false);
+    }
+
+    #[test]
+    fn test_search_query_requires_all_tags() {
+        let term = UsersSearchTerms {
+            email: None,
+            phone: None,
+            first_name: None,
+            last_name: None,
+            is_blocked: None,
+            data_region: None,
+            tags: Some(vec!["vip".to_string(), "beta_testers".to_string()]),
+        };
+        let (sql, binds) = debug_sql(&search_query(None, &term, None));
+        assert_eq!(sql.matches("\"user_tags\".\"tag\" = $").count(), 2);
+        assert_eq!(binds.contains("\"vip\""), true);
+        assert_eq!(binds.contains("\"beta_testers\""), true);
+    }
+
+    #[test]
+    fn test_search_query_matches_blind_indexes() {
+        let cipher = create_cipher(&[1u8; 32], Arc::new(KeyStoreMock::default()));
+        let term = UsersSearchTerms {
+            email: Some("john@example.com".to_string()),
+            phone: None,
+            first_name: Some("John".to_string()),
+            last_name: None,
+            is_blocked: None,
+            data_region: None,
+            tags: None,
+        };
+        let (sql, binds) = debug_sql(&search_query(None, &term, Some(&cipher)));
+        assert_eq!(sql.contains("first_name ILIKE"), true);
+        assert_eq!(sql.contains("\"users\".\"first_name\" = $"), true);
+        assert_eq!(binds.contains(&cipher.blind_index("first_name", "John")), true);
+        assert_eq!(binds.contains(&cipher.blind_index("email", "john@example.com")), true);
+    }
+
+    #[test]
+    fn test_contains_pattern_escapes_wildcards() {
+        assert_eq!(contains_pattern("john"), "%john%");
+        assert_eq!(contains_pattern("100%_\\"), "%100\\%\\_\\\\%");
+    }
+}
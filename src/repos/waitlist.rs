@@ -0,0 +1,123 @@
+//! Repo for waitlist table, people waiting for invites while registration is invite-only
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_types::UserId;
+
+use repos::legacy_acl::*;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::*;
+use models::{NewWaitlistEntry, WaitlistEntry};
+use schema::waitlist::dsl::*;
+
+/// Waitlist repository, responsible for handling waitlist entries
+pub trait WaitlistRepo {
+    /// Adds email to the waitlist, the token of existing entry is replaced.
+    /// Emails are added by people not registered yet, no ACL check
+    fn upsert(&self, payload: NewWaitlistEntry) -> RepoResult<WaitlistEntry>;
+
+    /// Find entry by confirmation token, no ACL check
+    fn find_by_token(&self, token: String) -> RepoResult<Option<WaitlistEntry>>;
+
+    /// Marks email of the entry confirmed, no ACL check
+    fn confirm(&self, email: String) -> RepoResult<WaitlistEntry>;
+
+    /// Returns the earliest confirmed entries not invited yet, locking them until the end of current transaction
+    fn list_to_invite(&self, count: i64) -> RepoResult<Vec<WaitlistEntry>>;
+
+    /// Saves invite issued for the entry
+    fn set_invite(&self, email: String, invite_code: String) -> RepoResult<WaitlistEntry>;
+}
+
+/// Implementation of Waitlist trait
+pub struct WaitlistRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, WaitlistEntry>>,
+    pub db_conn: &'a T,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> WaitlistRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, WaitlistEntry>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> WaitlistRepo for WaitlistRepoImpl<'a, T> {
+    /// Adds email to the waitlist, the token of existing entry is replaced.
+    /// Emails are added by people not registered yet, no ACL check
+    fn upsert(&self, payload: NewWaitlistEntry) -> RepoResult<WaitlistEntry> {
+        let query = diesel::insert_into(waitlist)
+            .values(&payload)
+            .on_conflict(email)
+            .do_update()
+            .set(token.eq(&payload.token));
+        query
+            .get_result::<WaitlistEntry>(self.db_conn)
+            .map_err(|e| e.context(format!("Add {} to waitlist error occured", payload.email)).into())
+    }
+
+    /// Find entry by confirmation token, no ACL check
+    fn find_by_token(&self, token_arg: String) -> RepoResult<Option<WaitlistEntry>> {
+        let query = waitlist.filter(token.eq(token_arg));
+        query
+            .get_result::<WaitlistEntry>(self.db_conn)
+            .optional()
+            .map_err(|e| e.context("Find waitlist entry by token error occured").into())
+    }
+
+    /// Marks email of the entry confirmed, no ACL check
+    fn confirm(&self, email_arg: String) -> RepoResult<WaitlistEntry> {
+        let query = diesel::update(waitlist.find(&email_arg)).set(confirmed.eq(true));
+        query
+            .get_result::<WaitlistEntry>(self.db_conn)
+            .map_err(|e| e.context(format!("Confirm waitlist entry {} error occured", email_arg)).into())
+    }
+
+    /// Returns the earliest confirmed entries not invited yet, locking them until the end of current transaction
+    fn list_to_invite(&self, count: i64) -> RepoResult<Vec<WaitlistEntry>> {
+        acl::check(&*self.acl, Resource::Waitlist, Action::Update, self, None)?;
+
+        let query = waitlist
+            .filter(confirmed.eq(true))
+            .filter(invite_code.is_null())
+            .order(created_at)
+            .limit(count)
+            .for_update();
+        query
+            .get_results::<WaitlistEntry>(self.db_conn)
+            .map_err(|e| e.context(format!("List {} waitlist entries to invite error occured", count)).into())
+    }
+
+    /// Saves invite issued for the entry
+    fn set_invite(&self, email_arg: String, invite_code_arg: String) -> RepoResult<WaitlistEntry> {
+        acl::check(&*self.acl, Resource::Waitlist, Action::Update, self, None)?;
+
+        let query = diesel::update(waitlist.find(&email_arg)).set(invite_code.eq(&invite_code_arg));
+        query.get_result::<WaitlistEntry>(self.db_conn).map_err(|e| {
+            e.context(format!(
+                "Set invite {} of waitlist entry {} error occured",
+                invite_code_arg, email_arg
+            ))
+            .into()
+        })
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, WaitlistEntry>
+    for WaitlistRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, _user_id: UserId, scope: &Scope, _obj: Option<&WaitlistEntry>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => false,
+        }
+    }
+}
@@ -1,3 +1,145 @@
+table! {
+    access_tokens (id) {
+        id -> Uuid,
+        user_id -> Int4,
+        name -> Varchar,
+        token_hash -> Varchar,
+        scopes -> Array<Varchar>,
+        expires_at -> Nullable<Timestamp>,
+        last_used_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    audit_log (id) {
+        id -> Int4,
+        user_id -> Int4,
+        actor_id -> Nullable<Int4>,
+        action -> Varchar,
+        data -> Nullable<Jsonb>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    child_accounts (child_id) {
+        child_id -> Int4,
+        parent_id -> Int4,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    client_brandings (client_id) {
+        client_id -> Varchar,
+        app_name -> Varchar,
+        support_email -> Varchar,
+        logo_url -> Nullable<Varchar>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    clients (id) {
+        id -> Varchar,
+        name -> Varchar,
+        audience -> Varchar,
+        jwt_expiration_s -> Int8,
+        refresh_timeout_s -> Int8,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        secret_hash -> Nullable<Varchar>,
+        service_user_id -> Nullable<Int4>,
+        is_third_party -> Bool,
+        refresh_token_cookie -> Bool,
+        session_mode -> Bool,
+        remember_me_expiration_s -> Nullable<Int8>,
+        remember_me_refresh_timeout_s -> Nullable<Int8>,
+    }
+}
+
+table! {
+    countries (alpha3) {
+        alpha3 -> Varchar,
+        alpha2 -> Varchar,
+        name -> Varchar,
+        phone_prefix -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    crm_drifts (id) {
+        id -> Int4,
+        job_id -> Uuid,
+        user_id -> Int4,
+        emarsys_id -> Int4,
+        field -> Varchar,
+        user_value -> Nullable<Varchar>,
+        crm_value -> Nullable<Varchar>,
+        corrected -> Bool,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    deletion_confirmations (user_id, service) {
+        user_id -> Int4,
+        service -> Varchar,
+        approved -> Bool,
+        reason -> Nullable<Varchar>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    deletion_requests (user_id) {
+        user_id -> Int4,
+        state -> Varchar,
+        requested_by -> Nullable<Int4>,
+        deadline -> Timestamp,
+        job_id -> Nullable<Uuid>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        not_before -> Timestamp,
+    }
+}
+
+table! {
+    device_codes (device_code) {
+        device_code -> Varchar,
+        user_code -> Varchar,
+        client_id -> Varchar,
+        user_id -> Nullable<Int4>,
+        expires_at -> Timestamp,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        scope -> Nullable<Varchar>,
+        last_polled_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    devices (id) {
+        id -> Uuid,
+        user_id -> Int4,
+        fingerprint -> Varchar,
+        name -> Nullable<Varchar>,
+        trusted_until -> Nullable<Timestamp>,
+        first_seen_at -> Timestamp,
+        last_seen_at -> Timestamp,
+    }
+}
+
+table! {
+    funnel_events (user_id, step) {
+        step -> Varchar,
+        created_at -> Timestamp,
+        user_id -> Int4,
+    }
+}
+
 table! {
     identities (user_id) {
         user_id -> Int4,
@@ -5,6 +147,141 @@ table! {
         password -> Nullable<Varchar>,
         provider -> Varchar,
         saga_id -> Varchar,
+        must_change_password -> Bool,
+        password_hash_format -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    invites (code) {
+        code -> Varchar,
+        created_by -> Nullable<Int4>,
+        max_uses -> Int4,
+        uses -> Int4,
+        expires_at -> Timestamp,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    jobs (id) {
+        id -> Uuid,
+        kind -> Varchar,
+        state -> Varchar,
+        progress -> Int4,
+        total -> Nullable<Int4>,
+        result_location -> Nullable<Varchar>,
+        error -> Nullable<Varchar>,
+        created_by -> Nullable<Int4>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    legacy_sync_states (user_id) {
+        user_id -> Int4,
+        operation -> Varchar,
+        synced -> Bool,
+        error -> Nullable<Varchar>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    login_history (id) {
+        id -> Int4,
+        user_id -> Int4,
+        provider -> Varchar,
+        client_ip -> Nullable<Varchar>,
+        user_agent -> Nullable<Varchar>,
+        fraud_score -> Nullable<Float8>,
+        decision -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    login_stats (date, provider) {
+        date -> Date,
+        provider -> Varchar,
+        logins -> Int8,
+    }
+}
+
+table! {
+    oauth_consents (user_id, client_id) {
+        user_id -> Int4,
+        client_id -> Varchar,
+        scopes -> Array<Varchar>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    oauth_revocations (user_id, client_id) {
+        user_id -> Int4,
+        client_id -> Varchar,
+        revoke_before -> Timestamp,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    profile_prompt_dismissals (user_id, field) {
+        user_id -> Int4,
+        field -> Varchar,
+        snoozed_until -> Timestamp,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    provider_blocks (user_id, provider) {
+        user_id -> Int4,
+        provider -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    recovery_approvals (request_id, contact_id) {
+        request_id -> Uuid,
+        contact_id -> Int4,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    recovery_requests (id) {
+        id -> Uuid,
+        user_id -> Int4,
+        state -> Varchar,
+        required_approvals -> Int4,
+        expires_at -> Timestamp,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    refresh_tokens (id) {
+        id -> Uuid,
+        family_id -> Uuid,
+        user_id -> Int4,
+        client_id -> Varchar,
+        provider -> Varchar,
+        auth_time -> Nullable<Int8>,
+        scope -> Nullable<Varchar>,
+        token_hash -> Varchar,
+        expires_at -> Timestamp,
+        rotated_at -> Nullable<Timestamp>,
+        revoked -> Bool,
+        family_created_at -> Timestamp,
+        created_at -> Timestamp,
     }
 }
 
@@ -19,6 +296,82 @@ table! {
     }
 }
 
+table! {
+    role_access_policies (role) {
+        role -> Varchar,
+        starts_at -> Time,
+        ends_at -> Time,
+        utc_offset_minutes -> Int4,
+        restrict_login -> Bool,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    role_requests (id) {
+        id -> Uuid,
+        user_id -> Int4,
+        name -> Varchar,
+        data -> Nullable<Jsonb>,
+        expires_at -> Nullable<Timestamp>,
+        requested_by -> Int4,
+        approved_by -> Nullable<Int4>,
+        approved_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    security_answers (user_id, question_id) {
+        user_id -> Int4,
+        question_id -> Varchar,
+        answer_hash -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    segment_exports (id) {
+        id -> Uuid,
+        filters -> Jsonb,
+        csv -> Nullable<Text>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    sessions (id) {
+        id -> Uuid,
+        session_hash -> Varchar,
+        user_id -> Int4,
+        client_id -> Varchar,
+        provider -> Varchar,
+        auth_time -> Nullable<Int8>,
+        expires_at -> Timestamp,
+        last_seen_at -> Timestamp,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    trusted_contacts (id) {
+        id -> Int4,
+        user_id -> Int4,
+        email -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    used_tokens (id) {
+        id -> Varchar,
+        expires_at -> Timestamp,
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     user_roles (id) {
         user_id -> Int4,
@@ -27,6 +380,15 @@ table! {
         name -> Varchar,
         data -> Nullable<Jsonb>,
         id -> Uuid,
+        expires_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    user_tags (user_id, tag) {
+        user_id -> Int4,
+        tag -> Varchar,
+        created_at -> Timestamp,
     }
 }
 
@@ -55,15 +417,81 @@ table! {
         country -> Nullable<Varchar>,
         referer -> Nullable<Varchar>,
         revoke_before -> Timestamp,
+        data_region -> Varchar,
+        sealed_pii -> Nullable<Bytea>,
+        pending_deletion -> Bool,
+    }
+}
+
+table! {
+    waitlist (email) {
+        email -> Varchar,
+        token -> Varchar,
+        confirmed -> Bool,
+        invite_code -> Nullable<Varchar>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
     }
 }
 
+joinable!(client_brandings -> clients (client_id));
+joinable!(clients -> users (service_user_id));
+joinable!(crm_drifts -> jobs (job_id));
+joinable!(deletion_confirmations -> deletion_requests (user_id));
+joinable!(deletion_requests -> jobs (job_id));
+joinable!(device_codes -> clients (client_id));
+joinable!(device_codes -> users (user_id));
 joinable!(identities -> users (user_id));
+joinable!(invites -> users (created_by));
+joinable!(oauth_consents -> clients (client_id));
+joinable!(oauth_revocations -> clients (client_id));
+joinable!(profile_prompt_dismissals -> users (user_id));
+joinable!(recovery_approvals -> recovery_requests (request_id));
+joinable!(recovery_approvals -> trusted_contacts (contact_id));
+joinable!(refresh_tokens -> clients (client_id));
+joinable!(security_answers -> users (user_id));
+joinable!(segment_exports -> jobs (id));
+joinable!(sessions -> clients (client_id));
 joinable!(user_roles -> users (user_id));
+joinable!(user_tags -> users (user_id));
+joinable!(waitlist -> invites (invite_code));
 
 allow_tables_to_appear_in_same_query!(
+    access_tokens,
+    audit_log,
+    child_accounts,
+    client_brandings,
+    clients,
+    countries,
+    crm_drifts,
+    deletion_confirmations,
+    deletion_requests,
+    device_codes,
+    devices,
+    funnel_events,
     identities,
+    invites,
+    jobs,
+    legacy_sync_states,
+    login_history,
+    login_stats,
+    oauth_consents,
+    oauth_revocations,
+    profile_prompt_dismissals,
+    provider_blocks,
+    recovery_approvals,
+    recovery_requests,
+    refresh_tokens,
     reset_tokens,
+    role_access_policies,
+    role_requests,
+    security_answers,
+    segment_exports,
+    sessions,
+    trusted_contacts,
+    used_tokens,
     user_roles,
+    user_tags,
     users,
+    waitlist,
 );
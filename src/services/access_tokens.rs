@@ -0,0 +1,332 @@
+//! Personal access tokens Services. Users create named tokens limited to configured scopes
+//! for API consumers who should not embed their password. Tokens are passed in `Authorization`
+//! header to the gateway, which resolves them to users via introspection.
+//! Tokens are saved hashed on the primary shard, as they are looked up without knowing the user.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::future;
+use futures::Future;
+use r2d2::ManageConnection;
+use uuid::Uuid;
+
+use super::oauth::authenticate_client;
+use super::util::{signed_token_create, signed_token_verify, token_hash};
+use errors::Error;
+use models::*;
+use repos::repo_factory::ReposFactory;
+use services::types::ServiceFuture;
+use services::Service;
+
+pub trait AccessTokensService {
+    /// Creates personal access token of current user, the token itself is returned only once
+    fn create_access_token(&self, payload: NewAccessTokenPayload) -> ServiceFuture<CreatedAccessToken>;
+    /// Returns personal access tokens of current user
+    fn get_access_tokens(&self) -> ServiceFuture<Vec<AccessToken>>;
+    /// Revokes personal access token of current user
+    fn revoke_access_token(&self, token_id: Uuid) -> ServiceFuture<AccessToken>;
+    /// Resolves personal access token to its user and scopes for an authenticated first-party client
+    fn introspect_access_token(&self, request: IntrospectionRequest) -> ServiceFuture<Introspection>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > AccessTokensService for Service<T, M, F>
+{
+    /// Creates personal access token of current user, the token itself is returned only once
+    fn create_access_token(&self, payload: NewAccessTokenPayload) -> ServiceFuture<CreatedAccessToken> {
+        let current_uid = match self.dynamic_context.user_id {
+            Some(user_id) => user_id,
+            None => {
+                return Box::new(future::err(
+                    Error::Forbidden.context("Only authorized user can create access tokens").into(),
+                ))
+            }
+        };
+        let repo_factory = self.static_context.repo_factory.clone();
+        let signing_key = self.static_context.hmac_key.clone();
+        let conf = &self.static_context.config.access_tokens;
+        let max_tokens = conf.max_tokens;
+
+        if payload.scopes.is_empty() {
+            return Box::new(future::err(
+                Error::Validate(validation_errors!({"scopes": ["required" => "At least one scope is required"]})).into(),
+            ));
+        }
+        if payload.scopes.iter().any(|scope| !conf.scopes.contains(scope)) {
+            return Box::new(future::err(
+                Error::Validate(validation_errors!({"scopes": ["unknown" => "Unknown scope"]})).into(),
+            ));
+        }
+        if let Some(expiration_s) = payload.expiration_s {
+            if expiration_s == 0 || expiration_s > conf.max_expiration_s {
+                return Box::new(future::err(
+                    Error::Validate(validation_errors!({"expiration_s": ["range" => "Expiration is out of range"]})).into(),
+                ));
+            }
+        }
+
+        let token = format!("{}{}", ACCESS_TOKEN_PREFIX, signed_token_create(&signing_key));
+        let mut scopes = payload.scopes;
+        scopes.sort();
+        scopes.dedup();
+        let new_token = NewAccessToken {
+            id: Uuid::new_v4(),
+            user_id: current_uid,
+            name: payload.name,
+            token_hash: token_hash(&token),
+            scopes,
+            expires_at: payload
+                .expiration_s
+                .map(|expiration_s| SystemTime::now() + Duration::from_secs(expiration_s)),
+        };
+
+        debug!("Creating access token {} of user {}", new_token.name, current_uid);
+
+        self.spawn_on_pool(move |conn| {
+            let access_tokens_repo = repo_factory.create_access_tokens_repo(&*conn, Some(current_uid));
+            conn.transaction::<CreatedAccessToken, FailureError, _>(|| {
+                access_tokens_repo.lock_user(current_uid)?;
+                if access_tokens_repo.list_for_user(current_uid)?.len() as u32 >= max_tokens {
+                    return Err(Error::Validate(validation_errors!({"name": ["too_many_tokens" => "Too many access tokens"]})).into());
+                }
+                let access_token = access_tokens_repo.create(new_token)?;
+                Ok(CreatedAccessToken { access_token, token })
+            })
+            .map_err(|e: FailureError| {
+                e.context("Service access_tokens, create_access_token endpoint error occured.")
+                    .into()
+            })
+        })
+    }
+
+    /// Returns personal access tokens of current user
+    fn get_access_tokens(&self) -> ServiceFuture<Vec<AccessToken>> {
+        let current_uid = match self.dynamic_context.user_id {
+            Some(user_id) => user_id,
+            None => {
+                return Box::new(future::err(
+                    Error::Forbidden.context("Only authorized user can get access tokens").into(),
+                ))
+            }
+        };
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let access_tokens_repo = repo_factory.create_access_tokens_repo(&*conn, Some(current_uid));
+            access_tokens_repo
+                .list_for_user(current_uid)
+                .map_err(|e: FailureError| e.context("Service access_tokens, get_access_tokens endpoint error occured.").into())
+        })
+    }
+
+    /// Revokes personal access token of current user
+    fn revoke_access_token(&self, token_id: Uuid) -> ServiceFuture<AccessToken> {
+        let current_uid = match self.dynamic_context.user_id {
+            Some(user_id) => user_id,
+            None => {
+                return Box::new(future::err(
+                    Error::Forbidden.context("Only authorized user can revoke access tokens").into(),
+                ))
+            }
+        };
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        debug!("Revoking access token {} of user {}", token_id, current_uid);
+
+        self.spawn_on_pool(move |conn| {
+            let access_tokens_repo = repo_factory.create_access_tokens_repo(&*conn, Some(current_uid));
+            access_tokens_repo
+                .delete(current_uid, token_id)?
+                .ok_or_else(|| Error::NotFound.context(format!("Access token {} not found", token_id)).into())
+        })
+    }
+
+    /// Resolves personal access token to its user and scopes for an authenticated first-party client
+    fn introspect_access_token(&self, request: IntrospectionRequest) -> ServiceFuture<Introspection> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let users_repo_factory = repo_factory.clone();
+        let signing_key = self.static_context.hmac_key.clone();
+        let read_only = self.static_context.config.read_only;
+        let service = self.clone();
+        let IntrospectionRequest {
+            token,
+            client_id,
+            client_secret,
+        } = request;
+
+        // tokens issued before HMACs got their own key are signed with the JWT key, the signature
+        // only filters out forged tokens before the lookup by hash
+        let signed = token.starts_with(ACCESS_TOKEN_PREFIX) && {
+            let signed_token = &token[ACCESS_TOKEN_PREFIX.len()..];
+            signed_token_verify(&signing_key, signed_token) || signed_token_verify(&self.static_context.jwt_private_key, signed_token)
+        };
+
+        Box::new(
+            self.spawn_on_pool(move |conn| {
+                // RFC 7662 requires the caller to authenticate, public and third-party clients must not resolve tokens to users
+                let clients_repo = repo_factory.create_clients_repo(&*conn);
+                let client = authenticate_client(&*clients_repo, client_id, client_secret)?;
+                if client.secret_hash.is_none() || client.is_third_party {
+                    return Err(format_err!("Client {} is not allowed to introspect tokens", client.id)
+                        .context(Error::OAuth(OAuthErrorCode::InvalidClient))
+                        .into());
+                }
+                if !signed {
+                    return Ok(None);
+                }
+
+                let access_tokens_repo = repo_factory.create_access_tokens_repo(&*conn, None);
+                let now = SystemTime::now();
+                match access_tokens_repo.find_by_hash(token_hash(&token))? {
+                    Some(access_token) if !access_token.is_expired(now) => {
+                        // last use is not recorded by the standby, its replica is not writable
+                        if !read_only {
+                            access_tokens_repo.touch(access_token.id, now)?;
+                        }
+                        Ok(Some(access_token))
+                    }
+                    _ => Ok(None),
+                }
+            })
+            .and_then(move |access_token| -> ServiceFuture<Introspection> {
+                let access_token = match access_token {
+                    Some(access_token) => access_token,
+                    None => return Box::new(future::ok(Introspection::default())),
+                };
+                // tokens of blocked and deleted users are not active
+                service.spawn_on_shard(access_token.user_id, move |conn| {
+                    let users_repo = users_repo_factory.create_users_repo_with_sys_acl(&*conn);
+                    Ok(match users_repo.find(access_token.user_id)? {
+                        Some(ref user) if !user.is_blocked => introspection(&access_token),
+                        _ => Introspection::default(),
+                    })
+                })
+            })
+            .map_err(|e: FailureError| {
+                e.context("Service access_tokens, introspect_access_token endpoint error occured.")
+                    .into()
+            }),
+        )
+    }
+}
+
+fn introspection(access_token: &AccessToken) -> Introspection {
+    Introspection {
+        active: true,
+        user_id: Some(access_token.user_id),
+        scope: Some(access_token.scopes.join(" ")),
+        exp: access_token
+            .expires_at
+            .and_then(|expires_at| expires_at.duration_since(UNIX_EPOCH).ok())
+            .map(|exp| exp.as_secs() as i64),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use tokio_core::reactor::Core;
+    use uuid::Uuid;
+
+    use stq_types::UserId;
+
+    use models::*;
+    use repos::repo_factory::tests::*;
+    use services::access_tokens::*;
+    use services::util::signed_token_create;
+
+    fn create_payload(scopes: &[&str]) -> NewAccessTokenPayload {
+        NewAccessTokenPayload {
+            name: "ci".to_string(),
+            scopes: scopes.iter().map(|scope| scope.to_string()).collect(),
+            expiration_s: None,
+        }
+    }
+
+    #[test]
+    fn test_create_access_token() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.create_access_token(create_payload(&["write", "read", "read"]));
+        let result = core.run(work).unwrap();
+        assert_eq!(result.token.starts_with(ACCESS_TOKEN_PREFIX), true);
+        assert_eq!(result.access_token.token_hash, token_hash(&result.token));
+        assert_eq!(result.access_token.scopes, vec!["read".to_string(), "write".to_string()]);
+    }
+
+    #[test]
+    fn test_create_access_token_unknown_scope() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.create_access_token(create_payload(&["admin"]));
+        assert_eq!(core.run(work).is_err(), true);
+    }
+
+    #[test]
+    fn test_revoke_unknown_access_token() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.revoke_access_token(Uuid::new_v4());
+        assert_eq!(core.run(work).is_err(), true);
+    }
+
+    fn introspection_request(token: String, client_id: &str, client_secret: Option<&str>) -> IntrospectionRequest {
+        IntrospectionRequest {
+            token,
+            client_id: Some(client_id.to_string()),
+            client_secret: client_secret.map(|secret| secret.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_introspect_access_token() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let token = format!("{}{}", ACCESS_TOKEN_PREFIX, signed_token_create(&service.static_context.hmac_key));
+        let request = introspection_request(token, MOCK_CONFIDENTIAL_CLIENT_ID, Some(MOCK_CLIENT_SECRET));
+        let work = service.introspect_access_token(request);
+        let result = core.run(work).unwrap();
+        assert_eq!(result.active, true);
+        assert_eq!(result.user_id, Some(UserId(1)));
+        assert_eq!(result.scope, Some("read".to_string()));
+    }
+
+    #[test]
+    fn test_introspect_access_token_unauthenticated() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let token = format!("{}{}", ACCESS_TOKEN_PREFIX, signed_token_create(&service.static_context.hmac_key));
+
+        let work = service.introspect_access_token(introspection_request(token.clone(), MOCK_CLIENT_ID, None));
+        assert_eq!(core.run(work).is_err(), true);
+
+        let work = service.introspect_access_token(introspection_request(token, MOCK_CONFIDENTIAL_CLIENT_ID, Some("wrong")));
+        assert_eq!(core.run(work).is_err(), true);
+    }
+
+    #[test]
+    fn test_introspect_forged_access_token() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let token = format!("{}{}", ACCESS_TOKEN_PREFIX, MOCK_TOKEN);
+        let request = introspection_request(token, MOCK_CONFIDENTIAL_CLIENT_ID, Some(MOCK_CLIENT_SECRET));
+        let work = service.introspect_access_token(request);
+        let result = core.run(work).unwrap();
+        assert_eq!(result, Introspection::default());
+    }
+}
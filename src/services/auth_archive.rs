@@ -0,0 +1,469 @@
+//! Auth archive Services, exports and imports identities with password hashes and roles of users,
+//! for moving auth data between environments or restoring a tenant. Archives are encrypted with
+//! AES-256-GCM by the key shared by the environments exchanging them.
+//!
+//! Endpoints are disabled unless `auth_archive` is configured and are allowed to superusers
+//! calling from configured addresses only. Every export and import is recorded in the audit log.
+//! Users themselves are not archived, an import restores auth data of users already present.
+//! Sensitive roles are not restored right away, the import requests them as `POST /roles` does.
+
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::time::SystemTime;
+
+use base64;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::future;
+use futures::Future;
+use r2d2::ManageConnection;
+use ring::aead::{self, OpeningKey, SealingKey, AES_256_GCM};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_json;
+
+use stq_types::{UserId, UsersRole};
+
+use config;
+use errors::Error;
+use models::*;
+use repos::repo_factory::ReposFactory;
+use repos::{IdentitiesRepo, UserRolesRepo, UsersRepo};
+use services::types::ServiceFuture;
+use services::user_roles::request_role;
+use services::work_pool::WorkPriority;
+use services::Service;
+
+/// Prefix of archives, it is authenticated along with the contents
+const ARCHIVE_PREFIX: &str = "auth-archive-v1.";
+const NONCE_LEN: usize = 12;
+
+pub trait AuthArchiveService {
+    /// Exports identities and roles of all users to encrypted archive
+    fn export_auth_data(&self) -> ServiceFuture<AuthArchive>;
+    /// Imports identities and roles from encrypted archive, present rows are kept
+    fn import_auth_data(&self, payload: AuthArchive) -> ServiceFuture<AuthArchiveImport>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > AuthArchiveService for Service<T, M, F>
+{
+    /// Exports identities and roles of all users to encrypted archive
+    fn export_auth_data(&self) -> ServiceFuture<AuthArchive> {
+        let (current_uid, key) = match archive_access(
+            self.static_context.config.auth_archive.as_ref(),
+            self.dynamic_context.client_ip,
+            self.dynamic_context.user_id,
+        ) {
+            Ok(access) => access,
+            Err(e) => return Box::new(future::err(e)),
+        };
+        let repo_factory = self.static_context.repo_factory.clone();
+        let cpu_pool = self.static_context.work_pool.cpu_pool(WorkPriority::Batch);
+        let service = self.clone();
+        let audit_service = self.clone();
+
+        info!(
+            "Exporting auth data by user {} from {:?}",
+            current_uid, self.dynamic_context.client_ip
+        );
+
+        Box::new(
+            check_superuser(self, current_uid)
+                .and_then(move |_| {
+                    service.spawn_on_all_shards(move |conn| {
+                        let identities_repo = repo_factory.create_identities_repo(&*conn);
+                        let user_roles_repo = repo_factory.create_user_roles_repo_with_sys_acl(&*conn);
+                        Ok((identities_repo.list_all()?, user_roles_repo.list_all()?))
+                    })
+                })
+                .and_then(move |shards| {
+                    let mut data = AuthArchiveData {
+                        version: AUTH_ARCHIVE_VERSION,
+                        created_at: SystemTime::now(),
+                        identities: vec![],
+                        roles: vec![],
+                    };
+                    for (identities, roles) in shards {
+                        data.identities.extend(identities);
+                        data.roles.extend(roles.into_iter().map(ArchivedRole::from));
+                    }
+                    // large archives are encrypted on the pool not to block the event loop
+                    cpu_pool.spawn_fn(move || {
+                        let mut counts = serde_json::Map::new();
+                        counts.insert("identities".to_string(), data.identities.len().into());
+                        counts.insert("roles".to_string(), data.roles.len().into());
+                        seal_archive(&key, &data).map(|archive| (AuthArchive { archive }, counts))
+                    })
+                })
+                .and_then(move |(archive, counts)| {
+                    audit(&audit_service, current_uid, AuditAction::AuthDataExported, counts.into()).map(|_| archive)
+                })
+                .map_err(|e: FailureError| e.context("Service auth_archive, export_auth_data endpoint error occured.").into()),
+        )
+    }
+
+    /// Imports identities and roles from encrypted archive, present rows are kept
+    fn import_auth_data(&self, payload: AuthArchive) -> ServiceFuture<AuthArchiveImport> {
+        let (current_uid, key) = match archive_access(
+            self.static_context.config.auth_archive.as_ref(),
+            self.dynamic_context.client_ip,
+            self.dynamic_context.user_id,
+        ) {
+            Ok(access) => access,
+            Err(e) => return Box::new(future::err(e)),
+        };
+        let cpu_pool = self.static_context.work_pool.cpu_pool(WorkPriority::Batch);
+        let sensitive_roles = self.static_context.config.role_approvals.sensitive_roles.clone();
+        let service = self.clone();
+        let requests_service = self.clone();
+        let audit_service = self.clone();
+
+        info!(
+            "Importing auth data by user {} from {:?}",
+            current_uid, self.dynamic_context.client_ip
+        );
+
+        Box::new(
+            check_superuser(self, current_uid)
+                .and_then(move |_| cpu_pool.spawn_fn(move || open_archive(&key, &payload.archive)))
+                .and_then(move |data| {
+                    let shards = split_by_shard(data, |user_id| service.static_context.db_pool.shard_index(user_id));
+                    let imports = shards
+                        .into_iter()
+                        .map(|(user_id, identities, roles)| {
+                            let repo_factory = service.static_context.repo_factory.clone();
+                            let sensitive_roles = sensitive_roles.clone();
+                            service.spawn_on_shard(user_id, move |conn| {
+                                let users_repo = repo_factory.create_users_repo_with_sys_acl(&*conn);
+                                let identities_repo = repo_factory.create_identities_repo(&*conn);
+                                let user_roles_repo = repo_factory.create_user_roles_repo_with_sys_acl(&*conn);
+                                conn.transaction(|| {
+                                    import_shard(
+                                        &*users_repo,
+                                        &*identities_repo,
+                                        &*user_roles_repo,
+                                        &sensitive_roles,
+                                        identities,
+                                        roles,
+                                    )
+                                })
+                            })
+                        })
+                        .collect::<Vec<_>>();
+                    future::join_all(imports)
+                })
+                .and_then(move |imports| {
+                    // role requests are kept on the primary shard, another admin approves them
+                    let repo_factory = requests_service.static_context.repo_factory.clone();
+                    requests_service.spawn_on_pool(move |conn| {
+                        let role_requests_repo = repo_factory.create_role_requests_repo(&*conn, Some(current_uid));
+                        let audit_log_repo = repo_factory.create_audit_log_repo(&*conn, Some(current_uid));
+                        conn.transaction::<AuthArchiveImport, FailureError, _>(|| {
+                            let mut import = AuthArchiveImport::default();
+                            for (shard_import, requested_roles) in imports {
+                                import = import.add(shard_import);
+                                for role in requested_roles {
+                                    request_role(&*role_requests_repo, &*audit_log_repo, role, current_uid)?;
+                                    import.roles_requested += 1;
+                                }
+                            }
+                            Ok(import)
+                        })
+                    })
+                })
+                .and_then(move |import| {
+                    future::result(serde_json::to_value(&import))
+                        .map_err(FailureError::from)
+                        .and_then(move |data| audit(&audit_service, current_uid, AuditAction::AuthDataImported, data))
+                        .map(move |_| import)
+                })
+                .map_err(|e: FailureError| e.context("Service auth_archive, import_auth_data endpoint error occured.").into()),
+        )
+    }
+}
+
+/// Checks that the endpoints are configured and the caller is allowed to use them,
+/// returns the caller and the archive key
+fn archive_access(
+    conf: Option<&config::AuthArchive>,
+    client_ip: Option<IpAddr>,
+    current_uid: Option<UserId>,
+) -> Result<(UserId, Vec<u8>), FailureError> {
+    let conf = conf.ok_or_else(|| Error::NotFound.context("Auth archive is not configured"))?;
+    match client_ip {
+        Some(ip) if conf.allowed_ips.contains(&ip) => (),
+        _ => {
+            return Err(Error::Forbidden
+                .context(format!("Address {:?} is not allowed to access auth archive", client_ip))
+                .into())
+        }
+    }
+    let current_uid = current_uid.ok_or_else(|| Error::Forbidden.context("Auth archive is not available to anonymous users"))?;
+    let key = base64::decode(&conf.key).map_err(|e| e.context("Auth archive key is not valid base64"))?;
+    Ok((current_uid, key))
+}
+
+fn check_superuser<T, M, F>(service: &Service<T, M, F>, current_uid: UserId) -> ServiceFuture<()>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    let repo_factory = service.static_context.repo_factory.clone();
+    service.spawn_on_shard(current_uid, move |conn| {
+        let user_roles_repo = repo_factory.create_user_roles_repo_with_sys_acl(&*conn);
+        if user_roles_repo.list_for_user(current_uid)?.contains(&UsersRole::Superuser) {
+            Ok(())
+        } else {
+            Err(Error::Forbidden
+                .context(format!("User {} is not allowed to access auth archive", current_uid))
+                .into())
+        }
+    })
+}
+
+fn audit<T, M, F>(service: &Service<T, M, F>, current_uid: UserId, action: AuditAction, data: serde_json::Value) -> ServiceFuture<()>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    let repo_factory = service.static_context.repo_factory.clone();
+    service.spawn_on_shard(current_uid, move |conn| {
+        let audit_log_repo = repo_factory.create_audit_log_repo(&*conn, Some(current_uid));
+        audit_log_repo
+            .add(NewAuditEvent {
+                user_id: current_uid,
+                actor_id: Some(current_uid),
+                action,
+                data: Some(data),
+            })
+            .map(|_| ())
+    })
+}
+
+/// Groups archived rows by shard of their users, every group comes with a user to find the shard by
+fn split_by_shard<S>(data: AuthArchiveData, shard_index: S) -> Vec<(UserId, Vec<Identity>, Vec<ArchivedRole>)>
+where
+    S: Fn(UserId) -> usize,
+{
+    let mut shards = BTreeMap::new();
+    for ident in data.identities {
+        let user_id = ident.user_id;
+        shards
+            .entry(shard_index(user_id))
+            .or_insert_with(|| (user_id, vec![], vec![]))
+            .1
+            .push(ident);
+    }
+    for role in data.roles {
+        let user_id = role.user_id;
+        shards
+            .entry(shard_index(user_id))
+            .or_insert_with(|| (user_id, vec![], vec![]))
+            .2
+            .push(role);
+    }
+    shards.into_iter().map(|(_, shard)| shard).collect()
+}
+
+/// Imports rows of one shard. Rows of users missing in this environment are skipped, as are
+/// identities of users or e-mails having one already and roles the users already have.
+/// Sensitive roles are returned to be requested instead, so that they are granted with approval only.
+fn import_shard(
+    users_repo: &UsersRepo,
+    identities_repo: &IdentitiesRepo,
+    user_roles_repo: &UserRolesRepo,
+    sensitive_roles: &[UsersRole],
+    identities: Vec<Identity>,
+    roles: Vec<ArchivedRole>,
+) -> Result<(AuthArchiveImport, Vec<NewUserRole>), FailureError> {
+    let mut import = AuthArchiveImport::default();
+    let mut requested_roles = vec![];
+    for ident in identities {
+        if users_repo.find(ident.user_id)?.is_some() && identities_repo.restore(ident)? {
+            import.identities_imported += 1;
+        } else {
+            import.identities_skipped += 1;
+        }
+    }
+    for role in roles {
+        let expired = role.expires_at.map_or(false, |expires_at| expires_at <= SystemTime::now());
+        if expired || users_repo.find(role.user_id)?.is_none() || user_roles_repo.list_for_user(role.user_id)?.contains(&role.name) {
+            import.roles_skipped += 1;
+            continue;
+        }
+        let new_role = NewUserRole {
+            id: None,
+            user_id: role.user_id,
+            name: role.name,
+            data: role.data,
+            expires_at: role.expires_at,
+        };
+        if sensitive_roles.contains(&new_role.name) {
+            requested_roles.push(new_role);
+            continue;
+        }
+        user_roles_repo.create(new_role)?;
+        import.roles_imported += 1;
+    }
+    Ok((import, requested_roles))
+}
+
+fn seal_archive(key: &[u8], data: &AuthArchiveData) -> Result<String, FailureError> {
+    let sealing_key =
+        SealingKey::new(&AES_256_GCM, key).map_err(|_| format_err!("Auth archive key must be {} bytes", AES_256_GCM.key_len()))?;
+    let mut nonce = [0u8; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| format_err!("Couldn't generate nonce of auth archive"))?;
+
+    let tag_len = AES_256_GCM.tag_len();
+    let mut in_out = serde_json::to_vec(data)?;
+    in_out.extend(vec![0u8; tag_len]);
+    let sealed_len = aead::seal_in_place(&sealing_key, &nonce, ARCHIVE_PREFIX.as_bytes(), &mut in_out, tag_len)
+        .map_err(|_| format_err!("Couldn't encrypt auth archive"))?;
+
+    let mut sealed = nonce.to_vec();
+    sealed.extend_from_slice(&in_out[..sealed_len]);
+    Ok(format!("{}{}", ARCHIVE_PREFIX, base64::encode(&sealed)))
+}
+
+fn open_archive(key: &[u8], archive: &str) -> Result<AuthArchiveData, FailureError> {
+    let opening_key =
+        OpeningKey::new(&AES_256_GCM, key).map_err(|_| format_err!("Auth archive key must be {} bytes", AES_256_GCM.key_len()))?;
+    let invalid = || -> FailureError {
+        Error::Validate(validation_errors!({"archive": ["invalid" => "Archive is damaged or encrypted by another key"]})).into()
+    };
+    if !archive.starts_with(ARCHIVE_PREFIX) {
+        return Err(invalid());
+    }
+    let mut sealed = base64::decode(&archive[ARCHIVE_PREFIX.len()..]).map_err(|_| invalid())?;
+    if sealed.len() < NONCE_LEN {
+        return Err(invalid());
+    }
+
+    let (nonce, in_out) = sealed.split_at_mut(NONCE_LEN);
+    let contents = aead::open_in_place(&opening_key, nonce, ARCHIVE_PREFIX.as_bytes(), 0, in_out).map_err(|_| invalid())?;
+    let data: AuthArchiveData = serde_json::from_slice(contents).map_err(|e| e.context(Error::Parse))?;
+    if data.version != AUTH_ARCHIVE_VERSION {
+        return Err(Error::Validate(validation_errors!({"archive": ["version" => "Archive version is not supported"]})).into());
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use tokio_core::reactor::{Core, Handle};
+
+    use super::*;
+    use repos::repo_factory::tests::*;
+
+    const ALLOWED_IP: &str = "10.0.0.1";
+
+    fn create_archive_service(
+        user_id: UserId,
+        client_ip: &str,
+        handle: Arc<Handle>,
+    ) -> Service<MockConnection, MockConnectionManager, ReposFactoryMock> {
+        let mut service = create_service(Some(user_id), handle);
+        {
+            let static_context = Arc::make_mut(&mut service.static_context);
+            Arc::make_mut(&mut static_context.config).auth_archive = Some(config::AuthArchive {
+                key: base64::encode(&[7u8; 32]),
+                allowed_ips: vec![ALLOWED_IP.parse().unwrap()],
+            });
+        }
+        Arc::make_mut(&mut service.dynamic_context).client_ip = Some(client_ip.parse().unwrap());
+        service
+    }
+
+    #[test]
+    fn test_export_and_import_auth_data() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_archive_service(UserId(1), ALLOWED_IP, handle);
+        let archive = core.run(service.export_auth_data()).unwrap();
+        assert_eq!(archive.archive.starts_with(ARCHIVE_PREFIX), true);
+
+        // mocked identity and role are present already
+        let import = core.run(service.import_auth_data(archive)).unwrap();
+        assert_eq!(
+            import,
+            AuthArchiveImport {
+                identities_imported: 0,
+                identities_skipped: 1,
+                roles_imported: 0,
+                roles_skipped: 1,
+                roles_requested: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_import_requests_sensitive_roles() {
+        let archived_role = |name| ArchivedRole {
+            user_id: UserId(2),
+            name,
+            data: None,
+            expires_at: None,
+        };
+        let (import, requested_roles) = import_shard(
+            &UsersRepoMock::default(),
+            &IdentitiesRepoMock::default(),
+            &UserRolesRepoMock::default(),
+            &[UsersRole::Superuser],
+            vec![],
+            vec![archived_role(UsersRole::Superuser), archived_role(UsersRole::Moderator)],
+        )
+        .unwrap();
+        assert_eq!(import.roles_imported, 1);
+        assert_eq!(import.roles_requested, 0);
+        assert_eq!(requested_roles.len(), 1);
+        assert_eq!(requested_roles[0].name, UsersRole::Superuser);
+    }
+
+    #[test]
+    fn test_auth_archive_access_is_restricted() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_archive_service(UserId(1), "10.0.0.2", handle.clone());
+        assert_eq!(core.run(service.export_auth_data()).is_err(), true);
+
+        let service = create_archive_service(UserId(2), ALLOWED_IP, handle);
+        assert_eq!(core.run(service.export_auth_data()).is_err(), true);
+    }
+
+    #[test]
+    fn test_tampered_archive_is_rejected() {
+        let key = [7u8; 32];
+        let data = AuthArchiveData {
+            version: AUTH_ARCHIVE_VERSION,
+            created_at: SystemTime::now(),
+            identities: vec![],
+            roles: vec![ArchivedRole {
+                user_id: UserId(1),
+                name: UsersRole::Superuser,
+                data: None,
+                expires_at: None,
+            }],
+        };
+        let archive = seal_archive(&key, &data).unwrap();
+        assert_eq!(open_archive(&key, &archive).unwrap().roles, data.roles);
+        assert_eq!(open_archive(&[8u8; 32], &archive).is_err(), true);
+
+        let mut sealed = base64::decode(&archive[ARCHIVE_PREFIX.len()..]).unwrap();
+        let middle = sealed.len() / 2;
+        sealed[middle] ^= 1;
+        let tampered = format!("{}{}", ARCHIVE_PREFIX, base64::encode(&sealed));
+        assert_eq!(open_archive(&key, &tampered).is_err(), true);
+    }
+}
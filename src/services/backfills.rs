@@ -0,0 +1,185 @@
+//! Backfills bringing rows stored before a normalization change to the current form. They run in
+//! background once on startup, every shard in batches. Backfills are idempotent, so instances
+//! starting together don't conflict; rows which can't be fixed are logged for manual review.
+//!
+//! Once PII encryption is configured, PII of users stored in plain text is sealed after the
+//! normalizations and emails of their identities are replaced by blind indexes, see `repos::pii_cipher`.
+
+use std::io;
+use std::thread::{self, JoinHandle};
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use r2d2::{ManageConnection, Pool};
+
+use stq_types::UserId;
+
+use errors::Error;
+use models::{normalize_email, normalize_phone};
+use repos::sharding::ShardedPool;
+use repos::{BackfillsRepo, IdentitiesRepo, ReposFactory, UsersRepo};
+
+const BATCH_SIZE: i64 = 100;
+
+/// Runs backfills on all shards in background, phones in national format are resolved with `phone_region`.
+/// PII of users is sealed if `seal_pii` is set.
+pub fn start_backfills<T, M, F>(
+    db_pool: ShardedPool<M>,
+    repo_factory: F,
+    phone_region: String,
+    seal_pii: bool,
+) -> io::Result<JoinHandle<()>>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    thread::Builder::new().name("backfills".to_string()).spawn(move || {
+        for pool in db_pool.shards() {
+            if let Err(e) = normalize_idn_emails(pool, &repo_factory) {
+                error!("IDN emails were not normalized: {}", e);
+            }
+            if let Err(e) = pool
+                .get()
+                .map_err(|e| e.context(Error::Connection).into())
+                .and_then(|conn| normalize_national_phones(&*repo_factory.create_backfills_repo(&*conn), &phone_region))
+            {
+                error!("National phones were not normalized: {}", e);
+            }
+            if !seal_pii {
+                continue;
+            }
+            if let Err(e) = pool.get().map_err(|e| e.context(Error::Connection).into()).and_then(|conn| {
+                seal_users_pii(
+                    &*repo_factory.create_backfills_repo(&*conn),
+                    &*repo_factory.create_users_repo_with_sys_acl(&*conn),
+                )
+            }) {
+                error!("PII of users was not sealed: {}", e);
+            }
+            if let Err(e) = pool.get().map_err(|e| e.context(Error::Connection).into()).and_then(|conn| {
+                seal_identities_emails(
+                    &*repo_factory.create_backfills_repo(&*conn),
+                    &*repo_factory.create_identities_repo(&*conn),
+                )
+            }) {
+                error!("Emails of identities were not sealed: {}", e);
+            }
+        }
+    })
+}
+
+/// Converts IDN domains of emails stored in Unicode to punycode, see `models::normalize_email`
+fn normalize_idn_emails<T, M, F>(pool: &Pool<M>, repo_factory: &F) -> Result<(), FailureError>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    let conn = pool.get().map_err(|e| e.context(Error::Connection))?;
+    let backfills_repo = repo_factory.create_backfills_repo(&*conn);
+    let mut from = UserId(0);
+    loop {
+        let batch = backfills_repo.list_idn_emails(from, BATCH_SIZE)?;
+        if batch.is_empty() {
+            return Ok(());
+        }
+        for (user_id, email) in batch {
+            from = user_id;
+            let normalized = normalize_email(&email);
+            // the same address may be registered with ASCII domain too, it is left for review
+            if let Err(e) = conn.transaction::<_, FailureError, _>(|| backfills_repo.set_email(user_id, normalized)) {
+                error!("Email of user {} was not normalized: {}", user_id, e);
+            }
+        }
+    }
+}
+
+/// Converts phones stored in national format to E.164, see `models::normalize_phone`. Numbers
+/// which can't be resolved with the region are reported and left as they are.
+fn normalize_national_phones(backfills_repo: &BackfillsRepo, phone_region: &str) -> Result<usize, FailureError> {
+    let mut from = UserId(0);
+    let mut normalized = 0;
+    loop {
+        let batch = backfills_repo.list_national_phones(from, BATCH_SIZE)?;
+        if batch.is_empty() {
+            return Ok(normalized);
+        }
+        for (user_id, phone) in batch {
+            from = user_id;
+            match normalize_phone(&phone, phone_region) {
+                Some(e164) => {
+                    backfills_repo.set_phone(user_id, e164)?;
+                    normalized += 1;
+                }
+                None => error!("Phone of user {} can not be normalized with region {}", user_id, phone_region),
+            }
+        }
+    }
+}
+
+/// Seals PII of users stored in plain text, returns the number of sealed users
+fn seal_users_pii(backfills_repo: &BackfillsRepo, users_repo: &UsersRepo) -> Result<usize, FailureError> {
+    let mut from = UserId(0);
+    let mut sealed = 0;
+    loop {
+        let batch = backfills_repo.list_unsealed(from, BATCH_SIZE)?;
+        if batch.is_empty() {
+            return Ok(sealed);
+        }
+        for user_id in batch {
+            from = user_id;
+            if users_repo.seal_pii(user_id)? {
+                sealed += 1;
+            }
+        }
+    }
+}
+
+/// Replaces emails of identities of sealed users by blind indexes, returns the number of sealed identities
+fn seal_identities_emails(backfills_repo: &BackfillsRepo, ident_repo: &IdentitiesRepo) -> Result<usize, FailureError> {
+    let mut from = UserId(0);
+    let mut sealed = 0;
+    loop {
+        let batch = backfills_repo.list_unsealed_identities(from, BATCH_SIZE)?;
+        if batch.is_empty() {
+            return Ok(sealed);
+        }
+        for user_id in batch {
+            from = user_id;
+            if ident_repo.seal_email(user_id)? {
+                sealed += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use repos::repo_factory::tests::*;
+    use services::backfills::*;
+
+    #[test]
+    fn test_normalize_national_phones() {
+        let backfills_repo = BackfillsRepoMock::default();
+        assert_eq!(normalize_national_phones(&backfills_repo, "RU").unwrap(), 1);
+        assert_eq!(normalize_national_phones(&backfills_repo, "XX").unwrap(), 0);
+    }
+
+    #[test]
+    fn test_seal_users_pii() {
+        let backfills_repo = BackfillsRepoMock::default();
+        let users_repo = UsersRepoMock::default();
+        assert_eq!(seal_users_pii(&backfills_repo, &users_repo).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_seal_identities_emails() {
+        let backfills_repo = BackfillsRepoMock::default();
+        let ident_repo = IdentitiesRepoMock::default();
+        assert_eq!(seal_identities_emails(&backfills_repo, &ident_repo).unwrap(), 1);
+    }
+}
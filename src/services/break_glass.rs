@@ -0,0 +1,296 @@
+//! Break-glass Services, emergency superuser access for incidents where normal admin access is
+//! broken, e.g. superusers are locked out or their roles were revoked by mistake. The account is
+//! configured by `break_glass` and is activated by a token signed offline by the break-glass key,
+//! so that activation depends on nothing the incident may have broken.
+//!
+//! Activation grants superuser role to the account for `duration_s` bypassing role approvals,
+//! records it in the audit log and pages the webhook. Every token activates the account once.
+//! Requests of the account are written to the access log at warn level, see `controller`.
+
+use std::fs;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::future;
+use futures::Future;
+use futures::IntoFuture;
+use hyper::Method;
+use jsonwebtoken::{decode, encode, Algorithm, Header, Validation};
+use r2d2::ManageConnection;
+use serde_json;
+
+use stq_http::client::HttpClient;
+use stq_static_resources::Provider;
+use stq_types::{UserId, UsersRole};
+
+use errors::Error;
+use models::*;
+use repos::repo_factory::ReposFactory;
+use services::event_schemas::{validate_event, BREAK_GLASS_ACTIVATED};
+use services::types::ServiceFuture;
+use services::util::analytics_id;
+use services::Service;
+
+const BREAK_GLASS_DOMAIN: &'static str = "break_glass";
+
+pub trait BreakGlassService {
+    /// Activates break-glass account by offline signed token, returns token of the account
+    fn activate_break_glass(&self, payload: BreakGlassActivation) -> ServiceFuture<JWT>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > BreakGlassService for Service<T, M, F>
+{
+    /// Activates break-glass account by offline signed token, returns token of the account
+    fn activate_break_glass(&self, payload: BreakGlassActivation) -> ServiceFuture<JWT> {
+        let conf = match self.static_context.config.break_glass.clone() {
+            Some(conf) => conf,
+            None => return Box::new(future::err(Error::NotFound.context("Break-glass access is not configured").into())),
+        };
+        let repo_factory = self.static_context.repo_factory.clone();
+        let jwt_private_key = self.static_context.jwt_private_key.clone();
+        let analytics = self.static_context.config.analytics.clone();
+        let http_client = self.dynamic_context.http_client.clone();
+        let client_ip = self.dynamic_context.client_ip;
+        let user_id = conf.user_id;
+        let webhook_url = conf.webhook_url.clone();
+
+        warn!("Break-glass activation of user {} requested from {:?}", user_id, client_ip);
+
+        let fut = self
+            .spawn_on_shard(user_id, move |conn| {
+                let public_key = fs::read(&conf.public_key_path)
+                    .map_err(|e| e.context(format!("Couldn't read break-glass public key {}", conf.public_key_path)))?;
+                let claims = verify_break_glass_token(&payload.token, &public_key)?;
+
+                let users_repo = repo_factory.create_users_repo_with_sys_acl(&*conn);
+                let user_roles_repo = repo_factory.create_user_roles_repo_with_sys_acl(&*conn);
+                let audit_log_repo = repo_factory.create_audit_log_repo_with_sys_acl(&*conn);
+                let used_tokens_repo = repo_factory.create_used_tokens_repo(&*conn);
+
+                users_repo
+                    .find(user_id)?
+                    .ok_or_else(|| Error::NotFound.context(format!("Break-glass user {} not found", user_id)))?;
+
+                let now = SystemTime::now();
+                let expires_at = now + Duration::from_secs(conf.duration_s);
+                let exp = unix_time(expires_at);
+
+                let mut data = serde_json::Map::new();
+                data.insert("jti".to_string(), claims.jti.clone().into());
+                data.insert("reason".to_string(), claims.reason.clone().into());
+                data.insert("expires_at".to_string(), exp.into());
+                data.insert("client_ip".to_string(), serde_json::to_value(&client_ip)?);
+
+                conn.transaction::<_, FailureError, _>(|| {
+                    // the unique id of used tokens rejects concurrent activations by the same token
+                    let used = NewUsedToken {
+                        id: format!("{}:{}", BREAK_GLASS_DOMAIN, claims.jti),
+                        expires_at: UNIX_EPOCH + Duration::from_secs(claims.exp as u64),
+                    };
+                    if !used_tokens_repo.mark_used(used)? {
+                        return Err(Error::Forbidden
+                            .context(format!("Break-glass token {} was used already", claims.jti))
+                            .into());
+                    }
+                    user_roles_repo.create(NewUserRole {
+                        id: None,
+                        user_id,
+                        name: UsersRole::Superuser,
+                        data: None,
+                        expires_at: Some(expires_at),
+                    })?;
+                    audit_log_repo.add(NewAuditEvent {
+                        user_id,
+                        actor_id: Some(user_id),
+                        action: AuditAction::BreakGlassActivated,
+                        data: Some(data.clone().into()),
+                    })?;
+                    Ok(())
+                })?;
+
+                warn!(
+                    "Break-glass user {} got superuser role until {} by token {}: {}",
+                    user_id, exp, claims.jti, claims.reason
+                );
+
+                let tokenpayload = JWTPayload::new(user_id, exp, Provider::Email)
+                    .with_auth_time(unix_time(now))
+                    .with_analytics_id(analytics_id(&analytics, user_id));
+                let token = encode(&Header::new(Algorithm::RS256), &tokenpayload, jwt_private_key.as_ref()).map_err(|e| {
+                    format_err!("{}", e)
+                        .context(Error::Parse)
+                        .context(format!("Couldn't encode jwt: {:?}.", tokenpayload))
+                })?;
+                Ok((
+                    JWT {
+                        token,
+                        status: UserStatus::Exists,
+                        timing: None,
+                    },
+                    data,
+                ))
+            })
+            .and_then(move |(jwt, mut data)| {
+                data.insert("event".to_string(), BREAK_GLASS_ACTIVATED.into());
+                data.insert("user_id".to_string(), user_id.0.into());
+                let payload = serde_json::Value::from(data);
+                // the account is active already, failed pages must not prevent its use
+                validate_event(BREAK_GLASS_ACTIVATED, &payload)
+                    .into_future()
+                    .and_then(move |_| {
+                        http_client.request_json::<serde_json::Value>(Method::Post, webhook_url, Some(payload.to_string()), None)
+                    })
+                    .then(move |res| {
+                        if let Err(e) = res {
+                            error!("Couldn't page break-glass activation of user {}: {}", user_id, e);
+                        }
+                        Ok(jwt)
+                    })
+            });
+
+        Box::new(fut.map_err(|e: FailureError| {
+            e.context("Service break_glass, activate_break_glass endpoint error occured.")
+                .into()
+        }))
+    }
+}
+
+/// Verifies signature, expiry and subject of break-glass activation token
+pub fn verify_break_glass_token(token: &str, public_key: &[u8]) -> Result<BreakGlassClaims, FailureError> {
+    let validation = Validation {
+        sub: Some(BREAK_GLASS_SUBJECT.to_string()),
+        ..Validation::new(Algorithm::RS256)
+    };
+    decode::<BreakGlassClaims>(token, public_key, &validation)
+        .map(|data| data.claims)
+        .map_err(|e| Error::Forbidden.context(format!("Break-glass token is not valid: {}", e)).into())
+}
+
+fn unix_time(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::fs::File;
+    use std::io::Read;
+    use std::sync::Arc;
+
+    use chrono::Utc;
+    use tokio_core::reactor::{Core, Handle};
+
+    use super::*;
+    use config;
+    use repos::repo_factory::tests::*;
+
+    fn read_key(path: &str) -> Vec<u8> {
+        let mut key = Vec::new();
+        File::open(path).unwrap().read_to_end(&mut key).unwrap();
+        key
+    }
+
+    fn create_claims(sub: &str, exp: i64) -> BreakGlassClaims {
+        BreakGlassClaims {
+            sub: sub.to_string(),
+            jti: "incident-42".to_string(),
+            iat: Utc::now().timestamp(),
+            exp,
+            reason: "Superusers are locked out".to_string(),
+        }
+    }
+
+    fn sign(claims: &BreakGlassClaims) -> String {
+        encode(&Header::new(Algorithm::RS256), claims, &read_key("config/keys/private_key.der")).unwrap()
+    }
+
+    #[test]
+    fn test_verify_break_glass_token() {
+        let public_key = read_key("config/keys/public_key.der");
+        let token = sign(&create_claims(BREAK_GLASS_SUBJECT, Utc::now().timestamp() + 600));
+        let claims = verify_break_glass_token(&token, &public_key).unwrap();
+        assert_eq!(claims.jti, "incident-42");
+    }
+
+    #[test]
+    fn test_verify_expired_break_glass_token() {
+        let public_key = read_key("config/keys/public_key.der");
+        let token = sign(&create_claims(BREAK_GLASS_SUBJECT, Utc::now().timestamp() - 600));
+        assert_eq!(verify_break_glass_token(&token, &public_key).is_err(), true);
+    }
+
+    #[test]
+    fn test_verify_break_glass_token_of_other_subject() {
+        let public_key = read_key("config/keys/public_key.der");
+        let token = sign(&create_claims("user", Utc::now().timestamp() + 600));
+        assert_eq!(verify_break_glass_token(&token, &public_key).is_err(), true);
+    }
+
+    #[test]
+    fn test_verify_tampered_break_glass_token() {
+        let public_key = read_key("config/keys/public_key.der");
+        let token = sign(&create_claims(BREAK_GLASS_SUBJECT, Utc::now().timestamp() + 600));
+        let mut parts = token.split('.').map(str::to_string).collect::<Vec<_>>();
+        parts[1] = sign(&create_claims(BREAK_GLASS_SUBJECT, Utc::now().timestamp() + 6000))
+            .split('.')
+            .nth(1)
+            .unwrap()
+            .to_string();
+        assert_eq!(verify_break_glass_token(&parts.join("."), &public_key).is_err(), true);
+    }
+
+    #[test]
+    fn test_activate_break_glass_not_configured() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let token = sign(&create_claims(BREAK_GLASS_SUBJECT, Utc::now().timestamp() + 600));
+        let work = service.activate_break_glass(BreakGlassActivation { token });
+        assert_eq!(core.run(work).is_err(), true);
+    }
+
+    fn create_break_glass_service(handle: Arc<Handle>) -> Service<MockConnection, MockConnectionManager, ReposFactoryMock> {
+        let mut service = create_service(None, handle);
+        {
+            let static_context = Arc::make_mut(&mut service.static_context);
+            Arc::make_mut(&mut static_context.config).break_glass = Some(config::BreakGlass {
+                user_id: UserId(1),
+                public_key_path: "config/keys/public_key.der".to_string(),
+                duration_s: 3600,
+                webhook_url: "http://127.0.0.1:1/break_glass".to_string(),
+            });
+        }
+        service
+    }
+
+    #[test]
+    fn test_activate_break_glass() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_break_glass_service(handle);
+        let token = sign(&create_claims(BREAK_GLASS_SUBJECT, Utc::now().timestamp() + 600));
+        let work = service.activate_break_glass(BreakGlassActivation { token });
+        let jwt = core.run(work).unwrap();
+        assert_eq!(jwt.token.is_empty(), false);
+    }
+
+    #[test]
+    fn test_activate_break_glass_used_token() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_break_glass_service(handle);
+        let claims = BreakGlassClaims {
+            jti: MOCK_USED_TOKEN_ID.to_string(),
+            ..create_claims(BREAK_GLASS_SUBJECT, Utc::now().timestamp() + 600)
+        };
+        let work = service.activate_break_glass(BreakGlassActivation { token: sign(&claims) });
+        assert_eq!(core.run(work).is_err(), true);
+    }
+}
@@ -0,0 +1,289 @@
+//! Child accounts Services. Parents create limited sub-accounts for their children, view their
+//! profiles and reset their passwords. Child accounts can not have children of their own,
+//! the parent is exposed in tokens of the child for parental control in downstream services.
+//! Links are saved on the primary shard.
+
+use std::time::{Duration, SystemTime};
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::future;
+use futures::Future;
+use r2d2::ManageConnection;
+use uuid::Uuid;
+
+use stq_static_resources::Provider;
+use stq_types::UserId;
+
+use super::password_strength::check_password_policy;
+use super::util::rollout_password_create;
+use errors::Error;
+use models::*;
+use repos::repo_factory::ReposFactory;
+use repos::ChildAccountsRepo;
+use services::types::ServiceFuture;
+use services::users::UsersService;
+use services::Service;
+
+pub trait ChildAccountsService {
+    /// Creates child account of current user
+    fn create_child_account(&self, payload: NewChildAccountPayload) -> ServiceFuture<User>;
+    /// Returns child accounts of current user
+    fn get_child_accounts(&self) -> ServiceFuture<Vec<ChildAccount>>;
+    /// Returns profile of the child of current user
+    fn get_child_profile(&self, child_id: UserId) -> ServiceFuture<User>;
+    /// Sets new password of the child of current user, tokens of the child are revoked
+    fn reset_child_password(&self, child_id: UserId, payload: ChildPasswordReset) -> ServiceFuture<()>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > ChildAccountsService for Service<T, M, F>
+{
+    /// Creates child account of current user
+    fn create_child_account(&self, payload: NewChildAccountPayload) -> ServiceFuture<User> {
+        let current_uid = match self.dynamic_context.user_id {
+            Some(user_id) => user_id,
+            None => {
+                return Box::new(future::err(
+                    Error::Forbidden.context("Only authorized user can create child accounts").into(),
+                ))
+            }
+        };
+        let repo_factory = self.static_context.repo_factory.clone();
+        let link_repo_factory = repo_factory.clone();
+        let max_children = self.static_context.config.child_accounts.max_children;
+        let service = self.clone();
+        let link_service = self.clone();
+
+        debug!("Creating child account of user {}", current_uid);
+
+        Box::new(
+            self.spawn_on_pool(move |conn| {
+                let child_accounts_repo = repo_factory.create_child_accounts_repo_with_sys_acl(&*conn);
+                if child_accounts_repo.find_by_child(current_uid)?.is_some() {
+                    return Err(Error::Forbidden
+                        .context(format!("Child account {} can not have child accounts", current_uid))
+                        .into());
+                }
+                if child_accounts_repo.list_children(current_uid)?.len() as u32 >= max_children {
+                    return Err(Error::Validate(validation_errors!({"email": ["too_many_children" => "Too many child accounts"]})).into());
+                }
+                Ok(())
+            })
+            .and_then(move |_| {
+                let invite_code = payload.invite_code.clone();
+                let (identity, user) = payload.into_identity(Uuid::new_v4().to_string());
+                service.create(identity, Some(user), invite_code)
+            })
+            .and_then(move |user| {
+                link_service.spawn_on_pool(move |conn| {
+                    let child_accounts_repo = link_repo_factory.create_child_accounts_repo(&*conn, Some(current_uid));
+                    child_accounts_repo
+                        .create(NewChildAccount {
+                            child_id: user.id,
+                            parent_id: current_uid,
+                        })
+                        .map(|_| user)
+                })
+            })
+            .map_err(|e: FailureError| {
+                e.context("Service child_accounts, create_child_account endpoint error occured.")
+                    .into()
+            }),
+        )
+    }
+
+    /// Returns child accounts of current user
+    fn get_child_accounts(&self) -> ServiceFuture<Vec<ChildAccount>> {
+        let current_uid = match self.dynamic_context.user_id {
+            Some(user_id) => user_id,
+            None => {
+                return Box::new(future::err(
+                    Error::Forbidden.context("Only authorized user can get child accounts").into(),
+                ))
+            }
+        };
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let child_accounts_repo = repo_factory.create_child_accounts_repo(&*conn, Some(current_uid));
+            child_accounts_repo.list_children(current_uid).map_err(|e: FailureError| {
+                e.context("Service child_accounts, get_child_accounts endpoint error occured.")
+                    .into()
+            })
+        })
+    }
+
+    /// Returns profile of the child of current user
+    fn get_child_profile(&self, child_id: UserId) -> ServiceFuture<User> {
+        let current_uid = match self.dynamic_context.user_id {
+            Some(user_id) => user_id,
+            None => {
+                return Box::new(future::err(
+                    Error::Forbidden.context("Only authorized user can get child accounts").into(),
+                ))
+            }
+        };
+        let repo_factory = self.static_context.repo_factory.clone();
+        let users_repo_factory = repo_factory.clone();
+        let service = self.clone();
+
+        Box::new(
+            self.spawn_on_pool(move |conn| {
+                let child_accounts_repo = repo_factory.create_child_accounts_repo(&*conn, Some(current_uid));
+                find_child(&*child_accounts_repo, current_uid, child_id)
+            })
+            .and_then(move |_| {
+                service.spawn_on_shard(child_id, move |conn| {
+                    let users_repo = users_repo_factory.create_users_repo_with_sys_acl(&*conn);
+                    users_repo
+                        .find(child_id)?
+                        .ok_or_else(|| Error::NotFound.context(format!("User {} not found", child_id)).into())
+                })
+            })
+            .map_err(|e: FailureError| {
+                e.context("Service child_accounts, get_child_profile endpoint error occured.")
+                    .into()
+            }),
+        )
+    }
+
+    /// Sets new password of the child of current user, tokens of the child are revoked
+    fn reset_child_password(&self, child_id: UserId, payload: ChildPasswordReset) -> ServiceFuture<()> {
+        let current_uid = match self.dynamic_context.user_id {
+            Some(user_id) => user_id,
+            None => {
+                return Box::new(future::err(
+                    Error::Forbidden
+                        .context("Only authorized user can reset password of child accounts")
+                        .into(),
+                ))
+            }
+        };
+        let repo_factory = self.static_context.repo_factory.clone();
+        let jwt_expiration_s = self.static_context.config.tokens.jwt_expiration_s;
+        let password_strength = self.static_context.config.password_strength.clone();
+        let rollouts = self.static_context.rollouts.clone();
+        // tokens of the child given before now are revoked
+        let revoke_before = SystemTime::now() + Duration::from_secs(jwt_expiration_s);
+
+        debug!("Resetting password of child account {} by user {}", child_id, current_uid);
+
+        self.spawn_on_pool(move |conn| {
+            let child_accounts_repo = repo_factory.create_child_accounts_repo(&*conn, Some(current_uid));
+            let ident_repo = repo_factory.create_identities_repo(&*conn);
+            let users_repo = repo_factory.create_users_repo_with_sys_acl(&*conn);
+            let user_roles_repo = repo_factory.create_user_roles_repo_with_sys_acl(&*conn);
+            conn.transaction::<(), FailureError, _>(move || {
+                find_child(&*child_accounts_repo, current_uid, child_id)?;
+                let identity = ident_repo.find_by_id_provider(child_id, Provider::Email)?;
+                check_password_policy(&password_strength, &payload.new_password, &[identity.email.clone()])?;
+                ident_repo.update(
+                    identity,
+                    UpdateIdentity {
+                        password: Some(rollout_password_create(&rollouts, child_id, payload.new_password)),
+                        provider: None,
+                        must_change_password: None,
+                    },
+                )?;
+                users_repo.revoke_tokens(child_id, revoke_before)?;
+                user_roles_repo.invalidate_cache(child_id);
+                Ok(())
+            })
+            .map_err(|e: FailureError| {
+                e.context("Service child_accounts, reset_child_password endpoint error occured.")
+                    .into()
+            })
+        })
+    }
+}
+
+/// Returns link of the child to the parent, `NotFound` if the user is not a child of the parent
+fn find_child(child_accounts_repo: &ChildAccountsRepo, parent_id: UserId, child_id: UserId) -> Result<ChildAccount, FailureError> {
+    child_accounts_repo
+        .find_by_child(child_id)?
+        .and_then(|child| if child.parent_id == parent_id { Some(child) } else { None })
+        .ok_or_else(|| Error::NotFound.context(format!("Child account {} not found", child_id)).into())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use tokio_core::reactor::Core;
+
+    use stq_types::UserId;
+
+    use models::*;
+    use repos::repo_factory::tests::*;
+    use services::child_accounts::*;
+
+    fn create_child_payload(email: &str) -> NewChildAccountPayload {
+        NewChildAccountPayload {
+            email: email.to_string(),
+            password: MOCK_PASSWORD.to_string(),
+            first_name: Some("Alice".to_string()),
+            last_name: None,
+            birthdate: None,
+            invite_code: None,
+        }
+    }
+
+    #[test]
+    fn test_create_child_account() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(MOCK_PARENT_ID), handle);
+        let work = service.create_child_account(create_child_payload("child@mail.com"));
+        let result = core.run(work).unwrap();
+        assert_eq!(result.email, "child@mail.com");
+    }
+
+    #[test]
+    fn test_create_child_account_of_child() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(MOCK_CHILD_ID), handle);
+        let work = service.create_child_account(create_child_payload("child@mail.com"));
+        assert_eq!(core.run(work).is_err(), true);
+    }
+
+    #[test]
+    fn test_get_child_profile() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(MOCK_PARENT_ID), handle);
+        let work = service.get_child_profile(MOCK_CHILD_ID);
+        let result = core.run(work).unwrap();
+        assert_eq!(result.id, MOCK_CHILD_ID);
+    }
+
+    #[test]
+    fn test_get_child_profile_of_other_parent() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(2)), handle);
+        let work = service.get_child_profile(MOCK_CHILD_ID);
+        assert_eq!(core.run(work).is_err(), true);
+    }
+
+    #[test]
+    fn test_reset_child_password() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(MOCK_PARENT_ID), handle);
+        let work = service.reset_child_password(
+            MOCK_CHILD_ID,
+            ChildPasswordReset {
+                new_password: "new_password".to_string(),
+            },
+        );
+        assert_eq!(core.run(work).is_ok(), true);
+    }
+}
@@ -0,0 +1,110 @@
+//! Client branding Services. Clients of different marketplaces carry the brand of their
+//! marketplace, read by the notifications service for email templates and shown on consent
+//! screens. Branding is public, it is set by superusers only.
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use r2d2::ManageConnection;
+
+use errors::Error;
+use models::{ClientBranding, ClientBrandingPayload};
+use repos::repo_factory::ReposFactory;
+use services::types::ServiceFuture;
+use services::Service;
+
+pub trait ClientBrandingService {
+    /// Returns branding of the client
+    fn get_client_branding(&self, client_id: String) -> ServiceFuture<ClientBranding>;
+    /// Creates or replaces branding of the client
+    fn set_client_branding(&self, client_id: String, payload: ClientBrandingPayload) -> ServiceFuture<ClientBranding>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > ClientBrandingService for Service<T, M, F>
+{
+    /// Returns branding of the client
+    fn get_client_branding(&self, client_id: String) -> ServiceFuture<ClientBranding> {
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let client_brandings_repo = repo_factory.create_client_brandings_repo_with_sys_acl(&*conn);
+            client_brandings_repo
+                .find(client_id.clone())
+                .and_then(|branding| {
+                    branding.ok_or_else(|| {
+                        Error::NotFound
+                            .context(format!("Branding of client {} not found", client_id))
+                            .into()
+                    })
+                })
+                .map_err(|e: FailureError| {
+                    e.context("Service client_branding, get_client_branding endpoint error occured.")
+                        .into()
+                })
+        })
+    }
+
+    /// Creates or replaces branding of the client
+    fn set_client_branding(&self, client_id: String, payload: ClientBrandingPayload) -> ServiceFuture<ClientBranding> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let clients_repo = repo_factory.create_clients_repo(&*conn);
+            let client_brandings_repo = repo_factory.create_client_brandings_repo(&*conn, current_uid);
+            clients_repo
+                .find(client_id.clone())
+                .and_then(|client| {
+                    let client = client.ok_or_else(|| Error::NotFound.context(format!("Client {} not found", client_id)))?;
+                    client_brandings_repo.upsert(payload.into_new(client.id))
+                })
+                .map_err(|e: FailureError| {
+                    e.context("Service client_branding, set_client_branding endpoint error occured.")
+                        .into()
+                })
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use tokio_core::reactor::Core;
+
+    use stq_types::UserId;
+
+    use models::ClientBrandingPayload;
+    use repos::repo_factory::tests::*;
+    use services::client_branding::ClientBrandingService;
+
+    #[test]
+    fn test_get_client_branding() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let work = service.get_client_branding(MOCK_CLIENT_ID.to_string());
+        let result = core.run(work).unwrap();
+        assert_eq!(result.client_id, MOCK_CLIENT_ID);
+    }
+
+    #[test]
+    fn test_set_branding_of_unknown_client() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let payload = ClientBrandingPayload {
+            app_name: "Marketplace".to_string(),
+            support_email: "support@example.com".to_string(),
+            logo_url: None,
+        };
+        let work = service.set_client_branding("unknown".to_string(), payload);
+        assert_eq!(core.run(work).is_err(), true);
+    }
+}
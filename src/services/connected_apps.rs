@@ -0,0 +1,145 @@
+//! Connected apps Services. Users see third-party clients they consented to and disconnect them,
+//! which deletes the consent and revokes tokens issued to the client. Gateway and the client are
+//! notified by the database trigger on `oauth_client_revoked` channel.
+
+use std::time::{Duration, SystemTime};
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::future;
+use r2d2::ManageConnection;
+
+use errors::Error;
+use models::*;
+use repos::repo_factory::ReposFactory;
+use services::types::ServiceFuture;
+use services::Service;
+
+pub trait ConnectedAppsService {
+    /// Returns third-party clients current user consented to
+    fn get_connected_apps(&self) -> ServiceFuture<Vec<ConnectedApp>>;
+    /// Disconnects third-party client from current user, revoking its tokens
+    fn disconnect_app(&self, client_id: String) -> ServiceFuture<ConnectedApp>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > ConnectedAppsService for Service<T, M, F>
+{
+    /// Returns third-party clients current user consented to
+    fn get_connected_apps(&self) -> ServiceFuture<Vec<ConnectedApp>> {
+        let current_uid = match self.dynamic_context.user_id {
+            Some(user_id) => user_id,
+            None => {
+                return Box::new(future::err(
+                    Error::Forbidden.context("Only authorized user can get connected apps").into(),
+                ))
+            }
+        };
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let clients_repo = repo_factory.create_clients_repo(&*conn);
+            let oauth_consents_repo = repo_factory.create_oauth_consents_repo(&*conn, Some(current_uid));
+            oauth_consents_repo
+                .list_for_user(current_uid)
+                .and_then(|consents| {
+                    let clients = clients_repo.list()?;
+                    Ok(consents
+                        .into_iter()
+                        .filter_map(|consent| {
+                            let client = clients.iter().find(|client| client.id == consent.client_id)?;
+                            Some(ConnectedApp::new(consent, client))
+                        })
+                        .collect())
+                })
+                .map_err(|e: FailureError| {
+                    e.context("Service connected_apps, get_connected_apps endpoint error occured.")
+                        .into()
+                })
+        })
+    }
+
+    /// Disconnects third-party client from current user, revoking its tokens
+    fn disconnect_app(&self, client_id: String) -> ServiceFuture<ConnectedApp> {
+        let current_uid = match self.dynamic_context.user_id {
+            Some(user_id) => user_id,
+            None => {
+                return Box::new(future::err(
+                    Error::Forbidden.context("Only authorized user can disconnect apps").into(),
+                ))
+            }
+        };
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        debug!("Disconnecting client {} from user {}", client_id, current_uid);
+
+        self.spawn_on_pool(move |conn| {
+            let clients_repo = repo_factory.create_clients_repo(&*conn);
+            let oauth_consents_repo = repo_factory.create_oauth_consents_repo(&*conn, Some(current_uid));
+            conn.transaction::<ConnectedApp, FailureError, _>(|| {
+                let client = clients_repo
+                    .find(client_id.clone())?
+                    .ok_or_else(|| Error::NotFound.context(format!("Client {} not found", client_id)))?;
+                // tokens of the client given before now are revoked
+                let revoke_before = SystemTime::now() + Duration::from_secs(client.jwt_expiration_s as u64);
+                let consent = oauth_consents_repo
+                    .revoke(NewOAuthRevocation {
+                        user_id: current_uid,
+                        client_id: client.id.clone(),
+                        revoke_before,
+                    })?
+                    .ok_or_else(|| Error::NotFound.context(format!("Client {} is not connected", client_id)))?;
+                Ok(ConnectedApp::new(consent, &client))
+            })
+            .map_err(|e: FailureError| e.context("Service connected_apps, disconnect_app endpoint error occured.").into())
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use tokio_core::reactor::Core;
+
+    use stq_types::UserId;
+
+    use repos::repo_factory::tests::*;
+    use services::connected_apps::*;
+
+    #[test]
+    fn test_get_connected_apps() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.get_connected_apps();
+        let result = core.run(work).unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].client_id, MOCK_THIRD_PARTY_CLIENT_ID);
+    }
+
+    #[test]
+    fn test_disconnect_app() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.disconnect_app(MOCK_THIRD_PARTY_CLIENT_ID.to_string());
+        let result = core.run(work).unwrap();
+        assert_eq!(result.scopes, vec!["profile:read".to_string()]);
+    }
+
+    #[test]
+    fn test_disconnect_not_connected_app() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.disconnect_app(MOCK_CLIENT_ID.to_string());
+        assert_eq!(core.run(work).is_err(), true);
+    }
+}
@@ -0,0 +1,57 @@
+//! Countries Services, presents countries reference data
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use r2d2::ManageConnection;
+
+use models::Country;
+use repos::ReposFactory;
+use services::types::ServiceFuture;
+use services::Service;
+
+pub trait CountriesService {
+    /// Returns all countries
+    fn get_countries(&self) -> ServiceFuture<Vec<Country>>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > CountriesService for Service<T, M, F>
+{
+    /// Returns all countries
+    fn get_countries(&self) -> ServiceFuture<Vec<Country>> {
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let countries_repo = repo_factory.create_countries_repo(&*conn);
+            countries_repo
+                .list()
+                .map_err(|e: FailureError| e.context("Service countries, get_countries endpoint error occured.").into())
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use tokio_core::reactor::Core;
+
+    use repos::repo_factory::tests::*;
+    use services::countries::CountriesService;
+
+    #[test]
+    fn test_get_countries() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let work = service.get_countries();
+        let result = core.run(work).unwrap();
+        assert_eq!(result[0].alpha3.0, MOCK_COUNTRY);
+    }
+}
@@ -0,0 +1,290 @@
+//! CRM reconciliation Services. Users linked to CRM contacts by `emarsys_id` are compared with
+//! their contacts every `interval_s` in a background thread, shard by shard. Every run is a job,
+//! differences of key fields are saved as drifts of the job and replace drifts of the previous
+//! run once it is done. Fields listed in `auto_correct` are corrected in CRM with values of the
+//! user, users service being the source of truth.
+//!
+//! Reconciliation is off unless `crm_reconciliation` is configured.
+
+use std::io;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::Future;
+use hyper::Method;
+use r2d2::ManageConnection;
+use serde_json;
+use uuid::Uuid;
+
+use stq_http::client::{ClientHandle, HttpClient, TimeLimitedHttpClient};
+use stq_types::{EmarsysId, UserId};
+
+use config;
+use errors::Error;
+use models::*;
+use repos::repo_factory::ReposFactory;
+use repos::sharding::ShardedPool;
+use services::jobs::{run_job, JobContext};
+use services::types::ServiceFuture;
+use services::Service;
+
+pub trait CrmReconciliationService {
+    /// Returns drifts found by the last done reconciliation
+    fn get_crm_drift_report(&self) -> ServiceFuture<CrmDriftReport>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > CrmReconciliationService for Service<T, M, F>
+{
+    /// Returns drifts found by the last done reconciliation
+    fn get_crm_drift_report(&self) -> ServiceFuture<CrmDriftReport> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+        let limit = self.static_context.config.repo_limits.max_count;
+
+        self.spawn_on_pool(move |conn| {
+            let jobs_repo = repo_factory.create_jobs_repo(&*conn, current_uid);
+            let crm_drifts_repo = repo_factory.create_crm_drifts_repo(&*conn, current_uid);
+            jobs_repo
+                .find_latest(JobKind::CrmReconciliation, JobState::Done)
+                .and_then(|job| {
+                    let drifts = match job {
+                        Some(ref job) => crm_drifts_repo.list_for_job(job.id, limit)?,
+                        None => vec![],
+                    };
+                    Ok(CrmDriftReport { job, drifts })
+                })
+                .map_err(|e: FailureError| {
+                    e.context("Service crm_reconciliation, get_crm_drift_report endpoint error occured.")
+                        .into()
+                })
+        })
+    }
+}
+
+/// Reconciles users against CRM contacts every `interval_s`, in a background thread
+pub fn start_crm_reconciliation<T, M, F>(
+    db_pool: ShardedPool<M>,
+    repo_factory: F,
+    client_handle: ClientHandle,
+    page_size: i64,
+    conf: config::CrmReconciliation,
+) -> io::Result<JoinHandle<()>>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    let http_client = TimeLimitedHttpClient::new(client_handle, Duration::from_secs(conf.timeout_s));
+    thread::Builder::new().name("crm_reconciliation".to_string()).spawn(move || loop {
+        thread::sleep(Duration::from_secs(conf.interval_s));
+        let context = match create_reconciliation_job(&db_pool, &repo_factory) {
+            Ok(context) => context,
+            Err(e) => {
+                error!("CRM reconciliation was not started: {}", e);
+                continue;
+            }
+        };
+        info!("Reconciling users against CRM with job {}", context.job_id);
+        run_job(&context, |job| run_crm_reconciliation(job, &http_client, &conf, page_size));
+    })
+}
+
+fn create_reconciliation_job<T, M, F>(db_pool: &ShardedPool<M>, repo_factory: &F) -> Result<JobContext<M, F>, FailureError>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    let conn = db_pool.primary().get().map_err(|e| e.context(Error::Connection))?;
+    let jobs_repo = repo_factory.create_jobs_repo_with_sys_acl(&*conn);
+    let job = jobs_repo.create(NewJob::new(JobKind::CrmReconciliation, None))?;
+    Ok(JobContext {
+        job_id: job.id,
+        user_id: None,
+        db_pool: db_pool.clone(),
+        repo_factory: repo_factory.clone(),
+    })
+}
+
+/// Compares users of all shards with their CRM contacts in pages of `page_size` users ordered
+/// by id, reporting number of checked users as progress of the job. Users failed to be compared
+/// are skipped.
+fn run_crm_reconciliation<T, M, F, C>(
+    job: &JobContext<M, F>,
+    http_client: &C,
+    conf: &config::CrmReconciliation,
+    page_size: i64,
+) -> Result<Option<String>, FailureError>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+    C: HttpClient,
+{
+    job.update(UpdateJob::running(None))?;
+    let mut checked = 0;
+
+    for shard_pool in job.db_pool.shards() {
+        let conn = shard_pool.get().map_err(|e| e.context(Error::Connection))?;
+        let users_repo = job.repo_factory.create_users_repo_with_sys_acl(&*conn);
+        let mut from = UserId(1);
+        loop {
+            let users = users_repo.list(from, page_size)?;
+            let mut drifts = vec![];
+            for user in &users {
+                if let Some(emarsys_id) = user.emarsys_id {
+                    match reconcile_user(http_client, conf, job.job_id, user, emarsys_id) {
+                        Ok(user_drifts) => drifts.extend(user_drifts),
+                        Err(e) => warn!("User {} was not reconciled with CRM contact {}: {}", user.id, emarsys_id, e),
+                    }
+                }
+                checked += 1;
+            }
+            save_drifts(job, drifts)?;
+            job.report_progress(checked);
+            match users.last() {
+                Some(last) if users.len() as i64 == page_size => from = UserId(last.id.0 + 1),
+                _ => break,
+            }
+        }
+    }
+
+    let conn = job.db_pool.primary().get().map_err(|e| e.context(Error::Connection))?;
+    let crm_drifts_repo = job.repo_factory.create_crm_drifts_repo_with_sys_acl(&*conn);
+    crm_drifts_repo.delete_other_jobs(job.job_id)?;
+
+    Ok(Some("/crm_reconciliation/report".to_string()))
+}
+
+/// Drifts are stored on the primary shard
+fn save_drifts<T, M, F>(job: &JobContext<M, F>, drifts: Vec<NewCrmDrift>) -> Result<(), FailureError>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    if drifts.is_empty() {
+        return Ok(());
+    }
+    let conn = job.db_pool.primary().get().map_err(|e| e.context(Error::Connection))?;
+    let crm_drifts_repo = job.repo_factory.create_crm_drifts_repo_with_sys_acl(&*conn);
+    crm_drifts_repo.add(drifts)
+}
+
+/// Compares the user with its CRM contact. Drifts of `auto_correct` fields are corrected in CRM,
+/// unless the user has no value to correct them with.
+fn reconcile_user<C: HttpClient>(
+    http_client: &C,
+    conf: &config::CrmReconciliation,
+    job_id: Uuid,
+    user: &User,
+    emarsys_id: EmarsysId,
+) -> Result<Vec<NewCrmDrift>, FailureError> {
+    let url = format!("{}/contacts/{}", conf.url, emarsys_id);
+    let contact = http_client
+        .request_json::<CrmContact>(Method::Get, url.clone(), None, None)
+        .wait()
+        .map_err(|e| e.context(Error::HttpClient))?;
+    let drifts = contact_drifts(user, &contact);
+
+    let mut correction = CrmContact::default();
+    let mut corrected_fields = vec![];
+    for &(field, ref user_value, _) in &drifts {
+        if user_value.is_some() && conf.auto_correct.contains(&field) {
+            field.set_contact_value(&mut correction, user_value.clone());
+            corrected_fields.push(field);
+        }
+    }
+    if !corrected_fields.is_empty() {
+        let body = serde_json::to_string(&correction)?;
+        let corrected = http_client
+            .request_json::<serde_json::Value>(Method::Put, url, Some(body), None)
+            .wait();
+        if let Err(e) = corrected {
+            warn!("CRM contact {} of user {} was not corrected: {}", emarsys_id, user.id, e);
+            corrected_fields.clear();
+        }
+    }
+
+    Ok(drifts
+        .into_iter()
+        .map(|(field, user_value, crm_value)| NewCrmDrift {
+            job_id,
+            user_id: user.id,
+            emarsys_id,
+            field,
+            user_value,
+            crm_value,
+            corrected: corrected_fields.contains(&field),
+        })
+        .collect())
+}
+
+/// Fields of the user differing from its CRM contact, along with values of the user and of the contact
+pub fn contact_drifts(user: &User, contact: &CrmContact) -> Vec<(CrmField, Option<String>, Option<String>)> {
+    CrmField::all()
+        .iter()
+        .filter_map(|field| {
+            let user_value = field.user_value(user);
+            let crm_value = field.contact_value(contact);
+            if field.is_drift(&user_value, &crm_value) {
+                Some((*field, user_value, crm_value))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use tokio_core::reactor::Core;
+
+    use stq_types::UserId;
+
+    use models::*;
+    use repos::repo_factory::tests::*;
+    use services::crm_reconciliation::*;
+
+    #[test]
+    fn test_contact_drifts() {
+        let mut user = create_user(UserId(2), "User@example.com".to_string());
+        user.first_name = Some("Name".to_string());
+        user.last_name = Some("Lastname".to_string());
+        let contact = CrmContact {
+            email: Some("user@example.com".to_string()),
+            first_name: Some("Name".to_string()),
+            last_name: Some("Other".to_string()),
+            phone: Some(" ".to_string()),
+        };
+        let drifts = contact_drifts(&user, &contact);
+        assert_eq!(drifts.len(), 1);
+        assert_eq!(
+            drifts[0],
+            (CrmField::LastName, Some("Lastname".to_string()), Some("Other".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_get_crm_drift_report() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.get_crm_drift_report();
+        let result = core.run(work).unwrap();
+        let job = result.job.unwrap();
+        assert_eq!(job.kind, JobKind::CrmReconciliation);
+        assert_eq!(result.drifts[0].job_id, job.id);
+    }
+}
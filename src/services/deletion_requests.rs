@@ -0,0 +1,452 @@
+//! Deletion requests Services, two-phase deletion of users. Request is saved on the primary shard,
+//! dependent services are notified by the database trigger and answer with confirmations.
+//! The user is marked pending deletion meanwhile. Once the grace period passed and all of them
+//! confirmed, or the deadline passed, the user is purged by a job.
+//! A vetoed deletion can be requested again, e.g. when the obligations are closed.
+
+use std::io;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::Future;
+use futures_cpupool::CpuPool;
+use r2d2::ManageConnection;
+
+use stq_types::UserId;
+
+use errors::Error;
+use models::*;
+use repos::repo_factory::ReposFactory;
+use repos::sharding::ShardedPool;
+use repos::DeletionRequestsRepo;
+use services::jobs::{spawn_job, JobContext};
+use services::types::ServiceFuture;
+use services::work_pool::WorkPriority;
+use services::Service;
+
+pub trait DeletionRequestsService {
+    /// Requests deletion of the user, dependent services have until the deadline to veto it
+    fn request_deletion(&self, user_id: UserId) -> ServiceFuture<DeletionStatus>;
+    /// Returns state of deletion of the user
+    fn get_deletion_request(&self, user_id: UserId) -> ServiceFuture<Option<DeletionStatus>>;
+    /// Saves answer of a dependent service, the user is purged once all the services confirmed
+    fn confirm_deletion(&self, user_id: UserId, payload: DeletionConfirmationPayload) -> ServiceFuture<DeletionStatus>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > DeletionRequestsService for Service<T, M, F>
+{
+    /// Requests deletion of the user, dependent services have until the deadline to veto it
+    fn request_deletion(&self, user_id: UserId) -> ServiceFuture<DeletionStatus> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+        let user_repo_factory = repo_factory.clone();
+        let db_pool = self.static_context.db_pool.clone();
+        let cpu_pool = self.static_context.work_pool.cpu_pool(WorkPriority::Batch);
+        let config = self.static_context.config.deletion.clone();
+        let now = SystemTime::now();
+        let not_before = now + Duration::from_secs(config.grace_period_s);
+        let deadline = now + Duration::from_secs(config.confirmation_timeout_s);
+        let service = self.clone();
+
+        debug!("Requesting deletion of user {}", user_id);
+
+        Box::new(
+            self.spawn_on_shard(user_id, move |conn| {
+                let users_repo = user_repo_factory.create_users_repo(&*conn, current_uid);
+                let users_repo_with_sys_acl = user_repo_factory.create_users_repo_with_sys_acl(&*conn);
+                users_repo
+                    .find(user_id)?
+                    .ok_or_else(|| Error::NotFound.context(format!("User {} not found", user_id)))?;
+                users_repo_with_sys_acl.set_pending_deletion(user_id, true).map(|_| ())
+            })
+            .and_then(move |_| {
+                service.spawn_on_pool(move |conn| {
+                    let deletion_requests_repo = repo_factory.create_deletion_requests_repo(&*conn, current_uid);
+                    let request = match deletion_requests_repo.find(user_id)? {
+                        Some(ref request) if request.state != DeletionState::Vetoed => request.clone(),
+                        _ => deletion_requests_repo.create(NewDeletionRequest::new(user_id, current_uid, not_before, deadline))?,
+                    };
+                    let request = settle_deletion(&*conn, &db_pool, &repo_factory, &cpu_pool, &config.confirming_services, request)?;
+                    deletion_status(&*deletion_requests_repo, request, &config.confirming_services)
+                })
+            })
+            .map_err(|e: FailureError| {
+                e.context("Service deletion_requests, request_deletion endpoint error occured.")
+                    .into()
+            }),
+        )
+    }
+
+    /// Returns state of deletion of the user
+    fn get_deletion_request(&self, user_id: UserId) -> ServiceFuture<Option<DeletionStatus>> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+        let confirming_services = self.static_context.config.deletion.confirming_services.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let deletion_requests_repo = repo_factory.create_deletion_requests_repo(&*conn, current_uid);
+            deletion_requests_repo
+                .find(user_id)
+                .and_then(|request| match request {
+                    Some(request) => deletion_status(&*deletion_requests_repo, request, &confirming_services).map(Some),
+                    None => Ok(None),
+                })
+                .map_err(|e: FailureError| {
+                    e.context("Service deletion_requests, get_deletion_request endpoint error occured.")
+                        .into()
+                })
+        })
+    }
+
+    /// Saves answer of a dependent service, the user is purged once all the services confirmed
+    fn confirm_deletion(&self, user_id: UserId, payload: DeletionConfirmationPayload) -> ServiceFuture<DeletionStatus> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+        let db_pool = self.static_context.db_pool.clone();
+        let cpu_pool = self.static_context.work_pool.cpu_pool(WorkPriority::Batch);
+        let confirming_services = self.static_context.config.deletion.confirming_services.clone();
+
+        debug!("Confirming deletion of user {} with payload: {:?}", user_id, payload);
+
+        self.spawn_on_pool(move |conn| {
+            let deletion_requests_repo = repo_factory.create_deletion_requests_repo(&*conn, current_uid);
+            conn.transaction::<DeletionRequest, FailureError, _>(|| {
+                if !confirming_services.contains(&payload.service) {
+                    return Err(Error::Validate(
+                        validation_errors!({"service": ["unknown" => "Service is not expected to confirm deletions"]}),
+                    )
+                    .into());
+                }
+                let request = deletion_requests_repo
+                    .find_for_update(user_id)?
+                    .ok_or_else(|| Error::NotFound.context(format!("Deletion request of user {} not found", user_id)))?;
+                if request.state != DeletionState::Pending {
+                    return Err(
+                        Error::Validate(validation_errors!({"state": ["not_pending" => "Deletion request is not pending"]})).into(),
+                    );
+                }
+                deletion_requests_repo.confirm(NewDeletionConfirmation {
+                    user_id,
+                    service: payload.service,
+                    approved: payload.approved,
+                    reason: payload.reason,
+                })?;
+                Ok(request)
+            })
+            .and_then(|request| settle_deletion(&*conn, &db_pool, &repo_factory, &cpu_pool, &confirming_services, request))
+            .and_then(|request| deletion_status(&*deletion_requests_repo, request, &confirming_services))
+            .map_err(|e: FailureError| {
+                e.context("Service deletion_requests, confirm_deletion endpoint error occured.")
+                    .into()
+            })
+        })
+    }
+}
+
+/// Decides on pending deletion: vetoed if any service vetoed it, approved after the grace period once all the
+/// confirming services approved it or the deadline passed, `None` while waiting for answers
+fn deletion_decision(
+    request: &DeletionRequest,
+    confirmations: &[DeletionConfirmation],
+    confirming_services: &[String],
+    now: SystemTime,
+) -> Option<DeletionState> {
+    if confirmations.iter().any(|confirmation| !confirmation.approved) {
+        Some(DeletionState::Vetoed)
+    } else if request.not_before > now {
+        None
+    } else if awaiting_services(confirmations, confirming_services).is_empty() || request.deadline <= now {
+        Some(DeletionState::Approved)
+    } else {
+        None
+    }
+}
+
+fn awaiting_services(confirmations: &[DeletionConfirmation], confirming_services: &[String]) -> Vec<String> {
+    confirming_services
+        .iter()
+        .filter(|service| !confirmations.iter().any(|confirmation| &confirmation.service == *service))
+        .cloned()
+        .collect()
+}
+
+fn deletion_status(
+    deletion_requests_repo: &DeletionRequestsRepo,
+    request: DeletionRequest,
+    confirming_services: &[String],
+) -> Result<DeletionStatus, FailureError> {
+    let confirmations = deletion_requests_repo.list_confirmations(request.user_id)?;
+    let awaiting = if request.state == DeletionState::Pending {
+        awaiting_services(&confirmations, confirming_services)
+    } else {
+        vec![]
+    };
+    Ok(DeletionStatus {
+        request,
+        confirmations,
+        awaiting,
+    })
+}
+
+/// Applies decision on the pending request and starts purge of the user if the deletion is approved.
+/// The user is unmarked pending deletion if the deletion is vetoed.
+/// Must not be called inside a transaction, the job is started right after the request is updated.
+fn settle_deletion<T, M, F>(
+    conn: &T,
+    db_pool: &ShardedPool<M>,
+    repo_factory: &F,
+    cpu_pool: &CpuPool,
+    confirming_services: &[String],
+    request: DeletionRequest,
+) -> Result<DeletionRequest, FailureError>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    if request.state != DeletionState::Pending {
+        return Ok(request);
+    }
+
+    let user_id = request.user_id;
+    let deletion_requests_repo = repo_factory.create_deletion_requests_repo_with_sys_acl(conn);
+    let jobs_repo = repo_factory.create_jobs_repo_with_sys_acl(conn);
+    let (request, purge_job) = conn.transaction::<(DeletionRequest, Option<Job>), FailureError, _>(|| {
+        let request = deletion_requests_repo
+            .find_for_update(user_id)?
+            .ok_or_else(|| Error::NotFound.context(format!("Deletion request of user {} not found", user_id)))?;
+        if request.state != DeletionState::Pending {
+            return Ok((request, None));
+        }
+
+        let confirmations = deletion_requests_repo.list_confirmations(user_id)?;
+        match deletion_decision(&request, &confirmations, confirming_services, SystemTime::now()) {
+            Some(DeletionState::Approved) => {
+                let job = jobs_repo.create(NewJob::new(JobKind::UserPurge, request.requested_by))?;
+                let update = UpdateDeletionRequest {
+                    state: Some(DeletionState::Approved),
+                    job_id: Some(job.id),
+                };
+                Ok((deletion_requests_repo.update(user_id, update)?, Some(job)))
+            }
+            Some(state) => {
+                let update = UpdateDeletionRequest {
+                    state: Some(state),
+                    ..UpdateDeletionRequest::default()
+                };
+                Ok((deletion_requests_repo.update(user_id, update)?, None))
+            }
+            None => Ok((request, None)),
+        }
+    })?;
+
+    if request.state == DeletionState::Vetoed {
+        let shard_conn = db_pool.for_user(user_id).get().map_err(|e| e.context(Error::Connection))?;
+        let users_repo = repo_factory.create_users_repo_with_sys_acl(&*shard_conn);
+        users_repo.set_pending_deletion(user_id, false)?;
+    }
+
+    if let Some(job) = purge_job {
+        info!("Deletion of user {} is approved, purging with job {}", user_id, job.id);
+        let context = JobContext {
+            job_id: job.id,
+            user_id: request.requested_by,
+            db_pool: db_pool.clone(),
+            repo_factory: repo_factory.clone(),
+        };
+        spawn_job(cpu_pool, context, move |job| run_user_purge(job, user_id));
+    }
+
+    Ok(request)
+}
+
+/// Deletes the user from its shard, funnel events of the user from the primary shard, and marks
+/// the deletion done. The data key of the user is deleted too, so sealed PII left in backups can't be read anymore.
+fn run_user_purge<T, M, F>(job: &JobContext<M, F>, user_id: UserId) -> Result<Option<String>, FailureError>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    let conn = job.db_pool.for_user(user_id).get().map_err(|e| e.context(Error::Connection))?;
+    let users_repo = job.repo_factory.create_users_repo_with_sys_acl(&*conn);
+    users_repo.delete(user_id)?;
+
+    let conn = job.db_pool.primary().get().map_err(|e| e.context(Error::Connection))?;
+    let funnel_events_repo = job.repo_factory.create_funnel_events_repo_with_sys_acl(&*conn);
+    funnel_events_repo.delete_by_user(user_id)?;
+    let audit_log_repo = job.repo_factory.create_audit_log_repo_with_sys_acl(&*conn);
+    audit_log_repo.redact_user(user_id)?;
+    let deletion_requests_repo = job.repo_factory.create_deletion_requests_repo_with_sys_acl(&*conn);
+    let update = UpdateDeletionRequest {
+        state: Some(DeletionState::Done),
+        ..UpdateDeletionRequest::default()
+    };
+    deletion_requests_repo.update(user_id, update)?;
+
+    Ok(Some(format!("/users/{}/delete_request", user_id)))
+}
+
+/// Settles requests with passed grace period or deadline every `interval`, in a background thread
+pub fn start_deletion_checks<T, M, F>(
+    db_pool: ShardedPool<M>,
+    repo_factory: F,
+    cpu_pool: CpuPool,
+    confirming_services: Vec<String>,
+    interval: Duration,
+) -> io::Result<JoinHandle<()>>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    thread::Builder::new().name("deletion_checks".to_string()).spawn(move || loop {
+        thread::sleep(interval);
+        if let Err(e) = check_due_deletions(&db_pool, &repo_factory, &cpu_pool, &confirming_services) {
+            error!("Due deletion requests were not checked: {}", e);
+        }
+    })
+}
+
+fn check_due_deletions<T, M, F>(
+    db_pool: &ShardedPool<M>,
+    repo_factory: &F,
+    cpu_pool: &CpuPool,
+    confirming_services: &[String],
+) -> Result<(), FailureError>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    let conn = db_pool.primary().get().map_err(|e| e.context(Error::Connection))?;
+    let deletion_requests_repo = repo_factory.create_deletion_requests_repo_with_sys_acl(&*conn);
+    for request in deletion_requests_repo.list_due(SystemTime::now())? {
+        let user_id = request.user_id;
+        if let Err(e) = settle_deletion(&*conn, db_pool, repo_factory, cpu_pool, confirming_services, request) {
+            warn!("Due deletion request of user {} was not settled: {}", user_id, e);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+
+    use tokio_core::reactor::Core;
+
+    use stq_types::UserId;
+
+    use models::*;
+    use repos::repo_factory::tests::*;
+    use services::deletion_requests::*;
+
+    fn create_confirmation(service: &str, approved: bool) -> DeletionConfirmation {
+        DeletionConfirmation {
+            user_id: UserId(1),
+            service: service.to_string(),
+            approved,
+            reason: None,
+            created_at: SystemTime::now(),
+        }
+    }
+
+    #[test]
+    fn test_deletion_decision() {
+        let services = vec!["orders".to_string(), "billing".to_string()];
+        let request = create_deletion_request(UserId(1));
+        let now = SystemTime::now();
+
+        let confirmations = vec![create_confirmation("orders", true)];
+        assert_eq!(deletion_decision(&request, &confirmations, &services, now), None);
+
+        let confirmations = vec![create_confirmation("orders", true), create_confirmation("billing", false)];
+        assert_eq!(
+            deletion_decision(&request, &confirmations, &services, now),
+            Some(DeletionState::Vetoed)
+        );
+
+        let confirmations = vec![create_confirmation("orders", true), create_confirmation("billing", true)];
+        assert_eq!(
+            deletion_decision(&request, &confirmations, &services, now),
+            Some(DeletionState::Approved)
+        );
+
+        let in_grace_period = DeletionRequest {
+            not_before: now + Duration::from_secs(60),
+            ..request.clone()
+        };
+        assert_eq!(deletion_decision(&in_grace_period, &confirmations, &services, now), None);
+        assert_eq!(deletion_decision(&in_grace_period, &[], &[], now), None);
+
+        let after_deadline = request.deadline + Duration::from_secs(1);
+        assert_eq!(
+            deletion_decision(&request, &[], &services, after_deadline),
+            Some(DeletionState::Approved)
+        );
+        assert_eq!(deletion_decision(&request, &[], &[], now), Some(DeletionState::Approved));
+    }
+
+    #[test]
+    fn test_get_deletion_request() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.get_deletion_request(UserId(1));
+        let result = core.run(work).unwrap().unwrap();
+        assert_eq!(result.request.user_id, UserId(1));
+        assert_eq!(result.request.state, DeletionState::Pending);
+    }
+
+    #[test]
+    fn test_request_deletion() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.request_deletion(UserId(1));
+        let result = core.run(work).unwrap();
+        assert_eq!(result.request.state, DeletionState::Pending);
+        assert_eq!(result.awaiting, vec!["orders".to_string(), "billing".to_string()]);
+    }
+
+    #[test]
+    fn test_confirm_deletion_unknown_service() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let payload = DeletionConfirmationPayload {
+            service: "unknown".to_string(),
+            approved: true,
+            reason: None,
+        };
+        let work = service.confirm_deletion(UserId(1), payload);
+        let result = core.run(work);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_confirm_deletion() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let payload = DeletionConfirmationPayload {
+            service: "orders".to_string(),
+            approved: true,
+            reason: None,
+        };
+        let work = service.confirm_deletion(UserId(1), payload);
+        let result = core.run(work).unwrap();
+        assert_eq!(result.request.user_id, UserId(1));
+    }
+}
@@ -0,0 +1,156 @@
+//! Dependency health Services, reported by `/ready/dependencies` for the saga orchestrator to
+//! decide whether flows needing downstreams of the users service may be started. Configured
+//! dependencies are checked concurrently on every request, a dependency is down if its check
+//! fails or times out.
+//!
+//! The users service is down if it is not ready or any critical dependency is down, degraded
+//! if any other dependency is not up.
+
+use std::time::{Duration, Instant};
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use futures::future;
+use futures::Future;
+use hyper::Method;
+use r2d2::ManageConnection;
+use serde_json;
+
+use stq_http::client::{HttpClient, TimeLimitedHttpClient};
+
+use config;
+use models::{DependencyHealth, HealthStatus};
+use repos::repo_factory::ReposFactory;
+use services::types::ServiceFuture;
+use services::Service;
+
+/// Name of the root of the dependency graph
+pub const SERVICE_NAME: &'static str = "users";
+
+pub trait DependencyHealthService {
+    /// Returns dependency graph of the users service along with health of every dependency
+    fn get_dependency_health(&self) -> ServiceFuture<DependencyHealth>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > DependencyHealthService for Service<T, M, F>
+{
+    /// Returns dependency graph of the users service along with health of every dependency
+    fn get_dependency_health(&self) -> ServiceFuture<DependencyHealth> {
+        let not_ready_reason = self.static_context.readiness.not_ready_reason();
+        let checks: Vec<ServiceFuture<DependencyHealth>> = match self.static_context.config.dependency_checks {
+            Some(ref conf) => {
+                let http_client =
+                    TimeLimitedHttpClient::new(self.static_context.client_handle.clone(), Duration::from_millis(conf.timeout_ms));
+                conf.dependencies
+                    .iter()
+                    .map(|dependency| check_dependency(&http_client, dependency.clone()))
+                    .collect()
+            }
+            None => vec![],
+        };
+
+        Box::new(future::join_all(checks).map(move |dependencies| DependencyHealth {
+            name: SERVICE_NAME.to_string(),
+            status: DependencyHealth::aggregate_status(not_ready_reason.is_none(), &dependencies),
+            critical: true,
+            latency_ms: None,
+            error: not_ready_reason.map(String::from),
+            dependencies,
+        }))
+    }
+}
+
+/// Checks health of the dependency, any successful JSON response means it is up. Dependencies
+/// responding with their own dependency graph are reported with their status and subgraph.
+fn check_dependency<C: HttpClient>(http_client: &C, dependency: config::Dependency) -> ServiceFuture<DependencyHealth> {
+    let started = Instant::now();
+    Box::new(
+        http_client
+            .request_json::<serde_json::Value>(Method::Get, dependency.url.clone(), None, None)
+            .then(move |res| -> Result<DependencyHealth, FailureError> {
+                let latency = started.elapsed();
+                let latency_ms = Some(latency.as_secs() * 1000 + u64::from(latency.subsec_millis()));
+                Ok(match res {
+                    Ok(body) => {
+                        let reported = serde_json::from_value::<DependencyHealth>(body).ok();
+                        DependencyHealth {
+                            name: dependency.name,
+                            status: reported.as_ref().map(|graph| graph.status).unwrap_or(HealthStatus::Up),
+                            critical: dependency.critical,
+                            latency_ms,
+                            error: reported.as_ref().and_then(|graph| graph.error.clone()),
+                            dependencies: reported.map(|graph| graph.dependencies).unwrap_or_default(),
+                        }
+                    }
+                    Err(e) => {
+                        warn!("Health check of dependency {} failed: {}", dependency.name, e);
+                        DependencyHealth {
+                            name: dependency.name,
+                            status: HealthStatus::Down,
+                            critical: dependency.critical,
+                            latency_ms,
+                            error: Some(e.to_string()),
+                            dependencies: vec![],
+                        }
+                    }
+                })
+            }),
+    )
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use tokio_core::reactor::Core;
+
+    use models::{DependencyHealth, HealthStatus};
+    use repos::repo_factory::tests::*;
+    use services::dependency_health::DependencyHealthService;
+
+    fn dependency(status: HealthStatus, critical: bool) -> DependencyHealth {
+        DependencyHealth {
+            name: "notifications".to_string(),
+            status,
+            critical,
+            latency_ms: Some(1),
+            error: None,
+            dependencies: vec![],
+        }
+    }
+
+    #[test]
+    fn test_aggregate_status() {
+        assert_eq!(DependencyHealth::aggregate_status(true, &[]), HealthStatus::Up);
+        assert_eq!(DependencyHealth::aggregate_status(false, &[]), HealthStatus::Down);
+        assert_eq!(
+            DependencyHealth::aggregate_status(true, &[dependency(HealthStatus::Down, true)]),
+            HealthStatus::Down
+        );
+        assert_eq!(
+            DependencyHealth::aggregate_status(true, &[dependency(HealthStatus::Down, false)]),
+            HealthStatus::Degraded
+        );
+        assert_eq!(
+            DependencyHealth::aggregate_status(true, &[dependency(HealthStatus::Degraded, true)]),
+            HealthStatus::Degraded
+        );
+    }
+
+    #[test]
+    fn test_get_dependency_health_without_dependencies() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let work = service.get_dependency_health();
+        let result = core.run(work).unwrap();
+        assert_eq!(result.name, "users");
+        assert_eq!(result.dependencies.len(), 0);
+    }
+}
@@ -0,0 +1,268 @@
+//! Devices Services, known devices of users. Logins sending `X-Device-Fingerprint` header are
+//! recorded per device, users list their devices and remove the ones they don't recognize.
+//!
+//! Users trust the device they are using for `devices.trust_duration_s`. The device gets a
+//! cookie signed for the user and the device, suspicious logins sending it skip step-up
+//! authentication, see `login_risk`. Removing the device revokes its trust.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::{decode_config, encode_config, URL_SAFE_NO_PAD};
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::future;
+use r2d2::ManageConnection;
+use uuid::Uuid;
+
+use stq_types::UserId;
+
+use super::util::{hmac_sign, hmac_verify, http_only_cookie};
+use errors::Error;
+use models::*;
+use repos::repo_factory::ReposFactory;
+use repos::types::RepoResult;
+use repos::DevicesRepo;
+use services::types::ServiceFuture;
+use services::Service;
+
+pub trait DevicesService {
+    /// Returns known devices of current user
+    fn get_devices(&self) -> ServiceFuture<Vec<Device>>;
+    /// Returns known device of current user
+    fn get_device(&self, device_id: Uuid) -> ServiceFuture<Device>;
+    /// Removes known device of current user, revoking its trust
+    fn delete_device(&self, device_id: Uuid) -> ServiceFuture<Device>;
+    /// Trusts the device current user makes the request from
+    fn trust_device(&self) -> ServiceFuture<TrustedDevice>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > DevicesService for Service<T, M, F>
+{
+    /// Returns known devices of current user
+    fn get_devices(&self) -> ServiceFuture<Vec<Device>> {
+        let current_uid = match self.dynamic_context.user_id {
+            Some(user_id) => user_id,
+            None => return Box::new(future::err(Error::Forbidden.context("Only authorized user can get devices").into())),
+        };
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let devices_repo = repo_factory.create_devices_repo(&*conn, Some(current_uid));
+            devices_repo
+                .list_for_user(current_uid)
+                .map_err(|e: FailureError| e.context("Service devices, get_devices endpoint error occured.").into())
+        })
+    }
+
+    /// Returns known device of current user
+    fn get_device(&self, device_id: Uuid) -> ServiceFuture<Device> {
+        let current_uid = match self.dynamic_context.user_id {
+            Some(user_id) => user_id,
+            None => return Box::new(future::err(Error::Forbidden.context("Only authorized user can get devices").into())),
+        };
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let devices_repo = repo_factory.create_devices_repo(&*conn, Some(current_uid));
+            devices_repo
+                .find(current_uid, device_id)?
+                .ok_or_else(|| Error::NotFound.context(format!("Device {} not found", device_id)).into())
+        })
+    }
+
+    /// Removes known device of current user, revoking its trust
+    fn delete_device(&self, device_id: Uuid) -> ServiceFuture<Device> {
+        let current_uid = match self.dynamic_context.user_id {
+            Some(user_id) => user_id,
+            None => {
+                return Box::new(future::err(
+                    Error::Forbidden.context("Only authorized user can delete devices").into(),
+                ))
+            }
+        };
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        debug!("Deleting device {} of user {}", device_id, current_uid);
+
+        self.spawn_on_pool(move |conn| {
+            let devices_repo = repo_factory.create_devices_repo(&*conn, Some(current_uid));
+            devices_repo
+                .delete(current_uid, device_id)?
+                .ok_or_else(|| Error::NotFound.context(format!("Device {} not found", device_id)).into())
+        })
+    }
+
+    /// Trusts the device current user makes the request from
+    fn trust_device(&self) -> ServiceFuture<TrustedDevice> {
+        let current_uid = match self.dynamic_context.user_id {
+            Some(user_id) => user_id,
+            None => {
+                return Box::new(future::err(
+                    Error::Forbidden.context("Only authorized user can trust devices").into(),
+                ))
+            }
+        };
+        let fingerprint = match self.dynamic_context.device_fingerprint.clone() {
+            Some(fingerprint) => fingerprint,
+            None => {
+                return Box::new(future::err(
+                    Error::Validate(validation_errors!({"device": ["not_identified" => "Device fingerprint is missing"]})).into(),
+                ))
+            }
+        };
+        let repo_factory = self.static_context.repo_factory.clone();
+        let signing_key = self.static_context.hmac_key.clone();
+        let conf = self.static_context.config.devices.clone();
+        let user_agent = self.dynamic_context.user_agent.clone();
+
+        debug!("Trusting device of user {}", current_uid);
+
+        self.spawn_on_pool(move |conn| {
+            let devices_repo = repo_factory.create_devices_repo(&*conn, Some(current_uid));
+            let now = SystemTime::now();
+            let trusted_until = now + Duration::from_secs(conf.trust_duration_s);
+            conn.transaction::<TrustedDevice, FailureError, _>(|| {
+                let device = devices_repo.touch(
+                    NewDevice {
+                        id: Uuid::new_v4(),
+                        user_id: current_uid,
+                        fingerprint,
+                        name: user_agent,
+                    },
+                    now,
+                )?;
+                let device = devices_repo
+                    .set_trusted_until(current_uid, device.id, Some(trusted_until))?
+                    .ok_or_else(|| Error::NotFound.context(format!("Device {} not found", device.id)))?;
+                let cookie = device_cookie_create(&signing_key, current_uid, device.id, unix_time(trusted_until));
+                Ok(TrustedDevice {
+                    device,
+                    set_cookie: http_only_cookie(&conf.cookie_name, &cookie, &conf.cookie_path, conf.trust_duration_s as i64),
+                })
+            })
+            .map_err(|e: FailureError| e.context("Service devices, trust_device endpoint error occured.").into())
+        })
+    }
+}
+
+/// Records login of the user from the device, if the client identified it.
+/// Returns whether the device is trusted, i.e. the cookie is signed for it and its trust is not revoked.
+pub fn track_device(
+    devices_repo: &DevicesRepo,
+    signing_key: &[u8],
+    user_id: UserId,
+    fingerprint: Option<String>,
+    user_agent: Option<String>,
+    cookie: Option<&str>,
+) -> RepoResult<bool> {
+    let fingerprint = match fingerprint {
+        Some(fingerprint) => fingerprint,
+        None => return Ok(false),
+    };
+    let now = SystemTime::now();
+    let device = devices_repo.touch(
+        NewDevice {
+            id: Uuid::new_v4(),
+            user_id,
+            fingerprint,
+            name: user_agent,
+        },
+        now,
+    )?;
+    let signed_device_id = cookie.and_then(|cookie| device_cookie_verify(signing_key, user_id, cookie, unix_time(now)));
+    Ok(signed_device_id == Some(device.id) && device.is_trusted(now))
+}
+
+/// Value of the trusted device cookie, `<device id>.<trusted until>.<signature>`
+pub fn device_cookie_create(key: &[u8], user_id: UserId, device_id: Uuid, trusted_until: i64) -> String {
+    let signature = hmac_sign(key, device_cookie_payload(user_id, device_id, trusted_until).as_bytes());
+    format!("{}.{}.{}", device_id, trusted_until, encode_config(&signature, URL_SAFE_NO_PAD))
+}
+
+/// Returns the device the cookie is signed for, unless the cookie is expired or signed for other user
+pub fn device_cookie_verify(key: &[u8], user_id: UserId, cookie: &str, now: i64) -> Option<Uuid> {
+    let parts = cookie.split('.').collect::<Vec<_>>();
+    if parts.len() != 3 {
+        return None;
+    }
+    let device_id = parts[0].parse::<Uuid>().ok()?;
+    let trusted_until = parts[1].parse::<i64>().ok()?;
+    let signature = decode_config(parts[2], URL_SAFE_NO_PAD).ok()?;
+    if trusted_until <= now || !hmac_verify(key, device_cookie_payload(user_id, device_id, trusted_until).as_bytes(), &signature) {
+        return None;
+    }
+    Some(device_id)
+}
+
+fn device_cookie_payload(user_id: UserId, device_id: Uuid, trusted_until: i64) -> String {
+    format!("trusted_device:{}:{}:{}", user_id, device_id, trusted_until)
+}
+
+fn unix_time(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use tokio_core::reactor::Core;
+
+    use super::*;
+    use repos::repo_factory::tests::*;
+
+    const KEY: &[u8] = b"signing key";
+
+    #[test]
+    fn test_device_cookie() {
+        let device_id = Uuid::new_v4();
+        let cookie = device_cookie_create(KEY, UserId(1), device_id, 2000);
+        assert_eq!(device_cookie_verify(KEY, UserId(1), &cookie, 1000), Some(device_id));
+    }
+
+    #[test]
+    fn test_device_cookie_of_other_user() {
+        let cookie = device_cookie_create(KEY, UserId(1), Uuid::new_v4(), 2000);
+        assert_eq!(device_cookie_verify(KEY, UserId(2), &cookie, 1000), None);
+    }
+
+    #[test]
+    fn test_expired_device_cookie() {
+        let cookie = device_cookie_create(KEY, UserId(1), Uuid::new_v4(), 2000);
+        assert_eq!(device_cookie_verify(KEY, UserId(1), &cookie, 3000), None);
+    }
+
+    #[test]
+    fn test_tampered_device_cookie() {
+        let cookie = device_cookie_create(KEY, UserId(1), Uuid::new_v4(), 2000);
+        let parts = cookie.split('.').collect::<Vec<_>>();
+        let extended = format!("{}.{}.{}", parts[0], 9000, parts[2]);
+        assert_eq!(device_cookie_verify(KEY, UserId(1), &extended, 3000), None);
+        assert_eq!(device_cookie_verify(KEY, UserId(1), "garbage", 1000), None);
+    }
+
+    #[test]
+    fn test_trust_device_without_fingerprint() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.trust_device();
+        assert_eq!(core.run(work).is_err(), true);
+    }
+
+    #[test]
+    fn test_get_devices_unauthorized() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let work = service.get_devices();
+        assert_eq!(core.run(work).is_err(), true);
+    }
+}
@@ -0,0 +1,221 @@
+//! Event schema registry. Schemas of events emitted to other systems are kept in
+//! `events/schemas` and served by `/events/schemas`, so consumers fetch the authoritative
+//! definitions. Payloads are validated against the schema of their event before they are
+//! emitted, invalid payloads are not emitted at all.
+//!
+//! Events notified by database triggers are built in SQL and can not be validated before they
+//! are emitted, their schemas are checked against the payloads the triggers build in tests, so
+//! changes of the triggers go along with a schema update.
+//!
+//! Validation supports the subset of JSON Schema the registered schemas use: `type`, `enum`,
+//! `required`, `properties`, `items` and local `$ref`.
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use futures::future;
+use r2d2::ManageConnection;
+use serde_json::{self, Value};
+
+use models::EventSchema;
+use repos::repo_factory::ReposFactory;
+use services::types::ServiceFuture;
+use services::Service;
+
+/// User created, mirrored to the legacy user API
+pub const USER_CREATED: &str = "user_created";
+/// User updated, mirrored to the legacy user API
+pub const USER_UPDATED: &str = "user_updated";
+/// Break-glass account activated, paged to the break-glass webhook
+pub const BREAK_GLASS_ACTIVATED: &str = "break_glass_activated";
+/// User deletion requested, notified by the `notify_user_deletion_requested` trigger
+pub const USER_DELETION_REQUESTED: &str = "user_deletion_requested";
+/// Tokens of the OAuth client revoked, notified by the `notify_oauth_client_revoked` trigger
+pub const OAUTH_CLIENT_REVOKED: &str = "oauth_client_revoked";
+
+lazy_static! {
+    static ref EVENT_SCHEMAS: Vec<EventSchema> = {
+        let user = parse_schema(include_str!("../../events/schemas/user.json"));
+        vec![
+            EventSchema {
+                event: USER_CREATED,
+                version: 1,
+                schema: user.clone(),
+            },
+            EventSchema {
+                event: USER_UPDATED,
+                version: 1,
+                schema: user,
+            },
+            EventSchema {
+                event: BREAK_GLASS_ACTIVATED,
+                version: 1,
+                schema: parse_schema(include_str!("../../events/schemas/break_glass_activated.json")),
+            },
+            EventSchema {
+                event: USER_DELETION_REQUESTED,
+                version: 1,
+                schema: parse_schema(include_str!("../../events/schemas/user_deletion_requested.json")),
+            },
+            EventSchema {
+                event: OAUTH_CLIENT_REVOKED,
+                version: 1,
+                schema: parse_schema(include_str!("../../events/schemas/oauth_client_revoked.json")),
+            },
+        ]
+    };
+}
+
+fn parse_schema(schema: &str) -> Value {
+    serde_json::from_str(schema).expect("Invalid event schema")
+}
+
+pub trait EventSchemasService {
+    /// Returns schemas of all emitted events
+    fn get_event_schemas(&self) -> ServiceFuture<Vec<EventSchema>>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > EventSchemasService for Service<T, M, F>
+{
+    /// Returns schemas of all emitted events
+    fn get_event_schemas(&self) -> ServiceFuture<Vec<EventSchema>> {
+        Box::new(future::ok(EVENT_SCHEMAS.clone()))
+    }
+}
+
+/// Validates payload of the event against its registered schema
+pub fn validate_event(event: &str, payload: &Value) -> Result<(), FailureError> {
+    let schema = EVENT_SCHEMAS
+        .iter()
+        .find(|schema| schema.event == event)
+        .ok_or_else(|| format_err!("Event {} has no registered schema", event))?;
+    let mut errors = vec![];
+    validate(&schema.schema, &schema.schema, payload, "$", &mut errors);
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(format_err!(
+            "Payload of event {} does not match its schema: {}",
+            event,
+            errors.join("; ")
+        ))
+    }
+}
+
+fn validate(root: &Value, schema: &Value, value: &Value, path: &str, errors: &mut Vec<String>) {
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        match root.pointer(reference.trim_left_matches('#')) {
+            Some(referenced) => validate(root, referenced, value, path, errors),
+            None => errors.push(format!("{}: unknown reference {}", path, reference)),
+        }
+        return;
+    }
+
+    if let Some(types) = schema.get("type") {
+        let matches = match *types {
+            Value::String(ref name) => is_of_type(value, name),
+            Value::Array(ref names) => names.iter().filter_map(Value::as_str).any(|name| is_of_type(value, name)),
+            _ => true,
+        };
+        if !matches {
+            errors.push(format!("{}: expected {}, found {}", path, types, value));
+            return;
+        }
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            errors.push(format!("{}: {} is not one of {:?}", path, value, allowed));
+        }
+    }
+
+    if let Value::Object(ref object) = *value {
+        if let Some(required) = schema.get("required").and_then(Value::as_array) {
+            for field in required.iter().filter_map(Value::as_str) {
+                if !object.contains_key(field) {
+                    errors.push(format!("{}: missing {}", path, field));
+                }
+            }
+        }
+        if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+            for (field, field_schema) in properties {
+                if let Some(field_value) = object.get(field) {
+                    validate(root, field_schema, field_value, &format!("{}.{}", path, field), errors);
+                }
+            }
+        }
+    }
+
+    if let (&Value::Array(ref items), Some(items_schema)) = (value, schema.get("items")) {
+        for (i, item) in items.iter().enumerate() {
+            validate(root, items_schema, item, &format!("{}[{}]", path, i), errors);
+        }
+    }
+}
+
+fn is_of_type(value: &Value, name: &str) -> bool {
+    match name {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use serde_json;
+    use stq_types::UserId;
+
+    use repos::repo_factory::tests::*;
+    use services::event_schemas::*;
+
+    #[test]
+    fn test_user_matches_schema() {
+        let user = create_user(UserId(1), MOCK_EMAIL.to_string());
+        let payload = serde_json::to_value(&user).unwrap();
+        assert_eq!(validate_event(USER_CREATED, &payload).is_ok(), true);
+        assert_eq!(validate_event(USER_UPDATED, &payload).is_ok(), true);
+    }
+
+    #[test]
+    fn test_trigger_payloads_match_schemas() {
+        // `pg_notify('user_deletion_requested', NEW.user_id::text)`
+        let payload = serde_json::from_str("42").unwrap();
+        assert_eq!(validate_event(USER_DELETION_REQUESTED, &payload).is_ok(), true);
+
+        // `json_build_object('user_id', .., 'client_id', .., 'revoke_before', ..)::text`
+        let payload =
+            serde_json::from_str(r#"{"user_id" : 42, "client_id" : "mobile-app", "revoke_before" : "2019-03-07T10:00:00.123456"}"#)
+                .unwrap();
+        assert_eq!(validate_event(OAUTH_CLIENT_REVOKED, &payload).is_ok(), true);
+
+        let payload = serde_json::from_str(r#"{"user_id" : "42", "client_id" : "mobile-app"}"#).unwrap();
+        let error = validate_event(OAUTH_CLIENT_REVOKED, &payload).unwrap_err().to_string();
+        assert!(error.contains("$.user_id"));
+        assert!(error.contains("missing revoke_before"));
+    }
+
+    #[test]
+    fn test_invalid_payload() {
+        let user = create_user(UserId(1), MOCK_EMAIL.to_string());
+        let mut payload = serde_json::to_value(&user).unwrap();
+        payload["email"] = serde_json::Value::Null;
+        payload["created_at"] = serde_json::Value::String("yesterday".to_string());
+        payload.as_object_mut().unwrap().remove("saga_id");
+        let error = validate_event(USER_CREATED, &payload).unwrap_err().to_string();
+        assert!(error.contains("$.email"));
+        assert!(error.contains("$.created_at"));
+        assert!(error.contains("missing saga_id"));
+        assert_eq!(validate_event("user_renamed", &payload).is_err(), true);
+    }
+}
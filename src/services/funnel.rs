@@ -0,0 +1,143 @@
+//! Funnel Services, registration funnel analytics. Steps reached by users are recorded
+//! as events on the primary shard, conversion is computed for users who registered in a date range.
+//! Events are keyed by user id, so that no PII is kept or logged, and are deleted with the user.
+
+use chrono::NaiveDate;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use r2d2::ManageConnection;
+
+use stq_types::UserId;
+
+use models::{FunnelStats, FunnelStep, FunnelStepStats, NewFunnelEvent};
+use repos::{FunnelEventsRepo, ReposFactory};
+use services::types::ServiceFuture;
+use services::Service;
+
+pub trait FunnelService {
+    /// Returns funnel of users who submitted registration from `from` to `to` inclusive
+    fn get_funnel_stats(&self, from: NaiveDate, to: NaiveDate) -> ServiceFuture<FunnelStats>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > FunnelService for Service<T, M, F>
+{
+    /// Returns funnel of users who submitted registration from `from` to `to` inclusive
+    fn get_funnel_stats(&self, from: NaiveDate, to: NaiveDate) -> ServiceFuture<FunnelStats> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        let service = self.clone();
+
+        debug!("Fetching registration funnel from {} to {}", from, to);
+
+        let key = format!("{:?}:{}:{}", current_uid, from, to);
+        self.static_context.single_flights.funnel_stats.run(key, move || {
+            service.spawn_on_pool(move |conn| {
+                let funnel_events_repo = repo_factory.create_funnel_events_repo(&*conn, current_uid);
+                FunnelStep::all()
+                    .into_iter()
+                    .map(|step| funnel_events_repo.count_cohort_step(from, to, step).map(|users| (step, users)))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map(|steps| FunnelStats {
+                        from,
+                        to,
+                        steps: funnel_conversion(&steps),
+                    })
+                    .map_err(|e: FailureError| e.context("Service funnel, get_funnel_stats endpoint error occured.").into())
+            })
+        })
+    }
+}
+
+/// Records the step reached by the user. Failure to record it does not fail the request.
+pub fn track_funnel_step(funnel_events_repo: &FunnelEventsRepo, user_id: UserId, step: FunnelStep) {
+    info!("Funnel step {} reached by user {}", step, user_id);
+    let event = NewFunnelEvent { user_id, step };
+    if let Err(e) = funnel_events_repo.add(event) {
+        warn!("Funnel step {} of user {} was not recorded: {}", step, user_id, e);
+    }
+}
+
+/// Conversion of every step relative to the first step and to the previous one
+fn funnel_conversion(steps: &[(FunnelStep, i64)]) -> Vec<FunnelStepStats> {
+    let ratio = |users: i64, of: i64| if of > 0 { users as f64 / of as f64 } else { 0.0 };
+    let registered = steps.first().map(|&(_, users)| users).unwrap_or(0);
+    let mut previous = registered;
+
+    steps
+        .iter()
+        .map(|&(step, users)| {
+            let stats = FunnelStepStats {
+                step,
+                users,
+                conversion: ratio(users, registered),
+                step_conversion: ratio(users, previous),
+            };
+            previous = users;
+            stats
+        })
+        .collect()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use chrono::NaiveDate;
+    use tokio_core::reactor::Core;
+
+    use stq_types::UserId;
+
+    use models::{FunnelStep, FunnelStepStats};
+    use repos::repo_factory::tests::*;
+    use services::funnel::*;
+
+    #[test]
+    fn test_funnel_conversion_of_empty_cohort() {
+        let result = funnel_conversion(&[(FunnelStep::RegistrationSubmitted, 0), (FunnelStep::EmailSent, 0)]);
+        assert_eq!(
+            result[1],
+            FunnelStepStats {
+                step: FunnelStep::EmailSent,
+                users: 0,
+                conversion: 0.0,
+                step_conversion: 0.0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_get_funnel_stats() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.get_funnel_stats(NaiveDate::from_ymd(2019, 2, 1), NaiveDate::from_ymd(2019, 2, 28));
+        let result = core.run(work).unwrap();
+        assert_eq!(result.steps.len(), 4);
+        assert_eq!(
+            result.steps[2],
+            FunnelStepStats {
+                step: FunnelStep::EmailVerified,
+                users: 100,
+                conversion: 0.5,
+                step_conversion: 0.5,
+            }
+        );
+        assert_eq!(
+            result.steps[3],
+            FunnelStepStats {
+                step: FunnelStep::FirstLogin,
+                users: 50,
+                conversion: 0.25,
+                step_conversion: 0.5,
+            }
+        );
+    }
+}
@@ -0,0 +1,129 @@
+//! Geo-restriction of logins. Tokens are issued only if the country of the client address,
+//! resolved by the gateway from GeoIP, is allowed for the client the user logs in to, see
+//! `config::GeoRestriction`. Denied logins are recorded in the audit log of the user.
+
+use serde_json;
+
+use stq_static_resources::Provider;
+use stq_types::UserId;
+
+use config::GeoRestriction;
+use errors::Error;
+use models::{AuditAction, NewAuditEvent};
+use repos::types::RepoResult;
+use repos::AuditLogRepo;
+
+/// Where the login with valid credentials is made from
+#[derive(Clone, Debug)]
+pub struct LoginLocation {
+    pub user_id: UserId,
+    pub provider: Provider,
+    pub client_id: Option<String>,
+    pub client_ip: Option<String>,
+    /// ISO 3166-1 alpha-3 code, absent if the gateway could not resolve it
+    pub country: Option<String>,
+}
+
+/// Checks the login is made from an allowed country, denied logins are audited and returned as errors
+pub fn check_login_country(audit_log_repo: &AuditLogRepo, conf: Option<&GeoRestriction>, location: LoginLocation) -> RepoResult<()> {
+    let conf = match conf {
+        Some(conf) => conf,
+        None => return Ok(()),
+    };
+    if conf.allows(
+        location.client_id.as_ref().map(String::as_str),
+        location.country.as_ref().map(String::as_str),
+    ) {
+        return Ok(());
+    }
+
+    warn!(
+        "Login of user {} from country {:?} to client {:?} is denied",
+        location.user_id, location.country, location.client_id
+    );
+    let mut data = serde_json::Map::new();
+    data.insert("provider".to_string(), serde_json::to_value(&location.provider)?);
+    data.insert("client_id".to_string(), serde_json::to_value(&location.client_id)?);
+    data.insert("client_ip".to_string(), serde_json::to_value(&location.client_ip)?);
+    data.insert("country".to_string(), serde_json::to_value(&location.country)?);
+    audit_log_repo.add(NewAuditEvent {
+        user_id: location.user_id,
+        actor_id: None,
+        action: AuditAction::LoginGeoRestricted,
+        data: Some(data.into()),
+    })?;
+    Err(Error::Validate(validation_errors!({"country": ["geo_restricted" => "Login from the country is not allowed"]})).into())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::collections::HashMap;
+
+    use config::CountryLists;
+    use repos::repo_factory::tests::*;
+
+    use super::*;
+
+    fn geo_restriction() -> GeoRestriction {
+        let mut clients = HashMap::new();
+        clients.insert(
+            MOCK_CLIENT_ID.to_string(),
+            CountryLists {
+                allowed: Some(vec!["RUS".to_string(), "EST".to_string()]),
+                denied: vec![],
+            },
+        );
+        GeoRestriction {
+            default: CountryLists {
+                allowed: None,
+                denied: vec!["PRK".to_string()],
+            },
+            clients,
+        }
+    }
+
+    fn location(client_id: Option<&str>, country: Option<&str>) -> LoginLocation {
+        LoginLocation {
+            user_id: UserId(1),
+            provider: Provider::Email,
+            client_id: client_id.map(str::to_string),
+            client_ip: Some("127.0.0.1".to_string()),
+            country: country.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_denied_country() {
+        let conf = geo_restriction();
+        assert_eq!(conf.allows(None, Some("prk")), false);
+        assert_eq!(conf.allows(None, Some("USA")), true);
+        assert_eq!(conf.allows(None, None), true);
+    }
+
+    #[test]
+    fn test_allowed_countries_of_client() {
+        let conf = geo_restriction();
+        assert_eq!(conf.allows(Some(MOCK_CLIENT_ID), Some("EST")), true);
+        assert_eq!(conf.allows(Some(MOCK_CLIENT_ID), Some("USA")), false);
+        assert_eq!(conf.allows(Some(MOCK_CLIENT_ID), None), false);
+        assert_eq!(conf.allows(Some(MOCK_SESSION_CLIENT_ID), Some("USA")), true);
+    }
+
+    #[test]
+    fn test_check_login_country() {
+        let audit_log_repo = AuditLogRepoMock::default();
+        let conf = geo_restriction();
+        assert_eq!(
+            check_login_country(&audit_log_repo, None, location(None, Some("PRK"))).is_ok(),
+            true
+        );
+        assert_eq!(
+            check_login_country(&audit_log_repo, Some(&conf), location(None, Some("EST"))).is_ok(),
+            true
+        );
+        assert_eq!(
+            check_login_country(&audit_log_repo, Some(&conf), location(None, Some("PRK"))).is_err(),
+            true
+        );
+    }
+}
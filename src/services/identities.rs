@@ -0,0 +1,194 @@
+//! Identities Services, support operations on identities of users for superusers, e.g. recovering
+//! accounts with a compromised social provider. Every change is recorded in the audit log of the
+//! user and revokes tokens issued to the user before it.
+
+use std::time::{Duration, SystemTime};
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::future;
+use r2d2::ManageConnection;
+use rand::{self, Rng};
+use serde_json;
+
+use stq_static_resources::Provider;
+use stq_types::UserId;
+
+use errors::Error;
+use models::{AuditAction, IdentityInfo, NewAuditEvent, NewProviderBlock, TemporaryPassword};
+use repos::ReposFactory;
+use services::types::ServiceFuture;
+use services::util::rollout_password_create;
+use services::Service;
+
+/// Length of generated temporary passwords
+const TEMPORARY_PASSWORD_LENGTH: usize = 16;
+
+pub trait IdentitiesService {
+    /// Returns identities of the user
+    fn get_identities(&self, user_id: UserId) -> ServiceFuture<Vec<IdentityInfo>>;
+    /// Unlinks the social provider from the user, the user logs in with e-mail afterwards and
+    /// logins with the provider are rejected
+    fn unlink_provider(&self, user_id: UserId, provider: Provider) -> ServiceFuture<IdentityInfo>;
+    /// Sets generated temporary password of the user, to be changed on the next login
+    fn set_temporary_password(&self, user_id: UserId) -> ServiceFuture<TemporaryPassword>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > IdentitiesService for Service<T, M, F>
+{
+    /// Returns identities of the user
+    fn get_identities(&self, user_id: UserId) -> ServiceFuture<Vec<IdentityInfo>> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let ident_repo = repo_factory.create_identities_repo_with_acl(&*conn, current_uid);
+            ident_repo
+                .list_for_user(user_id)
+                .map(|idents| idents.into_iter().map(IdentityInfo::from).collect())
+                .map_err(|e: FailureError| e.context("Service identities, get_identities endpoint error occured.").into())
+        })
+    }
+
+    /// Unlinks the social provider from the user, the user logs in with e-mail afterwards
+    fn unlink_provider(&self, user_id: UserId, provider: Provider) -> ServiceFuture<IdentityInfo> {
+        let actor_id = match self.dynamic_context.user_id {
+            Some(current_uid) => current_uid,
+            None => return Box::new(future::err(Error::Forbidden.context("Only admins can unlink providers").into())),
+        };
+        if provider == Provider::Email {
+            return Box::new(future::err(
+                Error::Validate(validation_errors!({"provider": ["not_social" => "Only social providers can be unlinked"]})).into(),
+            ));
+        }
+        let repo_factory = self.static_context.repo_factory.clone();
+        let revoke_before = SystemTime::now() + Duration::from_secs(self.static_context.config.tokens.jwt_expiration_s);
+
+        info!("Unlinking provider {} of user {} by user {}", provider, user_id, actor_id);
+
+        self.spawn_on_pool(move |conn| {
+            let ident_repo = repo_factory.create_identities_repo_with_acl(&*conn, Some(actor_id));
+            let users_repo = repo_factory.create_users_repo(&*conn, Some(actor_id));
+            let provider_blocks_repo = repo_factory.create_provider_blocks_repo(&*conn);
+            let audit_log_repo = repo_factory.create_audit_log_repo_with_sys_acl(&*conn);
+            conn.transaction::<IdentityInfo, FailureError, _>(|| {
+                let ident = ident_repo
+                    .unlink_provider(user_id, provider.clone())?
+                    .ok_or_else(|| Error::NotFound.context(format!("User {} has no identity with provider {}", user_id, provider)))?;
+                provider_blocks_repo.block(NewProviderBlock {
+                    user_id,
+                    provider: provider.clone(),
+                })?;
+                users_repo.revoke_tokens(user_id, revoke_before)?;
+                let mut data = serde_json::Map::new();
+                data.insert("provider".to_string(), serde_json::to_value(&provider)?);
+                audit_log_repo.add(NewAuditEvent {
+                    user_id,
+                    actor_id: Some(actor_id),
+                    action: AuditAction::ProviderUnlinked,
+                    data: Some(data.into()),
+                })?;
+                Ok(ident.into())
+            })
+            .map_err(|e: FailureError| e.context("Service identities, unlink_provider endpoint error occured.").into())
+        })
+    }
+
+    /// Sets generated temporary password of the user
+    fn set_temporary_password(&self, user_id: UserId) -> ServiceFuture<TemporaryPassword> {
+        let actor_id = match self.dynamic_context.user_id {
+            Some(current_uid) => current_uid,
+            None => {
+                return Box::new(future::err(
+                    Error::Forbidden.context("Only admins can set temporary passwords").into(),
+                ))
+            }
+        };
+        let repo_factory = self.static_context.repo_factory.clone();
+        let rollouts = self.static_context.rollouts.clone();
+        let revoke_before = SystemTime::now() + Duration::from_secs(self.static_context.config.tokens.jwt_expiration_s);
+
+        info!("Setting temporary password of user {} by user {}", user_id, actor_id);
+
+        self.spawn_on_pool(move |conn| {
+            let ident_repo = repo_factory.create_identities_repo_with_acl(&*conn, Some(actor_id));
+            let users_repo = repo_factory.create_users_repo(&*conn, Some(actor_id));
+            let audit_log_repo = repo_factory.create_audit_log_repo_with_sys_acl(&*conn);
+            let password = temporary_password();
+            conn.transaction::<TemporaryPassword, FailureError, _>(|| {
+                let ident = ident_repo
+                    .set_password(user_id, rollout_password_create(&rollouts, user_id, password.clone()))?
+                    .ok_or_else(|| Error::NotFound.context(format!("User {} has no identity", user_id)))?;
+                users_repo.revoke_tokens(user_id, revoke_before)?;
+                audit_log_repo.add(NewAuditEvent {
+                    user_id,
+                    actor_id: Some(actor_id),
+                    action: AuditAction::TemporaryPasswordSet,
+                    data: None,
+                })?;
+                Ok(TemporaryPassword {
+                    identity: ident.into(),
+                    password: password.clone(),
+                })
+            })
+            .map_err(|e: FailureError| {
+                e.context("Service identities, set_temporary_password endpoint error occured.")
+                    .into()
+            })
+        })
+    }
+}
+
+fn temporary_password() -> String {
+    rand::thread_rng().gen_ascii_chars().take(TEMPORARY_PASSWORD_LENGTH).collect()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use tokio_core::reactor::Core;
+
+    use repos::repo_factory::tests::*;
+
+    use super::*;
+
+    #[test]
+    fn test_unlink_email_provider() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.unlink_provider(UserId(2), Provider::Email);
+        assert_eq!(core.run(work).is_err(), true);
+    }
+
+    #[test]
+    fn test_unlink_provider() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.unlink_provider(UserId(2), Provider::Google);
+        let result = core.run(work).unwrap();
+        assert_eq!(result.provider, Provider::Email);
+        assert_eq!(result.has_password, false);
+    }
+
+    #[test]
+    fn test_set_temporary_password() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.set_temporary_password(UserId(2));
+        let result = core.run(work).unwrap();
+        assert_eq!(result.password.len(), TEMPORARY_PASSWORD_LENGTH);
+        assert_eq!(result.identity.has_password, true);
+        assert_eq!(result.identity.must_change_password, true);
+    }
+}
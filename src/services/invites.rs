@@ -0,0 +1,123 @@
+//! Invites Services, invite codes for registration in invite-only mode
+
+use std::time::Duration;
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::future;
+use r2d2::ManageConnection;
+
+use errors::Error;
+use models::{Invite, NewInvite, NewInvitePayload};
+use repos::{InvitesRepo, ReposFactory};
+use services::types::ServiceFuture;
+use services::Service;
+
+pub trait InvitesService {
+    /// Creates invite
+    fn create_invite(&self, payload: NewInvitePayload) -> ServiceFuture<Invite>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > InvitesService for Service<T, M, F>
+{
+    /// Creates invite
+    fn create_invite(&self, payload: NewInvitePayload) -> ServiceFuture<Invite> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+        let conf = &self.static_context.config.invites;
+
+        let max_uses = payload.max_uses.unwrap_or(conf.max_uses);
+        let expiration_s = payload.expiration_s.unwrap_or(conf.expiration_s);
+        if max_uses < 1 || max_uses > conf.max_uses {
+            return Box::new(future::err(
+                Error::Validate(validation_errors!({"max_uses": ["range" => "Number of uses is out of range"]})).into(),
+            ));
+        }
+        if expiration_s == 0 || expiration_s > conf.max_expiration_s {
+            return Box::new(future::err(
+                Error::Validate(validation_errors!({"expiration_s": ["range" => "Expiration is out of range"]})).into(),
+            ));
+        }
+        let new_invite = NewInvite::new(current_uid, max_uses, Duration::from_secs(expiration_s));
+
+        self.spawn_on_pool(move |conn| {
+            let invites_repo = repo_factory.create_invites_repo(&*conn, current_uid);
+            invites_repo
+                .create(new_invite)
+                .map_err(|e: FailureError| e.context("Service invites, create_invite endpoint error occured.").into())
+        })
+    }
+}
+
+/// Uses the invite on registration in invite-only mode, the use must be returned
+/// with `InvitesRepo::release_invite` if registration fails
+pub fn use_invite(invites_repo: &InvitesRepo, code: Option<String>) -> Result<String, FailureError> {
+    let code = match code {
+        Some(code) => code,
+        None => {
+            return Err(Error::Validate(validation_errors!({"invite_code": ["required" => "Registration is by invite only"]})).into());
+        }
+    };
+
+    match invites_repo.use_invite(code.clone())? {
+        Some(_) => Ok(code),
+        None => Err(Error::Validate(validation_errors!({"invite_code": ["invalid" => "Invite code is invalid or expired"]})).into()),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use tokio_core::reactor::Core;
+
+    use stq_types::UserId;
+
+    use models::*;
+    use repos::repo_factory::tests::*;
+    use services::invites::*;
+
+    #[test]
+    fn test_use_invite() {
+        let invites_repo = InvitesRepoMock::default();
+        assert_eq!(use_invite(&invites_repo, None).is_err(), true);
+        assert_eq!(use_invite(&invites_repo, Some("unknown".to_string())).is_err(), true);
+        assert_eq!(
+            use_invite(&invites_repo, Some(MOCK_INVITE_CODE.to_string())).unwrap(),
+            MOCK_INVITE_CODE.to_string()
+        );
+    }
+
+    #[test]
+    fn test_create_invite() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.create_invite(NewInvitePayload {
+            max_uses: Some(5),
+            expiration_s: None,
+        });
+        let result = core.run(work).unwrap();
+        assert_eq!(result.max_uses, 5);
+        assert_eq!(result.created_by, Some(UserId(1)));
+    }
+
+    #[test]
+    fn test_create_invite_over_quota() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.create_invite(NewInvitePayload {
+            max_uses: Some(1_000_000),
+            expiration_s: None,
+        });
+        assert_eq!(core.run(work).is_err(), true);
+    }
+}
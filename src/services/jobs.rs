@@ -0,0 +1,149 @@
+//! Jobs Services, tracking of long-running operations. Async endpoints create a job,
+//! run the work in background with `spawn_job` and respond with the job,
+//! clients poll `GET /jobs/<id>` until it is done and fetch the result from its location.
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use futures_cpupool::CpuPool;
+use r2d2::ManageConnection;
+use uuid::Uuid;
+
+use stq_types::UserId;
+
+use errors::Error;
+use models::{Job, UpdateJob};
+use repos::repo_factory::ReposFactory;
+use repos::sharding::ShardedPool;
+use repos::types::RepoResult;
+use services::types::ServiceFuture;
+use services::Service;
+
+pub trait JobsService {
+    /// Returns job by ID
+    fn get_job(&self, id: Uuid) -> ServiceFuture<Option<Job>>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > JobsService for Service<T, M, F>
+{
+    /// Returns job by ID
+    fn get_job(&self, id: Uuid) -> ServiceFuture<Option<Job>> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let jobs_repo = repo_factory.create_jobs_repo(&*conn, current_uid);
+            jobs_repo
+                .find(id)
+                .map_err(|e: FailureError| e.context("Service jobs, get_job endpoint error occured.").into())
+        })
+    }
+}
+
+/// Everything a background task needs to work with db and to update its job
+pub struct JobContext<M: ManageConnection, F> {
+    pub job_id: Uuid,
+    pub user_id: Option<UserId>,
+    pub db_pool: ShardedPool<M>,
+    pub repo_factory: F,
+}
+
+impl<T, M, F> JobContext<M, F>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    /// Context of the job created by the current user of the service
+    pub fn new(service: &Service<T, M, F>, job_id: Uuid) -> Self {
+        Self {
+            job_id,
+            user_id: service.dynamic_context.user_id,
+            db_pool: service.static_context.db_pool.clone(),
+            repo_factory: service.static_context.repo_factory.clone(),
+        }
+    }
+
+    /// Updates the job, jobs are stored on the primary shard. The job may be started
+    /// by the system on behalf of a user who can not update jobs, so no ACL check
+    pub fn update(&self, update: UpdateJob) -> RepoResult<Job> {
+        let conn = self.db_pool.primary().get().map_err(|e| e.context(Error::Connection))?;
+        let jobs_repo = self.repo_factory.create_jobs_repo_with_sys_acl(&*conn);
+        jobs_repo.update(self.job_id, update)
+    }
+
+    /// Saves number of processed items, failure to save it does not stop the job
+    pub fn report_progress(&self, progress: i32) {
+        if let Err(e) = self.update(UpdateJob::progress(progress)) {
+            warn!("Progress of job {} was not saved: {}", self.job_id, e);
+        }
+    }
+}
+
+/// Runs `task` in background on `cpu_pool`. The task returns location of its result,
+/// the job is marked done with it, or failed with the error of the task.
+pub fn spawn_job<T, M, F, Task>(cpu_pool: &CpuPool, context: JobContext<M, F>, task: Task)
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+    Task: FnOnce(&JobContext<M, F>) -> Result<Option<String>, FailureError> + Send + 'static,
+{
+    cpu_pool
+        .spawn_fn(move || {
+            run_job(&context, task);
+            Ok::<(), ()>(())
+        })
+        .forget();
+}
+
+/// Runs `task` in the current thread, for tasks already run in background. The job is marked
+/// done or failed same as by `spawn_job`.
+pub fn run_job<T, M, F, Task>(context: &JobContext<M, F>, task: Task)
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+    Task: FnOnce(&JobContext<M, F>) -> Result<Option<String>, FailureError>,
+{
+    let update = match task(context) {
+        Ok(result_location) => UpdateJob::done(result_location),
+        Err(e) => {
+            error!("Job {} failed: {}", context.job_id, e);
+            UpdateJob::failed(e.to_string())
+        }
+    };
+    if let Err(e) = context.update(update) {
+        error!("Result of job {} was not saved: {}", context.job_id, e);
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use tokio_core::reactor::Core;
+    use uuid::Uuid;
+
+    use stq_types::UserId;
+
+    use repos::repo_factory::tests::*;
+    use services::jobs::JobsService;
+
+    #[test]
+    fn test_get_job() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let id = Uuid::new_v4();
+        let work = service.get_job(id);
+        let result = core.run(work).unwrap();
+        assert_eq!(result.unwrap().id, id);
+    }
+}
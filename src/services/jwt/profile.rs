@@ -5,7 +5,7 @@ use std::time::SystemTime;
 
 use stq_static_resources::Gender;
 
-use models::{NewUser, UpdateUser, User};
+use models::{normalize_email, NewUser, UpdateUser, User};
 
 use uuid::Uuid;
 
@@ -23,7 +23,7 @@ pub struct GoogleProfile {
 impl From<GoogleProfile> for NewUser {
     fn from(google_id: GoogleProfile) -> Self {
         NewUser {
-            email: google_id.email,
+            email: normalize_email(&google_id.email),
             phone: None,
             first_name: Some(google_id.given_name),
             last_name: google_id.family_name,
@@ -36,6 +36,8 @@ impl From<GoogleProfile> for NewUser {
             utm_marks: None,
             country: None,
             referer: None,
+            data_region: None,
+            id: None,
         }
     }
 }
@@ -59,7 +61,7 @@ impl From<FacebookProfile> for NewUser {
             None
         };
         NewUser {
-            email: facebook_id.email,
+            email: normalize_email(&facebook_id.email),
             phone: None,
             first_name: Some(facebook_id.first_name),
             last_name: facebook_id.last_name,
@@ -72,6 +74,8 @@ impl From<FacebookProfile> for NewUser {
             utm_marks: None,
             country: None,
             referer: None,
+            data_region: None,
+            id: None,
         }
     }
 }
@@ -83,13 +87,13 @@ pub trait Email {
 
 impl Email for FacebookProfile {
     fn get_email(&self) -> String {
-        self.email.clone()
+        normalize_email(&self.email)
     }
 }
 
 impl Email for GoogleProfile {
     fn get_email(&self) -> String {
-        self.email.clone()
+        normalize_email(&self.email)
     }
 }
 
@@ -0,0 +1,150 @@
+//! Legacy sync Services, dual-write of users to the legacy user API during cutover from it.
+//! Created and updated users are mirrored in background after the write is committed here,
+//! mirroring never fails the request. Outcome of the last mirrored write of every user is
+//! kept, users whose last write failed are listed in the reconciliation report for resync.
+//!
+//! Mirroring is off unless `legacy_sync` is configured.
+
+use std::time::Duration;
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::Future;
+use hyper::Method;
+use r2d2::ManageConnection;
+use serde_json;
+
+use stq_http::client::{HttpClient, TimeLimitedHttpClient};
+
+use errors::Error;
+use models::{LegacySyncOperation, LegacySyncReport, NewLegacySyncState, User};
+use repos::repo_factory::ReposFactory;
+use services::event_schemas::{validate_event, USER_CREATED, USER_UPDATED};
+use services::types::ServiceFuture;
+use services::work_pool::WorkPriority;
+use services::Service;
+
+/// How many failed users are listed in the report, the rest are only counted
+const REPORT_FAILURES_LIMIT: i64 = 1000;
+
+pub trait LegacySyncService {
+    /// Returns reconciliation report of mirroring users to the legacy system
+    fn get_legacy_sync_report(&self) -> ServiceFuture<LegacySyncReport>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > LegacySyncService for Service<T, M, F>
+{
+    /// Returns reconciliation report of mirroring users to the legacy system
+    fn get_legacy_sync_report(&self) -> ServiceFuture<LegacySyncReport> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        Box::new(
+            self.spawn_on_pool(move |conn| {
+                let legacy_sync_states_repo = repo_factory.create_legacy_sync_states_repo(&*conn, current_uid);
+                let synced = legacy_sync_states_repo.count(true)?;
+                let failed = legacy_sync_states_repo.count(false)?;
+                let failures = legacy_sync_states_repo.list_failed(REPORT_FAILURES_LIMIT)?;
+                Ok(LegacySyncReport { synced, failed, failures })
+            })
+            .map_err(|e: FailureError| {
+                e.context("Service legacy_sync, get_legacy_sync_report endpoint error occured.")
+                    .into()
+            }),
+        )
+    }
+}
+
+/// Mirrors the write of the user to the legacy user API in background, if configured.
+/// Sync states are stored on the primary shard.
+pub fn mirror_to_legacy<T, M, F>(service: &Service<T, M, F>, operation: LegacySyncOperation, user: &User)
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    let conf = match service.static_context.config.legacy_sync {
+        Some(ref conf) => conf.clone(),
+        None => return,
+    };
+    let http_client = TimeLimitedHttpClient::new(service.static_context.client_handle.clone(), Duration::from_secs(conf.timeout_s));
+    let db_pool = service.static_context.db_pool.clone();
+    let repo_factory = service.static_context.repo_factory.clone();
+    let user = user.clone();
+
+    service
+        .static_context
+        .work_pool
+        .cpu_pool(WorkPriority::Batch)
+        .spawn_fn(move || {
+            let (method, url) = match operation {
+                LegacySyncOperation::Create => (Method::Post, format!("{}/users", conf.url)),
+                LegacySyncOperation::Update => (Method::Put, format!("{}/users/{}", conf.url, user.id)),
+            };
+            let event = match operation {
+                LegacySyncOperation::Create => USER_CREATED,
+                LegacySyncOperation::Update => USER_UPDATED,
+            };
+            let res = serde_json::to_value(&user).map_err(FailureError::from).and_then(|payload| {
+                validate_event(event, &payload)?;
+                http_client
+                    .request_json::<serde_json::Value>(method, url, Some(payload.to_string()), None)
+                    .wait()
+                    .map_err(|e| e.context(Error::HttpClient).into())
+            });
+            let error = match res {
+                Ok(_) => None,
+                Err(e) => {
+                    error!("Couldn't mirror {} of user {} to legacy system: {}", operation, user.id, e);
+                    Some(e.to_string())
+                }
+            };
+            let state = NewLegacySyncState {
+                user_id: user.id,
+                operation,
+                synced: error.is_none(),
+                error,
+            };
+            let saved = db_pool
+                .primary()
+                .get()
+                .map_err(|e| e.context(Error::Connection).into())
+                .and_then(|conn| repo_factory.create_legacy_sync_states_repo_with_sys_acl(&*conn).upsert(state));
+            if let Err(e) = saved {
+                error!("Legacy sync state of user {} was not saved: {}", user.id, e);
+            }
+            Ok::<(), ()>(())
+        })
+        .forget();
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use tokio_core::reactor::Core;
+
+    use stq_types::UserId;
+
+    use repos::repo_factory::tests::*;
+    use services::legacy_sync::LegacySyncService;
+
+    #[test]
+    fn test_get_legacy_sync_report() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.get_legacy_sync_report();
+        let result = core.run(work).unwrap();
+        assert_eq!(result.synced, 2);
+        assert_eq!(result.failed, 1);
+        assert_eq!(result.failures[0].user_id, UserId(1));
+    }
+}
@@ -0,0 +1,249 @@
+//! Login risk Services. Logins with valid credentials are posted to the fraud-scoring service
+//! along with device, IP and velocity features computed from the login history of the user.
+//! Logins scored above `step_up_threshold` get tokens requiring step-up authentication, those
+//! scored above `block_threshold` are blocked. Users who can't step up, i.e. have no password,
+//! are blocked instead. Logins from trusted devices skip step-up, see `devices`.
+//!
+//! Logins are allowed if scoring is not configured or the service is not available. The
+//! decision is recorded in the login history either way, blocked logins included.
+
+use std::collections::HashSet;
+use std::time::{Duration, SystemTime};
+
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::Future;
+use hyper::Method;
+use serde_json;
+
+use stq_http::client::HttpClient;
+use stq_static_resources::Provider;
+use stq_types::UserId;
+
+use config;
+use errors::Error;
+use models::{FraudScoreRequest, FraudScoreResponse, LoginDecision, LoginEvent, LoginFeatures, NewLoginEvent};
+use repos::LoginHistoryRepo;
+
+/// How far back the login history is looked for known IPs and user agents
+const HISTORY_WINDOW_S: u64 = 30 * 24 * 3600;
+const HOUR_S: u64 = 3600;
+const DAY_S: u64 = 24 * 3600;
+
+/// Login with valid credentials, to be scored
+#[derive(Clone, Debug)]
+pub struct LoginAttempt {
+    pub user_id: UserId,
+    pub provider: Provider,
+    pub client_ip: Option<String>,
+    pub user_agent: Option<String>,
+    /// Whether the user has a password to step up with
+    pub can_step_up: bool,
+    /// Whether the login is made from a device the user trusted
+    pub trusted_device: bool,
+}
+
+/// Scores the login and records the decision, blocked logins are returned as errors.
+/// Requests the fraud-scoring service synchronously, so is called on pool threads only.
+pub fn assess_login<C: HttpClient>(
+    login_history_repo: &LoginHistoryRepo,
+    http_client: &C,
+    conf: Option<&config::FraudScoring>,
+    attempt: LoginAttempt,
+) -> Result<LoginDecision, FailureError> {
+    let now = SystemTime::now();
+    let history = login_history_repo.list_since(attempt.user_id, now - Duration::from_secs(HISTORY_WINDOW_S))?;
+
+    let score = conf.and_then(|conf| {
+        let features = login_features(
+            &history,
+            attempt.client_ip.as_ref().map(String::as_str),
+            attempt.user_agent.as_ref().map(String::as_str),
+            attempt.trusted_device,
+            now,
+        );
+        let request = FraudScoreRequest {
+            user_id: attempt.user_id,
+            provider: attempt.provider.clone(),
+            client_ip: attempt.client_ip.clone(),
+            user_agent: attempt.user_agent.clone(),
+            features,
+        };
+        let score = serde_json::to_string(&request).map_err(FailureError::from).and_then(|body| {
+            http_client
+                .request_json::<FraudScoreResponse>(Method::Post, conf.url.clone(), Some(body), None)
+                .wait()
+                .map_err(|e| e.context(Error::HttpClient).into())
+        });
+        match score {
+            Ok(response) => Some(response.score),
+            Err(e) => {
+                error!("Couldn't score login of user {}, allowing it: {}", attempt.user_id, e);
+                None
+            }
+        }
+    });
+
+    let decision = match (conf, score) {
+        (Some(conf), Some(score)) => decide(score, conf, attempt.can_step_up, attempt.trusted_device),
+        _ => LoginDecision::Allowed,
+    };
+
+    login_history_repo.add(NewLoginEvent {
+        user_id: attempt.user_id,
+        provider: attempt.provider,
+        client_ip: attempt.client_ip,
+        user_agent: attempt.user_agent,
+        fraud_score: score,
+        decision,
+    })?;
+
+    if decision == LoginDecision::Blocked {
+        warn!("Login of user {} is blocked, fraud score {:?}", attempt.user_id, score);
+        return Err(Error::Validate(validation_errors!({"email": ["suspicious" => "Login attempt looks suspicious"]})).into());
+    }
+    Ok(decision)
+}
+
+/// Decision on the login by its fraud score
+pub fn decide(score: f64, conf: &config::FraudScoring, can_step_up: bool, trusted_device: bool) -> LoginDecision {
+    if score > conf.block_threshold {
+        LoginDecision::Blocked
+    } else if score > conf.step_up_threshold {
+        if trusted_device {
+            LoginDecision::Allowed
+        } else if can_step_up {
+            LoginDecision::StepUpRequired
+        } else {
+            LoginDecision::Blocked
+        }
+    } else {
+        LoginDecision::Allowed
+    }
+}
+
+/// Computes features of the login from the history of the user, latest first. IPs and user
+/// agents of blocked logins are not considered known.
+pub fn login_features(
+    history: &[LoginEvent],
+    client_ip: Option<&str>,
+    user_agent: Option<&str>,
+    trusted_device: bool,
+    now: SystemTime,
+) -> LoginFeatures {
+    let age = |event: &LoginEvent| now.duration_since(event.created_at).unwrap_or(Duration::new(0, 0));
+    let trusted = || history.iter().filter(|event| event.decision != LoginDecision::Blocked);
+
+    let last_day = history.iter().filter(|event| age(event).as_secs() < DAY_S).collect::<Vec<_>>();
+    let mut ips_last_day = last_day
+        .iter()
+        .filter_map(|event| event.client_ip.as_ref().map(String::as_str))
+        .collect::<HashSet<_>>();
+    ips_last_day.extend(client_ip);
+
+    LoginFeatures {
+        logins_last_hour: last_day.iter().filter(|event| age(event).as_secs() < HOUR_S).count() as u32,
+        logins_last_day: last_day.len() as u32,
+        ips_last_day: ips_last_day.len() as u32,
+        new_ip: client_ip.map_or(false, |ip| {
+            !trusted().any(|event| event.client_ip.as_ref().map(String::as_str) == Some(ip))
+        }),
+        new_user_agent: user_agent.map_or(false, |user_agent| {
+            !trusted().any(|event| event.user_agent.as_ref().map(String::as_str) == Some(user_agent))
+        }),
+        seconds_since_last_login: history.first().map(|event| age(event).as_secs()),
+        trusted_device,
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn create_conf() -> config::FraudScoring {
+        config::FraudScoring {
+            url: "http://fraud-scoring/score".to_string(),
+            step_up_threshold: 0.7,
+            block_threshold: 0.95,
+        }
+    }
+
+    fn create_event(ip: &str, user_agent: &str, age_s: u64, decision: LoginDecision, now: SystemTime) -> LoginEvent {
+        LoginEvent {
+            id: 1,
+            user_id: UserId(1),
+            provider: Provider::Email,
+            client_ip: Some(ip.to_string()),
+            user_agent: Some(user_agent.to_string()),
+            fraud_score: Some(0.1),
+            decision,
+            created_at: now - Duration::from_secs(age_s),
+        }
+    }
+
+    #[test]
+    fn test_decide() {
+        let conf = create_conf();
+        assert_eq!(decide(0.1, &conf, true, false), LoginDecision::Allowed);
+        assert_eq!(decide(0.7, &conf, true, false), LoginDecision::Allowed);
+        assert_eq!(decide(0.8, &conf, true, false), LoginDecision::StepUpRequired);
+        assert_eq!(decide(0.99, &conf, true, false), LoginDecision::Blocked);
+    }
+
+    #[test]
+    fn test_decide_on_trusted_device() {
+        let conf = create_conf();
+        assert_eq!(decide(0.8, &conf, true, true), LoginDecision::Allowed);
+        assert_eq!(decide(0.8, &conf, false, true), LoginDecision::Allowed);
+        assert_eq!(decide(0.99, &conf, true, true), LoginDecision::Blocked);
+    }
+
+    #[test]
+    fn test_decide_without_password() {
+        let conf = create_conf();
+        assert_eq!(decide(0.1, &conf, false, false), LoginDecision::Allowed);
+        assert_eq!(decide(0.8, &conf, false, false), LoginDecision::Blocked);
+    }
+
+    #[test]
+    fn test_login_features() {
+        let now = SystemTime::now();
+        let history = vec![
+            create_event("10.0.0.1", "firefox", 600, LoginDecision::Allowed, now),
+            create_event("10.0.0.2", "chrome", 7200, LoginDecision::Allowed, now),
+            create_event("10.0.0.3", "firefox", 3 * DAY_S, LoginDecision::Allowed, now),
+        ];
+        let features = login_features(&history, Some("10.0.0.3"), Some("firefox"), false, now);
+        assert_eq!(
+            features,
+            LoginFeatures {
+                logins_last_hour: 1,
+                logins_last_day: 2,
+                ips_last_day: 3,
+                new_ip: false,
+                new_user_agent: false,
+                seconds_since_last_login: Some(600),
+                trusted_device: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_login_features_of_blocked_device() {
+        let now = SystemTime::now();
+        let history = vec![create_event("10.0.0.9", "curl", 60, LoginDecision::Blocked, now)];
+        let features = login_features(&history, Some("10.0.0.9"), Some("curl"), false, now);
+        assert_eq!(features.new_ip, true);
+        assert_eq!(features.new_user_agent, true);
+        assert_eq!(features.logins_last_hour, 1);
+    }
+
+    #[test]
+    fn test_login_features_of_first_login() {
+        let features = login_features(&[], Some("10.0.0.1"), None, false, SystemTime::now());
+        assert_eq!(features.ips_last_day, 1);
+        assert_eq!(features.new_ip, true);
+        assert_eq!(features.new_user_agent, false);
+        assert_eq!(features.seconds_since_last_login, None);
+    }
+}
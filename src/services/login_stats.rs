@@ -0,0 +1,130 @@
+//! LoginStats Services, login analytics. Every token issued for a login is counted
+//! per provider per day on the primary shard, stats are aggregated on request.
+
+use std::collections::BTreeMap;
+
+use chrono::{NaiveDate, Utc};
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use r2d2::ManageConnection;
+
+use stq_static_resources::Provider;
+
+use models::{LoginStat, StatsGranularity};
+use repos::{LoginStatsRepo, ReposFactory};
+use services::types::ServiceFuture;
+use services::Service;
+
+pub trait LoginStatsService {
+    /// Returns logins per provider from `from` to `to` inclusive, aggregated by `granularity`
+    fn get_login_stats(&self, from: NaiveDate, to: NaiveDate, granularity: StatsGranularity) -> ServiceFuture<Vec<LoginStat>>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > LoginStatsService for Service<T, M, F>
+{
+    /// Returns logins per provider from `from` to `to` inclusive, aggregated by `granularity`
+    fn get_login_stats(&self, from: NaiveDate, to: NaiveDate, granularity: StatsGranularity) -> ServiceFuture<Vec<LoginStat>> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        let service = self.clone();
+
+        debug!("Fetching login stats from {} to {} by {:?}", from, to, granularity);
+
+        let key = format!("{:?}:{}:{}:{:?}", current_uid, from, to, granularity);
+        self.static_context.single_flights.login_stats.run(key, move || {
+            service.spawn_on_pool(move |conn| {
+                let login_stats_repo = repo_factory.create_login_stats_repo(&*conn, current_uid);
+                login_stats_repo
+                    .list(from, to)
+                    .map(|stats| aggregate_login_stats(stats, granularity))
+                    .map_err(|e: FailureError| e.context("Service login_stats, get_login_stats endpoint error occured.").into())
+            })
+        })
+    }
+}
+
+/// Counts a login with the provider today. Failure to count it does not fail the login.
+pub fn count_login(login_stats_repo: &LoginStatsRepo, provider: Provider) {
+    if let Err(e) = login_stats_repo.add_login(Utc::today().naive_utc(), provider.clone()) {
+        warn!("{} login was not counted: {}", provider, e);
+    }
+}
+
+/// Sums daily counters over periods, ordered by period and provider
+pub fn aggregate_login_stats(stats: Vec<LoginStat>, granularity: StatsGranularity) -> Vec<LoginStat> {
+    let mut periods = BTreeMap::new();
+    for stat in stats {
+        let date = granularity.period_start(stat.date);
+        periods
+            .entry((date, stat.provider.to_string()))
+            .or_insert_with(|| LoginStat {
+                date,
+                logins: 0,
+                ..stat.clone()
+            })
+            .logins += stat.logins;
+    }
+    periods.into_iter().map(|(_, stat)| stat).collect()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use chrono::NaiveDate;
+    use tokio_core::reactor::Core;
+
+    use stq_static_resources::Provider;
+    use stq_types::UserId;
+
+    use models::StatsGranularity;
+    use repos::repo_factory::tests::*;
+    use services::login_stats::*;
+
+    #[test]
+    fn test_aggregate_login_stats_by_week() {
+        let stats = vec![
+            create_login_stat(NaiveDate::from_ymd(2019, 2, 18), Provider::Email, 3),
+            create_login_stat(NaiveDate::from_ymd(2019, 2, 18), Provider::Google, 2),
+            create_login_stat(NaiveDate::from_ymd(2019, 2, 20), Provider::Email, 1),
+            create_login_stat(NaiveDate::from_ymd(2019, 2, 25), Provider::Email, 4),
+        ];
+        let result = aggregate_login_stats(stats, StatsGranularity::Week);
+        assert_eq!(
+            result,
+            vec![
+                create_login_stat(NaiveDate::from_ymd(2019, 2, 18), Provider::Email, 4),
+                create_login_stat(NaiveDate::from_ymd(2019, 2, 18), Provider::Google, 2),
+                create_login_stat(NaiveDate::from_ymd(2019, 2, 25), Provider::Email, 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_get_login_stats_by_month() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.get_login_stats(
+            NaiveDate::from_ymd(2019, 2, 1),
+            NaiveDate::from_ymd(2019, 2, 28),
+            StatsGranularity::Month,
+        );
+        let result = core.run(work).unwrap();
+        assert_eq!(
+            result,
+            vec![
+                create_login_stat(NaiveDate::from_ymd(2019, 2, 1), Provider::Email, 4),
+                create_login_stat(NaiveDate::from_ymd(2019, 2, 1), Provider::Google, 2),
+            ]
+        );
+    }
+}
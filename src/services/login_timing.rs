@@ -0,0 +1,122 @@
+//! Forensic mode of logins for investigating intermittent latency. Superusers set `X-Login-Timing`
+//! on a login request to get durations of its stages in the `timing` section of the response.
+//! The header is ignored for everyone else, so it can't be used to learn about other users.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use stq_types::{UserId, UsersRole};
+
+use models::{LoginStage, LoginStageTiming, LoginTiming};
+use repos::types::RepoResult;
+use repos::UserRolesRepo;
+
+/// Records durations of login stages, does nothing unless enabled
+#[derive(Clone, Debug)]
+pub struct LoginTimer {
+    started: Instant,
+    stages: Option<Arc<Mutex<Vec<LoginStageTiming>>>>,
+}
+
+impl LoginTimer {
+    pub fn disabled() -> Self {
+        Self {
+            started: Instant::now(),
+            stages: None,
+        }
+    }
+
+    pub fn enabled() -> Self {
+        Self {
+            started: Instant::now(),
+            stages: Some(Arc::new(Mutex::new(vec![]))),
+        }
+    }
+
+    /// Runs the stage and records its duration
+    pub fn time<T, F: FnOnce() -> T>(&self, stage: LoginStage, f: F) -> T {
+        if self.stages.is_none() {
+            return f();
+        }
+        let started = Instant::now();
+        let res = f();
+        self.record(stage, started.elapsed());
+        res
+    }
+
+    /// Records duration of the stage measured elsewhere, e.g. of a future
+    pub fn record(&self, stage: LoginStage, duration: Duration) {
+        if let Some(ref stages) = self.stages {
+            let duration_us = as_micros(duration);
+            debug!("Login stage {} took {} us", stage, duration_us);
+            stages.lock().unwrap().push(LoginStageTiming { stage, duration_us });
+        }
+    }
+
+    /// Recorded stages, none if the timer is disabled
+    pub fn timing(&self) -> Option<LoginTiming> {
+        self.stages.as_ref().map(|stages| LoginTiming {
+            stages: stages.lock().unwrap().clone(),
+            total_us: as_micros(self.started.elapsed()),
+        })
+    }
+}
+
+/// Timer of the login, enabled if requested by a superuser
+pub fn login_timer(user_roles_repo: &UserRolesRepo, requested: bool, current_uid: Option<UserId>) -> RepoResult<LoginTimer> {
+    if !requested {
+        return Ok(LoginTimer::disabled());
+    }
+    match current_uid {
+        Some(current_uid) if user_roles_repo.list_for_user(current_uid)?.contains(&UsersRole::Superuser) => {
+            info!("Login timing requested by user {}", current_uid);
+            Ok(LoginTimer::enabled())
+        }
+        _ => {
+            warn!("Login timing requested by user {:?} who is not a superuser, ignoring", current_uid);
+            Ok(LoginTimer::disabled())
+        }
+    }
+}
+
+fn as_micros(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000_000 + u64::from(duration.subsec_micros())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use repos::repo_factory::tests::*;
+
+    #[test]
+    fn test_login_timer_of_superuser_only() {
+        let user_roles_repo = UserRolesRepoMock::default();
+        assert_eq!(
+            login_timer(&user_roles_repo, true, Some(UserId(1))).unwrap().timing().is_some(),
+            true
+        );
+        assert_eq!(
+            login_timer(&user_roles_repo, true, Some(UserId(2))).unwrap().timing().is_some(),
+            false
+        );
+        assert_eq!(login_timer(&user_roles_repo, true, None).unwrap().timing().is_some(), false);
+        assert_eq!(
+            login_timer(&user_roles_repo, false, Some(UserId(1))).unwrap().timing().is_some(),
+            false
+        );
+    }
+
+    #[test]
+    fn test_login_timer_records_stages() {
+        let timer = LoginTimer::enabled();
+        assert_eq!(timer.time(LoginStage::PasswordHash, || 42), 42);
+        timer.clone().record(LoginStage::ProviderFetch, Duration::from_millis(3));
+        let timing = timer.timing().unwrap();
+        assert_eq!(
+            timing.stages.iter().map(|timing| timing.stage).collect::<Vec<_>>(),
+            vec![LoginStage::PasswordHash, LoginStage::ProviderFetch]
+        );
+        assert_eq!(timing.stages[1].duration_us, 3000);
+        assert_eq!(LoginTimer::disabled().time(LoginStage::JwtEncode, || 42), 42);
+    }
+}
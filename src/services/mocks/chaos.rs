@@ -0,0 +1,123 @@
+//! Fault injection into calls to upstream OAuth providers, built for tests and with `chaos` feature only.
+//! Wraps provider service and adds latency, 5xx responses and malformed JSON bodies
+//! with configured rates, failures are reported the same way as by http client.
+//! There is no circuit breaker in front of providers yet, and failed calls are not retried
+//! by services, so every injected fault ends up in the response to the client.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::future;
+use futures::{Future, IntoFuture};
+use futures_cpupool::CpuPool;
+use hyper::{Headers, StatusCode};
+use rand::{self, Rng};
+use serde;
+use serde_json;
+
+use config::Faults;
+use errors::Error;
+use models::NewUser;
+use services::jwt::profile::{Email, IntoUser};
+use services::jwt::JWTProviderService;
+use services::types::ServiceFuture;
+
+/// Body of a response cut off in the middle
+const MALFORMED_JSON: &'static str = r#"{"email": "user@mail.com", "name": "Us"#;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fault {
+    ServerError,
+    MalformedJson,
+}
+
+pub struct ChaosProviderService<P>
+where
+    P: Email + Clone + Send + 'static,
+    NewUser: From<P>,
+    P: for<'a> serde::Deserialize<'a>,
+    P: IntoUser,
+{
+    inner: Arc<JWTProviderService<P>>,
+    faults: Faults,
+    cpu_pool: CpuPool,
+    calls: AtomicUsize,
+}
+
+impl<P> ChaosProviderService<P>
+where
+    P: Email + Clone + Send + 'static,
+    NewUser: From<P>,
+    P: for<'a> serde::Deserialize<'a>,
+    P: IntoUser,
+{
+    /// Latency is awaited on `cpu_pool`, so that event loop is not blocked
+    pub fn new(inner: Arc<JWTProviderService<P>>, faults: Faults, cpu_pool: CpuPool) -> Self {
+        Self {
+            inner,
+            faults,
+            cpu_pool,
+            calls: AtomicUsize::new(0),
+        }
+    }
+
+    /// Number of calls made to provider
+    pub fn calls(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+
+    fn pick_fault(&self) -> Option<Fault> {
+        let point = rand::thread_rng().gen::<f64>();
+        if point < self.faults.server_error_rate {
+            Some(Fault::ServerError)
+        } else if point < self.faults.server_error_rate + self.faults.malformed_json_rate {
+            Some(Fault::MalformedJson)
+        } else {
+            None
+        }
+    }
+}
+
+impl<P> JWTProviderService<P> for ChaosProviderService<P>
+where
+    P: Email + Clone + Send + 'static,
+    NewUser: From<P>,
+    P: for<'a> serde::Deserialize<'a>,
+    P: IntoUser,
+{
+    fn get_profile(&self, url: String, headers: Option<Headers>) -> ServiceFuture<serde_json::Value> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+
+        let response: ServiceFuture<serde_json::Value> = match self.pick_fault() {
+            Some(Fault::ServerError) => Box::new(future::err(
+                format_err!("Injected {} response from {}", StatusCode::ServiceUnavailable, url)
+                    .context(Error::HttpClient)
+                    .into(),
+            )),
+            Some(Fault::MalformedJson) => Box::new(
+                serde_json::from_str::<serde_json::Value>(MALFORMED_JSON)
+                    .map_err(|e| e.context(Error::HttpClient).into())
+                    .into_future(),
+            ),
+            None => self.inner.get_profile(url, headers),
+        };
+
+        if self.faults.latency_ms == 0 {
+            return response;
+        }
+
+        let latency = Duration::from_millis(self.faults.latency_ms);
+        Box::new(
+            self.cpu_pool
+                .spawn_fn(move || {
+                    thread::sleep(latency);
+                    Ok::<_, FailureError>(())
+                })
+                .and_then(move |_| response),
+        )
+    }
+}
@@ -1 +1,3 @@
+#[cfg(any(test, feature = "chaos"))]
+pub mod chaos;
 pub mod jwt;
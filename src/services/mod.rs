@@ -1,11 +1,57 @@
 //! Services is a core layer for the app business logic like
 //! validation, authorization, etc.
 
+pub mod access_tokens;
+pub mod auth_archive;
+pub mod backfills;
+pub mod break_glass;
+pub mod child_accounts;
+pub mod client_branding;
+pub mod connected_apps;
+pub mod countries;
+pub mod crm_reconciliation;
+pub mod deletion_requests;
+pub mod dependency_health;
+pub mod devices;
+pub mod event_schemas;
+pub mod funnel;
+pub mod geo_restriction;
+pub mod identities;
+pub mod invites;
+pub mod jobs;
 pub mod jwt;
+pub mod legacy_sync;
+pub mod login_risk;
+pub mod login_stats;
+pub mod login_timing;
 pub mod mocks;
+pub mod name_screening;
+pub mod oauth;
+pub mod password_strength;
+pub mod profile_completion;
+pub mod profile_prompts;
+pub mod readiness;
+pub mod recovery;
+pub mod refresh_tokens;
+pub mod role_access_policies;
+pub mod rollout;
+pub mod schema_check;
+pub mod security_questions;
+pub mod segment_export;
+pub mod session_limits;
+pub mod sessions;
+pub mod signed_action;
+pub mod single_flight;
+pub mod stats;
+pub mod token_attempts;
 pub mod types;
+pub mod user_import;
 pub mod user_roles;
+pub mod user_tags;
 pub mod users;
 pub mod util;
+pub mod waitlist;
+pub mod warmup;
+pub mod work_pool;
 
 pub use self::types::Service;
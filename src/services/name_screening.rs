@@ -0,0 +1,143 @@
+//! Screening of user names for profanity and impersonation of staff accounts.
+//! Names are folded to a "skeleton" before matching, so that simple obfuscations
+//! like "Adm1n", "s.u.p.p.o.r.t" or Cyrillic look-alike letters are still caught.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use unicode_normalization::UnicodeNormalization;
+use validator::{ValidationError, ValidationErrors};
+
+use config::NameScreening;
+use errors::Error;
+use repos::types::RepoResult;
+
+pub trait NameScreeningService: Send + Sync {
+    /// Checks single name, returns validation error if it must not be used
+    fn screen(&self, name: &str) -> Result<(), ValidationError>;
+}
+
+/// Default screening based on configurable denylist and reserved names
+pub struct NameScreeningServiceImpl {
+    denylist: Vec<String>,
+    reserved_names: Vec<String>,
+}
+
+impl NameScreeningServiceImpl {
+    pub fn new(config: NameScreening) -> Self {
+        let skeletons = |words: Vec<String>| {
+            words
+                .iter()
+                .map(|word| skeleton(word))
+                .filter(|word| !word.is_empty())
+                .collect::<Vec<_>>()
+        };
+
+        Self {
+            denylist: skeletons(config.denylist),
+            reserved_names: skeletons(config.reserved_names),
+        }
+    }
+}
+
+impl NameScreeningService for NameScreeningServiceImpl {
+    fn screen(&self, name: &str) -> Result<(), ValidationError> {
+        let name = skeleton(name);
+
+        if self.denylist.iter().any(|word| name.contains(word.as_str())) {
+            return Err(screening_error("profanity", "Name contains inappropriate words"));
+        }
+        if self.reserved_names.iter().any(|word| name.contains(word.as_str())) {
+            return Err(screening_error("reserved", "Name is reserved"));
+        }
+
+        Ok(())
+    }
+}
+
+fn screening_error(code: &'static str, message: &'static str) -> ValidationError {
+    ValidationError {
+        code: Cow::from(code),
+        message: Some(Cow::from(message)),
+        params: HashMap::new(),
+    }
+}
+
+/// Folds name to lowercase letters and digits, undoing common substitutions
+fn skeleton(name: &str) -> String {
+    name.nfkc()
+        .flat_map(char::to_lowercase)
+        .filter_map(|c| match c {
+            '0' | 'о' => Some('o'),
+            '1' | '!' | '|' => Some('i'),
+            '3' | 'е' => Some('e'),
+            '4' | '@' | 'а' => Some('a'),
+            '5' | '$' => Some('s'),
+            '7' => Some('t'),
+            'р' => Some('p'),
+            'с' => Some('c'),
+            'у' => Some('y'),
+            'х' => Some('x'),
+            c if c.is_alphanumeric() => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Screens optional name fields of a payload, errors are collected by field
+pub fn screen_names(screening: &NameScreeningService, names: &[(&'static str, &Option<String>)]) -> RepoResult<()> {
+    let mut errors = ValidationErrors::new();
+    for &(field, name) in names {
+        if let Some(ref name) = *name {
+            if let Err(error) = screening.screen(name) {
+                errors.add(field, error);
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Validate(errors).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_screening() -> NameScreeningServiceImpl {
+        NameScreeningServiceImpl::new(NameScreening {
+            denylist: vec!["badword".to_string()],
+            reserved_names: vec!["admin".to_string(), "support".to_string()],
+        })
+    }
+
+    #[test]
+    fn test_screen_name() {
+        let screening = create_screening();
+        assert_eq!(screening.screen("Alice").is_ok(), true);
+        assert_eq!(screening.screen("Иван").is_ok(), true);
+        assert_eq!(screening.screen("Admin").unwrap_err().code, "reserved");
+        assert_eq!(screening.screen("Adm1n").unwrap_err().code, "reserved");
+        // Cyrillic "а" and "о" instead of Latin ones
+        assert_eq!(screening.screen("Suppоrt Teаm").unwrap_err().code, "reserved");
+        assert_eq!(screening.screen("s.u.p.p.o.r.t").unwrap_err().code, "reserved");
+        assert_eq!(screening.screen("B4dw0rd").unwrap_err().code, "profanity");
+    }
+
+    #[test]
+    fn test_screen_names() {
+        let screening = create_screening();
+        let first_name = Some("Admin".to_string());
+        let last_name = Some("Smith".to_string());
+        assert_eq!(
+            screen_names(&screening, &[("first_name", &first_name), ("last_name", &last_name)]).is_err(),
+            true
+        );
+        assert_eq!(
+            screen_names(&screening, &[("first_name", &None), ("last_name", &last_name)]).is_ok(),
+            true
+        );
+    }
+}
@@ -0,0 +1,659 @@
+//! OAuth2 Services, presents token endpoint of authorization server mode
+//! for integrations expecting standard OAuth2 semantics. Third-party clients use device
+//! authorization only, their tokens carry scopes the user consented to.
+
+use std::time::Duration;
+
+use chrono::Utc;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::future;
+use futures::{Future, IntoFuture};
+use jsonwebtoken::{encode, Algorithm, Header};
+use r2d2::ManageConnection;
+
+use stq_static_resources::Provider;
+use stq_types::UserId;
+
+use super::util::password_verify;
+use config::DeviceFlow;
+use errors::Error;
+use models::{
+    format_scope, format_user_code, normalize_email, parse_scope, Client, ConsentScreen, DeviceApproval, DeviceAuthorization,
+    DeviceAuthorizationRequest, DeviceCode, EmailIdentity, JWTPayload, NewDeviceCode, NewOAuthConsent, OAuthErrorCode, OAuthGrantType,
+    OAuthScope, OAuthToken, OAuthTokenRequest,
+};
+use repos::clients::ClientsRepo;
+use repos::device_codes::DeviceCodesRepo;
+use repos::oauth_consents::OAuthConsentsRepo;
+use repos::repo_factory::ReposFactory;
+use repos::types::RepoResult;
+use repos::users::UsersRepo;
+use services::jwt::JWTService;
+use services::login_stats::count_login;
+use services::types::ServiceFuture;
+use services::Service;
+
+pub trait OAuthService {
+    /// Issues access token for `client_credentials`, `password` or `device_code` grant
+    fn oauth_token(&self, request: OAuthTokenRequest) -> ServiceFuture<OAuthToken>;
+    /// Starts device authorization, returning codes the device shows to the user
+    fn device_authorization(&self, request: DeviceAuthorizationRequest) -> ServiceFuture<DeviceAuthorization>;
+    /// Returns consent screen data for the device awaiting approval by user code
+    fn device_consent(&self, user_code: String) -> ServiceFuture<ConsentScreen>;
+    /// Approves device by user code on behalf of current user, recording consent to requested scopes
+    fn approve_device(&self, approval: DeviceApproval) -> ServiceFuture<()>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > OAuthService for Service<T, M, F>
+{
+    /// Issues access token for `client_credentials`, `password` or `device_code` grant
+    fn oauth_token(&self, request: OAuthTokenRequest) -> ServiceFuture<OAuthToken> {
+        let grant_type = match request.grant_type.parse::<OAuthGrantType>() {
+            Ok(grant_type) => grant_type,
+            Err(code) => {
+                return Box::new(future::err(
+                    format_err!("Unsupported grant type {}", request.grant_type)
+                        .context(Error::OAuth(code))
+                        .into(),
+                ));
+            }
+        };
+
+        let repo_factory = self.static_context.repo_factory.clone();
+        let jwt_private_key = self.static_context.jwt_private_key.clone();
+        let polling_interval = Duration::from_secs(self.static_context.config.device_flow.polling_interval_s);
+        let service = self.clone();
+        let OAuthTokenRequest {
+            client_id,
+            client_secret,
+            username,
+            password,
+            device_code,
+            ..
+        } = request;
+
+        debug!("Issuing OAuth2 token, grant type: {:?}, client: {:?}", grant_type, client_id);
+
+        let client_fut = self.spawn_on_pool({
+            let repo_factory = repo_factory.clone();
+            move |conn| {
+                let clients_repo = repo_factory.create_clients_repo(&conn);
+                authenticate_client(&*clients_repo, client_id, client_secret)
+            }
+        });
+
+        let fut = client_fut.and_then(move |client| -> ServiceFuture<OAuthToken> {
+            match grant_type {
+                OAuthGrantType::ClientCredentials => Box::new(client_credentials_token(&client, &jwt_private_key).into_future()),
+                OAuthGrantType::DeviceCode => {
+                    let device_code = match device_code {
+                        Some(device_code) => device_code,
+                        None => {
+                            return Box::new(future::err(
+                                format_err!("device_code is required for device_code grant")
+                                    .context(Error::OAuth(OAuthErrorCode::InvalidRequest))
+                                    .into(),
+                            ));
+                        }
+                    };
+                    service.spawn_on_pool(move |conn| {
+                        let device_codes_repo = repo_factory.create_device_codes_repo(&conn);
+                        let users_repo = repo_factory.create_users_repo_with_sys_acl(&conn);
+                        let login_stats_repo = repo_factory.create_login_stats_repo(&conn, None);
+                        device_code_token(
+                            &*device_codes_repo,
+                            &*users_repo,
+                            &client,
+                            device_code,
+                            polling_interval,
+                            &jwt_private_key,
+                        )
+                        .map(|token| {
+                            count_login(&*login_stats_repo, Provider::Email);
+                            token
+                        })
+                    })
+                }
+                OAuthGrantType::Password if client.is_third_party => Box::new(future::err(
+                    format_err!("Third-party client {} is not allowed to use password grant", client.id)
+                        .context(Error::OAuth(OAuthErrorCode::UnauthorizedClient))
+                        .into(),
+                )),
+                OAuthGrantType::Password => {
+                    let (email, password) = match (username, password) {
+                        (Some(email), Some(password)) => (email, password),
+                        _ => {
+                            return Box::new(future::err(
+                                format_err!("Username and password are required for password grant")
+                                    .context(Error::OAuth(OAuthErrorCode::InvalidRequest))
+                                    .into(),
+                            ));
+                        }
+                    };
+                    let exp = client.token_expiration();
+                    let ident = EmailIdentity {
+                        email: normalize_email(&email),
+                        password,
+                        client_id: Some(client.id),
+                        remember_me: false,
+                    };
+                    Box::new(
+                        service
+                            .create_token_email(ident, exp)
+                            .map_err(|e| e.context(Error::OAuth(OAuthErrorCode::InvalidGrant)).into())
+                            .map(move |jwt| OAuthToken::bearer(jwt.token, exp, Utc::now().timestamp())),
+                    )
+                }
+            }
+        });
+
+        Box::new(fut.map_err(|e: FailureError| e.context("Service oauth, token endpoint error occured.").into()))
+    }
+
+    /// Starts device authorization, returning codes the device shows to the user
+    fn device_authorization(&self, request: DeviceAuthorizationRequest) -> ServiceFuture<DeviceAuthorization> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let DeviceFlow {
+            verification_uri,
+            code_expiration_s,
+            polling_interval_s,
+        } = self.static_context.config.device_flow.clone();
+
+        let fut = self.spawn_on_pool(move |conn| {
+            let clients_repo = repo_factory.create_clients_repo(&conn);
+            let device_codes_repo = repo_factory.create_device_codes_repo(&conn);
+
+            let client = authenticate_client(&*clients_repo, Some(request.client_id), request.client_secret)?;
+            // scopes limit third-party clients only, first-party tokens are not limited
+            let scope = if client.is_third_party {
+                Some(requested_scope(request.scope)?)
+            } else {
+                None
+            };
+            debug!("Creating device code for client {}, scope: {:?}", client.id, scope);
+            let device_code = device_codes_repo.create(NewDeviceCode::new(client.id, scope, Duration::from_secs(code_expiration_s)))?;
+
+            Ok(DeviceAuthorization {
+                device_code: device_code.device_code,
+                user_code: device_code.user_code,
+                verification_uri,
+                expires_in: code_expiration_s,
+                interval: polling_interval_s,
+            })
+        });
+
+        Box::new(fut.map_err(|e: FailureError| e.context("Service oauth, device_authorization endpoint error occured.").into()))
+    }
+
+    /// Returns consent screen data for the device awaiting approval by user code
+    fn device_consent(&self, user_code: String) -> ServiceFuture<ConsentScreen> {
+        let current_uid = match self.dynamic_context.user_id {
+            Some(current_uid) => current_uid,
+            None => {
+                return Box::new(future::err(
+                    Error::Forbidden.context("Only authorized user can approve device").into(),
+                ));
+            }
+        };
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        let fut = self.spawn_on_pool(move |conn| {
+            let clients_repo = repo_factory.create_clients_repo(&conn);
+            let client_brandings_repo = repo_factory.create_client_brandings_repo_with_sys_acl(&conn);
+            let device_codes_repo = repo_factory.create_device_codes_repo(&conn);
+            let oauth_consents_repo = repo_factory.create_oauth_consents_repo(&conn, Some(current_uid));
+
+            let device_code = find_pending_device_code(&*device_codes_repo, &user_code)?;
+            let client = clients_repo
+                .find(device_code.client_id.clone())?
+                .ok_or_else(|| Error::NotFound.context(format!("Client {} not found", device_code.client_id)))?;
+            let scopes = device_code_scopes(&device_code);
+            let granted = oauth_consents_repo
+                .find(current_uid, client.id.clone())?
+                .map(|consent| consent.scopes)
+                .unwrap_or_default();
+            let branding = client_brandings_repo.find(client.id.clone())?;
+
+            Ok(ConsentScreen {
+                client_id: client.id,
+                client_name: client.name,
+                granted_scopes: scopes.iter().filter(|scope| granted.contains(scope)).cloned().collect(),
+                scopes,
+                branding,
+            })
+        });
+
+        Box::new(fut.map_err(|e: FailureError| e.context("Service oauth, device_consent endpoint error occured.").into()))
+    }
+
+    /// Approves device by user code on behalf of current user, recording consent to requested scopes
+    fn approve_device(&self, approval: DeviceApproval) -> ServiceFuture<()> {
+        let current_uid = match self.dynamic_context.user_id {
+            Some(current_uid) => current_uid,
+            None => {
+                return Box::new(future::err(
+                    Error::Forbidden.context("Only authorized user can approve device").into(),
+                ));
+            }
+        };
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        let fut = self.spawn_on_pool(move |conn| {
+            let device_codes_repo = repo_factory.create_device_codes_repo(&conn);
+            let oauth_consents_repo = repo_factory.create_oauth_consents_repo(&conn, Some(current_uid));
+
+            conn.transaction::<(), FailureError, _>(|| {
+                let device_code = find_pending_device_code(&*device_codes_repo, &approval.user_code)?;
+
+                debug!("User {} approves device for client {}", current_uid, device_code.client_id);
+                let scopes = device_code_scopes(&device_code);
+                if !scopes.is_empty() {
+                    let mut granted = oauth_consents_repo
+                        .find(current_uid, device_code.client_id.clone())?
+                        .map(|consent| consent.scopes)
+                        .unwrap_or_default();
+                    for scope in scopes {
+                        if !granted.contains(&scope) {
+                            granted.push(scope);
+                        }
+                    }
+                    oauth_consents_repo.upsert(NewOAuthConsent {
+                        user_id: current_uid,
+                        client_id: device_code.client_id.clone(),
+                        scopes: granted,
+                    })?;
+                }
+                device_codes_repo
+                    .approve(device_code.device_code, current_uid)?
+                    .map(|_| ())
+                    .ok_or_else(|| Error::Validate(validation_errors!({"user_code": ["not_exists" => "Unknown or expired code"]})).into())
+            })
+        });
+
+        Box::new(fut.map_err(|e: FailureError| e.context("Service oauth, approve_device endpoint error occured.").into()))
+    }
+}
+
+/// Scopes of password change and personal access tokens
+const CLIENT_FORBIDDEN_SCOPES: &[OAuthScope] = &[OAuthScope::PasswordChange, OAuthScope::StepUp, OAuthScope::Read, OAuthScope::Write];
+
+/// Checks that client is registered and, for confidential clients, that the secret matches
+pub fn authenticate_client(clients_repo: &ClientsRepo, client_id: Option<String>, client_secret: Option<String>) -> RepoResult<Client> {
+    let client_id = client_id.ok_or_else(|| format_err!("client_id is missing").context(Error::OAuth(OAuthErrorCode::InvalidRequest)))?;
+    let client = clients_repo
+        .find(client_id.clone())?
+        .ok_or_else(|| format_err!("Client {} is not registered", client_id).context(Error::OAuth(OAuthErrorCode::InvalidClient)))?;
+
+    let authenticated = match (&client.secret_hash, client_secret) {
+        (&Some(ref secret_hash), Some(client_secret)) => password_verify(secret_hash, client_secret)?,
+        (&Some(_), None) => false,
+        (&None, _) => true,
+    };
+
+    if authenticated {
+        Ok(client)
+    } else {
+        Err(format_err!("Client {} authentication failed", client_id)
+            .context(Error::OAuth(OAuthErrorCode::InvalidClient))
+            .into())
+    }
+}
+
+/// Validates scopes requested by third-party client, at least one scope is required
+fn requested_scope(scope: Option<String>) -> RepoResult<String> {
+    let scopes = scope
+        .as_ref()
+        .map(|scope| parse_scope(scope))
+        .unwrap_or_else(|| Ok(vec![]))
+        .map_err(|code| format_err!("Unknown scope requested: {:?}", scope).context(Error::OAuth(code)))?;
+
+    if scopes.is_empty() {
+        return Err(format_err!("scope is required for third-party clients")
+            .context(Error::OAuth(OAuthErrorCode::InvalidScope))
+            .into());
+    }
+    if let Some(scope) = scopes.iter().find(|scope| CLIENT_FORBIDDEN_SCOPES.contains(scope)) {
+        return Err(format_err!("Scope {} can not be requested by clients", scope)
+            .context(Error::OAuth(OAuthErrorCode::InvalidScope))
+            .into());
+    }
+
+    Ok(format_scope(&scopes))
+}
+
+/// Scopes requested with the device code, empty for first-party clients
+fn device_code_scopes(device_code: &DeviceCode) -> Vec<String> {
+    device_code
+        .scope
+        .as_ref()
+        .map(|scope| scope.split_whitespace().map(String::from).collect())
+        .unwrap_or_default()
+}
+
+/// Finds device code awaiting approval by user code as entered by the user
+fn find_pending_device_code(device_codes_repo: &DeviceCodesRepo, user_code: &str) -> RepoResult<DeviceCode> {
+    device_codes_repo
+        .find_by_user_code(format_user_code(user_code))?
+        .filter(|device_code| !device_code.is_expired() && device_code.user_id.is_none())
+        .ok_or_else(|| Error::Validate(validation_errors!({"user_code": ["not_exists" => "Unknown or expired code"]})).into())
+}
+
+/// Issues token for the service user the client acts as. Only confidential clients can use this grant.
+fn client_credentials_token(client: &Client, jwt_private_key: &[u8]) -> RepoResult<OAuthToken> {
+    let service_user_id = match (client.is_confidential(), client.service_user_id) {
+        (true, Some(service_user_id)) => service_user_id,
+        _ => {
+            return Err(format_err!("Client {} is not allowed to use client_credentials grant", client.id)
+                .context(Error::OAuth(OAuthErrorCode::UnauthorizedClient))
+                .into());
+        }
+    };
+
+    client_token(service_user_id, client, None, jwt_private_key)
+}
+
+/// Exchanges device code approved by user for a token. Device code can be used only once,
+/// pending device code can be polled once per `polling_interval`.
+fn device_code_token(
+    device_codes_repo: &DeviceCodesRepo,
+    users_repo: &UsersRepo,
+    client: &Client,
+    device_code: String,
+    polling_interval: Duration,
+    jwt_private_key: &[u8],
+) -> RepoResult<OAuthToken> {
+    let oauth_error = |code, message: &str| -> FailureError { format_err!("{}", message).context(Error::OAuth(code)).into() };
+
+    let code = device_codes_repo
+        .find(device_code.clone())?
+        .filter(|code| code.client_id == client.id)
+        .ok_or_else(|| oauth_error(OAuthErrorCode::InvalidGrant, "Device code not found"))?;
+
+    if code.is_expired() {
+        device_codes_repo.delete(device_code)?;
+        return Err(oauth_error(OAuthErrorCode::ExpiredToken, "Device code has expired"));
+    }
+
+    if code.user_id.is_none() {
+        return if device_codes_repo.poll(device_code, polling_interval)? {
+            Err(oauth_error(OAuthErrorCode::AuthorizationPending, "Device is not approved yet"))
+        } else {
+            Err(oauth_error(OAuthErrorCode::SlowDown, "Device polls faster than the interval"))
+        };
+    }
+
+    let code = device_codes_repo
+        .redeem(device_code)?
+        .ok_or_else(|| oauth_error(OAuthErrorCode::InvalidGrant, "Device code is already used"))?;
+    let user_id = code
+        .user_id
+        .ok_or_else(|| oauth_error(OAuthErrorCode::InvalidGrant, "Device code is not approved"))?;
+
+    match users_repo.find(user_id)? {
+        Some(ref user) if !user.is_blocked => client_token(user_id, client, code.scope, jwt_private_key),
+        _ => Err(oauth_error(OAuthErrorCode::AccessDenied, "User is blocked or deleted")),
+    }
+}
+
+/// Issues token for user bound to the client, limited to the scope for third-party clients
+fn client_token(user_id: UserId, client: &Client, scope: Option<String>, jwt_private_key: &[u8]) -> RepoResult<OAuthToken> {
+    let now = Utc::now().timestamp();
+    let tokenpayload = JWTPayload::new(user_id, now, Provider::Email)
+        .with_auth_time(now)
+        .with_client(client)
+        .with_scope(scope);
+    encode(&Header::new(Algorithm::RS256), &tokenpayload, jwt_private_key)
+        .map_err(|e| {
+            format_err!("{}", e)
+                .context(Error::Parse)
+                .context(format!("Couldn't encode jwt: {:?}.", tokenpayload))
+                .into()
+        })
+        .map(|token| OAuthToken::bearer(token, tokenpayload.exp, now))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use base64::{decode_config, URL_SAFE_NO_PAD};
+    use failure::Context;
+    use serde_json;
+    use tokio_core::reactor::Core;
+
+    use stq_types::UserId;
+
+    use errors::Error;
+    use models::*;
+    use repos::repo_factory::tests::*;
+    use services::oauth::OAuthService;
+
+    fn token_request(grant_type: &str, client_id: &str, client_secret: Option<&str>) -> OAuthTokenRequest {
+        OAuthTokenRequest {
+            grant_type: grant_type.to_string(),
+            client_id: Some(client_id.to_string()),
+            client_secret: client_secret.map(|s| s.to_string()),
+            username: None,
+            password: None,
+            scope: None,
+            device_code: None,
+        }
+    }
+
+    #[test]
+    fn test_oauth_client_credentials() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let request = token_request("client_credentials", MOCK_CONFIDENTIAL_CLIENT_ID, Some(MOCK_CLIENT_SECRET));
+        let work = service.oauth_token(request);
+        let result = core.run(work).unwrap();
+        assert_eq!(result.token_type, "bearer");
+        assert!(result.expires_in > 0);
+    }
+
+    #[test]
+    fn test_oauth_client_credentials_wrong_secret() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let request = token_request("client_credentials", MOCK_CONFIDENTIAL_CLIENT_ID, Some("wrong secret"));
+        let work = service.oauth_token(request);
+        let result = core.run(work);
+        assert_eq!(result.is_err(), true);
+    }
+
+    #[test]
+    fn test_oauth_client_credentials_public_client() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let request = token_request("client_credentials", MOCK_CLIENT_ID, None);
+        let work = service.oauth_token(request);
+        let result = core.run(work);
+        assert_eq!(result.is_err(), true);
+    }
+
+    #[test]
+    fn test_oauth_password() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let request = OAuthTokenRequest {
+            username: Some(MOCK_EMAIL.to_string()),
+            password: Some(MOCK_PASSWORD.to_string()),
+            ..token_request("password", MOCK_CLIENT_ID, None)
+        };
+        let work = service.oauth_token(request);
+        let result = core.run(work).unwrap();
+        assert_eq!(result.token_type, "bearer");
+    }
+
+    #[test]
+    fn test_oauth_unsupported_grant_type() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let request = token_request("implicit", MOCK_CLIENT_ID, None);
+        let work = service.oauth_token(request);
+        let result = core.run(work);
+        assert_eq!(result.is_err(), true);
+    }
+
+    #[test]
+    fn test_oauth_device_flow() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let work = service.device_authorization(DeviceAuthorizationRequest {
+            client_id: MOCK_CLIENT_ID.to_string(),
+            client_secret: None,
+            scope: None,
+        });
+        let result = core.run(work).unwrap();
+        assert_eq!(result.user_code.len(), 9);
+        assert!(result.expires_in > 0);
+    }
+
+    #[test]
+    fn test_oauth_approve_device() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.approve_device(DeviceApproval {
+            user_code: MOCK_USER_CODE.to_lowercase().replace("-", ""),
+        });
+        let result = core.run(work);
+        assert_eq!(result.is_ok(), true);
+    }
+
+    #[test]
+    fn test_oauth_device_code_approved() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let request = OAuthTokenRequest {
+            device_code: Some(MOCK_APPROVED_DEVICE_CODE.to_string()),
+            ..token_request("urn:ietf:params:oauth:grant-type:device_code", MOCK_CLIENT_ID, None)
+        };
+        let work = service.oauth_token(request);
+        let result = core.run(work).unwrap();
+        assert_eq!(result.token_type, "bearer");
+    }
+
+    #[test]
+    fn test_oauth_device_code_pending() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let request = OAuthTokenRequest {
+            device_code: Some(MOCK_PENDING_DEVICE_CODE.to_string()),
+            ..token_request("urn:ietf:params:oauth:grant-type:device_code", MOCK_CLIENT_ID, None)
+        };
+        let work = service.oauth_token(request);
+        let result = core.run(work);
+        assert_eq!(result.is_err(), true);
+    }
+
+    #[test]
+    fn test_oauth_device_code_slow_down() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let request = OAuthTokenRequest {
+            device_code: Some(MOCK_SLOW_DEVICE_CODE.to_string()),
+            ..token_request("urn:ietf:params:oauth:grant-type:device_code", MOCK_CLIENT_ID, None)
+        };
+        let error = core.run(service.oauth_token(request)).unwrap_err();
+        let slow_down = error.iter_chain().any(|cause| match cause.downcast_ref::<Context<Error>>() {
+            Some(context) => match *context.get_context() {
+                Error::OAuth(OAuthErrorCode::SlowDown) => true,
+                _ => false,
+            },
+            None => false,
+        });
+        assert_eq!(slow_down, true);
+    }
+
+    #[test]
+    fn test_oauth_password_third_party_client() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let request = OAuthTokenRequest {
+            username: Some(MOCK_EMAIL.to_string()),
+            password: Some(MOCK_PASSWORD.to_string()),
+            ..token_request("password", MOCK_THIRD_PARTY_CLIENT_ID, None)
+        };
+        let work = service.oauth_token(request);
+        let result = core.run(work);
+        assert_eq!(result.is_err(), true);
+    }
+
+    #[test]
+    fn test_oauth_device_flow_third_party_scope() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let request = |scope: Option<&str>| DeviceAuthorizationRequest {
+            client_id: MOCK_THIRD_PARTY_CLIENT_ID.to_string(),
+            client_secret: None,
+            scope: scope.map(|scope| scope.to_string()),
+        };
+        assert_eq!(core.run(service.device_authorization(request(None))).is_err(), true);
+        assert_eq!(
+            core.run(service.device_authorization(request(Some("profile:write")))).is_err(),
+            true
+        );
+        assert_eq!(core.run(service.device_authorization(request(Some("profile:read")))).is_ok(), true);
+    }
+
+    #[test]
+    fn test_oauth_device_consent() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.device_consent(MOCK_THIRD_PARTY_USER_CODE.to_string());
+        let result = core.run(work).unwrap();
+        assert_eq!(result.client_id, MOCK_THIRD_PARTY_CLIENT_ID);
+        assert_eq!(result.scopes, vec!["profile:read".to_string(), "email:read".to_string()]);
+        assert_eq!(result.granted_scopes, vec!["profile:read".to_string()]);
+    }
+
+    #[test]
+    fn test_oauth_approve_third_party_device() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.approve_device(DeviceApproval {
+            user_code: MOCK_THIRD_PARTY_USER_CODE.to_string(),
+        });
+        let result = core.run(work);
+        assert_eq!(result.is_ok(), true);
+    }
+
+    #[test]
+    fn test_oauth_third_party_device_code() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let request = OAuthTokenRequest {
+            device_code: Some(MOCK_THIRD_PARTY_DEVICE_CODE.to_string()),
+            ..token_request("urn:ietf:params:oauth:grant-type:device_code", MOCK_THIRD_PARTY_CLIENT_ID, None)
+        };
+        let work = service.oauth_token(request);
+        let result = core.run(work).unwrap();
+        let payload = result.access_token.split('.').nth(1).unwrap();
+        let payload = decode_config(payload, URL_SAFE_NO_PAD).unwrap();
+        let payload = serde_json::from_slice::<JWTPayload>(&payload).unwrap();
+        assert_eq!(payload.scope, Some("profile:read email:read".to_string()));
+    }
+}
@@ -0,0 +1,577 @@
+//! Password strength Services. Strength is estimated the zxcvbn way: the password is split into
+//! guessable patterns, i.e. common passwords, user inputs, repeats, sequences, keyboard rows and
+//! years, the rest is guessed by brute force, and the number of guesses is taken for the cheapest
+//! split. The same estimate backs `POST /password/strength` and `password_strength.min_score`
+//! policy, so that passwords shown as strong enough are never rejected on change.
+
+use chrono::{Datelike, Utc};
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use futures::future;
+use r2d2::ManageConnection;
+
+use config;
+use errors::Error;
+use i18n::Locale;
+use models::{PasswordHint, PasswordStrength, PasswordStrengthRequest};
+use repos::ReposFactory;
+use services::types::ServiceFuture;
+use services::Service;
+
+/// Common passwords by frequency, guesses of a common password is its rank
+const COMMON_PASSWORDS: &[&str] = &[
+    "123456",
+    "password",
+    "12345678",
+    "qwerty",
+    "123456789",
+    "12345",
+    "1234",
+    "111111",
+    "1234567",
+    "dragon",
+    "123123",
+    "baseball",
+    "abc123",
+    "football",
+    "monkey",
+    "letmein",
+    "696969",
+    "shadow",
+    "master",
+    "666666",
+    "qwertyuiop",
+    "123321",
+    "mustang",
+    "1234567890",
+    "michael",
+    "654321",
+    "superman",
+    "1qaz2wsx",
+    "7777777",
+    "121212",
+    "000000",
+    "qazwsx",
+    "123qwe",
+    "killer",
+    "trustno1",
+    "jordan",
+    "jennifer",
+    "zxcvbnm",
+    "asdfgh",
+    "hunter",
+    "buster",
+    "soccer",
+    "harley",
+    "batman",
+    "andrew",
+    "tigger",
+    "sunshine",
+    "iloveyou",
+    "charlie",
+    "robert",
+    "thomas",
+    "hockey",
+    "ranger",
+    "daniel",
+    "starwars",
+    "112233",
+    "george",
+    "computer",
+    "michelle",
+    "jessica",
+    "pepper",
+    "1111",
+    "zxcvbn",
+    "555555",
+    "11111111",
+    "131313",
+    "freedom",
+    "777777",
+    "pass",
+    "maggie",
+    "159753",
+    "aaaaaa",
+    "ginger",
+    "princess",
+    "joshua",
+    "cheese",
+    "amanda",
+    "summer",
+    "love",
+    "ashley",
+    "nicole",
+    "chelsea",
+    "matthew",
+    "access",
+    "yankees",
+    "987654321",
+    "dallas",
+    "austin",
+    "thunder",
+    "taylor",
+    "matrix",
+    "welcome",
+    "admin",
+    "login",
+    "secret",
+    "storiqa",
+];
+const KEYBOARD_ROWS: &[&str] = &["qwertyuiop", "asdfghjkl", "zxcvbnm"];
+/// Guesses of a character not covered by any pattern
+const BRUTEFORCE_CARDINALITY: f64 = 10.0;
+const MIN_YEAR_SPACE: i32 = 20;
+/// Longer passwords are estimated by their prefix, it is strong enough anyway
+const MAX_ESTIMATED_LEN: usize = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Pattern {
+    Common,
+    UserInput,
+    Repeat,
+    Sequence,
+    Keyboard,
+    Year,
+}
+
+/// Guessable part of the password from `i` to `j` inclusive
+#[derive(Clone, Debug)]
+struct Match {
+    pattern: Pattern,
+    i: usize,
+    j: usize,
+    guesses_log10: f64,
+    capitalized: bool,
+    substituted: bool,
+}
+
+impl Match {
+    fn new(pattern: Pattern, i: usize, j: usize, guesses: f64) -> Self {
+        Self {
+            pattern,
+            i,
+            j,
+            guesses_log10: guesses.log10(),
+            capitalized: false,
+            substituted: false,
+        }
+    }
+}
+
+/// Strength of the password with hints as codes, see `localize`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Estimate {
+    pub score: u8,
+    pub guesses_log10: f64,
+    pub warning: Option<&'static str>,
+    pub suggestions: Vec<&'static str>,
+}
+
+impl Estimate {
+    /// Strength with hints in the locale
+    pub fn localize(self, locale: Locale) -> PasswordStrength {
+        PasswordStrength {
+            score: self.score,
+            guesses_log10: self.guesses_log10,
+            warning: self.warning.map(|code| hint(code, locale)),
+            suggestions: self.suggestions.into_iter().map(|code| hint(code, locale)).collect(),
+        }
+    }
+}
+
+fn hint(code: &str, locale: Locale) -> PasswordHint {
+    let message_key = format!("password_strength.{}", code);
+    let message = locale
+        .message(&message_key)
+        .or_else(|| Locale::default().message(&message_key))
+        .unwrap_or(code)
+        .to_string();
+    PasswordHint {
+        code: code.to_string(),
+        message_key,
+        message,
+    }
+}
+
+pub trait PasswordStrengthService {
+    /// Estimates strength of the password, hints are in the locale
+    fn check_password_strength(&self, payload: PasswordStrengthRequest, locale: Locale) -> ServiceFuture<PasswordStrength>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > PasswordStrengthService for Service<T, M, F>
+{
+    /// Estimates strength of the password, hints are in the locale
+    fn check_password_strength(&self, payload: PasswordStrengthRequest, locale: Locale) -> ServiceFuture<PasswordStrength> {
+        let estimate = estimate_strength(&payload.password, &payload.user_inputs);
+        Box::new(future::ok(estimate.localize(locale)))
+    }
+}
+
+/// Rejects new passwords scored below `min_score`
+pub fn check_password_policy(conf: &config::PasswordStrength, password: &str, user_inputs: &[String]) -> Result<(), FailureError> {
+    if conf.min_score == 0 {
+        return Ok(());
+    }
+    if estimate_strength(password, user_inputs).score < conf.min_score {
+        return Err(Error::Validate(validation_errors!({"password": ["weak" => "Password is too weak"]})).into());
+    }
+    Ok(())
+}
+
+/// Estimates number of guesses needed to crack the password, `user_inputs` are e.g. email and
+/// names of the user
+pub fn estimate_strength(password: &str, user_inputs: &[String]) -> Estimate {
+    let chars = password.chars().take(MAX_ESTIMATED_LEN).collect::<Vec<_>>();
+    let lower = chars.iter().map(|&c| lower_char(c)).collect::<Vec<_>>();
+
+    let common = COMMON_PASSWORDS.iter().map(|word| word.chars().collect()).collect::<Vec<_>>();
+    let inputs = user_input_words(user_inputs);
+
+    let mut matches = Vec::new();
+    matches.extend(dictionary_matches(&chars, &lower, &common, Pattern::Common));
+    matches.extend(dictionary_matches(&chars, &lower, &inputs, Pattern::UserInput));
+    matches.extend(repeat_matches(&lower));
+    matches.extend(sequence_matches(&lower));
+    matches.extend(keyboard_matches(&lower));
+    matches.extend(year_matches(&lower));
+
+    // cheapest guesses of every prefix, along with the last match of its split
+    let n = chars.len();
+    let mut best = vec![0.0; n + 1];
+    let mut last = vec![None; n + 1];
+    for k in 1..=n {
+        best[k] = best[k - 1] + BRUTEFORCE_CARDINALITY.log10();
+        for (index, m) in matches.iter().enumerate() {
+            if m.j + 1 == k && best[m.i] + m.guesses_log10 < best[k] {
+                best[k] = best[m.i] + m.guesses_log10;
+                last[k] = Some(index);
+            }
+        }
+    }
+
+    let mut split = Vec::new();
+    let mut k = n;
+    while k > 0 {
+        match last[k] {
+            Some(index) => {
+                split.push(&matches[index]);
+                k = matches[index].i;
+            }
+            None => k -= 1,
+        }
+    }
+
+    let guesses_log10 = best[n];
+    let score = score(guesses_log10);
+    let (warning, suggestions) = feedback(n, score, &split);
+    Estimate {
+        score,
+        guesses_log10,
+        warning,
+        suggestions,
+    }
+}
+
+fn score(guesses_log10: f64) -> u8 {
+    if guesses_log10 < 3.0 {
+        0
+    } else if guesses_log10 < 6.0 {
+        1
+    } else if guesses_log10 < 8.0 {
+        2
+    } else if guesses_log10 < 10.0 {
+        3
+    } else {
+        4
+    }
+}
+
+/// Warning about the longest pattern of the split and suggestions, strong passwords get none
+fn feedback(len: usize, score: u8, split: &[&Match]) -> (Option<&'static str>, Vec<&'static str>) {
+    if len == 0 {
+        return (None, vec!["use_words", "no_need_for_symbols"]);
+    }
+    if score > 2 {
+        return (None, vec![]);
+    }
+
+    let mut suggestions = vec!["add_words"];
+    let longest = split.iter().max_by_key(|m| m.j - m.i);
+    let longest = match longest {
+        Some(longest) => longest,
+        None => return (None, suggestions),
+    };
+    let (warning, suggestion) = match longest.pattern {
+        Pattern::Common => ("common", None),
+        Pattern::UserInput => ("user_input", Some("avoid_personal")),
+        Pattern::Repeat => ("repeat", Some("avoid_repeats")),
+        Pattern::Sequence => ("sequence", Some("avoid_sequences")),
+        Pattern::Keyboard => ("keyboard", Some("avoid_keyboard")),
+        Pattern::Year => ("year", Some("avoid_years")),
+    };
+    suggestions.extend(suggestion);
+    if longest.capitalized {
+        suggestions.push("capitalization");
+    }
+    if longest.substituted {
+        suggestions.push("substitutions");
+    }
+    (Some(warning), suggestions)
+}
+
+/// Keeps positions of characters, unlike `str::to_lowercase`
+fn lower_char(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+/// Reverts common substitutions of letters, e.g. `p@ssw0rd`
+fn unsubstitute_char(c: char) -> char {
+    match c {
+        '4' | '@' => 'a',
+        '3' => 'e',
+        '1' | '!' => 'i',
+        '0' => 'o',
+        '5' | '$' => 's',
+        '7' => 't',
+        _ => c,
+    }
+}
+
+fn is_digit(c: char) -> bool {
+    c.is_ascii_digit()
+}
+
+/// Words of user inputs by their order, e.g. parts of email and names
+fn user_input_words(user_inputs: &[String]) -> Vec<Vec<char>> {
+    let mut words = Vec::new();
+    for input in user_inputs {
+        let input = input.to_lowercase();
+        words.push(input.chars().collect::<Vec<_>>());
+        words.extend(
+            input
+                .split(|c: char| !c.is_alphanumeric())
+                .filter(|word| word.chars().count() >= 3)
+                .map(|word| word.chars().collect()),
+        );
+    }
+    words
+}
+
+fn dictionary_matches(chars: &[char], lower: &[char], words: &[Vec<char>], pattern: Pattern) -> Vec<Match> {
+    let unsubstituted = lower.iter().map(|&c| unsubstitute_char(c)).collect::<Vec<_>>();
+    let mut matches = Vec::new();
+    for (rank, word) in words.iter().enumerate() {
+        if word.len() < 3 || word.len() > lower.len() {
+            continue;
+        }
+        for i in 0..=lower.len() - word.len() {
+            let j = i + word.len() - 1;
+            let substituted = if lower[i..=j] == word[..] {
+                false
+            } else if unsubstituted[i..=j] == word[..] {
+                true
+            } else {
+                continue;
+            };
+            let mut m = Match::new(pattern, i, j, (rank + 1) as f64);
+            let uppers = chars[i..=j].iter().filter(|c| c.is_uppercase()).count();
+            if uppers > 0 {
+                // capitalized first letter or all caps are tried first
+                let obvious = uppers == word.len() || (uppers == 1 && chars[i].is_uppercase());
+                m.guesses_log10 += if obvious { 2f64.log10() } else { uppers as f64 * 2f64.log10() };
+                m.capitalized = true;
+            }
+            if substituted {
+                let substitutions = (i..=j).filter(|&k| lower[k] != unsubstituted[k]).count();
+                m.guesses_log10 += substitutions as f64 * 2f64.log10();
+                m.substituted = true;
+            }
+            matches.push(m);
+        }
+    }
+    matches
+}
+
+/// Runs of the same character, e.g. `aaaa`
+fn repeat_matches(lower: &[char]) -> Vec<Match> {
+    let mut matches = Vec::new();
+    let mut i = 0;
+    while i < lower.len() {
+        let mut j = i;
+        while j + 1 < lower.len() && lower[j + 1] == lower[i] {
+            j += 1;
+        }
+        if j - i >= 2 {
+            let cardinality = if is_digit(lower[i]) { 10.0 } else { 26.0 };
+            matches.push(Match::new(Pattern::Repeat, i, j, cardinality * (j - i + 1) as f64));
+        }
+        i = j + 1;
+    }
+    matches
+}
+
+/// Runs of consecutive letters or digits, e.g. `abcd` or `9876`
+fn sequence_matches(lower: &[char]) -> Vec<Match> {
+    let same_class = |a: char, b: char| (is_digit(a) && is_digit(b)) || (a.is_ascii_lowercase() && b.is_ascii_lowercase());
+    let mut matches = Vec::new();
+    let mut i = 0;
+    while i + 1 < lower.len() {
+        let delta = lower[i + 1] as i64 - lower[i] as i64;
+        let mut j = i;
+        if delta.abs() == 1 {
+            while j + 1 < lower.len() && lower[j + 1] as i64 - lower[j] as i64 == delta && same_class(lower[j], lower[j + 1]) {
+                j += 1;
+            }
+        }
+        if j - i >= 2 {
+            let base = match lower[i] {
+                'a' | 'z' | '0' | '1' | '9' => 4.0,
+                c if is_digit(c) => 10.0,
+                _ => 26.0,
+            };
+            let direction = if delta < 0 { 2.0 } else { 1.0 };
+            matches.push(Match::new(Pattern::Sequence, i, j, base * direction * (j - i + 1) as f64));
+            i = j;
+        } else {
+            i += 1;
+        }
+    }
+    matches
+}
+
+fn keyboard_position(c: char) -> Option<(usize, usize)> {
+    KEYBOARD_ROWS
+        .iter()
+        .enumerate()
+        .filter_map(|(row, keys)| keys.chars().position(|key| key == c).map(|column| (row, column)))
+        .next()
+}
+
+/// Runs of adjacent keys of a keyboard row, e.g. `asdf` or `poiu`
+fn keyboard_matches(lower: &[char]) -> Vec<Match> {
+    let mut matches = Vec::new();
+    let positions = lower.iter().map(|&c| keyboard_position(c)).collect::<Vec<_>>();
+    let step = |a: Option<(usize, usize)>, b: Option<(usize, usize)>| match (a, b) {
+        (Some((row_a, column_a)), Some((row_b, column_b))) if row_a == row_b => Some(column_b as i64 - column_a as i64),
+        _ => None,
+    };
+    let mut i = 0;
+    while i + 1 < lower.len() {
+        let direction = step(positions[i], positions[i + 1]);
+        let mut j = i;
+        if direction == Some(1) || direction == Some(-1) {
+            while j + 1 < lower.len() && step(positions[j], positions[j + 1]) == direction {
+                j += 1;
+            }
+        }
+        if j - i >= 3 {
+            let keys = KEYBOARD_ROWS.iter().map(|keys| keys.len()).sum::<usize>() as f64;
+            matches.push(Match::new(Pattern::Keyboard, i, j, keys * 2.0 * (j - i + 1) as f64));
+            i = j;
+        } else {
+            i += 1;
+        }
+    }
+    matches
+}
+
+/// Recent years, e.g. `1987`
+fn year_matches(lower: &[char]) -> Vec<Match> {
+    let current_year = Utc::now().year();
+    let mut matches = Vec::new();
+    if lower.len() < 4 {
+        return matches;
+    }
+    for i in 0..=lower.len() - 4 {
+        if !lower[i..i + 4].iter().all(|&c| is_digit(c)) {
+            continue;
+        }
+        let year = lower[i..i + 4].iter().collect::<String>().parse::<i32>().unwrap_or(0);
+        if year >= 1900 && year <= current_year + 20 {
+            let space = (year - current_year).abs().max(MIN_YEAR_SPACE);
+            matches.push(Match::new(Pattern::Year, i, i + 3, f64::from(space)));
+        }
+    }
+    matches
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use tokio_core::reactor::Core;
+
+    use config;
+    use i18n::Locale;
+    use models::PasswordStrengthRequest;
+    use repos::repo_factory::tests::*;
+    use services::password_strength::*;
+
+    #[test]
+    fn test_common_passwords_are_weak() {
+        let estimate = estimate_strength("password", &[]);
+        assert_eq!(estimate.score, 0);
+        assert_eq!(estimate.warning, Some("common"));
+
+        let estimate = estimate_strength("P@ssw0rd", &[]);
+        assert_eq!(estimate.score, 0);
+        assert_eq!(estimate.suggestions.contains(&"substitutions"), true);
+        assert_eq!(estimate.suggestions.contains(&"capitalization"), true);
+    }
+
+    #[test]
+    fn test_patterns_are_weak() {
+        assert_eq!(estimate_strength("abcdefgh", &[]).warning, Some("sequence"));
+        assert_eq!(estimate_strength("aaaaaaaaaa", &[]).warning, Some("repeat"));
+        assert_eq!(estimate_strength("asdfghjkl", &[]).warning, Some("keyboard"));
+        assert_eq!(estimate_strength("1987", &[]).warning, Some("year"));
+    }
+
+    #[test]
+    fn test_user_inputs_are_weak() {
+        let inputs = vec!["john.smith@example.com".to_string()];
+        let estimate = estimate_strength("smith1987", &inputs);
+        assert_eq!(estimate.warning, Some("user_input"));
+        assert!(estimate.score < estimate_strength("smith1987", &[]).score);
+    }
+
+    #[test]
+    fn test_passphrases_are_strong() {
+        let estimate = estimate_strength("correct horse battery staple", &[]);
+        assert_eq!(estimate.score, 4);
+        assert_eq!(estimate.warning, None);
+        assert_eq!(estimate.suggestions.is_empty(), true);
+    }
+
+    #[test]
+    fn test_check_password_policy() {
+        let conf = config::PasswordStrength { min_score: 3 };
+        assert_eq!(check_password_policy(&conf, "qwerty123", &[]).is_err(), true);
+        assert_eq!(check_password_policy(&conf, "violet tundra anchors", &[]).is_ok(), true);
+
+        let conf = config::PasswordStrength { min_score: 0 };
+        assert_eq!(check_password_policy(&conf, "qwerty123", &[]).is_ok(), true);
+    }
+
+    #[test]
+    fn test_check_password_strength() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let payload = PasswordStrengthRequest {
+            password: "qwerty".to_string(),
+            user_inputs: vec![],
+        };
+        let result = core.run(service.check_password_strength(payload, Locale::Ru)).unwrap();
+        assert_eq!(result.score, 0);
+        let warning = result.warning.unwrap();
+        assert_eq!(warning.message_key, "password_strength.common");
+        assert_ne!(warning.message, "common");
+    }
+}
@@ -0,0 +1,183 @@
+//! Computed profile fields: completion percentage by configurable field weights and birthday flag
+
+use std::collections::HashMap;
+
+use chrono::{Datelike, NaiveDate};
+
+use stq_types::UserId;
+
+use models::{CurrentUser, ProfileCompletionStats, ProfilePrompt, User};
+
+/// Number of histogram buckets in completion stats, 10% each
+const HISTOGRAM_BUCKETS: usize = 10;
+
+/// Returns `None` for fields that can not be taken into account
+fn is_filled(user: &User, field: &str) -> Option<bool> {
+    match field {
+        "email_verified" => Some(user.email_verified),
+        "phone" => Some(user.phone.is_some()),
+        "phone_verified" => Some(user.phone_verified),
+        "first_name" => Some(user.first_name.is_some()),
+        "last_name" => Some(user.last_name.is_some()),
+        "middle_name" => Some(user.middle_name.is_some()),
+        "gender" => Some(user.gender.is_some()),
+        "birthdate" => Some(user.birthdate.is_some()),
+        "avatar" => Some(user.avatar.is_some()),
+        "country" => Some(user.country.is_some()),
+        _ => None,
+    }
+}
+
+/// Weighted percentage of filled profile fields, unknown fields in weights are skipped
+pub fn profile_completion(user: &User, weights: &HashMap<String, u32>) -> u8 {
+    let (filled, total) = weights
+        .iter()
+        .filter_map(|(field, weight)| is_filled(user, field).map(|filled| (filled, u64::from(*weight))))
+        .fold((0, 0), |(filled_sum, total_sum), (filled, weight)| {
+            (if filled { filled_sum + weight } else { filled_sum }, total_sum + weight)
+        });
+
+    if total == 0 {
+        100
+    } else {
+        (filled * 100 / total) as u8
+    }
+}
+
+/// Checks if `today` is a birthday, people born on Feb 29 celebrate on Feb 28 in non-leap years
+pub fn is_birthday(birthdate: NaiveDate, today: NaiveDate) -> bool {
+    if birthdate.month() == 2 && birthdate.day() == 29 && NaiveDate::from_ymd_opt(today.year(), 2, 29).is_none() {
+        return today.month() == 2 && today.day() == 28;
+    }
+
+    birthdate.month() == today.month() && birthdate.day() == today.day()
+}
+
+pub fn current_user(user: User, weights: &HashMap<String, u32>, today: NaiveDate) -> CurrentUser {
+    CurrentUser {
+        profile_completion: profile_completion(&user, weights),
+        is_birthday: user.birthdate.map(|birthdate| is_birthday(birthdate, today)).unwrap_or(false),
+        user,
+    }
+}
+
+/// Aggregates completion over a page of users, `next_offset` is set if the page is full
+pub fn completion_stats(users: &[User], weights: &HashMap<String, u32>, page_size: i64) -> ProfileCompletionStats {
+    let mut histogram = vec![0; HISTOGRAM_BUCKETS];
+    let mut sum = 0u64;
+
+    for user in users {
+        let completion = profile_completion(user, weights);
+        sum += u64::from(completion);
+        histogram[(completion as usize * HISTOGRAM_BUCKETS / 100).min(HISTOGRAM_BUCKETS - 1)] += 1;
+    }
+
+    let users_count = users.len() as u64;
+    ProfileCompletionStats {
+        users_count,
+        average: if users_count == 0 { 0.0 } else { sum as f64 / users_count as f64 },
+        histogram,
+        next_offset: if users_count as i64 >= page_size {
+            users.last().map(|user| UserId(user.id.0 + 1))
+        } else {
+            None
+        },
+    }
+}
+
+/// Next missing fields to prompt in `fields` order, unknown, filled and `snoozed` fields are skipped
+pub fn profile_prompts(
+    user: &User,
+    fields: &[String],
+    weights: &HashMap<String, u32>,
+    snoozed: &[String],
+    limit: usize,
+) -> Vec<ProfilePrompt> {
+    fields
+        .iter()
+        .filter(|field| is_filled(user, field) == Some(false))
+        .filter(|field| !snoozed.contains(field))
+        .take(limit)
+        .map(|field| ProfilePrompt {
+            field: field.clone(),
+            weight: weights.get(field).cloned().unwrap_or(0),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use repos::repo_factory::tests::*;
+
+    fn create_weights() -> HashMap<String, u32> {
+        let mut weights = HashMap::new();
+        weights.insert("email_verified".to_string(), 50);
+        weights.insert("first_name".to_string(), 25);
+        weights.insert("last_name".to_string(), 25);
+        weights.insert("unknown".to_string(), 100);
+        weights
+    }
+
+    #[test]
+    fn test_profile_completion() {
+        let mut user = create_user(UserId(1), MOCK_EMAIL.to_string());
+        assert_eq!(profile_completion(&user, &create_weights()), 50);
+        user.first_name = Some("Alice".to_string());
+        assert_eq!(profile_completion(&user, &create_weights()), 75);
+        assert_eq!(profile_completion(&user, &HashMap::new()), 100);
+    }
+
+    #[test]
+    fn test_is_birthday() {
+        let birthdate = NaiveDate::from_ymd(1990, 5, 17);
+        assert_eq!(is_birthday(birthdate, NaiveDate::from_ymd(2019, 5, 17)), true);
+        assert_eq!(is_birthday(birthdate, NaiveDate::from_ymd(2019, 5, 18)), false);
+        let leap_birthdate = NaiveDate::from_ymd(1992, 2, 29);
+        assert_eq!(is_birthday(leap_birthdate, NaiveDate::from_ymd(2019, 2, 28)), true);
+        assert_eq!(is_birthday(leap_birthdate, NaiveDate::from_ymd(2020, 2, 28)), false);
+        assert_eq!(is_birthday(leap_birthdate, NaiveDate::from_ymd(2020, 2, 29)), true);
+    }
+
+    #[test]
+    fn test_completion_stats() {
+        let users = vec![
+            create_user(UserId(2), MOCK_EMAIL.to_string()),
+            create_user(UserId(3), MOCK_EMAIL.to_string()),
+        ];
+        let stats = completion_stats(&users, &create_weights(), 2);
+        assert_eq!(stats.users_count, 2);
+        assert_eq!(stats.average as u64, 50);
+        assert_eq!(stats.histogram[5], 2);
+        assert_eq!(stats.next_offset, Some(UserId(4)));
+    }
+
+    #[test]
+    fn test_profile_prompts() {
+        let mut user = create_user(UserId(1), MOCK_EMAIL.to_string());
+        user.first_name = Some("Alice".to_string());
+        let fields = vec![
+            "email_verified".to_string(),
+            "unknown".to_string(),
+            "first_name".to_string(),
+            "last_name".to_string(),
+            "phone".to_string(),
+            "avatar".to_string(),
+        ];
+        let prompts = profile_prompts(&user, &fields, &create_weights(), &["phone".to_string()], 2);
+        assert_eq!(
+            prompts,
+            vec![
+                ProfilePrompt {
+                    field: "last_name".to_string(),
+                    weight: 25,
+                },
+                ProfilePrompt {
+                    field: "avatar".to_string(),
+                    weight: 0,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,159 @@
+//! Progressive profiling Services. Current user is prompted to fill missing profile fields
+//! in configured order, a dismissed prompt is snoozed for a configured time.
+//! Dismissals are saved on the user shard.
+
+use std::time::{Duration, SystemTime};
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::future;
+use r2d2::ManageConnection;
+
+use super::profile_completion::profile_prompts;
+use errors::Error;
+use models::*;
+use repos::repo_factory::ReposFactory;
+use services::types::ServiceFuture;
+use services::Service;
+
+pub trait ProfilePromptsService {
+    /// Returns next missing profile fields current user is prompted to fill
+    fn get_profile_prompts(&self) -> ServiceFuture<Vec<ProfilePrompt>>;
+    /// Snoozes prompt of the field for current user
+    fn dismiss_profile_prompt(&self, payload: DismissProfilePrompt) -> ServiceFuture<ProfilePromptDismissal>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > ProfilePromptsService for Service<T, M, F>
+{
+    /// Returns next missing profile fields current user is prompted to fill
+    fn get_profile_prompts(&self) -> ServiceFuture<Vec<ProfilePrompt>> {
+        let current_uid = match self.dynamic_context.user_id {
+            Some(user_id) => user_id,
+            None => {
+                return Box::new(future::err(
+                    Error::Forbidden.context("Only authorized user can get profile prompts").into(),
+                ))
+            }
+        };
+        let repo_factory = self.static_context.repo_factory.clone();
+        let conf = self.static_context.config.profile_prompts.clone();
+        let weights = self.static_context.config.profile_completion.weights.clone();
+
+        self.spawn_on_shard(current_uid, move |conn| {
+            {
+                let users_repo = repo_factory.create_users_repo(&*conn, Some(current_uid));
+                let profile_prompts_repo = repo_factory.create_profile_prompts_repo(&*conn, Some(current_uid));
+                let user = users_repo
+                    .find(current_uid)?
+                    .ok_or_else(|| Error::NotFound.context(format!("User {} not found", current_uid)))?;
+                let snoozed = profile_prompts_repo
+                    .list_snoozed(current_uid, SystemTime::now())?
+                    .into_iter()
+                    .map(|dismissal| dismissal.field)
+                    .collect::<Vec<_>>();
+                Ok(profile_prompts(&user, &conf.fields, &weights, &snoozed, conf.limit))
+            }
+            .map_err(|e: FailureError| {
+                e.context("Service profile_prompts, get_profile_prompts endpoint error occured.")
+                    .into()
+            })
+        })
+    }
+
+    /// Snoozes prompt of the field for current user
+    fn dismiss_profile_prompt(&self, payload: DismissProfilePrompt) -> ServiceFuture<ProfilePromptDismissal> {
+        let current_uid = match self.dynamic_context.user_id {
+            Some(user_id) => user_id,
+            None => {
+                return Box::new(future::err(
+                    Error::Forbidden.context("Only authorized user can dismiss profile prompts").into(),
+                ))
+            }
+        };
+        let repo_factory = self.static_context.repo_factory.clone();
+        let conf = &self.static_context.config.profile_prompts;
+
+        if !conf.fields.contains(&payload.field) {
+            return Box::new(future::err(
+                Error::Validate(validation_errors!({"field": ["unknown" => "Unknown profile field"]})).into(),
+            ));
+        }
+        let dismissal = NewProfilePromptDismissal {
+            user_id: current_uid,
+            field: payload.field,
+            snoozed_until: SystemTime::now() + Duration::from_secs(conf.snooze_s),
+        };
+
+        self.spawn_on_shard(current_uid, move |conn| {
+            let profile_prompts_repo = repo_factory.create_profile_prompts_repo(&*conn, Some(current_uid));
+            profile_prompts_repo.dismiss(dismissal).map_err(|e: FailureError| {
+                e.context("Service profile_prompts, dismiss_profile_prompt endpoint error occured.")
+                    .into()
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use tokio_core::reactor::Core;
+
+    use stq_types::UserId;
+
+    use models::*;
+    use repos::repo_factory::tests::*;
+    use services::profile_prompts::*;
+
+    #[test]
+    fn test_get_profile_prompts() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.get_profile_prompts();
+        let result = core.run(work).unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].field, "last_name");
+    }
+
+    #[test]
+    fn test_get_profile_prompts_unauthorized() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let work = service.get_profile_prompts();
+        assert_eq!(core.run(work).is_err(), true);
+    }
+
+    #[test]
+    fn test_dismiss_profile_prompt() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.dismiss_profile_prompt(DismissProfilePrompt {
+            field: "last_name".to_string(),
+        });
+        let result = core.run(work).unwrap();
+        assert_eq!(result.user_id, UserId(1));
+        assert_eq!(result.field, "last_name");
+    }
+
+    #[test]
+    fn test_dismiss_unknown_profile_prompt() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.dismiss_profile_prompt(DismissProfilePrompt {
+            field: "password".to_string(),
+        });
+        assert_eq!(core.run(work).is_err(), true);
+    }
+}
@@ -0,0 +1,39 @@
+//! Readiness of the instance to serve requests, reported by `/ready`. Liveness is not
+//! affected, so instances which are not ready are taken out of traffic but not restarted.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[derive(Debug)]
+pub struct Readiness {
+    warmed_up: AtomicBool,
+    schema_compatible: AtomicBool,
+}
+
+impl Readiness {
+    pub fn new(warmed_up: bool, schema_compatible: bool) -> Self {
+        Self {
+            warmed_up: AtomicBool::new(warmed_up),
+            schema_compatible: AtomicBool::new(schema_compatible),
+        }
+    }
+
+    pub fn set_warmed_up(&self) {
+        self.warmed_up.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns previous compatibility of the schema
+    pub fn set_schema_compatible(&self, compatible: bool) -> bool {
+        self.schema_compatible.swap(compatible, Ordering::SeqCst)
+    }
+
+    /// Reason the instance is not ready, `None` if it is ready
+    pub fn not_ready_reason(&self) -> Option<&'static str> {
+        if !self.warmed_up.load(Ordering::SeqCst) {
+            Some("Warmup is not done")
+        } else if !self.schema_compatible.load(Ordering::SeqCst) {
+            Some("Database schema is not compatible")
+        } else {
+            None
+        }
+    }
+}
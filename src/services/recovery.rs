@@ -0,0 +1,360 @@
+//! Recovery Services, account recovery via trusted contacts. The user designates contacts in advance,
+//! recovery request is sent to all of them as signed approval links, and once `required_approvals`
+//! contacts followed their links a password reset token is issued for the user.
+//! Contacts and requests are saved on the primary shard.
+
+use std::time::{Duration, SystemTime};
+
+use base64::{decode_config, encode_config, URL_SAFE_NO_PAD};
+use chrono::Utc;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::Future;
+use r2d2::ManageConnection;
+use uuid::Uuid;
+
+use stq_static_resources::TokenType;
+use stq_types::UserId;
+
+use super::util::{hmac_sign, hmac_verify, signed_token_create};
+use errors::Error;
+use models::*;
+use repos::repo_factory::ReposFactory;
+use repos::types::RepoResult;
+use services::types::ServiceFuture;
+use services::Service;
+
+const RECOVERY_APPROVAL_DOMAIN: &'static str = "recovery_approval";
+
+fn approval_payload(request_id: Uuid, contact_id: i32, exp: i64) -> String {
+    format!("{}.{}.{}", request_id, contact_id, exp)
+}
+
+/// Signs approval, token has `request_id.contact_id.exp.signature` form and is url-safe
+pub fn sign_approval(approval: &SignedRecoveryApproval, key: &[u8]) -> String {
+    let payload = approval_payload(approval.request_id, approval.contact_id, approval.exp);
+    let code = hmac_sign(key, format!("{}:{}", RECOVERY_APPROVAL_DOMAIN, payload).as_bytes());
+    format!("{}.{}", payload, encode_config(&code, URL_SAFE_NO_PAD))
+}
+
+/// Checks signature and expiry of the token created by `sign_approval`
+pub fn verify_approval(token: &str, key: &[u8]) -> RepoResult<SignedRecoveryApproval> {
+    let invalid = || -> FailureError {
+        format_err!("Recovery approval token is malformed")
+            .context(Error::InvalidToken)
+            .into()
+    };
+
+    let parts = token.split('.').collect::<Vec<_>>();
+    if parts.len() != 4 {
+        return Err(invalid());
+    }
+    let request_id = parts[0].parse::<Uuid>().map_err(|_| invalid())?;
+    let contact_id = parts[1].parse::<i32>().map_err(|_| invalid())?;
+    let exp = parts[2].parse::<i64>().map_err(|_| invalid())?;
+    let code = decode_config(parts[3], URL_SAFE_NO_PAD).map_err(|_| invalid())?;
+
+    let payload = approval_payload(request_id, contact_id, exp);
+    if !hmac_verify(key, format!("{}:{}", RECOVERY_APPROVAL_DOMAIN, payload).as_bytes(), &code) {
+        return Err(format_err!("Recovery approval signature mismatch")
+            .context(Error::InvalidToken)
+            .into());
+    }
+    if exp < Utc::now().timestamp() {
+        return Err(format_err!("Recovery approval has expired").context(Error::InvalidToken).into());
+    }
+
+    Ok(SignedRecoveryApproval {
+        request_id,
+        contact_id,
+        exp,
+    })
+}
+
+pub trait RecoveryService {
+    /// Returns trusted contacts of the user
+    fn get_trusted_contacts(&self, user_id: UserId) -> ServiceFuture<Vec<TrustedContact>>;
+    /// Adds trusted contact of the user
+    fn add_trusted_contact(&self, user_id: UserId, payload: TrustedContactPayload) -> ServiceFuture<TrustedContact>;
+    /// Removes trusted contact of the user
+    fn remove_trusted_contact(&self, user_id: UserId, contact_id: i32) -> ServiceFuture<TrustedContact>;
+    /// Starts recovery of the account, returns approval links to be sent to the trusted contacts
+    fn start_recovery(&self, payload: RecoveryStart) -> ServiceFuture<RecoveryStarted>;
+    /// Saves approval of the contact, password reset token is issued once enough contacts approved
+    fn approve_recovery(&self, token: String) -> ServiceFuture<RecoveryStatus>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > RecoveryService for Service<T, M, F>
+{
+    /// Returns trusted contacts of the user
+    fn get_trusted_contacts(&self, user_id: UserId) -> ServiceFuture<Vec<TrustedContact>> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let recovery_repo = repo_factory.create_recovery_repo(&*conn, current_uid);
+            recovery_repo
+                .list_contacts(user_id)
+                .map_err(|e: FailureError| e.context("Service recovery, get_trusted_contacts endpoint error occured.").into())
+        })
+    }
+
+    /// Adds trusted contact of the user
+    fn add_trusted_contact(&self, user_id: UserId, payload: TrustedContactPayload) -> ServiceFuture<TrustedContact> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+        let user_repo_factory = repo_factory.clone();
+        let max_contacts = self.static_context.config.recovery.max_contacts;
+        let email = normalize_email(&payload.email);
+        let service = self.clone();
+
+        debug!("Adding trusted contact {} of user {}", email, user_id);
+
+        Box::new(
+            self.spawn_on_shard(user_id, move |conn| {
+                let users_repo = user_repo_factory.create_users_repo(&*conn, current_uid);
+                users_repo
+                    .find(user_id)?
+                    .ok_or_else(|| Error::NotFound.context(format!("User {} not found", user_id)).into())
+            })
+            .and_then(move |user| {
+                service.spawn_on_pool(move |conn| {
+                    if normalize_email(&user.email) == email {
+                        return Err(Error::Validate(
+                            validation_errors!({"email": ["own_email" => "You can not be your own trusted contact"]}),
+                        )
+                        .into());
+                    }
+                    let recovery_repo = repo_factory.create_recovery_repo(&*conn, current_uid);
+                    conn.transaction::<TrustedContact, FailureError, _>(|| {
+                        let contacts = recovery_repo.list_contacts(user_id)?;
+                        if contacts.len() as u32 >= max_contacts && !contacts.iter().any(|contact| contact.email == email) {
+                            return Err(Error::Validate(
+                                validation_errors!({"email": ["too_many_contacts" => "Too many trusted contacts"]}),
+                            )
+                            .into());
+                        }
+                        recovery_repo.add_contact(NewTrustedContact { user_id, email })
+                    })
+                })
+            })
+            .map_err(|e: FailureError| e.context("Service recovery, add_trusted_contact endpoint error occured.").into()),
+        )
+    }
+
+    /// Removes trusted contact of the user
+    fn remove_trusted_contact(&self, user_id: UserId, contact_id: i32) -> ServiceFuture<TrustedContact> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let recovery_repo = repo_factory.create_recovery_repo(&*conn, current_uid);
+            conn.transaction::<Option<TrustedContact>, FailureError, _>(move || recovery_repo.remove_contact(user_id, contact_id))
+                .and_then(|contact| contact.ok_or_else(|| Error::NotFound.context("Trusted contact not found").into()))
+                .map_err(|e: FailureError| e.context("Service recovery, remove_trusted_contact endpoint error occured.").into())
+        })
+    }
+
+    /// Starts recovery of the account, returns approval links to be sent to the trusted contacts
+    fn start_recovery(&self, payload: RecoveryStart) -> ServiceFuture<RecoveryStarted> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let key = self.static_context.hmac_key.clone();
+        let config = self.static_context.config.recovery.clone();
+        let email_sending_timeout = self.static_context.config.tokens.email_sending_timeout_s;
+        let email = normalize_email(&payload.email);
+
+        debug!("Starting recovery of account {}", email);
+
+        self.spawn_on_pool(move |conn| {
+            let users_repo = repo_factory.create_users_repo_with_sys_acl(&*conn);
+            let recovery_repo = repo_factory.create_recovery_repo_with_sys_acl(&*conn);
+            conn.transaction::<RecoveryStarted, FailureError, _>(|| {
+                let user = users_repo
+                    .find_by_email(email.clone())?
+                    .ok_or_else(|| Error::Validate(validation_errors!({"email": ["not_exists" => "Email does not exist"]})))?;
+                let contacts = recovery_repo.list_contacts(user.id)?;
+                if (contacts.len() as u32) < config.required_approvals {
+                    return Err(Error::Validate(
+                        validation_errors!({"email": ["recovery_not_enabled" => "Recovery via trusted contacts is not set up"]}),
+                    )
+                    .into());
+                }
+
+                if let Some(last_request) = recovery_repo.last_request(user.id)? {
+                    let elapsed = SystemTime::now()
+                        .duration_since(last_request.created_at)
+                        .map_err(|e| Error::InvalidTime.context(format!("Can not calc duration : {}", e.to_string())))?;
+                    if elapsed.as_secs() < email_sending_timeout {
+                        return Err(Error::Validate(
+                            validation_errors!({"email": ["email_timeout" => "Can not send email more often then 30 seconds"]}),
+                        )
+                        .into());
+                    }
+                }
+
+                let expires_at = SystemTime::now() + Duration::from_secs(config.expiration_s);
+                let request =
+                    recovery_repo.create_request(NewRecoveryRequest::new(user.id, config.required_approvals as i32, expires_at))?;
+                let exp = Utc::now().timestamp() + config.expiration_s as i64;
+                let links = contacts
+                    .into_iter()
+                    .map(|contact| {
+                        let token = sign_approval(
+                            &SignedRecoveryApproval {
+                                request_id: request.id,
+                                contact_id: contact.id,
+                                exp,
+                            },
+                            &key,
+                        );
+                        RecoveryLink {
+                            contact_id: contact.id,
+                            email: contact.email,
+                            url: format!("{}?token={}", config.approve_url, token),
+                            token,
+                        }
+                    })
+                    .collect();
+
+                Ok(RecoveryStarted { request, links })
+            })
+            .map_err(|e: FailureError| e.context("Service recovery, start_recovery endpoint error occured.").into())
+        })
+    }
+
+    /// Saves approval of the contact, password reset token is issued once enough contacts approved
+    fn approve_recovery(&self, token: String) -> ServiceFuture<RecoveryStatus> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let key = self.static_context.hmac_key.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let users_repo = repo_factory.create_users_repo_with_sys_acl(&*conn);
+            let recovery_repo = repo_factory.create_recovery_repo_with_sys_acl(&*conn);
+            let reset_repo = repo_factory.create_reset_token_repo(&*conn);
+            conn.transaction::<RecoveryStatus, FailureError, _>(|| {
+                let approval = verify_approval(&token, &key)?;
+                let request = recovery_repo
+                    .find_request_for_update(approval.request_id)?
+                    .ok_or_else(|| Error::InvalidToken.context(format!("Recovery request {} not found", approval.request_id)))?;
+                let contact = recovery_repo
+                    .find_contact(approval.contact_id)?
+                    .ok_or_else(|| Error::InvalidToken.context(format!("Trusted contact {} was removed", approval.contact_id)))?;
+                if contact.user_id != request.user_id {
+                    return Err(format_err!("Contact {} is not trusted by user {}", contact.id, request.user_id)
+                        .context(Error::InvalidToken)
+                        .into());
+                }
+                if request.state != RecoveryState::Pending {
+                    return Ok(RecoveryStatus {
+                        approvals: recovery_repo.count_approvals(request.id)?,
+                        request,
+                    });
+                }
+
+                debug!("Recovery request {} is approved by contact {}", request.id, contact.id);
+                recovery_repo.approve(NewRecoveryApproval {
+                    request_id: request.id,
+                    contact_id: contact.id,
+                })?;
+                let approvals = recovery_repo.count_approvals(request.id)?;
+                if approvals < i64::from(request.required_approvals) {
+                    return Ok(RecoveryStatus { request, approvals });
+                }
+
+                let user = users_repo
+                    .find(request.user_id)?
+                    .ok_or_else(|| Error::NotFound.context(format!("User {} not found", request.user_id)))?;
+                reset_repo.upsert(user.email, TokenType::PasswordReset, Some(request.id), signed_token_create(&key))?;
+                let request = recovery_repo.update_request_state(request.id, RecoveryState::Approved)?;
+                Ok(RecoveryStatus { request, approvals })
+            })
+            .map_err(|e: FailureError| e.context("Service recovery, approve_recovery endpoint error occured.").into())
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use chrono::Utc;
+    use tokio_core::reactor::Core;
+    use uuid::Uuid;
+
+    use stq_types::UserId;
+
+    use models::*;
+    use repos::repo_factory::tests::*;
+    use services::recovery::*;
+
+    fn signed_approval(exp: i64) -> SignedRecoveryApproval {
+        SignedRecoveryApproval {
+            request_id: Uuid::new_v4(),
+            contact_id: 1,
+            exp,
+        }
+    }
+
+    #[test]
+    fn test_approval_roundtrip() {
+        let approval = signed_approval(Utc::now().timestamp() + 60);
+        let token = sign_approval(&approval, b"key");
+        assert_eq!(verify_approval(&token, b"key").unwrap(), approval);
+        assert_eq!(verify_approval(&token, b"another key").is_err(), true);
+        assert_eq!(verify_approval(&token.replacen(".1.", ".2.", 1), b"key").is_err(), true);
+    }
+
+    #[test]
+    fn test_approval_expired() {
+        let token = sign_approval(&signed_approval(Utc::now().timestamp() - 1), b"key");
+        assert_eq!(verify_approval(&token, b"key").is_err(), true);
+    }
+
+    #[test]
+    fn test_start_recovery() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let work = service.start_recovery(RecoveryStart {
+            email: MOCK_EMAIL.to_string(),
+        });
+        let result = core.run(work).unwrap();
+        assert_eq!(result.request.state, RecoveryState::Pending);
+        assert_eq!(result.links.len(), MOCK_TRUSTED_CONTACTS_COUNT as usize);
+        assert_eq!(result.links[0].url.ends_with(&result.links[0].token), true);
+    }
+
+    #[test]
+    fn test_approve_recovery() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let key = service.static_context.hmac_key.clone();
+        let token = sign_approval(&signed_approval(Utc::now().timestamp() + 60), &key);
+        let work = service.approve_recovery(token);
+        let result = core.run(work).unwrap();
+        assert_eq!(result.request.state, RecoveryState::Approved);
+        assert_eq!(result.approvals, MOCK_RECOVERY_APPROVALS);
+    }
+
+    #[test]
+    fn test_add_own_email_as_trusted_contact() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.add_trusted_contact(
+            UserId(1),
+            TrustedContactPayload {
+                email: MOCK_EMAIL.to_string(),
+            },
+        );
+        assert_eq!(core.run(work).is_err(), true);
+    }
+}
@@ -0,0 +1,365 @@
+//! Refresh tokens Services, silent token renewal for browser clients. Every renewal rotates
+//! the refresh token, tokens issued from the same session form a family. A rotated token
+//! presented again means it was stolen or replayed, so the whole family is revoked.
+//! Clients with cookie mode get refresh tokens as `httpOnly` cookie set by the gateway,
+//! so that scripts of the page can not read them.
+
+use std::time::{Duration, SystemTime};
+
+use chrono::Utc;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::future;
+use futures::Future;
+use jsonwebtoken::{encode, Algorithm, Header};
+use r2d2::ManageConnection;
+use uuid::Uuid;
+
+use super::util::{analytics_id, http_only_cookie, signed_token_create, token_hash};
+use config;
+use errors::Error;
+use models::*;
+use repos::repo_factory::ReposFactory;
+use repos::types::RepoResult;
+use repos::{ChildAccountsRepo, ClientsRepo, RefreshTokensRepo};
+use services::session_limits::enforce_session_limit;
+use services::types::ServiceFuture;
+use services::Service;
+
+pub trait RefreshTokensService {
+    /// Starts refresh token family for the session of the browser client
+    fn create_refresh_session(&self, payload: JWTPayload) -> ServiceFuture<Renewal>;
+    /// Exchanges refresh token for new access token and the next refresh token of the family
+    fn renew_token(&self, payload: RenewalRequest) -> ServiceFuture<Renewal>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > RefreshTokensService for Service<T, M, F>
+{
+    /// Starts refresh token family for the session of the browser client
+    fn create_refresh_session(&self, payload: JWTPayload) -> ServiceFuture<Renewal> {
+        let client_id = match payload.client_id.clone() {
+            Some(client_id) => client_id,
+            None => {
+                return Box::new(future::err(
+                    Error::InvalidToken
+                        .context("Refresh tokens are issued for registered clients only")
+                        .into(),
+                ))
+            }
+        };
+        if payload.exp < Utc::now().timestamp() {
+            return Box::new(future::err(
+                Error::Validate(validation_errors!({"token": ["expired" => "JWT has expired."]})).into(),
+            ));
+        }
+        let repo_factory = self.static_context.repo_factory.clone();
+        let jwt_private_key = self.static_context.jwt_private_key.clone();
+        let token_key = self.static_context.hmac_key.clone();
+        let conf = self.static_context.config.clone();
+
+        debug!("Creating refresh session of user {} for client {}", payload.user_id, client_id);
+
+        self.spawn_on_pool(move |conn| {
+            let clients_repo = repo_factory.create_clients_repo(&*conn);
+            let refresh_tokens_repo = repo_factory.create_refresh_tokens_repo(&*conn);
+            let sessions_repo = repo_factory.create_sessions_repo(&*conn);
+            let child_accounts_repo = repo_factory.create_child_accounts_repo_with_sys_acl(&*conn);
+            conn.transaction::<Renewal, FailureError, _>(|| {
+                find_browser_client(&*clients_repo, client_id).and_then(|client| {
+                    if payload.aud.as_ref() != Some(&client.audience) {
+                        return Err(Error::InvalidToken
+                            .context(format!("Token audience does not match client {}", client.id))
+                            .into());
+                    }
+                    enforce_session_limit(
+                        &*sessions_repo,
+                        &*refresh_tokens_repo,
+                        conf.session_limits.as_ref(),
+                        Duration::from_secs(conf.sessions.idle_timeout_s),
+                        payload.user_id,
+                    )?;
+                    let refresh_token = signed_token_create(&token_key);
+                    let now = SystemTime::now();
+                    let new_token = NewRefreshToken {
+                        id: Uuid::new_v4(),
+                        family_id: Uuid::new_v4(),
+                        user_id: payload.user_id,
+                        client_id: client.id.clone(),
+                        provider: payload.provider,
+                        auth_time: payload.auth_time,
+                        scope: None,
+                        token_hash: token_hash(&refresh_token),
+                        expires_at: now + Duration::from_secs(client.refresh_timeout_s as u64),
+                        family_created_at: now,
+                    };
+                    issue_renewal(
+                        &*refresh_tokens_repo,
+                        &*child_accounts_repo,
+                        &client,
+                        new_token,
+                        refresh_token,
+                        &jwt_private_key,
+                        &conf,
+                    )
+                })
+            })
+            .map_err(|e: FailureError| {
+                e.context("Service refresh_tokens, create_refresh_session endpoint error occured.")
+                    .into()
+            })
+        })
+    }
+
+    /// Exchanges refresh token for new access token and the next refresh token of the family
+    fn renew_token(&self, payload: RenewalRequest) -> ServiceFuture<Renewal> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let users_repo_factory = repo_factory.clone();
+        let issue_repo_factory = repo_factory.clone();
+        let jwt_private_key = self.static_context.jwt_private_key.clone();
+        let token_key = self.static_context.hmac_key.clone();
+        let conf = self.static_context.config.clone();
+        let service = self.clone();
+        let issue_service = self.clone();
+        let hash = token_hash(&payload.refresh_token);
+        let client_id = payload.client_id;
+
+        Box::new(
+            self.spawn_on_pool(move |conn| {
+                let clients_repo = repo_factory.create_clients_repo(&*conn);
+                let refresh_tokens_repo = repo_factory.create_refresh_tokens_repo(&*conn);
+                let client = find_browser_client(&*clients_repo, client_id)?;
+                let token = refresh_tokens_repo
+                    .find_by_hash(hash)?
+                    .ok_or_else(|| Error::InvalidToken.context("Refresh token not found"))?;
+                let now = SystemTime::now();
+                if token.client_id != client.id || token.revoked || token.is_expired(now) {
+                    return Err(Error::InvalidToken
+                        .context(format!("Refresh token {} is revoked or expired", token.id))
+                        .into());
+                }
+                // rotated token is presented again, it was stolen or replayed
+                if token.rotated_at.is_some() {
+                    refresh_tokens_repo.revoke_family(token.family_id)?;
+                    return Err(Error::InvalidToken.context(reuse_detected(&token)).into());
+                }
+                Ok((client, token))
+            })
+            .and_then(move |(client, token)| {
+                service.spawn_on_shard(token.user_id, move |conn| {
+                    let users_repo = users_repo_factory.create_users_repo_with_sys_acl(&*conn);
+                    match users_repo.find(token.user_id)? {
+                        Some(ref user) if !user.is_blocked && !is_family_revoked(&token, &client, user.revoke_before) => {
+                            Ok((client, token))
+                        }
+                        _ => Err(Error::InvalidToken
+                            .context(format!("Tokens of user {} are revoked", token.user_id))
+                            .into()),
+                    }
+                })
+            })
+            .and_then(move |(client, token)| {
+                issue_service.spawn_on_pool(move |conn| {
+                    let refresh_tokens_repo = issue_repo_factory.create_refresh_tokens_repo(&*conn);
+                    let child_accounts_repo = issue_repo_factory.create_child_accounts_repo_with_sys_acl(&*conn);
+                    let oauth_consents_repo = issue_repo_factory.create_oauth_consents_repo(&*conn, None);
+                    // the token is rotated along with issuing the next one, so that a failure in between
+                    // leaves it valid for a retry instead of revoking the family on it
+                    let rotation = conn.transaction::<Rotation, FailureError, _>(|| {
+                        if let Some(revocation) = oauth_consents_repo.find_revocation(token.user_id, client.id.clone())? {
+                            if is_family_revoked(&token, &client, revocation.revoke_before) {
+                                refresh_tokens_repo.revoke_family(token.family_id)?;
+                                return Ok(Rotation::Revoked(format!(
+                                    "Tokens of client {} are revoked by user {}",
+                                    client.id, token.user_id
+                                )));
+                            }
+                        }
+                        if !refresh_tokens_repo.rotate(token.id, SystemTime::now())? {
+                            refresh_tokens_repo.revoke_family(token.family_id)?;
+                            return Ok(Rotation::Revoked(reuse_detected(&token)));
+                        }
+                        let refresh_token = signed_token_create(&token_key);
+                        let expires_at = SystemTime::now() + Duration::from_secs(client.refresh_timeout_s as u64);
+                        let new_token = NewRefreshToken::rotate(&token, token_hash(&refresh_token), expires_at);
+                        issue_renewal(
+                            &*refresh_tokens_repo,
+                            &*child_accounts_repo,
+                            &client,
+                            new_token,
+                            refresh_token,
+                            &jwt_private_key,
+                            &conf,
+                        )
+                        .map(Rotation::Renewed)
+                    })?;
+                    match rotation {
+                        Rotation::Renewed(renewal) => Ok(renewal),
+                        Rotation::Revoked(reason) => Err(Error::InvalidToken.context(reason).into()),
+                    }
+                })
+            })
+            .map_err(|e: FailureError| e.context("Service refresh_tokens, renew_token endpoint error occured.").into()),
+        )
+    }
+}
+
+/// Outcome of renewal, revocations of the family are committed before the renewal is refused
+enum Rotation {
+    Renewed(Renewal),
+    Revoked(String),
+}
+
+fn reuse_detected(token: &RefreshToken) -> String {
+    format!(
+        "Reuse of refresh token {} detected, family {} is revoked",
+        token.id, token.family_id
+    )
+}
+
+/// Resolves registered client renewing tokens, third-party clients get tokens via device authorization only
+fn find_browser_client(clients_repo: &ClientsRepo, client_id: String) -> RepoResult<Client> {
+    let client = clients_repo
+        .find(client_id.clone())?
+        .ok_or_else(|| Error::InvalidToken.context(format!("Client {} is not registered", client_id)))?;
+    if client.is_third_party {
+        return Err(Error::InvalidToken
+            .context(format!("Third-party client {} can not renew tokens", client_id))
+            .into());
+    }
+    Ok(client)
+}
+
+/// Family is revoked along with access tokens issued when the session was started
+fn is_family_revoked(token: &RefreshToken, client: &Client, revoke_before: SystemTime) -> bool {
+    token.family_created_at + Duration::from_secs(client.jwt_expiration_s as u64) <= revoke_before
+}
+
+/// Saves the next refresh token of the family and issues access token along with it
+fn issue_renewal(
+    refresh_tokens_repo: &RefreshTokensRepo,
+    child_accounts_repo: &ChildAccountsRepo,
+    client: &Client,
+    new_token: NewRefreshToken,
+    refresh_token: String,
+    jwt_private_key: &[u8],
+    conf: &config::Config,
+) -> RepoResult<Renewal> {
+    let token = refresh_tokens_repo.create(new_token)?;
+    let tokenpayload = JWTPayload {
+        auth_time: token.auth_time,
+        ..JWTPayload::new(token.user_id, 0, token.provider.clone())
+    }
+    .with_parent(child_accounts_repo.find_by_child(token.user_id)?)
+    .with_client(client)
+    .with_scope(token.scope.clone())
+    .with_analytics_id(analytics_id(&conf.analytics, token.user_id));
+    let access_token = encode(&Header::new(Algorithm::RS256), &tokenpayload, jwt_private_key).map_err(|e| {
+        format_err!("{}", e)
+            .context(Error::Parse)
+            .context(format!("Couldn't encode jwt: {:?}.", tokenpayload))
+    })?;
+
+    let (refresh_token, set_cookie) = if client.refresh_token_cookie {
+        let cookie = http_only_cookie(
+            &conf.refresh_tokens.cookie_name,
+            &refresh_token,
+            &conf.refresh_tokens.cookie_path,
+            client.refresh_timeout_s,
+        );
+        (None, Some(cookie))
+    } else {
+        (Some(refresh_token), None)
+    };
+
+    Ok(Renewal {
+        token: access_token,
+        expires_in: client.jwt_expiration_s,
+        refresh_token,
+        set_cookie,
+    })
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use chrono::Utc;
+    use tokio_core::reactor::Core;
+
+    use stq_static_resources::Provider;
+    use stq_types::UserId;
+
+    use models::*;
+    use repos::repo_factory::tests::*;
+    use services::refresh_tokens::RefreshTokensService;
+
+    fn renewal_request(client_id: &str, refresh_token: &str) -> RenewalRequest {
+        RenewalRequest {
+            client_id: client_id.to_string(),
+            refresh_token: refresh_token.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_create_refresh_session() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let payload = JWTPayload::new(UserId(1), Utc::now().timestamp() + 60, Provider::Email)
+            .with_client(&create_client(MOCK_CLIENT_ID.to_string()));
+        let work = service.create_refresh_session(payload);
+        let result = core.run(work).unwrap();
+        assert_eq!(result.refresh_token.is_some(), true);
+        assert_eq!(result.set_cookie, None);
+    }
+
+    #[test]
+    fn test_renew_token() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let work = service.renew_token(renewal_request(MOCK_CLIENT_ID, MOCK_REFRESH_TOKEN));
+        let result = core.run(work).unwrap();
+        assert_eq!(result.expires_in, 3600);
+        assert_eq!(result.refresh_token.is_some(), true);
+        assert_ne!(result.refresh_token, Some(MOCK_REFRESH_TOKEN.to_string()));
+    }
+
+    #[test]
+    fn test_renew_token_reused() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let work = service.renew_token(renewal_request(MOCK_CLIENT_ID, MOCK_ROTATED_REFRESH_TOKEN));
+        assert_eq!(core.run(work).is_err(), true);
+    }
+
+    #[test]
+    fn test_renew_token_of_other_client() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let work = service.renew_token(renewal_request(MOCK_COOKIE_CLIENT_ID, MOCK_REFRESH_TOKEN));
+        assert_eq!(core.run(work).is_err(), true);
+    }
+
+    #[test]
+    fn test_renew_token_cookie() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let work = service.renew_token(renewal_request(MOCK_COOKIE_CLIENT_ID, MOCK_COOKIE_REFRESH_TOKEN));
+        let result = core.run(work).unwrap();
+        assert_eq!(result.refresh_token, None);
+        let cookie = result.set_cookie.unwrap();
+        assert_eq!(cookie.starts_with("refresh_token="), true);
+        assert_eq!(cookie.contains("; HttpOnly"), true);
+    }
+}
@@ -0,0 +1,177 @@
+//! Role access policies Services, time windows admin roles are in effect during, e.g. superuser
+//! actions only 08:00–20:00. Roles are dropped from the ACL of the user outside the window, see
+//! `ReposFactoryImpl::get_roles_in_effect`. Policies restricting logins also reject tokens of
+//! users with the role outside the window. The break-glass account is never restricted.
+
+use chrono::{DateTime, Utc};
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::future;
+use r2d2::ManageConnection;
+
+use stq_types::{UserId, UsersRole};
+
+use errors::Error;
+use models::{NewRoleAccessPolicy, RoleAccessPolicy};
+use repos::repo_factory::ReposFactory;
+use repos::types::RepoResult;
+use repos::{RoleAccessPoliciesRepo, UserRolesRepo};
+use services::types::ServiceFuture;
+use services::Service;
+
+pub trait RoleAccessPoliciesService {
+    /// Returns access policies of all roles
+    fn get_role_access_policies(&self) -> ServiceFuture<Vec<RoleAccessPolicy>>;
+    /// Creates the policy of the role or replaces the existing one
+    fn upsert_role_access_policy(&self, payload: NewRoleAccessPolicy) -> ServiceFuture<RoleAccessPolicy>;
+    /// Deletes the policy of the role, the role is in effect at any time
+    fn delete_role_access_policy(&self, role: UsersRole) -> ServiceFuture<RoleAccessPolicy>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > RoleAccessPoliciesService for Service<T, M, F>
+{
+    /// Returns access policies of all roles
+    fn get_role_access_policies(&self) -> ServiceFuture<Vec<RoleAccessPolicy>> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let policies_repo = repo_factory.create_role_access_policies_repo(&*conn, current_uid);
+            policies_repo.list().map_err(|e: FailureError| {
+                e.context("Service role_access_policies, get_role_access_policies endpoint error occured.")
+                    .into()
+            })
+        })
+    }
+
+    /// Creates the policy of the role or replaces the existing one
+    fn upsert_role_access_policy(&self, payload: NewRoleAccessPolicy) -> ServiceFuture<RoleAccessPolicy> {
+        if payload.role == UsersRole::User {
+            return Box::new(future::err(
+                Error::Validate(validation_errors!({"role": ["not_admin" => "Access policies are set for admin roles only"]})).into(),
+            ));
+        }
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        info!("Setting access policy {:?} by user {:?}", payload, current_uid);
+
+        self.spawn_on_pool(move |conn| {
+            let policies_repo = repo_factory.create_role_access_policies_repo(&*conn, current_uid);
+            policies_repo.upsert(payload).map_err(|e: FailureError| {
+                e.context("Service role_access_policies, upsert_role_access_policy endpoint error occured.")
+                    .into()
+            })
+        })
+    }
+
+    /// Deletes the policy of the role, the role is in effect at any time
+    fn delete_role_access_policy(&self, role: UsersRole) -> ServiceFuture<RoleAccessPolicy> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        info!("Deleting access policy of role {:?} by user {:?}", role, current_uid);
+
+        self.spawn_on_pool(move |conn| {
+            let policies_repo = repo_factory.create_role_access_policies_repo(&*conn, current_uid);
+            policies_repo.delete(role)?.ok_or_else(|| {
+                Error::NotFound
+                    .context(format!("Access policy of role {:?} not found", role))
+                    .into()
+            })
+        })
+    }
+}
+
+/// Rejects the login of the user having a role restricted to logins within its window, outside the window
+pub fn check_login_hours(
+    user_roles_repo: &UserRolesRepo,
+    policies_repo: &RoleAccessPoliciesRepo,
+    break_glass_user: Option<UserId>,
+    user_id: UserId,
+) -> RepoResult<()> {
+    if break_glass_user == Some(user_id) {
+        return Ok(());
+    }
+    let roles = user_roles_repo.list_for_user(user_id)?;
+    if roles.iter().all(|role| *role == UsersRole::User) {
+        return Ok(());
+    }
+    let policies = policies_repo.list()?;
+    match closed_login_policy(&roles, &policies, Utc::now()) {
+        Some(policy) => {
+            warn!(
+                "Login of user {} with role {:?} outside access hours is denied",
+                user_id, policy.role
+            );
+            Err(Error::Validate(validation_errors!({"email": ["outside_access_hours" => "Login is not allowed at this time"]})).into())
+        }
+        None => Ok(()),
+    }
+}
+
+/// Policy restricting logins of one of the roles which is closed at the moment
+fn closed_login_policy<'a>(roles: &[UsersRole], policies: &'a [RoleAccessPolicy], now: DateTime<Utc>) -> Option<&'a RoleAccessPolicy> {
+    policies
+        .iter()
+        .find(|policy| policy.restrict_login && roles.contains(&policy.role) && !policy.is_open(now))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+    use std::time::SystemTime;
+
+    use chrono::{NaiveTime, TimeZone};
+    use tokio_core::reactor::Core;
+
+    use repos::repo_factory::tests::*;
+
+    use super::*;
+
+    fn policy(role: UsersRole, restrict_login: bool) -> RoleAccessPolicy {
+        RoleAccessPolicy {
+            role,
+            starts_at: NaiveTime::from_hms(8, 0, 0),
+            ends_at: NaiveTime::from_hms(20, 0, 0),
+            utc_offset_minutes: 0,
+            restrict_login,
+            created_at: SystemTime::now(),
+            updated_at: SystemTime::now(),
+        }
+    }
+
+    #[test]
+    fn test_login_outside_access_hours() {
+        let policies = vec![policy(UsersRole::Superuser, true), policy(UsersRole::Moderator, false)];
+        let night = Utc.ymd(2019, 3, 17).and_hms(23, 0, 0);
+        let day = Utc.ymd(2019, 3, 17).and_hms(12, 0, 0);
+        let superuser = vec![UsersRole::User, UsersRole::Superuser];
+        let moderator = vec![UsersRole::User, UsersRole::Moderator];
+        assert_eq!(closed_login_policy(&superuser, &policies, night).is_some(), true);
+        assert_eq!(closed_login_policy(&superuser, &policies, day).is_some(), false);
+        assert_eq!(closed_login_policy(&moderator, &policies, night).is_some(), false);
+    }
+
+    #[test]
+    fn test_upsert_policy_of_user_role() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.upsert_role_access_policy(NewRoleAccessPolicy {
+            role: UsersRole::User,
+            starts_at: NaiveTime::from_hms(8, 0, 0),
+            ends_at: NaiveTime::from_hms(20, 0, 0),
+            utc_offset_minutes: 0,
+            restrict_login: false,
+        });
+        assert_eq!(core.run(work).is_err(), true);
+    }
+}
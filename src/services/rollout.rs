@@ -0,0 +1,104 @@
+//! Percentage rollout of features. A user is assigned to a feature if the hash of the feature
+//! and user id falls below the rollout percentage of the feature, so the assignment is stable
+//! across instances and restarts and grows with the percentage. Features missing from
+//! `rollouts` config are disabled for everyone.
+//!
+//! Assignments are logged and counted, counters are served by the internal listener.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use sha3::{Digest, Sha3_256};
+
+use stq_types::UserId;
+
+use models::{Feature, RolloutStats};
+
+/// Rollout percentages of features along with assignment counters
+#[derive(Clone, Debug, Default)]
+pub struct Rollouts {
+    percentages: HashMap<Feature, u8>,
+    stats: Arc<Mutex<BTreeMap<Feature, RolloutStats>>>,
+}
+
+impl Rollouts {
+    pub fn new(percentages: HashMap<Feature, u8>) -> Self {
+        Self {
+            percentages,
+            stats: Arc::new(Mutex::new(BTreeMap::new())),
+        }
+    }
+
+    /// Whether the feature is rolled out to the user
+    pub fn is_enabled(&self, feature: Feature, user_id: UserId) -> bool {
+        let percentage = self.percentages.get(&feature).cloned().unwrap_or(0);
+        let enabled = rollout_bucket(feature, user_id) < percentage;
+        info!("Feature {} is {} for user {}", feature, if enabled { "on" } else { "off" }, user_id);
+
+        let mut stats = self.stats.lock().unwrap();
+        let feature_stats = stats.entry(feature).or_insert_with(RolloutStats::default);
+        feature_stats.percentage = percentage;
+        if enabled {
+            feature_stats.enabled += 1;
+        } else {
+            feature_stats.disabled += 1;
+        }
+        enabled
+    }
+
+    /// Assignment counters of features evaluated since start
+    pub fn stats(&self) -> BTreeMap<&'static str, RolloutStats> {
+        self.stats
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(feature, stats)| (feature.as_str(), stats.clone()))
+            .collect()
+    }
+}
+
+/// Bucket of the user in `0..100`, independent for every feature
+pub fn rollout_bucket(feature: Feature, user_id: UserId) -> u8 {
+    let mut hasher = Sha3_256::default();
+    hasher.input(format!("{}:{}", feature, user_id).as_bytes());
+    let hash = hasher.result();
+    let value = hash[..8].iter().fold(0u64, |acc, byte| (acc << 8) | u64::from(*byte));
+    (value % 100) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rollout_percentages() {
+        let none = Rollouts::new(HashMap::new());
+        let mut percentages = HashMap::new();
+        percentages.insert(Feature::Argon2Passwords, 100);
+        let all = Rollouts::new(percentages);
+        for id in 1..50 {
+            assert_eq!(none.is_enabled(Feature::Argon2Passwords, UserId(id)), false);
+            assert_eq!(all.is_enabled(Feature::Argon2Passwords, UserId(id)), true);
+        }
+        assert_eq!(all.stats()["argon2_passwords"].enabled, 49);
+    }
+
+    #[test]
+    fn test_rollout_grows_with_percentage() {
+        let rollouts = |percentage| {
+            let mut percentages = HashMap::new();
+            percentages.insert(Feature::Argon2Passwords, percentage);
+            Rollouts::new(percentages)
+        };
+        let (canary, wider) = (rollouts(30), rollouts(60));
+        let enabled = (1..1000)
+            .filter(|id| canary.is_enabled(Feature::Argon2Passwords, UserId(*id)))
+            .collect::<Vec<_>>();
+        // hash buckets are spread evenly enough for 1000 users
+        assert!(enabled.len() > 200 && enabled.len() < 400);
+        // users of the canary stay assigned when the rollout is widened
+        for id in enabled {
+            assert_eq!(wider.is_enabled(Feature::Argon2Passwords, UserId(id)), true);
+        }
+    }
+}
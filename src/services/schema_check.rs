@@ -0,0 +1,158 @@
+//! Schema compatibility checks for rolling deploys. Migrations are applied before new
+//! binaries are rolled out and must not break binaries already running, so a binary works
+//! with any schema from the version it requires up to `schema_check.max_version`, which is
+//! set only while a migration breaking the binary is pending. Instances with incompatible
+//! schema fail readiness, so that old and new binaries run side by side during deploys.
+
+use std::io;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use r2d2::ManageConnection;
+
+use errors::Error;
+use repos::repo_factory::ReposFactory;
+use repos::sharding::ShardedPool;
+use services::readiness::Readiness;
+
+/// Version of the last migration this binary requires
+pub const MIN_SCHEMA_VERSION: &str = "20190309100000";
+
+/// Compatibility of the schema version with this binary
+#[derive(Clone, Debug, PartialEq)]
+pub enum SchemaCompatibility {
+    Compatible,
+    /// Migrations required by this binary are not applied yet
+    Outdated {
+        version: Option<String>,
+    },
+    /// Migrations breaking this binary are applied
+    TooNew {
+        version: String,
+    },
+}
+
+/// Versions are timestamps of the same length, so they are compared as strings
+pub fn schema_compatibility(version: Option<&str>, min_version: &str, max_version: Option<&str>) -> SchemaCompatibility {
+    match version {
+        Some(version) if version < min_version => SchemaCompatibility::Outdated {
+            version: Some(version.to_string()),
+        },
+        Some(version) => match max_version {
+            Some(max_version) if version > max_version => SchemaCompatibility::TooNew {
+                version: version.to_string(),
+            },
+            _ => SchemaCompatibility::Compatible,
+        },
+        None => SchemaCompatibility::Outdated { version: None },
+    }
+}
+
+/// Checks schema of every shard on start and every `interval` after, in a background thread
+pub fn start_schema_checks<T, M, F>(
+    db_pool: ShardedPool<M>,
+    repo_factory: F,
+    max_version: Option<String>,
+    interval: Duration,
+    readiness: Arc<Readiness>,
+) -> io::Result<JoinHandle<()>>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    thread::Builder::new().name("schema_checks".to_string()).spawn(move || loop {
+        let compatible = match check_schema(&db_pool, &repo_factory, max_version.as_ref().map(String::as_str)) {
+            Ok(compatible) => compatible,
+            Err(e) => {
+                error!("Database schema was not checked: {}", e);
+                false
+            }
+        };
+        let was_compatible = readiness.set_schema_compatible(compatible);
+        if compatible && !was_compatible {
+            info!("Database schema is compatible, minimal version {}", MIN_SCHEMA_VERSION);
+        }
+        thread::sleep(interval);
+    })
+}
+
+/// Returns `false` if schema of any shard is not compatible
+fn check_schema<T, M, F>(db_pool: &ShardedPool<M>, repo_factory: &F, max_version: Option<&str>) -> Result<bool, FailureError>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    for (index, pool) in db_pool.shards().iter().enumerate() {
+        let conn = pool.get().map_err(|e| e.context(Error::Connection))?;
+        let schema_migrations_repo = repo_factory.create_schema_migrations_repo(&*conn);
+        let version = schema_migrations_repo.current_version()?;
+        match schema_compatibility(version.as_ref().map(String::as_str), MIN_SCHEMA_VERSION, max_version) {
+            SchemaCompatibility::Compatible => (),
+            incompatible => {
+                warn!("Database schema of shard {} is not compatible: {:?}", index, incompatible);
+                return Ok(false);
+            }
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use tokio_core::reactor::Core;
+
+    use super::*;
+    use repos::repo_factory::tests::*;
+
+    #[test]
+    fn test_schema_compatibility() {
+        let min_version = "20190309100000";
+        assert_eq!(
+            schema_compatibility(Some("20190309100000"), min_version, None),
+            SchemaCompatibility::Compatible
+        );
+        assert_eq!(
+            schema_compatibility(Some("20190401100000"), min_version, Some("20190401100000")),
+            SchemaCompatibility::Compatible
+        );
+        assert_eq!(
+            schema_compatibility(Some("20190308100000"), min_version, None),
+            SchemaCompatibility::Outdated {
+                version: Some("20190308100000".to_string())
+            }
+        );
+        assert_eq!(
+            schema_compatibility(Some("20190402100000"), min_version, Some("20190401100000")),
+            SchemaCompatibility::TooNew {
+                version: "20190402100000".to_string()
+            }
+        );
+        assert_eq!(
+            schema_compatibility(None, min_version, None),
+            SchemaCompatibility::Outdated { version: None }
+        );
+    }
+
+    #[test]
+    fn test_check_schema() {
+        let core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let context = &service.static_context;
+        assert_eq!(check_schema(&context.db_pool, &context.repo_factory, None).unwrap(), true);
+        assert_eq!(
+            check_schema(&context.db_pool, &context.repo_factory, Some("20190101100000")).unwrap(),
+            false
+        );
+    }
+}
@@ -0,0 +1,271 @@
+//! Security questions Services. Answers are a secondary verification on password reset,
+//! users who answered the questions have to answer them again to reset the password.
+//! Answers are normalized and hashed the same way as passwords.
+
+use std::cmp;
+use std::collections::HashSet;
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::future;
+use r2d2::ManageConnection;
+
+use stq_static_resources::TokenType;
+use stq_types::UserId;
+
+use super::util::{password_create, password_verify, signed_token_verify};
+use config::SecurityQuestions as SecurityQuestionsConf;
+use errors::Error;
+use i18n::Locale;
+use models::*;
+use repos::repo_factory::ReposFactory;
+use services::types::ServiceFuture;
+use services::Service;
+
+pub trait SecurityQuestionsService {
+    /// Returns catalog of questions in the locale
+    fn get_security_questions(&self, locale: Locale) -> ServiceFuture<Vec<SecurityQuestion>>;
+    /// Returns questions answered by the user, answers themselves are never returned
+    fn get_security_answers(&self, user_id: UserId) -> ServiceFuture<Vec<SecurityAnswer>>;
+    /// Replaces answers of the user
+    fn set_security_answers(&self, user_id: UserId, payload: SecurityAnswers) -> ServiceFuture<Vec<SecurityAnswer>>;
+    /// Returns questions to be answered to reset password with the token
+    fn get_reset_security_questions(&self, token: String, locale: Locale) -> ServiceFuture<Vec<SecurityQuestion>>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > SecurityQuestionsService for Service<T, M, F>
+{
+    /// Returns catalog of questions in the locale
+    fn get_security_questions(&self, locale: Locale) -> ServiceFuture<Vec<SecurityQuestion>> {
+        Box::new(future::ok(self.static_context.config.security_questions.catalog(locale)))
+    }
+
+    /// Returns questions answered by the user, answers themselves are never returned
+    fn get_security_answers(&self, user_id: UserId) -> ServiceFuture<Vec<SecurityAnswer>> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let security_answers_repo = repo_factory.create_security_answers_repo(&*conn, current_uid);
+            security_answers_repo.list_for_user(user_id).map_err(|e: FailureError| {
+                e.context("Service security_questions, get_security_answers endpoint error occured.")
+                    .into()
+            })
+        })
+    }
+
+    /// Replaces answers of the user
+    fn set_security_answers(&self, user_id: UserId, payload: SecurityAnswers) -> ServiceFuture<Vec<SecurityAnswer>> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        if let Err(e) = validate_answers(&self.static_context.config.security_questions, &payload.answers) {
+            return Box::new(future::err(e));
+        }
+        let answers = payload
+            .answers
+            .into_iter()
+            .map(|answer| NewSecurityAnswer {
+                user_id,
+                question_id: answer.question_id,
+                answer_hash: password_create(normalize_answer(&answer.answer)),
+            })
+            .collect::<Vec<_>>();
+
+        debug!("Setting security answers of user {}", user_id);
+
+        self.spawn_on_pool(move |conn| {
+            let security_answers_repo = repo_factory.create_security_answers_repo(&*conn, current_uid);
+            security_answers_repo.replace(user_id, answers).map_err(|e: FailureError| {
+                e.context("Service security_questions, set_security_answers endpoint error occured.")
+                    .into()
+            })
+        })
+    }
+
+    /// Returns questions to be answered to reset password with the token
+    fn get_reset_security_questions(&self, token: String, locale: Locale) -> ServiceFuture<Vec<SecurityQuestion>> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let signing_key = self.static_context.hmac_key.clone();
+        let catalog = self.static_context.config.security_questions.catalog(locale);
+
+        self.spawn_on_pool(move |conn| {
+            {
+                if !signed_token_verify(&signing_key, &token) {
+                    return Err(Error::InvalidToken.context("Token signature mismatch").into());
+                }
+                let reset_repo = repo_factory.create_reset_token_repo(&*conn);
+                let ident_repo = repo_factory.create_identities_repo(&*conn);
+                let security_answers_repo = repo_factory.create_security_answers_repo_with_sys_acl(&*conn);
+
+                let reset_token = reset_repo
+                    .find_by_token(token, TokenType::PasswordReset)
+                    .map_err(|e| e.context("Reset token by token search failure").context(Error::InvalidToken))?;
+                let ident = ident_repo.get_by_email(reset_token.email)?;
+                let answers = security_answers_repo.list_for_user(ident.user_id)?;
+                Ok(catalog
+                    .into_iter()
+                    .filter(|question| answers.iter().any(|answer| answer.question_id == question.id))
+                    .collect())
+            }
+            .map_err(|e: FailureError| {
+                e.context("Service security_questions, get_reset_security_questions endpoint error occured.")
+                    .into()
+            })
+        })
+    }
+}
+
+fn validate_answers(conf: &SecurityQuestionsConf, answers: &[SecurityAnswerPayload]) -> Result<(), FailureError> {
+    if answers.len() < conf.required_answers as usize {
+        return Err(
+            Error::Validate(validation_errors!({"security_answers": ["count" => "Not enough security questions are answered"]})).into(),
+        );
+    }
+
+    let mut question_ids = HashSet::new();
+    for answer in answers {
+        if !conf.is_known(&answer.question_id) {
+            return Err(
+                Error::Validate(validation_errors!({"security_answers": ["unknown_question" => "Unknown security question"]})).into(),
+            );
+        }
+        if !question_ids.insert(answer.question_id.as_str()) {
+            return Err(Error::Validate(validation_errors!({"security_answers": ["duplicate" => "Question is answered twice"]})).into());
+        }
+        if normalize_answer(&answer.answer).is_empty() {
+            return Err(Error::Validate(validation_errors!({"security_answers": ["empty" => "Answer must not be empty"]})).into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks answers given on password reset. Users without answers are not asked, otherwise
+/// `required_answers` of the questions (all of them, if the user answered fewer) must be answered
+/// correctly. Second factor is not supported yet, so the answers are asked of every user who set them.
+pub fn check_security_answers(
+    answers: &[SecurityAnswer],
+    given: &[SecurityAnswerPayload],
+    required_answers: u32,
+) -> Result<(), FailureError> {
+    if answers.is_empty() {
+        return Ok(());
+    }
+
+    let mut matched = HashSet::new();
+    for given_answer in given {
+        let answer = answers.iter().find(|answer| answer.question_id == given_answer.question_id);
+        match answer {
+            Some(answer) if password_verify(&answer.answer_hash, normalize_answer(&given_answer.answer))? => {
+                matched.insert(answer.question_id.as_str());
+            }
+            _ => {
+                return Err(
+                    Error::Validate(validation_errors!({"security_answers": ["mismatch" => "Wrong answer to security question"]})).into(),
+                );
+            }
+        }
+    }
+
+    if matched.len() < cmp::min(required_answers as usize, answers.len()) {
+        return Err(
+            Error::Validate(validation_errors!({"security_answers": ["required" => "Security questions must be answered"]})).into(),
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use tokio_core::reactor::Core;
+
+    use stq_types::UserId;
+
+    use i18n::Locale;
+    use models::*;
+    use repos::repo_factory::tests::*;
+    use services::security_questions::*;
+
+    fn answer(question_id: &str, answer: &str) -> SecurityAnswerPayload {
+        SecurityAnswerPayload {
+            question_id: question_id.to_string(),
+            answer: answer.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_normalize_answer() {
+        assert_eq!(normalize_answer("  New   York "), "new york");
+    }
+
+    #[test]
+    fn test_check_security_answers() {
+        let answers = vec![create_security_answer(UserId(1))];
+        assert_eq!(check_security_answers(&[], &[], 2).is_ok(), true);
+        assert_eq!(check_security_answers(&answers, &[], 2).is_err(), true);
+        assert_eq!(
+            check_security_answers(&answers, &[answer(MOCK_SECURITY_QUESTION, " REX ")], 2).is_ok(),
+            true
+        );
+        assert_eq!(
+            check_security_answers(&answers, &[answer(MOCK_SECURITY_QUESTION, "max")], 2).is_err(),
+            true
+        );
+        assert_eq!(check_security_answers(&answers, &[answer("birth_city", "rex")], 2).is_err(), true);
+    }
+
+    #[test]
+    fn test_get_security_questions() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let en = core.run(service.get_security_questions(Locale::En)).unwrap();
+        let ru = core.run(service.get_security_questions(Locale::Ru)).unwrap();
+        assert_eq!(en.is_empty(), false);
+        assert_eq!(
+            en.iter().map(|question| &question.id).collect::<Vec<_>>(),
+            ru.iter().map(|question| &question.id).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_set_security_answers() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.set_security_answers(
+            UserId(1),
+            SecurityAnswers {
+                answers: vec![answer("first_pet", "Rex"), answer("birth_city", "Moscow")],
+            },
+        );
+        let result = core.run(work).unwrap();
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].answer_hash.contains("Rex"), false);
+    }
+
+    #[test]
+    fn test_set_security_answers_duplicate() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.set_security_answers(
+            UserId(1),
+            SecurityAnswers {
+                answers: vec![answer("first_pet", "Rex"), answer("first_pet", "Max")],
+            },
+        );
+        assert_eq!(core.run(work).is_err(), true);
+    }
+}
@@ -0,0 +1,371 @@
+//! Segment export Services, exports users matching search terms to CSV for marketing.
+//! Export is run as a job in background, shard by shard, so that a large segment
+//! does not hold the request. CSV is saved with the export once the job is done and
+//! is dropped after `segment_exports.csv_ttl_s`, as it holds PII of the whole segment.
+
+use std::io;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, Utc};
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::future;
+use futures::Future;
+use r2d2::ManageConnection;
+use serde_json;
+use uuid::Uuid;
+
+use stq_types::UserId;
+
+use errors::Error;
+use models::*;
+use repos::repo_factory::ReposFactory;
+use repos::segment_exports::SegmentExportsRepo;
+use repos::sharding::ShardedPool;
+use services::jobs::{spawn_job, JobContext};
+use services::types::ServiceFuture;
+use services::work_pool::WorkPriority;
+use services::Service;
+
+/// Columns of exported CSV
+const CSV_HEADER: &'static [&'static str] = &[
+    "id",
+    "email",
+    "phone",
+    "first_name",
+    "last_name",
+    "country",
+    "data_region",
+    "is_blocked",
+    "created_at",
+];
+
+pub trait SegmentExportService {
+    /// Starts export of users matching the terms, returns job of the export
+    fn export_segment(&self, term: UsersSearchTerms) -> ServiceFuture<Job>;
+    /// Returns CSV of a done export
+    fn get_segment_export_csv(&self, id: Uuid) -> ServiceFuture<String>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > SegmentExportService for Service<T, M, F>
+{
+    /// Starts export of users matching the terms, returns job of the export
+    fn export_segment(&self, mut term: UsersSearchTerms) -> ServiceFuture<Job> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+        let cpu_pool = self.static_context.work_pool.cpu_pool(WorkPriority::Batch);
+        let page_size = self.static_context.config.repo_limits.max_count;
+        let default_region = &self.static_context.config.phone.default_region;
+
+        term.phone = term
+            .phone
+            .map(|term_phone| normalize_phone(&term_phone, default_region).unwrap_or(term_phone));
+
+        let filters = match serde_json::to_value(&term) {
+            Ok(filters) => filters,
+            Err(e) => return Box::new(future::err(e.context(Error::Parse).into())),
+        };
+        let new_job = NewJob::new(JobKind::SegmentExport, current_uid);
+        let new_export = NewSegmentExport { id: new_job.id, filters };
+        let job_context = JobContext::new(self, new_job.id);
+
+        debug!("Exporting users segment with payload: {:?}", term);
+
+        Box::new(
+            self.spawn_on_pool(move |conn| {
+                let jobs_repo = repo_factory.create_jobs_repo(&*conn, current_uid);
+                let segment_exports_repo = repo_factory.create_segment_exports_repo(&*conn);
+                conn.transaction::<Job, FailureError, _>(move || {
+                    let job = jobs_repo.create(new_job)?;
+                    segment_exports_repo.create(new_export)?;
+                    Ok(job)
+                })
+                .map_err(|e: FailureError| e.context("Service segment_export, export_segment endpoint error occured.").into())
+            })
+            .inspect(move |_| spawn_job(&cpu_pool, job_context, move |job| run_segment_export(job, page_size, &term))),
+        )
+    }
+
+    /// Returns CSV of a done export
+    fn get_segment_export_csv(&self, id: Uuid) -> ServiceFuture<String> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let jobs_repo = repo_factory.create_jobs_repo(&*conn, current_uid);
+            let segment_exports_repo = repo_factory.create_segment_exports_repo(&*conn);
+            jobs_repo
+                .find(id)
+                .and_then(|job| {
+                    let job = job.ok_or_else(|| Error::NotFound.context(format!("Segment export {} not found", id)))?;
+                    if job.kind != JobKind::SegmentExport || job.state != JobState::Done {
+                        return Err(format_err!("Job {} is not a done segment export", id)
+                            .context(Error::NotFound)
+                            .into());
+                    }
+                    segment_exports_repo
+                        .find(id)?
+                        .and_then(|export| export.csv)
+                        .ok_or_else(|| Error::NotFound.context(format!("CSV of segment export {} not found", id)).into())
+                })
+                .map_err(|e: FailureError| {
+                    e.context("Service segment_export, get_segment_export_csv endpoint error occured.")
+                        .into()
+                })
+        })
+    }
+}
+
+/// Exports users to CSV reporting number of exported users as progress of the job,
+/// returns location of the CSV
+fn run_segment_export<T, M, F>(job: &JobContext<M, F>, page_size: i64, term: &UsersSearchTerms) -> Result<Option<String>, FailureError>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    let total = segment_size(&job.db_pool, &job.repo_factory, job.user_id, term)?;
+    job.update(UpdateJob::running(Some(total)))?;
+
+    let csv = segment_csv(&job.db_pool, &job.repo_factory, job.user_id, page_size, term, &mut |rows_count| {
+        job.report_progress(rows_count)
+    })?;
+
+    let conn = job.db_pool.primary().get().map_err(|e| e.context(Error::Connection))?;
+    let segment_exports_repo = job.repo_factory.create_segment_exports_repo(&*conn);
+    segment_exports_repo.set_csv(job.job_id, csv)?;
+
+    Ok(Some(format!("/users/segments/export/{}/csv", job.job_id)))
+}
+
+/// Returns number of users of all shards matching the terms
+fn segment_size<T, M, F>(
+    db_pool: &ShardedPool<M>,
+    repo_factory: &F,
+    user_id: Option<UserId>,
+    term: &UsersSearchTerms,
+) -> Result<i32, FailureError>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    let mut total = 0;
+    for shard_pool in db_pool.shards() {
+        let conn = shard_pool.get().map_err(|e| e.context(Error::Connection))?;
+        let users_repo = repo_factory.create_users_repo(&*conn, user_id);
+        total += users_repo.search(None, 0, 1, term.clone())?.total_count as i32;
+    }
+    Ok(total)
+}
+
+/// Returns CSV with users of all shards matching the terms. Every shard is read in pages
+/// of `page_size` users ordered by id, `on_page` is called with number of users exported so far.
+pub fn segment_csv<T, M, F>(
+    db_pool: &ShardedPool<M>,
+    repo_factory: &F,
+    user_id: Option<UserId>,
+    page_size: i64,
+    term: &UsersSearchTerms,
+    on_page: &mut FnMut(i32),
+) -> Result<String, FailureError>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    let header = CSV_HEADER.iter().map(|column| column.to_string()).collect::<Vec<_>>();
+    let mut csv = csv_record(&header);
+    let mut rows_count = 0;
+
+    for shard_pool in db_pool.shards() {
+        let conn = shard_pool.get().map_err(|e| e.context(Error::Connection))?;
+        let users_repo = repo_factory.create_users_repo(&*conn, user_id);
+        let mut from = None;
+        loop {
+            let users = users_repo.search(from, 0, page_size, term.clone())?.users;
+            for user in &users {
+                csv.push_str(&csv_record(&user_csv_fields(user)));
+                rows_count += 1;
+            }
+            on_page(rows_count);
+            match users.last() {
+                Some(last) if users.len() as i64 == page_size => from = Some(UserId(last.id.0 + 1)),
+                _ => break,
+            }
+        }
+    }
+
+    Ok(csv)
+}
+
+/// Drops CSVs saved more than `csv_ttl` ago every `interval`, in a background thread.
+/// Exports are stored on the primary shard.
+pub fn start_segment_export_purge<T, M, F>(
+    db_pool: ShardedPool<M>,
+    repo_factory: F,
+    csv_ttl: Duration,
+    interval: Duration,
+) -> io::Result<JoinHandle<()>>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    thread::Builder::new().name("segment_export_purge".to_string()).spawn(move || loop {
+        if let Err(e) = purge_segment_exports(&db_pool, &repo_factory, csv_ttl) {
+            error!("Expired CSVs of segment exports were not dropped: {}", e);
+        }
+        thread::sleep(interval);
+    })
+}
+
+fn purge_segment_exports<T, M, F>(db_pool: &ShardedPool<M>, repo_factory: &F, csv_ttl: Duration) -> Result<usize, FailureError>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    let conn = db_pool.primary().get().map_err(|e| e.context(Error::Connection))?;
+    let segment_exports_repo = repo_factory.create_segment_exports_repo(&*conn);
+    purge_expired_csv(&*segment_exports_repo, SystemTime::now(), csv_ttl)
+}
+
+/// Drops CSVs saved more than `csv_ttl` before `now`
+fn purge_expired_csv(segment_exports_repo: &SegmentExportsRepo, now: SystemTime, csv_ttl: Duration) -> Result<usize, FailureError> {
+    let purged = segment_exports_repo.purge_csv(now - csv_ttl)?;
+    if purged > 0 {
+        info!("Dropped {} expired CSVs of segment exports", purged);
+    }
+    Ok(purged)
+}
+
+fn user_csv_fields(user: &User) -> Vec<String> {
+    vec![
+        user.id.to_string(),
+        user.email.clone(),
+        user.phone.clone().unwrap_or_default(),
+        user.first_name.clone().unwrap_or_default(),
+        user.last_name.clone().unwrap_or_default(),
+        user.country.as_ref().map(|country| country.0.clone()).unwrap_or_default(),
+        user.data_region.clone(),
+        user.is_blocked.to_string(),
+        format_time(user.created_at),
+    ]
+}
+
+fn format_time(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).to_rfc3339()
+}
+
+/// CSV line with fields quoted as in RFC 4180. Fields starting with formula characters
+/// are prefixed with `'`, so that spreadsheets do not evaluate user input.
+fn csv_record(fields: &[String]) -> String {
+    let mut record = fields.iter().map(|field| csv_field(field)).collect::<Vec<_>>().join(",");
+    record.push_str("\r\n");
+    record
+}
+
+fn csv_field(field: &str) -> String {
+    let field = if field.starts_with(|c: char| c == '=' || c == '+' || c == '-' || c == '@') {
+        format!("'{}", field)
+    } else {
+        field.to_string()
+    };
+
+    if field.contains(|c: char| c == ',' || c == '"' || c == '\r' || c == '\n') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+
+    use tokio_core::reactor::Core;
+    use uuid::Uuid;
+
+    use stq_types::UserId;
+
+    use models::*;
+    use repos::repo_factory::tests::*;
+    use services::segment_export::*;
+
+    fn empty_terms() -> UsersSearchTerms {
+        UsersSearchTerms {
+            email: None,
+            phone: None,
+            first_name: None,
+            last_name: None,
+            is_blocked: None,
+            data_region: None,
+            tags: Some(vec![MOCK_SEGMENT_TAG.to_string()]),
+        }
+    }
+
+    #[test]
+    fn test_csv_field() {
+        assert_eq!(csv_field("John"), "John");
+        assert_eq!(csv_field("Doe, John"), "\"Doe, John\"");
+        assert_eq!(csv_field("\"Johnny\""), "\"\"\"Johnny\"\"\"");
+        assert_eq!(csv_field("=HYPERLINK(\"x\")"), "\"'=HYPERLINK(\"\"x\"\")\"");
+        assert_eq!(csv_field("+79991234567"), "'+79991234567");
+    }
+
+    #[test]
+    fn test_segment_csv_reads_all_pages() {
+        let core = Core::new().unwrap();
+        let service = create_service(Some(UserId(1)), Arc::new(core.handle()));
+        let mut progress = vec![];
+        let csv = segment_csv(
+            &service.static_context.db_pool,
+            &MOCK_REPO_FACTORY,
+            Some(UserId(1)),
+            1000,
+            &empty_terms(),
+            &mut |rows_count| progress.push(rows_count),
+        )
+        .unwrap();
+        assert_eq!(progress, vec![1000, MOCK_USERS_COUNT]);
+        assert_eq!(csv.lines().count(), MOCK_USERS_COUNT as usize + 1);
+        assert_eq!(csv.starts_with("id,email,phone,"), true);
+    }
+
+    #[test]
+    fn test_purge_expired_csv() {
+        let purged = purge_expired_csv(&SegmentExportsRepoMock, SystemTime::now(), Duration::from_secs(86400)).unwrap();
+        assert_eq!(purged, 1);
+    }
+
+    #[test]
+    fn test_export_segment() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.export_segment(empty_terms());
+        let result = core.run(work).unwrap();
+        assert_eq!(result.kind, JobKind::SegmentExport);
+        assert_eq!(result.state, JobState::Pending);
+        assert_eq!(result.created_by, Some(UserId(1)));
+    }
+
+    #[test]
+    fn test_get_segment_export_csv_of_pending_job() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.get_segment_export_csv(Uuid::new_v4());
+        assert_eq!(core.run(work).is_err(), true);
+    }
+}
@@ -0,0 +1,132 @@
+//! Limits on simultaneous sessions of a user, required by security policies of some customers.
+//! Server-side sessions and refresh token families of browser clients count alike, the limit
+//! is enforced whenever either of them is started. Sessions of the user are locked while they are
+//! counted, so the limit is enforced in the same transaction the new session is created in.
+
+use std::time::{Duration, SystemTime};
+
+use failure::Error as FailureError;
+use uuid::Uuid;
+
+use stq_types::UserId;
+
+use config::{SessionLimitPolicy, SessionLimits};
+use errors::Error;
+use repos::types::RepoResult;
+use repos::{RefreshTokensRepo, SessionsRepo};
+
+/// Active session of the user along with the time it was started
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ActiveSession {
+    /// Server-side session by its id
+    Session(Uuid, SystemTime),
+    /// Refresh token family by its id
+    RefreshTokenFamily(Uuid, SystemTime),
+}
+
+impl ActiveSession {
+    fn created_at(&self) -> SystemTime {
+        match *self {
+            ActiveSession::Session(_, created_at) => created_at,
+            ActiveSession::RefreshTokenFamily(_, created_at) => created_at,
+        }
+    }
+}
+
+/// Makes room for a new session of the user, closing the oldest sessions or rejecting
+/// the new one by the policy. Sessions are not limited unless configured.
+///
+/// Sessions of the user stay locked until the end of current transaction, the new session
+/// must be created in it so that concurrent logins can not exceed the limit.
+pub fn enforce_session_limit(
+    sessions_repo: &SessionsRepo,
+    refresh_tokens_repo: &RefreshTokensRepo,
+    conf: Option<&SessionLimits>,
+    idle_timeout: Duration,
+    user_id: UserId,
+) -> RepoResult<()> {
+    let conf = match conf {
+        Some(conf) => conf,
+        None => return Ok(()),
+    };
+    sessions_repo.lock_user(user_id)?;
+    let now = SystemTime::now();
+    let mut active = sessions_repo
+        .list_active(user_id, now)?
+        .into_iter()
+        .filter(|session| !session.is_expired(now, idle_timeout))
+        .map(|session| ActiveSession::Session(session.id, session.created_at))
+        .collect::<Vec<_>>();
+    active.extend(
+        refresh_tokens_repo
+            .list_active(user_id, now)?
+            .into_iter()
+            .map(|token| ActiveSession::RefreshTokenFamily(token.family_id, token.family_created_at)),
+    );
+
+    let to_close = sessions_to_close(active, conf).map_err(|e| e.context(format!("User {} has too many active sessions", user_id)))?;
+    for session in to_close {
+        match session {
+            ActiveSession::Session(session_id, _) => sessions_repo.delete(session_id)?,
+            ActiveSession::RefreshTokenFamily(family_id, _) => refresh_tokens_repo.revoke_family(family_id)?,
+        }
+        debug!("Closed session {:?} of user {} over the limit", session, user_id);
+    }
+    Ok(())
+}
+
+/// Sessions to close before the new one is started, oldest first
+fn sessions_to_close(mut active: Vec<ActiveSession>, conf: &SessionLimits) -> Result<Vec<ActiveSession>, FailureError> {
+    if active.len() < conf.max_sessions {
+        return Ok(vec![]);
+    }
+    match conf.policy {
+        SessionLimitPolicy::RejectNew => {
+            Err(Error::Validate(validation_errors!({"token": ["too_many_sessions" => "Too many active sessions"]})).into())
+        }
+        SessionLimitPolicy::EvictOldest => {
+            active.sort_by_key(ActiveSession::created_at);
+            let excess = active.len() + 1 - conf.max_sessions;
+            active.truncate(excess);
+            Ok(active)
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn limits(max_sessions: usize, policy: SessionLimitPolicy) -> SessionLimits {
+        SessionLimits { max_sessions, policy }
+    }
+
+    fn active_sessions(now: SystemTime) -> Vec<ActiveSession> {
+        vec![
+            ActiveSession::Session(Uuid::new_v4(), now - Duration::from_secs(60)),
+            ActiveSession::RefreshTokenFamily(Uuid::new_v4(), now - Duration::from_secs(180)),
+            ActiveSession::Session(Uuid::new_v4(), now - Duration::from_secs(120)),
+        ]
+    }
+
+    #[test]
+    fn test_sessions_under_limit() {
+        let active = active_sessions(SystemTime::now());
+        let to_close = sessions_to_close(active, &limits(4, SessionLimitPolicy::RejectNew)).unwrap();
+        assert_eq!(to_close, vec![]);
+    }
+
+    #[test]
+    fn test_evict_oldest_sessions() {
+        let active = active_sessions(SystemTime::now());
+        let to_close = sessions_to_close(active.clone(), &limits(2, SessionLimitPolicy::EvictOldest)).unwrap();
+        assert_eq!(to_close, vec![active[1], active[2]]);
+    }
+
+    #[test]
+    fn test_reject_new_session() {
+        let active = active_sessions(SystemTime::now());
+        let result = sessions_to_close(active, &limits(3, SessionLimitPolicy::RejectNew));
+        assert_eq!(result.is_err(), true);
+    }
+}
@@ -0,0 +1,282 @@
+//! Sessions Services, stateful session mode for clients which can not safely hold tokens
+//! in the browser, e.g. server-rendered admin console. Session id is set as `httpOnly` cookie
+//! by the gateway, which resolves it to a short-lived token of the user on every request.
+//! Sessions are saved hashed on the primary shard, as they are looked up without knowing the user.
+
+use std::time::{Duration, SystemTime};
+
+use chrono::Utc;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::future;
+use futures::Future;
+use jsonwebtoken::{encode, Algorithm, Header};
+use r2d2::ManageConnection;
+use uuid::Uuid;
+
+use super::util::{analytics_id, http_only_cookie, signed_token_create, token_hash};
+use errors::Error;
+use models::*;
+use repos::repo_factory::ReposFactory;
+use repos::types::RepoResult;
+use repos::ClientsRepo;
+use services::session_limits::enforce_session_limit;
+use services::types::ServiceFuture;
+use services::Service;
+
+pub trait SessionsService {
+    /// Exchanges token of the client with session mode for server-side session
+    fn create_session(&self, payload: JWTPayload) -> ServiceFuture<CreatedSession>;
+    /// Resolves session id to short-lived token of the session user
+    fn resolve_session(&self, payload: SessionRequest) -> ServiceFuture<ResolvedSession>;
+    /// Deletes session and expires its cookie
+    fn delete_session(&self, payload: SessionRequest) -> ServiceFuture<ClosedSession>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > SessionsService for Service<T, M, F>
+{
+    /// Exchanges token of the client with session mode for server-side session
+    fn create_session(&self, payload: JWTPayload) -> ServiceFuture<CreatedSession> {
+        let client_id = match payload.client_id.clone() {
+            Some(client_id) => client_id,
+            None => {
+                return Box::new(future::err(
+                    Error::InvalidToken
+                        .context("Sessions are created for registered clients only")
+                        .into(),
+                ))
+            }
+        };
+        if payload.exp < Utc::now().timestamp() {
+            return Box::new(future::err(
+                Error::Validate(validation_errors!({"token": ["expired" => "JWT has expired."]})).into(),
+            ));
+        }
+        let repo_factory = self.static_context.repo_factory.clone();
+        let session_key = self.static_context.hmac_key.clone();
+        let conf = self.static_context.config.sessions.clone();
+        let session_limits = self.static_context.config.session_limits.clone();
+
+        debug!("Creating session of user {} for client {}", payload.user_id, client_id);
+
+        self.spawn_on_pool(move |conn| {
+            let clients_repo = repo_factory.create_clients_repo(&*conn);
+            let sessions_repo = repo_factory.create_sessions_repo(&*conn);
+            let refresh_tokens_repo = repo_factory.create_refresh_tokens_repo(&*conn);
+            conn.transaction::<CreatedSession, FailureError, _>(|| {
+                find_session_client(&*clients_repo, client_id).and_then(|client| {
+                    if payload.aud.as_ref() != Some(&client.audience) {
+                        return Err(Error::InvalidToken
+                            .context(format!("Token audience does not match client {}", client.id))
+                            .into());
+                    }
+                    enforce_session_limit(
+                        &*sessions_repo,
+                        &*refresh_tokens_repo,
+                        session_limits.as_ref(),
+                        Duration::from_secs(conf.idle_timeout_s),
+                        payload.user_id,
+                    )?;
+                    let session_id = signed_token_create(&session_key);
+                    sessions_repo.create(NewSession {
+                        id: Uuid::new_v4(),
+                        session_hash: token_hash(&session_id),
+                        user_id: payload.user_id,
+                        client_id: client.id.clone(),
+                        provider: payload.provider,
+                        auth_time: payload.auth_time,
+                        expires_at: SystemTime::now() + Duration::from_secs(client.refresh_timeout_s as u64),
+                    })?;
+                    Ok(CreatedSession {
+                        expires_in: client.refresh_timeout_s,
+                        set_cookie: http_only_cookie(&conf.cookie_name, &session_id, &conf.cookie_path, client.refresh_timeout_s),
+                    })
+                })
+            })
+            .map_err(|e: FailureError| e.context("Service sessions, create_session endpoint error occured.").into())
+        })
+    }
+
+    /// Resolves session id to short-lived token of the session user
+    fn resolve_session(&self, payload: SessionRequest) -> ServiceFuture<ResolvedSession> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let users_repo_factory = repo_factory.clone();
+        let jwt_private_key = self.static_context.jwt_private_key.clone();
+        let idle_timeout = Duration::from_secs(self.static_context.config.sessions.idle_timeout_s);
+        let analytics = self.static_context.config.analytics.clone();
+        let read_only = self.static_context.config.read_only;
+        let service = self.clone();
+        let hash = token_hash(&payload.session_id);
+
+        Box::new(
+            self.spawn_on_pool(move |conn| {
+                let clients_repo = repo_factory.create_clients_repo(&*conn);
+                let sessions_repo = repo_factory.create_sessions_repo(&*conn);
+                let child_accounts_repo = repo_factory.create_child_accounts_repo_with_sys_acl(&*conn);
+                let session = sessions_repo
+                    .find_by_hash(hash)?
+                    .ok_or_else(|| Error::InvalidToken.context("Session not found"))?;
+                let now = SystemTime::now();
+                if session.is_expired(now, idle_timeout) {
+                    return Err(Error::InvalidToken.context(format!("Session {} is expired", session.id)).into());
+                }
+                let client = find_session_client(&*clients_repo, session.client_id.clone())?;
+                // replica of the standby is not writable, idle time counts from the last touch on the primary
+                if !read_only {
+                    sessions_repo.touch(session.id, now)?;
+                }
+                let tokenpayload = JWTPayload {
+                    auth_time: session.auth_time,
+                    ..JWTPayload::new(session.user_id, 0, session.provider.clone())
+                }
+                .with_parent(child_accounts_repo.find_by_child(session.user_id)?)
+                .with_client(&client)
+                .with_analytics_id(analytics_id(&analytics, session.user_id));
+                Ok((session, client, tokenpayload))
+            })
+            .and_then(move |(session, client, tokenpayload)| {
+                service.spawn_on_shard(session.user_id, move |conn| {
+                    let users_repo = users_repo_factory.create_users_repo_with_sys_acl(&*conn);
+                    // sessions started before tokens of the user were revoked are revoked too
+                    let first_token_exp = session.created_at + Duration::from_secs(client.jwt_expiration_s as u64);
+                    let active = match users_repo.find(session.user_id)? {
+                        Some(ref user) => !user.is_blocked && first_token_exp > user.revoke_before,
+                        None => false,
+                    };
+                    if !active {
+                        return Err(Error::InvalidToken
+                            .context(format!("Session {} of user {} is revoked", session.id, session.user_id))
+                            .into());
+                    }
+                    encode(&Header::new(Algorithm::RS256), &tokenpayload, jwt_private_key.as_ref())
+                        .map_err(|e| {
+                            format_err!("{}", e)
+                                .context(Error::Parse)
+                                .context(format!("Couldn't encode jwt: {:?}.", tokenpayload))
+                                .into()
+                        })
+                        .map(|token| ResolvedSession {
+                            user_id: session.user_id,
+                            token,
+                        })
+                })
+            })
+            .map_err(|e: FailureError| e.context("Service sessions, resolve_session endpoint error occured.").into()),
+        )
+    }
+
+    /// Deletes session and expires its cookie
+    fn delete_session(&self, payload: SessionRequest) -> ServiceFuture<ClosedSession> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let conf = self.static_context.config.sessions.clone();
+        let hash = token_hash(&payload.session_id);
+
+        self.spawn_on_pool(move |conn| {
+            let sessions_repo = repo_factory.create_sessions_repo(&*conn);
+            // cookie is expired even if the session is already gone
+            if let Some(session) = sessions_repo.delete_by_hash(hash)? {
+                debug!("Deleted session {} of user {}", session.id, session.user_id);
+            }
+            Ok(ClosedSession {
+                set_cookie: http_only_cookie(&conf.cookie_name, "", &conf.cookie_path, 0),
+            })
+        })
+    }
+}
+
+/// Resolves registered client with session mode
+fn find_session_client(clients_repo: &ClientsRepo, client_id: String) -> RepoResult<Client> {
+    let client = clients_repo
+        .find(client_id.clone())?
+        .ok_or_else(|| Error::InvalidToken.context(format!("Client {} is not registered", client_id)))?;
+    if !client.session_mode {
+        return Err(Error::InvalidToken
+            .context(format!("Client {} does not use sessions", client_id))
+            .into());
+    }
+    Ok(client)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use chrono::Utc;
+    use tokio_core::reactor::Core;
+
+    use stq_static_resources::Provider;
+    use stq_types::UserId;
+
+    use models::*;
+    use repos::repo_factory::tests::*;
+    use services::sessions::SessionsService;
+
+    fn session_request(session_id: &str) -> SessionRequest {
+        SessionRequest {
+            session_id: session_id.to_string(),
+        }
+    }
+
+    fn client_payload(client_id: &str) -> JWTPayload {
+        JWTPayload::new(UserId(1), Utc::now().timestamp() + 60, Provider::Email).with_client(&create_client(client_id.to_string()))
+    }
+
+    #[test]
+    fn test_create_session() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let work = service.create_session(client_payload(MOCK_SESSION_CLIENT_ID));
+        let result = core.run(work).unwrap();
+        assert_eq!(result.set_cookie.starts_with("session_id="), true);
+        assert_eq!(result.set_cookie.contains("; HttpOnly"), true);
+    }
+
+    #[test]
+    fn test_create_session_for_token_client() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let work = service.create_session(client_payload(MOCK_CLIENT_ID));
+        assert_eq!(core.run(work).is_err(), true);
+    }
+
+    #[test]
+    fn test_resolve_session() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let work = service.resolve_session(session_request(MOCK_SESSION_ID));
+        let result = core.run(work).unwrap();
+        assert_eq!(result.user_id, UserId(1));
+    }
+
+    #[test]
+    fn test_resolve_idle_session() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let work = service.resolve_session(session_request(MOCK_IDLE_SESSION_ID));
+        assert_eq!(core.run(work).is_err(), true);
+    }
+
+    #[test]
+    fn test_delete_session() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let work = service.delete_session(session_request(MOCK_SESSION_ID));
+        let result = core.run(work).unwrap();
+        assert_eq!(
+            result.set_cookie,
+            "session_id=; Path=/; Max-Age=0; HttpOnly; Secure; SameSite=Strict"
+        );
+    }
+}
@@ -0,0 +1,227 @@
+//! Signed actions for one-click email links. Link token is HMAC over action, user, expiry and id,
+//! so no token row has to be persisted for every email sent. Ids of applied actions are recorded
+//! until expiry, so that a link can't be replayed.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::{decode_config, encode_config, URL_SAFE_NO_PAD};
+use chrono::Utc;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::future;
+use r2d2::ManageConnection;
+use uuid::Uuid;
+
+use stq_types::UserId;
+
+use super::util::{hmac_sign, hmac_verify};
+use errors::Error;
+use models::{NewSignedAction, NewUsedToken, SignedAction, SignedActionKind, SignedActionUrl, UpdateUser};
+use repos::repo_factory::ReposFactory;
+use repos::types::RepoResult;
+use services::types::ServiceFuture;
+use services::Service;
+
+const SIGNED_ACTION_DOMAIN: &'static str = "signed_action";
+
+fn signed_payload(action: SignedActionKind, user_id: UserId, exp: i64, id: &str) -> String {
+    format!("{}.{}.{}.{}", action, user_id, exp, id)
+}
+
+/// Signs action, token has `action.user_id.exp.id.signature` form and is url-safe
+pub fn sign(action: &SignedAction, key: &[u8]) -> String {
+    let payload = signed_payload(action.action, action.user_id, action.exp, &action.id);
+    let code = hmac_sign(key, format!("{}:{}", SIGNED_ACTION_DOMAIN, payload).as_bytes());
+    format!("{}.{}", payload, encode_config(&code, URL_SAFE_NO_PAD))
+}
+
+/// Checks signature and expiry of the token created by `sign`
+pub fn verify(token: &str, key: &[u8]) -> RepoResult<SignedAction> {
+    let invalid = || -> FailureError { format_err!("Signed action token is malformed").context(Error::InvalidToken).into() };
+
+    let parts = token.split('.').collect::<Vec<_>>();
+    if parts.len() != 5 || parts[3].is_empty() {
+        return Err(invalid());
+    }
+    let action = parts[0].parse::<SignedActionKind>().map_err(|_| invalid())?;
+    let user_id = parts[1].parse::<UserId>().map_err(|_| invalid())?;
+    let exp = parts[2].parse::<i64>().map_err(|_| invalid())?;
+    let id = parts[3].to_string();
+    let code = decode_config(parts[4], URL_SAFE_NO_PAD).map_err(|_| invalid())?;
+
+    let payload = signed_payload(action, user_id, exp, &id);
+    if !hmac_verify(key, format!("{}:{}", SIGNED_ACTION_DOMAIN, payload).as_bytes(), &code) {
+        return Err(format_err!("Signed action signature mismatch").context(Error::InvalidToken).into());
+    }
+    if exp < Utc::now().timestamp() {
+        return Err(format_err!("Signed action has expired").context(Error::InvalidToken).into());
+    }
+
+    Ok(SignedAction { action, user_id, exp, id })
+}
+
+pub trait SignedActionService {
+    /// Creates signed link for one-click email action
+    fn create_signed_action(&self, payload: NewSignedAction) -> ServiceFuture<SignedActionUrl>;
+    /// Verifies signed action and applies it. Actions owned by other flows are only verified
+    /// and returned to the caller.
+    fn apply_signed_action(&self, token: String) -> ServiceFuture<SignedAction>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > SignedActionService for Service<T, M, F>
+{
+    /// Creates signed link for one-click email action
+    fn create_signed_action(&self, payload: NewSignedAction) -> ServiceFuture<SignedActionUrl> {
+        if !self.dynamic_context.is_super_admin() {
+            return Box::new(future::err(Error::Forbidden.context("Cannot create signed action").into()));
+        }
+
+        let repo_factory = self.static_context.repo_factory.clone();
+        let key = self.static_context.hmac_key.clone();
+        let config = self.static_context.config.signed_actions.clone();
+
+        let fut = self.spawn_on_pool(move |conn| {
+            let users_repo = repo_factory.create_users_repo_with_sys_acl(&conn);
+            let user = users_repo
+                .find(payload.user_id)?
+                .ok_or_else(|| Error::NotFound.context(format!("User {} not found!", payload.user_id)))?;
+
+            let action = SignedAction {
+                action: payload.action,
+                user_id: user.id,
+                exp: Utc::now().timestamp() + config.expiration_s as i64,
+                id: Uuid::new_v4().simple().to_string(),
+            };
+            let token = sign(&action, &key);
+
+            Ok(SignedActionUrl {
+                url: format!("{}/{}?token={}", config.base_url, action.action, token),
+                token,
+                exp: action.exp,
+            })
+        });
+
+        Box::new(fut.map_err(|e: FailureError| e.context("Service signed_action, create endpoint error occured.").into()))
+    }
+
+    /// Verifies signed action and applies it, once
+    fn apply_signed_action(&self, token: String) -> ServiceFuture<SignedAction> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let key = self.static_context.hmac_key.clone();
+
+        let fut = self.spawn_on_pool(move |conn| {
+            let action = verify(&token, &key)?;
+            let users_repo = repo_factory.create_users_repo_with_sys_acl(&conn);
+            let used_tokens_repo = repo_factory.create_used_tokens_repo(&conn);
+
+            conn.transaction::<SignedAction, FailureError, _>(|| {
+                let user = users_repo
+                    .find(action.user_id)?
+                    .ok_or_else(|| Error::InvalidToken.context(format!("User {} not found!", action.user_id)))?;
+
+                let used = NewUsedToken {
+                    id: format!("{}:{}", SIGNED_ACTION_DOMAIN, action.id),
+                    expires_at: UNIX_EPOCH + Duration::from_secs(action.exp as u64),
+                };
+                used_tokens_repo.delete_expired(SystemTime::now())?;
+                if !used_tokens_repo.mark_used(used)? {
+                    return Err(format_err!("Signed action {} is already used", action.id)
+                        .context(Error::InvalidToken)
+                        .into());
+                }
+
+                debug!("Applying signed action {} for user {}", action.action, user.id);
+                if action.action == SignedActionKind::VerifyEmail && !user.email_verified {
+                    let update = UpdateUser {
+                        email_verified: Some(true),
+                        ..Default::default()
+                    };
+                    users_repo.update(user.id, update)?;
+                }
+
+                Ok(action)
+            })
+        });
+
+        Box::new(fut.map_err(|e: FailureError| e.context("Service signed_action, apply endpoint error occured.").into()))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use chrono::Utc;
+    use tokio_core::reactor::Core;
+
+    use stq_types::UserId;
+
+    use models::*;
+    use repos::repo_factory::tests::*;
+    use services::signed_action::{sign, verify, SignedActionService};
+
+    #[test]
+    fn test_signed_action_roundtrip() {
+        let action = SignedAction {
+            action: SignedActionKind::ApproveLogin,
+            user_id: UserId(1),
+            exp: Utc::now().timestamp() + 60,
+            id: "id".to_string(),
+        };
+        let token = sign(&action, b"key");
+        assert_eq!(verify(&token, b"key").unwrap(), action);
+        assert_eq!(verify(&token, b"another key").is_err(), true);
+        assert_eq!(verify(&token.replacen("approve_login", "verify_email", 1), b"key").is_err(), true);
+    }
+
+    #[test]
+    fn test_signed_action_expired() {
+        let action = SignedAction {
+            action: SignedActionKind::VerifyEmail,
+            user_id: UserId(1),
+            exp: Utc::now().timestamp() - 1,
+            id: "id".to_string(),
+        };
+        let token = sign(&action, b"key");
+        assert_eq!(verify(&token, b"key").is_err(), true);
+    }
+
+    #[test]
+    fn test_apply_signed_action() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.create_signed_action(NewSignedAction {
+            action: SignedActionKind::VerifyEmail,
+            user_id: UserId(1),
+        });
+        let signed = core.run(work).unwrap();
+        let work = service.apply_signed_action(signed.token);
+        let result = core.run(work).unwrap();
+        assert_eq!(result.action, SignedActionKind::VerifyEmail);
+        assert_eq!(result.user_id, UserId(1));
+    }
+
+    #[test]
+    fn test_apply_used_signed_action() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let action = SignedAction {
+            action: SignedActionKind::VerifyEmail,
+            user_id: UserId(1),
+            exp: Utc::now().timestamp() + 60,
+            id: MOCK_USED_TOKEN_ID.to_string(),
+        };
+        let token = sign(&action, &service.static_context.hmac_key);
+        let work = service.apply_signed_action(token);
+        assert_eq!(core.run(work).is_err(), true);
+    }
+}
@@ -0,0 +1,272 @@
+//! Single-flight coalescing of identical concurrent reads. The first request of a key runs
+//! the query, requests of the same key coming while it runs wait for its result, so that
+//! N simultaneous gateway requests result in one database query. Nothing is kept after
+//! the query is done, it is not a cache.
+//!
+//! Keys must contain everything the result depends on, the caller too, as repos check
+//! permissions of the caller.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::future;
+use futures::sync::oneshot;
+use futures::Future;
+
+use models::{FunnelStats, LoginStat, PublicStats, User};
+use services::types::ServiceFuture;
+
+type Waiters<V> = Vec<oneshot::Sender<Result<V, SharedError>>>;
+type InFlight<V> = Arc<Mutex<HashMap<String, Waiters<V>>>>;
+
+/// Error of the query passed to every coalesced request. The error chain is kept,
+/// so that coalesced requests are answered with the same status.
+#[derive(Clone)]
+struct SharedError(Arc<FailureError>);
+
+impl fmt::Debug for SharedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&*self.0, f)
+    }
+}
+
+impl fmt::Display for SharedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&*self.0, f)
+    }
+}
+
+impl Fail for SharedError {
+    fn cause(&self) -> Option<&Fail> {
+        Some(self.0.as_fail())
+    }
+}
+
+/// Snapshot of single-flight counters, `coalesced` requests did not query the database
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct SingleFlightStats {
+    pub calls: usize,
+    pub coalesced: usize,
+}
+
+#[derive(Debug, Default)]
+struct SingleFlightMetrics {
+    calls: AtomicUsize,
+    coalesced: AtomicUsize,
+}
+
+/// Coalesces concurrent queries of the same key, cloning is cheap
+pub struct SingleFlight<V> {
+    in_flight: InFlight<V>,
+    metrics: Arc<SingleFlightMetrics>,
+}
+
+impl<V> Default for SingleFlight<V> {
+    fn default() -> Self {
+        Self {
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+            metrics: Arc::new(SingleFlightMetrics::default()),
+        }
+    }
+}
+
+impl<V> Clone for SingleFlight<V> {
+    fn clone(&self) -> Self {
+        Self {
+            in_flight: self.in_flight.clone(),
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+impl<V: Clone + Send + 'static> SingleFlight<V> {
+    /// Runs query `f`, unless a query of the same key is running already, then waits for its result
+    pub fn run<F>(&self, key: String, f: F) -> ServiceFuture<V>
+    where
+        F: FnOnce() -> ServiceFuture<V> + 'static,
+    {
+        self.metrics.calls.fetch_add(1, Ordering::Relaxed);
+        let waiter = match self.in_flight.lock() {
+            Ok(mut in_flight) => {
+                let waiter = in_flight.get_mut(&key).map(|waiters| {
+                    let (sender, receiver) = oneshot::channel();
+                    waiters.push(sender);
+                    receiver
+                });
+                if waiter.is_none() {
+                    in_flight.insert(key.clone(), Vec::new());
+                }
+                waiter
+            }
+            Err(_) => {
+                warn!("Single-flight lock is poisoned, query {} is not coalesced", key);
+                return f();
+            }
+        };
+
+        if let Some(waiter) = waiter {
+            self.metrics.coalesced.fetch_add(1, Ordering::Relaxed);
+            return Box::new(waiter.then(move |result| -> ServiceFuture<V> {
+                match result {
+                    Ok(Ok(value)) => Box::new(future::ok(value)),
+                    Ok(Err(e)) => Box::new(future::err(e.into())),
+                    // the first request was dropped before its query was done
+                    Err(_) => f(),
+                }
+            }));
+        }
+
+        let flight = Flight {
+            key: Some(key),
+            in_flight: self.in_flight.clone(),
+        };
+        Box::new(f().then(move |result| {
+            let result = result.map_err(|e| SharedError(Arc::new(e)));
+            for waiter in flight.land() {
+                let _ = waiter.send(result.clone());
+            }
+            result.map_err(FailureError::from)
+        }))
+    }
+
+    pub fn stats(&self) -> SingleFlightStats {
+        SingleFlightStats {
+            calls: self.metrics.calls.load(Ordering::Relaxed),
+            coalesced: self.metrics.coalesced.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Running query of the key, the key is released even if the query future is dropped,
+/// then waiting requests run queries of their own
+struct Flight<V> {
+    key: Option<String>,
+    in_flight: InFlight<V>,
+}
+
+impl<V> Flight<V> {
+    /// Releases the key and returns requests waiting for the result
+    fn land(mut self) -> Waiters<V> {
+        self.release().unwrap_or_default()
+    }
+
+    fn release(&mut self) -> Option<Waiters<V>> {
+        let key = self.key.take()?;
+        self.in_flight.lock().ok().and_then(|mut in_flight| in_flight.remove(&key))
+    }
+}
+
+impl<V> Drop for Flight<V> {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+/// Coalesced reads, one group per query kind
+#[derive(Clone, Default)]
+pub struct SingleFlights {
+    pub users: SingleFlight<Option<User>>,
+    pub login_stats: SingleFlight<Vec<LoginStat>>,
+    pub funnel_stats: SingleFlight<FunnelStats>,
+    pub public_stats: SingleFlight<PublicStats>,
+}
+
+impl SingleFlights {
+    /// Counters of every group by group name
+    pub fn stats(&self) -> BTreeMap<&'static str, SingleFlightStats> {
+        let mut stats = BTreeMap::new();
+        stats.insert("users", self.users.stats());
+        stats.insert("login_stats", self.login_stats.stats());
+        stats.insert("funnel_stats", self.funnel_stats.stats());
+        stats.insert("public_stats", self.public_stats.stats());
+        stats
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use failure::Context;
+    use futures::sync::oneshot;
+    use futures::Future;
+
+    use super::*;
+    use errors::Error;
+
+    /// Query resolved by the returned sender, counts how many times it was run
+    fn pending_query(runs: &Rc<Cell<usize>>) -> (oneshot::Sender<Result<i64, FailureError>>, impl FnOnce() -> ServiceFuture<i64>) {
+        let (sender, receiver) = oneshot::channel();
+        let runs = runs.clone();
+        let query = move || -> ServiceFuture<i64> {
+            runs.set(runs.get() + 1);
+            Box::new(receiver.then(|result| result.unwrap()))
+        };
+        (sender, query)
+    }
+
+    fn counted_query(runs: &Rc<Cell<usize>>, value: i64) -> impl FnOnce() -> ServiceFuture<i64> {
+        let runs = runs.clone();
+        move || -> ServiceFuture<i64> {
+            runs.set(runs.get() + 1);
+            Box::new(future::ok(value))
+        }
+    }
+
+    #[test]
+    fn test_concurrent_reads_are_coalesced() {
+        let single_flight = SingleFlight::<i64>::default();
+        let runs = Rc::new(Cell::new(0));
+        let (sender, query) = pending_query(&runs);
+        let first = single_flight.run("count".to_string(), query);
+        let second = single_flight.run("count".to_string(), counted_query(&runs, 2));
+        sender.send(Ok(1)).unwrap();
+
+        assert_eq!(first.join(second).wait().unwrap(), (1, 1));
+        assert_eq!(runs.get(), 1);
+        assert_eq!(single_flight.stats(), SingleFlightStats { calls: 2, coalesced: 1 });
+
+        let third = single_flight.run("count".to_string(), counted_query(&runs, 3));
+        assert_eq!(third.wait().unwrap(), 3);
+        assert_eq!(runs.get(), 2);
+    }
+
+    #[test]
+    fn test_coalesced_error_keeps_chain() {
+        let single_flight = SingleFlight::<i64>::default();
+        let runs = Rc::new(Cell::new(0));
+        let (sender, query) = pending_query(&runs);
+        let first = single_flight.run("count".to_string(), query);
+        let second = single_flight.run("count".to_string(), counted_query(&runs, 2));
+        sender.send(Err(Error::NotFound.context("User not found").into())).unwrap();
+
+        assert_eq!(first.wait().is_err(), true);
+        let error = second.wait().unwrap_err();
+        let not_found = error.iter_chain().any(|cause| match cause.downcast_ref::<Context<Error>>() {
+            Some(context) => match *context.get_context() {
+                Error::NotFound => true,
+                _ => false,
+            },
+            None => false,
+        });
+        assert_eq!(not_found, true);
+    }
+
+    #[test]
+    fn test_dropped_read_is_not_awaited() {
+        let single_flight = SingleFlight::<i64>::default();
+        let runs = Rc::new(Cell::new(0));
+        let (_sender, query) = pending_query(&runs);
+        let first = single_flight.run("count".to_string(), query);
+        let second = single_flight.run("count".to_string(), counted_query(&runs, 2));
+        drop(first);
+
+        assert_eq!(second.wait().unwrap(), 2);
+        assert_eq!(runs.get(), 2);
+    }
+}
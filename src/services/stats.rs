@@ -0,0 +1,220 @@
+//! Stats Services, disclosure control of stats exposed outside of admin tools. Every published
+//! count gets Laplace noise of scale `1 / epsilon`, noisy counts smaller than `min_cohort` are
+//! withheld, so that users of small cohorts can't be re-identified by comparing nearby queries.
+//!
+//! Noise is calibrated for counts every user adds at most one to. Noise of a count is derived
+//! from the query and the count by HMAC with the noise key, repeating the query gives the same
+//! numbers, so noise can't be averaged out by asking many times.
+
+use std::f64;
+
+use chrono::{Duration, NaiveDate};
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::future;
+use r2d2::ManageConnection;
+use rand::{Rng, SeedableRng, XorShiftRng};
+
+use config;
+use errors::Error;
+use models::{FunnelStep, PublicFunnelStep, PublicLoginStat, PublicStats, StatsGranularity};
+use repos::ReposFactory;
+use services::login_stats::aggregate_login_stats;
+use services::types::ServiceFuture;
+use services::util::hmac_sign;
+use services::Service;
+
+pub trait StatsService {
+    /// Returns monthly logins per provider and registration funnel from `from` to `to` inclusive,
+    /// safe to be shown to anyone
+    fn get_public_stats(&self, from: NaiveDate, to: NaiveDate) -> ServiceFuture<PublicStats>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > StatsService for Service<T, M, F>
+{
+    /// Returns monthly logins per provider and registration funnel from `from` to `to` inclusive,
+    /// safe to be shown to anyone
+    fn get_public_stats(&self, from: NaiveDate, to: NaiveDate) -> ServiceFuture<PublicStats> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let conf = self.static_context.config.public_stats.clone();
+
+        if to < from || to - from >= Duration::days(conf.max_days) {
+            return Box::new(future::err(
+                Error::Validate(validation_errors!({"to": ["range" => "Date range is out of range"]})).into(),
+            ));
+        }
+
+        let service = self.clone();
+
+        debug!("Fetching public stats from {} to {}", from, to);
+
+        // the result is the same for every caller
+        let key = format!("{}:{}", from, to);
+        self.static_context.single_flights.public_stats.run(key, move || {
+            service.spawn_on_pool(move |conn| {
+                let login_stats_repo = repo_factory.create_login_stats_repo_with_sys_acl(&*conn);
+                let funnel_events_repo = repo_factory.create_funnel_events_repo_with_sys_acl(&*conn);
+                login_stats_repo
+                    .list(from, to)
+                    .and_then(|logins| {
+                        FunnelStep::all()
+                            .into_iter()
+                            .map(|step| funnel_events_repo.count_cohort_step(from, to, step).map(|users| (step, users)))
+                            .collect::<Result<Vec<_>, _>>()
+                            .map(|steps| (logins, steps))
+                    })
+                    .map(|(logins, steps)| {
+                        let query = format!("{}:{}", from, to);
+                        PublicStats {
+                            from,
+                            to,
+                            logins: aggregate_login_stats(logins, StatsGranularity::Month)
+                                .into_iter()
+                                .map(|stat| PublicLoginStat {
+                                    logins: publish_count(&conf, &format!("{}:logins:{}:{}", query, stat.date, stat.provider), stat.logins),
+                                    date: stat.date,
+                                    provider: stat.provider,
+                                })
+                                .collect(),
+                            funnel: public_funnel(&conf, &query, &steps),
+                        }
+                    })
+                    .map_err(|e: FailureError| e.context("Service stats, get_public_stats endpoint error occured.").into())
+            })
+        })
+    }
+}
+
+/// Noisy count of the cell, `None` if it is smaller than `min_cohort`. The noise is the same
+/// on every call with the same `cell`, cells must name the query and the count.
+pub fn publish_count(conf: &config::PublicStats, cell: &str, count: i64) -> Option<i64> {
+    let mut rng = cell_rng(&conf.noise_key, cell);
+    let noisy = (count as f64 + laplace_noise(&mut rng, 1.0 / conf.epsilon)).round() as i64;
+    if noisy < conf.min_cohort {
+        None
+    } else {
+        Some(noisy)
+    }
+}
+
+/// Sample of zero centered Laplace distribution, by inverse transform of a uniform sample
+pub fn laplace_noise<R: Rng>(rng: &mut R, scale: f64) -> f64 {
+    let u = rng.gen::<f64>() - 0.5;
+    -scale * u.signum() * (1.0 - 2.0 * u.abs()).max(f64::MIN_POSITIVE).ln()
+}
+
+/// Random generator of the cell seeded by HMAC of the cell name
+fn cell_rng(noise_key: &str, cell: &str) -> XorShiftRng {
+    let code = hmac_sign(noise_key.as_bytes(), cell.as_bytes());
+    let mut seed = [0u32; 4];
+    for (word, bytes) in seed.iter_mut().zip(code.chunks(4)) {
+        *word = bytes.iter().fold(0, |word, &byte| word << 8 | u32::from(byte));
+    }
+    // xorshift can't be seeded by zeros only
+    seed[0] |= 1;
+    XorShiftRng::from_seed(seed)
+}
+
+/// Funnel of noisy counts, conversion is computed from published counts only
+fn public_funnel(conf: &config::PublicStats, query: &str, steps: &[(FunnelStep, i64)]) -> Vec<PublicFunnelStep> {
+    let published = steps
+        .iter()
+        .map(|&(step, users)| (step, publish_count(conf, &format!("{}:funnel:{}", query, step), users)))
+        .collect::<Vec<_>>();
+    let registered = published.first().and_then(|&(_, users)| users);
+
+    published
+        .into_iter()
+        .map(|(step, users)| PublicFunnelStep {
+            step,
+            users,
+            conversion: match (users, registered) {
+                (Some(users), Some(registered)) => Some((users as f64 / registered as f64).min(1.0)),
+                _ => None,
+            },
+        })
+        .collect()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use chrono::NaiveDate;
+    use tokio_core::reactor::Core;
+
+    use stq_static_resources::Provider;
+
+    use config;
+    use models::FunnelStep;
+    use repos::repo_factory::tests::*;
+    use services::stats::*;
+
+    fn create_conf() -> config::PublicStats {
+        config::PublicStats {
+            min_cohort: 10,
+            epsilon: 1.0,
+            noise_key: "noise key".to_string(),
+            max_days: 366,
+        }
+    }
+
+    #[test]
+    fn test_publish_count_is_stable() {
+        let conf = create_conf();
+        let count = publish_count(&conf, "2019-02-01:2019-02-28:funnel:first_login", 500);
+        assert_eq!(count, publish_count(&conf, "2019-02-01:2019-02-28:funnel:first_login", 500));
+        let count = count.unwrap();
+        assert!(count > 480 && count < 520);
+    }
+
+    #[test]
+    fn test_small_cohorts_are_withheld() {
+        let conf = create_conf();
+        assert_eq!(publish_count(&conf, "2019-02-01:2019-02-28:funnel:first_login", 2), None);
+        assert_eq!(publish_count(&conf, "2019-02-01:2019-02-28:funnel:first_login", 0), None);
+    }
+
+    #[test]
+    fn test_get_public_stats() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let work = service.get_public_stats(NaiveDate::from_ymd(2019, 2, 1), NaiveDate::from_ymd(2019, 2, 28));
+        let result = core.run(work).unwrap();
+        assert_eq!(
+            result.logins,
+            vec![
+                PublicLoginStat {
+                    date: NaiveDate::from_ymd(2019, 2, 1),
+                    provider: Provider::Email,
+                    logins: None,
+                },
+                PublicLoginStat {
+                    date: NaiveDate::from_ymd(2019, 2, 1),
+                    provider: Provider::Google,
+                    logins: None,
+                },
+            ]
+        );
+        assert_eq!(result.funnel.len(), 4);
+        assert_eq!(result.funnel[3].step, FunnelStep::FirstLogin);
+        assert_eq!(result.funnel.iter().all(|step| step.users.is_some()), true);
+    }
+
+    #[test]
+    fn test_get_public_stats_of_long_range() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let work = service.get_public_stats(NaiveDate::from_ymd(2017, 1, 1), NaiveDate::from_ymd(2019, 1, 1));
+        assert_eq!(core.run(work).is_err(), true);
+    }
+}
@@ -0,0 +1,54 @@
+//! Guard for unauthenticated token-apply endpoints. Counts invalid token attempts
+//! per client IP and email, and locks further attempts once the limit is reached.
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use failure::Fail;
+
+use errors::Error;
+use repos::attempts_cache::AttemptsCache;
+use repos::types::RepoResult;
+
+pub struct TokenAttemptsGuard {
+    attempts: Arc<AttemptsCache>,
+    max_attempts: u32,
+    keys: Vec<String>,
+}
+
+impl TokenAttemptsGuard {
+    pub fn new(attempts: Arc<AttemptsCache>, max_attempts: u32, client_ip: Option<IpAddr>) -> Self {
+        Self {
+            attempts,
+            max_attempts,
+            keys: client_ip.map(|ip| format!("ip:{}", ip)).into_iter().collect(),
+        }
+    }
+
+    /// Starts counting attempts for the email as well, once it is known from the token
+    pub fn add_email(&mut self, email: &str) {
+        self.keys.push(format!("email:{}", email));
+    }
+
+    /// Fails if attempts limit is reached for the client IP or email
+    pub fn check(&self) -> RepoResult<()> {
+        match self.keys.iter().find(|key| self.attempts.get(key) >= self.max_attempts) {
+            Some(key) => Err(format_err!("Too many invalid token attempts for {}", key)
+                .context(Error::TooManyAttempts)
+                .into()),
+            None => Ok(()),
+        }
+    }
+
+    pub fn failed(&self) {
+        for key in &self.keys {
+            let attempts = self.attempts.increment(key);
+            warn!("Invalid token attempt {} for {}", attempts, key);
+        }
+    }
+
+    pub fn succeeded(&self) {
+        for key in &self.keys {
+            self.attempts.reset(key);
+        }
+    }
+}
@@ -1,10 +1,15 @@
+use std::sync::Arc;
+
 use diesel::connection::AnsiTransactionManager;
 use diesel::pg::Pg;
 use diesel::Connection;
 use failure::Error as FailureError;
 use failure::Fail;
+use futures::future;
 use futures::Future;
-use r2d2::{ManageConnection, PooledConnection};
+use r2d2::{ManageConnection, Pool, PooledConnection};
+
+use stq_types::UserId;
 
 use controller::context::{DynamicContext, StaticContext};
 use errors::Error;
@@ -13,15 +18,16 @@ use repos::repo_factory::*;
 /// Service layer Future
 pub type ServiceFuture<T> = Box<Future<Item = T, Error = FailureError>>;
 
-/// Service
+/// Service is a cheap facade over shared static context and per-request dynamic context,
+/// so that cloning it into futures does not copy config, pools or keys
 pub struct Service<T, M, F>
 where
     T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
     M: ManageConnection<Connection = T>,
     F: ReposFactory<T>,
 {
-    pub static_context: StaticContext<T, M, F>,
-    pub dynamic_context: DynamicContext,
+    pub static_context: Arc<StaticContext<T, M, F>>,
+    pub dynamic_context: Arc<DynamicContext>,
 }
 
 impl<
@@ -31,21 +37,61 @@ impl<
     > Service<T, M, F>
 {
     /// Create a new service
-    pub fn new(static_context: StaticContext<T, M, F>, dynamic_context: DynamicContext) -> Self {
+    pub fn new(static_context: Arc<StaticContext<T, M, F>>, dynamic_context: DynamicContext) -> Self {
         Self {
             static_context,
-            dynamic_context,
+            dynamic_context: Arc::new(dynamic_context),
         }
     }
 
+    /// Runs `f` with connection to the primary shard
     pub fn spawn_on_pool<R, Func>(&self, f: Func) -> ServiceFuture<R>
     where
         Func: FnOnce(PooledConnection<M>) -> Result<R, FailureError> + Send + 'static,
         R: Send + 'static,
     {
-        let db_pool = self.static_context.db_pool.clone();
-        let cpu_pool = self.static_context.cpu_pool.clone();
-        Box::new(cpu_pool.spawn_fn(move || db_pool.get().map_err(|e| e.context(Error::Connection).into()).and_then(f)))
+        let db_pool = self.static_context.db_pool.primary().clone();
+        self.spawn_on(db_pool, f)
+    }
+
+    /// Runs `f` with connection to the shard owning user data
+    pub fn spawn_on_shard<R, Func>(&self, user_id: UserId, f: Func) -> ServiceFuture<R>
+    where
+        Func: FnOnce(PooledConnection<M>) -> Result<R, FailureError> + Send + 'static,
+        R: Send + 'static,
+    {
+        let db_pool = self.static_context.db_pool.for_user(user_id).clone();
+        self.spawn_on(db_pool, f)
+    }
+
+    /// Runs `f` on every shard, results are returned in shard order
+    pub fn spawn_on_all_shards<R, Func>(&self, f: Func) -> ServiceFuture<Vec<R>>
+    where
+        Func: Fn(PooledConnection<M>) -> Result<R, FailureError> + Send + Sync + 'static,
+        R: Send + 'static,
+    {
+        let f = Arc::new(f);
+        let shards = self
+            .static_context
+            .db_pool
+            .shards()
+            .iter()
+            .map(|db_pool| {
+                let f = f.clone();
+                self.spawn_on(db_pool.clone(), move |conn| f(conn))
+            })
+            .collect::<Vec<_>>();
+        Box::new(future::join_all(shards))
+    }
+
+    fn spawn_on<R, Func>(&self, db_pool: Pool<M>, f: Func) -> ServiceFuture<R>
+    where
+        Func: FnOnce(PooledConnection<M>) -> Result<R, FailureError> + Send + 'static,
+        R: Send + 'static,
+    {
+        self.static_context.work_pool.spawn_fn(self.dynamic_context.work_priority, move || {
+            db_pool.get().map_err(|e| e.context(Error::Connection).into()).and_then(f)
+        })
     }
 }
 
@@ -0,0 +1,229 @@
+//! User import Services, creates users migrated from other systems along with their password
+//! hashes, so that they log in with their old passwords. Hashes keep their format tag and are
+//! verified with the matching algorithm until the first login, when the password is re-hashed
+//! by `password_create`. Allowed to superusers only, every import is recorded in the audit log.
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::future;
+use futures::Future;
+use r2d2::ManageConnection;
+use r2d2::PooledConnection;
+use serde_json;
+
+use stq_static_resources::Provider;
+
+use errors::Error;
+use models::{normalize_email, AuditAction, Identity, ImportedUser, NewAuditEvent, NewUser, UpdateUser, User};
+use repos::ReposFactory;
+use services::types::ServiceFuture;
+use services::users::{allocate_user_id, with_email_lock};
+use services::Service;
+
+pub trait UserImportService {
+    /// Creates user with password hash imported from another system
+    fn import_user(&self, payload: ImportedUser) -> ServiceFuture<User>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > UserImportService for Service<T, M, F>
+{
+    /// Creates user with password hash imported from another system
+    fn import_user(&self, payload: ImportedUser) -> ServiceFuture<User> {
+        let actor_id = match self.dynamic_context.user_id {
+            Some(current_uid) => current_uid,
+            None => return Box::new(future::err(Error::Forbidden.context("Only admins can import users").into())),
+        };
+        if !payload.password_hash_format.is_valid_hash(&payload.password_hash) {
+            return Box::new(future::err(
+                Error::Validate(validation_errors!({"password_hash": ["format" => "Password hash does not match its format"]})).into(),
+            ));
+        }
+        // identities are looked up by lowercase e-mail, same as for registrations
+        let payload = ImportedUser {
+            email: normalize_email(&payload.email),
+            ..payload
+        };
+        let repo_factory = self.static_context.repo_factory.clone();
+        let audit_repo_factory = self.static_context.repo_factory.clone();
+        let data_residency = self.static_context.config.data_residency.clone();
+        let db_pool = self.static_context.db_pool.clone();
+        let service = self.clone();
+        let audit_service = self.clone();
+        let format = payload.password_hash_format;
+
+        info!(
+            "Importing user with {} password hash by user {}",
+            payload.password_hash_format, actor_id
+        );
+
+        let imported = allocate_user_id(self, payload.email.clone()).and_then(move |new_user_id| {
+            let import = move |conn: PooledConnection<M>| -> Result<User, FailureError> {
+                let users_repo = repo_factory.create_users_repo(&conn, Some(actor_id));
+                let users_repo_with_sys_acl = repo_factory.create_users_repo_with_sys_acl(&conn);
+                let ident_repo = repo_factory.create_identities_repo(&conn);
+
+                // identities are unique by e-mail, same as for registrations
+                with_email_lock(&db_pool, &repo_factory, &*conn, payload.email.clone(), move || {
+                    let mut new_user = NewUser::from(&payload);
+                    new_user.id = new_user_id;
+                    new_user.data_region = Some(data_residency.region_for(None));
+                    let saga_id = new_user.saga_id.clone();
+                    let user = users_repo.create(new_user)?;
+                    ident_repo.restore(Identity {
+                        user_id: user.id,
+                        email: payload.email,
+                        password: Some(payload.password_hash),
+                        provider: Provider::Email,
+                        saga_id,
+                        must_change_password: payload.must_change_password,
+                        password_hash_format: Some(payload.password_hash_format),
+                    })?;
+
+                    if payload.email_verified {
+                        let update = UpdateUser {
+                            email_verified: Some(true),
+                            ..Default::default()
+                        };
+                        users_repo_with_sys_acl.update(user.id, update)
+                    } else {
+                        Ok(user)
+                    }
+                })
+            };
+
+            match new_user_id {
+                Some(user_id) => service.spawn_on_shard(user_id, import),
+                None => service.spawn_on_pool(import),
+            }
+        });
+
+        // audit log is kept on the primary shard
+        Box::new(
+            imported
+                .and_then(move |user| {
+                    audit_service.spawn_on_pool(move |conn| {
+                        let audit_log_repo = audit_repo_factory.create_audit_log_repo_with_sys_acl(&conn);
+                        let mut data = serde_json::Map::new();
+                        data.insert("password_hash_format".to_string(), serde_json::to_value(format)?);
+                        audit_log_repo.add(NewAuditEvent {
+                            user_id: user.id,
+                            actor_id: Some(actor_id),
+                            action: AuditAction::UserImported,
+                            data: Some(data.into()),
+                        })?;
+                        Ok(user)
+                    })
+                })
+                .map_err(|e: FailureError| e.context("Service user_import, import_user endpoint error occured.").into()),
+        )
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use bcrypt;
+    use tokio_core::reactor::Core;
+
+    use stq_types::UserId;
+
+    use models::PasswordHashFormat;
+    use repos::repo_factory::tests::*;
+    use services::util::{imported_password_verify, password_create, stored_password_verify};
+
+    use super::*;
+
+    fn imported_user(password_hash: &str, password_hash_format: PasswordHashFormat) -> ImportedUser {
+        ImportedUser {
+            email: "imported@example.com".to_string(),
+            password_hash: password_hash.to_string(),
+            password_hash_format,
+            first_name: None,
+            last_name: None,
+            email_verified: true,
+            must_change_password: false,
+        }
+    }
+
+    #[test]
+    fn test_import_user_with_invalid_hash() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.import_user(imported_user("not a hash", PasswordHashFormat::Bcrypt));
+        assert_eq!(core.run(work).is_err(), true);
+    }
+
+    #[test]
+    fn test_import_user_normalizes_email() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let hash = bcrypt::hash("password", 4).unwrap();
+        let payload = ImportedUser {
+            email: "Imported@Example.COM".to_string(),
+            email_verified: false,
+            ..imported_user(&hash, PasswordHashFormat::Bcrypt)
+        };
+        let work = service.import_user(payload);
+        let result = core.run(work).unwrap();
+        assert_eq!(result.email, "imported@example.com");
+    }
+
+    #[test]
+    fn test_import_user_by_anonymous() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let hash = bcrypt::hash("password", 4).unwrap();
+        let work = service.import_user(imported_user(&hash, PasswordHashFormat::Bcrypt));
+        assert_eq!(core.run(work).is_err(), true);
+    }
+
+    #[test]
+    fn test_imported_password_verify() {
+        let bcrypt_hash = bcrypt::hash("password", 4).unwrap();
+        assert_eq!(PasswordHashFormat::Bcrypt.is_valid_hash(&bcrypt_hash), true);
+        assert_eq!(
+            imported_password_verify(&bcrypt_hash, PasswordHashFormat::Bcrypt, "password".to_string()).unwrap(),
+            true
+        );
+        assert_eq!(
+            imported_password_verify(&bcrypt_hash, PasswordHashFormat::Bcrypt, "wrong".to_string()).unwrap(),
+            false
+        );
+
+        let sha1_hash = "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8";
+        assert_eq!(PasswordHashFormat::Sha1.is_valid_hash(sha1_hash), true);
+        assert_eq!(
+            imported_password_verify(sha1_hash, PasswordHashFormat::Sha1, "password".to_string()).unwrap(),
+            true
+        );
+        assert_eq!(
+            imported_password_verify(sha1_hash, PasswordHashFormat::Sha1, "wrong".to_string()).unwrap(),
+            false
+        );
+    }
+
+    #[test]
+    fn test_stored_password_verify() {
+        let sha1_hash = "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8";
+        assert_eq!(
+            stored_password_verify(sha1_hash, Some(PasswordHashFormat::Sha1), "password".to_string()).unwrap(),
+            true
+        );
+        assert_eq!(stored_password_verify(sha1_hash, None, "password".to_string()).is_err(), true);
+
+        let native_hash = password_create("password".to_string());
+        assert_eq!(stored_password_verify(&native_hash, None, "password".to_string()).unwrap(), true);
+        assert_eq!(stored_password_verify(&native_hash, None, "wrong".to_string()).unwrap(), false);
+    }
+}
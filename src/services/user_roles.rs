@@ -1,23 +1,40 @@
-//! UserRoles Services, presents CRUD operations with user_roles
+//! UserRoles Services, presents CRUD operations with user_roles. Roles may be granted until
+//! `expires_at`, expired roles are ignored right away and revoked by a background check.
+//! Grants of sensitive roles are pending until a second admin approves them.
+
+use std::io;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
 
 use diesel::connection::AnsiTransactionManager;
 use diesel::pg::Pg;
 use diesel::Connection;
 use failure::Error as FailureError;
-use r2d2::ManageConnection;
+use failure::Fail;
+use futures::future;
+use r2d2::{ManageConnection, Pool};
+use serde_json;
+use uuid::Uuid;
 
 use stq_types::{RoleId, UserId, UsersRole};
 
-use models::{NewUserRole, RemoveUserRole, UserRole};
-use repos::ReposFactory;
+use config::DomainRoles;
+use errors::Error;
+use models::{AuditAction, NewAuditEvent, NewRoleRequest, NewUserRole, RemoveUserRole, RoleRequest, User, UserRole, UserRoleGrant};
+use repos::sharding::ShardedPool;
+use repos::{AuditLogRepo, ReposFactory, RoleRequestsRepo, UserRolesRepo};
 use services::types::ServiceFuture;
 use services::Service;
 
 pub trait UserRolesService {
     /// Returns role by user ID
     fn get_roles(&self, user_id: UserId) -> ServiceFuture<Vec<UsersRole>>;
-    /// Creates new user_role
-    fn create_user_role(&self, payload: NewUserRole) -> ServiceFuture<UserRole>;
+    /// Creates new user_role, grants of sensitive roles are pending until approved
+    fn create_user_role(&self, payload: NewUserRole) -> ServiceFuture<UserRoleGrant>;
+    /// Returns grants of sensitive roles waiting for approval
+    fn get_role_requests(&self) -> ServiceFuture<Vec<RoleRequest>>;
+    /// Approves the grant requested by another admin and creates the role
+    fn approve_role_request(&self, id: Uuid) -> ServiceFuture<UserRole>;
     /// Remove user_role
     fn delete_user_role(&self, payload: RemoveUserRole) -> ServiceFuture<UserRole>;
     /// Deletes roles for user
@@ -45,15 +62,102 @@ impl<
         })
     }
 
-    /// Creates new user_role
-    fn create_user_role(&self, new_user_role: NewUserRole) -> ServiceFuture<UserRole> {
+    /// Creates new user_role, grants of sensitive roles are pending until approved
+    fn create_user_role(&self, new_user_role: NewUserRole) -> ServiceFuture<UserRoleGrant> {
         let current_uid = self.dynamic_context.user_id;
         let repo_factory = self.static_context.repo_factory.clone();
+        let is_sensitive = self
+            .static_context
+            .config
+            .role_approvals
+            .sensitive_roles
+            .contains(&new_user_role.name);
+
+        if new_user_role.expires_at.map_or(false, |expires_at| expires_at <= SystemTime::now()) {
+            return Box::new(future::err(
+                Error::Validate(validation_errors!({"expires_at": ["past" => "Role can't expire in the past"]})).into(),
+            ));
+        }
+
+        if !is_sensitive {
+            return self.spawn_on_pool(move |conn| {
+                let user_roles_repo = repo_factory.create_user_roles_repo(&*conn, current_uid);
+                conn.transaction::<UserRole, FailureError, _>(move || user_roles_repo.create(new_user_role))
+                    .map(UserRoleGrant::Granted)
+                    .map_err(|e: FailureError| e.context("Service user_roles, create endpoint error occured.").into())
+            });
+        }
+
+        let requested_by = match current_uid {
+            Some(current_uid) => current_uid,
+            None => {
+                return Box::new(future::err(
+                    Error::Forbidden.context("Only admins can request sensitive roles").into(),
+                ))
+            }
+        };
 
         self.spawn_on_pool(move |conn| {
+            let role_requests_repo = repo_factory.create_role_requests_repo(&*conn, current_uid);
+            let audit_log_repo = repo_factory.create_audit_log_repo(&*conn, current_uid);
+            conn.transaction::<RoleRequest, FailureError, _>(move || {
+                request_role(&*role_requests_repo, &*audit_log_repo, new_user_role, requested_by)
+            })
+            .map(UserRoleGrant::Pending)
+            .map_err(|e: FailureError| e.context("Service user_roles, create endpoint error occured.").into())
+        })
+    }
+
+    /// Returns grants of sensitive roles waiting for approval
+    fn get_role_requests(&self) -> ServiceFuture<Vec<RoleRequest>> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let role_requests_repo = repo_factory.create_role_requests_repo(&*conn, current_uid);
+            role_requests_repo
+                .list_pending()
+                .map_err(|e: FailureError| e.context("Service user_roles, get_role_requests endpoint error occured.").into())
+        })
+    }
+
+    /// Approves the grant requested by another admin and creates the role
+    fn approve_role_request(&self, id: Uuid) -> ServiceFuture<UserRole> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+        let request_ttl = Duration::from_secs(self.static_context.config.role_approvals.request_ttl_s);
+
+        let approved_by = match current_uid {
+            Some(current_uid) => current_uid,
+            None => return Box::new(future::err(Error::Forbidden.context("Only admins can approve roles").into())),
+        };
+
+        self.spawn_on_pool(move |conn| {
+            let role_requests_repo = repo_factory.create_role_requests_repo(&*conn, current_uid);
             let user_roles_repo = repo_factory.create_user_roles_repo(&*conn, current_uid);
-            conn.transaction::<UserRole, FailureError, _>(move || user_roles_repo.create(new_user_role))
-                .map_err(|e: FailureError| e.context("Service user_roles, create endpoint error occured.").into())
+            let audit_log_repo = repo_factory.create_audit_log_repo(&*conn, current_uid);
+            conn.transaction::<UserRole, FailureError, _>(move || {
+                let request = role_requests_repo
+                    .find_for_update(id)?
+                    .ok_or_else(|| format_err!("Role request {} not found", id).context(Error::NotFound))?;
+                check_role_request(&request, approved_by, request_ttl, SystemTime::now())?;
+                let mut new_user_role = request.new_user_role();
+                new_user_role.id = Some(RoleId::new());
+                let role = user_roles_repo.create(new_user_role)?;
+                role_requests_repo.approve(id, approved_by)?;
+                audit_log_repo.add(NewAuditEvent {
+                    user_id: request.user_id,
+                    actor_id: Some(approved_by),
+                    action: AuditAction::RoleRequestApproved,
+                    data: Some(role_request_data(&request)?),
+                })?;
+                info!(
+                    "Role {:?} of user {} requested by user {} approved by user {}",
+                    request.name, request.user_id, request.requested_by, approved_by
+                );
+                Ok(role)
+            })
+            .map_err(|e: FailureError| e.context("Service user_roles, approve_role_request endpoint error occured.").into())
         })
     }
 
@@ -95,3 +199,265 @@ impl<
         })
     }
 }
+
+/// Assigns roles configured for domain of the user email, roles the user already has are skipped.
+/// Every assigned role is recorded in the audit log. Must be called in the transaction verifying the email.
+pub fn assign_domain_roles(
+    user_roles_repo: &UserRolesRepo,
+    audit_log_repo: &AuditLogRepo,
+    domain_roles: &DomainRoles,
+    user: &User,
+) -> Result<Vec<UsersRole>, FailureError> {
+    let roles = domain_roles.roles_for(&user.email);
+    if roles.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let existing = user_roles_repo.list_for_user(user.id)?;
+    let mut assigned = vec![];
+    for role in roles {
+        if existing.contains(&role) {
+            continue;
+        }
+        user_roles_repo.create(NewUserRole {
+            id: Some(RoleId::new()),
+            user_id: user.id,
+            name: role.clone(),
+            data: None,
+            expires_at: None,
+        })?;
+        let mut data = serde_json::Map::new();
+        data.insert("role".to_string(), serde_json::to_value(&role)?);
+        data.insert("reason".to_string(), "email_domain".into());
+        audit_log_repo.add(NewAuditEvent {
+            user_id: user.id,
+            actor_id: None,
+            action: AuditAction::RoleAssigned,
+            data: Some(data.into()),
+        })?;
+        info!("Role {:?} assigned to user {} by email domain", role, user.id);
+        assigned.push(role);
+    }
+
+    Ok(assigned)
+}
+
+/// Checks the request can be approved by the user at `now`, requests are approved once
+/// and never by the admin who made them
+fn check_role_request(request: &RoleRequest, approved_by: UserId, request_ttl: Duration, now: SystemTime) -> Result<(), FailureError> {
+    if request.approved_by.is_some() {
+        return Err(Error::Validate(validation_errors!({"id": ["approved" => "Role request is approved already"]})).into());
+    }
+    if request.requested_by == approved_by {
+        return Err(Error::Forbidden
+            .context(format!("Role request {} must be approved by another admin", request.id))
+            .into());
+    }
+    if request.created_at + request_ttl <= now {
+        return Err(Error::Validate(validation_errors!({"id": ["expired" => "Role request is expired"]})).into());
+    }
+    if request.expires_at.map_or(false, |expires_at| expires_at <= now) {
+        return Err(Error::Validate(validation_errors!({"expires_at": ["past" => "Role can't expire in the past"]})).into());
+    }
+    Ok(())
+}
+
+/// Audit data of the request
+/// Creates pending grant of sensitive role, it is recorded in the audit log
+pub fn request_role(
+    role_requests_repo: &RoleRequestsRepo,
+    audit_log_repo: &AuditLogRepo,
+    new_user_role: NewUserRole,
+    requested_by: UserId,
+) -> Result<RoleRequest, FailureError> {
+    let request = role_requests_repo.create(NewRoleRequest::new(new_user_role, requested_by))?;
+    audit_log_repo.add(NewAuditEvent {
+        user_id: request.user_id,
+        actor_id: Some(requested_by),
+        action: AuditAction::RoleRequested,
+        data: Some(role_request_data(&request)?),
+    })?;
+    info!(
+        "Role {:?} of user {} requested by user {}",
+        request.name, request.user_id, requested_by
+    );
+    Ok(request)
+}
+
+fn role_request_data(request: &RoleRequest) -> Result<serde_json::Value, FailureError> {
+    let mut data = serde_json::Map::new();
+    data.insert("role".to_string(), serde_json::to_value(&request.name)?);
+    data.insert("request_id".to_string(), request.id.to_string().into());
+    data.insert("requested_by".to_string(), request.requested_by.0.into());
+    Ok(data.into())
+}
+
+/// Revokes roles expired by `now`, every revoked role is recorded in the audit log
+pub fn revoke_expired_roles(
+    user_roles_repo: &UserRolesRepo,
+    audit_log_repo: &AuditLogRepo,
+    now: SystemTime,
+) -> Result<Vec<UserRole>, FailureError> {
+    let expired = user_roles_repo.delete_expired(now)?;
+    for role in &expired {
+        let mut data = serde_json::Map::new();
+        data.insert("role".to_string(), serde_json::to_value(&role.name)?);
+        audit_log_repo.add(NewAuditEvent {
+            user_id: role.user_id,
+            actor_id: None,
+            action: AuditAction::RoleExpired,
+            data: Some(data.into()),
+        })?;
+        info!("Role {:?} of user {} expired", role.name, role.user_id);
+    }
+    Ok(expired)
+}
+
+/// Revokes expired roles on every shard every `interval`, in a background thread
+pub fn start_role_expiry_checks<T, M, F>(db_pool: ShardedPool<M>, repo_factory: F, interval: Duration) -> io::Result<JoinHandle<()>>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    thread::Builder::new().name("role_expiry_checks".to_string()).spawn(move || loop {
+        thread::sleep(interval);
+        for pool in db_pool.shards() {
+            if let Err(e) = check_expired_roles(pool, &repo_factory) {
+                error!("Expired roles were not revoked: {}", e);
+            }
+        }
+    })
+}
+
+fn check_expired_roles<T, M, F>(pool: &Pool<M>, repo_factory: &F) -> Result<(), FailureError>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    let conn = pool.get().map_err(|e| e.context(Error::Connection))?;
+    let user_roles_repo = repo_factory.create_user_roles_repo_with_sys_acl(&*conn);
+    let audit_log_repo = repo_factory.create_audit_log_repo(&*conn, None);
+    conn.transaction::<_, FailureError, _>(|| revoke_expired_roles(&*user_roles_repo, &*audit_log_repo, SystemTime::now()))
+        .map(|_| ())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+
+    use tokio_core::reactor::Core;
+
+    use stq_types::{UserId, UsersRole};
+
+    use config::{DomainRoleRule, DomainRoles};
+    use repos::repo_factory::tests::*;
+    use services::user_roles::*;
+
+    #[test]
+    fn test_assign_domain_roles() {
+        let domain_roles = DomainRoles {
+            rules: vec![DomainRoleRule {
+                domain: "storiqa.com".to_string(),
+                roles: vec![UsersRole::Moderator, UsersRole::User],
+            }],
+        };
+        let user_roles_repo = UserRolesRepoMock::default();
+        let audit_log_repo = AuditLogRepoMock::default();
+
+        let staff = create_user(UserId(2), "john@Storiqa.com".to_string());
+        let assigned = assign_domain_roles(&user_roles_repo, &audit_log_repo, &domain_roles, &staff).unwrap();
+        assert_eq!(assigned, vec![UsersRole::Moderator]);
+
+        let customer = create_user(UserId(3), MOCK_EMAIL.to_string());
+        let assigned = assign_domain_roles(&user_roles_repo, &audit_log_repo, &domain_roles, &customer).unwrap();
+        assert_eq!(assigned.is_empty(), true);
+    }
+
+    #[test]
+    fn test_revoke_expired_roles() {
+        let user_roles_repo = UserRolesRepoMock::default();
+        let audit_log_repo = AuditLogRepoMock::default();
+        let revoked = revoke_expired_roles(&user_roles_repo, &audit_log_repo, SystemTime::now()).unwrap();
+        assert_eq!(revoked.len(), 1);
+        assert_eq!(revoked[0].name, UsersRole::Moderator);
+    }
+
+    #[test]
+    fn test_create_role_expired_in_the_past() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let new_role = NewUserRole {
+            id: None,
+            user_id: UserId(2),
+            name: UsersRole::Moderator,
+            data: None,
+            expires_at: Some(SystemTime::now() - Duration::from_secs(60)),
+        };
+        assert_eq!(core.run(service.create_user_role(new_role.clone())).is_err(), true);
+
+        let new_role = NewUserRole {
+            expires_at: Some(SystemTime::now() + Duration::from_secs(3600)),
+            ..new_role
+        };
+        match core.run(service.create_user_role(new_role)).unwrap() {
+            UserRoleGrant::Granted(role) => assert_eq!(role.is_expired(SystemTime::now()), false),
+            UserRoleGrant::Pending(_) => panic!("Moderator role must be granted right away"),
+        }
+    }
+
+    #[test]
+    fn test_sensitive_role_is_pending() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let new_role = NewUserRole {
+            id: None,
+            user_id: UserId(2),
+            name: UsersRole::Superuser,
+            data: None,
+            expires_at: None,
+        };
+        match core.run(service.create_user_role(new_role)).unwrap() {
+            UserRoleGrant::Pending(request) => {
+                assert_eq!(request.requested_by, UserId(1));
+                assert_eq!(request.approved_by, None);
+            }
+            UserRoleGrant::Granted(_) => panic!("Superuser role must wait for approval"),
+        }
+    }
+
+    #[test]
+    fn test_approve_role_request() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let role = core.run(service.approve_role_request(Uuid::new_v4())).unwrap();
+        assert_eq!(role.user_id, UserId(3));
+        assert_eq!(role.name, UsersRole::Superuser);
+    }
+
+    #[test]
+    fn test_role_request_is_approved_by_another_admin() {
+        let request = create_role_request(Uuid::new_v4());
+        let ttl = Duration::from_secs(86400);
+        assert_eq!(check_role_request(&request, UserId(1), ttl, SystemTime::now()).is_ok(), true);
+        assert_eq!(
+            check_role_request(&request, request.requested_by, ttl, SystemTime::now()).is_err(),
+            true
+        );
+        assert_eq!(
+            check_role_request(&request, UserId(1), ttl, SystemTime::now() + Duration::from_secs(86401)).is_err(),
+            true
+        );
+
+        let approved = RoleRequest {
+            approved_by: Some(UserId(1)),
+            ..create_role_request(Uuid::new_v4())
+        };
+        assert_eq!(check_role_request(&approved, UserId(4), ttl, SystemTime::now()).is_err(), true);
+    }
+}
@@ -0,0 +1,106 @@
+//! UserTags Services, presents operations with tags of users.
+//! Tags are stored on the shard of the tagged user.
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use r2d2::ManageConnection;
+
+use stq_types::UserId;
+
+use errors::Error;
+use models::{normalize_tag, NewUserTag, UserTag};
+use repos::ReposFactory;
+use services::types::ServiceFuture;
+use services::Service;
+
+pub trait UserTagsService {
+    /// Returns tags of a user
+    fn get_user_tags(&self, user_id: UserId) -> ServiceFuture<Vec<UserTag>>;
+    /// Tags a user
+    fn add_user_tag(&self, user_id: UserId, payload: NewUserTag) -> ServiceFuture<UserTag>;
+    /// Removes tag of a user
+    fn remove_user_tag(&self, user_id: UserId, tag: String) -> ServiceFuture<UserTag>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > UserTagsService for Service<T, M, F>
+{
+    /// Returns tags of a user
+    fn get_user_tags(&self, user_id: UserId) -> ServiceFuture<Vec<UserTag>> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_shard(user_id, move |conn| {
+            let user_tags_repo = repo_factory.create_user_tags_repo(&*conn, current_uid);
+            user_tags_repo
+                .list_for_user(user_id)
+                .map_err(|e: FailureError| e.context("Service user_tags, get_user_tags endpoint error occured.").into())
+        })
+    }
+
+    /// Tags a user
+    fn add_user_tag(&self, user_id: UserId, payload: NewUserTag) -> ServiceFuture<UserTag> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+        let tag = normalize_tag(&payload.tag);
+
+        self.spawn_on_shard(user_id, move |conn| {
+            let user_tags_repo = repo_factory.create_user_tags_repo(&*conn, current_uid);
+            conn.transaction::<UserTag, FailureError, _>(move || user_tags_repo.add(user_id, tag))
+                .map_err(|e: FailureError| e.context("Service user_tags, add_user_tag endpoint error occured.").into())
+        })
+    }
+
+    /// Removes tag of a user
+    fn remove_user_tag(&self, user_id: UserId, tag: String) -> ServiceFuture<UserTag> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+        let tag = normalize_tag(&tag);
+
+        self.spawn_on_shard(user_id, move |conn| {
+            let user_tags_repo = repo_factory.create_user_tags_repo(&*conn, current_uid);
+            conn.transaction::<Option<UserTag>, FailureError, _>(move || user_tags_repo.remove(user_id, tag))
+                .and_then(|user_tag| user_tag.ok_or_else(|| Error::NotFound.context("User tag not found").into()))
+                .map_err(|e: FailureError| e.context("Service user_tags, remove_user_tag endpoint error occured.").into())
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use tokio_core::reactor::Core;
+
+    use stq_types::UserId;
+
+    use models::NewUserTag;
+    use repos::repo_factory::tests::*;
+    use services::user_tags::UserTagsService;
+
+    #[test]
+    fn test_add_user_tag_normalizes_tag() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.add_user_tag(UserId(2), NewUserTag { tag: " VIP ".to_string() });
+        let result = core.run(work).unwrap();
+        assert_eq!(result.user_id, UserId(2));
+        assert_eq!(result.tag, "vip");
+    }
+
+    #[test]
+    fn test_remove_missing_user_tag() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        assert_eq!(core.run(service.remove_user_tag(UserId(2), MOCK_TAG.to_uppercase())).is_ok(), true);
+        assert_eq!(core.run(service.remove_user_tag(UserId(2), "vip".to_string())).is_err(), true);
+    }
+}
@@ -12,18 +12,30 @@ use futures::future;
 use futures::{Future, IntoFuture};
 use jsonwebtoken::{encode, Algorithm, Header};
 
-use r2d2::ManageConnection;
+use r2d2::{ManageConnection, PooledConnection};
 use uuid::Uuid;
 
 use stq_static_resources::{Provider, TokenType};
-use stq_types::UserId;
-
+use stq_types::{Alpha3, UserId};
+
+use super::funnel::track_funnel_step;
+use super::invites::use_invite;
+use super::legacy_sync::mirror_to_legacy;
+use super::login_stats::count_login;
+use super::name_screening::screen_names;
+use super::password_strength::check_password_policy;
+use super::profile_completion::{completion_stats, current_user};
+use super::security_questions::check_security_answers;
+use super::token_attempts::TokenAttemptsGuard;
 use super::types::ServiceFuture;
-use super::util::{password_create, password_verify};
+use super::user_roles::assign_domain_roles;
+use super::util::{analytics_id, rollout_password_create, signed_token_create, signed_token_verify, stored_password_verify};
 use errors::Error;
 use models::*;
 use repos::repo_factory::ReposFactory;
-use repos::UsersRepo;
+use repos::sharding::ShardedPool;
+use repos::types::RepoLimits;
+use repos::{CountriesRepo, UsersRepo};
 use services::jwt::JWTService;
 use services::Service;
 
@@ -32,8 +44,10 @@ pub trait UsersService {
     fn get(&self, user_id: UserId) -> ServiceFuture<Option<User>>;
     /// Returns total user count
     fn count(&self, only_active_users: bool) -> ServiceFuture<i64>;
-    /// Returns current user
-    fn current(&self) -> ServiceFuture<Option<User>>;
+    /// Returns current user with computed profile fields
+    fn current(&self) -> ServiceFuture<Option<CurrentUser>>;
+    /// Returns profile completion stats over users limited by `from` and `count` parameters
+    fn profile_completion_stats(&self, from: UserId, count: i64) -> ServiceFuture<ProfileCompletionStats>;
     /// Lists users limited by `from` and `count` parameters
     fn list(&self, from: UserId, count: i64) -> ServiceFuture<Vec<User>>;
     /// Deactivates specific user
@@ -43,7 +57,7 @@ pub trait UsersService {
     /// Delete user by id
     fn delete(self, user_id: UserId) -> ServiceFuture<()>;
     /// Creates new user
-    fn create(&self, payload: NewIdentity, user_payload: Option<NewUser>) -> ServiceFuture<User>;
+    fn create(&self, payload: NewIdentity, user_payload: Option<NewUser>, invite_code: Option<String>) -> ServiceFuture<User>;
     /// Get existing reset token
     fn get_existing_reset_token(&self, user: UserId, token_type: TokenType) -> ServiceFuture<ResetToken>;
     /// Get email verification token
@@ -57,9 +71,16 @@ pub trait UsersService {
     /// Get password reset token
     fn get_password_reset_token(&self, email_arg: String, uuid: Uuid) -> ServiceFuture<String>;
     /// Apply password reset
-    fn password_reset_apply(&self, token: String, new_pass: String) -> ServiceFuture<ResetApplyToken>;
+    fn password_reset_apply(
+        &self,
+        token: String,
+        new_pass: String,
+        security_answers: Vec<SecurityAnswerPayload>,
+    ) -> ServiceFuture<ResetApplyToken>;
     /// Find by email
     fn find_by_email(&self, email: String) -> ServiceFuture<Option<User>>;
+    /// Find by phone, phone is normalized to E.164 before lookup
+    fn find_by_phone(&self, phone: String) -> ServiceFuture<Option<User>>;
     /// Search users limited by `from`, `skip` and `count` parameters
     fn search(&self, from: Option<UserId>, skip: i64, count: i64, term: UsersSearchTerms) -> ServiceFuture<UserSearchResults>;
     /// Set block status for specific user
@@ -81,13 +102,18 @@ impl<
         let current_uid = self.dynamic_context.user_id;
         let repo_factory = self.static_context.repo_factory.clone();
 
+        let service = self.clone();
+
         debug!("Getting user {}", user_id);
 
-        self.spawn_on_pool(move |conn| {
-            let users_repo = repo_factory.create_users_repo(&conn, current_uid);
-            users_repo
-                .find(user_id)
-                .map_err(|e: FailureError| e.context("Service users, get endpoint error occured.").into())
+        let key = format!("{:?}:{}", current_uid, user_id);
+        self.static_context.single_flights.users.run(key, move || {
+            service.spawn_on_shard(user_id, move |conn| {
+                let users_repo = repo_factory.create_users_repo(&conn, current_uid);
+                users_repo
+                    .find(user_id)
+                    .map_err(|e: FailureError| e.context("Service users, get endpoint error occured.").into())
+            })
         })
     }
 
@@ -98,25 +124,30 @@ impl<
 
         debug!("Getting user count");
 
-        self.spawn_on_pool(move |conn| {
-            let users_repo = repo_factory.create_users_repo(&conn, current_uid);
-            users_repo
-                .count(only_active_users)
-                .map_err(|e: FailureError| e.context("Service `users`, `count` endpoint error occurred.").into())
-        })
+        Box::new(
+            self.spawn_on_all_shards(move |conn| {
+                let users_repo = repo_factory.create_users_repo(&conn, current_uid);
+                users_repo
+                    .count(only_active_users)
+                    .map_err(|e: FailureError| e.context("Service `users`, `count` endpoint error occurred.").into())
+            })
+            .map(|counts| counts.into_iter().sum()),
+        )
     }
 
-    /// Returns current user
-    fn current(&self) -> ServiceFuture<Option<User>> {
+    /// Returns current user with computed profile fields
+    fn current(&self) -> ServiceFuture<Option<CurrentUser>> {
         if let Some(id) = self.dynamic_context.user_id {
             let repo_factory = self.static_context.repo_factory.clone();
+            let weights = self.static_context.config.profile_completion.weights.clone();
 
             debug!("Fetching current user ({})", id);
 
-            self.spawn_on_pool(move |conn| {
+            self.spawn_on_shard(id, move |conn| {
                 let users_repo = repo_factory.create_users_repo(&conn, Some(id));
                 users_repo
                     .find(id)
+                    .map(|user| user.map(|user| current_user(user, &weights, Utc::today().naive_utc())))
                     .map_err(|e: FailureError| e.context("Service users, current endpoint error occured.").into())
             })
         } else {
@@ -124,6 +155,25 @@ impl<
         }
     }
 
+    /// Returns profile completion stats over users limited by `from` and `count` parameters
+    fn profile_completion_stats(&self, from: UserId, count: i64) -> ServiceFuture<ProfileCompletionStats> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+        let weights = self.static_context.config.profile_completion.weights.clone();
+
+        debug!("Computing profile completion stats for {} users starting from {}", count, from);
+
+        Box::new(
+            self.spawn_on_all_shards(move |conn| {
+                let users_repo = repo_factory.create_users_repo(&conn, current_uid);
+                users_repo
+                    .list(from, count)
+                    .map_err(|e: FailureError| e.context("Service users, profile_completion_stats endpoint error occured.").into())
+            })
+            .map(move |shard_users| completion_stats(&merge_shard_users(shard_users, 0, count), &weights, count)),
+        )
+    }
+
     /// Lists users limited by `from` and `count` parameters
     fn list(&self, from: UserId, count: i64) -> ServiceFuture<Vec<User>> {
         let current_uid = self.dynamic_context.user_id;
@@ -131,12 +181,15 @@ impl<
 
         debug!("Fetching {} users starting from {}", count, from);
 
-        self.spawn_on_pool(move |conn| {
-            let users_repo = repo_factory.create_users_repo(&conn, current_uid);
-            users_repo
-                .list(from, count)
-                .map_err(|e: FailureError| e.context("Service users, list endpoint error occured.").into())
-        })
+        Box::new(
+            self.spawn_on_all_shards(move |conn| {
+                let users_repo = repo_factory.create_users_repo(&conn, current_uid);
+                users_repo
+                    .list(from, count)
+                    .map_err(|e: FailureError| e.context("Service users, list endpoint error occured.").into())
+            })
+            .map(move |shard_users| merge_shard_users(shard_users, 0, count)),
+        )
     }
 
     /// Deactivates specific user
@@ -146,7 +199,7 @@ impl<
 
         debug!("Deactivating user {}", &user_id);
 
-        self.spawn_on_pool(move |conn| {
+        self.spawn_on_shard(user_id, move |conn| {
             let users_repo = repo_factory.create_users_repo(&conn, current_uid);
             users_repo
                 .deactivate(user_id)
@@ -160,7 +213,7 @@ impl<
         let repo_factory = self.static_context.repo_factory.clone();
         debug!("Set block status {} for user {}", is_blocked, &user_id);
 
-        self.spawn_on_pool(move |conn| {
+        self.spawn_on_shard(user_id, move |conn| {
             let users_repo = repo_factory.create_users_repo(&conn, current_uid);
             users_repo
                 .set_block_status(user_id, is_blocked)
@@ -195,7 +248,7 @@ impl<
             return Box::new(future::err(Error::Forbidden.context("Cannot delete user").into()));
         }
 
-        self.spawn_on_pool(move |conn| {
+        self.spawn_on_shard(user_id_arg, move |conn| {
             let users_repo = repo_factory.create_users_repo(&conn, current_uid);
 
             users_repo
@@ -205,51 +258,157 @@ impl<
     }
 
     /// Creates new user
-    fn create(&self, payload: NewIdentity, user_payload: Option<NewUser>) -> ServiceFuture<User> {
+    fn create(&self, payload: NewIdentity, user_payload: Option<NewUser>, invite_code: Option<String>) -> ServiceFuture<User> {
         let current_uid = self.dynamic_context.user_id;
         let repo_factory = self.static_context.repo_factory.clone();
+        let name_screening = self.static_context.name_screening.clone();
+        let default_region = self.static_context.config.phone.default_region.clone();
+        let data_residency = self.static_context.config.data_residency.clone();
+        let domain_roles = self.static_context.config.domain_roles.clone();
+        let invites_required = self.static_context.config.invites.required;
+        let db_pool = self.static_context.db_pool.clone();
+        let rollouts = self.static_context.rollouts.clone();
+
+        debug!("Creating new user with provider {}", payload.provider);
+
+        if let Some(ref password) = payload.password {
+            let mut user_inputs = vec![payload.email.clone()];
+            if let Some(ref user) = user_payload {
+                user_inputs.extend(user.first_name.clone());
+                user_inputs.extend(user.last_name.clone());
+            }
+            if let Err(e) = check_password_policy(&self.static_context.config.password_strength, password, &user_inputs) {
+                return Box::new(future::err(e.context("Service users, create endpoint error occured.").into()));
+            }
+        }
 
-        debug!(
-            "Creating new user with payload: {:?} and user_payload: {:?}",
-            &payload, &user_payload
+        // in invite-only mode the invite is used first and released if registration fails
+        let used_invite: ServiceFuture<Option<String>> = if invites_required {
+            let invite_repo_factory = repo_factory.clone();
+            self.spawn_on_pool(move |conn| {
+                let invites_repo = invite_repo_factory.create_invites_repo(&conn, None);
+                use_invite(&*invites_repo, invite_code)
+                    .map(Some)
+                    .map_err(|e: FailureError| e.context("Service users, create endpoint error occured.").into())
+            })
+        } else {
+            Box::new(future::ok(None))
+        };
+
+        let service = self.clone();
+        let new_user_id: ServiceFuture<Option<UserId>> = Box::new(
+            allocate_user_id(self, payload.email.clone())
+                .map_err(|e: FailureError| e.context("Service users, create endpoint error occured.").into()),
         );
 
-        self.spawn_on_pool(move |conn| {
-            let users_repo = repo_factory.create_users_repo(&conn, current_uid);
-            let ident_repo = repo_factory.create_identities_repo(&conn);
-            let users_repo_with_sys_acl = repo_factory.create_users_repo_with_sys_acl(&conn);
-
-            conn.transaction::<User, FailureError, _>(move || {
-                let exists = ident_repo.email_exists(payload.email.to_string())?;
-                if !exists {
-                    let mut new_user = user_payload.unwrap_or(NewUser::from(payload.clone()));
-                    check_referal(&*users_repo, &mut new_user)?;
-                    let user = users_repo.create(new_user)?;
-                    ident_repo.create(
-                        payload.email,
-                        payload.password.map(password_create),
-                        payload.provider,
-                        user.id,
-                        payload.saga_id,
-                    )?;
-
-                    let update_user = set_email_verified_social(&*users_repo_with_sys_acl, user.id, payload.provider)?;
-                    Ok(update_user.unwrap_or(user))
-                } else {
-                    Err(Error::Validate(validation_errors!({"email": ["exists" => "Email already exists"]})).into())
-                }
-            })
-            .map_err(|e: FailureError| e.context("Service users, create endpoint error occured.").into())
-        })
+        let funnel_repo_factory = repo_factory.clone();
+        let release_repo_factory = repo_factory.clone();
+        let funnel_service = self.clone();
+        let release_service = self.clone();
+        let legacy_sync_service = self.clone();
+        let registered = used_invite.and_then(move |used_code| {
+            new_user_id
+                .and_then(move |new_user_id| {
+                    let create = move |conn: PooledConnection<M>| -> Result<User, FailureError> {
+                        let users_repo = repo_factory.create_users_repo(&conn, current_uid);
+                        let ident_repo = repo_factory.create_identities_repo(&conn);
+                        let users_repo_with_sys_acl = repo_factory.create_users_repo_with_sys_acl(&conn);
+                        let countries_repo = repo_factory.create_countries_repo(&conn);
+
+                        if let Some(ref user) = user_payload {
+                            screen_names(
+                                &*name_screening,
+                                &[
+                                    ("first_name", &user.first_name),
+                                    ("last_name", &user.last_name),
+                                    ("middle_name", &user.middle_name),
+                                ],
+                            )?;
+                        }
+
+                        // concurrent registrations must not both pass the e-mail check and fail on the unique index
+                        with_email_lock(&db_pool, &repo_factory, &*conn, payload.email.clone(), move || {
+                            let mut new_user = user_payload.unwrap_or(NewUser::from(payload.clone()));
+                            new_user.id = new_user_id;
+                            normalize_phone_field(&mut new_user.phone, &default_region)?;
+                            check_country(&*countries_repo, &mut new_user.country)?;
+                            new_user.data_region =
+                                Some(data_residency.region_for(new_user.country.as_ref().map(|country| country.0.as_str())));
+                            check_referal(&*users_repo, &mut new_user)?;
+                            let user = users_repo.create(new_user)?;
+                            let user_id = user.id;
+                            ident_repo.create(
+                                payload.email,
+                                payload
+                                    .password
+                                    .map(|password| rollout_password_create(&rollouts, user_id, password)),
+                                payload.provider,
+                                user.id,
+                                payload.saga_id,
+                            )?;
+
+                            let update_user = set_email_verified_social(&*users_repo_with_sys_acl, user.id, payload.provider)?;
+                            Ok(update_user.unwrap_or(user))
+                        })
+                        .map_err(|e: FailureError| e.context("Service users, create endpoint error occured.").into())
+                    };
+
+                    match new_user_id {
+                        Some(user_id) => service.spawn_on_shard(user_id, create),
+                        None => service.spawn_on_pool(create),
+                    }
+                })
+                .or_else(move |e| -> ServiceFuture<User> {
+                    match used_code {
+                        Some(code) => Box::new(
+                            release_service
+                                .spawn_on_pool(move |conn| {
+                                    let invites_repo = release_repo_factory.create_invites_repo(&conn, None);
+                                    invites_repo.release_invite(code)
+                                })
+                                .then(move |_| -> Result<User, FailureError> { Err(e) }),
+                        ),
+                        None => Box::new(future::err(e)),
+                    }
+                })
+        });
+
+        // funnel events and roles are kept on the primary shard
+        Box::new(
+            registered
+                .and_then(move |user| {
+                    funnel_service.spawn_on_pool(move |conn| {
+                        let funnel_events_repo = funnel_repo_factory.create_funnel_events_repo(&conn, None);
+                        track_funnel_step(&*funnel_events_repo, user.id, FunnelStep::RegistrationSubmitted);
+                        // users registered with social providers have their emails verified right away
+                        if user.email_verified {
+                            let user_roles_repo = funnel_repo_factory.create_user_roles_repo_with_sys_acl(&conn);
+                            let audit_log_repo = funnel_repo_factory.create_audit_log_repo(&conn, None);
+                            conn.transaction::<_, FailureError, _>(|| {
+                                assign_domain_roles(&*user_roles_repo, &*audit_log_repo, &domain_roles, &user)
+                            })
+                            .map_err(|e: FailureError| e.context("Service users, create endpoint error occured."))?;
+                        }
+                        Ok(user)
+                    })
+                })
+                .map(move |user| {
+                    mirror_to_legacy(&legacy_sync_service, LegacySyncOperation::Create, &user);
+                    user
+                }),
+        )
     }
 
     /// Get verification token
     fn get_email_verification_token(&self, email: String) -> ServiceFuture<String> {
         let repo_factory = self.static_context.repo_factory.clone();
         let email_sending_timeout = self.static_context.config.tokens.email_sending_timeout_s;
+        let signing_key = self.static_context.hmac_key.clone();
 
         self.spawn_on_pool(move |conn| {
             let reset_repo = repo_factory.create_reset_token_repo(&conn);
+            let funnel_events_repo = repo_factory.create_funnel_events_repo(&conn, None);
+            let users_repo = repo_factory.create_users_repo_with_sys_acl(&conn);
             let token = reset_repo
                 .find_by_email(email.clone(), TokenType::EmailVerify)
                 .map_err(|e| e.context(format!("Can not find token by email {}", email.clone())))?;
@@ -268,8 +427,15 @@ impl<
             }
 
             reset_repo
-                .upsert(email.clone(), TokenType::EmailVerify, None)
-                .map(|t| t.token)
+                .upsert(email.clone(), TokenType::EmailVerify, None, signed_token_create(&signing_key))
+                .map(|t| {
+                    match users_repo.find_by_email(email.clone()) {
+                        Ok(Some(user)) => track_funnel_step(&*funnel_events_repo, user.id, FunnelStep::EmailSent),
+                        Ok(None) => (),
+                        Err(e) => warn!("Funnel step {} was not recorded: {}", FunnelStep::EmailSent, e),
+                    }
+                    t.token
+                })
                 .map_err(|e| e.context("Can not create reset token").into())
                 .map_err(|e: FailureError| e.context("Service users, resend_verification_link endpoint error occured.").into())
         })
@@ -307,6 +473,10 @@ impl<
         let secret = self.static_context.jwt_private_key.clone();
         let verify_expiration_s = self.static_context.config.tokens.verify_expiration_s;
         let jwt_expiration_s = self.static_context.config.tokens.jwt_expiration_s;
+        let max_apply_attempts = self.static_context.config.tokens.max_apply_attempts;
+        let client_ip = self.dynamic_context.client_ip;
+        let domain_roles = self.static_context.config.domain_roles.clone();
+        let signing_key = self.static_context.hmac_key.clone();
         let service = self.clone();
 
         let fut = self
@@ -314,10 +484,25 @@ impl<
                 {
                     let users_repo = repo_factory.create_users_repo_with_sys_acl(&conn);
                     let reset_repo = repo_factory.create_reset_token_repo(&conn);
+                    let login_stats_repo = repo_factory.create_login_stats_repo(&conn, None);
+                    let funnel_events_repo = repo_factory.create_funnel_events_repo(&conn, None);
+                    let user_roles_repo = repo_factory.create_user_roles_repo_with_sys_acl(&conn);
+                    let audit_log_repo = repo_factory.create_audit_log_repo(&conn, None);
+                    let mut attempts = TokenAttemptsGuard::new(repo_factory.create_attempts_cache(), max_apply_attempts, client_ip);
+                    attempts.check()?;
+
+                    if !signed_token_verify(&signing_key, &token_arg) {
+                        attempts.failed();
+                        return Err(Error::InvalidToken.context("Token signature mismatch").into());
+                    }
 
-                    let reset_token: ResetToken = reset_repo
-                        .find_by_token(token_arg.clone(), TokenType::EmailVerify)
-                        .map_err(|e| e.context(Error::InvalidToken))?;
+                    let reset_token: ResetToken = reset_repo.find_by_token(token_arg.clone(), TokenType::EmailVerify).map_err(|e| {
+                        attempts.failed();
+                        e.context(Error::InvalidToken)
+                    })?;
+
+                    attempts.add_email(&reset_token.email);
+                    attempts.check()?;
 
                     let user = match SystemTime::now().duration_since(reset_token.updated_at) {
                         Ok(elapsed) => {
@@ -333,7 +518,11 @@ impl<
                                             ..Default::default()
                                         };
 
-                                        users_repo.update(user.id.clone(), update)
+                                        conn.transaction::<User, FailureError, _>(|| {
+                                            let user = users_repo.update(user.id.clone(), update)?;
+                                            assign_domain_roles(&*user_roles_repo, &*audit_log_repo, &domain_roles, &user)?;
+                                            Ok(user)
+                                        })
                                     }
                                 } else {
                                     Err(Error::InvalidToken
@@ -341,12 +530,16 @@ impl<
                                         .into())
                                 }
                             } else {
+                                attempts.failed();
                                 Err(Error::InvalidToken.into())
                             }
                         }
                         Err(_) => Err(Error::InvalidToken.into()),
                     }?;
 
+                    attempts.succeeded();
+                    track_funnel_step(&*funnel_events_repo, user.id, FunnelStep::EmailVerified);
+                    count_login(&*login_stats_repo, Provider::Email);
                     Ok(user)
                 }
                 .map_err(|e: FailureError| e.context("Service users, verify_email endpoint error occured.").into())
@@ -354,8 +547,9 @@ impl<
             .and_then(move |user| {
                 let provider = Provider::Email;
                 let exp = Utc::now().timestamp() + jwt_expiration_s as i64;
+                let analytics_id = analytics_id(&service.static_context.config.analytics, user.id);
                 service
-                    .create_jwt(user.id, exp, secret, provider)
+                    .create_jwt(user.id, exp, secret, provider, None, analytics_id, None)
                     .and_then(move |token| future::ok(EmailVerifyApplyToken { token, user }))
             });
 
@@ -363,19 +557,42 @@ impl<
     }
 
     /// Updates specific user
-    fn update(&self, user_id: UserId, payload: UpdateUser) -> ServiceFuture<User> {
+    fn update(&self, user_id: UserId, mut payload: UpdateUser) -> ServiceFuture<User> {
         let current_uid = self.dynamic_context.user_id;
         let repo_factory = self.static_context.repo_factory.clone();
+        let name_screening = self.static_context.name_screening.clone();
+        let default_region = self.static_context.config.phone.default_region.clone();
 
-        debug!("Updating user {} with payload: {:?}", &user_id, &payload);
+        debug!("Updating user {}", &user_id);
 
-        self.spawn_on_pool(move |conn| {
+        let legacy_sync_service = self.clone();
+
+        let updated = self.spawn_on_shard(user_id, move |conn| {
             let users_repo = repo_factory.create_users_repo(&conn, current_uid);
-            users_repo
-                .find(user_id.clone())
-                .and_then(move |_user| users_repo.update(user_id, payload))
+            let screening = screen_names(
+                &*name_screening,
+                &[
+                    ("first_name", &payload.first_name),
+                    ("last_name", &payload.last_name),
+                    ("middle_name", &payload.middle_name),
+                ],
+            );
+            let phone_normalization = normalize_phone_field(&mut payload.phone, &default_region);
+
+            screening
+                .and(phone_normalization)
+                .and_then(move |_| {
+                    users_repo
+                        .find(user_id.clone())
+                        .and_then(move |_user| users_repo.update(user_id, payload))
+                })
                 .map_err(|e: FailureError| e.context("Service users, update endpoint error occured.").into())
-        })
+        });
+
+        Box::new(updated.map(move |user| {
+            mirror_to_legacy(&legacy_sync_service, LegacySyncOperation::Update, &user);
+            user
+        }))
     }
 
     fn change_password(&self, payload: ChangeIdentityPassword) -> ServiceFuture<String> {
@@ -383,6 +600,8 @@ impl<
         match self.dynamic_context.user_id {
             Some(current_uid) => {
                 let repo_factory = self.static_context.repo_factory.clone();
+                let password_strength = self.static_context.config.password_strength.clone();
+                let rollouts = self.static_context.rollouts.clone();
 
                 debug!("Updating user password {}", &current_uid);
 
@@ -396,16 +615,18 @@ impl<
                             let identity = ident_repo.find_by_id_provider(current_uid.clone(), Provider::Email)?;
                             let ident_clone = identity.clone();
                             if let Some(passwd) = ident_clone.password {
-                                let verified = password_verify(&passwd, old_password)?;
+                                let verified = stored_password_verify(&passwd, ident_clone.password_hash_format, old_password)?;
                                 if !verified {
                                     //password not verified
                                     Err(Error::Validate(validation_errors!({"password": ["password" => "Wrong password"]})).into())
                                 } else {
                                     //password verified
+                                    check_password_policy(&password_strength, &new_password, &[identity.email.clone()])?;
                                     debug!("Changing password for identity {:?}", &identity);
                                     let update = UpdateIdentity {
-                                        password: Some(password_create(new_password)),
+                                        password: Some(rollout_password_create(&rollouts, current_uid, new_password)),
                                         provider: None,
+                                        must_change_password: Some(false),
                                     };
                                     ident_repo.update(identity, update)
                                 }
@@ -429,6 +650,7 @@ impl<
         let email = email_arg.clone();
         let repo_factory = self.static_context.repo_factory.clone();
         let email_sending_timeout = self.static_context.config.tokens.email_sending_timeout_s;
+        let signing_key = self.static_context.hmac_key.clone();
 
         self.spawn_on_pool(move |conn| {
             let reset_repo = repo_factory.create_reset_token_repo(&conn);
@@ -462,7 +684,12 @@ impl<
                 }
 
                 let t = reset_repo
-                    .upsert(ident.email.clone(), TokenType::PasswordReset, Some(uuid))
+                    .upsert(
+                        ident.email.clone(),
+                        TokenType::PasswordReset,
+                        Some(uuid),
+                        signed_token_create(&signing_key),
+                    )
                     .map_err(|e| e.context("Can not create reset token"))?;
                 Ok(t.token)
             }
@@ -470,10 +697,21 @@ impl<
         })
     }
 
-    fn password_reset_apply(&self, token_arg: String, new_pass: String) -> ServiceFuture<ResetApplyToken> {
+    fn password_reset_apply(
+        &self,
+        token_arg: String,
+        new_pass: String,
+        security_answers: Vec<SecurityAnswerPayload>,
+    ) -> ServiceFuture<ResetApplyToken> {
         let repo_factory = self.static_context.repo_factory.clone();
         let service = self.clone();
         let reset_expiration_s = self.static_context.config.tokens.reset_expiration_s;
+        let max_apply_attempts = self.static_context.config.tokens.max_apply_attempts;
+        let password_strength = self.static_context.config.password_strength.clone();
+        let required_answers = self.static_context.config.security_questions.required_answers;
+        let rollouts = self.static_context.rollouts.clone();
+        let client_ip = self.dynamic_context.client_ip;
+        let signing_key = self.static_context.hmac_key.clone();
 
         debug!("Resetting password for token {}.", &token_arg);
 
@@ -482,37 +720,60 @@ impl<
                 {
                     let reset_repo = repo_factory.create_reset_token_repo(&conn);
                     let ident_repo = repo_factory.create_identities_repo(&conn);
+                    let security_answers_repo = repo_factory.create_security_answers_repo_with_sys_acl(&conn);
+                    let mut attempts = TokenAttemptsGuard::new(repo_factory.create_attempts_cache(), max_apply_attempts, client_ip);
+                    attempts.check()?;
+
+                    if !signed_token_verify(&signing_key, &token_arg) {
+                        attempts.failed();
+                        return Err(Error::InvalidToken.context("Token signature mismatch").into());
+                    }
+
+                    let reset_token = reset_repo.find_by_token(token_arg.clone(), TokenType::PasswordReset).map_err(|e| {
+                        attempts.failed();
+                        e.context("Reset token by token search failure").context(Error::InvalidToken)
+                    })?;
 
-                    let reset_token = reset_repo
-                        .find_by_token(token_arg.clone(), TokenType::PasswordReset)
-                        .map_err(|e| e.context("Reset token by token search failure").context(Error::InvalidToken))?;
+                    attempts.add_email(&reset_token.email);
+                    attempts.check()?;
 
                     debug!("Checking reset token's {:?} expiration", &reset_token);
                     let identity = match SystemTime::now().duration_since(reset_token.updated_at) {
                         Ok(elapsed) => {
                             if elapsed.as_secs() < reset_expiration_s {
                                 let ident = ident_repo.get_by_email(reset_token.email.clone())?;
+                                let answers = security_answers_repo.list_for_user(ident.user_id)?;
+                                if let Err(e) = check_security_answers(&answers, &security_answers, required_answers) {
+                                    attempts.failed();
+                                    return Err(e);
+                                }
+                                check_password_policy(&password_strength, &new_pass, &[ident.email.clone()])?;
                                 debug!("Token check successful, resetting password for identity {:?}", &ident);
 
+                                let password = rollout_password_create(&rollouts, ident.user_id, new_pass);
                                 let update = match ident.provider {
                                     Provider::Email => UpdateIdentity {
-                                        password: Some(password_create(new_pass)),
+                                        password: Some(password),
                                         provider: None,
+                                        must_change_password: Some(false),
                                     },
                                     _ => UpdateIdentity {
-                                        password: Some(password_create(new_pass)),
+                                        password: Some(password),
                                         provider: Some(Provider::Email),
+                                        must_change_password: Some(false),
                                     },
                                 };
 
                                 ident_repo.update(ident, update)
                             } else {
+                                attempts.failed();
                                 Err(Error::InvalidToken.context(format!("Token {:?} has expired", &reset_token)).into())
                             }
                         }
                         Err(_) => Err(Error::InvalidToken.into()),
                     }?;
 
+                    attempts.succeeded();
                     Ok(identity)
                 }
                 .map_err(|e: FailureError| e.context("Service users, password_reset_apply endpoint error occured.").into())
@@ -534,47 +795,98 @@ impl<
         let current_uid = self.dynamic_context.user_id;
         let repo_factory = self.static_context.repo_factory.clone();
 
-        debug!("Getting user by email {}", email);
+        debug!("Getting user by email");
 
-        self.spawn_on_pool(move |conn| {
-            let users_repo = repo_factory.create_users_repo(&conn, current_uid);
-            users_repo
-                .find_by_email(email)
-                .map_err(|e: FailureError| e.context("Service users, find by email endpoint error occured.").into())
-        })
+        Box::new(
+            self.spawn_on_all_shards(move |conn| {
+                let users_repo = repo_factory.create_users_repo(&conn, current_uid);
+                users_repo
+                    .find_by_email(email.clone())
+                    .map_err(|e: FailureError| e.context("Service users, find by email endpoint error occured.").into())
+            })
+            .map(|shard_users| shard_users.into_iter().filter_map(|user| user).next()),
+        )
     }
 
-    /// Search users limited by `from`, `skip` and `count` parameters
-    fn search(&self, from: Option<UserId>, skip: i64, count: i64, term: UsersSearchTerms) -> ServiceFuture<UserSearchResults> {
+    /// Find by phone, phone is normalized to E.164 before lookup
+    fn find_by_phone(&self, phone: String) -> ServiceFuture<Option<User>> {
         let current_uid = self.dynamic_context.user_id;
         let repo_factory = self.static_context.repo_factory.clone();
+        let default_region = self.static_context.config.phone.default_region.clone();
 
-        debug!(
-            "Searching for users (from: {:?}, skip: {}, count: {}) with payload: {:?}",
-            from, skip, count, term
-        );
+        debug!("Getting user by phone");
 
         self.spawn_on_pool(move |conn| {
             let users_repo = repo_factory.create_users_repo(&conn, current_uid);
-            users_repo
-                .search(from, skip, count, term)
-                .map_err(|e: FailureError| e.context("Service `users`, `search` endpoint error occured.").into())
+            normalize_phone(&phone, &default_region)
+                .ok_or_else(|| -> FailureError {
+                    Error::Validate(validation_errors!({"phone": ["phone" => "Incorrect phone format"]})).into()
+                })
+                .and_then(|phone| users_repo.find_by_phone(phone))
+                .map_err(|e: FailureError| e.context("Service users, find by phone endpoint error occured.").into())
         })
     }
 
+    /// Search users limited by `from`, `skip` and `count` parameters
+    fn search(&self, from: Option<UserId>, skip: i64, count: i64, mut term: UsersSearchTerms) -> ServiceFuture<UserSearchResults> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+        let default_region = &self.static_context.config.phone.default_region;
+
+        term.phone = term
+            .phone
+            .map(|term_phone| normalize_phone(&term_phone, default_region).unwrap_or(term_phone));
+
+        debug!("Searching for users (from: {:?}, skip: {}, count: {})", from, skip, count);
+
+        // single database pages results itself, otherwise every shard returns the first `skip + count` users,
+        // at most `max_count` of them, and page is cut after merging, so deep pages are reached with `from`
+        let limits = RepoLimits {
+            max_count: self.static_context.config.repo_limits.max_count,
+        };
+        let (shard_skip, shard_count, merge_skip) = if !self.static_context.db_pool.is_sharded() {
+            (skip, count, 0)
+        } else if count > 0 {
+            (0, limits.clamp(skip.max(0) + count), skip)
+        } else {
+            (0, 0, skip)
+        };
+        let merge_count = limits.clamp(count);
+
+        Box::new(
+            self.spawn_on_all_shards(move |conn| {
+                let users_repo = repo_factory.create_users_repo(&conn, current_uid);
+                users_repo
+                    .search(from, shard_skip, shard_count, term.clone())
+                    .map_err(|e: FailureError| e.context("Service `users`, `search` endpoint error occured.").into())
+            })
+            .map(move |shard_results| {
+                let total_count = shard_results.iter().map(|results| results.total_count).sum();
+                let shard_users = shard_results.into_iter().map(|results| results.users).collect();
+                UserSearchResults {
+                    total_count,
+                    users: merge_shard_users(shard_users, merge_skip, merge_count),
+                }
+            }),
+        )
+    }
+
     /// Fuzzy search users by email
     fn fuzzy_search_by_email(&self, term_email: String) -> ServiceFuture<Vec<User>> {
         let current_uid = self.dynamic_context.user_id;
         let repo_factory = self.static_context.repo_factory.clone();
 
-        debug!("Searching for users email containing {}", term_email);
+        debug!("Searching for users by email");
 
-        self.spawn_on_pool(move |conn| {
-            let users_repo = repo_factory.create_users_repo(&conn, current_uid);
-            users_repo
-                .fuzzy_search_by_email(term_email)
-                .map_err(|e: FailureError| e.context("Service users, fuzzy_search_by_email endpoint error occured.").into())
-        })
+        Box::new(
+            self.spawn_on_all_shards(move |conn| {
+                let users_repo = repo_factory.create_users_repo(&conn, current_uid);
+                users_repo
+                    .fuzzy_search_by_email(term_email.clone())
+                    .map_err(|e: FailureError| e.context("Service users, fuzzy_search_by_email endpoint error occured.").into())
+            })
+            .map(|shard_users| merge_shard_users(shard_users, 0, 0)),
+        )
     }
 
     /// Revoke all tokens for user
@@ -583,6 +895,7 @@ impl<
         let repo_factory = self.static_context.repo_factory.clone();
         let jwt_expiration_s = self.static_context.config.tokens.jwt_expiration_s;
         let secret = self.static_context.jwt_private_key.clone();
+        let analytics = self.static_context.config.analytics.clone();
         // revoking all tokens given before current date
         // expiration date of tokens must be later than now + jwt_exp
         let revoke_before = SystemTime::now() + Duration::from_secs(jwt_expiration_s);
@@ -592,13 +905,20 @@ impl<
         Box::new(
             self.spawn_on_pool(move |conn| {
                 let users_repo = repo_factory.create_users_repo(&conn, current_uid);
+                let user_roles_repo = repo_factory.create_user_roles_repo_with_sys_acl(&conn);
+                let child_accounts_repo = repo_factory.create_child_accounts_repo_with_sys_acl(&conn);
                 users_repo
                     .revoke_tokens(user_id, revoke_before)
+                    .map(|_| user_roles_repo.invalidate_cache(user_id))
+                    .and_then(|_| child_accounts_repo.find_by_child(user_id))
                     .map_err(|e: FailureError| e.context("Service users, revoke_tokens endpoint error occured.").into())
             })
-            .and_then(move |_| {
+            .and_then(move |child| {
                 let exp = Utc::now().timestamp() + jwt_expiration_s as i64;
-                let tokenpayload = JWTPayload::new(user_id, exp, provider);
+                let tokenpayload = JWTPayload::new(user_id, exp, provider)
+                    .with_auth_time(Utc::now().timestamp())
+                    .with_parent(child)
+                    .with_analytics_id(analytics_id(&analytics, user_id));
                 encode(&Header::new(Algorithm::RS256), &tokenpayload, secret.as_ref())
                     .map_err(|e| {
                         format_err!("{}", e)
@@ -616,6 +936,29 @@ impl<
     }
 }
 
+/// Normalizes phone to E.164, returns validation error if phone can not be normalized
+fn normalize_phone_field(phone: &mut Option<String>, default_region: &str) -> Result<(), FailureError> {
+    if let Some(ref mut phone) = *phone {
+        let normalized = normalize_phone(phone, default_region)
+            .ok_or_else(|| Error::Validate(validation_errors!({"phone": ["phone" => "Incorrect phone format"]})))?;
+        *phone = normalized;
+    }
+
+    Ok(())
+}
+
+/// Merges users fetched from all shards into a single page ordered by id
+fn merge_shard_users(shard_users: Vec<Vec<User>>, skip: i64, count: i64) -> Vec<User> {
+    let mut users = shard_users.into_iter().flat_map(|users| users).collect::<Vec<_>>();
+    users.sort_by_key(|user| user.id.0);
+    let users = users.into_iter().skip(skip.max(0) as usize);
+    if count > 0 {
+        users.take(count as usize).collect()
+    } else {
+        users.collect()
+    }
+}
+
 fn check_referal(users_repo: &UsersRepo, new_user: &mut NewUser) -> Result<(), FailureError> {
     if let Some(referal) = new_user.referal {
         if users_repo.find(referal)?.is_none() {
@@ -625,6 +968,102 @@ fn check_referal(users_repo: &UsersRepo, new_user: &mut NewUser) -> Result<(), F
     Ok(())
 }
 
+/// Country codes are upper-cased and must be present in countries reference data
+fn check_country(countries_repo: &CountriesRepo, country: &mut Option<Alpha3>) -> Result<(), FailureError> {
+    if let Some(ref mut country) = *country {
+        country.0 = country.0.trim().to_uppercase();
+        if countries_repo.find(country.clone())?.is_none() {
+            return Err(Error::Validate(validation_errors!({"country": ["not_exists" => "Unknown country"]})).into());
+        }
+    }
+    Ok(())
+}
+
+/// Runs `f` in a transaction on `conn` once the e-mail is checked not to exist. Identities are unique
+/// by e-mail, so concurrent registrations with the same e-mail are run one by one: the e-mail is locked
+/// until the end of the transaction. If users are sharded, the lock is taken on the primary shard for all
+/// shards and the e-mail is checked on every shard, as identities are unique within a shard only.
+pub fn with_email_lock<T, M, F, R, Func>(
+    db_pool: &ShardedPool<M>,
+    repo_factory: &F,
+    conn: &T,
+    email: String,
+    f: Func,
+) -> Result<R, FailureError>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+    Func: FnOnce() -> Result<R, FailureError>,
+{
+    let email_exists = || -> FailureError { Error::Validate(validation_errors!({"email": ["exists" => "Email already exists"]})).into() };
+
+    if !db_pool.is_sharded() {
+        let ident_repo = repo_factory.create_identities_repo(conn);
+        return conn.transaction::<R, FailureError, _>(|| {
+            ident_repo.lock_email(email.to_lowercase())?;
+            if ident_repo.email_exists(email.clone())? {
+                return Err(email_exists());
+            }
+            f()
+        });
+    }
+
+    let primary_conn = db_pool.primary().get().map_err(|e| e.context(Error::Connection))?;
+    let primary_ident_repo = repo_factory.create_identities_repo(&*primary_conn);
+    primary_conn.transaction::<R, FailureError, _>(|| {
+        primary_ident_repo.lock_email(email.to_lowercase())?;
+        for shard_pool in db_pool.shards() {
+            let shard_conn = shard_pool.get().map_err(|e| e.context(Error::Connection))?;
+            let ident_repo = repo_factory.create_identities_repo(&*shard_conn);
+            if ident_repo.email_exists(email.clone())? {
+                return Err(email_exists());
+            }
+        }
+        conn.transaction::<R, FailureError, _>(f)
+    })
+}
+
+/// If users are sharded, allocates id beforehand to pick the shard of the new user. E-mails are
+/// checked on all shards first, as identities are unique within a shard only, and checked again
+/// under the lock by `with_email_lock`
+pub fn allocate_user_id<
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+>(
+    service: &Service<T, M, F>,
+    email: String,
+) -> ServiceFuture<Option<UserId>> {
+    if !service.static_context.db_pool.is_sharded() {
+        return Box::new(future::ok(None));
+    }
+    let current_uid = service.dynamic_context.user_id;
+    let exists_repo_factory = service.static_context.repo_factory.clone();
+    let id_repo_factory = service.static_context.repo_factory.clone();
+    let id_service = service.clone();
+    Box::new(
+        service
+            .spawn_on_all_shards(move |conn| {
+                let ident_repo = exists_repo_factory.create_identities_repo(&conn);
+                ident_repo.email_exists(email.clone())
+            })
+            .and_then(|exists| -> Result<(), FailureError> {
+                if exists.into_iter().any(|exists| exists) {
+                    Err(Error::Validate(validation_errors!({"email": ["exists" => "Email already exists"]})).into())
+                } else {
+                    Ok(())
+                }
+            })
+            .and_then(move |_| {
+                id_service.spawn_on_pool(move |conn| {
+                    let users_repo = id_repo_factory.create_users_repo(&conn, current_uid);
+                    users_repo.next_id().map(Some)
+                })
+            }),
+    )
+}
+
 fn set_email_verified_social(users_repo: &UsersRepo, user_id: UserId, provider: Provider) -> Result<Option<User>, FailureError> {
     match provider {
         Provider::Facebook | Provider::Google => {
@@ -647,13 +1086,32 @@ pub mod tests {
 
     use std::sync::Arc;
 
+    use r2d2;
     use tokio_core::reactor::Core;
 
     use stq_static_resources::Provider;
-    use stq_types::UserId;
+    use stq_types::{Alpha3, UserId};
 
+    use models::{NewUser, User};
     use repos::repo_factory::tests::*;
-    use services::users::UsersService;
+    use repos::sharding::{ShardMap, ShardedPool};
+    use services::users::{merge_shard_users, with_email_lock, UsersService};
+    use services::util::signed_token_create;
+
+    #[test]
+    fn test_with_email_lock_checks_all_shards() {
+        let pools = (0..2)
+            .map(|_| r2d2::Pool::builder().build(MockConnectionManager::default()).unwrap())
+            .collect();
+        let db_pool = ShardedPool::new(ShardMap::new(2, vec![(0, 0), (1, 1)]).unwrap(), pools);
+        let conn = db_pool.shards()[1].get().unwrap();
+
+        let exists = with_email_lock(&db_pool, &MOCK_REPO_FACTORY, &*conn, MOCK_EMAIL.to_string(), || Ok(()));
+        assert!(exists.is_err());
+
+        let created = with_email_lock(&db_pool, &MOCK_REPO_FACTORY, &*conn, "new@mail.com".to_string(), || Ok(UserId(2)));
+        assert_eq!(created.unwrap(), UserId(2));
+    }
 
     #[test]
     fn test_get_user() {
@@ -671,8 +1129,9 @@ pub mod tests {
         let handle = Arc::new(core.handle());
         let service = create_service(Some(UserId(1)), handle);
         let work = service.current();
-        let result = core.run(work).unwrap();
-        assert_eq!(result.unwrap().email, MOCK_EMAIL.to_string());
+        let result = core.run(work).unwrap().unwrap();
+        assert_eq!(result.user.email, MOCK_EMAIL.to_string());
+        assert_eq!(result.profile_completion, 20);
     }
 
     #[test]
@@ -695,6 +1154,47 @@ pub mod tests {
         assert_eq!(result.len(), 5);
     }
 
+    #[test]
+    fn test_merge_shard_users() {
+        let shard_users = vec![
+            vec![
+                create_user(UserId(2), MOCK_EMAIL.to_string()),
+                create_user(UserId(5), MOCK_EMAIL.to_string()),
+            ],
+            vec![
+                create_user(UserId(3), MOCK_EMAIL.to_string()),
+                create_user(UserId(4), MOCK_EMAIL.to_string()),
+            ],
+        ];
+        let ids = |users: Vec<User>| users.into_iter().map(|user| user.id.0).collect::<Vec<_>>();
+        assert_eq!(ids(merge_shard_users(shard_users.clone(), 0, 0)), vec![2, 3, 4, 5]);
+        assert_eq!(ids(merge_shard_users(shard_users, 1, 2)), vec![3, 4]);
+    }
+
+    #[test]
+    fn test_profile_completion_stats() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.profile_completion_stats(UserId(2), 5);
+        let result = core.run(work).unwrap();
+        assert_eq!(result.users_count, 5);
+        assert_eq!(result.next_offset, Some(UserId(7)));
+    }
+
+    #[test]
+    fn test_find_by_phone() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.find_by_phone("8 (999) 123-45-67".to_string());
+        let result = core.run(work).unwrap();
+        assert_eq!(result.unwrap().phone, Some(MOCK_PHONE.to_string()));
+        let work = service.find_by_phone("not a phone".to_string());
+        let result = core.run(work);
+        assert_eq!(result.is_err(), true);
+    }
+
     #[test]
     fn test_create_allready_existed() {
         let mut core = Core::new().unwrap();
@@ -706,7 +1206,7 @@ pub mod tests {
             Provider::Email,
             MOCK_SAGA_ID.to_string(),
         );
-        let work = service.create(new_ident, None);
+        let work = service.create(new_ident, None, None);
         let result = core.run(work);
         assert_eq!(result.is_err(), true);
     }
@@ -722,11 +1222,31 @@ pub mod tests {
             Provider::Email,
             MOCK_SAGA_ID.to_string(),
         );
-        let work = service.create(new_ident, None);
+        let work = service.create(new_ident, None, None);
         let result = core.run(work).unwrap();
         assert_eq!(result.email, "new_user@mail.com".to_string());
     }
 
+    #[test]
+    fn test_create_user_with_unknown_country() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let new_ident = create_new_identity(
+            "new_user@mail.com".to_string(),
+            MOCK_PASSWORD.to_string(),
+            Provider::Email,
+            MOCK_SAGA_ID.to_string(),
+        );
+        let new_user = NewUser {
+            country: Some(Alpha3("XXX".to_string())),
+            ..NewUser::from(new_ident.clone())
+        };
+        let work = service.create(new_ident, Some(new_user), None);
+        let result = core.run(work);
+        assert_eq!(result.is_err(), true);
+    }
+
     #[test]
     fn test_update() {
         let mut core = Core::new().unwrap();
@@ -739,6 +1259,18 @@ pub mod tests {
         assert_eq!(result.email, MOCK_EMAIL.to_string());
     }
 
+    #[test]
+    fn test_update_reserved_name() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let mut new_user = create_update_user(MOCK_EMAIL.to_string());
+        new_user.first_name = Some("Adm1n".to_string());
+        let work = service.update(UserId(1), new_user);
+        let result = core.run(work);
+        assert_eq!(result.is_err(), true);
+    }
+
     #[test]
     fn test_deactivate() {
         let mut core = Core::new().unwrap();
@@ -749,4 +1281,48 @@ pub mod tests {
         assert_eq!(result.id, UserId(1));
         assert_eq!(result.is_active, false);
     }
+
+    #[test]
+    fn test_password_reset_apply_forged_token() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let work = service.password_reset_apply(MOCK_TOKEN.to_string(), MOCK_PASSWORD.to_string(), vec![]);
+        let result = core.run(work);
+        assert_eq!(result.is_err(), true);
+    }
+
+    #[test]
+    fn test_password_reset_apply_locked_ip() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let mut service = create_service(None, handle);
+        Arc::make_mut(&mut service.dynamic_context).client_ip = Some(MOCK_LOCKED_IP.parse().unwrap());
+        let token = signed_token_create(&service.static_context.hmac_key);
+        let work = service.password_reset_apply(token, MOCK_PASSWORD.to_string(), vec![]);
+        let result = core.run(work);
+        assert_eq!(result.is_err(), true);
+    }
+
+    #[test]
+    fn test_password_reset_apply_without_security_answers() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let token = signed_token_create(&service.static_context.hmac_key);
+        let work = service.password_reset_apply(token, MOCK_PASSWORD.to_string(), vec![]);
+        let result = core.run(work);
+        assert_eq!(result.is_err(), true);
+    }
+
+    #[test]
+    fn test_verify_email() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let token = signed_token_create(&service.static_context.hmac_key);
+        let work = service.verify_email(token);
+        let result = core.run(work).unwrap();
+        assert_eq!(result.user.email, MOCK_EMAIL.to_string());
+    }
 }
@@ -1,10 +1,22 @@
-use base64::{decode, encode};
+use argon2;
+use base64::{decode, decode_config, encode, encode_config, URL_SAFE_NO_PAD};
+use bcrypt;
+use hmac::{Hmac, Mac};
 use rand;
 use rand::Rng;
+use ring::digest;
 use sha3::{Digest, Sha3_256};
+use uuid::Uuid;
 
+use stq_types::UserId;
+
+use config::Analytics;
 use errors::Error;
+use models::{Feature, PasswordHashFormat};
 use repos::types::RepoResult;
+use services::rollout::Rollouts;
+
+const ARGON2_HASH_PREFIX: &str = "$argon2";
 
 pub fn password_create(clear_password: String) -> String {
     let salt = rand::thread_rng().gen_ascii_chars().take(10).collect::<String>();
@@ -16,7 +28,27 @@ pub fn password_create(clear_password: String) -> String {
     computed_hash + "." + &salt
 }
 
+/// Hashes password with argon2, the hash is encoded along with its parameters and salt
+pub fn argon2_password_create(clear_password: String) -> String {
+    let salt = rand::thread_rng().gen::<[u8; 16]>();
+    argon2::hash_encoded(clear_password.as_bytes(), &salt, &argon2::Config::default()).expect("Argon2 hashing with default config failed")
+}
+
+/// Hashes password with argon2 if it is rolled out to the user
+pub fn rollout_password_create(rollouts: &Rollouts, user_id: UserId, clear_password: String) -> String {
+    if rollouts.is_enabled(Feature::Argon2Passwords, user_id) {
+        argon2_password_create(clear_password)
+    } else {
+        password_create(clear_password)
+    }
+}
+
+/// Verifies password against its hash, argon2 hashes are verified regardless of rollout
+/// not to lock users out when it is rolled back
 pub fn password_verify(db_hash: &str, clear_password: String) -> RepoResult<bool> {
+    if db_hash.starts_with(ARGON2_HASH_PREFIX) {
+        return imported_password_verify(db_hash, PasswordHashFormat::Argon2, clear_password);
+    }
     let v: Vec<&str> = db_hash.split('.').collect();
     if v.len() != 2 {
         Err(Error::Validate(validation_errors!({"password": ["password" => "Password in db has wrong format"]})).into())
@@ -31,3 +63,97 @@ pub fn password_verify(db_hash: &str, clear_password: String) -> RepoResult<bool
             .map_err(|_| Error::Validate(validation_errors!({"password": ["password" => "Password in db has wrong format"]})).into())
     }
 }
+
+/// Verifies password against hash imported from another system, by the algorithm of its format
+pub fn imported_password_verify(db_hash: &str, format: PasswordHashFormat, clear_password: String) -> RepoResult<bool> {
+    let verified = match format {
+        PasswordHashFormat::Bcrypt => bcrypt::verify(&clear_password, db_hash).map_err(|_| ()),
+        PasswordHashFormat::Argon2 => argon2::verify_encoded(db_hash, clear_password.as_bytes()).map_err(|_| ()),
+        PasswordHashFormat::Sha1 => {
+            let computed_hash = digest::digest(&digest::SHA1, clear_password.as_bytes())
+                .as_ref()
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<String>();
+            Ok(computed_hash == db_hash.to_lowercase())
+        }
+    };
+    verified.map_err(|_| Error::Validate(validation_errors!({"password": ["password" => "Password in db has wrong format"]})).into())
+}
+
+/// Verifies password against the stored hash of an identity, hashes imported from another
+/// system are verified by the algorithm of their format until they are replaced
+pub fn stored_password_verify(db_hash: &str, format: Option<PasswordHashFormat>, clear_password: String) -> RepoResult<bool> {
+    match format {
+        Some(format) => imported_password_verify(db_hash, format, clear_password),
+        None => password_verify(db_hash, clear_password),
+    }
+}
+
+/// Hash of a high-entropy token for lookups, tokens are random so no salt is needed
+pub fn token_hash(token: &str) -> String {
+    let mut hasher = Sha3_256::default();
+    hasher.input(token.as_bytes());
+    encode(&hasher.result()[..])
+}
+
+type HmacSha3 = Hmac<Sha3_256>;
+
+const UUID_LEN: usize = 16;
+const ANALYTICS_ID_LEN: usize = 16;
+
+fn token_mac(key: &[u8], payload: &[u8]) -> HmacSha3 {
+    let mut mac = HmacSha3::new_varkey(key).expect("HMAC can take key of any size");
+    mac.input(payload);
+    mac
+}
+
+/// Computes HMAC-SHA3-256 of the payload
+pub fn hmac_sign(key: &[u8], payload: &[u8]) -> Vec<u8> {
+    token_mac(key, payload).result().code().to_vec()
+}
+
+/// Checks HMAC of the payload in constant time
+pub fn hmac_verify(key: &[u8], payload: &[u8], code: &[u8]) -> bool {
+    token_mac(key, payload).verify(code).is_ok()
+}
+
+/// Creates high-entropy token: random UUIDv4 followed by its HMAC, url-safe base64 encoded.
+/// Signature allows rejecting forged tokens before looking them up in db.
+pub fn signed_token_create(key: &[u8]) -> String {
+    let uuid = Uuid::new_v4();
+    let mut token = uuid.as_bytes().to_vec();
+    token.extend_from_slice(&hmac_sign(key, uuid.as_bytes()));
+    encode_config(&token, URL_SAFE_NO_PAD)
+}
+
+/// Pseudonymous id of the user for analytics, stable until the pepper is rotated. Prefixed with
+/// the pepper version, so that pipelines can tell ids made with different peppers apart.
+pub fn analytics_id(conf: &Analytics, user_id: UserId) -> String {
+    let code = hmac_sign(conf.pepper.as_bytes(), format!("analytics:{}", user_id).as_bytes());
+    format!(
+        "v{}.{}",
+        conf.pepper_version,
+        encode_config(&code[..ANALYTICS_ID_LEN], URL_SAFE_NO_PAD)
+    )
+}
+
+/// `Set-Cookie` header value for cookies the gateway sets on behalf of the service,
+/// not readable by scripts of the page and sent to the same site only
+pub fn http_only_cookie(name: &str, value: &str, path: &str, max_age_s: i64) -> String {
+    format!(
+        "{}={}; Path={}; Max-Age={}; HttpOnly; Secure; SameSite=Strict",
+        name, value, path, max_age_s
+    )
+}
+
+/// Checks that token was created by `signed_token_create` with the same key
+pub fn signed_token_verify(key: &[u8], token: &str) -> bool {
+    match decode_config(token, URL_SAFE_NO_PAD) {
+        Ok(ref bytes) if bytes.len() > UUID_LEN => {
+            let (uuid, code) = bytes.split_at(UUID_LEN);
+            hmac_verify(key, uuid, code)
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,175 @@
+//! Waitlist Services. While registration is invite-only, people leave their emails on the waitlist
+//! and confirm them with a token sent by email, admins convert confirmed entries into invites.
+//! Waitlist is saved on the primary shard.
+
+use std::time::{Duration, SystemTime};
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::future;
+use r2d2::ManageConnection;
+
+use super::util::{signed_token_create, signed_token_verify};
+use errors::Error;
+use models::*;
+use repos::repo_factory::ReposFactory;
+use services::types::ServiceFuture;
+use services::Service;
+
+pub trait WaitlistService {
+    /// Adds email to the waitlist, returns token confirming the email
+    fn join_waitlist(&self, email: String) -> ServiceFuture<String>;
+    /// Confirms email on the waitlist
+    fn confirm_waitlist(&self, token: String) -> ServiceFuture<WaitlistEntry>;
+    /// Issues invites to the earliest confirmed entries
+    fn invite_waitlist(&self, payload: WaitlistInvitesPayload) -> ServiceFuture<Vec<WaitlistInvite>>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > WaitlistService for Service<T, M, F>
+{
+    /// Adds email to the waitlist, returns token confirming the email
+    fn join_waitlist(&self, email: String) -> ServiceFuture<String> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let signing_key = self.static_context.hmac_key.clone();
+
+        if !self.static_context.config.invites.required {
+            return Box::new(future::err(
+                Error::Validate(validation_errors!({"email": ["registration_open" => "Registration is open, no need to wait"]})).into(),
+            ));
+        }
+
+        self.spawn_on_pool(move |conn| {
+            let waitlist_repo = repo_factory.create_waitlist_repo(&*conn, None);
+            waitlist_repo
+                .upsert(NewWaitlistEntry {
+                    email,
+                    token: signed_token_create(&signing_key),
+                })
+                .map(|entry| entry.token)
+                .map_err(|e: FailureError| e.context("Service waitlist, join_waitlist endpoint error occured.").into())
+        })
+    }
+
+    /// Confirms email on the waitlist
+    fn confirm_waitlist(&self, token: String) -> ServiceFuture<WaitlistEntry> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let signing_key = self.static_context.hmac_key.clone();
+        let verify_expiration_s = self.static_context.config.tokens.verify_expiration_s;
+
+        self.spawn_on_pool(move |conn| {
+            {
+                if !signed_token_verify(&signing_key, &token) {
+                    return Err(Error::InvalidToken.context("Token signature mismatch").into());
+                }
+                let waitlist_repo = repo_factory.create_waitlist_repo(&*conn, None);
+                let entry = waitlist_repo
+                    .find_by_token(token)?
+                    .ok_or_else(|| format_err!("Waitlist entry not found").context(Error::InvalidToken))?;
+                let expired = SystemTime::now()
+                    .duration_since(entry.updated_at)
+                    .map(|elapsed| elapsed.as_secs() >= verify_expiration_s)
+                    .unwrap_or(false);
+                if expired {
+                    return Err(format_err!("Waitlist token has expired").context(Error::InvalidToken).into());
+                }
+                if entry.confirmed {
+                    Ok(entry)
+                } else {
+                    waitlist_repo.confirm(entry.email)
+                }
+            }
+            .map_err(|e: FailureError| e.context("Service waitlist, confirm_waitlist endpoint error occured.").into())
+        })
+    }
+
+    /// Issues invites to the earliest confirmed entries
+    fn invite_waitlist(&self, payload: WaitlistInvitesPayload) -> ServiceFuture<Vec<WaitlistInvite>> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+        let max_count = self.static_context.config.repo_limits.max_count;
+        let conf = &self.static_context.config.invites;
+
+        let expiration_s = payload.expiration_s.unwrap_or(conf.expiration_s);
+        if payload.count < 1 || payload.count > max_count {
+            return Box::new(future::err(
+                Error::Validate(validation_errors!({"count": ["range" => "Count is out of range"]})).into(),
+            ));
+        }
+        if expiration_s == 0 || expiration_s > conf.max_expiration_s {
+            return Box::new(future::err(
+                Error::Validate(validation_errors!({"expiration_s": ["range" => "Expiration is out of range"]})).into(),
+            ));
+        }
+
+        self.spawn_on_pool(move |conn| {
+            let waitlist_repo = repo_factory.create_waitlist_repo(&*conn, current_uid);
+            let invites_repo = repo_factory.create_invites_repo(&*conn, current_uid);
+            conn.transaction::<Vec<WaitlistInvite>, FailureError, _>(|| {
+                let entries = waitlist_repo.list_to_invite(payload.count)?;
+                let mut invites = vec![];
+                for entry in entries {
+                    let invite = invites_repo.create(NewInvite::new(current_uid, 1, Duration::from_secs(expiration_s)))?;
+                    let entry = waitlist_repo.set_invite(entry.email, invite.code.clone())?;
+                    invites.push(WaitlistInvite {
+                        email: entry.email,
+                        invite_code: invite.code,
+                    });
+                }
+                Ok(invites)
+            })
+            .map_err(|e: FailureError| e.context("Service waitlist, invite_waitlist endpoint error occured.").into())
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use tokio_core::reactor::Core;
+
+    use stq_types::UserId;
+
+    use models::*;
+    use repos::repo_factory::tests::*;
+    use services::waitlist::*;
+
+    #[test]
+    fn test_join_waitlist_registration_open() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let work = service.join_waitlist(MOCK_EMAIL.to_string());
+        assert_eq!(core.run(work).is_err(), true);
+    }
+
+    #[test]
+    fn test_confirm_waitlist_forged_token() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let work = service.confirm_waitlist(MOCK_TOKEN.to_string());
+        assert_eq!(core.run(work).is_err(), true);
+    }
+
+    #[test]
+    fn test_invite_waitlist() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.invite_waitlist(WaitlistInvitesPayload {
+            count: 3,
+            expiration_s: None,
+        });
+        let result = core.run(work).unwrap();
+        assert_eq!(result.len(), 3);
+        assert_eq!(result[0].invite_code.is_empty(), false);
+    }
+}
@@ -0,0 +1,126 @@
+//! Warmup of pools and caches after deploys. Until it is done `/ready` fails, so that the
+//! instance gets no traffic while the first requests would pay for opening database
+//! connections, loading roles of superusers and parsing the signing key.
+//! Failed warmup steps are logged and the instance is reported ready anyway.
+
+use std::io;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use jsonwebtoken::{encode, Algorithm, Header};
+use r2d2::{ManageConnection, Pool};
+
+use stq_static_resources::Provider;
+use stq_types::{UserId, UsersRole};
+
+use controller::access_log::duration_ms;
+use errors::Error;
+use models::JWTPayload;
+use repos::acl::warm_up_permissions;
+use repos::repo_factory::ReposFactory;
+use repos::sharding::ShardedPool;
+use services::readiness::Readiness;
+
+/// Warms up pools and caches in a background thread, then marks the instance warmed up
+pub fn start_warmup<T, M, F>(
+    db_pool: ShardedPool<M>,
+    repo_factory: F,
+    jwt_private_key: Vec<u8>,
+    max_superusers: i64,
+    readiness: Arc<Readiness>,
+) -> io::Result<JoinHandle<()>>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    thread::Builder::new().name("warmup".to_string()).spawn(move || {
+        let started_at = Instant::now();
+        if let Err(e) = warm_up(&db_pool, &repo_factory, &jwt_private_key, max_superusers) {
+            warn!("Warmup was not completed: {}", e);
+        }
+        readiness.set_warmed_up();
+        info!("Warmup took {} ms", duration_ms(started_at.elapsed()));
+    })
+}
+
+fn warm_up<T, M, F>(db_pool: &ShardedPool<M>, repo_factory: &F, jwt_private_key: &[u8], max_superusers: i64) -> Result<(), FailureError>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    warm_up_permissions();
+    sign_dummy_token(jwt_private_key)?;
+    for pool in db_pool.shards() {
+        open_connections(pool)?;
+        cache_superuser_roles(pool, repo_factory, max_superusers)?;
+    }
+    Ok(())
+}
+
+/// Checks out every connection of the pool at once, so that the pool opens all of them
+fn open_connections<T, M>(pool: &Pool<M>) -> Result<(), FailureError>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+{
+    let conns = (0..pool.max_size())
+        .map(|_| pool.get())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.context(Error::Connection))?;
+    for conn in &conns {
+        conn.execute("SELECT 1")?;
+    }
+    debug!("Opened {} database connections", conns.len());
+    Ok(())
+}
+
+/// Loads roles of superusers into roles cache, superusers are the ones using admin tools right after deploys
+fn cache_superuser_roles<T, M, F>(pool: &Pool<M>, repo_factory: &F, max_superusers: i64) -> Result<(), FailureError>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    let conn = pool.get().map_err(|e| e.context(Error::Connection))?;
+    let user_roles_repo = repo_factory.create_user_roles_repo_with_sys_acl(&*conn);
+    let superusers = user_roles_repo.list_users_with_role(UsersRole::Superuser, max_superusers)?;
+    for user_id in &superusers {
+        user_roles_repo.list_for_user(*user_id)?;
+    }
+    debug!("Cached roles of {} superusers", superusers.len());
+    Ok(())
+}
+
+/// Signs a token nobody gets, the signing key is parsed on the first use
+fn sign_dummy_token(jwt_private_key: &[u8]) -> Result<(), FailureError> {
+    let payload = JWTPayload::new(UserId(0), 0, Provider::Email);
+    encode(&Header::new(Algorithm::RS256), &payload, jwt_private_key)
+        .map(|_| ())
+        .map_err(|e| format_err!("{}", e).context("Couldn't encode dummy jwt").into())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use tokio_core::reactor::Core;
+
+    use super::*;
+    use repos::repo_factory::tests::*;
+
+    #[test]
+    fn test_sign_dummy_token() {
+        let core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        assert_eq!(sign_dummy_token(&service.static_context.jwt_private_key).is_ok(), true);
+    }
+}
@@ -0,0 +1,199 @@
+//! Prioritized pool of blocking db work, replacing a single `CpuPool`. Work is split into
+//! priority classes run on separate threads, so that a bulk export or a search storm can't
+//! delay token issuance. Queues are bounded, work coming to a full queue is rejected with 503
+//! right away instead of waiting behind everything queued before it.
+//!
+//! Priority of request work is decided by its route, see `Route::work_priority`. Background
+//! jobs and CPU-bound work not touching the database use threads of the classes directly.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::future;
+use futures_cpupool::CpuPool;
+
+use config::WorkQueues;
+use errors::Error;
+use services::types::ServiceFuture;
+
+/// Priority class of db work
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum WorkPriority {
+    /// Token issuance and validation
+    Auth,
+    /// Other requests
+    Regular,
+    /// Admin reports, searches, exports and background jobs
+    Batch,
+}
+
+impl WorkPriority {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            WorkPriority::Auth => "auth",
+            WorkPriority::Regular => "regular",
+            WorkPriority::Batch => "batch",
+        }
+    }
+}
+
+impl fmt::Display for WorkPriority {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Snapshot of a queue, `depth` counts queued and running work
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct WorkQueueStats {
+    pub threads: usize,
+    pub queue_size: usize,
+    pub depth: usize,
+    pub rejected: usize,
+}
+
+struct WorkQueue {
+    cpu_pool: CpuPool,
+    threads: usize,
+    queue_size: usize,
+    depth: Arc<AtomicUsize>,
+    rejected: AtomicUsize,
+}
+
+impl WorkQueue {
+    fn new(threads: usize, queue_size: usize) -> Self {
+        Self {
+            cpu_pool: CpuPool::new(threads),
+            threads,
+            queue_size,
+            depth: Arc::new(AtomicUsize::new(0)),
+            rejected: AtomicUsize::new(0),
+        }
+    }
+}
+
+/// Takes work off the queue depth once the work is done, or dropped without being run
+struct DepthGuard(Arc<AtomicUsize>);
+
+impl Drop for DepthGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Queues of db work by priority, cloning is cheap
+#[derive(Clone)]
+pub struct WorkPool {
+    queues: Arc<BTreeMap<WorkPriority, WorkQueue>>,
+}
+
+impl WorkPool {
+    /// Regular work runs on `regular_threads`, i.e. `server.thread_count`
+    pub fn new(conf: &WorkQueues, regular_threads: usize) -> Self {
+        let mut queues = BTreeMap::new();
+        queues.insert(WorkPriority::Auth, WorkQueue::new(conf.auth.threads, conf.auth.queue_size));
+        queues.insert(WorkPriority::Regular, WorkQueue::new(regular_threads, conf.regular_queue_size));
+        queues.insert(WorkPriority::Batch, WorkQueue::new(conf.batch.threads, conf.batch.queue_size));
+        Self { queues: Arc::new(queues) }
+    }
+
+    /// Runs `f` on threads of the priority, unless its queue is full
+    pub fn spawn_fn<R, Func>(&self, priority: WorkPriority, f: Func) -> ServiceFuture<R>
+    where
+        Func: FnOnce() -> Result<R, FailureError> + Send + 'static,
+        R: Send + 'static,
+    {
+        let queue = &self.queues[&priority];
+        let depth = queue.depth.fetch_add(1, Ordering::SeqCst);
+        let guard = DepthGuard(queue.depth.clone());
+        if depth >= queue.queue_size {
+            queue.rejected.fetch_add(1, Ordering::SeqCst);
+            return Box::new(future::err(
+                format_err!("Queue of {} work is full, {} tasks are queued or running", priority, depth)
+                    .context(Error::Overloaded)
+                    .into(),
+            ));
+        }
+        Box::new(queue.cpu_pool.spawn_fn(move || {
+            let _guard = guard;
+            f()
+        }))
+    }
+
+    /// Threads of the priority for work not limited by the queue, e.g. background jobs
+    pub fn cpu_pool(&self, priority: WorkPriority) -> CpuPool {
+        self.queues[&priority].cpu_pool.clone()
+    }
+
+    /// Queue depths and numbers of rejected tasks since start
+    pub fn stats(&self) -> BTreeMap<&'static str, WorkQueueStats> {
+        self.queues
+            .iter()
+            .map(|(priority, queue)| {
+                let stats = WorkQueueStats {
+                    threads: queue.threads,
+                    queue_size: queue.queue_size,
+                    depth: queue.depth.load(Ordering::SeqCst),
+                    rejected: queue.rejected.load(Ordering::SeqCst),
+                };
+                (priority.as_str(), stats)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::mpsc;
+
+    use futures::Future;
+
+    use super::*;
+    use config::WorkQueue as WorkQueueConf;
+
+    fn create_work_pool() -> WorkPool {
+        let conf = WorkQueues {
+            auth: WorkQueueConf { threads: 1, queue_size: 1 },
+            regular_queue_size: 1,
+            batch: WorkQueueConf { threads: 1, queue_size: 1 },
+        };
+        WorkPool::new(&conf, 1)
+    }
+
+    #[test]
+    fn test_full_queue_rejects_work() {
+        let work_pool = create_work_pool();
+        let (release, released) = mpsc::channel::<()>();
+        let export = work_pool.spawn_fn(WorkPriority::Batch, move || {
+            released.recv().unwrap();
+            Ok(())
+        });
+        assert_eq!(work_pool.spawn_fn(WorkPriority::Batch, || Ok(())).wait().is_err(), true);
+        assert_eq!(work_pool.stats()["batch"].depth, 1);
+        assert_eq!(work_pool.stats()["batch"].rejected, 1);
+
+        release.send(()).unwrap();
+        export.wait().unwrap();
+        assert_eq!(work_pool.stats()["batch"].depth, 0);
+        assert_eq!(work_pool.spawn_fn(WorkPriority::Batch, || Ok(42)).wait().unwrap(), 42);
+    }
+
+    #[test]
+    fn test_busy_batch_queue_does_not_delay_auth() {
+        let work_pool = create_work_pool();
+        let (release, released) = mpsc::channel::<()>();
+        let export = work_pool.spawn_fn(WorkPriority::Batch, move || {
+            released.recv().unwrap();
+            Ok(())
+        });
+        assert_eq!(work_pool.spawn_fn(WorkPriority::Auth, || Ok(42)).wait().unwrap(), 42);
+        assert_eq!(work_pool.stats()["auth"].depth, 0);
+
+        release.send(()).unwrap();
+        export.wait().unwrap();
+    }
+}
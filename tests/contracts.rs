@@ -0,0 +1,175 @@
+//! Contracts of the HTTP API with its consumers, generated from the controller with mocked repos.
+//! Run with `cargo test --features test-mocks --test contracts`, see `scripts/contracts.sh`.
+//!
+//! Every interaction is a request a consumer makes along with the response of the controller.
+//! Interactions are written as Pact v2 files `users-<consumer>.json` to `CONTRACTS_DIR`,
+//! `target/contracts` by default. Response bodies are matched by type, as values come from mocks.
+
+extern crate hyper;
+#[macro_use]
+extern crate serde_json;
+extern crate stq_http;
+extern crate tokio_core;
+extern crate users_lib;
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use hyper::header::Authorization;
+use hyper::{Method, Request};
+use serde_json::Value;
+use tokio_core::reactor::Core;
+
+use stq_http::controller::Controller;
+
+use users_lib::controller::ControllerImpl;
+use users_lib::repos::repo_factory::tests::*;
+
+const PROVIDER: &str = "users";
+
+/// Request of a consumer to the API
+struct Interaction {
+    consumer: &'static str,
+    description: &'static str,
+    method: Method,
+    path: &'static str,
+    query: Option<&'static str>,
+    /// Authorized as the user the same way the gateway does it
+    user_id: Option<&'static str>,
+    body: Option<Value>,
+}
+
+fn interactions() -> Vec<Interaction> {
+    vec![
+        Interaction {
+            consumer: "gateway",
+            description: "a request for the current user",
+            method: Method::Get,
+            path: "/users/current",
+            query: None,
+            user_id: Some("1"),
+            body: None,
+        },
+        Interaction {
+            consumer: "gateway",
+            description: "a request for a user by id",
+            method: Method::Get,
+            path: "/users/1",
+            query: None,
+            user_id: Some("1"),
+            body: None,
+        },
+        Interaction {
+            consumer: "gateway",
+            description: "a search of users",
+            method: Method::Post,
+            path: "/users/search",
+            query: Some("count=2"),
+            user_id: Some("1"),
+            body: Some(json!({ "email": MOCK_EMAIL })),
+        },
+        Interaction {
+            consumer: "saga",
+            description: "a request for a user by email",
+            method: Method::Get,
+            path: "/users/by_email",
+            query: Some("email=example%40mail.com"),
+            user_id: Some("1"),
+            body: None,
+        },
+        Interaction {
+            consumer: "saga",
+            description: "a deletion of a user created by a failed saga",
+            method: Method::Delete,
+            path: "/users/by_saga_id/saga",
+            query: None,
+            user_id: Some("1"),
+            body: None,
+        },
+    ]
+}
+
+fn request(interaction: &Interaction) -> Request {
+    let uri = match interaction.query {
+        Some(query) => format!("{}?{}", interaction.path, query),
+        None => interaction.path.to_string(),
+    };
+    let mut req = Request::new(interaction.method.clone(), uri.parse().unwrap());
+    if let Some(user_id) = interaction.user_id {
+        req.headers_mut().set(Authorization(user_id.to_string()));
+    }
+    if let Some(ref body) = interaction.body {
+        req.set_body(body.to_string());
+    }
+    req
+}
+
+fn pact_interaction(interaction: &Interaction, response: &str) -> Value {
+    let mut request = json!({
+        "method": interaction.method.to_string(),
+        "path": interaction.path,
+    });
+    if let Some(query) = interaction.query {
+        request["query"] = json!(query);
+    }
+    if let Some(user_id) = interaction.user_id {
+        request["headers"] = json!({ "Authorization": user_id });
+    }
+    if let Some(ref body) = interaction.body {
+        request["body"] = body.clone();
+    }
+    json!({
+        "description": interaction.description,
+        "request": request,
+        "response": {
+            "status": 200,
+            "headers": { "Content-Type": "application/json" },
+            "body": serde_json::from_str::<Value>(response).unwrap(),
+            "matchingRules": { "$.body": { "match": "type" } },
+        },
+    })
+}
+
+fn contracts_dir() -> PathBuf {
+    env::var("CONTRACTS_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("target/contracts"))
+}
+
+#[test]
+fn generate_contracts() {
+    let mut core = Core::new().unwrap();
+    let handle = Arc::new(core.handle());
+    let controller = ControllerImpl::new(create_service(None, handle).static_context);
+
+    let mut pacts = BTreeMap::new();
+    for interaction in interactions() {
+        let response = core.run(controller.call(request(&interaction))).unwrap_or_else(|e| {
+            panic!(
+                "Interaction '{}' of {} failed: {}",
+                interaction.description, interaction.consumer, e
+            )
+        });
+        pacts
+            .entry(interaction.consumer)
+            .or_insert_with(Vec::new)
+            .push(pact_interaction(&interaction, &response));
+    }
+
+    let dir = contracts_dir();
+    fs::create_dir_all(&dir).unwrap();
+    for (consumer, interactions) in pacts {
+        let pact = json!({
+            "consumer": { "name": consumer },
+            "provider": { "name": PROVIDER },
+            "interactions": interactions,
+            "metadata": { "pactSpecification": { "version": "2.0.0" } },
+        });
+        let mut file = File::create(dir.join(format!("{}-{}.json", PROVIDER, consumer))).unwrap();
+        file.write_all(serde_json::to_string_pretty(&pact).unwrap().as_bytes()).unwrap();
+    }
+}